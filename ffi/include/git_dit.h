@@ -0,0 +1,99 @@
+/*
+ * git-dit - the distributed issue tracker for git
+ * Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+ * Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+/*
+ * C interface of the git-dit library
+ *
+ * Handles are allocated by the library and released via the corresponding
+ * `_free` function. Strings returned by accessors are owned by the handle and
+ * remain valid until the handle is released. Functions returning an `int`
+ * return one of the error codes below; the message of the last error on the
+ * calling thread is available via `git_dit_last_error()`.
+ */
+
+#ifndef GIT_DIT_H
+#define GIT_DIT_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+
+/* error codes */
+#define GIT_DIT_OK          0
+#define GIT_DIT_ERROR      -1
+#define GIT_DIT_EINVALID   -2
+#define GIT_DIT_ITEROVER   -3
+
+
+/* opaque handles */
+typedef struct git_dit_repository git_dit_repository;
+typedef struct git_dit_issue git_dit_issue;
+typedef struct git_dit_message git_dit_message;
+typedef struct git_dit_issue_iter git_dit_issue_iter;
+typedef struct git_dit_message_iter git_dit_message_iter;
+
+
+const char *git_dit_last_error(void);
+
+
+/* repositories */
+int git_dit_repository_open(const char *path, git_dit_repository **out);
+void git_dit_repository_free(git_dit_repository *repo);
+
+
+/* issues */
+int git_dit_issues(const git_dit_repository *repo, git_dit_issue_iter **out);
+int git_dit_issue_iter_next(git_dit_issue_iter *iter, git_dit_issue **out);
+void git_dit_issue_iter_free(git_dit_issue_iter *iter);
+
+int git_dit_issue_find(const git_dit_repository *repo,
+                       const char *id,
+                       git_dit_issue **out);
+int git_dit_issue_create(const git_dit_repository *repo,
+                         const char *message,
+                         git_dit_issue **out);
+
+const char *git_dit_issue_id(const git_dit_issue *issue);
+void git_dit_issue_free(git_dit_issue *issue);
+
+
+/* messages */
+int git_dit_messages(const git_dit_repository *repo,
+                     const git_dit_issue *issue,
+                     git_dit_message_iter **out);
+int git_dit_message_iter_next(git_dit_message_iter *iter, git_dit_message **out);
+void git_dit_message_iter_free(git_dit_message_iter *iter);
+
+int git_dit_message_find(const git_dit_repository *repo,
+                         const char *id,
+                         git_dit_message **out);
+int git_dit_message_add(const git_dit_repository *repo,
+                        const git_dit_issue *issue,
+                        const char *parent,
+                        const char *message,
+                        git_dit_message **out);
+
+const char *git_dit_message_id(const git_dit_message *message);
+const char *git_dit_message_subject(const git_dit_message *message);
+const char *git_dit_message_text(const git_dit_message *message);
+const char *git_dit_message_author_name(const git_dit_message *message);
+const char *git_dit_message_author_email(const git_dit_message *message);
+int64_t git_dit_message_time(const git_dit_message *message);
+void git_dit_message_free(git_dit_message *message);
+
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GIT_DIT_H */
@@ -0,0 +1,600 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! C interface of the git-dit library
+//!
+//! This crate exposes the main operations of libgitdit as C functions, which
+//! are declared in `include/git_dit.h`. All functions are prefixed with
+//! `git_dit_`.
+//!
+//! Repositories, issues, messages and iterators are represented by opaque
+//! handles allocated by the library. Each handle is released by passing it to
+//! the corresponding `_free` function. Issue and message handles own all of
+//! their data and hence may outlive the repository handle they were obtained
+//! from. Strings returned by accessors are owned by the handle and remain valid
+//! until the handle is released.
+//!
+//! Functions which may fail return one of the `GIT_DIT_*` error codes and
+//! report their results via out-parameters. The message of the last error
+//! which occurred on the calling thread may be retrieved via
+//! `git_dit_last_error()`. Panics are caught and reported as errors.
+//!
+//! # Safety
+//!
+//! All pointers passed to the functions of this crate must either be null or
+//! valid. Handles must have been obtained from this library and must not be
+//! used after being released. Strings must be NUL-terminated. Repository
+//! handles must not be used by several threads at once.
+//!
+
+#![allow(clippy::missing_safety_doc)]
+
+extern crate git2;
+extern crate libgitdit;
+
+use git2::Oid;
+use libgitdit::{Issue, RepositoryExt};
+use libgitdit::issue::IssueHandle;
+use libgitdit::message::LineIteratorExt;
+use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, UnwindSafe};
+use std::ptr;
+use std::vec;
+
+
+/// The operation succeeded
+///
+pub const GIT_DIT_OK: c_int = 0;
+
+/// The operation failed
+///
+pub const GIT_DIT_ERROR: c_int = -1;
+
+/// An argument was null or malformed
+///
+pub const GIT_DIT_EINVALID: c_int = -2;
+
+/// An iterator has no more items
+///
+pub const GIT_DIT_ITEROVER: c_int = -3;
+
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+
+/// Reason for the failure of an operation
+///
+enum Failure {
+    Invalid(String),
+    Error(libgitdit::error::Error),
+}
+
+impl Failure {
+    /// Record the failure as the last error and get its error code
+    ///
+    fn record(self) -> c_int {
+        let (code, message) = match self {
+            Failure::Invalid(message) => (GIT_DIT_EINVALID, message),
+            Failure::Error(err) => {
+                let mut messages = vec![err.to_string()];
+                let mut current = err.source();
+                while let Some(cause) = current {
+                    messages.push(cause.to_string());
+                    current = cause.source();
+                }
+                (GIT_DIT_ERROR, messages.join(": "))
+            },
+        };
+        set_last_error(message);
+        code
+    }
+}
+
+impl From<libgitdit::error::Error> for Failure {
+    fn from(err: libgitdit::error::Error) -> Self {
+        Failure::Error(err)
+    }
+}
+
+impl From<git2::Error> for Failure {
+    fn from(err: git2::Error) -> Self {
+        Failure::Error(err.into())
+    }
+}
+
+
+/// Repository handle
+///
+pub struct RepositoryHandle {
+    repo: git2::Repository,
+}
+
+/// Owned issue
+///
+pub struct IssueData {
+    handle: IssueHandle,
+    id: CString,
+}
+
+impl IssueData {
+    fn new(issue: &Issue) -> Self {
+        IssueData { handle: issue.handle(), id: c_string(issue.id().to_string()) }
+    }
+}
+
+/// Owned message
+///
+pub struct MessageData {
+    id: CString,
+    subject: CString,
+    text: CString,
+    author_name: CString,
+    author_email: CString,
+    time: i64,
+}
+
+impl<'r> From<git2::Commit<'r>> for MessageData {
+    fn from(commit: git2::Commit<'r>) -> Self {
+        let author = commit.author();
+        MessageData {
+            id: c_string(commit.id().to_string()),
+            subject: c_string(commit.summary_bytes().unwrap_or_default()),
+            text: c_string(commit.message_bytes()),
+            author_name: c_string(author.name_bytes()),
+            author_email: c_string(author.email_bytes()),
+            time: author.when().seconds(),
+        }
+    }
+}
+
+/// Iterator over issues
+///
+pub struct IssueIter {
+    issues: vec::IntoIter<IssueData>,
+}
+
+/// Iterator over messages
+///
+pub struct MessageIter {
+    messages: vec::IntoIter<MessageData>,
+}
+
+
+/// Get the message of the last error which occurred on the calling thread
+///
+/// Returns null if no error occurred. The string remains valid until the next
+/// error occurs on the calling thread.
+///
+#[no_mangle]
+pub extern "C" fn git_dit_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last
+        .borrow()
+        .as_ref()
+        .map(|message| message.as_ptr())
+        .unwrap_or(ptr::null()))
+}
+
+
+/// Open a repository
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_repository_open(path: *const c_char,
+                                                 out: *mut *mut RepositoryHandle
+) -> c_int {
+    wrap(|| {
+        let path = str_arg(path, "path")?;
+        let repo = git2::Repository::open(path)?;
+        put(out, RepositoryHandle { repo: repo })
+    })
+}
+
+/// Release a repository handle
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_repository_free(repo: *mut RepositoryHandle) {
+    release(repo)
+}
+
+
+/// Get an iterator over all issues of a repository
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_issues(repo: *const RepositoryHandle,
+                                        out: *mut *mut IssueIter
+) -> c_int {
+    wrap(|| {
+        let repo = &handle_arg(repo, "repo")?.repo;
+        let issues: Vec<_> = repo.issues()?.iter().map(IssueData::new).collect();
+        put(out, IssueIter { issues: issues.into_iter() })
+    })
+}
+
+/// Retrieve the next issue from an iterator
+///
+/// Returns `GIT_DIT_ITEROVER` if there are no more issues.
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_issue_iter_next(iter: *mut IssueIter,
+                                                 out: *mut *mut IssueData
+) -> c_int {
+    wrap(|| {
+        let iter = handle_arg_mut(iter, "iter")?;
+        match iter.issues.next() {
+            Some(issue) => put(out, issue),
+            None => Ok(GIT_DIT_ITEROVER),
+        }
+    })
+}
+
+/// Release an issue iterator
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_issue_iter_free(iter: *mut IssueIter) {
+    release(iter)
+}
+
+
+/// Find an issue by its id
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_issue_find(repo: *const RepositoryHandle,
+                                            id: *const c_char,
+                                            out: *mut *mut IssueData
+) -> c_int {
+    wrap(|| {
+        let repo = &handle_arg(repo, "repo")?.repo;
+        let issue = repo.find_issue(oid_arg(id, "id")?)?;
+        put(out, IssueData::new(&issue))
+    })
+}
+
+/// Create a new issue
+///
+/// The initial message is created with the text supplied and an empty tree.
+/// Author and committer are taken from the repository's configuration. The
+/// trailers required via "dit.new.required" are not enforced.
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_issue_create(repo: *const RepositoryHandle,
+                                              message: *const c_char,
+                                              out: *mut *mut IssueData
+) -> c_int {
+    wrap(|| {
+        let repo = &handle_arg(repo, "repo")?.repo;
+        let message = message_arg(message)?;
+        let sig = repo.signature()?;
+        let issue = repo.import_issue_in_component(None, &sig, &sig, message, &repo.empty_tree()?, Vec::new())?;
+        put(out, IssueData::new(&issue))
+    })
+}
+
+/// Get the id of an issue
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_issue_id(issue: *const IssueData) -> *const c_char {
+    issue.as_ref().map(|issue| issue.id.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Release an issue handle
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_issue_free(issue: *mut IssueData) {
+    release(issue)
+}
+
+
+/// Get an iterator over all messages of an issue
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_messages(repo: *const RepositoryHandle,
+                                          issue: *const IssueData,
+                                          out: *mut *mut MessageIter
+) -> c_int {
+    wrap(|| {
+        let repo = &handle_arg(repo, "repo")?.repo;
+        let issue = handle_arg(issue, "issue")?.handle.bind(repo)?;
+        let messages = issue
+            .messages()?
+            .map(|message| message.map(MessageData::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        put(out, MessageIter { messages: messages.into_iter() })
+    })
+}
+
+/// Retrieve the next message from an iterator
+///
+/// Returns `GIT_DIT_ITEROVER` if there are no more messages.
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_iter_next(iter: *mut MessageIter,
+                                                   out: *mut *mut MessageData
+) -> c_int {
+    wrap(|| {
+        let iter = handle_arg_mut(iter, "iter")?;
+        match iter.messages.next() {
+            Some(message) => put(out, message),
+            None => Ok(GIT_DIT_ITEROVER),
+        }
+    })
+}
+
+/// Release a message iterator
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_iter_free(iter: *mut MessageIter) {
+    release(iter)
+}
+
+
+/// Find a message by its id
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_find(repo: *const RepositoryHandle,
+                                              id: *const c_char,
+                                              out: *mut *mut MessageData
+) -> c_int {
+    wrap(|| {
+        let repo = &handle_arg(repo, "repo")?.repo;
+        let commit = repo.find_commit(oid_arg(id, "id")?)?;
+        put(out, MessageData::from(commit))
+    })
+}
+
+/// Add a new message to an issue
+///
+/// The message is created as a reply to the message with the id `parent`,
+/// with the text supplied and an empty tree. Author and committer are taken
+/// from the repository's configuration.
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_add(repo: *const RepositoryHandle,
+                                             issue: *const IssueData,
+                                             parent: *const c_char,
+                                             message: *const c_char,
+                                             out: *mut *mut MessageData
+) -> c_int {
+    wrap(|| {
+        let repo = &handle_arg(repo, "repo")?.repo;
+        let issue = handle_arg(issue, "issue")?.handle.bind(repo)?;
+        let parent = repo.find_commit(oid_arg(parent, "parent")?)?;
+        let message = message_arg(message)?;
+        let sig = repo.signature()?;
+        let message = issue.add_message(&sig, &sig, message, &repo.empty_tree()?, Some(&parent))?;
+        put(out, MessageData::from(message))
+    })
+}
+
+/// Get the id of a message
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_id(message: *const MessageData) -> *const c_char {
+    message.as_ref().map(|message| message.id.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Get the subject line of a message
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_subject(message: *const MessageData) -> *const c_char {
+    message.as_ref().map(|message| message.subject.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Get the full text of a message, including any trailers
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_text(message: *const MessageData) -> *const c_char {
+    message.as_ref().map(|message| message.text.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Get the name of a message's author
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_author_name(message: *const MessageData) -> *const c_char {
+    message.as_ref().map(|message| message.author_name.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Get the email address of a message's author
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_author_email(message: *const MessageData) -> *const c_char {
+    message.as_ref().map(|message| message.author_email.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Get the time at which a message was authored, in seconds since the epoch
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_time(message: *const MessageData) -> i64 {
+    message.as_ref().map(|message| message.time).unwrap_or(0)
+}
+
+/// Release a message handle
+///
+#[no_mangle]
+pub unsafe extern "C" fn git_dit_message_free(message: *mut MessageData) {
+    release(message)
+}
+
+
+/// Run an operation, translating failures and panics into error codes
+///
+fn wrap<F>(f: F) -> c_int
+    where F: FnOnce() -> Result<c_int, Failure> + UnwindSafe
+{
+    match panic::catch_unwind(f) {
+        Ok(Ok(code)) => code,
+        Ok(Err(failure)) => failure.record(),
+        Err(_) => {
+            set_last_error("Internal error".to_owned());
+            GIT_DIT_ERROR
+        },
+    }
+}
+
+/// Set the last error of the calling thread
+///
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(c_string(message)));
+}
+
+/// Create a C string, truncating the data at the first NUL byte
+///
+fn c_string<T: Into<Vec<u8>>>(data: T) -> CString {
+    let mut data = data.into();
+    if let Some(pos) = data.iter().position(|byte| *byte == 0) {
+        data.truncate(pos);
+    }
+    CString::new(data).unwrap_or_default()
+}
+
+/// Hand an object to the caller via an out-parameter
+///
+unsafe fn put<T>(out: *mut *mut T, value: T) -> Result<c_int, Failure> {
+    if out.is_null() {
+        return Err(Failure::Invalid("Missing out-parameter".to_owned()));
+    }
+    *out = Box::into_raw(Box::new(value));
+    Ok(GIT_DIT_OK)
+}
+
+/// Release an object handed to the caller
+///
+unsafe fn release<T>(handle: *mut T) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Access a handle passed by the caller
+///
+unsafe fn handle_arg<'a, T>(handle: *const T, name: &str) -> Result<&'a T, Failure> {
+    handle
+        .as_ref()
+        .ok_or_else(|| Failure::Invalid(format!("Argument '{}' is null", name)))
+}
+
+/// Access a handle passed by the caller mutably
+///
+unsafe fn handle_arg_mut<'a, T>(handle: *mut T, name: &str) -> Result<&'a mut T, Failure> {
+    handle
+        .as_mut()
+        .ok_or_else(|| Failure::Invalid(format!("Argument '{}' is null", name)))
+}
+
+/// Access a string passed by the caller
+///
+unsafe fn str_arg<'a>(string: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if string.is_null() {
+        return Err(Failure::Invalid(format!("Argument '{}' is null", name)));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| Failure::Invalid(format!("Argument '{}' is not valid UTF-8", name)))
+}
+
+/// Parse an object id passed by the caller
+///
+unsafe fn oid_arg(id: *const c_char, name: &str) -> Result<Oid, Failure> {
+    Oid::from_str(str_arg(id, name)?)
+        .map_err(|_| Failure::Invalid(format!("Argument '{}' is not an object id", name)))
+}
+
+/// Access and check the text of a new message passed by the caller
+///
+unsafe fn message_arg<'a>(message: *const c_char) -> Result<&'a str, Failure> {
+    let message = str_arg(message, "message")?;
+    message
+        .lines()
+        .check_message_format()
+        .map_err(|err| Failure::Invalid(format!("Malformed message: {}", err)))?;
+    Ok(message)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Read a string returned by an accessor
+    ///
+    unsafe fn read(string: *const c_char) -> String {
+        assert!(!string.is_null());
+        CStr::from_ptr(string).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn c_interface() {
+        let path = ::std::env::temp_dir().join("git-dit-ffi-c_interface");
+        fs::remove_dir_all(&path).ok();
+        {
+            let repo = git2::Repository::init_bare(&path).expect("Could not init repository");
+            let mut config = repo.config().expect("Could not open config");
+            config.set_str("user.name", "Foo Bar").expect("Could not set name");
+            config.set_str("user.email", "foo.bar@example.com").expect("Could not set email");
+        }
+        let path = CString::new(path.to_string_lossy().into_owned()).unwrap();
+
+        unsafe {
+            let mut repo = ptr::null_mut();
+            assert_eq!(git_dit_repository_open(path.as_ptr(), &mut repo), GIT_DIT_OK);
+
+            let mut issue = ptr::null_mut();
+            let text = CString::new("Test message 1\n\nSome text").unwrap();
+            assert_eq!(git_dit_issue_create(repo, text.as_ptr(), &mut issue), GIT_DIT_OK);
+            let id = CString::new(read(git_dit_issue_id(issue))).unwrap();
+
+            let mut message = ptr::null_mut();
+            let text = CString::new("Test message 2").unwrap();
+            assert_eq!(git_dit_message_add(repo, issue, id.as_ptr(), text.as_ptr(), &mut message), GIT_DIT_OK);
+            assert_eq!(read(git_dit_message_subject(message)), "Test message 2");
+            assert_eq!(read(git_dit_message_author_name(message)), "Foo Bar");
+            assert_eq!(read(git_dit_message_author_email(message)), "foo.bar@example.com");
+            git_dit_message_free(message);
+
+            let mut found = ptr::null_mut();
+            assert_eq!(git_dit_issue_find(repo, id.as_ptr(), &mut found), GIT_DIT_OK);
+            assert_eq!(read(git_dit_issue_id(found)), read(git_dit_issue_id(issue)));
+            git_dit_issue_free(found);
+
+            let mut issues = ptr::null_mut();
+            assert_eq!(git_dit_issues(repo, &mut issues), GIT_DIT_OK);
+            let mut item = ptr::null_mut();
+            assert_eq!(git_dit_issue_iter_next(issues, &mut item), GIT_DIT_OK);
+            git_dit_issue_free(item);
+            assert_eq!(git_dit_issue_iter_next(issues, &mut item), GIT_DIT_ITEROVER);
+            git_dit_issue_iter_free(issues);
+
+            let mut messages = ptr::null_mut();
+            assert_eq!(git_dit_messages(repo, issue, &mut messages), GIT_DIT_OK);
+            let mut count = 0;
+            while git_dit_message_iter_next(messages, &mut message) == GIT_DIT_OK {
+                count += 1;
+                git_dit_message_free(message);
+            }
+            assert_eq!(count, 2);
+            git_dit_message_iter_free(messages);
+
+            let malformed = CString::new("Subject\nNo blank line").unwrap();
+            assert_eq!(git_dit_issue_create(repo, malformed.as_ptr(), &mut found), GIT_DIT_EINVALID);
+            assert!(!git_dit_last_error().is_null());
+            let unknown = CString::new("0000000000000000000000000000000000000000").unwrap();
+            assert_eq!(git_dit_message_find(repo, unknown.as_ptr(), &mut message), GIT_DIT_ERROR);
+            assert_eq!(git_dit_issues(ptr::null(), &mut issues), GIT_DIT_EINVALID);
+
+            git_dit_issue_free(issue);
+            git_dit_repository_free(repo);
+        }
+    }
+}
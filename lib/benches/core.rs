@@ -0,0 +1,113 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Benchmarks of core paths
+//!
+//! The benchmarks operate on synthetic trackers generated via the
+//! `TrackerGenerator`. Trackers are generated in the system's temporary
+//! directory once per benchmark run.
+//!
+
+#[macro_use] extern crate criterion;
+extern crate git2;
+extern crate libgitdit;
+
+use criterion::Criterion;
+use git2::Repository;
+use libgitdit::RepositoryExt;
+use libgitdit::generator::TrackerGenerator;
+use libgitdit::iter::MessagesExt;
+use libgitdit::trailer::spec;
+use std::fs;
+
+
+/// Sizes of the trackers benchmarked, in numbers of issues
+///
+const SIZES: [usize; 2] = [100, 1000];
+
+/// Number of replies generated for each issue
+///
+const REPLIES: usize = 5;
+
+
+/// Create a repository holding a synthetic tracker
+///
+fn tracker(issues: usize) -> Repository {
+    let path = ::std::env::temp_dir().join(format!("git-dit-bench-{}", issues));
+    fs::remove_dir_all(&path).ok();
+    let repo = Repository::init_bare(&path).expect("Could not init repository");
+    TrackerGenerator::new(&repo)
+        .issues(issues)
+        .replies(REPLIES)
+        .generate()
+        .expect("Could not generate tracker");
+    repo
+}
+
+
+fn issue_enumeration(c: &mut Criterion) {
+    for &size in SIZES.iter() {
+        let repo = tracker(size);
+        c.bench_function(&format!("issues/{}", size), |b| b.iter(|| {
+            repo.issues().expect("Could not retrieve issues").len()
+        }));
+    }
+}
+
+fn metadata_accumulation(c: &mut Criterion) {
+    let specs = [spec::ISSUE_STATUS_SPEC, spec::ISSUE_TAG_SPEC];
+    for &size in SIZES.iter() {
+        let repo = tracker(size);
+        let issues: Vec<_> = repo.issues().expect("Could not retrieve issues").into_iter().collect();
+        c.bench_function(&format!("metadata/{}", size), |b| b.iter(|| {
+            for issue in &issues {
+                let head = issue
+                    .local_head_target()
+                    .expect("Could not retrieve head")
+                    .expect("No local head");
+                issue
+                    .messages_from(head)
+                    .expect("Could not retrieve messages")
+                    .map(|message| message.expect("Could not retrieve message"))
+                    .accumulate_trailers(specs.iter());
+            }
+        }));
+    }
+}
+
+fn message_iteration(c: &mut Criterion) {
+    for &size in SIZES.iter() {
+        let repo = tracker(size);
+        let issues: Vec<_> = repo.issues().expect("Could not retrieve issues").into_iter().collect();
+        c.bench_function(&format!("messages/{}", size), |b| b.iter(|| {
+            issues
+                .iter()
+                .map(|issue| issue.messages().expect("Could not retrieve messages").count())
+                .sum::<usize>()
+        }));
+    }
+}
+
+fn gc_discovery(c: &mut Criterion) {
+    for &size in SIZES.iter() {
+        let repo = tracker(size);
+        let issues: Vec<_> = repo.issues().expect("Could not retrieve issues").into_iter().collect();
+        c.bench_function(&format!("gc/{}", size), |b| b.iter(|| {
+            let collect = repo.collectable_refs();
+            issues
+                .iter()
+                .map(|issue| collect.for_issue(issue).expect("Could not collect references").count())
+                .sum::<usize>()
+        }));
+    }
+}
+
+
+criterion_group!(benches, issue_enumeration, metadata_accumulation, message_iteration, gc_discovery);
+criterion_main!(benches);
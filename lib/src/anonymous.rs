@@ -0,0 +1,134 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Anonymous reporting
+//!
+//! Projects may accept anonymous messages by committing them with a shared
+//! anonymous signature. In order to still be able to recognize messages from
+//! the same reporter, e.g. for deduplicating reports, a keyed hash (HMAC) of
+//! the real author's email address is recorded in a `Dit-reporter-hmac`
+//! trailer. Without the key, which is configured for the repository and never
+//! published, the hash does not reveal the reporter's identity.
+//!
+
+use git2::Signature;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use trailer::Trailer;
+use trailer::spec::REPORTER_HMAC_SPEC;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Anonymous identity
+///
+/// An anonymous identity consists of the signature used for authoring and
+/// committing messages and the key used for hashing the real authors' email
+/// addresses.
+///
+pub struct AnonymousIdentity {
+    name: String,
+    email: String,
+    key: Vec<u8>,
+}
+
+impl AnonymousIdentity {
+    /// Create a new anonymous identity
+    ///
+    /// An empty key is rejected, since it would permit anyone to identify
+    /// reporters.
+    ///
+    pub fn new<N, E, K>(name: N, email: E, key: K) -> Result<Self>
+        where N: Into<String>,
+              E: Into<String>,
+              K: Into<Vec<u8>>
+    {
+        let key = key.into();
+        if key.is_empty() {
+            return Err(Error::from_kind(EK::MissingAnonymityKey));
+        }
+        Ok(AnonymousIdentity { name: name.into(), email: email.into(), key: key })
+    }
+
+    /// Get a signature for the current time
+    ///
+    pub fn signature(&self) -> Result<Signature<'static>> {
+        Signature::now(&self.name, &self.email).map_err(From::from)
+    }
+
+    /// Compute the reporter hash for an email address
+    ///
+    /// The email address is normalized prior to hashing. The hash is returned
+    /// in hexadecimal form.
+    ///
+    pub fn reporter_hmac(&self, email: &str) -> String {
+        // The key was checked to be non-empty and HMAC accepts keys of any
+        // length, so this will never fail.
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key)
+            .expect("HMAC rejected key");
+        mac.update(email.trim().to_lowercase().as_bytes());
+
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Create a reporter trailer for a real author
+    ///
+    pub fn reporter_trailer(&self, author: &Signature) -> Trailer {
+        let hash = self.reporter_hmac(author.email().unwrap_or(""));
+        Trailer::new(REPORTER_HMAC_SPEC.key, &hash)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // AnonymousIdentity tests
+
+    #[test]
+    fn empty_key() {
+        assert!(AnonymousIdentity::new("Anonymous", "anonymous@example.com", "").is_err());
+    }
+
+    #[test]
+    fn reporter_hmac() {
+        let identity = AnonymousIdentity::new("Anonymous", "anonymous@example.com", "secret")
+            .expect("Could not create identity");
+        let other = AnonymousIdentity::new("Anonymous", "anonymous@example.com", "other")
+            .expect("Could not create identity");
+
+        let hash = identity.reporter_hmac("foo.bar@example.com");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, identity.reporter_hmac(" Foo.Bar@example.com"));
+        assert_ne!(hash, identity.reporter_hmac("baz@example.com"));
+        assert_ne!(hash, other.reporter_hmac("foo.bar@example.com"));
+        assert!(!hash.contains("foo"));
+    }
+
+    #[test]
+    fn reporter_trailer() {
+        let identity = AnonymousIdentity::new("Anonymous", "anonymous@example.com", "secret")
+            .expect("Could not create identity");
+        let author = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+
+        let trailer = identity.reporter_trailer(&author);
+        assert_eq!(trailer.key.as_ref(), "Dit-reporter-hmac");
+        assert_eq!(trailer.value.to_string(), identity.reporter_hmac("foo.bar@example.com"));
+    }
+}
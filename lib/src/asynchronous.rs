@@ -0,0 +1,388 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Asynchronous interface
+//!
+//! This module provides the `AsyncRepository`, which exposes the main
+//! operations of `RepositoryExt` as futures, e.g. for integration into async
+//! servers and bots. It is only available if the library is built with the
+//! "async" feature.
+//!
+//! The interface does not depend on any particular async runtime. Operations
+//! are run on a pool of worker threads owned by the `AsyncRepository` and
+//! their results are delivered via `Task`s, which implement `Future`. Since
+//! repository handles may not be shared between threads, each worker opens a
+//! handle of its own. For the same reason, issues are represented by
+//! `IssueHandle`s rather than `Issue`s and messages are identified by their
+//! ids.
+//!
+
+use git2::{self, Oid, Repository};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, mpsc};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+
+use issue::IssueHandle;
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Operation run by a worker
+///
+type Job = Box<dyn FnOnce(&Repository) + Send>;
+
+
+/// Owned identity used for creating messages
+///
+/// Unlike `git2::Signature`, an `Identity` may be sent to a worker. Messages
+/// are signed with the time of their creation.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+}
+
+impl Identity {
+    /// Create a new identity
+    ///
+    pub fn new<N, E>(name: N, email: E) -> Self
+        where N: Into<String>,
+              E: Into<String>
+    {
+        Identity { name: name.into(), email: email.into() }
+    }
+
+    /// Create a signature for the current time
+    ///
+    fn signature(&self) -> Result<git2::Signature<'static>> {
+        git2::Signature::now(&self.name, &self.email).chain_err(|| EK::CannotCreateMessage)
+    }
+}
+
+
+/// Repository operated on by worker threads
+///
+pub struct AsyncRepository {
+    path: PathBuf,
+    jobs: Mutex<mpsc::Sender<Job>>,
+}
+
+impl AsyncRepository {
+    /// Open a repository with a given number of worker threads
+    ///
+    /// If zero threads are requested, one thread per CPU is used. The
+    /// workers terminate when the `AsyncRepository` is dropped, after
+    /// finishing the operations already requested.
+    ///
+    pub fn open<P: AsRef<Path>>(path: P, threads: usize) -> Result<Self> {
+        // Workers open their handles via the path of the git directory, which
+        // is determined by opening the repository once.
+        let path = Repository::open(path)?.path().to_owned();
+
+        let threads = match threads {
+            0 => thread::available_parallelism().map(usize::from).unwrap_or(1),
+            n => n,
+        };
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            let path = path.clone();
+            thread::spawn(move || {
+                let repo = match Repository::open(&path) {
+                    Ok(repo) => repo,
+                    Err(_) => return,
+                };
+                loop {
+                    let job = match receiver.lock().map(|jobs| jobs.recv()) {
+                        Ok(Ok(job)) => job,
+                        _ => return,
+                    };
+                    // A panicking job only fails its own task.
+                    panic::catch_unwind(AssertUnwindSafe(|| job(&repo))).ok();
+                }
+            });
+        }
+
+        Ok(AsyncRepository { path: path, jobs: Mutex::new(sender) })
+    }
+
+    /// Get the path of the repository's git directory
+    ///
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run an arbitrary operation on a worker
+    ///
+    /// The function is passed the worker's repository handle.
+    ///
+    pub fn run<T, F>(&self, f: F) -> Task<T>
+        where T: Send + 'static,
+              F: FnOnce(&Repository) -> Result<T> + Send + 'static
+    {
+        let (task, completer) = Task::new();
+        let job: Job = Box::new(move |repo| completer.complete(f(repo)));
+
+        // If the job can't be sent, it is dropped along with the completer,
+        // which fails the task.
+        if let Ok(jobs) = self.jobs.lock() {
+            jobs.send(job).ok();
+        }
+        task
+    }
+
+    /// Get all issues
+    ///
+    /// See `RepositoryExt::issues()`.
+    ///
+    pub fn issues(&self) -> Task<Vec<IssueHandle>> {
+        self.run(|repo| Ok(repo.issues()?.into_iter().map(|issue| issue.handle()).collect()))
+    }
+
+    /// Find an issue by its id
+    ///
+    /// See `RepositoryExt::find_issue()`.
+    ///
+    pub fn find_issue(&self, id: Oid) -> Task<IssueHandle> {
+        self.run(move |repo| repo.find_issue(id).map(|issue| issue.handle()))
+    }
+
+    /// Get the issue a message belongs to
+    ///
+    /// See `RepositoryExt::issue_with_message()`.
+    ///
+    pub fn issue_with_message(&self, message: Oid) -> Task<IssueHandle> {
+        self.run(move |repo| {
+            let message = repo.find_commit(message).chain_err(|| EK::CannotGetCommit(message))?;
+            repo.issue_with_message(&message).map(|issue| issue.handle())
+        })
+    }
+
+    /// Get the ids of the messages of an issue
+    ///
+    /// See `Issue::messages()`.
+    ///
+    pub fn messages(&self, issue: IssueHandle) -> Task<Vec<Oid>> {
+        self.run(move |repo| issue
+            .bind(repo)?
+            .messages()?
+            .map(|message| message.map(|message| message.id()))
+            .collect())
+    }
+
+    /// Create a new issue
+    ///
+    /// The initial message is created with an empty tree. See
+    /// `RepositoryExt::create_issue_in_component()`.
+    ///
+    pub fn create_issue(&self,
+                        component: Option<String>,
+                        author: Identity,
+                        committer: Identity,
+                        message: String,
+                        parents: Vec<Oid>
+    ) -> Task<IssueHandle> {
+        self.run(move |repo| {
+            let parents = find_commits(repo, &parents)?;
+            repo.create_issue_in_component(component.as_ref().map(String::as_str),
+                                           &author.signature()?,
+                                           &committer.signature()?,
+                                           message,
+                                           &repo.empty_tree()?,
+                                           parents.iter())
+                .map(|issue| issue.handle())
+        })
+    }
+
+    /// Add a new message to an issue
+    ///
+    /// The message is created with an empty tree. Returns the id of the new
+    /// message. See `Issue::add_message()`.
+    ///
+    pub fn add_message(&self,
+                       issue: IssueHandle,
+                       author: Identity,
+                       committer: Identity,
+                       message: String,
+                       parents: Vec<Oid>
+    ) -> Task<Oid> {
+        self.run(move |repo| {
+            let parents = find_commits(repo, &parents)?;
+            issue
+                .bind(repo)?
+                .add_message(&author.signature()?,
+                             &committer.signature()?,
+                             message,
+                             &repo.empty_tree()?,
+                             parents.iter())
+                .map(|message| message.id())
+        })
+    }
+}
+
+
+/// Result of an operation run by a worker
+///
+/// A `Task` is a `Future` which completes once the worker finished the
+/// operation. If the operation panics or can't be run, the task completes
+/// with an error.
+///
+pub struct Task<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Task<T> {
+    /// Create a new task along with the completer for the worker
+    ///
+    fn new() -> (Self, Completer<T>) {
+        let shared = Arc::new(Mutex::new(Shared { result: None, done: false, waker: None }));
+        (Task { shared: shared.clone() }, Completer { shared: shared })
+    }
+
+    /// Block the current thread until the operation is finished
+    ///
+    pub fn wait(mut self) -> Result<T> {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(result) = Pin::new(&mut self).poll(&mut context) {
+                return result;
+            }
+            thread::park();
+        }
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_) => return Poll::Ready(Err(Error::from_kind(EK::WorkerFailed))),
+        };
+        if shared.done {
+            Poll::Ready(shared.result.take().unwrap_or_else(|| Err(Error::from_kind(EK::WorkerFailed))))
+        } else {
+            shared.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+
+/// State shared between a `Task` and its `Completer`
+///
+struct Shared<T> {
+    result: Option<Result<T>>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+
+/// Handle for completing a `Task`
+///
+/// The task is completed when the completer is dropped, with an error if no
+/// result was supplied.
+///
+struct Completer<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Completer<T> {
+    /// Complete the task with a result
+    ///
+    fn complete(self, result: Result<T>) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.result = Some(result);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        let waker = match self.shared.lock() {
+            Ok(mut shared) => {
+                shared.done = true;
+                shared.waker.take()
+            },
+            Err(_) => None,
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+
+/// Waker unparking a thread
+///
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+
+/// Look up the commits with the given ids
+///
+fn find_commits<'r>(repo: &'r Repository, ids: &[Oid]) -> Result<Vec<git2::Commit<'r>>> {
+    ids.iter()
+        .map(|id| repo.find_commit(*id).chain_err(|| EK::CannotGetCommit(*id)))
+        .collect()
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    #[test]
+    fn async_repository() {
+        let mut testing_repo = TestingRepo::new("async_repository");
+        let repo = AsyncRepository::open(testing_repo.repo().path(), 2)
+            .expect("Could not open repository");
+        let identity = Identity::new("Foo Bar", "foo.bar@example.com");
+
+        let issue = repo
+            .create_issue(None, identity.clone(), identity.clone(), "Test message 1".to_owned(), vec![])
+            .wait()
+            .expect("Could not create issue");
+        let message = repo
+            .add_message(issue.clone(), identity.clone(), identity.clone(), "Test message 2".to_owned(), vec![issue.id()])
+            .wait()
+            .expect("Could not add message");
+
+        assert_eq!(repo.issues().wait().expect("Could not retrieve issues"), vec![issue.clone()]);
+        assert_eq!(repo.find_issue(issue.id()).wait().expect("Could not find issue"), issue);
+        assert_eq!(repo.issue_with_message(message).wait().expect("Could not find issue"), issue);
+
+        let mut messages = repo.messages(issue.clone()).wait().expect("Could not retrieve messages");
+        messages.sort();
+        let mut expected = vec![issue.id(), message];
+        expected.sort();
+        assert_eq!(messages, expected);
+
+        assert!(repo.run(|_| -> Result<()> { panic!("Failing job") }).wait().is_err());
+        assert!(repo.issue_with_message(Oid::zero()).wait().is_err());
+    }
+}
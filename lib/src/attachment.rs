@@ -0,0 +1,186 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Attachments
+//!
+//! This module provides the `Attachments` type for assembling the tree of a
+//! new message from files attached to it. Each file is stored as a blob which
+//! is referenced from the tree under the file's name. Like the rest of the
+//! tree, attachments are inherited by replies which reuse their parent's tree.
+//!
+//! Since blobs are addressed by their contents, a file which is already present
+//! in the repository, e.g. because it was attached to another message, is not
+//! stored again. Such files are reported as deduplicated, which lets frontends
+//! inform users about how much a message actually adds to the repository.
+//! In addition, the total size of the files attached to a message may be
+//! limited.
+//!
+
+use git2::{self, ObjectType, Oid};
+use std::collections::HashMap;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Summary of the files attached to a message
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttachmentReport {
+    /// Number of bytes written to the repository
+    pub stored: u64,
+    /// Number of bytes of files which were already present in the repository
+    pub deduplicated: u64,
+    /// Names of the files which were already present in the repository
+    pub duplicates: Vec<String>,
+}
+
+
+/// Builder for the tree of a message with attachments
+///
+pub struct Attachments<'r> {
+    repo: &'r git2::Repository,
+    builder: git2::TreeBuilder<'r>,
+    limit: Option<u64>,
+    sizes: HashMap<String, u64>,
+    report: AttachmentReport,
+}
+
+impl<'r> Attachments<'r> {
+    /// Start a new tree, optionally based on an existing one
+    ///
+    /// Usually, the base is the tree of the message replied to. Files in the
+    /// base don't count towards the size limit.
+    ///
+    pub fn new(repo: &'r git2::Repository, base: Option<&git2::Tree>) -> Result<Self> {
+        let builder = repo.treebuilder(base).chain_err(|| EK::CannotBuildTree)?;
+        Ok(Attachments {
+            repo: repo,
+            builder: builder,
+            limit: None,
+            sizes: HashMap::new(),
+            report: Default::default(),
+        })
+    }
+
+    /// Limit the total size of the files attached, in bytes
+    ///
+    pub fn with_limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Attach a file
+    ///
+    /// The file replaces any file of the same name, including files inherited
+    /// from the base tree. Names must not contain slashes. Returns the id of
+    /// the blob holding the file's contents.
+    ///
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<Oid> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(Error::from_kind(EK::CannotAttach(name.to_owned())));
+        }
+
+        let size = data.len() as u64;
+        if let Some(limit) = self.limit {
+            let others: u64 = self
+                .sizes
+                .iter()
+                .filter(|&(other, _)| other != name)
+                .map(|(_, size)| size)
+                .sum();
+            if others + size > limit {
+                return Err(Error::from_kind(EK::AttachmentLimitExceeded(name.to_owned(), limit)));
+            }
+        }
+
+        let id = Oid::hash_object(ObjectType::Blob, data)
+            .chain_err(|| EK::CannotAttach(name.to_owned()))?;
+        let present = self.repo
+            .odb()
+            .map(|odb| odb.exists(id))
+            .chain_err(|| EK::CannotAttach(name.to_owned()))?;
+        if present {
+            self.report.deduplicated += size;
+            self.report.duplicates.push(name.to_owned());
+        } else {
+            self.repo.blob(data).chain_err(|| EK::CannotAttach(name.to_owned()))?;
+            self.report.stored += size;
+        }
+
+        self.builder
+            .insert(name, id, 0o100644)
+            .chain_err(|| EK::CannotAttach(name.to_owned()))?;
+        self.sizes.insert(name.to_owned(), size);
+        Ok(id)
+    }
+
+    /// Get the total size of the files attached, in bytes
+    ///
+    pub fn size(&self) -> u64 {
+        self.sizes.values().sum()
+    }
+
+    /// Get a summary of the files attached so far
+    ///
+    pub fn report(&self) -> &AttachmentReport {
+        &self.report
+    }
+
+    /// Write the tree
+    ///
+    pub fn write(self) -> Result<git2::Tree<'r>> {
+        self.builder
+            .write()
+            .and_then(|id| self.repo.find_tree(id))
+            .chain_err(|| EK::CannotBuildTree)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    #[test]
+    fn attachments() {
+        let mut testing_repo = TestingRepo::new("attachments");
+        let repo = testing_repo.repo();
+
+        let mut attachments = Attachments::new(repo, None)
+            .expect("Could not start tree")
+            .with_limit(Some(12));
+        attachments.add("foo.txt", b"foo").expect("Could not attach file");
+        attachments.add("bar.txt", b"foo").expect("Could not attach file");
+        assert!(attachments.add("baz.txt", b"too large").is_err());
+        assert!(attachments.add("foo/bar", b"").is_err());
+        attachments.add("foo.txt", b"replaced").expect("Could not attach file");
+        assert_eq!(attachments.size(), 11);
+        {
+            let report = attachments.report();
+            assert_eq!(report.stored, 11);
+            assert_eq!(report.deduplicated, 3);
+            assert_eq!(report.duplicates, vec!["bar.txt".to_owned()]);
+        }
+        let tree = attachments.write().expect("Could not write tree");
+        assert_eq!(tree.len(), 2);
+
+        // files of the base are inherited but don't count towards the limit
+        let mut attachments = Attachments::new(repo, Some(&tree))
+            .expect("Could not start tree")
+            .with_limit(Some(3));
+        attachments.add("baz.txt", b"foo").expect("Could not attach file");
+        assert_eq!(attachments.report().duplicates, vec!["baz.txt".to_owned()]);
+        let tree = attachments.write().expect("Could not write tree");
+        assert_eq!(tree.len(), 3);
+    }
+}
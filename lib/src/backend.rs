@@ -0,0 +1,184 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Primitive repository operations
+//!
+//! This module provides the `Backend` trait, which captures a few primitive
+//! read operations on a repository: enumerating references and reading
+//! messages. It is implemented for `git2::Repository` only.
+//!
+//! The trait is used by the `MessageIndex` and the search index, which only
+//! need these operations. The rest of the library, including `RepositoryExt`,
+//! `Issue` and the iterators, is written against git2 directly. Objects are
+//! identified by git2's `Oid`.
+//!
+
+use git2::{self, Oid};
+use std::path::Path;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Primitive repository operations
+///
+pub trait Backend {
+    /// Get the path of the repository's git directory
+    ///
+    fn git_dir(&self) -> &Path;
+
+    /// Get the names of all configured remotes
+    ///
+    fn remotes(&self) -> Result<Vec<String>>;
+
+    /// Get the names and targets of the references matching a glob
+    ///
+    /// Symbolic references and references with names which are not valid
+    /// UTF-8 are not reported.
+    ///
+    fn references(&self, glob: &str) -> Result<Vec<(String, Oid)>>;
+
+    /// Get the target of a reference
+    ///
+    /// Returns `None` if the reference does not exist.
+    ///
+    fn reference_target(&self, name: &str) -> Result<Option<Oid>>;
+
+    /// Get the ids of a message's parents
+    ///
+    fn parent_ids(&self, message: Oid) -> Result<Vec<Oid>>;
+
+    /// Get the id of a message's first parent
+    ///
+    fn first_parent(&self, message: Oid) -> Result<Option<Oid>> {
+        self.parent_ids(message).map(|parents| parents.first().cloned())
+    }
+
+    /// Get the raw text of a message
+    ///
+    fn message_text(&self, message: Oid) -> Result<String>;
+}
+
+impl Backend for git2::Repository {
+    fn git_dir(&self) -> &Path {
+        self.path()
+    }
+
+    fn remotes(&self) -> Result<Vec<String>> {
+        let remotes = git2::Repository::remotes(self)?;
+        Ok(remotes.iter().flatten().map(String::from).collect())
+    }
+
+    fn references(&self, glob: &str) -> Result<Vec<(String, Oid)>> {
+        let mut retval = Vec::new();
+        for reference in self.references_glob(glob).chain_err(|| EK::CannotGetReferences(glob.to_owned()))? {
+            let reference = reference?;
+            if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+                retval.push((name.to_owned(), target));
+            }
+        }
+        Ok(retval)
+    }
+
+    fn reference_target(&self, name: &str) -> Result<Option<Oid>> {
+        match self.find_reference(name) {
+            Ok(reference) => Ok(reference.target()),
+            Err(ref err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err).chain_err(|| EK::CannotGetReference(name.to_owned())),
+        }
+    }
+
+    fn parent_ids(&self, message: Oid) -> Result<Vec<Oid>> {
+        self.find_commit(message)
+            .map(|commit| commit.parent_ids().collect())
+            .chain_err(|| EK::CannotGetCommit(message))
+    }
+
+    fn first_parent(&self, message: Oid) -> Result<Option<Oid>> {
+        self.find_commit(message)
+            .map(|commit| commit.parent_id(0).ok())
+            .chain_err(|| EK::CannotGetCommit(message))
+    }
+
+    fn message_text(&self, message: Oid) -> Result<String> {
+        let commit = self.find_commit(message).chain_err(|| EK::CannotGetCommit(message))?;
+        commit
+            .message()
+            .map(String::from)
+            .ok_or_else(|| Error::from_kind(EK::NonUtf8Message(message)))
+    }
+}
+
+
+/// Get the dit references matching a pattern
+///
+/// Like `iter::DitRefs`, this function considers the local `refs/dit/`
+/// namespace and the `dit/` namespaces of all configured remotes.
+///
+pub fn dit_references<B>(backend: &B, pattern: &str) -> Result<Vec<(String, Oid)>>
+    where B: Backend + ?Sized
+{
+    let mut retval = backend.references(&format!("refs/dit/{}", pattern))?;
+    for remote in backend.remotes()? {
+        retval.extend(backend.references(&format!("refs/remotes/{}/dit/{}", remote, pattern))?);
+    }
+    Ok(retval)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use repository::RepositoryExt;
+
+    #[test]
+    fn git2_backend() {
+        let mut testing_repo = TestingRepo::new("git2_backend");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let backend: &dyn Backend = repo;
+        let mut refs = dit_references(backend, "**")
+            .expect("Could not retrieve references");
+        refs.sort();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].0, format!("refs/dit/{}/head", issue.id()));
+        assert_eq!(refs[0].1, issue.id());
+        assert_eq!(refs[1].0, format!("refs/dit/{}/leaves/{}", issue.id(), message.id()));
+
+        let head = backend
+            .reference_target(&refs[0].0)
+            .expect("Could not retrieve reference");
+        assert_eq!(head, Some(issue.id()));
+        assert_eq!(backend.reference_target("refs/dit/foo").expect("Could not retrieve reference"), None);
+
+        assert_eq!(backend.parent_ids(message.id()).expect("Could not retrieve parents"), vec![issue.id()]);
+        assert_eq!(backend.first_parent(issue.id()).expect("Could not retrieve parent"), None);
+        assert_eq!(backend.message_text(message.id()).expect("Could not retrieve message"), "Test message 2");
+    }
+}
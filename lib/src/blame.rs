@@ -0,0 +1,158 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Metadata attribution
+//!
+//! The metadata of an issue is accumulated from the trailers of its messages.
+//! This module provides functionality for determining the message which
+//! established each of the values in effect, e.g. the message which closed an
+//! issue or added a specific tag. This is the metadata analogue of
+//! `git blame`.
+//!
+
+use git2::Commit;
+
+use message::Message;
+use trailer::TrailerValue;
+use trailer::accumulation::ValueAccumulator;
+use trailer::spec::TrailerSpec;
+
+use error::*;
+
+
+/// Attribution of a metadata value to a message
+///
+pub struct Attribution<'r> {
+    key: String,
+    value: TrailerValue,
+    message: Commit<'r>,
+}
+
+impl<'r> Attribution<'r> {
+    /// Get the key of the trailer
+    ///
+    pub fn key(&self) -> &str {
+        self.key.as_ref()
+    }
+
+    /// Get the value in effect
+    ///
+    pub fn value(&self) -> &TrailerValue {
+        &self.value
+    }
+
+    /// Get the message which established the value
+    ///
+    pub fn message(&self) -> &Commit<'r> {
+        &self.message
+    }
+}
+
+
+/// Attribute the metadata in effect to messages
+///
+/// The messages supplied are expected to be ordered from the newest to the
+/// oldest one, e.g. as returned by `Issue::messages_from()` for an issue's
+/// head. The values in effect for the trailers specified are accumulated
+/// according to the specifications' policies. Each value is returned along
+/// with the message establishing it. The attributions are ordered by the
+/// specifications supplied and, within a specification, like the values
+/// accumulated.
+///
+pub fn blame<'r, 'k, I>(messages: I, specs: &[TrailerSpec<'k>]) -> Result<Vec<Attribution<'r>>>
+    where I: IntoIterator<Item = Result<Commit<'r>>>
+{
+    let mut accumulators: Vec<(ValueAccumulator, Vec<Attribution>)> = specs
+        .iter()
+        .map(|spec| (ValueAccumulator::from(spec.accumulation.clone()), Vec::new()))
+        .collect();
+
+    for message in messages {
+        let message = message?;
+        for trailer in message.trailers() {
+            let (key, value) = trailer.into();
+            let position = specs.iter().position(|spec| spec.key == key.as_ref());
+            let &mut (ref mut accumulator, ref mut attributions) = match position {
+                Some(position) => &mut accumulators[position],
+                None => continue,
+            };
+
+            // Values accumulated are always appended, so new values are found
+            // at the end.
+            let known = accumulator.clone().into_iter().count();
+            accumulator.process(value);
+            for value in accumulator.clone().into_iter().skip(known) {
+                attributions.push(Attribution {
+                    key: key.as_ref().to_owned(),
+                    value: value,
+                    message: message.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(accumulators
+        .into_iter()
+        .flat_map(|(_, attributions)| attributions)
+        .collect())
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use git2::Signature;
+    use repository::RepositoryExt;
+    use trailer::spec::{ISSUE_STATUS_SPEC, ISSUE_TAG_SPEC};
+
+    // blame tests
+
+    #[test]
+    fn metadata_blame() {
+        let mut testing_repo = TestingRepo::new("metadata_blame");
+        let repo = testing_repo.repo();
+
+        let sig = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1\n\nDit-status: open\nDit-tag: a", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 2\n\nDit-tag: b\nDit-tag: c", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let message3 = issue
+            .add_message(&sig, &sig, "Test message 3\n\nDit-status: closed\nDit-tag: -b", &empty_tree, vec![&message2])
+            .expect("Could not add message");
+
+        let messages = issue
+            .messages_from(message3.id())
+            .expect("Could not retrieve messages");
+        let attributions: Vec<_> = blame(messages, &[ISSUE_STATUS_SPEC, ISSUE_TAG_SPEC])
+            .expect("Could not attribute metadata")
+            .into_iter()
+            .map(|a| (a.key().to_owned(), a.value().to_string(), a.message().id()))
+            .collect();
+        assert_eq!(attributions, vec![
+            ("Dit-status".to_owned(), "closed".to_owned(), message3.id()),
+            ("Dit-tag".to_owned(), "c".to_owned(), message2.id()),
+            ("Dit-tag".to_owned(), "a".to_owned(), issue.id()),
+        ]);
+    }
+}
@@ -0,0 +1,426 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Configuration
+//!
+//! This module provides the `DitConfig` type for reading the `dit.*` options
+//! from a repository's git configuration. Each option is exposed via a typed
+//! accessor returning `None` if the option is not set, which leaves the choice
+//! of a default to the caller. Malformed values are reported as
+//! `InvalidConfigValue` errors naming the offending option.
+//!
+//! In addition, the module provides `IdentityProfile`s, which allow users to
+//! author messages under different identities, e.g. for contributing on behalf
+//! of different organizations.
+//!
+
+use git2;
+use std::result::Result as RResult;
+use std::str::FromStr;
+use std::time::Duration;
+
+use gc::{parse_age, ReferenceCollectionSpec};
+use issue::{check_component, HeadPolicy};
+use message::lint::{Rule, Severity};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Identity profile
+///
+/// A profile named `<name>` is configured via the options
+/// `dit.identity.<name>.name` and `dit.identity.<name>.email`, which default
+/// to `user.name` and `user.email`, respectively.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdentityProfile {
+    /// Name of the author
+    pub name: String,
+    /// Email address of the author
+    pub email: String,
+}
+
+impl IdentityProfile {
+    /// Create a signature for the profile using the current time
+    ///
+    pub fn signature(&self) -> Result<git2::Signature<'static>> {
+        git2::Signature::now(&self.name, &self.email).map_err(Error::from)
+    }
+}
+
+
+/// Typed access to the `dit.*` configuration options
+///
+pub struct DitConfig {
+    config: git2::Config,
+}
+
+impl DitConfig {
+    /// Create a new `DitConfig` reading from the given configuration
+    ///
+    pub fn new(config: git2::Config) -> Self {
+        DitConfig { config: config }
+    }
+
+    /// Open the configuration of a repository
+    ///
+    pub fn open(repo: &git2::Repository) -> Result<Self> {
+        repo.config().map(Self::new).map_err(Error::from)
+    }
+
+    /// Get the component in which new issues are created
+    ///
+    /// The component is read from `dit.component`. Issue references of new
+    /// issues are placed under `refs/dit/<component>/`.
+    ///
+    pub fn component(&self) -> Result<Option<String>> {
+        self.parsed("dit.component", |value| check_component(value).map(|_| value.to_owned()))
+    }
+
+    /// Get the policy for updating the head of an issue
+    ///
+    /// The policy is read from `dit.head-policy`.
+    ///
+    pub fn head_policy(&self) -> Result<Option<HeadPolicy>> {
+        self.parsed("dit.head-policy", HeadPolicy::from_str)
+    }
+
+    /// Check whether remote references are considered during collection
+    ///
+    /// The option is read from `dit.gc.consider-remotes`.
+    ///
+    pub fn gc_consider_remotes(&self) -> Result<Option<bool>> {
+        config_value(self.config.get_bool("dit.gc.consider-remotes"))
+    }
+
+    /// Get the condition under which heads are collected
+    ///
+    /// The condition is read from `dit.gc.collect-heads`, which may be "never"
+    /// or "backed-by-remote".
+    ///
+    pub fn gc_collect_heads(&self) -> Result<Option<ReferenceCollectionSpec>> {
+        let key = "dit.gc.collect-heads";
+        match config_value(self.config.get_string(key))? {
+            Some(ref value) if value == "never" => Ok(Some(ReferenceCollectionSpec::Never)),
+            Some(ref value) if value == "backed-by-remote" => Ok(Some(ReferenceCollectionSpec::BackedByRemoteHead)),
+            Some(value) => Err(Error::from_kind(EK::InvalidConfigValue(key.to_owned(), value))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the condition under which leaves are collected
+    ///
+    /// The condition is read from `dit.gc.collect-leaves`, which may be
+    /// "always" or "backed-by-remote".
+    ///
+    pub fn gc_collect_leaves(&self) -> Result<Option<ReferenceCollectionSpec>> {
+        let key = "dit.gc.collect-leaves";
+        match config_value(self.config.get_string(key))? {
+            Some(ref value) if value == "always" => Ok(Some(ReferenceCollectionSpec::Always)),
+            Some(ref value) if value == "backed-by-remote" => Ok(Some(ReferenceCollectionSpec::BackedByRemote)),
+            Some(value) => Err(Error::from_kind(EK::InvalidConfigValue(key.to_owned(), value))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the age references must have for being collected
+    ///
+    /// The age is read from `dit.gc.older-than`. See `gc::parse_age()` for
+    /// the format.
+    ///
+    pub fn gc_older_than(&self) -> Result<Option<Duration>> {
+        self.parsed("dit.gc.older-than", parse_age)
+    }
+
+    /// Get the patterns of references protected from collection
+    ///
+    /// The patterns are read from all values of `dit.gc.protect`.
+    ///
+    pub fn gc_protect(&self) -> Result<Vec<String>> {
+        let mut retval = Vec::new();
+        for entry in &self.config.multivar("dit.gc.protect", None)? {
+            retval.extend(entry?.value().map(String::from));
+        }
+        Ok(retval)
+    }
+
+    /// Get the default format for listing issues
+    ///
+    /// The format is read from `dit.list.format`. Formats are defined by
+    /// frontends, hence the value is not checked.
+    ///
+    pub fn list_format(&self) -> Result<Option<String>> {
+        config_value(self.config.get_string("dit.list.format"))
+    }
+
+    /// Get the keys of the trailers required for new issues
+    ///
+    /// The keys are read from all values of `dit.new.required`.
+    ///
+    pub fn required_trailers(&self) -> Result<Vec<String>> {
+        let mut retval = Vec::new();
+        for entry in &self.config.multivar("dit.new.required", None)? {
+            retval.extend(entry?.value().map(String::from));
+        }
+        Ok(retval)
+    }
+
+    /// Get the severity assigned to a lint rule
+    ///
+    /// The severity is read from `dit.lint.<rule>`.
+    ///
+    pub fn lint_severity(&self, rule: Rule) -> Result<Option<Severity>> {
+        self.parsed(&format!("dit.lint.{}", rule.name()), Severity::from_str)
+    }
+
+    /// Get the period of inactivity after which issues are considered stale
+    ///
+    /// The period is read from `dit.stale.age`. See `gc::parse_age()` for the
+    /// format.
+    ///
+    pub fn stale_age(&self) -> Result<Option<Duration>> {
+        self.parsed("dit.stale.age", parse_age)
+    }
+
+    /// Get the tags of issues never considered stale
+    ///
+    /// The tags are read from all values of `dit.stale.exclude-tag`.
+    ///
+    pub fn stale_excluded_tags(&self) -> Result<Vec<String>> {
+        let mut retval = Vec::new();
+        for entry in &self.config.multivar("dit.stale.exclude-tag", None)? {
+            retval.extend(entry?.value().map(String::from));
+        }
+        Ok(retval)
+    }
+
+    /// Get the maximum length of subject lines
+    ///
+    /// The length is read from `dit.lint.max-subject-length`.
+    ///
+    pub fn lint_max_subject_length(&self) -> Result<Option<usize>> {
+        let key = "dit.lint.max-subject-length";
+        match config_value(self.config.get_i64(key))? {
+            Some(length) if length > 0 => Ok(Some(length as usize)),
+            Some(length) => Err(Error::from_kind(EK::InvalidConfigValue(key.to_owned(), length.to_string()))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the maximum number of links in messages accepted by the filter
+    ///
+    /// The number is read from `dit.filter.max-links`.
+    ///
+    pub fn filter_max_links(&self) -> Result<Option<usize>> {
+        let key = "dit.filter.max-links";
+        match config_value(self.config.get_i64(key))? {
+            Some(links) if links >= 0 => Ok(Some(links as usize)),
+            Some(links) => Err(Error::from_kind(EK::InvalidConfigValue(key.to_owned(), links.to_string()))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the identity configured for an alias
+    ///
+    /// The identity is read from `dit.identity.<alias>`.
+    ///
+    pub fn identity(&self, alias: &str) -> Result<Option<String>> {
+        config_value(self.config.get_string(&format!("dit.identity.{}", alias)))
+    }
+
+    /// Get the name of the identity profile selected for the repository
+    ///
+    /// The name is read from `dit.profile`.
+    ///
+    pub fn profile(&self) -> Result<Option<String>> {
+        config_value(self.config.get_string("dit.profile"))
+    }
+
+    /// Get an identity profile
+    ///
+    /// Returns `None` if none of the profile's options are set.
+    ///
+    pub fn identity_profile(&self, profile: &str) -> Result<Option<IdentityProfile>> {
+        let key = |option: &str| format!("dit.identity.{}.{}", profile, option);
+        let name = config_value(self.config.get_string(&key("name")))?;
+        let email = config_value(self.config.get_string(&key("email")))?;
+        if name.is_none() && email.is_none() {
+            return Ok(None);
+        }
+
+        let name = match name {
+            Some(name) => name,
+            None => self.config.get_string("user.name").chain_err(|| EK::IncompleteProfile(profile.to_owned()))?,
+        };
+        let email = match email {
+            Some(email) => email,
+            None => self.config.get_string("user.email").chain_err(|| EK::IncompleteProfile(profile.to_owned()))?,
+        };
+        Ok(Some(IdentityProfile { name: name, email: email }))
+    }
+
+    /// Get the value of a template variable
+    ///
+    /// The value is read from `dit.template.var.<name>`.
+    ///
+    pub fn template_variable(&self, name: &str) -> Result<Option<String>> {
+        config_value(self.config.get_string(&format!("dit.template.var.{}", name)))
+    }
+
+    /// Get the locale for user-facing messages
+    ///
+    /// The locale is read from `dit.locale`.
+    ///
+    pub fn locale(&self) -> Result<Option<String>> {
+        config_value(self.config.get_string("dit.locale"))
+    }
+
+    /// Check whether a search index is to be maintained
+    ///
+    /// The option is read from `dit.search.index`.
+    ///
+    pub fn search_index(&self) -> Result<Option<bool>> {
+        config_value(self.config.get_bool("dit.search.index"))
+    }
+
+    /// Get the default remote for fetching and pushing issues
+    ///
+    /// The remote is read from `dit.remote`.
+    ///
+    pub fn remote(&self) -> Result<Option<String>> {
+        config_value(self.config.get_string("dit.remote"))
+    }
+
+    /// Get the priorization of remotes
+    ///
+    /// The priorization is read from `dit.remote-prios` as a comma separated
+    /// list of remote names or patterns.
+    ///
+    pub fn remote_prios(&self) -> Result<Option<String>> {
+        config_value(self.config.get_string("dit.remote-prios"))
+    }
+
+    /// Get and parse a string option
+    ///
+    /// Parse errors are reported as `InvalidConfigValue` errors.
+    ///
+    fn parsed<T, F>(&self, key: &str, parse: F) -> Result<Option<T>>
+        where F: FnOnce(&str) -> Result<T>
+    {
+        match config_value(self.config.get_string(key))? {
+            Some(value) => parse(&value)
+                .chain_err(|| EK::InvalidConfigValue(key.to_owned(), value.clone()))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+
+/// Get an optional configuration value
+///
+/// Returns `None` if the option is not set.
+///
+pub fn config_value<T>(value: RResult<T, git2::Error>) -> Result<Option<T>> {
+    match value {
+        Ok(value) => Ok(Some(value)),
+        Err(ref err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(Error::from(err)),
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    #[test]
+    fn dit_config() {
+        let mut testing_repo = TestingRepo::new("dit_config");
+        let repo = testing_repo.repo();
+
+        let config = DitConfig::open(repo).expect("Could not open config");
+        assert_eq!(config.head_policy().expect("Could not read config"), None);
+        assert_eq!(config.remote().expect("Could not read config"), None);
+        assert!(config.gc_protect().expect("Could not read config").is_empty());
+
+        let mut git_config = repo.config().expect("Could not open config");
+        git_config.set_str("dit.head-policy", "always").expect("Could not configure repository");
+        git_config.set_str("dit.component", "frontend").expect("Could not configure repository");
+        git_config.set_str("dit.gc.collect-leaves", "backed-by-remote").expect("Could not configure repository");
+        git_config.set_str("dit.gc.older-than", "2d").expect("Could not configure repository");
+        git_config.set_str("dit.identity.foo", "Foo Bar <foo.bar@example.com>").expect("Could not configure repository");
+        git_config.set_str("dit.remote", "upstream").expect("Could not configure repository");
+        git_config.set_str("dit.template.var.version", "1.2.3").expect("Could not configure repository");
+        git_config.set_str("dit.lint.broken-quoting", "error").expect("Could not configure repository");
+        git_config.set_i64("dit.lint.max-subject-length", 50).expect("Could not configure repository");
+        git_config.set_str("dit.stale.age", "4w").expect("Could not configure repository");
+        git_config.set_multivar("dit.stale.exclude-tag", "^$", "pinned").expect("Could not configure repository");
+
+        assert_eq!(config.head_policy().expect("Could not read config"), Some(HeadPolicy::Always));
+        assert_eq!(config.component().expect("Could not read config"), Some("frontend".to_owned()));
+        assert_eq!(config.gc_collect_leaves().expect("Could not read config"),
+                   Some(ReferenceCollectionSpec::BackedByRemote));
+        assert_eq!(config.gc_older_than().expect("Could not read config"),
+                   Some(Duration::from_secs(2 * 24 * 60 * 60)));
+        assert_eq!(config.identity("foo").expect("Could not read config"),
+                   Some("Foo Bar <foo.bar@example.com>".to_owned()));
+        assert_eq!(config.identity("bar").expect("Could not read config"), None);
+        assert_eq!(config.remote().expect("Could not read config"), Some("upstream".to_owned()));
+        assert_eq!(config.template_variable("version").expect("Could not read config"), Some("1.2.3".to_owned()));
+        assert_eq!(config.lint_severity(Rule::BrokenQuoting).expect("Could not read config"), Some(Severity::Error));
+        assert_eq!(config.lint_severity(Rule::SubjectLength).expect("Could not read config"), None);
+        assert_eq!(config.lint_max_subject_length().expect("Could not read config"), Some(50));
+        assert_eq!(config.filter_max_links().expect("Could not read config"), None);
+        assert_eq!(config.stale_age().expect("Could not read config"),
+                   Some(Duration::from_secs(4 * 7 * 24 * 60 * 60)));
+        assert_eq!(config.stale_excluded_tags().expect("Could not read config"), vec!["pinned".to_owned()]);
+
+        git_config.set_str("dit.head-policy", "sometimes").expect("Could not configure repository");
+        git_config.set_str("dit.component", "meta").expect("Could not configure repository");
+        let err = config.head_policy().expect_err("Accepted invalid head policy");
+        assert_eq!(err.kind(),
+                   Some(&EK::InvalidConfigValue("dit.head-policy".to_owned(), "sometimes".to_owned())));
+        assert!(config.component().is_err());
+
+        git_config.set_i64("dit.filter.max-links", -1).expect("Could not configure repository");
+        let err = config.filter_max_links().expect_err("Accepted negative number of links");
+        assert_eq!(err.kind(),
+                   Some(&EK::InvalidConfigValue("dit.filter.max-links".to_owned(), "-1".to_owned())));
+    }
+
+    #[test]
+    fn identity_profiles() {
+        let mut testing_repo = TestingRepo::new("identity_profiles");
+        let repo = testing_repo.repo();
+
+        let config = DitConfig::open(repo).expect("Could not open config");
+        let mut git_config = repo.config().expect("Could not open config");
+        git_config.set_str("user.name", "Foo Bar").expect("Could not configure repository");
+        git_config.set_str("dit.profile", "work").expect("Could not configure repository");
+        git_config.set_str("dit.identity.work.email", "foo.bar@example.com").expect("Could not configure repository");
+
+        assert_eq!(config.profile().expect("Could not read config"), Some("work".to_owned()));
+        let profile = config
+            .identity_profile("work")
+            .expect("Could not read config")
+            .expect("Profile not found");
+        assert_eq!(profile, IdentityProfile {
+            name: "Foo Bar".to_owned(),
+            email: "foo.bar@example.com".to_owned(),
+        });
+        let signature = profile.signature().expect("Could not create signature");
+        assert_eq!(signature.email(), Some("foo.bar@example.com"));
+        assert!(config.identity_profile("home").expect("Could not read config").is_none());
+    }
+}
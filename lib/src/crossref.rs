@@ -0,0 +1,283 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Cross-repository issue references
+//!
+//! Issues may refer to issues living in other repositories via `See-also`
+//! trailers of the form `See-also: <repo-url>#<issue-id>`. This module provides
+//! a type representing such references as well as a resolver which may be used
+//! for opening the referenced issues, given a set of known sibling
+//! repositories.
+//!
+
+use git2::{self, Oid, Repository};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::result::Result as RResult;
+use std::str::FromStr;
+
+use issue::Issue;
+use repository::RepositoryExt;
+use trailer::Trailer;
+use trailer::spec::SEE_ALSO_SPEC;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Reference to an issue in some repository
+///
+/// A reference consists of the URL of the repository and the issue's id. Its
+/// textual representation is `<repo-url>#<issue-id>`.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IssueReference {
+    pub repo: String,
+    pub issue: Oid,
+}
+
+impl IssueReference {
+    /// Create a new reference to an issue in a repository
+    ///
+    pub fn new<S>(repo: S, issue: Oid) -> Self
+        where S: Into<String>
+    {
+        IssueReference { repo: repo.into(), issue: issue }
+    }
+
+    /// Extract a reference from a trailer
+    ///
+    /// If the trailer is not a `See-also` trailer, `None` is returned.
+    ///
+    pub fn from_trailer(trailer: &Trailer) -> Option<Result<Self>> {
+        if trailer.key.as_ref() == SEE_ALSO_SPEC.key {
+            Some(trailer.value.to_string().parse())
+        } else {
+            None
+        }
+    }
+
+    /// Create a `See-also` trailer for this reference
+    ///
+    pub fn to_trailer(&self) -> Trailer {
+        Trailer::new(SEE_ALSO_SPEC.key, &self.to_string())
+    }
+}
+
+impl fmt::Display for IssueReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        write!(f, "{}#{}", self.repo, self.issue)
+    }
+}
+
+impl FromStr for IssueReference {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // The URL itself may contain a `#`, but the issue id will not.
+        let mut parts = s.trim().rsplitn(2, '#');
+        let issue = parts
+            .next()
+            .and_then(|id| Oid::from_str(id).ok())
+            .ok_or_else(|| Error::from_kind(EK::MalformedIssueReference(s.to_owned())))?;
+        match parts.next() {
+            Some(repo) if !repo.is_empty() => Ok(IssueReference::new(repo, issue)),
+            _ => Err(Error::from_kind(EK::MalformedIssueReference(s.to_owned()))),
+        }
+    }
+}
+
+
+/// Repository holding a referenced issue
+///
+/// Instances of this type are returned by the `Resolver`. Since an `Issue`
+/// borrows its repository, this type owns the sibling repository and hands out
+/// the issue on demand.
+///
+pub struct ResolvedReference {
+    pub repo: Repository,
+    pub issue: Oid,
+}
+
+impl ResolvedReference {
+    /// Get the referenced issue
+    ///
+    pub fn issue(&self) -> Result<Issue> {
+        self.repo.find_issue(self.issue)
+    }
+}
+
+
+/// Resolver for cross-repository references
+///
+/// The resolver maps repository URLs to local paths of sibling repositories.
+/// Siblings are usually configured via the `dit.sibling.<name>.url` and
+/// `dit.sibling.<name>.path` configuration options.
+///
+pub struct Resolver {
+    siblings: HashMap<String, PathBuf>,
+}
+
+impl Resolver {
+    /// Create a new resolver without any known siblings
+    ///
+    pub fn new() -> Self {
+        Resolver { siblings: HashMap::new() }
+    }
+
+    /// Create a resolver from the siblings configured in a git config
+    ///
+    /// Siblings for which either the URL or the path is missing are ignored.
+    ///
+    pub fn from_config(config: &git2::Config) -> Result<Self> {
+        let mut urls = HashMap::new();
+        let mut paths = HashMap::new();
+
+        let entries = config.entries(Some(r"^dit\.sibling\..*\.(url|path)$"))?;
+        for entry in &entries {
+            let entry = entry?;
+            let (name, value) = match (entry.name(), entry.value()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => continue,
+            };
+
+            // The name of the sibling may itself contain dots.
+            let mut parts = name["dit.sibling.".len()..].rsplitn(2, '.');
+            match (parts.next(), parts.next()) {
+                (Some("url"), Some(sibling)) => {
+                    urls.insert(sibling.to_owned(), value.to_owned());
+                },
+                (Some("path"), Some(sibling)) => {
+                    paths.insert(sibling.to_owned(), PathBuf::from(value));
+                },
+                _ => {},
+            }
+        }
+
+        let mut retval = Self::new();
+        for (sibling, url) in urls {
+            if let Some(path) = paths.remove(&sibling) {
+                retval.add_sibling(url, path);
+            }
+        }
+        Ok(retval)
+    }
+
+    /// Register a sibling repository
+    ///
+    /// References to issues in the repository with the given URL will be
+    /// resolved to the repository at the given path.
+    ///
+    pub fn add_sibling<S, P>(&mut self, url: S, path: P)
+        where S: Into<String>,
+              P: AsRef<Path>
+    {
+        self.siblings.insert(url.into(), path.as_ref().to_owned());
+    }
+
+    /// Get the path of the sibling with the given URL, if known
+    ///
+    pub fn sibling_path(&self, url: &str) -> Option<&Path> {
+        self.siblings.get(url).map(PathBuf::as_path)
+    }
+
+    /// Resolve a reference
+    ///
+    /// Opens the sibling repository referred to and makes sure the referenced
+    /// issue exists in that repository.
+    ///
+    pub fn resolve(&self, reference: &IssueReference) -> Result<ResolvedReference> {
+        let path = self
+            .sibling_path(reference.repo.as_ref())
+            .ok_or_else(|| Error::from_kind(EK::UnknownRepository(reference.repo.clone())))?;
+        let repo = Repository::open(path)
+            .chain_err(|| EK::UnknownRepository(reference.repo.clone()))?;
+
+        let retval = ResolvedReference { repo: repo, issue: reference.issue };
+        retval.issue()?;
+        Ok(retval)
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // IssueReference tests
+
+    #[test]
+    fn parse_reference() {
+        let reference: IssueReference = "https://example.com/foo.git#65b56706fdc3501749d008750c61a1f24b888f72"
+            .parse()
+            .expect("Could not parse reference");
+        assert_eq!(reference.repo, "https://example.com/foo.git");
+        assert_eq!(reference.issue.to_string(), "65b56706fdc3501749d008750c61a1f24b888f72");
+        assert_eq!(
+            reference.to_string(),
+            "https://example.com/foo.git#65b56706fdc3501749d008750c61a1f24b888f72"
+        );
+    }
+
+    #[test]
+    fn parse_malformed_reference() {
+        assert!("https://example.com/foo.git".parse::<IssueReference>().is_err());
+        assert!("#65b56706fdc3501749d008750c61a1f24b888f72".parse::<IssueReference>().is_err());
+        assert!("https://example.com/foo.git#foo".parse::<IssueReference>().is_err());
+    }
+
+    #[test]
+    fn reference_from_trailer() {
+        let trailer = Trailer::new("See-also", "ssh://example.com/bar#65b56706fdc3501749d008750c61a1f24b888f72");
+        let reference = IssueReference::from_trailer(&trailer)
+            .expect("Trailer not recognized")
+            .expect("Could not parse reference");
+        assert_eq!(reference.repo, "ssh://example.com/bar");
+        assert!(IssueReference::from_trailer(&Trailer::new("Dit-status", "open")).is_none());
+    }
+
+    // Resolver tests
+
+    #[test]
+    fn resolve_reference() {
+        let mut sibling_repo = TestingRepo::new("resolve_reference_sibling");
+        let sibling = sibling_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = sibling
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let issue = sibling
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let mut resolver = Resolver::new();
+        resolver.add_sibling("https://example.com/sibling.git", sibling.path());
+
+        let resolved = resolver
+            .resolve(&IssueReference::new("https://example.com/sibling.git", issue.id()))
+            .expect("Could not resolve reference");
+        assert_eq!(resolved.issue().expect("Could not retrieve issue").id(), issue.id());
+
+        assert!(resolver
+            .resolve(&IssueReference::new("https://example.com/unknown.git", issue.id()))
+            .is_err());
+    }
+}
@@ -7,102 +7,436 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 //
 
-use git2::Oid;
+//! Error types
+//!
+//! Operations of the library report failures as `DitError`s. An error usually
+//! carries an `ErrorKind` describing the operation which failed, including the
+//! ids and reference names involved, and the error which caused the failure.
+//! The latter is exposed via `std::error::Error::source()`, which allows
+//! callers to inspect the whole chain, e.g. for the underlying `git2::Error`.
+//!
 
-error_chain! {
-    foreign_links {
-        GitError(::git2::Error);
+use git2::{self, Oid};
+use std::error::Error as StdError;
+use std::result;
+
+
+/// Error of a library operation
+///
+#[derive(Debug, Error)]
+pub enum DitError {
+    /// An operation failed because of another error
+    #[error("{kind}")]
+    Failed {
+        kind: ErrorKind,
+        #[source]
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// An operation failed without an underlying error
+    #[error(transparent)]
+    Kind(#[from] ErrorKind),
+    /// An error reported by git2
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+impl DitError {
+    /// Create an error of a given kind without an underlying error
+    ///
+    pub fn from_kind(kind: ErrorKind) -> Self {
+        DitError::Kind(kind)
     }
 
-    errors {
-        CannotCreateMessage {
-            description("Cannot create message")
-            display("Cannot create a message")
-        }
+    /// Create an error of a given kind caused by another error
+    ///
+    pub fn with_chain<E>(source: E, kind: ErrorKind) -> Self
+        where E: Into<Box<dyn StdError + Send + Sync>>
+    {
+        DitError::Failed { kind: kind, source: source.into() }
+    }
 
-        CannotConstructRevwalk {
-            description("Cannot construct revwalk")
-            display("Cannot construct a revwalk for iterating over commits")
+    /// Get the kind of the error
+    ///
+    /// Returns `None` if the error is a plain `git2::Error`.
+    ///
+    pub fn kind(&self) -> Option<&ErrorKind> {
+        match *self {
+            DitError::Failed { ref kind, .. } => Some(kind),
+            DitError::Kind(ref kind) => Some(kind),
+            DitError::Git(_) => None,
         }
+    }
 
-        CannotGetCommit {
-            description("Cannot get a commit from the repository")
-            display("Cannot get a specific commit from repository")
+    /// Get the `git2::Error` underlying the error, if any
+    ///
+    /// The whole chain of errors is searched.
+    ///
+    pub fn git_error(&self) -> Option<&git2::Error> {
+        let mut current = Some(self as &(dyn StdError + 'static));
+        while let Some(err) = current {
+            if let Some(git_error) = err.downcast_ref::<git2::Error>() {
+                return Some(git_error);
+            }
+            current = err.source();
         }
+        None
+    }
 
-        CannotGetCommitForRev(rev: String) {
-            description("Cannot get commit from rev")
-            display("Cannot get commit from rev '{}'", rev)
+    /// Get the id of the object the error refers to, if any
+    ///
+    /// The whole chain of errors is searched. The outermost id is returned,
+    /// which usually refers to the issue or message the failed operation was
+    /// performed on.
+    ///
+    pub fn object_id(&self) -> Option<Oid> {
+        let mut current = Some(self as &(dyn StdError + 'static));
+        while let Some(err) = current {
+            let id = err
+                .downcast_ref::<DitError>()
+                .and_then(DitError::kind)
+                .and_then(ErrorKind::object_id);
+            if id.is_some() {
+                return id;
+            }
+            current = err.source();
         }
+        None
+    }
+}
 
-        ReferenceNameError {
-            description("Error getting reference name")
-            display("Error getting reference name")
-        }
 
-        CannotGetReferences(glob: String) {
-            description("Cannot get references from repository")
-            display("Cannot get references '{}' from repository", glob)
-        }
+/// Kind of an error
+///
+/// The kind describes the operation which failed. Variants carry the ids of the
+/// objects and the names of the references involved, if any, as typed fields.
+/// Hence, callers may find out which issue or reference an operation failed
+/// for, e.g. for skipping it during bulk operations.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ErrorKind {
+    #[error("Cannot create a message")]
+    CannotCreateMessage,
 
-        CannotGetReference {
-            description("Cannot get a reference from repository")
-            display("Cannot get a specific reference from repository")
-        }
+    #[error("Cannot add a message to issue {0}")]
+    CannotAddMessage(Oid),
 
-        CannotDeleteReference(reference: String) {
-            description("Cannot delete a specific reference")
-            display("Cannot delete the reference '{}'", reference)
-        }
+    #[error("Cannot construct a revwalk for iterating over commits")]
+    CannotConstructRevwalk,
 
-        CannotBuildTree {
-            description("Cannot build Tree")
-            display("Cannot build Tree")
-        }
+    #[error("Cannot iterate over messages")]
+    CannotIterateMessages,
 
-        CannotFindIssueHead(id: Oid) {
-            description("Cannot find issue HEAD")
-            display("Cannot find issue HEAD for {}", id)
-        }
+    #[error("Cannot get commit {0} from repository")]
+    CannotGetCommit(Oid),
 
-        CannotSetReference(refname: String) {
-            description("Cannot set some reference")
-            display("Cannot update or create reference '{}'", refname)
-        }
+    #[error("Reference name is not valid UTF-8: {0}")]
+    ReferenceNameError(String),
 
-        NoTreeInitFound(id: Oid) {
-            description("Cannot find any tree init")
-            display("Cannot find any tree init for {}", id)
-        }
+    #[error("Cannot get references '{0}' from repository")]
+    CannotGetReferences(String),
 
-        OidFormatError(name: String) {
-            description("Malformed HEAD OID")
-            display("Malformed OID: {}", name)
-        }
+    #[error("Cannot get the reference '{0}'")]
+    CannotGetReference(String),
 
-        MalFormedHeadReference(name: String) {
-            description("Found malformed HEAD reference")
-            display("Malformed head refernece: {}", name)
-        }
+    #[error("Cannot delete the reference '{0}'")]
+    CannotDeleteReference(String),
 
-        TrailerFormatError(trailer: String) {
-            description("Found malformed trailer")
-            display("Malformed trailer: {}", trailer)
-        }
+    #[error("Cannot build Tree")]
+    CannotBuildTree,
+
+    #[error("Cannot find issue HEAD for {0}")]
+    CannotFindIssueHead(Oid),
+
+    #[error("Cannot update or create reference '{0}'")]
+    CannotSetReference(String),
+
+    #[error("Cannot find any tree init for {0}")]
+    NoTreeInitFound(Oid),
+
+    #[error("Malformed OID: {0}")]
+    OidFormatError(String),
+
+    #[error("Malformed head refernece: {0}")]
+    MalFormedHeadReference(String),
+
+    #[error("Malformed trailer: {0}")]
+    TrailerFormatError(String),
+
+    #[error("The message is empty")]
+    EmptyMessage,
+
+    #[error("Empty subject line")]
+    EmptySubject,
+
+    #[error("The message supplied is malformed")]
+    MalformedMessage,
+
+    #[error("The text of message {0} is not valid UTF-8")]
+    NonUtf8Message(Oid),
+
+    #[error("Trailer not part of a block of trailers: {0}")]
+    MisplacedTrailer(String),
+
+    #[error("Malformed issue reference: {0}")]
+    MalformedIssueReference(String),
+
+    #[error("Malformed component name: {0}")]
+    MalformedComponent(String),
+
+    #[error("Unknown head policy: {0}")]
+    UnknownHeadPolicy(String),
+
+    #[error("Cannot find repository '{0}'")]
+    UnknownRepository(String),
+
+    #[error("Malformed moderation data in '{0}'")]
+    MalformedModerationData(String),
+
+    #[error("Error while running the message filter program '{0}'")]
+    FilterProgramError(String),
+
+    #[error("Anonymous messages require a non-empty key for hashing reporters")]
+    MissingAnonymityKey,
+
+    #[error("Cannot encrypt a message without any recipients")]
+    NoRecipients,
+
+    #[error("Cannot encrypt a message")]
+    CannotEncrypt,
+
+    #[error("Cannot decrypt a message")]
+    CannotDecrypt,
+
+    #[error("Error while running the encryption program '{0}'")]
+    CipherProgramError(String),
+
+    #[error("Cannot write the message index '{0}'")]
+    CannotWriteMessageIndex(String),
+
+    #[error("Cannot update the references {} in a transaction", .0.join(", "))]
+    CannotUpdateReferences(Vec<String>),
+
+    #[error("The worker thread failed to run the operation")]
+    WorkerFailed,
+
+    #[error("Attaching '{0}' exceeds the limit of {1} bytes per message")]
+    AttachmentLimitExceeded(String, u64),
+
+    #[error("Cannot attach '{0}'")]
+    CannotAttach(String),
+
+    #[error("Malformed age: {0}")]
+    MalformedAge(String),
+
+    #[error("Invalid value for configuration option '{0}': {1}")]
+    InvalidConfigValue(String, String),
+
+    #[error("Cannot collect the references of issue {0}")]
+    CannotCollectReferences(Oid),
+
+    #[error("The identity profile '{0}' lacks a name or email address")]
+    IncompleteProfile(String),
 
-        EmptyMessage {
-            description("An empty message was supplied")
-            display("The message is empty")
+    #[error("Invalid template name: '{0}'")]
+    InvalidTemplateName(String),
+
+    #[error("Malformed template '{0}'")]
+    MalformedTemplate(String),
+
+    #[error("Required trailers are missing: {}", .0.join(", "))]
+    MissingTrailers(Vec<String>),
+
+    #[error("Unknown lint severity: {0}")]
+    UnknownLintSeverity(String),
+
+    #[error("Malformed search pattern: {0}")]
+    MalformedPattern(String),
+
+    #[error("Cannot access the search index '{0}'")]
+    CannotAccessSearchIndex(String),
+
+    #[error("Unknown sort key: {0}")]
+    UnknownSortKey(String),
+
+    #[error("Unknown sort order: {0}")]
+    UnknownSortOrder(String),
+
+    #[error("Unknown group key: {0}")]
+    UnknownGroupKey(String),
+
+    #[error("Unknown kind of involvement: {0}")]
+    UnknownInvolvement(String),
+}
+
+impl ErrorKind {
+    /// Get the id of the object the kind refers to, if any
+    ///
+    pub fn object_id(&self) -> Option<Oid> {
+        match *self {
+            ErrorKind::CannotAddMessage(id) |
+            ErrorKind::CannotGetCommit(id) |
+            ErrorKind::CannotFindIssueHead(id) |
+            ErrorKind::NoTreeInitFound(id) |
+            ErrorKind::NonUtf8Message(id) |
+            ErrorKind::CannotCollectReferences(id) => Some(id),
+            _ => None,
         }
+    }
+}
 
-        EmptySubject {
-            description("The subject line of the message is empty")
-            display("Empty subject line")
+
+/// Alias for `DitError`
+///
+pub type Error = DitError;
+
+/// Result type of library operations
+///
+pub type Result<T> = result::Result<T, DitError>;
+
+
+/// Outcome of a bulk operation
+///
+/// Operations processing many items, e.g. all issues of a repository, report
+/// failures for individual items alongside the outcome for the remaining ones
+/// rather than failing as a whole. Hence, a single malformed reference doesn't
+/// hide the rest of the tracker.
+///
+#[derive(Debug, Default)]
+pub struct BulkResult<T, E = DitError> {
+    /// Outcome for the items processed successfully
+    pub value: T,
+    /// Failures for individual items
+    pub failures: Vec<E>,
+}
+
+impl<T, E> BulkResult<T, E> {
+    /// Create a bulk result without any failures
+    ///
+    pub fn new(value: T) -> Self {
+        BulkResult { value: value, failures: Vec::new() }
+    }
+
+    /// Check whether all items were processed successfully
+    ///
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Convert into a regular result
+    ///
+    /// Returns the first failure, if any.
+    ///
+    pub fn into_result(self) -> result::Result<T, E> {
+        match self.failures.into_iter().next() {
+            Some(failure) => Err(failure),
+            None => Ok(self.value),
         }
+    }
+}
 
-        MalformedMessage {
-            description("The message supplied is malformed")
-            display("The message supplied is malformed")
+impl<T, I, E> ::std::iter::FromIterator<result::Result<I, E>> for BulkResult<T, E>
+    where T: Default + Extend<I>
+{
+    fn from_iter<J>(iter: J) -> Self
+        where J: IntoIterator<Item = result::Result<I, E>>
+    {
+        let mut retval = BulkResult::new(T::default());
+        for item in iter {
+            match item {
+                Ok(item) => retval.value.extend(Some(item)),
+                Err(failure) => retval.failures.push(failure),
+            }
         }
+        retval
+    }
+}
+
+
+/// Extension trait for wrapping errors in a `DitError` of a specific kind
+///
+pub trait ResultExt<T> {
+    /// Wrap an error in a `DitError` of the kind returned by a function
+    ///
+    fn chain_err<F>(self, kind: F) -> Result<T>
+        where F: FnOnce() -> ErrorKind;
+}
+
+impl<T, E> ResultExt<T> for result::Result<T, E>
+    where E: Into<Box<dyn StdError + Send + Sync>>
+{
+    fn chain_err<F>(self, kind: F) -> Result<T>
+        where F: FnOnce() -> ErrorKind
+    {
+        self.map_err(|err| DitError::with_chain(err, kind()))
+    }
+}
+
+impl<T> ResultExt<T> for Option<T> {
+    fn chain_err<F>(self, kind: F) -> Result<T>
+        where F: FnOnce() -> ErrorKind
+    {
+        self.ok_or_else(|| DitError::from_kind(kind()))
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use issue::Issue;
+
+    #[test]
+    fn error_sources() {
+        let git_error = git2::Error::from_str("not found");
+        let err: Result<()> = Err(git_error).chain_err(|| ErrorKind::CannotGetReference("refs/dit/foo".to_owned()));
+        let err = err.chain_err(|| ErrorKind::CannotSetReference("refs/dit/foo".to_owned()))
+            .expect_err("Error was lost");
+
+        assert_eq!(err.to_string(), "Cannot update or create reference 'refs/dit/foo'");
+        assert_eq!(err.kind(), Some(&ErrorKind::CannotSetReference("refs/dit/foo".to_owned())));
+        let source = err.source().expect("Source was lost");
+        assert_eq!(source.to_string(), "Cannot get the reference 'refs/dit/foo'");
+        assert_eq!(err.git_error().map(git2::Error::message), Some("not found"));
+
+        let err = DitError::from_kind(ErrorKind::EmptyMessage);
+        assert!(err.source().is_none());
+        assert!(err.git_error().is_none());
+    }
+
+    #[test]
+    fn bulk_result() {
+        let items = vec![Ok(1), Err(DitError::from_kind(ErrorKind::EmptyMessage)), Ok(2)];
+        let result: BulkResult<Vec<u32>> = items.into_iter().collect();
+        assert_eq!(result.value, vec![1, 2]);
+        assert_eq!(result.failures.len(), 1);
+        assert!(!result.is_complete());
+        assert!(result.into_result().is_err());
+
+        let result: BulkResult<Vec<u32>> = BulkResult::new(vec![3]);
+        assert!(result.is_complete());
+        assert_eq!(result.into_result().expect("Spurious failure"), vec![3]);
+    }
+
+    #[test]
+    fn error_context() {
+        let mut testing_repo = TestingRepo::new("error_context");
+        let repo = testing_repo.repo();
+
+        let id = Oid::from_str("0123456789abcdef0123456789abcdef01234567")
+            .expect("Could not parse id");
+        let err = Issue::new(repo, id)
+            .err()
+            .expect("Found issue which does not exist");
+        assert_eq!(err.kind(), Some(&ErrorKind::CannotGetCommit(id)));
+        assert_eq!(err.git_error().map(git2::Error::code), Some(git2::ErrorCode::NotFound));
+        assert_eq!(err.object_id(), Some(id));
+
+        let err = DitError::with_chain(err, ErrorKind::CannotCollectReferences(Oid::zero()));
+        assert_eq!(err.object_id(), Some(Oid::zero()));
+        assert!(DitError::from_kind(ErrorKind::EmptyMessage).object_id().is_none());
     }
 }
@@ -0,0 +1,173 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Issue events
+//!
+//! The activity in an issue tracker may be viewed as a stream of events, e.g.
+//! the creation of a new issue or a reply to a message. Each message
+//! constitutes exactly one event. This module provides functionality for
+//! classifying messages as events and assembling event streams.
+//!
+
+use git2::{Commit, Oid, Time};
+
+use issue::Issue;
+use message::Message;
+use trailer::spec::ISSUE_STATUS_SPEC;
+
+use error::*;
+
+
+/// Kind of an event
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// A new issue was created
+    NewIssue,
+    /// A reply was added to an issue
+    Reply,
+    /// The status of an issue was changed to the value given
+    StatusChange(String),
+}
+
+
+/// Issue event
+///
+/// An event associates a message with the issue it is part of.
+///
+pub struct Event<'r> {
+    issue: Oid,
+    message: Commit<'r>,
+    kind: EventKind,
+}
+
+impl<'r> Event<'r> {
+    /// Create an event from a message of an issue
+    ///
+    /// The initial message of an issue is considered the creation of the
+    /// issue. Other messages are considered a status change if they contain
+    /// a status trailer and a reply otherwise.
+    ///
+    pub fn from_message(issue: &Issue<'r>, message: Commit<'r>) -> Self {
+        let kind = if message.id() == issue.id() {
+            EventKind::NewIssue
+        } else {
+            message
+                .trailers()
+                .filter(|trailer| trailer.key.as_ref() == ISSUE_STATUS_SPEC.key)
+                .last()
+                .map(|trailer| EventKind::StatusChange(trailer.value.to_string()))
+                .unwrap_or(EventKind::Reply)
+        };
+
+        Event { issue: issue.id(), message: message, kind: kind }
+    }
+
+    /// Get the id of the issue the event is associated with
+    ///
+    pub fn issue(&self) -> Oid {
+        self.issue
+    }
+
+    /// Get the message constituting the event
+    ///
+    pub fn message(&self) -> &Commit<'r> {
+        &self.message
+    }
+
+    /// Get the kind of the event
+    ///
+    pub fn kind(&self) -> &EventKind {
+        &self.kind
+    }
+
+    /// Get the time at which the event occurred
+    ///
+    /// The time of an event is the time at which its message was authored.
+    ///
+    pub fn time(&self) -> Time {
+        self.message.author().when()
+    }
+}
+
+
+/// Get the events of a number of issues
+///
+/// The events of all messages of the issues supplied are returned in reverse
+/// chronological order, i.e. most recent events first.
+///
+pub fn events<'r, I>(issues: I) -> Result<Vec<Event<'r>>>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    let mut retval = Vec::new();
+    for issue in issues {
+        for message in issue.messages()? {
+            retval.push(Event::from_message(&issue, message?));
+        }
+    }
+
+    retval.sort_by(|a, b| b.time().cmp(&a.time()));
+    Ok(retval)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use git2::Signature;
+    use repository::RepositoryExt;
+
+    // event tests
+
+    #[test]
+    fn issue_events() {
+        let mut testing_repo = TestingRepo::new("issue_events");
+        let repo = testing_repo.repo();
+
+        let sig = |seconds| Signature::new("Foo Bar", "foo.bar@example.com", &Time::new(seconds, 0))
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig(100), &sig(100), "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message1 = issue1
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let reply = issue1
+            .add_message(&sig(300), &sig(300), "Test message 2", &empty_tree, vec![&initial_message1])
+            .expect("Could not add message");
+        let status = issue1
+            .add_message(&sig(400), &sig(400), "Test message 3\n\nDit-status: closed", &empty_tree, vec![&reply])
+            .expect("Could not add message");
+
+        let issue2 = repo
+            .create_issue(&sig(200), &sig(200), "Test message 4", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let (id1, id2) = (issue1.id(), issue2.id());
+        let events: Vec<_> = events(vec![issue1, issue2])
+            .expect("Could not retrieve events")
+            .into_iter()
+            .map(|event| (event.issue(), event.message().id(), event.kind().clone()))
+            .collect();
+        assert_eq!(events, vec![
+            (id1, status.id(), EventKind::StatusChange("closed".to_owned())),
+            (id1, reply.id(), EventKind::Reply),
+            (id2, id2, EventKind::NewIssue),
+            (id1, id1, EventKind::NewIssue),
+        ]);
+    }
+}
@@ -0,0 +1,657 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Consistency checks
+//!
+//! Issues are represented by references following a fixed layout: an issue's
+//! head resides at `refs/dit/<issue>/head` and its leaves at
+//! `refs/dit/<issue>/leaves/<message>`, possibly in a component namespace.
+//! References of remotes follow the same layout below
+//! `refs/remotes/<remote>/dit/`. This module provides functionality for
+//! finding references and messages which violate this layout or which are
+//! otherwise inconsistent.
+//!
+
+use git2::{Commit, Oid, Reference, Repository};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::result::Result as RResult;
+
+use issue::{self, Issue, IssueRefType};
+use message::{LineIteratorExt, Message};
+use message_index::MessageIndex;
+use observer::{self, Operation};
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Severity of a problem
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The problem does not affect the operation of git-dit
+    Warning,
+    /// The problem causes issues or messages to be missed or misattributed
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        f.write_str(match *self {
+            Severity::Warning => "warning",
+            Severity::Error   => "error",
+        })
+    }
+}
+
+
+/// Problem found by a consistency check
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The name of the reference does not follow the layout of dit references
+    MalformedRef(String),
+    /// The reference refers to an object which is missing or not a message
+    DanglingRef(String),
+    /// The reference given first should be located at the name given second
+    MisplacedRef(String, String),
+    /// The reference refers to a message not belonging to any issue
+    ForeignRef(String),
+    /// An issue has leaves but no head, which should be located at the name
+    /// given
+    MissingHead(String),
+    /// The head refers to a message which is neither the initial message nor
+    /// reachable from any of the issue's leaves, although the issue has some
+    UnreachableHead(String),
+    /// The message is malformed, for the reason given
+    MalformedMessage(Oid, String),
+}
+
+impl Problem {
+    /// Get the severity of the problem
+    ///
+    pub fn severity(&self) -> Severity {
+        match *self {
+            Problem::UnreachableHead(..) | Problem::MalformedMessage(..) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// Determine how the problem may be repaired
+    ///
+    /// Misplaced references are renamed, or deleted if a reference with the
+    /// expected name already refers to the same message. Missing heads are
+    /// recreated from the newest leaf and dangling references are deleted.
+    /// `None` is returned for problems which cannot be repaired automatically.
+    ///
+    pub fn repair(&self, repo: &Repository) -> Result<Option<Repair>> {
+        Ok(match *self {
+            Problem::MisplacedRef(ref name, ref expected) => {
+                let existing = repo
+                    .find_reference(expected)
+                    .ok()
+                    .and_then(|reference| target_of(repo, &reference));
+                let target = repo
+                    .find_reference(name)
+                    .ok()
+                    .and_then(|reference| target_of(repo, &reference));
+                if existing.is_some() && existing == target {
+                    Some(Repair::Delete(name.clone()))
+                } else {
+                    Some(Repair::Rename(name.clone(), expected.clone()))
+                }
+            },
+            Problem::DanglingRef(ref name) => Some(Repair::Delete(name.clone())),
+            Problem::MissingHead(ref name) => {
+                let issue_prefix = name.rsplitn(2, '/').nth(1).unwrap_or_default();
+                let glob = format!("{}/leaves/*", issue_prefix);
+                let mut newest: Option<Commit> = None;
+                for reference in repo.references_glob(&glob).chain_err(|| EK::CannotGetReferences(glob.clone()))? {
+                    let leaf = reference.chain_err(|| EK::CannotGetReferences(glob.clone()))?;
+                    let message = match target_of(repo, &leaf) {
+                        Some(id) => repo.find_commit(id)?,
+                        None => continue,
+                    };
+                    if newest.as_ref().map(|n| n.time() < message.time()).unwrap_or(true) {
+                        newest = Some(message);
+                    }
+                }
+                newest.map(|message| Repair::CreateHead(name.clone(), message.id()))
+            },
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        match *self {
+            Problem::MalformedRef(ref name) =>
+                write!(f, "{}: malformed reference name", name),
+            Problem::DanglingRef(ref name) =>
+                write!(f, "{}: refers to a missing message", name),
+            Problem::MisplacedRef(ref name, ref expected) =>
+                write!(f, "{}: misplaced, expected at {}", name, expected),
+            Problem::ForeignRef(ref name) =>
+                write!(f, "{}: refers to a message not part of the issue", name),
+            Problem::MissingHead(ref name) =>
+                write!(f, "{}: missing head for existing leaves", name),
+            Problem::UnreachableHead(ref name) =>
+                write!(f, "{}: not reachable from any leaf", name),
+            Problem::MalformedMessage(id, ref reason) =>
+                write!(f, "{}: {}", id, reason),
+        }
+    }
+}
+
+
+/// Repair of a problem
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Repair {
+    /// Rename the reference given first to the name given second
+    Rename(String, String),
+    /// Create a head with the name given, referring to the message given
+    CreateHead(String, Oid),
+    /// Delete the reference given
+    Delete(String),
+}
+
+impl Repair {
+    /// Perform the repair
+    ///
+    /// Existing references are never overwritten.
+    ///
+    pub fn apply(&self, repo: &Repository) -> Result<()> {
+        match *self {
+            Repair::Rename(ref name, ref new_name) => {
+                let reflogmsg = format!("git-dit: repair, renamed from {}", name);
+                let reference = repo.find_reference(name)
+                    .and_then(|mut reference| reference.rename(new_name, false, &reflogmsg))
+                    .chain_err(|| EK::CannotSetReference(new_name.clone()))?;
+                observer::notify(repo, &Operation::ReferenceDeleted(name.clone()));
+                if let Some(operation) = reference.target().and_then(|id| Operation::for_update(new_name, id)) {
+                    observer::notify(repo, &operation);
+                }
+                Ok(())
+            },
+            Repair::CreateHead(ref name, message) => {
+                repo.reference(name, message, false, "git-dit: repair, recreated head")
+                    .chain_err(|| EK::CannotSetReference(name.clone()))?;
+                if let Some(operation) = Operation::for_update(name, message) {
+                    observer::notify(repo, &operation);
+                }
+                Ok(())
+            },
+            Repair::Delete(ref name) => {
+                repo.find_reference(name)
+                    .and_then(|mut reference| reference.delete())
+                    .chain_err(|| EK::CannotDeleteReference(name.clone()))?;
+                observer::notify(repo, &Operation::ReferenceDeleted(name.clone()));
+                Ok(())
+            },
+        }
+    }
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        match *self {
+            Repair::Rename(ref name, ref new_name) => write!(f, "rename {} to {}", name, new_name),
+            Repair::CreateHead(ref name, message) => write!(f, "create {} at {}", name, message),
+            Repair::Delete(ref name) => write!(f, "delete {}", name),
+        }
+    }
+}
+
+
+/// Check the references and messages of all issues
+///
+/// All references in the `refs/dit/` namespace as well as the corresponding
+/// references of remotes are checked. References in the reserved `meta`
+/// namespace, e.g. the moderation reference, are ignored. The following
+/// conditions are reported:
+///
+///  * references with names not following the layout of dit references,
+///  * references which do not refer to an existing message,
+///  * heads and leaves which do not refer to a message of their issue, i.e. a
+///    message from which the initial message is not reachable via first
+///    parents,
+///  * leaves not named after the message they refer to or placed in the
+///    namespace of the wrong issue,
+///  * issues with leaves but without a head,
+///  * heads which refer to a message other than the initial message but are
+///    not reachable from any of the leaves in their namespace, if there are
+///    any, and
+///  * messages with a malformed format or misplaced trailers.
+///
+/// Problems concerning references are returned first, ordered by the names of
+/// the references, followed by missing heads, unreachable heads and malformed
+/// messages.
+///
+pub fn check(repo: &Repository) -> Result<Vec<Problem>> {
+    let mut retval = Vec::new();
+
+    // The heads present and the tips of the messages to check, for each
+    // namespace and issue.
+    let mut heads = BTreeSet::new();
+    let mut tips: BTreeMap<(String, String), Vec<Oid>> = BTreeMap::new();
+
+    // The targets of the heads and leaves, for checking the reachability of
+    // the former from the latter.
+    let mut head_targets = Vec::new();
+    let mut leaves: BTreeMap<(String, String), Vec<Oid>> = BTreeMap::new();
+
+    let mut references = Vec::new();
+    for glob in &["refs/dit/**", "refs/remotes/*/dit/**"] {
+        for reference in repo.references_glob(glob).chain_err(|| EK::CannotGetReferences(glob.to_string()))? {
+            references.push(reference.chain_err(|| EK::CannotGetReferences(glob.to_string()))?);
+        }
+    }
+    references.sort_by(|a, b| a.name_bytes().cmp(b.name_bytes()));
+
+    let index = MessageIndex::updated(repo);
+    for reference in references {
+        let name = String::from_utf8_lossy(reference.name_bytes()).into_owned();
+        let namespace = match namespace_of(&name) {
+            Some(namespace) => namespace.to_owned(),
+            None => continue,
+        };
+        if name[namespace.len()..].starts_with("/meta/") {
+            continue;
+        }
+
+        let target = match target_of(repo, &reference) {
+            Some(target) => target,
+            None => {
+                retval.push(Problem::DanglingRef(name));
+                continue;
+            },
+        };
+
+        let (id, ref_type) = match IssueRefType::of_ref(&name) {
+            Some(data) => data,
+            None => {
+                // If the message belongs to an issue, the reference should be
+                // one of its leaves. The issue's namespace is considered first
+                // since the issue may lack a head.
+                let owner = match issue_of_namespace(repo, &name) {
+                    Some(issue) => if chains_to(repo, target, issue.id())? { Some(issue) } else { None },
+                    None => None,
+                };
+                retval.push(match owner.or_else(|| issue_of_message(repo, &index, target)) {
+                    Some(issue) => Problem::MisplacedRef(name, leaf_name(&namespace, &issue, target)),
+                    None => Problem::MalformedRef(name),
+                });
+                continue;
+            },
+        };
+        let component = IssueRefType::component_of_ref(&name, id);
+        let ref_part = match component {
+            Some(ref component) => if issue::check_component(component).is_ok() {
+                format!("{}/{}", component, id)
+            } else {
+                retval.push(Problem::MalformedRef(name));
+                continue;
+            },
+            None => id.to_string(),
+        };
+
+        if !chains_to(repo, target, id)? {
+            // Leaves may be moved to the issue the message belongs to.
+            let owner = if ref_type == IssueRefType::Leaf {
+                issue_of_message(repo, &index, target)
+            } else {
+                None
+            };
+            retval.push(match owner {
+                Some(issue) => Problem::MisplacedRef(name, leaf_name(&namespace, &issue, target)),
+                None => Problem::ForeignRef(name),
+            });
+            continue;
+        }
+
+        if ref_type == IssueRefType::Head {
+            heads.insert((namespace.clone(), ref_part.clone()));
+            if target != id {
+                head_targets.push((name, (namespace.clone(), ref_part.clone()), target));
+            }
+        } else {
+            leaves.entry((namespace.clone(), ref_part.clone())).or_insert_with(Vec::new).push(target);
+            let expected = format!("{}/{}/leaves/{}", namespace, ref_part, target);
+            if name != expected {
+                retval.push(Problem::MisplacedRef(name, expected));
+            }
+        }
+        tips.entry((namespace, ref_part)).or_insert_with(Vec::new).push(target);
+    }
+
+    for &(ref namespace, ref ref_part) in tips.keys() {
+        if !heads.contains(&(namespace.clone(), ref_part.clone())) {
+            retval.push(Problem::MissingHead(format!("{}/{}/head", namespace, ref_part)));
+        }
+    }
+
+    // Heads of issues without leaves, e.g. after garbage collection, are
+    // considered the only tip of their issue.
+    for (name, key, target) in head_targets {
+        let leaves = match leaves.get(&key) {
+            Some(leaves) => leaves,
+            None => continue,
+        };
+        let mut reachable = false;
+        for &leaf in leaves {
+            if leaf == target || repo.graph_descendant_of(leaf, target).chain_err(|| EK::CannotGetCommit(leaf))? {
+                reachable = true;
+                break;
+            }
+        }
+        if !reachable {
+            retval.push(Problem::UnreachableHead(name));
+        }
+    }
+
+    // Messages are checked only once per issue, regardless of the number of
+    // namespaces they are referred to from.
+    let mut issue_tips: BTreeMap<String, Vec<Oid>> = BTreeMap::new();
+    for ((_, ref_part), targets) in tips {
+        issue_tips.entry(ref_part).or_insert_with(Vec::new).extend(targets);
+    }
+    for (ref_part, targets) in issue_tips {
+        let (component, id) = match ref_part.rfind('/') {
+            Some(pos) => (Some(&ref_part[..pos]), &ref_part[pos + 1..]),
+            None => (None, ref_part.as_str()),
+        };
+        let issue = Issue::new_in_component(repo, Oid::from_str(id)?, component)?;
+        let mut messages = issue.terminated_messages()?;
+        for target in targets {
+            messages.revwalk.push(target)?;
+        }
+        for message in messages {
+            let message = message?;
+            let check = message
+                .message_lines()
+                .check_message_format()
+                .and_then(|_| message.message_lines().check_trailers());
+            if let Err(err) = check {
+                retval.push(Problem::MalformedMessage(message.id(), err.to_string()));
+            }
+        }
+    }
+
+    Ok(retval)
+}
+
+
+/// Get the namespace of a dit reference
+///
+/// The namespace is either `refs/dit` or `refs/remotes/<remote>/dit`.
+///
+fn namespace_of(refname: &str) -> Option<&str> {
+    if refname.starts_with("refs/dit/") {
+        return Some("refs/dit");
+    }
+
+    let mut parts = refname.splitn(4, '/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("refs"), Some("remotes"), Some(remote), Some(rest)) if rest.starts_with("dit/") =>
+            Some(&refname[.."refs/remotes/".len() + remote.len() + "/dit".len()]),
+        _ => None,
+    }
+}
+
+
+/// Get the id of the message a reference refers to
+///
+/// `None` is returned if the reference does not refer to an existing commit.
+///
+fn target_of(repo: &Repository, reference: &Reference) -> Option<Oid> {
+    reference
+        .resolve()
+        .ok()
+        .and_then(|reference| reference.target())
+        .and_then(|id| repo.find_commit(id).ok())
+        .map(|message| message.id())
+}
+
+
+/// Get the issue a message belongs to
+///
+fn issue_of_message<'r>(repo: &'r Repository, index: &MessageIndex, message: Oid) -> Option<Issue<'r>> {
+    repo.find_commit(message)
+        .ok()
+        .and_then(|message| index.issue_with_message(repo, &message).ok())
+}
+
+
+/// Get the issue in whose namespace a malformed reference resides
+///
+fn issue_of_namespace<'r>(repo: &'r Repository, refname: &str) -> Option<Issue<'r>> {
+    refname
+        .split('/')
+        .filter(|part| part.len() == 40)
+        .filter_map(|part| Oid::from_str(part).ok())
+        .filter_map(|id| {
+            let component = IssueRefType::component_of_ref(refname, id);
+            Issue::new_in_component(repo, id, component).ok()
+        })
+        .next()
+}
+
+
+/// Check whether an initial message is reachable from a message via first parents
+///
+fn chains_to(repo: &Repository, message: Oid, initial: Oid) -> Result<bool> {
+    for id in repo.first_parent_messages(message)?.revwalk {
+        if id? == initial {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+
+/// Get the name of the leaf of an issue for a message in a namespace
+///
+fn leaf_name(namespace: &str, issue: &Issue, message: Oid) -> String {
+    format!("{}/{}/leaves/{}", namespace, issue.ref_part(), message)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use git2::Signature;
+
+    // check tests
+
+    #[test]
+    fn check_refs() {
+        let mut testing_repo = TestingRepo::new("fsck_check_refs");
+        let repo = testing_repo.repo();
+
+        let sig = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message1 = issue1
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue1
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message1])
+            .expect("Could not add message");
+        let issue2 = repo
+            .create_issue(&sig, &sig, "Test message 3\n\nFoo\nDit-status: closed", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        assert_eq!(check(repo).expect("Could not check repository"), vec![
+            Problem::MalformedMessage(issue2.id(), "Trailer not part of a block of trailers: Dit-status: closed".to_owned()),
+        ]);
+
+        // leaf in the wrong issue and orphaned leaves
+        let wrong_leaf = format!("refs/dit/{}/leaves/{}", issue2.id(), message.id());
+        repo.reference(&wrong_leaf, message.id(), false, "test")
+            .expect("Could not create reference");
+        let orphan = repo
+            .commit(None, &sig, &sig, "Orphan", &empty_tree, &[])
+            .expect("Could not create commit");
+        let foreign_leaf = format!("refs/dit/{}/leaves/{}", issue1.id(), orphan);
+        repo.reference(&foreign_leaf, orphan, false, "test")
+            .expect("Could not create reference");
+
+        // references not following the layout
+        let malformed = format!("refs/dit/{}/foo", issue1.id());
+        repo.reference(&malformed, orphan, false, "test")
+            .expect("Could not create reference");
+        let stray = format!("refs/dit/{}/bar", issue2.id());
+        repo.reference(&stray, message.id(), false, "test")
+            .expect("Could not create reference");
+
+        // leaves without a head
+        let issue3 = repo
+            .create_issue(&sig, &sig, "Test message 4", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message3 = issue3
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        issue3
+            .add_message(&sig, &sig, "Test message 5", &empty_tree, vec![&initial_message3])
+            .expect("Could not add message");
+        issue3
+            .local_head()
+            .expect("Could not retrieve head")
+            .delete()
+            .expect("Could not delete head");
+
+        let problems = check(repo).expect("Could not check repository");
+        let expected_leaf = format!("refs/dit/{}/leaves/{}", issue1.id(), message.id());
+        let mut expected = vec![
+            Problem::MisplacedRef(wrong_leaf, expected_leaf.clone()),
+            Problem::ForeignRef(foreign_leaf),
+            Problem::MalformedRef(malformed),
+            Problem::MisplacedRef(stray, expected_leaf),
+            Problem::MissingHead(format!("refs/dit/{}/head", issue3.id())),
+        ];
+        for problem in problems.iter().filter(|problem| problem.severity() == Severity::Error) {
+            assert!(expected.contains(problem), "Unexpected problem: {}", problem);
+        }
+        expected.retain(|problem| !problems.contains(problem));
+        assert!(expected.is_empty());
+    }
+
+    #[test]
+    fn check_head_reachability() {
+        let mut testing_repo = TestingRepo::new("fsck_check_head_reachability");
+        let repo = testing_repo.repo();
+
+        let sig = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        // heads referring to the initial message or to a leaf are fine
+        assert_eq!(check(repo).expect("Could not check repository"), vec![]);
+        issue
+            .update_head(message.id(), true)
+            .expect("Could not update head");
+        assert_eq!(check(repo).expect("Could not check repository"), vec![]);
+
+        // a head referring to a message without leaf
+        let reply = repo
+            .commit(None, &sig, &sig, "Test message 3", &empty_tree, &[&initial_message])
+            .expect("Could not create commit");
+        issue
+            .update_head(reply, true)
+            .expect("Could not update head");
+        let head = format!("refs/dit/{}/head", issue.id());
+        let problems = check(repo).expect("Could not check repository");
+        assert_eq!(problems, vec![Problem::UnreachableHead(head)]);
+        assert_eq!(problems[0].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn repair_refs() {
+        let mut testing_repo = TestingRepo::new("fsck_repair_refs");
+        let repo = testing_repo.repo();
+
+        let sig = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        // misplaced leaf, leaf present twice and missing head
+        let leaf = format!("refs/dit/{}/leaves/{}", issue.id(), message.id());
+        let misplaced = format!("refs/dit/{}/foo", issue.id());
+        repo.reference(&misplaced, initial_message.id(), false, "test")
+            .expect("Could not create reference");
+        let initial_leaf = format!("refs/dit/{}/leaves/{}", issue.id(), initial_message.id());
+        repo.find_reference(&leaf)
+            .and_then(|mut reference| reference.rename(&format!("refs/dit/{}/bar", issue.id()), false, "test"))
+            .expect("Could not rename reference");
+        repo.reference(&leaf, message.id(), false, "test")
+            .expect("Could not create reference");
+        issue
+            .local_head()
+            .expect("Could not retrieve head")
+            .delete()
+            .expect("Could not delete head");
+
+        let repairs: Vec<_> = check(repo)
+            .expect("Could not check repository")
+            .iter()
+            .filter_map(|problem| problem.repair(repo).expect("Could not determine repair"))
+            .collect();
+        let head = format!("refs/dit/{}/head", issue.id());
+        assert_eq!(repairs, vec![
+            Repair::Delete(format!("refs/dit/{}/bar", issue.id())),
+            Repair::Rename(misplaced, initial_leaf.clone()),
+            Repair::CreateHead(head.clone(), message.id()),
+        ]);
+
+        for repair in repairs {
+            repair.apply(repo).expect("Could not apply repair");
+        }
+        assert!(repo.find_reference(&initial_leaf).is_ok());
+        assert_eq!(repo.refname_to_id(&head).expect("Could not find head"), message.id());
+        assert_eq!(check(repo).expect("Could not check repository"), vec![]);
+    }
+}
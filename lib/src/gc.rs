@@ -11,13 +11,59 @@
 //!
 //! This module provides git-dit related garbage collection utilites.
 //!
+//! References may be collected if the messages they refer to are reachable
+//! via other references. When considering remote references, these may be
+//! remote-tracking references, which vanish once the remote drops them, e.g.
+//! because another clone collected them in turn. A subsequent `git gc` would
+//! then prune the messages. For this reason, the targets of references
+//! collected should be passed to `keep_messages()`, which keeps them reachable
+//! via the single reference `KEEP_REF`, before deleting the references. If
+//! both the update of `KEEP_REF` and the deletions are recorded in the same
+//! `RefTransaction`, no message becomes unreachable at any time.
+//!
+//! Since git only considers references, `KEEP_REF` is all that is needed for
+//! `git gc` to retain the messages. Deleting it releases them.
+//!
+//! `CollectableRefs::collect()` implements the entire pipeline: it determines
+//! the references to collect, keeps their messages if necessary and deletes
+//! them, returning a `GcReport`.
+//!
+//! References may be protected from collection, e.g. for pinning messages of
+//! forensic or legal relevance. Protected references are never yielded by
+//! `CollectableRefs`, but their messages still render other references
+//! collectable. References are protected via glob patterns matching their
+//! names, which are listed in the file `protected` of the commit referred to
+//! by `PROTECTED_REF`, configured via `dit.gc.protect` or supplied via
+//! `CollectableRefs::protect()`. Since `PROTECTED_REF` resides under
+//! `refs/dit/`, the list is distributed along with the issues.
+//!
+//! A repository may encode its collection policy in its configuration, which
+//! is read by `CollectableRefs::with_config()`:
+//!
+//!  * `dit.gc.consider-remotes`: whether remote references are considered
+//!  * `dit.gc.collect-heads`: condition under which local heads are collected,
+//!    either "never" or "backed-by-remote"
+//!  * `dit.gc.collect-leaves`: condition under which local leaves are
+//!    collected, either "always" or "backed-by-remote"
+//!  * `dit.gc.older-than`: only collect references to messages older than the
+//!    given age, e.g. "2w" (see `parse_age()`)
+//!
 
-use git2::{self, Reference};
+use git2::{self, Oid, Reference};
 use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use config::DitConfig;
+use graph::CommitGraph;
 use issue::{Issue, IssueRefType};
 use iter::{self, RefsReferringTo};
-use utils::ResultIterExt;
+use repository::RepositoryExt;
+use transaction::RefTransaction;
+use utils::{reference_target, ResultIterExt};
 
 use error::*;
 use error::ErrorKind as EK;
@@ -34,9 +80,244 @@ pub type ReferenceCollector<'r> = iter::ReferenceDeletingIter<
 >;
 
 
+/// Name of the reference keeping messages of collected references reachable
+///
+/// The reference is placed outside of the `refs/dit/` namespace, since it is
+/// not supposed to be pushed.
+///
+pub const KEEP_REF: &'static str = "refs/dit-keep";
+
+/// Reflog message used for updates of `KEEP_REF`
+///
+const KEEP_MESSAGE: &'static str = "git-dit: keep messages of collected references";
+
+
+/// Name of the reference holding the list of protected references
+///
+pub const PROTECTED_REF: &'static str = "refs/dit/meta/keep";
+
+/// Name of the file listing the patterns of protected references
+///
+const PROTECTED_FILE: &'static str = "protected";
+
+
+/// Condition under which references are collected
+///
+/// Conditions apply in addition to the requirement that the message referred
+/// to remains reachable via other references. Heads are only ever collected
+/// if backed by a remote head.
+///
+/// Leaves may be restricted to those backed by a remote reference. Messages of
+/// leaves which were never pushed then can't be lost, even if they are
+/// reachable via local references only.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ReferenceCollectionSpec {
     Never,
+    Always,
     BackedByRemoteHead,
+    /// The message referred to is reachable from a remote reference of a
+    /// configured remote
+    BackedByRemote,
+    /// The message referred to is older than the given age
+    OlderThan(Duration),
+    /// All of the given conditions hold
+    All(Vec<ReferenceCollectionSpec>),
+}
+
+impl ReferenceCollectionSpec {
+    /// Check whether the spec may admit any reference
+    ///
+    fn is_never(&self) -> bool {
+        match *self {
+            ReferenceCollectionSpec::Never => true,
+            ReferenceCollectionSpec::All(ref specs) => specs.iter().any(Self::is_never),
+            _ => false,
+        }
+    }
+
+    /// Check whether the spec admits a reference to a given message
+    ///
+    /// The targets of the remote references of the message's issue are
+    /// retrieved on demand, since they are only needed for some specs.
+    ///
+    fn admits(&self, repo: &git2::Repository, message: Oid, remote: &mut RemoteTargets) -> Result<bool> {
+        let reaches = |targets: &[Oid]| -> Result<bool> {
+            for &target in targets {
+                if target == message || repo.graph_descendant_of(target, message)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        };
+
+        match *self {
+            ReferenceCollectionSpec::Never => Ok(false),
+            ReferenceCollectionSpec::Always => Ok(true),
+            ReferenceCollectionSpec::BackedByRemoteHead => reaches(remote.heads()?),
+            ReferenceCollectionSpec::BackedByRemote => reaches(remote.all()?),
+            ReferenceCollectionSpec::OlderThan(age) => {
+                let time = repo
+                    .find_commit(message)
+                    .chain_err(|| EK::CannotGetCommit(message))?
+                    .time()
+                    .seconds();
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|now| now.as_secs() as i64)
+                    .unwrap_or(0);
+                Ok(time < now.saturating_sub(age.as_secs().min(i64::MAX as u64) as i64))
+            },
+            ReferenceCollectionSpec::All(ref specs) => {
+                for spec in specs {
+                    if !spec.admits(repo, message, remote)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            },
+        }
+    }
+}
+
+
+/// Targets of the remote references of an issue, retrieved on demand
+///
+/// Only references of configured remotes are considered.
+///
+struct RemoteTargets<'a, 'r: 'a> {
+    collectable: &'a CollectableRefs<'r>,
+    issue: &'a Issue<'r>,
+    heads: Option<Vec<Oid>>,
+    all: Option<Vec<Oid>>,
+}
+
+impl<'a, 'r> RemoteTargets<'a, 'r> {
+    fn new(collectable: &'a CollectableRefs<'r>, issue: &'a Issue<'r>) -> Self {
+        RemoteTargets { collectable: collectable, issue: issue, heads: None, all: None }
+    }
+
+    /// Get the targets of the remote heads
+    ///
+    fn heads(&mut self) -> Result<&[Oid]> {
+        if self.heads.is_none() {
+            self.heads = Some(self.collectable.remote_targets(self.issue, IssueRefType::Head)?);
+        }
+        Ok(self.heads.as_ref().map(Vec::as_slice).unwrap_or(&[]))
+    }
+
+    /// Get the targets of all remote references
+    ///
+    fn all(&mut self) -> Result<&[Oid]> {
+        if self.all.is_none() {
+            self.all = Some(self.collectable.remote_targets(self.issue, IssueRefType::Any)?);
+        }
+        Ok(self.all.as_ref().map(Vec::as_slice).unwrap_or(&[]))
+    }
+}
+
+
+/// Reason for which a reference is collectable
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollectionReason {
+    /// The message referred to is reachable from the issue's local head
+    MergedIntoHead,
+    /// The message referred to is reachable from another local leaf
+    SupersededByLeaf,
+    /// The message referred to is reachable from a remote reference
+    BackedByRemote,
+    /// The reference is a remote-tracking reference of a remote which no longer
+    /// exists
+    Orphaned,
+}
+
+impl fmt::Display for CollectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            CollectionReason::MergedIntoHead    => "merged into head",
+            CollectionReason::SupersededByLeaf  => "superseded by leaf",
+            CollectionReason::BackedByRemote    => "backed by remote",
+            CollectionReason::Orphaned          => "orphaned",
+        })
+    }
+}
+
+
+/// Iterator over the collectable references of multiple issues
+///
+/// The iterator is constructed via `CollectableRefs::for_all_issues()`. If the
+/// references of an issue cannot be determined, the error is yielded in their
+/// place and the iteration continues with the next issue. Failures to retrieve
+/// issues, e.g. because of malformed head references, are yielded first.
+///
+pub struct AllCollectableRefs<'a, 'r: 'a> {
+    collectable: &'a CollectableRefs<'r>,
+    failures: ::std::vec::IntoIter<Error>,
+    issues: ::std::vec::IntoIter<Issue<'r>>,
+    current: Option<RefsReferringTo<'r>>,
+}
+
+impl<'a, 'r> Iterator for AllCollectableRefs<'a, 'r> {
+    type Item = Result<Reference<'r>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.failures.next() {
+            return Some(Err(err));
+        }
+
+        loop {
+            if let Some(item) = self.current.as_mut().and_then(Iterator::next) {
+                return Some(item);
+            }
+
+            let issue = self.issues.next()?;
+            match self.collectable.for_issue(&issue) {
+                Ok(refs) => self.current = Some(refs),
+                Err(err) => {
+                    self.current = None;
+                    return Some(Err(err));
+                },
+            }
+        }
+    }
+}
+
+
+/// Collectable reference along with the reason for its collection
+///
+pub struct CollectableRef<'r> {
+    pub reference: Reference<'r>,
+    pub reason: CollectionReason,
+}
+
+
+/// Summary of a collection
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Number of references examined
+    pub examined: usize,
+    /// Number of local heads deleted
+    pub heads: usize,
+    /// Number of local leaves deleted
+    pub leaves: usize,
+    /// Number of references of removed remotes deleted
+    pub orphaned: usize,
+    /// Number of issues of which references were deleted
+    pub issues: usize,
+    /// Names and targets of the references deleted
+    pub refs: Vec<(String, Oid)>,
+    /// Time taken by the collection
+    pub duration: Duration,
+}
+
+impl GcReport {
+    /// Get the total number of references deleted
+    ///
+    pub fn deleted(&self) -> usize {
+        self.heads + self.leaves + self.orphaned
+    }
 }
 
 
@@ -52,6 +333,20 @@ pub struct CollectableRefs<'r>
     consider_remote_refs: bool,
     /// Under what circumstances should local heads be collected?
     collect_heads: ReferenceCollectionSpec,
+    /// Under what circumstances should local leaves be collected?
+    collect_leaves: ReferenceCollectionSpec,
+    /// Minimum age of the messages referred to by collected references
+    older_than: Option<Duration>,
+    /// Should references of removed remotes be collected by `collect()`?
+    collect_orphaned: bool,
+    /// Commit-graph used for walking messages, if available
+    graph: Option<Rc<CommitGraph>>,
+    /// Names of the configured remotes, retrieved on demand
+    remotes: RefCell<Option<HashSet<String>>>,
+    /// Patterns of references protected in addition to the listed ones
+    protect: Vec<String>,
+    /// Names of the protected references, retrieved on demand
+    protected: RefCell<Option<HashSet<String>>>,
 }
 
 impl<'r> CollectableRefs<'r>
@@ -61,13 +356,50 @@ impl<'r> CollectableRefs<'r>
     /// By default only local references are considered, e.g. references which
     /// are unnecessary due to remote references are not reported.
     ///
+    /// If the repository has a commit-graph, it is used for walking messages.
+    ///
     pub fn new(repo: &'r git2::Repository) -> Self
     {
         CollectableRefs {
             repo: repo,
             consider_remote_refs: false,
             collect_heads: ReferenceCollectionSpec::Never,
+            collect_leaves: ReferenceCollectionSpec::Always,
+            older_than: None,
+            collect_orphaned: false,
+            graph: CommitGraph::open(repo).map(Rc::new),
+            remotes: RefCell::new(None),
+            protect: Vec::new(),
+            protected: RefCell::new(None),
+        }
+    }
+
+    /// Create a new CollectableRefs object configured by the repository
+    ///
+    /// Unlike `new()`, this function reads the defaults from the `dit.gc.*`
+    /// options of the repository's configuration. Options which are not set
+    /// retain the defaults of `new()`. The builder functions may be used for
+    /// overriding the configuration.
+    ///
+    pub fn with_config(repo: &'r git2::Repository) -> Result<Self>
+    {
+        let config = DitConfig::open(repo)?;
+        let mut retval = Self::new(repo);
+
+        if let Some(option) = config.gc_consider_remotes()? {
+            retval.consider_remote_refs = option;
+        }
+        if let Some(spec) = config.gc_collect_heads()? {
+            retval.collect_heads = spec;
+        }
+        if let Some(spec) = config.gc_collect_leaves()? {
+            retval.collect_leaves = spec;
+        }
+        if let Some(age) = config.gc_older_than()? {
+            retval.older_than = Some(age);
         }
+
+        Ok(retval)
     }
 
     /// Causes remote references to be considered
@@ -91,56 +423,98 @@ impl<'r> CollectableRefs<'r>
         self
     }
 
+    /// Causes local leaves to be collected only under a specified condition
+    ///
+    /// By default, leaves are always collected if the messages they refer to
+    /// are reachable via other references. Using this function a user may
+    /// restrict the collection, e.g. to leaves older than a given age.
+    ///
+    pub fn collect_leaves(mut self, condition: ReferenceCollectionSpec) -> Self {
+        self.collect_leaves = condition;
+        self
+    }
+
+    /// Causes only references to messages older than a given age to be collected
+    ///
+    /// The age applies to both heads and leaves, in addition to the conditions
+    /// specified for them. By default, or if `None` is passed, references are
+    /// collected regardless of the age of their messages.
+    ///
+    pub fn older_than(mut self, age: Option<Duration>) -> Self {
+        self.older_than = age;
+        self
+    }
+
+    /// Protects the references matching a glob pattern from collection
+    ///
+    /// The references are protected in addition to those listed in
+    /// `PROTECTED_REF` or configured via `dit.gc.protect`, which are always
+    /// protected.
+    ///
+    pub fn protect<S>(mut self, pattern: S) -> Self
+        where S: Into<String>
+    {
+        self.protect.push(pattern.into());
+        *self.protected.borrow_mut() = None;
+        self
+    }
+
+    /// Causes references of removed remotes to be collected by `collect()`
+    ///
+    /// By default, only local references are collected. Regardless of this
+    /// option, references of removed remotes may be retrieved via
+    /// `orphaned_refs()`.
+    ///
+    pub fn collect_orphaned(mut self, option: bool) -> Self {
+        self.collect_orphaned = option;
+        self
+    }
+
+    /// Check whether references may be collected because of remote references
+    ///
+    /// If so, the messages of the references collected may only be reachable
+    /// via remote-tracking references afterwards. They should be kept via
+    /// `keep_messages()` when deleting the references.
+    ///
+    pub fn relies_on_remotes(&self) -> bool {
+        self.consider_remote_refs || !self.collect_heads.is_never()
+    }
+
     /// Find collectable references for an issue
     ///
     /// Construct an iterator yielding all collectable references for a given
     /// issue, according to the configuration.
     ///
     pub fn for_issue(&self, issue: &Issue<'r>) -> Result<RefsReferringTo<'r>> {
-        let mut retval = {
-            let mut messages = self
-                .repo
-                .revwalk()
-                .chain_err(|| EK::CannotConstructRevwalk)?;
-            RefsReferringTo::new(messages)
-        };
+        let mut retval = self.referring_refs()?;
+        let collect_heads = self.with_age(&self.collect_heads);
+        let collect_leaves = self.with_age(&self.collect_leaves);
+
+        let mut remote = RemoteTargets::new(self, issue);
 
         // local head
         if let Some(local_head) = issue.local_head().ok() {
             // Its ok to ignore failures to retrieve the local head. It will
             // not be present in user's repositories anyway.
-            retval.push(
-                local_head
-                    .peel(git2::ObjectType::Commit)
-                    .chain_err(|| EK::CannotGetCommit)?
-                    .id()
-            )?;
+            retval.push(reference_target(&local_head)?)?;
 
             // Whether the local head should be collected or not is computed
             // here, in the exact same way it is for leaves. We do that
             // because can't mix the computation with those of the leaves.
             // It would cause head references to be removed if any message
             // was posted as a reply to the current head.
-            let mut head_history = self
-                .repo
-                .revwalk()
-                .chain_err(|| EK::CannotConstructRevwalk)?;
-            match self.collect_heads {
-                ReferenceCollectionSpec::Never => {},
-                ReferenceCollectionSpec::BackedByRemoteHead => {
-                    for item in issue.remote_refs(IssueRefType::Head)? {
-                        head_history.push(
-                            item?
-                                .peel(git2::ObjectType::Commit)
-                                .chain_err(|| EK::CannotGetCommit)?
-                                .id()
-                        )?;
-                    }
-                },
-            };
-            let mut referring_refs = iter::RefsReferringTo::new(head_history);
-            referring_refs.watch_ref(local_head)?;
-            referring_refs.collect_result_into(&mut retval)?;
+            let mut head_history = self.referring_refs()?;
+            if !collect_heads.is_never() {
+                for &head in remote.heads()? {
+                    head_history.push(head)?;
+                }
+                let target = reference_target(&local_head)?;
+                if !self.is_protected(&local_head)? &&
+                    collect_heads.admits(self.repo, target, &mut remote)? {
+                    head_history.watch_ref(local_head)?;
+                }
+            }
+            head_history.collect_result_into(&mut retval)?;
         }
 
         // local leaves
@@ -150,23 +524,224 @@ impl<'r> CollectableRefs<'r>
             //       references themselves since that would cause the
             //       `RefsReferringTo` report that exact same reference.
             Self::push_ref_parents(&mut retval, &leaf)?;
-            retval.watch_ref(leaf)?;
+
+            // Leaves not admitted or protected are not watched, but their
+            // messages still render other leaves collectable.
+            let target = reference_target(&leaf)?;
+            if !self.is_protected(&leaf)? && collect_leaves.admits(self.repo, target, &mut remote)? {
+                retval.watch_ref(leaf)?;
+            }
         }
 
         // remote refs
         if self.consider_remote_refs {
-            for item in issue.remote_refs(IssueRefType::Any)? {
-                retval.push(item?
-                    .peel(git2::ObjectType::Commit)
-                    .chain_err(|| EK::CannotGetCommit)?
-                    .id()
-                )?;
+            for &target in remote.all()? {
+                retval.push(target)?;
             }
         }
 
         Ok(retval)
     }
 
+    /// Find collectable references for all issues
+    ///
+    /// Construct an iterator yielding the collectable references of all issues
+    /// in the repository for which the filter supplied returns `true`. The
+    /// issues are processed one after another, as if `for_issue()` was called
+    /// for each of them. References of removed remotes are not considered, see
+    /// `orphaned_refs()`. Issues which cannot be retrieved are reported as
+    /// errors by the iterator.
+    ///
+    pub fn for_all_issues<'a, F>(&'a self, filter: F) -> Result<AllCollectableRefs<'a, 'r>>
+        where F: FnMut(&Issue<'r>) -> bool
+    {
+        let found = self.repo.issues_partial()?;
+        let issues: Vec<_> = found.value.into_iter().filter(filter).collect();
+        Ok(AllCollectableRefs {
+            collectable: self,
+            failures: found.failures.into_iter(),
+            issues: issues.into_iter(),
+            current: None,
+        })
+    }
+
+    /// Find collectable references for an issue along with the reasons
+    ///
+    /// This function yields the same references as `for_issue()`. In addition,
+    /// it determines why each reference is collectable. Local reasons take
+    /// precedence: a reference which is reachable from both the local head and
+    /// a remote reference is reported as merged into the head.
+    ///
+    /// Determining the reasons requires additional walks over the messages.
+    /// Hence, this function is intended for previews, e.g. dry runs.
+    ///
+    pub fn preview(&self, issue: &Issue<'r>) -> Result<Vec<CollectableRef<'r>>> {
+        let head = issue.local_head_target()?;
+        let mut leaves = Vec::new();
+        for item in issue.local_refs(IssueRefType::Leaf)? {
+            leaves.extend(item?.target());
+        }
+
+        let mut retval = Vec::new();
+        for item in self.for_issue(issue)? {
+            let reference = item?;
+            let target = reference_target(&reference)?;
+            let is_head = reference
+                .name()
+                .and_then(IssueRefType::of_ref)
+                .map(|(_, ref_type)| ref_type == IssueRefType::Head)
+                .unwrap_or(false);
+
+            // Heads are only collected if backed by a remote head.
+            let reason = if is_head {
+                CollectionReason::BackedByRemote
+            } else if self.reaches(head, target)? {
+                CollectionReason::MergedIntoHead
+            } else if self.reaches_any(leaves.iter().filter(|leaf| **leaf != target), target)? {
+                CollectionReason::SupersededByLeaf
+            } else {
+                CollectionReason::BackedByRemote
+            };
+            retval.push(CollectableRef { reference: reference, reason: reason });
+        }
+        Ok(retval)
+    }
+
+    /// Find remote-tracking references of remotes which no longer exist
+    ///
+    /// Remote-tracking dit references are not removed along with their remote
+    /// and would linger forever. This function yields all of them, regardless
+    /// of the issue they belong to, with the reason `Orphaned`. Such references
+    /// are never considered as backing for other references.
+    ///
+    /// The messages referred to may not be reachable otherwise. Hence, they
+    /// should be kept via `keep_messages()` when deleting the references.
+    ///
+    pub fn orphaned_refs(&self) -> Result<Vec<CollectableRef<'r>>> {
+        self.find_orphaned().map(|(_, refs)| refs)
+    }
+
+    /// Collect references
+    ///
+    /// This function determines the collectable references of all issues for
+    /// which the filter supplied returns `true` and deletes them. If configured
+    /// via `collect_orphaned()`, references of removed remotes are collected as
+    /// well, given that their issue passes the filter.
+    ///
+    /// All references are deleted in a single transaction. If necessary, the
+    /// messages referred to are kept via `keep_messages()` in the same
+    /// transaction, using the repository's default signature or an "unknown"
+    /// identity if none is configured. The deletion of leaves is recorded in
+    /// the reflogs of their issues' heads.
+    ///
+    /// Issues for which the collectable references cannot be determined are
+    /// left alone and reported as failures. The references of the remaining
+    /// issues are collected regardless.
+    ///
+    pub fn collect<F>(&self, mut filter: F) -> Result<BulkResult<GcReport>>
+        where F: FnMut(&Issue<'r>) -> bool
+    {
+        trace_span!("collect");
+        let start = Instant::now();
+        let mut report = GcReport::default();
+
+        let found = self.repo.issues_partial()?;
+        let mut failures = found.failures;
+        let mut refs = Vec::new();
+        for issue in found.value.into_iter().filter(|issue| filter(issue)) {
+            trace_span!("collect_issue", issue = %issue.id());
+            let mut issue_refs = Vec::new();
+            let examined = issue
+                .local_refs(IssueRefType::Any)
+                .map(Iterator::count)
+                .and_then(|examined| {
+                    self.for_issue(&issue)?.collect_result_into(&mut issue_refs)?;
+                    Ok(examined)
+                })
+                .chain_err(|| EK::CannotCollectReferences(issue.id()));
+            trace_event!(refs = issue_refs.len(), "found collectable references");
+            match examined {
+                Ok(examined) => {
+                    report.examined += examined;
+                    refs.append(&mut issue_refs);
+                },
+                Err(err) => failures.push(err),
+            }
+        }
+
+        let mut orphaned = Vec::new();
+        if self.collect_orphaned {
+            let (examined, candidates) = self.find_orphaned()?;
+            report.examined += examined;
+            for candidate in candidates {
+                // References of issues which cannot be found are left alone.
+                let admitted = candidate
+                    .reference
+                    .name()
+                    .and_then(IssueRefType::of_ref)
+                    .and_then(|(id, _)| Issue::new(self.repo, id).ok())
+                    .map(|issue| filter(&issue))
+                    .unwrap_or(false);
+                if admitted {
+                    orphaned.push(candidate.reference);
+                }
+            }
+        }
+
+        let mut issues = HashSet::new();
+        let mut kept = Vec::new();
+        let candidates = refs.iter().map(|r| (r, false)).chain(orphaned.iter().map(|r| (r, true)));
+        for (reference, is_orphaned) in candidates {
+            let (name, target) = match (reference.name(), reference.target()) {
+                (Some(name), Some(target)) => (name, target),
+                _ => continue,
+            };
+            let issue_ref = IssueRefType::of_ref(name);
+            if is_orphaned {
+                report.orphaned += 1;
+            } else if issue_ref.as_ref().map(|&(_, ref ref_type)| *ref_type == IssueRefType::Head).unwrap_or(false) {
+                report.heads += 1;
+            } else {
+                report.leaves += 1;
+            }
+            issues.extend(issue_ref.map(|(id, _)| id));
+
+            // Messages may only be reachable via remote references after the
+            // collection. Messages referred to by references of removed
+            // remotes may not be reachable at all.
+            if is_orphaned || self.relies_on_remotes() {
+                kept.push(target);
+            }
+            report.refs.push((name.to_owned(), target));
+        }
+        report.issues = issues.len();
+
+        // the references are deleted all at once, so an interrupted collection
+        // doesn't leave an issue with only some of its references removed
+        if !report.refs.is_empty() {
+            let mut transaction = RefTransaction::new(self.repo)?;
+            if !kept.is_empty() {
+                let sig = self.signature()?;
+                keep_messages(self.repo, &mut transaction, &sig, kept)?;
+            }
+            for &(ref name, _) in &report.refs {
+                transaction.remove(name)?;
+            }
+            transaction.commit()?;
+            self.log_deletions(&report.refs)?;
+        }
+
+        report.duration = start.elapsed();
+        trace_event!(examined = report.examined,
+                     heads = report.heads,
+                     leaves = report.leaves,
+                     orphaned = report.orphaned,
+                     issues = report.issues,
+                     failures = failures.len(),
+                     "collected references");
+        Ok(BulkResult { value: report, failures: failures })
+    }
+
     /// Find collectable references for multiple issues
     ///
     /// This is a convenience function.
@@ -174,8 +749,7 @@ impl<'r> CollectableRefs<'r>
     /// # Note
     ///
     /// Internally, this function collects the references during the call.
-    /// Consider using the `for_issues` function in conjunction with
-    /// `flat_map()` on an issue iterator instead.
+    /// Consider using the `for_all_issues` function instead.
     ///
     #[deprecated]
     pub fn into_refs<I, J, K>(self, issues: I) -> Result<Vec<Reference<'r>>>
@@ -200,8 +774,7 @@ impl<'r> CollectableRefs<'r>
     /// # Note
     ///
     /// Internally, this function collects the references during the call.
-    /// Consider using the `for_issues` function in conjunction with
-    /// `flat_map()` on an issue iterator instead.
+    /// Consider using the `for_all_issues` function instead.
     ///
     #[deprecated]
     pub fn into_collector<I, J, K>(self, issues: I) -> Result<ReferenceCollector<'r>>
@@ -212,15 +785,182 @@ impl<'r> CollectableRefs<'r>
         self.into_refs(issues).map(ReferenceCollector::from)
     }
 
+    /// Create an empty `RefsReferringTo`
+    ///
+    /// The commit-graph is used if available.
+    ///
+    fn referring_refs(&self) -> Result<RefsReferringTo<'r>> {
+        match self.graph {
+            Some(ref graph) => Ok(RefsReferringTo::with_graph(self.repo, graph.clone())),
+            None => self
+                .repo
+                .revwalk()
+                .map(RefsReferringTo::new)
+                .chain_err(|| EK::CannotConstructRevwalk),
+        }
+    }
+
+    /// Record the deletion of references in the reflogs of their issues' heads
+    ///
+    /// Since the reflog of a reference is deleted along with the reference,
+    /// deletions are recorded in the reflog of the local head of the issue
+    /// instead, given that the head still exists and has a reflog.
+    ///
+    fn log_deletions(&self, refs: &[(String, Oid)]) -> Result<()> {
+        let sig = self.signature()?;
+        for &(ref name, target) in refs {
+            let head = match name.rfind("/leaves/") {
+                Some(pos) if name.starts_with("refs/dit/") => format!("{}/head", &name[..pos]),
+                _ => continue,
+            };
+            let head_target = match self.repo.find_reference(&head) {
+                Ok(reference) => reference.target(),
+                Err(ref err) if err.code() == git2::ErrorCode::NotFound => continue,
+                Err(err) => return Err(err).chain_err(|| EK::CannotGetReference(head.clone())),
+            };
+
+            let mut reflog = self.repo.reflog(&head).chain_err(|| EK::CannotGetReference(head.clone()))?;
+            if let (false, Some(head_target)) = (reflog.is_empty(), head_target) {
+                let message = format!("git-dit: gc, deleted {} at {}", name, target);
+                reflog
+                    .append(head_target, &sig, Some(&message))
+                    .and_then(|_| reflog.write())
+                    .chain_err(|| EK::CannotSetReference(head.clone()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the signature for recording a collection
+    ///
+    /// Like git, we fall back to an "unknown" identity if no signature is
+    /// configured.
+    ///
+    fn signature(&self) -> Result<git2::Signature<'static>> {
+        self.repo
+            .signature()
+            .or_else(|_| git2::Signature::now("unknown", "unknown"))
+            .map_err(From::from)
+    }
+
+    /// Find remote-tracking references of remotes which no longer exist
+    ///
+    /// In addition to the references, the number of remote-tracking dit
+    /// references examined is returned.
+    ///
+    fn find_orphaned(&self) -> Result<(usize, Vec<CollectableRef<'r>>)> {
+        let glob = "refs/remotes/*/dit/**";
+        let mut examined = 0;
+        let mut retval = Vec::new();
+        for item in self.repo.references_glob(glob).chain_err(|| EK::CannotGetReferences(glob.to_owned()))? {
+            let reference = item?;
+            examined += 1;
+            if !self.is_configured(&reference)? && !self.is_protected(&reference)? {
+                retval.push(CollectableRef { reference: reference, reason: CollectionReason::Orphaned });
+            }
+        }
+        Ok((examined, retval))
+    }
+
+    /// Combine a condition with the age cutoff, if any
+    ///
+    fn with_age(&self, spec: &ReferenceCollectionSpec) -> ReferenceCollectionSpec {
+        match self.older_than {
+            Some(age) => ReferenceCollectionSpec::All(vec![spec.clone(), ReferenceCollectionSpec::OlderThan(age)]),
+            None => spec.clone(),
+        }
+    }
+
+    /// Get the targets of an issue's remote references of configured remotes
+    ///
+    fn remote_targets(&self, issue: &Issue<'r>, ref_type: IssueRefType) -> Result<Vec<Oid>> {
+        let mut retval = Vec::new();
+        for item in issue.remote_refs(ref_type)? {
+            let reference = item?;
+            if self.is_configured(&reference)? {
+                retval.push(reference_target(&reference)?);
+            }
+        }
+        Ok(retval)
+    }
+
+    /// Check whether a reference is protected from collection
+    ///
+    fn is_protected(&self, reference: &Reference) -> Result<bool> {
+        if self.protected.borrow().is_none() {
+            let mut patterns = protected_patterns(self.repo)?;
+            patterns.extend(DitConfig::open(self.repo)?.gc_protect()?);
+            patterns.extend(self.protect.iter().cloned());
+
+            let mut protected = HashSet::new();
+            for pattern in patterns {
+                for item in self.repo.references_glob(&pattern).chain_err(|| EK::CannotGetReferences(pattern.clone()))? {
+                    protected.extend(item?.name().map(String::from));
+                }
+            }
+            *self.protected.borrow_mut() = Some(protected);
+        }
+
+        Ok(match (reference.name(), self.protected.borrow().as_ref()) {
+            (Some(name), Some(protected)) => protected.contains(name),
+            _ => false,
+        })
+    }
+
+    /// Check whether a remote-tracking reference belongs to a configured remote
+    ///
+    fn is_configured(&self, reference: &Reference) -> Result<bool> {
+        if self.remotes.borrow().is_none() {
+            let remotes = self
+                .repo
+                .remotes()?
+                .iter()
+                .flatten()
+                .map(String::from)
+                .collect();
+            *self.remotes.borrow_mut() = Some(remotes);
+        }
+
+        let remote = reference
+            .name()
+            .and_then(|name| name.strip_prefix("refs/remotes/"))
+            .and_then(|name| name.find("/dit/").map(|end| &name[..end]));
+        Ok(match (remote, self.remotes.borrow().as_ref()) {
+            (Some(remote), Some(remotes)) => remotes.contains(remote),
+            _ => false,
+        })
+    }
+
+    /// Check whether a message is reachable from another one
+    ///
+    fn reaches(&self, from: Option<Oid>, message: Oid) -> Result<bool> {
+        match from {
+            Some(from) if from == message => Ok(true),
+            Some(from) => self.repo.graph_descendant_of(from, message).chain_err(|| EK::CannotGetCommit(message)),
+            None => Ok(false),
+        }
+    }
+
+    /// Check whether a message is reachable from any of some other messages
+    ///
+    fn reaches_any<'a, I>(&self, from: I, message: Oid) -> Result<bool>
+        where I: IntoIterator<Item = &'a Oid>
+    {
+        for id in from {
+            if self.reaches(Some(*id), message)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     /// Push the parents of a referred commit to a revwalk
     ///
     fn push_ref_parents<'a>(target: &mut RefsReferringTo, reference: &'a Reference<'a>) -> Result<()>
     {
         let referred_commit = reference
-            .peel(git2::ObjectType::Commit)
-            .chain_err(|| EK::CannotGetCommit)?
-            .into_commit()
-            .map_err(|o| Error::from_kind(EK::CannotGetCommitForRev(o.id().to_string())))?;
+            .peel_to_commit()
+            .chain_err(|| EK::CannotGetReference(String::from_utf8_lossy(reference.name_bytes()).into_owned()))?;
         for parent in referred_commit.parent_ids() {
             target.push(parent)?;
         }
@@ -229,6 +969,151 @@ impl<'r> CollectableRefs<'r>
 }
 
 
+/// Parse an age, e.g. "2w"
+///
+/// An age is a number followed by one of the units 'h' (hours), 'd' (days),
+/// 'w' (weeks), 'm' (months of 30 days) or 'y' (years of 365 days). A number
+/// without a unit denotes days. Ages which cannot be represented are rejected.
+///
+pub fn parse_age(s: &str) -> Result<Duration> {
+    let (number, unit) = match s.char_indices().find(|&(_, c)| !c.is_ascii_digit()) {
+        Some((pos, _)) => (&s[..pos], &s[pos..]),
+        None => (s, "d"),
+    };
+
+    let factor = match unit {
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "m" => 30 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _   => return Err(Error::from_kind(EK::MalformedAge(s.to_owned()))),
+    };
+
+    let number = number
+        .parse::<u64>()
+        .chain_err(|| EK::MalformedAge(s.to_owned()))?;
+    number
+        .checked_mul(factor)
+        .map(Duration::from_secs)
+        .ok_or_else(|| Error::from_kind(EK::MalformedAge(s.to_owned())))
+}
+
+
+/// Get the patterns of the references listed as protected
+///
+/// The patterns are read from the commit referred to by `PROTECTED_REF`. If
+/// the reference does not exist, no references are listed.
+///
+pub fn protected_patterns(repo: &git2::Repository) -> Result<Vec<String>> {
+    let tree = match repo.find_reference(PROTECTED_REF) {
+        Ok(reference) => reference.peel_to_tree().chain_err(|| EK::CannotGetReference(PROTECTED_REF.to_owned()))?,
+        Err(ref err) if err.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).chain_err(|| EK::CannotGetReference(PROTECTED_REF.to_owned())),
+    };
+    let blob = match tree.get_name(PROTECTED_FILE) {
+        Some(entry) => entry
+            .to_object(repo)
+            .and_then(|obj| obj.peel_to_blob())
+            .chain_err(|| EK::CannotGetReference(PROTECTED_REF.to_owned()))?,
+        None => return Ok(Vec::new()),
+    };
+
+    let patterns = ::std::str::from_utf8(blob.content())
+        .chain_err(|| EK::CannotGetReference(PROTECTED_REF.to_owned()))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    Ok(patterns)
+}
+
+/// Update the list of protected references
+///
+/// A new commit listing the patterns supplied is created on top of the one
+/// currently referred to by `PROTECTED_REF`, which is then updated.
+///
+pub fn update_protected_patterns<I, S>(repo: &git2::Repository,
+                                       patterns: I,
+                                       author: &git2::Signature,
+                                       committer: &git2::Signature,
+                                       message: &str,
+) -> Result<Oid>
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str>
+{
+    let content = patterns
+        .into_iter()
+        .fold(String::new(), |acc, pattern| acc + pattern.as_ref() + "\n");
+    let tree = repo
+        .blob(content.as_bytes())
+        .and_then(|blob| {
+            let mut builder = repo.treebuilder(None)?;
+            builder.insert(PROTECTED_FILE, blob, 0o100644)?;
+            builder.write()
+        })
+        .and_then(|id| repo.find_tree(id))
+        .chain_err(|| EK::CannotBuildTree)?;
+    let parent = match repo.find_reference(PROTECTED_REF) {
+        Ok(reference) => Some(reference.peel_to_commit().chain_err(|| EK::CannotGetReference(PROTECTED_REF.to_owned()))?),
+        Err(_) => None,
+    };
+
+    repo.commit(Some(PROTECTED_REF), author, committer, message, &tree, &parent.iter().collect::<Vec<_>>())
+        .chain_err(|| EK::CannotSetReference(PROTECTED_REF.to_owned()))
+}
+
+
+/// Keep messages reachable independently of dit references
+///
+/// Creates a commit whose parents are the messages supplied along with those
+/// kept previously and records an update of `KEEP_REF` to it in the
+/// transaction. The commit is returned, or `None` if all the messages are
+/// already kept.
+///
+pub fn keep_messages<I>(repo: &git2::Repository,
+                        transaction: &mut RefTransaction,
+                        sig: &git2::Signature,
+                        messages: I
+) -> Result<Option<Oid>>
+    where I: IntoIterator<Item = Oid>
+{
+    let mut kept: Vec<Oid> = match repo.find_reference(KEEP_REF) {
+        Ok(reference) => reference
+            .peel_to_commit()
+            .chain_err(|| EK::CannotGetReference(KEEP_REF.to_owned()))?
+            .parent_ids()
+            .collect(),
+        Err(ref err) if err.code() == git2::ErrorCode::NotFound => Vec::new(),
+        Err(err) => return Err(err).chain_err(|| EK::CannotGetReference(KEEP_REF.to_owned())),
+    };
+    let count = kept.len();
+
+    let mut known: HashSet<Oid> = kept.iter().cloned().collect();
+    kept.extend(messages.into_iter().filter(|id| known.insert(*id)));
+    if kept.len() == count {
+        return Ok(None);
+    }
+
+    let parents = kept
+        .into_iter()
+        .map(|id| repo.find_commit(id).chain_err(|| EK::CannotGetCommit(id)))
+        .collect::<Result<Vec<_>>>()?;
+    let tree = repo
+        .treebuilder(None)
+        .and_then(|treebuilder| treebuilder.write())
+        .and_then(|id| repo.find_tree(id))
+        .chain_err(|| EK::CannotBuildTree)?;
+    let id = repo
+        .commit(None, sig, sig, KEEP_MESSAGE, &tree, &parents.iter().collect::<Vec<_>>())
+        .chain_err(|| EK::CannotCreateMessage)?;
+
+    transaction.set_target(KEEP_REF, id, KEEP_MESSAGE)?;
+    Ok(Some(id))
+}
+
+
 
 
 #[cfg(test)]
@@ -310,5 +1195,488 @@ mod tests {
         collected.sort();
         assert_eq!(refs_to_collect, collected);
     }
+
+    #[test]
+    fn collection_for_all_issues() {
+        let mut testing_repo = TestingRepo::new("collection_for_all_issues");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let mut issues = Vec::new();
+        let mut messages = Vec::new();
+        for n in 0..2 {
+            let issue = repo
+                .create_issue(&sig, &sig, format!("Test message {}", n), &empty_tree, vec![])
+                .expect("Could not create issue");
+            let initial_message = issue
+                .initial_message()
+                .expect("Could not retrieve initial message");
+            let message = issue
+                .add_message(&sig, &sig, "Reply", &empty_tree, vec![&initial_message])
+                .expect("Could not add message");
+            issue.update_head(message.id(), true).expect("Could not update head");
+            issues.push(issue);
+            messages.push(message.id());
+        }
+
+        let collectable = CollectableRefs::new(repo);
+        let mut collected: Vec<_> = collectable
+            .for_all_issues(|_| true)
+            .expect("Error during collection")
+            .map(|r| r.expect("Error during collection").target().expect("No target"))
+            .collect();
+        collected.sort();
+        let mut expected = messages.clone();
+        expected.sort();
+        assert_eq!(collected, expected);
+
+        let collected: Vec<_> = collectable
+            .for_all_issues(|issue| *issue == issues[1])
+            .expect("Error during collection")
+            .map(|r| r.expect("Error during collection").target().expect("No target"))
+            .collect();
+        assert_eq!(collected, vec![messages[1]]);
+
+        // a head reference of an issue which doesn't exist
+        let bogus = "refs/dit/0123456789abcdef0123456789abcdef01234567/head";
+        repo.reference(bogus, messages[0], false, "test").expect("Could not create reference");
+        let (collected, failures): (Vec<_>, Vec<_>) = collectable
+            .for_all_issues(|_| true)
+            .expect("Error during collection")
+            .partition(Result::is_ok);
+        assert_eq!(collected.len(), 2);
+        assert_eq!(failures.len(), 1);
+    }
+
+    #[test]
+    fn collection_preview() {
+        let mut testing_repo = TestingRepo::new("collection_preview");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        issue
+            .add_message(&sig, &sig, "Test message 4", &empty_tree, vec![&message2])
+            .expect("Could not add message");
+        issue.update_head(message1.id(), true).expect("Could not update head");
+
+        let mut preview: Vec<_> = CollectableRefs::new(repo)
+            .preview(&issue)
+            .expect("Error during collection")
+            .into_iter()
+            .map(|r| (r.reference.target().expect("No target"), r.reason))
+            .collect();
+        preview.sort_by_key(|&(id, _)| id);
+        let mut expected = vec![
+            (message1.id(), CollectionReason::MergedIntoHead),
+            (message2.id(), CollectionReason::SupersededByLeaf),
+        ];
+        expected.sort_by_key(|&(id, _)| id);
+        assert_eq!(preview, expected);
+    }
+
+    #[test]
+    fn collection_by_age() {
+        let mut testing_repo = TestingRepo::new("collection_by_age");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let old_sig = git2::Signature::new("Foo Bar", "foo.bar@example.com", &git2::Time::new(1_500_000_000, 0))
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&old_sig, &old_sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let old_message = issue
+            .add_message(&old_sig, &old_sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let new_message = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&old_message])
+            .expect("Could not add message");
+        issue.update_head(new_message.id(), true).expect("Could not update head");
+
+        let collect = |spec| -> Vec<Oid> {
+            CollectableRefs::new(repo)
+                .collect_leaves(spec)
+                .for_issue(&issue)
+                .expect("Error during collection")
+                .map(|r| r.expect("Error during collection").target().expect("No target"))
+                .collect()
+        };
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(collect(ReferenceCollectionSpec::Always).len(), 2);
+        assert_eq!(collect(ReferenceCollectionSpec::OlderThan(day)), vec![old_message.id()]);
+        assert!(collect(ReferenceCollectionSpec::All(vec![
+            ReferenceCollectionSpec::OlderThan(day),
+            ReferenceCollectionSpec::Never,
+        ])).is_empty());
+    }
+
+    #[test]
+    fn collection_config() {
+        let mut testing_repo = TestingRepo::new("collection_config");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let old_sig = git2::Signature::new("Foo Bar", "foo.bar@example.com", &git2::Time::new(1_500_000_000, 0))
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&old_sig, &old_sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let old_message = issue
+            .add_message(&old_sig, &old_sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let new_message = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&old_message])
+            .expect("Could not add message");
+        issue.update_head(new_message.id(), true).expect("Could not update head");
+
+        let mut config = repo.config().expect("Could not open config");
+        let collectable = CollectableRefs::with_config(repo).expect("Could not read config");
+        assert!(!collectable.relies_on_remotes());
+        assert_eq!(collectable.for_issue(&issue).expect("Error during collection").count(), 2);
+
+        config.set_str("dit.gc.older-than", "1w").expect("Could not configure repository");
+        config.set_str("dit.gc.collect-heads", "backed-by-remote").expect("Could not configure repository");
+        let collectable = CollectableRefs::with_config(repo).expect("Could not read config");
+        assert!(collectable.relies_on_remotes());
+        let collected: Vec<_> = collectable
+            .for_issue(&issue)
+            .expect("Error during collection")
+            .map(|r| r.expect("Error during collection").target().expect("No target"))
+            .collect();
+        assert_eq!(collected, vec![old_message.id()]);
+
+        // the builder overrides the configuration
+        let collectable = CollectableRefs::with_config(repo)
+            .expect("Could not read config")
+            .collect_heads(ReferenceCollectionSpec::Never)
+            .older_than(None);
+        assert!(!collectable.relies_on_remotes());
+        assert_eq!(collectable.for_issue(&issue).expect("Error during collection").count(), 2);
+
+        // without any remote, no leaf is backed by one
+        config.set_str("dit.gc.collect-leaves", "backed-by-remote").expect("Could not configure repository");
+        let collectable = CollectableRefs::with_config(repo)
+            .expect("Could not read config")
+            .collect_heads(ReferenceCollectionSpec::Never)
+            .older_than(None);
+        assert_eq!(collectable.for_issue(&issue).expect("Error during collection").count(), 0);
+
+        config.set_str("dit.gc.older-than", "1 week").expect("Could not configure repository");
+        assert!(CollectableRefs::with_config(repo).is_err());
+    }
+
+    #[test]
+    fn age_parsing() {
+        assert_eq!(parse_age("3h").expect("Could not parse age"), Duration::from_secs(3 * 60 * 60));
+        assert_eq!(parse_age("2").expect("Could not parse age"), Duration::from_secs(2 * 24 * 60 * 60));
+        assert_eq!(parse_age("1w").expect("Could not parse age"), Duration::from_secs(7 * 24 * 60 * 60));
+        assert!(parse_age("1x").is_err());
+        assert!(parse_age("w").is_err());
+        assert!(parse_age("99999999999999999y").is_err());
+    }
+
+    #[test]
+    fn orphaned_refs() {
+        let mut testing_repo = TestingRepo::new("orphaned_refs");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let collectable = || CollectableRefs::new(repo)
+            .consider_remote_refs(true)
+            .collect_heads(ReferenceCollectionSpec::Never);
+        let count = || collectable().for_issue(&issue).expect("Error during collection").count();
+
+        // references of remotes which don't exist don't back the local leaf
+        let orphan = format!("refs/remotes/gone/dit/{}/head", issue.id());
+        repo.reference(&orphan, message.id(), false, "test").expect("Could not create reference");
+        assert_eq!(count(), 0);
+
+        repo.remote("origin", "https://example.com/foo.git").expect("Could not add remote");
+        let name = format!("refs/remotes/origin/dit/{}/head", issue.id());
+        repo.reference(&name, message.id(), false, "test").expect("Could not create reference");
+        assert_eq!(count(), 1);
+
+        let orphaned: Vec<_> = collectable()
+            .orphaned_refs()
+            .expect("Could not find orphaned references")
+            .into_iter()
+            .map(|r| (r.reference.name().unwrap_or("").to_owned(), r.reason))
+            .collect();
+        assert_eq!(orphaned, vec![(orphan, CollectionReason::Orphaned)]);
+    }
+
+    #[test]
+    fn collection_backed_by_remote() {
+        let mut testing_repo = TestingRepo::new("collection_backed_by_remote");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let reply = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&message])
+            .expect("Could not add message");
+
+        let count = |condition| CollectableRefs::new(repo)
+            .collect_leaves(condition)
+            .for_issue(&issue)
+            .expect("Error during collection")
+            .count();
+        assert_eq!(count(ReferenceCollectionSpec::Always), 1);
+        assert_eq!(count(ReferenceCollectionSpec::BackedByRemote), 0);
+
+        // references of remotes which don't exist don't back the leaf
+        let name = format!("refs/remotes/gone/dit/{}/leaves/{}", issue.id(), reply.id());
+        repo.reference(&name, reply.id(), false, "test").expect("Could not create reference");
+        assert_eq!(count(ReferenceCollectionSpec::BackedByRemote), 0);
+
+        repo.remote("origin", "https://example.com/foo.git").expect("Could not add remote");
+        let name = format!("refs/remotes/origin/dit/{}/leaves/{}", issue.id(), reply.id());
+        repo.reference(&name, reply.id(), false, "test").expect("Could not create reference");
+        let collected: Vec<_> = CollectableRefs::new(repo)
+            .collect_leaves(ReferenceCollectionSpec::BackedByRemote)
+            .for_issue(&issue)
+            .expect("Error during collection")
+            .map(|r| r.expect("Error during collection").target().expect("No target"))
+            .collect();
+        assert_eq!(collected, vec![message.id()]);
+    }
+
+    #[test]
+    fn collection_report() {
+        let mut testing_repo = TestingRepo::new("collection_report");
+        let repo = testing_repo.repo();
+        repo.config()
+            .and_then(|mut config| {
+                config.set_str("user.name", "Foo Bar")?;
+                config.set_str("user.email", "foo.bar@example.com")?;
+                config.set_bool("core.logAllRefUpdates", true)
+            })
+            .expect("Could not configure repository");
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let mut issues = Vec::new();
+        for n in 0..2 {
+            let issue = repo
+                .create_issue(&sig, &sig, format!("Test message {}", n), &empty_tree, vec![])
+                .expect("Could not create issue");
+            let initial_message = issue
+                .initial_message()
+                .expect("Could not retrieve initial message");
+            let message = issue
+                .add_message(&sig, &sig, "Reply", &empty_tree, vec![&initial_message])
+                .expect("Could not add message");
+            issue.update_head(message.id(), true).expect("Could not update head");
+            issues.push(issue);
+        }
+        let orphan = format!("refs/remotes/gone/dit/{}/head", issues[0].id());
+        repo.reference(&orphan, issues[0].id(), false, "test").expect("Could not create reference");
+
+        let report = CollectableRefs::new(repo)
+            .collect_orphaned(true)
+            .collect(|issue| *issue == issues[0])
+            .expect("Error during collection")
+            .into_result()
+            .expect("Error during collection");
+        assert_eq!(report.examined, 3);
+        assert_eq!((report.heads, report.leaves, report.orphaned), (0, 1, 1));
+        assert_eq!(report.deleted(), 2);
+        assert_eq!(report.issues, 1);
+        assert!(report.refs.iter().any(|&(ref name, _)| *name == orphan));
+        assert!(repo.find_reference(&orphan).is_err());
+
+        // the orphaned reference's message is kept
+        let kept = repo
+            .find_reference(KEEP_REF)
+            .and_then(|reference| reference.peel_to_commit())
+            .expect("Could not find kept messages");
+        assert_eq!(kept.parent_ids().collect::<Vec<_>>(), vec![issues[0].id()]);
+
+        // the deletion of the leaf is recorded in the reflog of the head
+        let reflog = issues[0].head_reflog().expect("Could not read reflog");
+        let entry = reflog.get(0).expect("No reflog entry");
+        assert_eq!(entry.id_old(), entry.id_new());
+        assert!(entry.message().map(|m| m.starts_with("git-dit: gc, deleted refs/dit/")).unwrap_or(false));
+
+        // issues which cannot be retrieved don't keep others from being collected
+        let bogus = "refs/dit/0123456789abcdef0123456789abcdef01234567/head";
+        repo.reference(bogus, issues[1].id(), false, "test").expect("Could not create reference");
+        let result = CollectableRefs::new(repo)
+            .collect(|_| true)
+            .expect("Error during collection");
+        assert_eq!(result.failures.len(), 1);
+        assert_eq!((result.value.heads, result.value.leaves, result.value.orphaned), (0, 1, 0));
+        assert_eq!(result.value.issues, 1);
+    }
+
+    #[test]
+    fn protected_refs() {
+        let mut testing_repo = TestingRepo::new("protected_refs");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let mut parent = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let mut messages = Vec::new();
+        for n in 0..4 {
+            parent = issue
+                .add_message(&sig, &sig, format!("Reply {}", n), &empty_tree, vec![&parent])
+                .expect("Could not add message");
+            messages.push(parent.id());
+        }
+        let leaf = |n: usize| format!("refs/dit/{}/leaves/{}", issue.id(), messages[n]);
+
+        assert!(protected_patterns(repo).expect("Could not read protected refs").is_empty());
+        update_protected_patterns(repo, vec![leaf(0)], &sig, &sig, "Protect a leaf")
+            .expect("Could not protect refs");
+        assert_eq!(protected_patterns(repo).expect("Could not read protected refs"), vec![leaf(0)]);
+        repo.config()
+            .and_then(|mut config| config.set_multivar("dit.gc.protect", "^$", &leaf(1)))
+            .expect("Could not configure repository");
+
+        let collected = |collectable: CollectableRefs| -> Vec<Oid> {
+            let mut retval: Vec<_> = collectable
+                .for_issue(&issue)
+                .expect("Error during collection")
+                .map(|r| r.expect("Error during collection").target().expect("No target"))
+                .collect();
+            retval.sort();
+            retval
+        };
+
+        let mut expected = vec![messages[2]];
+        assert_eq!(collected(CollectableRefs::new(repo).protect(leaf(2))), Vec::<Oid>::new());
+        assert_eq!(collected(CollectableRefs::new(repo)), expected);
+        update_protected_patterns(repo, Vec::<String>::new(), &sig, &sig, "Release leaves")
+            .expect("Could not protect refs");
+        expected.push(messages[0]);
+        expected.sort();
+        assert_eq!(collected(CollectableRefs::new(repo)), expected);
+    }
+
+    #[test]
+    fn kept_messages() {
+        let mut testing_repo = TestingRepo::new("kept_messages");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let kept = |messages: Vec<Oid>| {
+            let mut transaction = RefTransaction::new(repo).expect("Could not start transaction");
+            let retval = keep_messages(repo, &mut transaction, &sig, messages)
+                .expect("Could not keep messages");
+            transaction.commit().expect("Could not commit transaction");
+            retval
+        };
+        let parents = || -> Vec<Oid> {
+            repo.find_reference(KEEP_REF)
+                .and_then(|reference| reference.peel_to_commit())
+                .expect("Could not find kept messages")
+                .parent_ids()
+                .collect()
+        };
+
+        assert!(kept(vec![message1.id()]).is_some());
+        assert_eq!(parents(), vec![message1.id()]);
+        assert!(kept(vec![message1.id()]).is_none());
+        assert!(kept(vec![message2.id(), message1.id()]).is_some());
+        assert_eq!(parents(), vec![message1.id(), message2.id()]);
+    }
 }
 
@@ -0,0 +1,152 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Generation of synthetic trackers
+//!
+//! This module provides the `TrackerGenerator` for populating a repository
+//! with synthetic issues, e.g. for benchmarking. It is only available if the
+//! library is built with the "generator" feature.
+//!
+//! Each issue consists of an initial message followed by a chain of replies,
+//! each of which has a leaf reference. Messages carry status and tag trailers,
+//! so accumulating metadata involves actual work. The heads of every other
+//! issue are advanced to the last reply, which renders the leaves of these
+//! issues collectable. Authors and times are derived from the position of a
+//! message, hence generating the same tracker twice results in identical
+//! messages.
+//!
+
+use git2::{self, Signature, Time};
+
+use issue::Issue;
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Time of the first message generated
+///
+const EPOCH: i64 = 1_500_000_000;
+
+
+/// Generator for synthetic trackers
+///
+pub struct TrackerGenerator<'r> {
+    repo: &'r git2::Repository,
+    issues: usize,
+    replies: usize,
+    tags: usize,
+}
+
+impl<'r> TrackerGenerator<'r> {
+    /// Create a new generator for a repository
+    ///
+    /// By default, 100 issues with 3 replies each and 10 distinct tags are
+    /// generated.
+    ///
+    pub fn new(repo: &'r git2::Repository) -> Self {
+        TrackerGenerator { repo: repo, issues: 100, replies: 3, tags: 10 }
+    }
+
+    /// Set the number of issues to generate
+    ///
+    pub fn issues(mut self, issues: usize) -> Self {
+        self.issues = issues;
+        self
+    }
+
+    /// Set the number of replies to generate for each issue
+    ///
+    pub fn replies(mut self, replies: usize) -> Self {
+        self.replies = replies;
+        self
+    }
+
+    /// Set the number of distinct tags to use
+    ///
+    pub fn tags(mut self, tags: usize) -> Self {
+        self.tags = tags.max(1);
+        self
+    }
+
+    /// Generate the tracker
+    ///
+    /// Returns the issues generated.
+    ///
+    pub fn generate(&self) -> Result<Vec<Issue<'r>>> {
+        let tree = self.repo.empty_tree()?;
+        let mut retval = Vec::with_capacity(self.issues);
+
+        for number in 0..self.issues {
+            let sig = self.signature(number, 0)?;
+            let text = format!("Issue {}\n\nSynthetic issue number {}.\n\nDit-status: open\nDit-tag: tag{}",
+                               number, number, number % self.tags);
+            let issue = self.repo.create_issue(&sig, &sig, text, &tree, Vec::new())?;
+
+            let mut parent = issue.initial_message()?;
+            for reply in 1..(self.replies + 1) {
+                let sig = self.signature(number, reply)?;
+                let text = match reply % 3 {
+                    0 => format!("Reply {}\n\nDit-status: closed", reply),
+                    1 => format!("Reply {}\n\nDit-tag: tag{}", reply, (number + reply) % self.tags),
+                    _ => format!("Reply {}\n\nSynthetic reply number {}.", reply, reply),
+                };
+                parent = issue.add_message(&sig, &sig, text, &tree, Some(&parent))?;
+            }
+
+            if number % 2 == 1 {
+                issue.update_head(parent.id(), true)?;
+            }
+            retval.push(issue);
+        }
+
+        Ok(retval)
+    }
+
+    /// Create the signature for a message
+    ///
+    fn signature(&self, issue: usize, reply: usize) -> Result<Signature<'static>> {
+        let author = (issue + reply) % 7;
+        let time = Time::new(EPOCH + (issue * (self.replies + 1) + reply) as i64 * 60, 0);
+        Signature::new(&format!("Author {}", author), &format!("author{}@example.com", author), &time)
+            .chain_err(|| EK::CannotCreateMessage)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    #[test]
+    fn generate_tracker() {
+        let mut testing_repo = TestingRepo::new("generate_tracker");
+        let repo = testing_repo.repo();
+
+        let issues = TrackerGenerator::new(repo)
+            .issues(4)
+            .replies(2)
+            .generate()
+            .expect("Could not generate tracker");
+        assert_eq!(issues.len(), 4);
+        assert_eq!(repo.issues().expect("Could not retrieve issues").len(), 4);
+
+        for issue in issues {
+            let messages = issue
+                .messages()
+                .expect("Could not retrieve messages")
+                .count();
+            assert_eq!(messages, 3);
+        }
+    }
+}
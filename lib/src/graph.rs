@@ -0,0 +1,379 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Commit-graph support
+//!
+//! This module provides read access to git's commit-graph file, which records
+//! the parents and generation numbers of commits. Generation numbers allow
+//! terminating walks early: a commit can only reach commits with a lower
+//! generation number. Since the file is only updated by git itself, e.g. by
+//! `git gc`, commits created afterwards are not covered by the graph. However,
+//! the graph is always closed under parents.
+//!
+//! Only a single commit-graph file, as written by `git commit-graph write`
+//! without `--split`, is supported. In any other case, no graph is provided
+//! and users are expected to fall back to a regular revwalk.
+//!
+
+use git2::{self, Oid};
+use std::collections::{BinaryHeap, HashSet};
+use std::fs;
+use std::rc::Rc;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Length of (SHA-1) object ids
+///
+const OID_LEN: usize = 20;
+
+/// Length of a commit data entry
+///
+const DATA_LEN: usize = OID_LEN + 16;
+
+/// Parent position indicating the absence of a parent
+///
+const NO_PARENT: u32 = 0x7000_0000;
+
+/// Flag indicating that a parent position refers to the extra edge list
+///
+const EDGE_FLAG: u32 = 0x8000_0000;
+
+
+/// Commit-graph
+///
+pub struct CommitGraph {
+    data: Vec<u8>,
+    fanout: usize,
+    oids: usize,
+    commits: usize,
+    edges: Option<usize>,
+    count: usize,
+}
+
+impl CommitGraph {
+    /// Open the commit-graph of a repository
+    ///
+    /// Returns `None` if the repository has no usable commit-graph or if the
+    /// use of commit-graphs was disabled via `core.commitGraph`.
+    ///
+    pub fn open(repo: &git2::Repository) -> Option<Self> {
+        let enabled = repo
+            .config()
+            .and_then(|config| config.get_bool("core.commitGraph"))
+            .unwrap_or(true);
+        if !enabled {
+            return None;
+        }
+
+        let path = repo.path().join("objects").join("info").join("commit-graph");
+        fs::read(path).ok().and_then(Self::from_bytes)
+    }
+
+    /// Create a commit-graph from the contents of a commit-graph file
+    ///
+    /// Returns `None` if the data is malformed, of an unsupported version or
+    /// if it lacks generation numbers.
+    ///
+    pub fn from_bytes(data: Vec<u8>) -> Option<Self> {
+        if data.len() < 8 || &data[0..4] != b"CGPH" || data[4] != 1 || data[5] != 1 || data[7] != 0 {
+            return None;
+        }
+
+        let mut fanout = None;
+        let mut oids = None;
+        let mut commits = None;
+        let mut edges = None;
+        for chunk in 0..data[6] as usize {
+            let entry = 8 + chunk * 12;
+            let id = data.get(entry..entry + 4)?;
+            let offset = read_u64(&data, entry + 4)? as usize;
+            match id {
+                b"OIDF" => fanout = Some(offset),
+                b"OIDL" => oids = Some(offset),
+                b"CDAT" => commits = Some(offset),
+                b"EDGE" => edges = Some(offset),
+                _ => {},
+            }
+        }
+        let fanout = fanout?;
+        let count = read_u32(&data, fanout + 255 * 4)? as usize;
+
+        let retval = CommitGraph {
+            fanout: fanout,
+            oids: oids?,
+            commits: commits?,
+            edges: edges,
+            count: count,
+            data: data,
+        };
+        if retval.data.len() < retval.oids + count * OID_LEN ||
+            retval.data.len() < retval.commits + count * DATA_LEN {
+            return None;
+        }
+
+        // Files written by old versions of git may not contain generation
+        // numbers, in which case they are zero.
+        if (0..count).any(|pos| retval.generation_at(pos) == 0) {
+            return None;
+        }
+        Some(retval)
+    }
+
+    /// Get the number of commits in the graph
+    ///
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Check whether the graph is empty
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Get the generation number of a commit
+    ///
+    /// Returns `None` if the commit is not part of the graph.
+    ///
+    pub fn generation(&self, id: Oid) -> Option<u32> {
+        self.position(id).map(|pos| self.generation_at(pos))
+    }
+
+    /// Get the parents of a commit
+    ///
+    /// Returns `None` if the commit is not part of the graph.
+    ///
+    pub fn parents(&self, id: Oid) -> Option<Vec<Oid>> {
+        let pos = self.position(id)?;
+        let entry = self.commits + pos * DATA_LEN + OID_LEN;
+
+        let mut retval = Vec::new();
+        let first = read_u32(&self.data, entry)?;
+        if first == NO_PARENT {
+            return Some(retval);
+        }
+        retval.push(self.oid_at(first as usize)?);
+
+        let second = read_u32(&self.data, entry + 4)?;
+        if second == NO_PARENT {
+            return Some(retval);
+        }
+        if second & EDGE_FLAG == 0 {
+            retval.push(self.oid_at(second as usize)?);
+            return Some(retval);
+        }
+
+        // octopus merges list their remaining parents in the edge list
+        let mut edge = self.edges? + (second & !EDGE_FLAG) as usize * 4;
+        loop {
+            let value = read_u32(&self.data, edge)?;
+            retval.push(self.oid_at((value & !EDGE_FLAG) as usize)?);
+            if value & EDGE_FLAG != 0 {
+                return Some(retval);
+            }
+            edge += 4;
+        }
+    }
+
+    /// Find the position of a commit in the graph
+    ///
+    fn position(&self, id: Oid) -> Option<usize> {
+        let bytes = id.as_bytes();
+        let first = bytes[0] as usize;
+        let mut low = match first {
+            0 => 0,
+            _ => read_u32(&self.data, self.fanout + (first - 1) * 4)? as usize,
+        };
+        let mut high = (read_u32(&self.data, self.fanout + first * 4)? as usize).min(self.count);
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let start = self.oids + mid * OID_LEN;
+            match self.data[start..start + OID_LEN].cmp(bytes) {
+                ::std::cmp::Ordering::Less => low = mid + 1,
+                ::std::cmp::Ordering::Greater => high = mid,
+                ::std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// Get the id of the commit at a given position
+    ///
+    fn oid_at(&self, pos: usize) -> Option<Oid> {
+        if pos >= self.count {
+            return None;
+        }
+        let start = self.oids + pos * OID_LEN;
+        Oid::from_bytes(&self.data[start..start + OID_LEN]).ok()
+    }
+
+    /// Get the generation number of the commit at a given position
+    ///
+    fn generation_at(&self, pos: usize) -> u32 {
+        read_u32(&self.data, self.commits + pos * DATA_LEN + OID_LEN + 8).unwrap_or(0) >> 2
+    }
+}
+
+
+/// Walk over commits in descending order of their generation numbers
+///
+/// Commits which are not part of the graph are considered to have a higher
+/// generation number than any commit in the graph. Their parents are looked up
+/// in the repository. Each commit is returned only once.
+///
+pub struct GraphWalk<'r> {
+    repo: &'r git2::Repository,
+    graph: Rc<CommitGraph>,
+    queue: BinaryHeap<(u32, Oid)>,
+    seen: HashSet<Oid>,
+}
+
+impl<'r> GraphWalk<'r> {
+    /// Create a new walk without any starting points
+    ///
+    pub fn new(repo: &'r git2::Repository, graph: Rc<CommitGraph>) -> Self {
+        GraphWalk { repo: repo, graph: graph, queue: BinaryHeap::new(), seen: HashSet::new() }
+    }
+
+    /// Push a starting point for the walk
+    ///
+    pub fn push(&mut self, id: Oid) {
+        if self.seen.insert(id) {
+            let generation = self.generation(id);
+            self.queue.push((generation, id));
+        }
+    }
+
+    /// Get the generation number of a commit
+    ///
+    /// Commits not covered by the graph are assigned the highest possible
+    /// generation number.
+    ///
+    pub fn generation(&self, id: Oid) -> u32 {
+        self.graph.generation(id).unwrap_or(::std::u32::MAX)
+    }
+
+    /// Get the next commit with a generation number of at least `cutoff`
+    ///
+    /// Returns `None` if no such commit is left. Since the walk is ordered by
+    /// generation numbers, none of the remaining commits can reach a commit
+    /// with a generation number of `cutoff` or higher in that case.
+    ///
+    pub fn next_above(&mut self, cutoff: u32) -> Option<Result<Oid>> {
+        match self.queue.peek() {
+            Some(&(generation, _)) if generation >= cutoff => {},
+            _ => return None,
+        }
+        let (_, id) = self.queue.pop()?;
+
+        let parents = match self.graph.parents(id) {
+            Some(parents) => parents,
+            None => match self.repo.find_commit(id) {
+                Ok(commit) => commit.parent_ids().collect(),
+                Err(err) => return Some(Err(Error::with_chain(err, EK::CannotGetCommit(id)))),
+            },
+        };
+        for parent in parents {
+            self.push(parent);
+        }
+        Some(Ok(id))
+    }
+}
+
+impl<'r> Iterator for GraphWalk<'r> {
+    type Item = Result<Oid>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_above(0)
+    }
+}
+
+
+/// Read a big-endian 32 bit integer
+///
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| bytes.iter().fold(0, |acc, byte| (acc << 8) | *byte as u32))
+}
+
+/// Read a big-endian 64 bit integer
+///
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    data.get(offset..offset + 8)
+        .map(|bytes| bytes.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64))
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::{commit_graph, TestingRepo};
+
+    use repository::RepositoryExt;
+
+    #[test]
+    fn generations_and_parents() {
+        let mut testing_repo = TestingRepo::new("commit_graph");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let root = repo
+            .commit(None, &sig, &sig, "Test message 1", &empty_tree, &[])
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        let a = repo
+            .commit(None, &sig, &sig, "Test message 2", &empty_tree, &[&root])
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        let b = repo
+            .commit(None, &sig, &sig, "Test message 3", &empty_tree, &[&root])
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        let merge = repo
+            .commit(None, &sig, &sig, "Test message 4", &empty_tree, &[&a, &b, &root])
+            .expect("Could not create commit");
+        let outside = repo
+            .commit(None, &sig, &sig, "Test message 5", &empty_tree, &[&a])
+            .expect("Could not create commit");
+
+        let graph = CommitGraph::from_bytes(commit_graph(repo, &[root.id(), a.id(), b.id(), merge]))
+            .expect("Could not parse commit-graph");
+        assert_eq!(graph.len(), 4);
+        assert_eq!(graph.generation(root.id()), Some(1));
+        assert_eq!(graph.generation(a.id()), Some(2));
+        assert_eq!(graph.generation(merge), Some(3));
+        assert_eq!(graph.generation(outside), None);
+        assert_eq!(graph.parents(root.id()), Some(vec![]));
+        assert_eq!(graph.parents(a.id()), Some(vec![root.id()]));
+        assert_eq!(graph.parents(merge), Some(vec![a.id(), b.id(), root.id()]));
+
+        let mut walk = GraphWalk::new(repo, Rc::new(graph));
+        walk.push(outside);
+        let walked: Vec<_> = walk
+            .map(|id| id.expect("Could not walk commits"))
+            .collect();
+        assert_eq!(walked, vec![outside, a.id(), root.id()]);
+    }
+
+    #[test]
+    fn malformed_graph() {
+        assert!(CommitGraph::from_bytes(Vec::new()).is_none());
+        assert!(CommitGraph::from_bytes(b"CGPH\x01\x01\x00\x00".to_vec()).is_none());
+    }
+}
@@ -13,13 +13,20 @@
 //!
 
 use git2::{self, Commit, Oid, Reference, References};
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::fmt;
 use std::hash;
+use std::path::{Path, PathBuf};
 use std::result::Result as RResult;
+use std::str::FromStr;
 
 use error::*;
 use error::ErrorKind as EK;
-use iter::Messages;
+use iter::{DitRefs, HeadRefs, Messages};
+use observer::{self, Operation};
+use repository::RepositoryExt;
+use transaction::RefTransaction;
 
 
 #[derive(PartialEq)]
@@ -79,6 +86,41 @@ impl IssueRefType {
         None
     }
 
+    /// Get the component of the issue assiciated with a reference
+    ///
+    /// Issue references may reside in a component namespace, e.g.
+    /// `refs/dit/<component>/<issue>/head`. This function returns the
+    /// component of a dit reference for the issue with the given id. If the
+    /// reference is not in a component namespace, `None` is returned.
+    ///
+    pub fn component_of_ref(refname: &str, id: Oid) -> Option<&str> {
+        // Strip the prefix of remote references, since the name of a remote
+        // may well be "dit".
+        let rest = if refname.starts_with("refs/dit/") {
+            &refname["refs/dit/".len()..]
+        } else if refname.starts_with("refs/remotes/") {
+            let rest = &refname["refs/remotes/".len()..];
+            let rest = &rest[rest.find('/')? + 1..];
+            if !rest.starts_with("dit/") {
+                return None;
+            }
+            &rest["dit/".len()..]
+        } else {
+            return None;
+        };
+
+        let len: usize = rest
+            .split('/')
+            .take_while(|part| Self::id_from_str(part) != Some(id))
+            .map(|part| part.len() + 1)
+            .sum();
+        if len == 0 {
+            None
+        } else {
+            Some(&rest[..len - 1])
+        }
+    }
+
     /// Create an Oid from a full 40-character representation
     ///
     /// If the number of characters is not exactly 40 or the string is not an
@@ -104,14 +146,71 @@ impl fmt::Debug for IssueRefType {
 }
 
 
+/// Policy for advancing an issue's head reference
+///
+/// When a new message is added to an issue, the local head reference may be
+/// advanced to the new message. With `FastForward`, the head is only advanced
+/// if the message it currently refers to is part of the new message's first
+/// parent chain, e.g. if the new message is a (transitive) reply to the head.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeadPolicy {
+    Never,
+    FastForward,
+    Always,
+}
+
+impl FromStr for HeadPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never"         => Ok(HeadPolicy::Never),
+            "fast-forward"  => Ok(HeadPolicy::FastForward),
+            "always"        => Ok(HeadPolicy::Always),
+            _ => Err(Error::from_kind(EK::UnknownHeadPolicy(s.to_owned()))),
+        }
+    }
+}
+
+
+/// Check whether a component name is valid
+///
+/// Components are used as namespaces for issue references. A component name
+/// may consist of multiple parts separated by slashes, but none of the parts
+/// may look like an issue id. The top-level namespace `meta` is reserved for
+/// tracker-wide data, e.g. moderation information.
+///
+pub fn check_component(component: &str) -> Result<()> {
+    let valid = !component.is_empty() &&
+        component.split('/').next() != Some("meta") &&
+        Reference::is_valid_name(&format!("refs/dit/{}/head", component)) &&
+        component.split('/').all(|part| IssueRefType::id_from_str(part).is_none());
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::from_kind(EK::MalformedComponent(component.to_owned())))
+    }
+}
+
+
 /// Issue handle
 ///
 /// Instances of this type represent single issues. Issues reside in
 /// repositories and are uniquely identified by an id.
 ///
+/// An issue may reside in a component namespace, in which case all its
+/// references are placed under `refs/dit/<component>/<issue>/`.
+///
+/// Creating a handle does not involve any reference lookup. The names of the
+/// issue's head references are only looked up when they are first needed and
+/// are cached, along with their targets, for the lifetime of the handle.
+///
 pub struct Issue<'r> {
     repo: &'r git2::Repository,
     obj: git2::Object<'r>,
+    component: Option<String>,
+    heads: RefCell<Option<Vec<(String, Oid)>>>,
 }
 
 impl<'r> Issue<'r> {
@@ -119,8 +218,20 @@ impl<'r> Issue<'r> {
     ///
     pub fn new(repo: &'r git2::Repository, id: Oid) -> Result<Self> {
         repo.find_object(id, Some(git2::ObjectType::Commit))
-            .chain_err(|| EK::CannotGetCommitForRev(id.to_string()))
-            .map(|obj| Issue { repo: repo, obj: obj })
+            .chain_err(|| EK::CannotGetCommit(id))
+            .map(|obj| Issue { repo: repo, obj: obj, component: None, heads: RefCell::new(None) })
+    }
+
+    /// Create a new handle for an issue with a given id in a component
+    ///
+    pub fn new_in_component(repo: &'r git2::Repository, id: Oid, component: Option<&str>) -> Result<Self> {
+        if let Some(c) = component {
+            check_component(c)?;
+        }
+        Self::new(repo, id).map(|mut issue| {
+            issue.component = component.map(String::from);
+            issue
+        })
     }
 
     /// Get the issue's id
@@ -129,13 +240,19 @@ impl<'r> Issue<'r> {
         self.obj.id()
     }
 
+    /// Get the component in which the issue resides, if any
+    ///
+    pub fn component(&self) -> Option<&str> {
+        self.component.as_ref().map(String::as_str)
+    }
+
     /// Get the issue's initial message
     ///
     pub fn initial_message(&self) -> Result<git2::Commit<'r>> {
         self.obj
             .clone()
             .into_commit()
-            .map_err(|obj| Error::from_kind(EK::CannotGetCommitForRev(obj.id().to_string())))
+            .map_err(|obj| Error::from_kind(EK::CannotGetCommit(obj.id())))
     }
 
     /// Get possible heads of the issue
@@ -143,11 +260,60 @@ impl<'r> Issue<'r> {
     /// Returns the head references from both the local repository and remotes
     /// for this issue.
     ///
-    pub fn heads(&self) -> Result<References<'r>> {
-        let glob = format!("**/dit/{}/head", self.ref_part());
-        self.repo
-            .references_glob(&glob)
-            .chain_err(|| EK::CannotFindIssueHead(self.id()))
+    /// The names of the head references are determined on the first call and
+    /// reused by subsequent calls. The references themselves are looked up
+    /// on each call, so their targets are always current.
+    ///
+    pub fn heads(&self) -> Result<HeadRefs<'r>> {
+        let names = self
+            .head_targets()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        Ok(HeadRefs::new(self.repo, names))
+    }
+
+    /// Get the names and targets of the heads of the issue
+    ///
+    /// Returns the names of the head references from both the local repository
+    /// and remotes along with their targets. The references are resolved on
+    /// the first call only. Hence, changes of the references other than via
+    /// this handle are not reflected.
+    ///
+    pub fn head_targets(&self) -> Result<Vec<(String, Oid)>> {
+        if let Some(ref heads) = *self.heads.borrow() {
+            return Ok(heads.clone());
+        }
+
+        let pattern = format!("{}/head", self.ref_part());
+        let heads: Vec<_> = DitRefs::new(self.repo, &pattern)
+            .chain_err(|| EK::CannotFindIssueHead(self.id()))?
+            .collect::<RResult<Vec<_>, _>>()
+            .chain_err(|| EK::CannotFindIssueHead(self.id()))?
+            .into_iter()
+            .filter_map(|reference| match (reference.name(), reference.target()) {
+                (Some(name), Some(target)) => Some((name.to_owned(), target)),
+                _ => None,
+            })
+            .collect();
+        *self.heads.borrow_mut() = Some(heads.clone());
+        Ok(heads)
+    }
+
+    /// Get the target of the local head of the issue
+    ///
+    /// Returns `None` if the issue has no local head. Unlike `head_targets()`,
+    /// this function resolves the head on each call. Hence, updates of the
+    /// head by other handles or processes are reflected, e.g. when deciding
+    /// whether to advance the head.
+    ///
+    pub fn local_head_target(&self) -> Result<Option<Oid>> {
+        let refname = format!("refs/dit/{}/head", self.ref_part());
+        match self.repo.find_reference(&refname) {
+            Ok(reference) => Ok(reference.target()),
+            Err(ref err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err).chain_err(|| EK::CannotFindIssueHead(self.id())),
+        }
     }
 
     /// Get the local issue head for the issue
@@ -191,11 +357,8 @@ impl<'r> Issue<'r> {
     /// Return all references of a specific type associated with the issue from
     /// both the local and remote repositories.
     ///
-    pub fn all_refs(&self, ref_type: IssueRefType) -> Result<References<'r>> {
-        let glob = format!("**/dit/{}/{}", self.ref_part(), ref_type.glob_part());
-        self.repo
-            .references_glob(&glob)
-            .chain_err(|| EK::CannotGetReferences(glob))
+    pub fn all_refs(&self, ref_type: IssueRefType) -> Result<DitRefs<'r>> {
+        DitRefs::new(self.repo, &format!("{}/{}", self.ref_part(), ref_type.glob_part()))
     }
 
     /// Get all Messages of the issue
@@ -205,14 +368,17 @@ impl<'r> Issue<'r> {
     pub fn messages(&self) -> Result<Messages<'r>> {
         self.terminated_messages()
             .and_then(|mut messages| {
-                let glob = format!("**/dit/{}/**", self.ref_part());
-
                 // The iterator will iterate over all the messages in the tree
                 // spanned but it will halt at the initial message.
-                messages
-                    .revwalk
-                    .push_glob(glob.as_ref())
-                    .chain_err(|| EK::CannotGetReferences(glob))?;
+                let pattern = format!("{}/**", self.ref_part());
+                for reference in DitRefs::new(self.repo, &pattern)? {
+                    if let Some(target) = reference?.target() {
+                        messages
+                            .revwalk
+                            .push(target)
+                            .chain_err(|| EK::CannotGetReferences(pattern.clone()))?;
+                    }
+                }
 
                 Ok(messages)
             })
@@ -229,7 +395,7 @@ impl<'r> Issue<'r> {
                 messages
                     .revwalk
                     .push(message)
-                    .chain_err(|| EK::CannotConstructRevwalk)?;
+                    .chain_err(|| EK::CannotGetCommit(message))?;
 
                 Ok(messages)
             })
@@ -244,8 +410,8 @@ impl<'r> Issue<'r> {
                 messages.terminate_at_initial(self)?;
 
                 // configure the revwalk
-                messages.revwalk.simplify_first_parent();
-                messages.revwalk.set_sorting(git2::Sort::TOPOLOGICAL);
+                messages.revwalk.simplify_first_parent()?;
+                messages.revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
 
                 Ok(messages)
             })
@@ -272,13 +438,18 @@ impl<'r> Issue<'r> {
         self.repo
             .commit(None, author, committer, message.as_ref(), tree, &parent_vec)
             .and_then(|id| self.repo.find_commit(id))
-            .chain_err(|| EK::CannotCreateMessage)
+            .chain_err(|| EK::CannotAddMessage(self.id()))
             .and_then(|message| self.add_leaf(message.id()).map(|_| message))
+            .map(|message| {
+                observer::notify(self.repo, &Operation::MessageAdded { issue: self.id(), message: message.id() });
+                message
+            })
     }
 
     /// Update the local head reference of the issue
     ///
     /// Updates the local head reference of the issue to the provided message.
+    /// The update is recorded in the reference's reflog.
     ///
     /// # Warnings
     ///
@@ -288,31 +459,228 @@ impl<'r> Issue<'r> {
     pub fn update_head(&self, message: Oid, replace: bool) -> Result<Reference<'r>> {
         let refname = format!("refs/dit/{}/head", self.ref_part());
         let reflogmsg = format!("git-dit: set head reference of {} to {}", self, message);
-        self.repo
-            .reference(&refname, message, replace, &reflogmsg)
-            .chain_err(|| EK::CannotSetReference(refname))
+        let head = self.repo
+            .reference_ensure_log(&refname)
+            .and_then(|_| self.repo.reference(&refname, message, replace, &reflogmsg))
+            .chain_err(|| EK::CannotSetReference(refname.clone()))?;
+
+        observer::notify(self.repo, &Operation::HeadUpdated {
+            issue: self.id(),
+            reference: refname.clone(),
+            target: message,
+        });
+
+        // keep the cached heads in sync
+        if let Some(ref mut heads) = *self.heads.borrow_mut() {
+            heads.retain(|&(ref name, _)| *name != refname);
+            heads.push((refname, message));
+        }
+        Ok(head)
+    }
+
+    /// Advance the local head reference according to a policy
+    ///
+    /// Returns the updated head reference or `None` if the policy did not
+    /// permit advancing the head. With `HeadPolicy::FastForward`, the head is
+    /// not created if it does not exist.
+    ///
+    pub fn advance_head(&self, message: &Commit, policy: HeadPolicy) -> Result<Option<Reference<'r>>> {
+        if self.head_advances(message, policy)? {
+            self.update_head(message.id(), true).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Check whether a policy permits advancing the local head to a message
+    ///
+    pub fn head_advances(&self, message: &Commit, policy: HeadPolicy) -> Result<bool> {
+        Ok(match policy {
+            HeadPolicy::Never       => false,
+            HeadPolicy::FastForward => match self.local_head_target()? {
+                Some(head) => {
+                    let mut messages = self.messages_from(message.id())?;
+                    messages.any(|item| item.map(|m| m.id() == head).unwrap_or(false))
+                },
+                None => false,
+            },
+            HeadPolicy::Always      => true,
+        })
+    }
+
+    /// Record an update of the local head reference in a transaction
+    ///
+    /// Like `update_head()`, but the reference is only written once the
+    /// transaction is committed. The head is always replaced.
+    ///
+    pub fn stage_head(&self, transaction: &mut RefTransaction, message: Oid) -> Result<()> {
+        let refname = format!("refs/dit/{}/head", self.ref_part());
+        let reflogmsg = format!("git-dit: set head reference of {} to {}", self, message);
+        transaction.set_target(&refname, message, &reflogmsg)?;
+
+        // the heads will be resolved anew once the transaction is committed
+        *self.heads.borrow_mut() = None;
+        Ok(())
     }
 
     /// Add a new leaf reference associated with the issue
     ///
-    /// Creates a new leaf reference for the message provided in the issue.
+    /// Creates a new leaf reference for the message provided in the issue. The
+    /// creation is recorded in the reference's reflog.
     ///
     pub fn add_leaf(&self, message: Oid) -> Result<Reference<'r>> {
         let refname = format!("refs/dit/{}/leaves/{}", self.ref_part(), message);
         let reflogmsg = format!("git-dit: new leaf for {}: {}", self, message);
         self.repo
-            .reference(&refname, message, false, &reflogmsg)
+            .reference_ensure_log(&refname)
+            .and_then(|_| self.repo.reference(&refname, message, false, &reflogmsg))
             .chain_err(|| EK::CannotSetReference(refname))
     }
 
+    /// Re-create a leaf reference for a message not created locally
+    ///
+    /// Like `add_leaf()`, but the creation is recorded in the reflog such that
+    /// the message is not reported by `last_created_message()`. This is used
+    /// for keeping messages reachable after removing a reply to them.
+    ///
+    pub fn restore_leaf(&self, message: Oid) -> Result<Reference<'r>> {
+        let refname = format!("refs/dit/{}/leaves/{}", self.ref_part(), message);
+        let reflogmsg = format!("git-dit: restore leaf for {}: {}", self, message);
+        self.repo
+            .reference_ensure_log(&refname)
+            .and_then(|_| self.repo.reference(&refname, message, false, &reflogmsg))
+            .chain_err(|| EK::CannotSetReference(refname))
+    }
+
+    /// Record the creation of a leaf reference in a transaction
+    ///
+    /// Like `add_leaf()`, but the reference is only written once the
+    /// transaction is committed.
+    ///
+    pub fn stage_leaf(&self, transaction: &mut RefTransaction, message: Oid) -> Result<()> {
+        let refname = format!("refs/dit/{}/leaves/{}", self.ref_part(), message);
+        let reflogmsg = format!("git-dit: new leaf for {}: {}", self, message);
+        transaction.set_target(&refname, message, &reflogmsg)
+    }
+
+    /// Get the reflog of the issue's local head
+    ///
+    /// The reflog records the updates of the head performed via git-dit as
+    /// well as the deletion of other references of the issue by the garbage
+    /// collection. Entries are ordered from newest to oldest. If the head has
+    /// no reflog, e.g. because reflogs are disabled via `core.logAllRefUpdates`,
+    /// the reflog returned is empty.
+    ///
+    pub fn head_reflog(&self) -> Result<git2::Reflog> {
+        let refname = format!("refs/dit/{}/head", self.ref_part());
+        self.repo.reflog(&refname).chain_err(|| EK::CannotGetReference(refname.clone()))
+    }
+
+    /// Get the message created most recently in the local repository
+    ///
+    /// Messages created locally are identified via the reflogs of their leaf
+    /// references, which record the creation of the references by git-dit.
+    /// Leaves restored via `restore_leaf()` are not considered.
+    /// Returns `None` if no leaf reference of the issue was created this way,
+    /// e.g. because reflogs are disabled via `core.logAllRefUpdates`.
+    ///
+    pub fn last_created_message(&self) -> Result<Option<Commit<'r>>> {
+        let mut retval: Option<(i64, Oid)> = None;
+        for leaf in self.local_refs(IssueRefType::Leaf)? {
+            let leaf = leaf?;
+            let name = match leaf.name() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            // The oldest entry records the creation of the reference
+            let reflog = self.repo.reflog(name)?;
+            let creation = match reflog.iter().last() {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let by_dit = creation.message().map(|m| m.starts_with("git-dit: new leaf")).unwrap_or(false);
+            if !creation.id_old().is_zero() || !by_dit {
+                continue;
+            }
+
+            // Messages created within the same second are ordered by ancestry
+            let (time, id) = (creation.committer().when().seconds(), creation.id_new());
+            let newer = match retval {
+                Some((latest, current)) => time > latest ||
+                    (time == latest && self.repo.graph_descendant_of(id, current)?),
+                None => true,
+            };
+            if newer {
+                retval = Some((time, id));
+            }
+        }
+
+        match retval {
+            Some((_, id)) => self.repo
+                .find_commit(id)
+                .map(Some)
+                .chain_err(|| EK::CannotGetCommit(id)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the author of the issue
+    ///
+    /// The author of the initial message is resolved via the repository's
+    /// mailmap.
+    ///
+    pub fn author(&self) -> Result<git2::Signature<'static>> {
+        self.repo.author_of(&self.initial_message()?)
+    }
+
+    /// Get the participants of the issue
+    ///
+    /// The authors of the issue's messages are returned in the order of their
+    /// first message. Authors are resolved via the repository's mailmap, hence
+    /// contributors who changed their name or email address are reported only
+    /// once, under their canonical identity.
+    ///
+    pub fn participants(&self) -> Result<Vec<git2::Signature<'static>>> {
+        let mailmap = self.repo.mailmap()?;
+        let mut authors = Vec::new();
+        for message in self.messages()? {
+            let message = message?;
+            let author = message.author();
+            authors.push((author.when().seconds(), mailmap.resolve_signature(&author)?));
+        }
+        authors.sort_by_key(|&(time, _)| time);
+
+        let mut known = HashSet::new();
+        Ok(authors
+            .into_iter()
+            .map(|(_, author)| author)
+            .filter(|author| known.insert(author.email_bytes().to_ascii_lowercase()))
+            .collect())
+    }
+
     /// Get reference part for this issue
     ///
     /// The references associated with an issue reside in paths specific to the
     /// issue. This function returns the part unique for the issue, e.g. the
-    /// part after the  `dit/`.
+    /// part after the  `dit/`, including the component if the issue resides
+    /// in a component namespace.
     ///
     pub fn ref_part(&self) -> String {
-        self.id().to_string()
+        match self.component {
+            Some(ref component) => format!("{}/{}", component, self.id()),
+            None => self.id().to_string(),
+        }
+    }
+
+    /// Get a handle of the issue which may be sent to other threads
+    ///
+    pub fn handle(&self) -> IssueHandle {
+        IssueHandle {
+            id: self.id(),
+            component: self.component.clone(),
+            path: self.repo.path().to_owned(),
+        }
     }
 }
 
@@ -339,6 +707,61 @@ impl<'r> hash::Hash for Issue<'r> {
 }
 
 
+/// Owned issue handle
+///
+/// Unlike an `Issue`, an `IssueHandle` does not borrow a repository. Hence it
+/// may be sent between threads, e.g. to workers which hold repository handles
+/// of their own. An `IssueHandle` is bound to such a handle via `bind()`.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IssueHandle {
+    id: Oid,
+    component: Option<String>,
+    path: PathBuf,
+}
+
+impl IssueHandle {
+    /// Get the issue's id
+    ///
+    pub fn id(&self) -> Oid {
+        self.id
+    }
+
+    /// Get the component in which the issue resides, if any
+    ///
+    pub fn component(&self) -> Option<&str> {
+        self.component.as_ref().map(String::as_str)
+    }
+
+    /// Get the path of the git directory of the issue's repository
+    ///
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Open a new handle of the issue's repository
+    ///
+    pub fn open(&self) -> Result<git2::Repository> {
+        git2::Repository::open(&self.path).map_err(From::from)
+    }
+
+    /// Bind the handle to a repository
+    ///
+    /// The repository need not be the one the handle was created from, as long
+    /// as it contains the issue's initial message.
+    ///
+    pub fn bind<'r>(&self, repo: &'r git2::Repository) -> Result<Issue<'r>> {
+        Issue::new_in_component(repo, self.id, self.component())
+    }
+}
+
+impl fmt::Display for IssueHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        write!(f, "{}", self.id)
+    }
+}
+
+
 
 
 #[cfg(test)]
@@ -374,6 +797,42 @@ mod tests {
         assert!(IssueRefType::of_ref("refs/foo/65b56706fdc3501749d008750c61a1f24b888f72/leaves/f6bd121bdc2ba5906e412da19191a2eaf2025755").is_none());
     }
 
+    #[test]
+    fn component_identification() {
+        let id = Oid::from_str("65b56706fdc3501749d008750c61a1f24b888f72").unwrap();
+
+        {
+            let (ref_id, reftype) = IssueRefType::of_ref("refs/dit/frontend/65b56706fdc3501749d008750c61a1f24b888f72/head")
+                .expect("Expected valid issue id and ref type");
+            assert_eq!(ref_id, id);
+            assert_eq!(reftype, IssueRefType::Head);
+        }
+
+        assert_eq!(
+            IssueRefType::component_of_ref("refs/dit/frontend/65b56706fdc3501749d008750c61a1f24b888f72/head", id),
+            Some("frontend")
+        );
+        assert_eq!(
+            IssueRefType::component_of_ref("refs/remotes/dit/dit/ui/web/65b56706fdc3501749d008750c61a1f24b888f72/head", id),
+            Some("ui/web")
+        );
+        assert!(IssueRefType::component_of_ref("refs/dit/65b56706fdc3501749d008750c61a1f24b888f72/head", id).is_none());
+        assert!(IssueRefType::component_of_ref("refs/remotes/origin/dit/65b56706fdc3501749d008750c61a1f24b888f72/head", id).is_none());
+    }
+
+    #[test]
+    fn component_check() {
+        check_component("frontend").expect("Valid component rejected");
+        check_component("ui/web").expect("Valid component rejected");
+        assert!(check_component("").is_err());
+        assert!(check_component("ui//web").is_err());
+        assert!(check_component("foo bar").is_err());
+        assert!(check_component("65b56706fdc3501749d008750c61a1f24b888f72").is_err());
+        assert!(check_component("meta").is_err());
+        assert!(check_component("meta/foo").is_err());
+        check_component("metadata").expect("Valid component rejected");
+    }
+
     // Issue tests
 
     #[test]
@@ -509,6 +968,46 @@ mod tests {
         assert!(iter2.next().is_none());
     }
 
+    #[test]
+    fn component_refs() {
+        let mut testing_repo = TestingRepo::new("component_refs");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue_in_component(Some("backend"), &sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        assert_eq!(issue.component(), Some("backend"));
+        assert_eq!(issue.ref_part(), format!("backend/{}", issue.id()));
+
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        assert_eq!(
+            issue.local_head().unwrap().name(),
+            Some(format!("refs/dit/backend/{}/head", issue.id()).as_str())
+        );
+        let leaf = issue
+            .local_refs(IssueRefType::Leaf)
+            .expect("Could not retrieve leaves")
+            .next()
+            .expect("Could not find leaf reference")
+            .expect("Could not retrieve leaf reference");
+        assert_eq!(
+            leaf.name(),
+            Some(format!("refs/dit/backend/{}/leaves/{}", issue.id(), message.id()).as_str())
+        );
+    }
+
     #[test]
     fn update_head() {
         let mut testing_repo = TestingRepo::new("update_head");
@@ -531,11 +1030,283 @@ mod tests {
             .expect("Could not add message");
 
         assert_eq!(issue.local_head().unwrap().target().unwrap(), issue.id());
+        assert_eq!(issue.local_head_target().unwrap(), Some(issue.id()));
 
         issue
             .update_head(message.id(), true)
             .expect("Could not update head reference");
         assert_eq!(issue.local_head().unwrap().target().unwrap(), message.id());
+        assert_eq!(issue.local_head_target().unwrap(), Some(message.id()));
+        assert_eq!(issue.head_targets().unwrap().len(), 1);
+
+        // updates via other handles are reflected
+        repo.find_issue(issue.id())
+            .and_then(|other| other.update_head(issue.id(), true))
+            .expect("Could not update head reference");
+        assert_eq!(issue.local_head_target().unwrap(), Some(issue.id()));
+        assert!(issue.head_advances(&message, HeadPolicy::FastForward).unwrap());
+    }
+
+    #[test]
+    fn head_reflog() {
+        let mut testing_repo = TestingRepo::new("head_reflog");
+        let repo = testing_repo.repo();
+
+        // reflogs are not written for bare repositories by default
+        repo.config()
+            .and_then(|mut config| config.set_bool("core.logAllRefUpdates", true))
+            .expect("Could not configure repository");
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = repo
+            .commit(None, &sig, &sig, "Test message 2", &empty_tree, &[&initial_message])
+            .expect("Could not create message");
+
+        issue.update_head(message, true).expect("Could not update head reference");
+        let mut transaction = RefTransaction::new(repo).expect("Could not start transaction");
+        issue.stage_head(&mut transaction, issue.id()).expect("Could not stage head");
+        issue.stage_leaf(&mut transaction, message).expect("Could not stage leaf");
+        transaction.commit().expect("Could not commit transaction");
+
+        let reflog = issue.head_reflog().expect("Could not read reflog");
+        let updates: Vec<_> = reflog
+            .iter()
+            .take(2)
+            .map(|entry| (entry.id_old(), entry.id_new()))
+            .collect();
+        assert_eq!(updates, vec![(message, issue.id()), (issue.id(), message)]);
+        assert!(reflog.iter().all(|entry| entry.message().map(|m| m.starts_with("git-dit:")).unwrap_or(false)));
+
+        let leaf = format!("refs/dit/{}/leaves/{}", issue.id(), message);
+        assert_eq!(repo.reflog(&leaf).expect("Could not read reflog").len(), 1);
+    }
+
+    #[test]
+    fn heads() {
+        let mut testing_repo = TestingRepo::new("heads");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let targets = |issue: &Issue| -> Vec<Oid> {
+            issue
+                .heads()
+                .expect("Could not retrieve heads")
+                .map(|head| head.expect("Could not retrieve head").target().unwrap())
+                .collect()
+        };
+        assert_eq!(targets(&issue), vec![issue.id()]);
+
+        issue
+            .update_head(message.id(), true)
+            .expect("Could not update head reference");
+        assert_eq!(targets(&issue), vec![message.id()]);
+
+        issue
+            .local_head()
+            .expect("Could not retrieve local head")
+            .delete()
+            .expect("Could not delete local head");
+        assert!(targets(&issue).is_empty());
+    }
+
+    #[test]
+    fn last_created_message() {
+        let mut testing_repo = TestingRepo::new("last_created_message");
+        let repo = testing_repo.repo();
+
+        // reflogs are not written for bare repositories by default
+        repo.config()
+            .and_then(|mut config| config.set_bool("core.logAllRefUpdates", true))
+            .expect("Could not configure repository");
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        assert!(issue.last_created_message().expect("Could not get last message").is_none());
+
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        // references not created by git-dit are not considered
+        let refname = format!("refs/dit/{}/leaves/{}", issue.id(), initial_message.id());
+        repo.reference_ensure_log(&refname)
+            .expect("Could not enable reflog");
+        repo.reference(&refname, initial_message.id(), false, "fetch: storing head")
+            .expect("Could not create reference");
+        repo.find_reference(&refname)
+            .and_then(|mut reference| reference.delete())
+            .expect("Could not delete reference");
+
+        // neither are restored leaves
+        issue.restore_leaf(initial_message.id()).expect("Could not restore leaf");
+
+        let last = issue
+            .last_created_message()
+            .expect("Could not get last message")
+            .expect("No message created");
+        assert_eq!(last.id(), message.id());
+    }
+
+    #[test]
+    fn participants() {
+        use std::fs::File;
+        use std::io::Write;
+
+        let mut testing_repo = TestingRepo::new("participants");
+        let repo = testing_repo.repo();
+
+        let mailmap = repo.path().join("mailmap");
+        File::create(&mailmap)
+            .and_then(|mut file| file.write_all(b"Foo Bar <foo@example.com> <foo.bar@example.com>\n"))
+            .expect("Could not write mailmap");
+        repo.config()
+            .and_then(|mut config| config.set_str("mailmap.file", &mailmap.to_string_lossy()))
+            .expect("Could not configure repository");
+
+        let time = |seconds| git2::Time::new(seconds, 0);
+        let old = git2::Signature::new("Foo Bar", "foo.bar@example.com", &time(1_500_000_000))
+            .expect("Could not create signature");
+        let other = git2::Signature::new("Baz Qux", "baz@example.com", &time(1_500_000_100))
+            .expect("Could not create signature");
+        let new = git2::Signature::new("Foo Bar", "foo@example.com", &time(1_500_000_200))
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&old, &old, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let reply = issue
+            .add_message(&other, &other, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        issue
+            .add_message(&new, &new, "Test message 3", &empty_tree, vec![&reply])
+            .expect("Could not add message");
+
+        let participants: Vec<_> = issue
+            .participants()
+            .expect("Could not get participants")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(participants, vec!["Foo Bar <foo@example.com>", "Baz Qux <baz@example.com>"]);
+        assert_eq!(repo.author_of(&initial_message).expect("Could not resolve author").email(),
+                   Some("foo@example.com"));
+    }
+
+    #[test]
+    fn advance_head() {
+        let mut testing_repo = TestingRepo::new("advance_head");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        assert!(issue
+            .advance_head(&message1, HeadPolicy::Never)
+            .expect("Could not advance head")
+            .is_none());
+        assert_eq!(issue.local_head().unwrap().target().unwrap(), issue.id());
+
+        issue
+            .advance_head(&message1, HeadPolicy::FastForward)
+            .expect("Could not advance head")
+            .expect("Head was not advanced");
+        assert_eq!(issue.local_head().unwrap().target().unwrap(), message1.id());
+
+        assert!(issue
+            .advance_head(&message2, HeadPolicy::FastForward)
+            .expect("Could not advance head")
+            .is_none());
+        assert_eq!(issue.local_head().unwrap().target().unwrap(), message1.id());
+
+        issue
+            .advance_head(&message2, HeadPolicy::Always)
+            .expect("Could not advance head")
+            .expect("Head was not advanced");
+        assert_eq!(issue.local_head().unwrap().target().unwrap(), message2.id());
+    }
+
+    #[test]
+    fn issue_handle() {
+        let mut testing_repo = TestingRepo::new("issue_handle");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue_in_component(Some("backend"), &sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let handle = issue.handle();
+
+        let (id, head) = ::std::thread::spawn(move || {
+            let repo = handle.open().expect("Could not open repository");
+            let issue = handle.bind(&repo).expect("Could not bind handle");
+            assert_eq!(issue.component(), Some("backend"));
+            (issue.id(), issue.local_head_target().expect("Could not retrieve head"))
+        }).join().expect("Thread panicked");
+        assert_eq!(id, issue.id());
+        assert_eq!(head, Some(issue.id()));
     }
 }
 
@@ -15,15 +15,75 @@
 use git2::{self, Repository};
 use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::iter::FromIterator;
+use std::rc::Rc;
+use std::sync::Arc;
 
+use graph::{CommitGraph, GraphWalk};
 use issue;
+use message::cache::{self, ParsedMessage};
 use repository::RepositoryExt;
 use trailer::{accumulation, spec};
 
 use error::*;
 use error::ErrorKind as EK;
 
+/// Iterator over dit references
+///
+/// This iterator yields the references matching a pattern in the local
+/// `refs/dit/` namespace and in the `dit/` namespaces of all configured
+/// remotes. Unlike a glob like `**/dit/**`, which requires git to consider
+/// every single reference, only those namespaces are enumerated. Hence, the
+/// number of branches and tags in a repository doesn't matter.
+///
+pub struct DitRefs<'r> {
+    globs: Vec<git2::References<'r>>,
+}
+
+impl<'r> DitRefs<'r> {
+    /// Create a new iterator over the dit references matching a pattern
+    ///
+    /// The pattern is matched against the part of a reference's name after
+    /// the `dit/` namespace, e.g. `**/head` for the head references of all
+    /// issues.
+    ///
+    pub fn new(repo: &'r Repository, pattern: &str) -> Result<Self> {
+        let remotes = repo.remotes().chain_err(|| EK::CannotGetReferences(pattern.to_owned()))?;
+        let globs = Some(format!("refs/dit/{}", pattern))
+            .into_iter()
+            .chain(remotes.iter().flatten().map(|remote| {
+                format!("refs/remotes/{}/dit/{}", remote, pattern)
+            }))
+            .map(|glob| repo.references_glob(&glob).chain_err(|| EK::CannotGetReferences(glob)))
+            .collect::<Result<Vec<_>>>()?;
+
+        // references are taken from the back
+        Ok(DitRefs { globs: globs.into_iter().rev().collect() })
+    }
+}
+
+impl<'r> From<git2::References<'r>> for DitRefs<'r> {
+    fn from(references: git2::References<'r>) -> Self {
+        DitRefs { globs: vec![references] }
+    }
+}
+
+impl<'r> Iterator for DitRefs<'r> {
+    type Item = ::std::result::Result<git2::Reference<'r>, git2::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(references) = self.globs.last_mut() {
+            if let Some(item) = references.next() {
+                return Some(item);
+            }
+            self.globs.pop();
+        }
+        None
+    }
+}
+
+
 /// Iterator for transforming the names of head references to issues
 ///
 /// This iterator wrapps a `ReferenceNames` iterator and returns issues
@@ -31,14 +91,16 @@ use error::ErrorKind as EK;
 ///
 pub struct HeadRefsToIssuesIter<'r>
 {
-    inner: git2::References<'r>,
+    inner: DitRefs<'r>,
     repo: &'r Repository
 }
 
 impl<'r> HeadRefsToIssuesIter<'r>
 {
-    pub fn new(repo: &'r Repository, inner: git2::References<'r>) -> Self {
-        HeadRefsToIssuesIter { inner: inner, repo: repo }
+    pub fn new<I>(repo: &'r Repository, inner: I) -> Self
+        where I: Into<DitRefs<'r>>
+    {
+        HeadRefsToIssuesIter { inner: inner.into(), repo: repo }
     }
 }
 
@@ -51,13 +113,47 @@ impl<'r> Iterator for HeadRefsToIssuesIter<'r>
             .next()
             .map(|reference| {
                 reference
-                    .chain_err(|| EK::CannotGetReference)
+                    .map_err(Error::from)
                     .and_then(|r| self.repo.issue_by_head_ref(&r))
             })
     }
 }
 
 
+/// Iterator over the head references of an issue
+///
+/// This iterator looks up references by name. References which do not exist
+/// (any more) are skipped.
+///
+pub struct HeadRefs<'r> {
+    names: ::std::vec::IntoIter<String>,
+    repo: &'r Repository,
+}
+
+impl<'r> HeadRefs<'r> {
+    /// Create a new iterator over the references with the given names
+    ///
+    pub fn new(repo: &'r Repository, names: Vec<String>) -> Self {
+        HeadRefs { names: names.into_iter(), repo: repo }
+    }
+}
+
+impl<'r> Iterator for HeadRefs<'r> {
+    type Item = Result<git2::Reference<'r>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for name in self.names.by_ref() {
+            match self.repo.find_reference(&name) {
+                Ok(reference) => return Some(Ok(reference)),
+                Err(ref err) if err.code() == git2::ErrorCode::NotFound => continue,
+                Err(err) => return Some(Err(Error::with_chain(err, EK::CannotGetReference(name)))),
+            }
+        }
+        None
+    }
+}
+
+
 /// Messages iter
 ///
 /// Use this iterator if you intend to iterate over messages rather than `Oid`s
@@ -101,6 +197,12 @@ impl<'r> Messages<'r> {
         }
         Ok(())
     }
+
+    /// Create a `ParsedMessages` iterator from this instance
+    ///
+    pub fn parsed(self) -> ParsedMessages<'r> {
+        ParsedMessages(self)
+    }
 }
 
 impl<'r> Iterator for Messages<'r> {
@@ -110,13 +212,57 @@ impl<'r> Iterator for Messages<'r> {
         self.revwalk
             .next()
             .map(|item| item
-                .and_then(|id| self.repo.find_commit(id))
-                .chain_err(|| EK::CannotGetCommit)
+                .chain_err(|| EK::CannotIterateMessages)
+                .and_then(|id| self.repo.find_commit(id).chain_err(|| EK::CannotGetCommit(id)))
             )
     }
 }
 
 
+/// Iterator over parsed messages
+///
+/// This iterator yields the same messages as the `Messages` iterator it was
+/// created from, but retrieves them from the shared message cache. Messages
+/// which are not cached are looked up and added to the cache.
+///
+pub struct ParsedMessages<'r>(Messages<'r>);
+
+impl<'r> ParsedMessages<'r> {
+    /// Accumulate trailers according to the specification provided
+    ///
+    /// Like `MessagesExt::accumulate_trailers()`, but using the trailers of the
+    /// cached messages.
+    ///
+    pub fn accumulate_trailers<'a, I, J>(self, specs: I) -> Result<HashMap<String, accumulation::ValueAccumulator>>
+        where I: IntoIterator<Item = J>,
+              J: Borrow<spec::TrailerSpec<'a>>
+    {
+        use trailer::accumulation::Accumulator;
+        use trailer::spec::ToMap;
+
+        let mut accumulator = specs.into_map();
+        for message in self {
+            accumulator.process_all(message?.trailers().iter().cloned());
+        }
+        Ok(accumulator)
+    }
+}
+
+impl<'r> Iterator for ParsedMessages<'r> {
+    type Item = Result<Arc<ParsedMessage>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let repo = self.0.repo;
+        self.0
+            .revwalk
+            .next()
+            .map(|item| item
+                .chain_err(|| EK::CannotIterateMessages)
+                .and_then(|id| cache::parsed(repo, id)))
+    }
+}
+
+
 /// Messages iterator extension trait
 ///
 /// This trait provides some convenience functionality for iterators over
@@ -167,10 +313,11 @@ pub struct IssueMessagesIter<'r>(Messages<'r>);
 impl<'r> IssueMessagesIter<'r> {
     /// Fuse the iterator is the id refers to an issue
     ///
-    fn fuse_if_initial(&mut self, id: git2::Oid) {
+    fn fuse_if_initial(&mut self, id: git2::Oid) -> Result<()> {
         if self.0.repo.find_issue(id).is_ok() {
-            self.0.revwalk.reset();
+            self.0.revwalk.reset()?;
         }
+        Ok(())
     }
 }
 
@@ -186,12 +333,10 @@ impl<'r> Iterator for IssueMessagesIter<'r> {
     fn next(&mut self) -> Option<Self::Item> {
         self.0
             .next()
-            .map(|item| {
-                if let Ok(ref commit) = item {
-                    self.fuse_if_initial(commit.id());
-                }
-                item
-            })
+            .map(|item| item.and_then(|commit| {
+                self.fuse_if_initial(commit.id())?;
+                Ok(commit)
+            }))
     }
 }
 
@@ -206,9 +351,13 @@ impl<'r> Iterator for IssueMessagesIter<'r> {
 /// through the `watch_ref()` function. Each reference will only be returned
 /// once.
 ///
+/// If constructed via `with_graph()`, the iterator walks the commits using a
+/// commit-graph instead. The walk then terminates as soon as none of the
+/// remaining commits may reach any of the watched references.
+///
 pub struct RefsReferringTo<'r> {
-    refs: HashMap<git2::Oid, Vec<git2::Reference<'r>>>,
-    inner: git2::Revwalk<'r>,
+    refs: HashMap<git2::Oid, WatchedRefs<'r>>,
+    inner: Walk<'r>,
     current_refs: Vec<git2::Reference<'r>>,
 }
 
@@ -217,7 +366,17 @@ impl<'r> RefsReferringTo<'r> {
     ///
     pub fn new(messages: git2::Revwalk<'r>) -> Self
     {
-        Self { refs: HashMap::new(), inner: messages, current_refs: Vec::new() }
+        Self { refs: HashMap::new(), inner: Walk::Revwalk(messages), current_refs: Vec::new() }
+    }
+
+    /// Create a new iterator walking messages using a commit-graph
+    ///
+    /// Messages not covered by the graph are looked up in the repository.
+    ///
+    pub fn with_graph(repo: &'r Repository, graph: Rc<CommitGraph>) -> Self
+    {
+        let walk = GraphWalk::new(repo, graph);
+        Self { refs: HashMap::new(), inner: Walk::Graph(walk, None), current_refs: Vec::new() }
     }
 
     /// Push a starting point for the iteration
@@ -226,7 +385,15 @@ impl<'r> RefsReferringTo<'r> {
     /// iterating over messages.
     ///
     pub fn push(&mut self, message: git2::Oid) -> Result<()> {
-        self.inner.push(message).chain_err(|| EK::CannotConstructRevwalk)
+        match self.inner {
+            Walk::Revwalk(ref mut revwalk) => revwalk
+                .push(message)
+                .chain_err(|| EK::CannotGetCommit(message)),
+            Walk::Graph(ref mut walk, _) => {
+                walk.push(message);
+                Ok(())
+            },
+        }
     }
 
     /// Start watching a reference
@@ -234,11 +401,21 @@ impl<'r> RefsReferringTo<'r> {
     /// A watched reference may be returned by the iterator.
     ///
     pub fn watch_ref(&mut self, reference: git2::Reference<'r>) -> Result<()> {
-        let id = reference
-            .peel(git2::ObjectType::Any)
-            .chain_err(|| EK::CannotGetCommitForRev(reference.name().unwrap_or_default().to_string()))?
-            .id();
-        self.refs.entry(id).or_insert_with(Vec::new).push(reference);
+        // Only symbolic references require a lookup.
+        let id = match reference.target() {
+            Some(id) => id,
+            None => reference
+                .peel(git2::ObjectType::Any)
+                .chain_err(|| EK::CannotGetReference(String::from_utf8_lossy(reference.name_bytes()).into_owned()))?
+                .id(),
+        };
+        match self.refs.entry(id) {
+            Entry::Occupied(mut entry) => entry.get_mut().more.push(reference),
+            Entry::Vacant(entry) => {
+                entry.insert(WatchedRefs { first: reference, more: Vec::new() });
+                self.inner.watch(id);
+            },
+        }
         Ok(())
     }
 
@@ -258,33 +435,93 @@ impl<'r> Iterator for RefsReferringTo<'r> {
     type Item = Result<git2::Reference<'r>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        'outer: loop {
-            if let Some(reference) = self.current_refs.pop() {
-                // get one of the references for the current commit
-                return Some(Ok(reference));
-            }
+        if let Some(reference) = self.current_refs.pop() {
+            // get one of the references for the current commit
+            return Some(Ok(reference));
+        }
 
-            // Refills may be rather expensive. Let's check whether we have any
-            // refs left, first.
-            if self.refs.is_empty() {
-                return None;
-            }
+        // Refills may be rather expensive. Let's check whether we have any
+        // refs left, first.
+        if self.refs.is_empty() {
+            return None;
+        }
 
-            // refill the stash of references for the next commit
-            'refill: for item in &mut self.inner {
-                match item.chain_err(|| EK::CannotGetCommit) {
-                    Ok(id) => if let Some(new_refs) = self.refs.remove(&id) {
-                        // NOTE: should new_refs be empty, we just loop once
-                        //       more through the 'outer loop
-                        self.current_refs = new_refs;
-                        continue 'outer;
-                    },
-                    Err(err) => return Some(Err(err)),
-                }
+        // refill the stash of references for the next commit
+        while let Some(item) = self.inner.next_referring(&self.refs) {
+            match item {
+                Ok(id) => if let Some(watched) = self.refs.remove(&id) {
+                    self.inner.unwatch(id);
+                    // the buffer is reused, so the refill doesn't allocate
+                    self.current_refs.extend(watched.more);
+                    return Some(Ok(watched.first));
+                },
+                Err(err) => return Some(Err(err)),
             }
+        }
 
-            // We depleted the inner iterator.
-            return None;
+        // We depleted the inner iterator.
+        None
+    }
+}
+
+
+/// References watched by a `RefsReferringTo` which refer to the same commit
+///
+/// Most commits are referred to by a single reference, which is held without
+/// allocating.
+///
+struct WatchedRefs<'r> {
+    first: git2::Reference<'r>,
+    more: Vec<git2::Reference<'r>>,
+}
+
+
+/// Walk underlying a `RefsReferringTo` iterator
+///
+/// For walks using a commit-graph, the lowest generation number of the watched
+/// commits is cached.
+///
+enum Walk<'r> {
+    Revwalk(git2::Revwalk<'r>),
+    Graph(GraphWalk<'r>, Option<u32>),
+}
+
+impl<'r> Walk<'r> {
+    /// Get the next commit which may refer to any of the references supplied
+    ///
+    fn next_referring<R>(&mut self, refs: &HashMap<git2::Oid, R>) -> Option<Result<git2::Oid>> {
+        match *self {
+            Walk::Revwalk(ref mut revwalk) => revwalk
+                .next()
+                .map(|item| item.chain_err(|| EK::CannotIterateMessages)),
+            Walk::Graph(ref mut walk, ref mut cutoff) => {
+                // commits with lower generation numbers than any of the watched
+                // ones can't reach any of them
+                let generation = match *cutoff {
+                    Some(generation) => generation,
+                    None => refs.keys().map(|id| walk.generation(*id)).min().unwrap_or(0),
+                };
+                *cutoff = Some(generation);
+                walk.next_above(generation)
+            },
+        }
+    }
+
+    /// Note that a commit is watched
+    ///
+    fn watch(&mut self, id: git2::Oid) {
+        if let Walk::Graph(ref walk, ref mut cutoff) = *self {
+            *cutoff = cutoff.map(|generation| generation.min(walk.generation(id)));
+        }
+    }
+
+    /// Note that a commit is no longer watched
+    ///
+    fn unwatch(&mut self, id: git2::Oid) {
+        if let Walk::Graph(ref walk, ref mut cutoff) = *self {
+            if *cutoff == Some(walk.generation(id)) {
+                *cutoff = None;
+            }
         }
     }
 }
@@ -352,7 +589,7 @@ impl<'r, I> Iterator for ReferenceDeletingIter<'r, I>
             .by_ref()
             .filter_map(|mut r| r
                 .delete()
-                .chain_err(|| EK::CannotDeleteReference(r.name().unwrap_or_default().to_string()))
+                .chain_err(|| EK::CannotDeleteReference(String::from_utf8_lossy(r.name_bytes()).into_owned()))
                 .err()
             )
             .next()
@@ -369,6 +606,40 @@ mod tests {
 
     use repository::RepositoryExt;
 
+    // DitRefs tests
+
+    #[test]
+    fn dit_refs() {
+        let mut testing_repo = TestingRepo::new("dit_refs");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        repo.remote("origin", "https://example.com/repo.git")
+            .expect("Could not create remote");
+        for name in &["refs/remotes/origin/dit", "refs/remotes/unknown/dit", "refs/heads/dit"] {
+            repo.reference(&format!("{}/{}/head", name, issue.id()), issue.id(), false, "test")
+                .expect("Could not create reference");
+        }
+
+        let mut names: Vec<_> = DitRefs::new(repo, "**/head")
+            .expect("Could not enumerate references")
+            .map(|item| item.expect("Could not retrieve reference").name().unwrap().to_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![
+            format!("refs/dit/{}/head", issue.id()),
+            format!("refs/remotes/origin/dit/{}/head", issue.id()),
+        ]);
+    }
+
     // RefsReferringTo tests
 
     #[test]
@@ -34,19 +34,48 @@
 //! contain arbitrary metadata in the form of git trailers.
 //!
 
-#[macro_use] extern crate error_chain;
+#[macro_use] extern crate thiserror;
 #[macro_use] extern crate lazy_static;
 extern crate git2;
+extern crate hmac;
 extern crate regex;
+extern crate sha2;
+#[cfg(feature = "search-index")] extern crate tantivy;
+#[cfg(feature = "trace")] extern crate tracing;
 
+#[macro_use] mod trace;
+
+pub mod anonymous;
+pub mod attachment;
+#[cfg(any(test, feature = "async"))]
+pub mod asynchronous;
+pub mod backend;
+pub mod blame;
+pub mod config;
+pub mod crossref;
 pub mod error;
+pub mod event;
+pub mod fsck;
 pub mod gc;
+#[cfg(any(test, feature = "generator"))]
+pub mod generator;
+pub mod graph;
 pub mod issue;
 pub mod iter;
+pub mod listing;
 pub mod message;
+pub mod message_index;
+pub mod moderation;
+pub mod observer;
+pub mod quarantine;
 pub mod remote;
 pub mod repository;
+pub mod search;
+#[cfg(feature = "search-index")]
+pub mod search_index;
+pub mod template;
 pub mod trailer;
+pub mod transaction;
 
 mod utils;
 
@@ -0,0 +1,937 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Sorting and grouping of issue listings
+//!
+//! This module provides the `SortKey`s by which listings of issues may be
+//! sorted, e.g. by the time of the last activity or the number of messages,
+//! and the `GroupKey`s by which they may be grouped. Keys derived from an
+//! issue's metadata, like its priority, are accumulated from the head selected
+//! by the caller, since the selection of heads is a matter of policy.
+//!
+//! Issues for which a key has no value, e.g. issues without a due date, are
+//! placed after all other issues regardless of the `Order`. Likewise, issues
+//! without a value for a `GroupKey` form the last group.
+//!
+//! Determining the `last_activity()` of an issue requires walking all of its
+//! messages. Hence, callers may cache it and sort by the cached values via
+//! `sort_issues_by()`.
+//!
+//! If only the number of issues is of interest, `count_issues()` computes the
+//! `Counts` of the groups without retaining the issues.
+//!
+//! Finally, `involvements()` determines how a person is involved in an issue,
+//! which allows presenting the issues of a user in separate buckets, and
+//! `Staleness` identifies issues without recent activity for triage.
+//!
+
+use git2::{self, Oid};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::result::Result as RResult;
+use std::str::FromStr;
+use std::time::Duration;
+
+use config::DitConfig;
+use issue::Issue;
+use trailer::spec::{self, TrailerSpec, DIT_KEY_PREFIX};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Key for sorting issues
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// Time of the latest message
+    LastActivity,
+    /// Time of the initial message
+    Created,
+    /// Priority, given as a number via `Dit-priority`, optionally prefixed
+    /// with "P"; lower numbers denote higher priorities
+    Priority,
+    /// Due date, given as "YYYY-MM-DD" via `Dit-due`
+    Due,
+    /// Number of messages
+    Messages,
+}
+
+impl SortKey {
+    /// All keys
+    ///
+    pub fn all() -> &'static [SortKey] {
+        &[SortKey::LastActivity, SortKey::Created, SortKey::Priority, SortKey::Due, SortKey::Messages]
+    }
+
+    /// Get the name of the key
+    ///
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SortKey::LastActivity   => "activity",
+            SortKey::Created        => "created",
+            SortKey::Priority       => "priority",
+            SortKey::Due            => "due",
+            SortKey::Messages       => "messages",
+        }
+    }
+
+    /// Get the order in which the key is sorted by default
+    ///
+    /// Issues are listed with the most recent activity, the highest priority,
+    /// the nearest due date or the most messages first.
+    ///
+    pub fn default_order(&self) -> Order {
+        match *self {
+            SortKey::Priority | SortKey::Due => Order::Ascending,
+            _ => Order::Descending,
+        }
+    }
+
+    /// Get the value of the key for an issue
+    ///
+    /// Metadata is accumulated from the head given. Returns `None` if the
+    /// issue has no value for the key or the value is malformed.
+    ///
+    pub fn value(&self, issue: &Issue, head: Oid) -> Result<Option<i64>> {
+        match *self {
+            SortKey::LastActivity => last_activity(issue),
+            SortKey::Created => Ok(Some(issue.initial_message()?.time().seconds())),
+            SortKey::Priority => Ok(latest_value(issue, head, &spec::ISSUE_PRIORITY_SPEC)?
+                .and_then(|value| parse_priority(&value))),
+            SortKey::Due => Ok(latest_value(issue, head, &spec::ISSUE_DUE_SPEC)?
+                .and_then(|value| parse_due_date(&value))),
+            SortKey::Messages => {
+                let mut retval = 0;
+                for message in issue.messages()? {
+                    message?;
+                    retval += 1;
+                }
+                Ok(Some(retval))
+            },
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        SortKey::all()
+            .iter()
+            .find(|key| key.name() == s)
+            .cloned()
+            .ok_or_else(|| Error::from_kind(EK::UnknownSortKey(s.to_owned())))
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        f.write_str(self.name())
+    }
+}
+
+
+/// Order in which issues are sorted
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+impl FromStr for Order {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "asc" | "ascending"     => Ok(Order::Ascending),
+            "desc" | "descending"   => Ok(Order::Descending),
+            _ => Err(Error::from_kind(EK::UnknownSortOrder(s.to_owned()))),
+        }
+    }
+}
+
+
+/// Sort issues
+///
+/// The values of the key are computed once per issue, using the head returned
+/// by the function supplied. Issues with equal values are ordered by their id.
+///
+pub fn sort_issues<'r, F>(issues: Vec<Issue<'r>>, key: SortKey, order: Order, mut head: F) -> Result<Vec<Issue<'r>>>
+    where F: FnMut(&Issue<'r>) -> Result<Oid>
+{
+    sort_issues_by(issues, order, |issue| key.value(issue, head(issue)?))
+}
+
+
+/// Sort issues by values supplied
+///
+/// Like `sort_issues()`, but the value of each issue is returned by the
+/// function supplied, e.g. from a cache. Issues without a value are placed
+/// last.
+///
+pub fn sort_issues_by<'r, F>(issues: Vec<Issue<'r>>, order: Order, mut value: F) -> Result<Vec<Issue<'r>>>
+    where F: FnMut(&Issue<'r>) -> Result<Option<i64>>
+{
+    let mut keyed = Vec::with_capacity(issues.len());
+    for issue in issues {
+        let value = value(&issue)?;
+        keyed.push((value, issue));
+    }
+
+    keyed.sort_by(|a, b| compare(a.0, b.0, order).then_with(|| a.1.id().cmp(&b.1.id())));
+    Ok(keyed.into_iter().map(|(_, issue)| issue).collect())
+}
+
+
+/// Get the time of the latest message of an issue
+///
+/// The time is returned in seconds since the epoch. All messages are
+/// considered, which requires walking the whole issue. Callers sorting or
+/// filtering many issues may want to cache the result.
+///
+pub fn last_activity(issue: &Issue) -> Result<Option<i64>> {
+    let mut retval = None;
+    for message in issue.messages()? {
+        let time = message?.time().seconds();
+        retval = Some(retval.map_or(time, |last: i64| last.max(time)));
+    }
+    Ok(retval)
+}
+
+
+/// Key for grouping issues
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupKey {
+    Tag,
+    Milestone,
+    Assignee,
+    Status,
+    /// Component namespace of the issue
+    Component,
+}
+
+impl GroupKey {
+    /// All keys
+    ///
+    pub fn all() -> &'static [GroupKey] {
+        &[GroupKey::Tag, GroupKey::Milestone, GroupKey::Assignee, GroupKey::Status, GroupKey::Component]
+    }
+
+    /// Get the name of the key
+    ///
+    pub fn name(&self) -> &'static str {
+        match *self {
+            GroupKey::Tag       => "tag",
+            GroupKey::Milestone => "milestone",
+            GroupKey::Assignee  => "assignee",
+            GroupKey::Status    => "status",
+            GroupKey::Component => "component",
+        }
+    }
+
+    /// Get the specification of the metadata grouped by
+    ///
+    /// Returns `None` for keys which do not refer to metadata.
+    ///
+    pub fn spec(&self) -> Option<TrailerSpec<'static>> {
+        match *self {
+            GroupKey::Tag       => Some(spec::ISSUE_TAG_SPEC),
+            GroupKey::Milestone => Some(spec::ISSUE_MILESTONE_SPEC),
+            GroupKey::Assignee  => Some(spec::ISSUE_ASSIGNEE_SPEC),
+            GroupKey::Status    => Some(spec::ISSUE_STATUS_SPEC),
+            GroupKey::Component => None,
+        }
+    }
+
+    /// Get the values of the key for an issue
+    ///
+    /// Metadata is accumulated from the head given. Tags and assignees may
+    /// yield multiple values.
+    ///
+    pub fn values(&self, issue: &Issue, head: Oid) -> Result<Vec<String>> {
+        let spec = match self.spec() {
+            Some(spec) => spec,
+            None => return Ok(issue.component().map(String::from).into_iter().collect()),
+        };
+        Ok(issue
+            .messages_from(head)?
+            .parsed()
+            .accumulate_trailers(Some(&spec))?
+            .remove(spec.key)
+            .into_iter()
+            .flatten()
+            .map(|value| value.to_string())
+            .collect())
+    }
+}
+
+impl FromStr for GroupKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        GroupKey::all()
+            .iter()
+            .find(|key| key.name() == s)
+            .cloned()
+            .ok_or_else(|| Error::from_kind(EK::UnknownGroupKey(s.to_owned())))
+    }
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        f.write_str(self.name())
+    }
+}
+
+
+/// Group of issues sharing a value
+///
+pub struct Group<'a, 'r: 'a> {
+    /// Value shared by the issues, or `None` for issues without a value
+    pub value: Option<String>,
+    /// Issues in the group, in the order in which they were supplied
+    pub issues: Vec<&'a Issue<'r>>,
+}
+
+impl<'a, 'r> Group<'a, 'r> {
+    /// Get the number of issues in the group
+    ///
+    pub fn len(&self) -> usize {
+        self.issues.len()
+    }
+
+    /// Check whether the group is empty
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+
+/// Group issues
+///
+/// The groups are ordered by their value, followed by the group of issues
+/// without a value, if any. Issues with multiple values, e.g. multiple tags,
+/// are part of multiple groups. The values are computed using the head
+/// returned by the function supplied.
+///
+pub fn group_issues<'a, 'r, F>(issues: &'a [Issue<'r>], key: GroupKey, mut head: F) -> Result<Vec<Group<'a, 'r>>>
+    where F: FnMut(&Issue<'r>) -> Result<Oid>
+{
+    let mut groups: BTreeMap<String, Vec<&'a Issue<'r>>> = BTreeMap::new();
+    let mut rest = Vec::new();
+    for issue in issues {
+        let values = key.values(issue, head(issue)?)?;
+        if values.is_empty() {
+            rest.push(issue);
+        }
+        for value in values {
+            groups.entry(value).or_default().push(issue);
+        }
+    }
+
+    let mut retval: Vec<_> = groups
+        .into_iter()
+        .map(|(value, issues)| Group { value: Some(value), issues: issues })
+        .collect();
+    if !rest.is_empty() {
+        retval.push(Group { value: None, issues: rest });
+    }
+    Ok(retval)
+}
+
+
+/// Numbers of issues
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// Number of issues counted
+    pub total: usize,
+    /// Numbers of issues per value, ordered like the groups of `group_issues()`
+    pub groups: Vec<(Option<String>, usize)>,
+}
+
+
+/// Count issues, optionally per group
+///
+/// The issues are consumed one by one. Without a key, neither the issues'
+/// messages nor their heads are looked up.
+///
+pub fn count_issues<'r, I, F>(issues: I, key: Option<GroupKey>, mut head: F) -> Result<Counts>
+    where I: IntoIterator<Item = Issue<'r>>,
+          F: FnMut(&Issue<'r>) -> Result<Oid>
+{
+    let key = match key {
+        Some(key) => key,
+        None => return Ok(Counts { total: issues.into_iter().count(), groups: Vec::new() }),
+    };
+
+    let mut total = 0;
+    let mut groups: BTreeMap<String, usize> = BTreeMap::new();
+    let mut rest = 0;
+    for issue in issues {
+        let values = match key {
+            GroupKey::Component => key.values(&issue, issue.id())?,
+            _ => key.values(&issue, head(&issue)?)?,
+        };
+        if values.is_empty() {
+            rest += 1;
+        }
+        for value in values {
+            *groups.entry(value).or_default() += 1;
+        }
+        total += 1;
+    }
+
+    let mut groups: Vec<_> = groups.into_iter().map(|(value, count)| (Some(value), count)).collect();
+    if rest > 0 {
+        groups.push((None, rest));
+    }
+    Ok(Counts { total: total, groups: groups })
+}
+
+
+/// Way in which a person is involved in an issue
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Involvement {
+    /// The person authored the initial message
+    Created,
+    /// The person is among the assignees
+    Assigned,
+    /// The person authored any of the messages
+    Participating,
+    /// The person is mentioned in the text of a message
+    Mentioned,
+}
+
+impl Involvement {
+    /// All kinds of involvement
+    ///
+    pub fn all() -> &'static [Involvement] {
+        &[Involvement::Created, Involvement::Assigned, Involvement::Participating, Involvement::Mentioned]
+    }
+
+    /// Get the name of the involvement
+    ///
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Involvement::Created        => "created",
+            Involvement::Assigned       => "assigned",
+            Involvement::Participating  => "participating",
+            Involvement::Mentioned      => "mentioned",
+        }
+    }
+}
+
+impl FromStr for Involvement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Involvement::all()
+            .iter()
+            .find(|involvement| involvement.name() == s)
+            .cloned()
+            .ok_or_else(|| Error::from_kind(EK::UnknownInvolvement(s.to_owned())))
+    }
+}
+
+impl fmt::Display for Involvement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        f.write_str(self.name())
+    }
+}
+
+
+/// Determine how a person is involved in an issue
+///
+/// The person is identified by the email address of the signature given,
+/// ignoring case. Authors of messages are resolved via the mailmap. An
+/// assignee matches if its value contains the email address or equals the
+/// person's name. A message mentions the person if a line of its text which is
+/// not a git-dit trailer contains the email address or its local part prefixed
+/// with "@", e.g. "@foo.bar". Assignees are accumulated from the head given.
+///
+pub fn involvements(issue: &Issue, head: Oid, person: &git2::Signature) -> Result<Vec<Involvement>> {
+    let name = person.name().unwrap_or("");
+    let email = person.email().unwrap_or("").to_lowercase();
+    let mut retval = Vec::new();
+    if email.is_empty() {
+        return Ok(retval);
+    }
+
+    if issue.author()?.email().map(str::to_lowercase) == Some(email.clone()) {
+        retval.push(Involvement::Created);
+    }
+
+    let assigned = GroupKey::Assignee
+        .values(issue, head)?
+        .iter()
+        .any(|assignee| assignee.to_lowercase().contains(&email) || assignee == name);
+    if assigned {
+        retval.push(Involvement::Assigned);
+    }
+
+    let participating = issue
+        .participants()?
+        .iter()
+        .any(|author| author.email().map(str::to_lowercase) == Some(email.clone()));
+    if participating {
+        retval.push(Involvement::Participating);
+    }
+
+    let handle = format!("@{}", email.split('@').next().unwrap_or(""));
+    for message in issue.messages()?.parsed() {
+        let mentioned = message?
+            .message_lines()
+            .iter()
+            .filter(|line| !line.starts_with(DIT_KEY_PREFIX))
+            .map(|line| line.to_lowercase())
+            .any(|line| line.contains(&email) || mentions(&line, &handle));
+        if mentioned {
+            retval.push(Involvement::Mentioned);
+            break;
+        }
+    }
+
+    Ok(retval)
+}
+
+
+/// Check whether a line contains a handle as a whole word
+///
+fn mentions(line: &str, handle: &str) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    line.match_indices(handle).any(|(pos, _)| {
+        let before = line[..pos].chars().next_back();
+        let after = line[pos + handle.len()..].chars().next();
+        !before.map(&is_word_char).unwrap_or(false) && !after.map(&is_word_char).unwrap_or(false)
+    })
+}
+
+
+/// Default period of inactivity after which issues are considered stale
+///
+pub const DEFAULT_STALE_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+
+/// Issue without recent activity
+///
+pub struct StaleIssue<'r> {
+    pub issue: Issue<'r>,
+    /// Time of the latest message, in seconds since the epoch
+    pub last_activity: i64,
+}
+
+
+/// Criteria for stale issues
+///
+/// An issue is stale if its latest message is older than the configured age
+/// and it carries none of the excluded tags, e.g. a tag marking issues which
+/// are kept open deliberately.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Staleness {
+    age: Duration,
+    excluded_tags: Vec<String>,
+}
+
+impl Staleness {
+    /// Create criteria for issues without activity for the given period
+    ///
+    pub fn new(age: Duration) -> Self {
+        Staleness { age: age, excluded_tags: Vec::new() }
+    }
+
+    /// Create criteria configured via the `dit.stale.*` options
+    ///
+    /// The age is read from `dit.stale.age` and defaults to
+    /// `DEFAULT_STALE_AGE`. The excluded tags are read from
+    /// `dit.stale.exclude-tag`.
+    ///
+    pub fn with_config(config: &DitConfig) -> Result<Self> {
+        let mut retval = Self::new(config.stale_age()?.unwrap_or(DEFAULT_STALE_AGE));
+        for tag in config.stale_excluded_tags()? {
+            retval = retval.exclude_tag(tag);
+        }
+        Ok(retval)
+    }
+
+    /// Set the period of inactivity after which issues are stale
+    ///
+    pub fn age(mut self, age: Duration) -> Self {
+        self.age = age;
+        self
+    }
+
+    /// Never consider issues with the given tag stale
+    ///
+    pub fn exclude_tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.excluded_tags.push(tag.into());
+        self
+    }
+
+    /// Select the stale issues
+    ///
+    /// Issues are stale if their last activity is older than the age relative
+    /// to `now`, given in seconds since the epoch. The time of each issue's
+    /// last activity is returned by `last_activity`, e.g. `last_activity()` or
+    /// a cache of its results, and tags are accumulated from the heads returned
+    /// by `head`. The stale issues are returned along with the time of their
+    /// last activity, least recently active first.
+    ///
+    pub fn stale_issues<'r, I, F, G>(&self,
+                                     issues: I,
+                                     now: i64,
+                                     mut head: F,
+                                     mut last_activity: G
+    ) -> Result<Vec<StaleIssue<'r>>>
+        where I: IntoIterator<Item = Issue<'r>>,
+              F: FnMut(&Issue<'r>) -> Result<Oid>,
+              G: FnMut(&Issue<'r>) -> Result<Option<i64>>
+    {
+        let cutoff = now.saturating_sub(self.age.as_secs().min(i64::MAX as u64) as i64);
+        let mut retval = Vec::new();
+        for issue in issues {
+            let last_activity = last_activity(&issue)?.unwrap_or(now);
+            if last_activity >= cutoff {
+                continue;
+            }
+            if !self.excluded_tags.is_empty() {
+                let tags = GroupKey::Tag.values(&issue, head(&issue)?)?;
+                if tags.iter().any(|tag| self.excluded_tags.contains(tag)) {
+                    continue;
+                }
+            }
+            retval.push(StaleIssue { issue: issue, last_activity: last_activity });
+        }
+
+        retval.sort_by_key(|stale| (stale.last_activity, stale.issue.id()));
+        Ok(retval)
+    }
+}
+
+
+/// Compare two values of a key
+///
+/// Missing values are greater than any other value.
+///
+fn compare(a: Option<i64>, b: Option<i64>, order: Order) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => match order {
+            Order::Ascending => a.cmp(&b),
+            Order::Descending => b.cmp(&a),
+        },
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+
+/// Get the latest value of a piece of metadata accumulated from a head
+///
+fn latest_value(issue: &Issue, head: Oid, spec: &TrailerSpec) -> Result<Option<String>> {
+    Ok(issue
+        .messages_from(head)?
+        .parsed()
+        .accumulate_trailers(Some(spec))?
+        .remove(spec.key)
+        .and_then(|values| values.into_iter().last())
+        .map(|value| value.to_string()))
+}
+
+
+/// Parse a priority
+///
+fn parse_priority(value: &str) -> Option<i64> {
+    let value = value.trim();
+    value
+        .trim_start_matches(&['P', 'p'][..])
+        .parse()
+        .ok()
+}
+
+
+/// Parse a due date of the form "YYYY-MM-DD"
+///
+/// The date is returned in seconds since the epoch, at midnight UTC.
+///
+fn parse_due_date(value: &str) -> Option<i64> {
+    let mut parts = value.trim().splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) => (year, month, day),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // days since the epoch, counting years from March so that leap days come last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some((era * 146097 + day_of_era - 719468) * 86400)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Signature, Time};
+    use repository::RepositoryExt;
+    use test_utils::TestingRepo;
+
+    // Sorting tests
+
+    #[test]
+    fn sorted_issues() {
+        let mut testing_repo = TestingRepo::new("sorted_issues");
+        let repo = testing_repo.repo();
+
+        let sig = |time| Signature::new("Foo Bar", "foo.bar@example.com", &Time::new(time, 0))
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig(1000), &sig(1000), "Test message 1\n\nDit-priority: P2", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue2 = repo
+            .create_issue(&sig(2000), &sig(2000), "Test message 2\n\nDit-due: 2024-02-29", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue3 = repo
+            .create_issue(&sig(3000), &sig(3000), "Test message 3\n\nDit-due: 2023-12-31", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue1
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let reply = issue1
+            .add_message(&sig(4000), &sig(4000), "Test message 4\n\nDit-priority: 1", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        issue1.update_head(reply.id(), true).expect("Could not update head");
+
+        let ids = |key, order| {
+            let issues = [&issue1, &issue2, &issue3]
+                .iter()
+                .map(|issue| Issue::new(repo, issue.id()).expect("Could not create handle"))
+                .collect();
+            sort_issues(issues, key, order, |issue| Ok(issue.local_head()?.target().unwrap()))
+                .expect("Could not sort issues")
+                .into_iter()
+                .map(|issue| issue.id())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(SortKey::Created, Order::Descending), vec![issue3.id(), issue2.id(), issue1.id()]);
+        assert_eq!(ids(SortKey::Created, Order::Ascending), vec![issue1.id(), issue2.id(), issue3.id()]);
+        assert_eq!(ids(SortKey::LastActivity, Order::Descending), vec![issue1.id(), issue3.id(), issue2.id()]);
+        assert_eq!(ids(SortKey::Messages, Order::Descending)[0], issue1.id());
+        assert_eq!(ids(SortKey::Due, Order::Ascending), vec![issue3.id(), issue2.id(), issue1.id()]);
+        assert_eq!(ids(SortKey::Due, Order::Descending), vec![issue2.id(), issue3.id(), issue1.id()]);
+        assert_eq!(ids(SortKey::Priority, Order::Ascending)[0], issue1.id());
+
+        assert_eq!(last_activity(&issue1).expect("Could not compute last activity"), Some(4000));
+        assert_eq!(last_activity(&issue2).expect("Could not compute last activity"), Some(2000));
+        let issues = [&issue1, &issue2, &issue3]
+            .iter()
+            .map(|issue| Issue::new(repo, issue.id()).expect("Could not create handle"))
+            .collect();
+        let sorted: Vec<_> = sort_issues_by(issues, Order::Ascending, |issue| {
+                Ok(if issue.id() == issue2.id() { None } else { last_activity(issue)? })
+            })
+            .expect("Could not sort issues")
+            .into_iter()
+            .map(|issue| issue.id())
+            .collect();
+        assert_eq!(sorted, vec![issue3.id(), issue1.id(), issue2.id()]);
+    }
+
+    #[test]
+    fn grouped_issues() {
+        let mut testing_repo = TestingRepo::new("grouped_issues");
+        let repo = testing_repo.repo();
+
+        let sig = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1\n\nDit-tag: a\nDit-tag: b", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue2 = repo
+            .create_issue(&sig, &sig, "Test message 2\n\nDit-tag: b\nDit-milestone: 1.0", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue3 = repo
+            .create_issue(&sig, &sig, "Test message 3", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let issues = vec![issue1, issue2, issue3];
+        let groups = |key| group_issues(&issues, key, |issue| Ok(issue.id()))
+            .expect("Could not group issues")
+            .into_iter()
+            .map(|group| (group.value, group.issues.into_iter().map(Issue::id).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(groups(GroupKey::Tag), vec![
+            (Some("a".to_owned()), vec![issues[0].id()]),
+            (Some("b".to_owned()), vec![issues[0].id(), issues[1].id()]),
+            (None, vec![issues[2].id()]),
+        ]);
+        assert_eq!(groups(GroupKey::Milestone), vec![
+            (Some("1.0".to_owned()), vec![issues[1].id()]),
+            (None, vec![issues[0].id(), issues[2].id()]),
+        ]);
+
+        let counts = |key| count_issues(issues.iter().map(|issue| Issue::new(repo, issue.id()).unwrap()),
+                                        key,
+                                        |issue| Ok(issue.id()))
+            .expect("Could not count issues");
+        assert_eq!(counts(None), Counts { total: 3, groups: vec![] });
+        assert_eq!(counts(Some(GroupKey::Tag)), Counts {
+            total: 3,
+            groups: vec![(Some("a".to_owned()), 1), (Some("b".to_owned()), 2), (None, 1)],
+        });
+        assert_eq!(counts(Some(GroupKey::Component)), Counts { total: 3, groups: vec![(None, 3)] });
+    }
+
+    #[test]
+    fn involved_issues() {
+        use std::fs::File;
+        use std::io::Write;
+
+        let mut testing_repo = TestingRepo::new("involved_issues");
+        let repo = testing_repo.repo();
+
+        let foo = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let baz = Signature::now("Baz", "baz@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&foo, &foo, "Test message 1\n\nDit-assignee: Baz <BAZ@example.com>", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue2 = repo
+            .create_issue(&baz, &baz, "Test message 2\n\nSome text", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue2
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let reply = issue2
+            .add_message(&foo, &foo, "Test message 3\n\nWhat do you think, @baz?", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let involved = |issue: &Issue, head, person| involvements(issue, head, person)
+            .expect("Could not determine involvement");
+        assert_eq!(involved(&issue1, issue1.id(), &foo), vec![Involvement::Created, Involvement::Participating]);
+        assert_eq!(involved(&issue1, issue1.id(), &baz), vec![Involvement::Assigned]);
+        assert_eq!(involved(&issue2, reply.id(), &foo), vec![Involvement::Participating]);
+        assert_eq!(involved(&issue2, reply.id(), &baz),
+                   vec![Involvement::Created, Involvement::Participating, Involvement::Mentioned]);
+
+        let mailmap = repo.path().join("mailmap");
+        File::create(&mailmap)
+            .and_then(|mut file| file.write_all(b"Foo Bar <foo@example.com> <foo.bar@example.com>\n"))
+            .expect("Could not write mailmap");
+        repo.config()
+            .and_then(|mut config| config.set_str("mailmap.file", &mailmap.to_string_lossy()))
+            .expect("Could not configure repository");
+        let canonical = Signature::now("Foo Bar", "foo@example.com")
+            .expect("Could not create signature");
+        assert_eq!(involved(&issue1, issue1.id(), &canonical), vec![Involvement::Created, Involvement::Participating]);
+    }
+
+    #[test]
+    fn stale_issues() {
+        let mut testing_repo = TestingRepo::new("stale_issues");
+        let repo = testing_repo.repo();
+
+        let sig = |time| Signature::new("Foo Bar", "foo.bar@example.com", &Time::new(time, 0))
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let day = 24 * 60 * 60;
+        let issue1 = repo
+            .create_issue(&sig(10 * day), &sig(10 * day), "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue2 = repo
+            .create_issue(&sig(day), &sig(day), "Test message 2", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue3 = repo
+            .create_issue(&sig(2 * day), &sig(2 * day), "Test message 3\n\nDit-tag: pinned", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue2
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        issue2
+            .add_message(&sig(3 * day), &sig(3 * day), "Test message 4", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let stale = |staleness: Staleness| {
+            let issues = [&issue1, &issue2, &issue3]
+                .iter()
+                .map(|issue| Issue::new(repo, issue.id()).expect("Could not create handle"))
+                .collect::<Vec<_>>();
+            staleness
+                .stale_issues(issues, 12 * day, |issue| Ok(issue.id()), last_activity)
+                .expect("Could not select stale issues")
+                .into_iter()
+                .map(|stale| (stale.issue.id(), stale.last_activity))
+                .collect::<Vec<_>>()
+        };
+        let week = Duration::from_secs(7 * day as u64);
+        assert_eq!(stale(Staleness::new(week)), vec![(issue3.id(), 2 * day), (issue2.id(), 3 * day)]);
+        assert_eq!(stale(Staleness::new(week).exclude_tag("pinned")), vec![(issue2.id(), 3 * day)]);
+        assert_eq!(stale(Staleness::new(week).age(Duration::from_secs(day as u64))),
+                   vec![(issue3.id(), 2 * day), (issue2.id(), 3 * day), (issue1.id(), 10 * day)]);
+    }
+
+    #[test]
+    fn handle_mentions() {
+        assert!(mentions("thanks @foo.bar!", "@foo.bar"));
+        assert!(mentions("@foo.bar: see above", "@foo.bar"));
+        assert!(!mentions("@foo.barista", "@foo.bar"));
+        assert!(!mentions("mail@foo.bar", "@foo.bar"));
+    }
+
+    #[test]
+    fn key_parsing() {
+        assert_eq!(SortKey::from_str("activity").expect("Could not parse key"), SortKey::LastActivity);
+        assert_eq!(SortKey::from_str("due").expect("Could not parse key"), SortKey::Due);
+        assert!(SortKey::from_str("size").is_err());
+        assert_eq!(GroupKey::from_str("assignee").expect("Could not parse key"), GroupKey::Assignee);
+        assert!(GroupKey::from_str("type").is_err());
+        assert_eq!(Involvement::from_str("mentioned").expect("Could not parse involvement"), Involvement::Mentioned);
+        assert_eq!(Order::from_str("asc").expect("Could not parse order"), Order::Ascending);
+        assert!(Order::from_str("up").is_err());
+    }
+
+    #[test]
+    fn due_dates() {
+        assert_eq!(parse_due_date("1970-01-01"), Some(0));
+        assert_eq!(parse_due_date("2000-03-01"), Some(951868800));
+        assert_eq!(parse_due_date("2024-02-29"), Some(1709164800));
+        assert_eq!(parse_due_date("2024-13-01"), None);
+        assert_eq!(parse_due_date("soon"), None);
+        assert_eq!(parse_priority("P3"), Some(3));
+        assert_eq!(parse_priority("high"), None);
+    }
+}
@@ -0,0 +1,315 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Cache of parsed messages
+//!
+//! This module provides the `ParsedMessage` type, holding the information of a
+//! message which is of interest for most operations, as well as the
+//! `MessageCache`, which keeps the least recently used parsed messages around.
+//!
+//! A process-wide cache is shared by all operations and is accessed via
+//! `parsed()`. Operations touching the same messages repeatedly, e.g. listing
+//! issues after filtering them by their metadata, hence look up and parse each
+//! message only once. Since messages are identified by their ids, the cache
+//! may be shared between repositories.
+//!
+//! Parsed messages reflect the decryption cipher registered at the time they
+//! were parsed. Registering a new cipher clears the shared cache.
+//!
+
+use git2::{self, Oid};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use message::Message;
+use trailer::Trailer;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Capacity of the shared cache, in messages
+///
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+
+lazy_static! {
+    // cache shared by all operations of the process
+    static ref SHARED_CACHE: Mutex<MessageCache> = Mutex::new(MessageCache::new(DEFAULT_CAPACITY));
+}
+
+
+/// Parsed message
+///
+/// A `ParsedMessage` holds a message's text, which is decrypted if possible,
+/// and its trailers. Unlike a `git2::Commit`, it doesn't borrow a repository.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedMessage {
+    id: Oid,
+    author: String,
+    time: i64,
+    lines: Vec<String>,
+    trailers: Vec<Trailer>,
+}
+
+impl ParsedMessage {
+    /// Parse a message
+    ///
+    pub fn new(commit: &git2::Commit) -> Self {
+        ParsedMessage {
+            id: commit.id(),
+            author: commit.author().name().unwrap_or("").to_owned(),
+            time: commit.time().seconds(),
+            lines: commit.message_lines().collect(),
+            trailers: commit.trailers().collect(),
+        }
+    }
+
+    /// Get the message's id
+    ///
+    pub fn id(&self) -> Oid {
+        self.id
+    }
+
+    /// Get the name of the message's author
+    ///
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    /// Get the time of the message's creation, in seconds since the epoch
+    ///
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// Get the message's subject line
+    ///
+    pub fn subject(&self) -> &str {
+        self.lines.first().map(String::as_str).unwrap_or("")
+    }
+
+    /// Get the message's text as a sequence of lines
+    ///
+    pub fn message_lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Get the message's body as a sequence of lines
+    ///
+    pub fn body_lines(&self) -> &[String] {
+        self.lines.get(2..).unwrap_or(&[])
+    }
+
+    /// Get the trailers in the message's body
+    ///
+    pub fn trailers(&self) -> &[Trailer] {
+        &self.trailers
+    }
+}
+
+
+/// Cache of parsed messages
+///
+/// The cache holds up to a fixed number of messages. If a message is added to
+/// a full cache, the least recently used message is evicted.
+///
+pub struct MessageCache {
+    capacity: usize,
+    messages: HashMap<Oid, (Arc<ParsedMessage>, u64)>,
+    uses: BTreeMap<u64, Oid>,
+    clock: u64,
+}
+
+impl MessageCache {
+    /// Create a new cache holding up to a given number of messages
+    ///
+    pub fn new(capacity: usize) -> Self {
+        MessageCache {
+            capacity: capacity,
+            messages: HashMap::new(),
+            uses: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Get the number of messages the cache may hold
+    ///
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the number of messages the cache may hold
+    ///
+    /// If the cache holds more messages than the new capacity permits, the
+    /// least recently used ones are evicted.
+    ///
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Get the number of messages held
+    ///
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Check whether the cache is empty
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Get a message from the cache, marking it as recently used
+    ///
+    pub fn get(&mut self, id: Oid) -> Option<Arc<ParsedMessage>> {
+        let tick = self.tick();
+        let uses = &mut self.uses;
+        self.messages.get_mut(&id).map(|&mut (ref message, ref mut used)| {
+            uses.remove(used);
+            uses.insert(tick, id);
+            *used = tick;
+            message.clone()
+        })
+    }
+
+    /// Add a message to the cache
+    ///
+    pub fn insert(&mut self, message: Arc<ParsedMessage>) {
+        let id = message.id();
+        let tick = self.tick();
+        if let Some((_, used)) = self.messages.insert(id, (message, tick)) {
+            self.uses.remove(&used);
+        }
+        self.uses.insert(tick, id);
+        self.evict();
+    }
+
+    /// Remove all messages from the cache
+    ///
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.uses.clear();
+    }
+
+    /// Advance the clock used for tracking uses
+    ///
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Evict least recently used messages until the capacity is respected
+    ///
+    fn evict(&mut self) {
+        while self.messages.len() > self.capacity {
+            let oldest = match self.uses.keys().next() {
+                Some(used) => *used,
+                None => break,
+            };
+            if let Some(id) = self.uses.remove(&oldest) {
+                self.messages.remove(&id);
+            }
+        }
+    }
+}
+
+
+/// Get a parsed message from the shared cache
+///
+/// If the message is not cached, it is looked up in the repository, parsed
+/// and added to the cache.
+///
+pub fn parsed(repo: &git2::Repository, id: Oid) -> Result<Arc<ParsedMessage>> {
+    if let Some(message) = SHARED_CACHE.lock().ok().and_then(|mut cache| cache.get(id)) {
+        return Ok(message);
+    }
+
+    // The lock is not held while parsing, which may be costly.
+    let commit = repo.find_commit(id).chain_err(|| EK::CannotGetCommit(id))?;
+    let message = Arc::new(ParsedMessage::new(&commit));
+    if let Ok(mut cache) = SHARED_CACHE.lock() {
+        cache.insert(message.clone());
+    }
+    Ok(message)
+}
+
+/// Set the number of messages the shared cache may hold
+///
+/// A capacity of zero disables caching.
+///
+pub fn set_capacity(capacity: usize) {
+    if let Ok(mut cache) = SHARED_CACHE.lock() {
+        cache.set_capacity(capacity);
+    }
+}
+
+/// Remove all messages from the shared cache
+///
+pub fn clear() {
+    if let Ok(mut cache) = SHARED_CACHE.lock() {
+        cache.clear();
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use repository::RepositoryExt;
+
+    #[test]
+    fn message_cache() {
+        let mut testing_repo = TestingRepo::new("message_cache");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let messages: Vec<_> = (0..3)
+            .map(|n| format!("Test message {}\n\nSome text\n\nDit-status: open", n))
+            .map(|text| repo.commit(None, &sig, &sig, &text, &empty_tree, &[]))
+            .map(|id| id.expect("Could not create commit"))
+            .collect();
+
+        let message = parsed(repo, messages[0]).expect("Could not parse message");
+        assert_eq!(message.subject(), "Test message 0");
+        assert_eq!(message.author(), "Foo Bar");
+        assert_eq!(message.body_lines().len(), 3);
+        assert_eq!(message.trailers().len(), 1);
+
+        let mut cache = MessageCache::new(2);
+        for id in &messages {
+            let commit = repo.find_commit(*id).expect("Could not find commit");
+            cache.insert(Arc::new(ParsedMessage::new(&commit)));
+            if *id == messages[1] {
+                // mark the first message as recently used
+                assert!(cache.get(messages[0]).is_some());
+            }
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(messages[1]).is_none());
+        assert_eq!(cache.get(messages[0]).map(|message| message.id()), Some(messages[0]));
+        assert!(cache.get(messages[2]).is_some());
+
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(messages[2]).is_some());
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
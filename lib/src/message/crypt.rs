@@ -0,0 +1,430 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Encryption of message bodies
+//!
+//! Messages of security sensitive issues may be encrypted to a list of
+//! recipients before they are committed. Only the body text of a message is
+//! encrypted. The subject line and the trailing block of trailers stay in
+//! clear text in order to keep the issue listable and its metadata accessible.
+//!
+//! The encrypted part is stored as an ASCII-armored block. If a decryption
+//! cipher is registered via `set_decryption_cipher()`, such blocks are
+//! transparently decrypted by the accessors of the `Message` trait. Blocks
+//! which cannot be decrypted are left untouched.
+//!
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::RwLock;
+use std::thread;
+
+use message::LineIteratorExt;
+use message::block::Block;
+use message::cache;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Armor delimiters of supported ciphertexts
+///
+const ARMOR_DELIMITERS: [(&'static str, &'static str); 2] = [
+    ("-----BEGIN PGP MESSAGE-----", "-----END PGP MESSAGE-----"),
+    ("-----BEGIN AGE ENCRYPTED FILE-----", "-----END AGE ENCRYPTED FILE-----"),
+];
+
+
+/// Cipher for encrypting and decrypting message bodies
+///
+/// Implementations are expected to produce ASCII-armored ciphertexts delimited
+/// by one of the supported armor lines.
+///
+pub trait Cipher: Send + Sync {
+    /// Encrypt a piece of text to the recipients given
+    ///
+    fn encrypt(&self, plaintext: &str, recipients: &[String]) -> Result<String>;
+
+    /// Decrypt a ciphertext
+    ///
+    fn decrypt(&self, ciphertext: &str) -> Result<String>;
+}
+
+
+/// Encryption backend
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Backend {
+    Gpg,
+    Age,
+}
+
+
+/// Cipher using an external encryption program
+///
+/// This cipher invokes either `gpg` or `age` for encryption and decryption.
+///
+pub struct ProgramCipher {
+    backend: Backend,
+    program: String,
+    identity: Option<String>,
+}
+
+impl ProgramCipher {
+    /// Create a new cipher for the given backend
+    ///
+    /// By default, the program is named after the backend.
+    ///
+    pub fn new(backend: Backend) -> Self {
+        let program = match backend {
+            Backend::Gpg => "gpg",
+            Backend::Age => "age",
+        };
+        ProgramCipher { backend: backend, program: program.to_owned(), identity: None }
+    }
+
+    /// Use a specific program rather than the default one
+    ///
+    pub fn with_program<S>(mut self, program: S) -> Self
+        where S: Into<String>
+    {
+        self.program = program.into();
+        self
+    }
+
+    /// Use an identity file for decryption
+    ///
+    /// The `age` backend requires an identity file for decryption. The `gpg`
+    /// backend ignores this option and uses the keyring instead.
+    ///
+    pub fn with_identity<S>(mut self, identity: S) -> Self
+        where S: Into<String>
+    {
+        self.identity = Some(identity.into());
+        self
+    }
+
+    /// Run the program with the given arguments, piping the input through it
+    ///
+    fn run(&self, args: &[String], input: &str) -> Result<String> {
+        let mut child = Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .chain_err(|| EK::CipherProgramError(self.program.clone()))?;
+
+        // The input is written from a separate thread since the program may
+        // not consume all of it before we read its output.
+        let mut stdin = child.stdin
+            .take()
+            .ok_or_else(|| Error::from_kind(EK::CipherProgramError(self.program.clone())))?;
+        let input = input.to_owned();
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+        let output = child
+            .wait_with_output()
+            .chain_err(|| EK::CipherProgramError(self.program.clone()))?;
+        let written = writer.join().map(|result| result.is_ok()).unwrap_or(false);
+        if !output.status.success() || !written {
+            return Err(Error::from_kind(EK::CipherProgramError(self.program.clone())));
+        }
+        String::from_utf8(output.stdout)
+            .chain_err(|| EK::CipherProgramError(self.program.clone()))
+    }
+}
+
+impl Cipher for ProgramCipher {
+    fn encrypt(&self, plaintext: &str, recipients: &[String]) -> Result<String> {
+        if recipients.is_empty() {
+            return Err(Error::from_kind(EK::NoRecipients));
+        }
+
+        let mut args: Vec<String> = match self.backend {
+            Backend::Gpg => vec!["--batch", "--quiet", "--armor", "--encrypt"],
+            Backend::Age => vec!["--armor"],
+        }.into_iter().map(String::from).collect();
+        for recipient in recipients {
+            args.push(match self.backend {
+                Backend::Gpg => "--recipient",
+                Backend::Age => "-r",
+            }.to_owned());
+            args.push(recipient.clone());
+        }
+
+        self.run(&args, plaintext).chain_err(|| EK::CannotEncrypt)
+    }
+
+    fn decrypt(&self, ciphertext: &str) -> Result<String> {
+        let mut args: Vec<String> = match self.backend {
+            Backend::Gpg => vec!["--batch", "--quiet", "--decrypt"],
+            Backend::Age => vec!["--decrypt"],
+        }.into_iter().map(String::from).collect();
+        if let (&Backend::Age, Some(identity)) = (&self.backend, self.identity.as_ref()) {
+            args.push("-i".to_owned());
+            args.push(identity.clone());
+        }
+
+        self.run(&args, ciphertext).chain_err(|| EK::CannotDecrypt)
+    }
+}
+
+
+lazy_static! {
+    // cipher used for transparent decryption
+    static ref DECRYPTION_CIPHER: RwLock<Option<Box<Cipher>>> = RwLock::new(None);
+}
+
+
+/// Register the cipher used for transparent decryption
+///
+/// After a cipher is registered, encrypted message bodies will be decrypted
+/// by the accessors of the `Message` trait. Pass `None` in order to disable
+/// transparent decryption. Messages parsed with the previous cipher are
+/// removed from the shared message cache.
+///
+pub fn set_decryption_cipher(cipher: Option<Box<Cipher>>) {
+    if let Ok(mut current) = DECRYPTION_CIPHER.write() {
+        *current = cipher;
+    }
+    cache::clear();
+}
+
+
+/// Encrypt a message
+///
+/// The subject line and a trailing block of trailers are kept in clear text.
+/// All other lines of the body are replaced by the ciphertext. A message
+/// without body text is returned unaltered.
+///
+pub fn encrypt_message<C>(cipher: &C, message: &str, recipients: &[String]) -> Result<String>
+    where C: Cipher + ?Sized
+{
+    let mut lines = message.lines();
+    let subject = lines.next().unwrap_or_default();
+
+    let mut blocks: Vec<Block> = lines.line_blocks().collect();
+    let trailers = match blocks.last() {
+        Some(&Block::Trailer(_)) => blocks.pop(),
+        _ => None,
+    };
+
+    let body = blocks
+        .into_iter()
+        .map(|block| match block {
+            Block::Text(lines) => lines.into_iter().collect_string(),
+            Block::Trailer(trailers) => trailers
+                .into_iter()
+                .map(|t| t.to_string())
+                .collect_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut retval = String::from(subject);
+    retval.push_str("\n\n");
+    if !body.trim().is_empty() {
+        retval.push_str(cipher.encrypt(&body, recipients)?.trim_end());
+        retval.push('\n');
+    }
+    if let Some(Block::Trailer(trailers)) = trailers {
+        retval.push('\n');
+        retval.push_str(&trailers.into_iter().map(|t| t.to_string()).collect_string());
+    }
+    Ok(retval)
+}
+
+
+/// Check whether a sequence of lines contains an encrypted block
+///
+pub fn is_encrypted<I, S>(lines: I) -> bool
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str>
+{
+    lines
+        .into_iter()
+        .any(|line| ARMOR_DELIMITERS.iter().any(|d| line.as_ref() == d.0))
+}
+
+
+/// Decrypt all encrypted blocks in a sequence of lines
+///
+/// Blocks which cannot be decrypted are left untouched.
+///
+pub fn decrypt_lines<C>(cipher: &C, lines: Vec<String>) -> Vec<String>
+    where C: Cipher + ?Sized
+{
+    let mut retval = Vec::with_capacity(lines.len());
+    let mut block: Option<(&'static str, Vec<String>)> = None;
+
+    for line in lines {
+        let end = match block {
+            Some((end, ref mut buf)) => {
+                buf.push(line);
+                if buf.last().map(String::as_str) == Some(end) { Some(end) } else { None }
+            },
+            None => {
+                match ARMOR_DELIMITERS.iter().find(|d| line == d.0) {
+                    Some(d) => block = Some((d.1, vec![line])),
+                    None => retval.push(line),
+                }
+                None
+            },
+        };
+
+        if end.is_some() {
+            let (_, buf) = block.take().unwrap();
+            match cipher.decrypt(&buf.iter().collect_string()) {
+                Ok(plaintext) => retval.extend(plaintext.trim_end().lines().map(String::from)),
+                Err(_) => retval.extend(buf),
+            }
+        }
+    }
+
+    // incomplete blocks are left untouched
+    if let Some((_, buf)) = block {
+        retval.extend(buf);
+    }
+
+    retval
+}
+
+
+/// Decrypt lines using the registered cipher
+///
+/// If no cipher is registered or the lines do not contain any encrypted block,
+/// the lines are returned as they are.
+///
+pub fn decrypt_registered(lines: Vec<String>) -> Vec<String> {
+    if !is_encrypted(lines.iter()) {
+        return lines;
+    }
+
+    match DECRYPTION_CIPHER.read() {
+        Ok(ref cipher) => match cipher.as_ref() {
+            Some(cipher) => decrypt_lines(cipher.as_ref(), lines),
+            None => lines,
+        },
+        Err(_) => lines,
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dummy cipher reversing the text
+    ///
+    struct ReversingCipher;
+
+    impl Cipher for ReversingCipher {
+        fn encrypt(&self, plaintext: &str, _: &[String]) -> Result<String> {
+            Ok(format!("{}\n{}\n{}\n",
+                       ARMOR_DELIMITERS[0].0,
+                       plaintext.trim_end().replace('\n', "|").chars().rev().collect::<String>(),
+                       ARMOR_DELIMITERS[0].1))
+        }
+
+        fn decrypt(&self, ciphertext: &str) -> Result<String> {
+            let mut lines = ciphertext.lines().skip(1);
+            Ok(lines.next().unwrap_or_default().chars().rev().collect::<String>().replace('|', "\n"))
+        }
+    }
+
+    // encryption tests
+
+    #[test]
+    fn encrypt_message_body() {
+        let recipients = vec!["foo@example.com".to_owned()];
+        let message = "Secret issue\n\nSome text\nmore text\n\nDit-status: open\n";
+        let encrypted = encrypt_message(&ReversingCipher, message, &recipients)
+            .expect("Could not encrypt message");
+
+        let lines: Vec<_> = encrypted.lines().map(String::from).collect();
+        assert_eq!(lines[0], "Secret issue");
+        assert_eq!(lines[1], "");
+        assert_eq!(lines[2], ARMOR_DELIMITERS[0].0);
+        assert_eq!(lines.last().map(String::as_str), Some("Dit-status: open"));
+        assert!(!encrypted.contains("Some text"));
+        assert!(is_encrypted(lines.iter()));
+
+        let decrypted = decrypt_lines(&ReversingCipher, lines);
+        assert_eq!(decrypted, vec![
+            "Secret issue",
+            "",
+            "Some text",
+            "more text",
+            "",
+            "Dit-status: open",
+        ]);
+    }
+
+    #[test]
+    fn encrypt_empty_body() {
+        let recipients = vec!["foo@example.com".to_owned()];
+        let encrypted = encrypt_message(&ReversingCipher, "Secret issue\n", &recipients)
+            .expect("Could not encrypt message");
+        assert!(!is_encrypted(encrypted.lines()));
+    }
+
+    #[test]
+    fn program_large_input() {
+        let input = "Some text\n".repeat(100000);
+        let output = ProgramCipher::new(Backend::Gpg)
+            .with_program("cat")
+            .run(&[], &input)
+            .expect("Could not run program");
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn raw_body_lines() {
+        use message::Message;
+        use repository::RepositoryExt;
+        use test_utils::{with_decryption_cipher, TestingRepo};
+
+        let mut testing_repo = TestingRepo::new("raw_body_lines");
+        let repo = testing_repo.repo();
+
+        let sig = ::git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let recipients = vec!["foo@example.com".to_owned()];
+        let text = encrypt_message(&ReversingCipher, "Secret issue\n\nSome text\n", &recipients)
+            .expect("Could not encrypt message");
+        let id = repo
+            .commit(None, &sig, &sig, &text, &empty_tree, &[])
+            .expect("Could not create message");
+        let message = repo.find_commit(id).expect("Could not find message");
+
+        let (body, raw): (Vec<_>, Vec<_>) = with_decryption_cipher(Box::new(ReversingCipher), || {
+            (message.body_lines().collect(), message.raw_body_lines().collect())
+        });
+
+        assert_eq!(body, vec!["Some text"]);
+        assert!(is_encrypted(raw.iter()));
+        assert!(!raw.iter().any(|line| line.contains("Some text")));
+    }
+
+    #[test]
+    fn decrypt_incomplete_block() {
+        let lines = vec![
+            "Foo".to_owned(),
+            ARMOR_DELIMITERS[0].0.to_owned(),
+            "oof".to_owned(),
+        ];
+        assert_eq!(decrypt_lines(&ReversingCipher, lines.clone()), lines);
+    }
+}
@@ -0,0 +1,377 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Message linting
+//!
+//! This module provides a `Linter` for detecting stylistic problems in
+//! messages, e.g. overly long subject lines or quotes not separated from the
+//! surrounding text. Unlike the checks of `LineIteratorExt`, lints do not
+//! render a message invalid by themselves. Each rule is assigned a `Severity`,
+//! which lets a caller decide whether to merely warn about a problem or refuse
+//! the message.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::result::Result as RResult;
+use std::str::FromStr;
+
+use config::DitConfig;
+use message::LineIteratorExt;
+use message::block::Block;
+use trailer::Trailer;
+use trailer::spec::DIT_KEY_PREFIX;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Default maximum length of subject lines, in characters
+///
+pub const DEFAULT_MAX_SUBJECT_LENGTH: usize = 72;
+
+
+/// Lint rule
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    /// The subject line exceeds the maximum length
+    SubjectLength,
+    /// A line ends with whitespace
+    TrailingWhitespace,
+    /// A git-dit trailer is embedded in a paragraph of text and thus ignored
+    TrailerPlacement,
+    /// A quote is malformed or not separated from the text following it
+    BrokenQuoting,
+}
+
+impl Rule {
+    /// All rules, in the order in which they are checked
+    ///
+    pub fn all() -> &'static [Rule] {
+        &[Rule::SubjectLength, Rule::TrailingWhitespace, Rule::TrailerPlacement, Rule::BrokenQuoting]
+    }
+
+    /// Get the name of the rule
+    ///
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Rule::SubjectLength      => "subject-length",
+            Rule::TrailingWhitespace => "trailing-whitespace",
+            Rule::TrailerPlacement   => "trailer-placement",
+            Rule::BrokenQuoting      => "broken-quoting",
+        }
+    }
+
+    /// Get the severity assigned to the rule by default
+    ///
+    pub fn default_severity(&self) -> Severity {
+        match *self {
+            Rule::TrailerPlacement => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        f.write_str(self.name())
+    }
+}
+
+
+/// Severity of a lint
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The rule is not checked
+    Off,
+    /// Violations are reported, but the message is acceptable
+    Warning,
+    /// Violations render the message unacceptable
+    Error,
+}
+
+impl FromStr for Severity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off"       => Ok(Severity::Off),
+            "warning"   => Ok(Severity::Warning),
+            "error"     => Ok(Severity::Error),
+            _ => Err(Error::from_kind(EK::UnknownLintSeverity(s.to_owned()))),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        f.write_str(match *self {
+            Severity::Off       => "off",
+            Severity::Warning   => "warning",
+            Severity::Error     => "error",
+        })
+    }
+}
+
+
+/// Problem detected in a message
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    /// Rule violated
+    pub rule: Rule,
+    /// Severity assigned to the rule
+    pub severity: Severity,
+    /// Number of the offending line, starting at 1
+    pub line: usize,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        write!(f, "{}: {}: {} [{}]", self.line, self.severity, self.message, self.rule)
+    }
+}
+
+
+/// Linter for messages
+///
+/// The linter checks all rules with the severities assigned. The message
+/// should be stripped of comments before being linted.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Linter {
+    severities: BTreeMap<Rule, Severity>,
+    max_subject_length: usize,
+}
+
+impl Linter {
+    /// Create a linter checking all rules with their default severity
+    ///
+    pub fn new() -> Self {
+        Linter {
+            severities: Rule::all().iter().map(|rule| (*rule, rule.default_severity())).collect(),
+            max_subject_length: DEFAULT_MAX_SUBJECT_LENGTH,
+        }
+    }
+
+    /// Create a linter configured via the `dit.lint.*` options
+    ///
+    /// The severity of a rule is read from `dit.lint.<rule>` and the maximum
+    /// length of subject lines from `dit.lint.max-subject-length`. Options
+    /// which are not set retain the defaults of `new()`.
+    ///
+    pub fn with_config(config: &DitConfig) -> Result<Self> {
+        let mut retval = Self::new();
+
+        for rule in Rule::all() {
+            if let Some(severity) = config.lint_severity(*rule)? {
+                retval = retval.severity(*rule, severity);
+            }
+        }
+        if let Some(length) = config.lint_max_subject_length()? {
+            retval = retval.max_subject_length(length);
+        }
+
+        Ok(retval)
+    }
+
+    /// Assign a severity to a rule
+    ///
+    pub fn severity(mut self, rule: Rule, severity: Severity) -> Self {
+        self.severities.insert(rule, severity);
+        self
+    }
+
+    /// Set the maximum length of subject lines, in characters
+    ///
+    pub fn max_subject_length(mut self, length: usize) -> Self {
+        self.max_subject_length = length;
+        self
+    }
+
+    /// Lint a message
+    ///
+    /// The lints are returned ordered by line.
+    ///
+    pub fn lint<I, S>(&self, lines: I) -> Vec<Lint>
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>
+    {
+        let lines: Vec<String> = lines.into_iter().map(|line| line.as_ref().to_owned()).collect();
+        let mut retval = Vec::new();
+
+        {
+            let mut report = |rule: Rule, line: usize, message: String| {
+                let severity = self.severities.get(&rule).cloned().unwrap_or(Severity::Off);
+                if severity != Severity::Off {
+                    retval.push(Lint { rule: rule, severity: severity, line: line, message: message });
+                }
+            };
+
+            if let Some(subject) = lines.first() {
+                let length = subject.chars().count();
+                if length > self.max_subject_length {
+                    report(Rule::SubjectLength, 1, format!("Subject line has {} characters, exceeding the limit of {}",
+                                                           length,
+                                                           self.max_subject_length));
+                }
+            }
+
+            for (number, line) in (1..).zip(lines.iter()) {
+                if line.ends_with(char::is_whitespace) {
+                    report(Rule::TrailingWhitespace, number, "Line ends with whitespace".to_owned());
+                }
+            }
+
+            for (number, line) in misplaced_trailers(&lines) {
+                report(Rule::TrailerPlacement, number, format!("Trailer '{}' is not part of a block of trailers", line));
+            }
+
+            let mut previous_quoted = false;
+            for (number, line) in (1..).zip(lines.iter()) {
+                let quoted = line.starts_with('>');
+                if quoted && !is_well_formed_quote(line) {
+                    report(Rule::BrokenQuoting, number, "Quote marker is not followed by a space".to_owned());
+                }
+                if previous_quoted && !quoted && !line.trim().is_empty() {
+                    report(Rule::BrokenQuoting, number, "Quote is not followed by an empty line".to_owned());
+                }
+                previous_quoted = quoted;
+            }
+        }
+
+        retval.sort_by_key(|lint| lint.line);
+        retval
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Check whether any of the lints given is an error
+///
+pub fn has_errors<'a, I>(lints: I) -> bool
+    where I: IntoIterator<Item = &'a Lint>
+{
+    lints.into_iter().any(|lint| lint.severity == Severity::Error)
+}
+
+
+/// Find git-dit trailers which are part of a paragraph of text
+///
+/// The line numbers, starting at 1, and the lines are returned.
+///
+fn misplaced_trailers(lines: &[String]) -> Vec<(usize, String)> {
+    // numbers of the first lines of the blocks following the subject
+    let starts = (2..)
+        .zip(lines.iter().skip(1))
+        .scan(true, |blank_before, (number, line)| {
+            let blank = line.trim().is_empty();
+            let start = *blank_before && !blank;
+            *blank_before = blank;
+            Some((number, start))
+        })
+        .filter(|&(_, start)| start)
+        .map(|(number, _)| number);
+
+    lines
+        .iter()
+        .skip(1)
+        .line_blocks()
+        .zip(starts)
+        .flat_map(|(block, start)| match block {
+            Block::Text(lines) => (start..)
+                .zip(lines)
+                .filter(|(_, line)| line.starts_with(DIT_KEY_PREFIX) && Trailer::from_str(line).is_ok())
+                .collect(),
+            Block::Trailer(_) => Vec::new(),
+        })
+        .collect()
+}
+
+
+/// Check whether a quoted line is well formed
+///
+/// Quote markers, which may be nested, must be followed by a space unless
+/// the quoted line is empty.
+///
+fn is_well_formed_quote(line: &str) -> bool {
+    let rest = line.trim_start_matches(&['>', ' '][..]);
+    let markers = &line[..line.len() - rest.len()];
+    rest.is_empty() || markers.ends_with(' ')
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Linter tests
+
+    #[test]
+    fn default_lints() {
+        let message = vec![
+            "A subject line which is way too long for a message, even for a message of git-dit",
+            "",
+            "Some text ",
+            "Dit-type: bug",
+            "",
+            "> quoted",
+            ">unspaced",
+            "> > nested",
+            ">",
+            "text",
+            "",
+            "Dit-tag: foo",
+        ];
+        let lints: Vec<(Rule, Severity, usize)> = Linter::new()
+            .lint(message)
+            .into_iter()
+            .map(|lint| (lint.rule, lint.severity, lint.line))
+            .collect();
+        assert_eq!(lints, vec![
+            (Rule::SubjectLength, Severity::Warning, 1),
+            (Rule::TrailingWhitespace, Severity::Warning, 3),
+            (Rule::TrailerPlacement, Severity::Error, 4),
+            (Rule::BrokenQuoting, Severity::Warning, 7),
+            (Rule::BrokenQuoting, Severity::Warning, 10),
+        ]);
+    }
+
+    #[test]
+    fn configured_lints() {
+        let message = vec!["Subject of moderate length", "", "Some text ", "Dit-type: bug"];
+        let linter = Linter::new()
+            .severity(Rule::TrailerPlacement, Severity::Off)
+            .severity(Rule::TrailingWhitespace, Severity::Error)
+            .max_subject_length(10);
+        let lints = linter.lint(message);
+        assert_eq!(lints.iter().map(|lint| lint.rule).collect::<Vec<_>>(),
+                   vec![Rule::SubjectLength, Rule::TrailingWhitespace]);
+        assert!(has_errors(&lints));
+        assert!(!has_errors(&Linter::new().lint(vec!["Foo", "", "Bar"])));
+    }
+
+    #[test]
+    fn severity_parsing() {
+        assert_eq!(Severity::from_str("warning").expect("Could not parse severity"), Severity::Warning);
+        assert_eq!(Severity::from_str("off").expect("Could not parse severity"), Severity::Off);
+        assert!(Severity::from_str("fatal").is_err());
+    }
+}
@@ -25,7 +25,10 @@ use git2::Commit;
 use std;
 
 pub mod block;
+pub mod cache;
+pub mod crypt;
 pub mod line_processor;
+pub mod lint;
 
 use self::line_processor::{Quoted, StrippingIter};
 
@@ -49,6 +52,31 @@ pub trait LineIteratorExt<S>
     ///
     fn check_message_format(self) -> Result<()>;
 
+    /// Check whether the trailers of a message are well placed
+    ///
+    /// Trailers are only recognized if they are part of a block consisting of
+    /// trailers only. This function reports git-dit specific trailers, which
+    /// would be ignored because they are embedded in a paragraph of text.
+    ///
+    fn check_trailers(self) -> Result<()>;
+
+    /// Get the required trailers missing from a message
+    ///
+    /// A required trailer is considered missing unless the message contains a
+    /// trailer with the same key, compared case-insensitively, and a non-empty
+    /// value. The subject line is not considered.
+    ///
+    fn missing_trailers<K>(self, required: &[K]) -> Vec<String>
+        where K: AsRef<str>;
+
+    /// Check whether a message contains all the trailers required
+    ///
+    /// Missing trailers are reported via a `MissingTrailers` error listing
+    /// their keys.
+    ///
+    fn check_required_trailers<K>(self, required: &[K]) -> Result<()>
+        where K: AsRef<str>;
+
     /// Create a whitespace and comment stripping iterator
     ///
     /// This function creates an iterator suitable for stripping parts of a
@@ -105,6 +133,54 @@ impl<L, S> LineIteratorExt<S> for L
         Ok(())
     }
 
+    fn check_trailers(self) -> Result<()> {
+        use std::str::FromStr;
+        use trailer::Trailer;
+        use trailer::spec::DIT_KEY_PREFIX;
+
+        for block in self.line_blocks() {
+            if let block::Block::Text(lines) = block {
+                let misplaced = lines
+                    .into_iter()
+                    .find(|line| line.starts_with(DIT_KEY_PREFIX) && Trailer::from_str(line).is_ok());
+                if let Some(line) = misplaced {
+                    return Err(Error::from_kind(EK::MisplacedTrailer(line)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn missing_trailers<K>(self, required: &[K]) -> Vec<String>
+        where K: AsRef<str>
+    {
+        let present: Vec<String> = self
+            .skip(1)
+            .trailers()
+            .filter(|trailer| !trailer.value.to_string().trim().is_empty())
+            .map(|trailer| trailer.key.as_ref().to_lowercase())
+            .collect();
+
+        required
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|key| !present.contains(&key.to_lowercase()))
+            .map(String::from)
+            .collect()
+    }
+
+    fn check_required_trailers<K>(self, required: &[K]) -> Result<()>
+        where K: AsRef<str>
+    {
+        let missing = self.missing_trailers(required);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::from_kind(EK::MissingTrailers(missing)))
+        }
+    }
+
     fn stripped(self) -> StrippingIter<Self::Iter, S> {
         line_processor::TrailingBlankTrimmer::from(
             line_processor::StripWhiteSpaceRightIter::from(
@@ -149,6 +225,8 @@ pub trait Message {
     /// Get the commit message as a sequence of lines
     ///
     /// If the commit has no message, an empty message will be simulated.
+    /// Encrypted parts of the message are decrypted transparently if a
+    /// decryption cipher is registered (see `crypt::set_decryption_cipher()`).
     ///
     fn message_lines(&self) -> std::vec::IntoIter<String>;
 
@@ -156,6 +234,14 @@ pub trait Message {
     ///
     fn body_lines(&self) -> BodyLines;
 
+    /// Get the commit message's body as a sequence of lines, as stored
+    ///
+    /// Unlike `body_lines()`, encrypted parts of the message are never
+    /// decrypted. Frontends should use this function for passing messages on
+    /// to third parties.
+    ///
+    fn raw_body_lines(&self) -> BodyLines;
+
     /// Get the commit message's body as a sequence of paragraphs and blocks of trailers
     ///
     fn body_blocks(&self) -> block::Blocks<BodyLines, String>;
@@ -178,13 +264,22 @@ impl<'c> Message for Commit<'c> {
                                       .lines()
                                       .map(String::from)
                                       .collect();
-        lines.into_iter()
+        crypt::decrypt_registered(lines).into_iter()
     }
 
     fn body_lines(&self) -> BodyLines {
         self.message_lines().skip(2)
     }
 
+    fn raw_body_lines(&self) -> BodyLines {
+        let lines : Vec<String> = self.message()
+                                      .unwrap_or("")
+                                      .lines()
+                                      .map(String::from)
+                                      .collect();
+        lines.into_iter().skip(2)
+    }
+
     fn body_blocks(&self) -> block::Blocks<BodyLines, String> {
         self.body_lines().line_blocks()
     }
@@ -238,4 +333,30 @@ mod tests {
     fn multiline_message_format_check() {
         vec!["Foo bar", "", "Baz"].into_iter().check_message_format().unwrap();
     }
+
+    #[test]
+    fn trailer_check() {
+        vec!["Foo bar", "", "Baz:", "qux", "", "Dit-type: bug", "Dit-tag: foo"]
+            .into_iter()
+            .check_trailers()
+            .unwrap();
+    }
+
+    #[test]
+    fn misplaced_trailer_check() {
+        assert!(vec!["Foo bar", "", "Baz", "Dit-type: bug"].into_iter().check_trailers().is_err());
+    }
+
+    #[test]
+    fn required_trailer_check() {
+        let message = vec!["Severity: subject", "", "Baz", "", "component: frontend", "Dit-tag:"];
+        assert_eq!(message.clone().into_iter().missing_trailers(&["Component", "Severity", "Dit-tag"]),
+                   vec!["Severity", "Dit-tag"]);
+        message.clone().into_iter().check_required_trailers(&["Component"]).unwrap();
+        let err = message
+            .into_iter()
+            .check_required_trailers(&["Severity"])
+            .expect_err("Accepted message without required trailer");
+        assert_eq!(err.kind(), Some(&EK::MissingTrailers(vec!["Severity".to_owned()])));
+    }
 }
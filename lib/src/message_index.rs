@@ -0,0 +1,294 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Reverse index of messages
+//!
+//! This module provides the `MessageIndex`, which maps messages to the issues
+//! they belong to. Without it, finding the issue of a message requires probing
+//! for a head reference for each message on the chain of first parents.
+//!
+//! The index is stored in the file `dit/messages` inside the repository's git
+//! directory, one message per line along with its issue. It is updated
+//! incrementally: only the messages between the targets of new references
+//! and already indexed messages are visited. Since it is a mere cache, it may
+//! be deleted at any time.
+//!
+
+use git2::{self, Oid};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use backend::{self, Backend};
+use issue::{Issue, IssueRefType};
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Map from messages to the issues they belong to
+///
+pub struct MessageIndex {
+    path: PathBuf,
+    issues: HashMap<Oid, Oid>,
+    changed: bool,
+    current: bool,
+}
+
+impl MessageIndex {
+    /// Open the message index of a repository
+    ///
+    /// A missing or malformed index is treated as an empty one.
+    ///
+    pub fn open<B: Backend + ?Sized>(backend: &B) -> Self {
+        let path = backend.git_dir().join("dit").join("messages");
+        let issues = fs::read_to_string(&path)
+            .map(|data| data
+                .lines()
+                .filter_map(|line| {
+                    let mut ids = line.split(' ').map(Oid::from_str);
+                    match (ids.next(), ids.next()) {
+                        (Some(Ok(message)), Some(Ok(issue))) => Some((message, issue)),
+                        _ => None,
+                    }
+                })
+                .collect())
+            .unwrap_or_default();
+        MessageIndex { path: path, issues: issues, changed: false, current: true }
+    }
+
+    /// Open and update the message index of a repository
+    ///
+    /// See `refresh()`.
+    ///
+    pub fn updated<B: Backend + ?Sized>(backend: &B) -> Self {
+        let mut index = Self::open(backend);
+        index.refresh(backend);
+        index
+    }
+
+    /// Update the index and write it if it changed
+    ///
+    /// Since the index is a mere cache, failing to write it is not an error.
+    /// If the index cannot be updated, e.g. because a reference points to a
+    /// missing message, lookups via `issue_with_message()` fall back to probing
+    /// for heads until the next successful refresh.
+    ///
+    pub fn refresh<B: Backend + ?Sized>(&mut self, backend: &B) {
+        self.current = self.update(backend).is_ok();
+        if self.current {
+            self.save().ok();
+        }
+    }
+
+    /// Index the messages reachable from the issue references of a repository
+    ///
+    /// Messages are visited following the first parent, starting from each
+    /// reference's target until an indexed message or an issue's initial
+    /// message is encountered. Like for `RepositoryExt::issue_with_message()`,
+    /// only initial messages for which a head exists are considered. Messages
+    /// of issues which no longer have a head are dropped from the index.
+    ///
+    pub fn update<B: Backend + ?Sized>(&mut self, backend: &B) -> Result<()> {
+        trace_span!("update_message_index");
+        let mut issues = HashSet::new();
+        let mut targets = Vec::new();
+        for (name, target) in backend::dit_references(backend, "**")? {
+            if let Some((issue, ref_type)) = IssueRefType::of_ref(&name) {
+                if ref_type == IssueRefType::Head {
+                    issues.insert(issue);
+                }
+                targets.push(target);
+            }
+        }
+
+        // Messages are attributed to the closest initial message. Hence, a new
+        // issue based on an indexed message invalidates the whole index.
+        let count = self.issues.len();
+        if issues.iter().any(|issue| self.issues.get(issue).map(|i| i != issue).unwrap_or(false)) {
+            self.issues.clear();
+        }
+        self.issues.retain(|_, issue| issues.contains(issue));
+        self.changed |= self.issues.len() != count;
+
+        trace_event!(references = targets.len(), issues = issues.len(), "indexing messages");
+        for target in targets {
+            let mut path = Vec::new();
+            let mut message = target;
+            let issue = loop {
+                if let Some(issue) = self.issue_id(message) {
+                    break Some(issue);
+                }
+                path.push(message);
+                if issues.contains(&message) {
+                    break Some(message);
+                }
+                match backend.first_parent(message)? {
+                    Some(parent) => message = parent,
+                    None => break None,
+                }
+            };
+
+            if let Some(issue) = issue {
+                self.changed |= !path.is_empty();
+                self.issues.extend(path.into_iter().map(|message| (message, issue)));
+            }
+        }
+        trace_event!(messages = self.issues.len(), "indexed messages");
+        Ok(())
+    }
+
+    /// Get the id of the issue an indexed message belongs to
+    ///
+    pub fn issue_id(&self, message: Oid) -> Option<Oid> {
+        self.issues.get(&message).cloned()
+    }
+
+    /// Get the issue a message belongs to
+    ///
+    /// The chain of first parents is followed until an indexed message is
+    /// encountered. Hence, the message itself need not be indexed. However,
+    /// the index is expected to be up to date. If the last refresh failed,
+    /// the chain is followed until an initial message for which a head exists
+    /// is encountered instead.
+    ///
+    pub fn issue_with_message<'r>(&self, repo: &'r git2::Repository, message: &git2::Commit<'r>) -> Result<Issue<'r>> {
+        for id in repo.first_parent_messages(message.id())?.revwalk {
+            let id = id?;
+            if !self.current {
+                let issue = repo.find_issue(id);
+                if issue.is_ok() {
+                    return issue;
+                }
+            } else if let Some(issue) = self.issue_id(id) {
+                return repo.find_issue(issue);
+            }
+        }
+
+        Err(Error::from_kind(EK::NoTreeInitFound(message.id())))
+    }
+
+    /// Get the issue a message belongs to, refreshing the index if necessary
+    ///
+    /// Unlike `issue_with_message()`, this function is suited for indices kept
+    /// around for a while, which may lack issues created or fetched since they
+    /// were last refreshed.
+    ///
+    pub fn lookup<'r>(&mut self, repo: &'r git2::Repository, message: &git2::Commit<'r>) -> Result<Issue<'r>> {
+        self.issue_with_message(repo, message).or_else(|_| {
+            self.refresh(repo);
+            self.issue_with_message(repo, message)
+        })
+    }
+
+    /// Write the index if it changed
+    ///
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let name = self.path.to_string_lossy().into_owned();
+        let write = || -> ::std::io::Result<()> {
+            if let Some(directory) = self.path.parent() {
+                fs::create_dir_all(directory)?;
+            }
+            let mut file = BufWriter::new(File::create(&self.path)?);
+            for (message, issue) in &self.issues {
+                writeln!(file, "{} {}", message, issue)?;
+            }
+            file.flush()
+        };
+        write().chain_err(|| EK::CannotWriteMessageIndex(name))?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    #[test]
+    fn message_index() {
+        let mut testing_repo = TestingRepo::new("message_index");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        // an unreferenced reply
+        let reply = repo
+            .commit(None, &sig, &sig, "Test message 3", &empty_tree, &[&message])
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+
+        let mut index = MessageIndex::open(repo);
+        index.update(repo).expect("Could not update index");
+        assert_eq!(index.issue_id(initial_message.id()), Some(issue.id()));
+        assert_eq!(index.issue_id(message.id()), Some(issue.id()));
+        assert_eq!(index.issue_id(reply.id()), None);
+        index.save().expect("Could not save index");
+
+        let index = MessageIndex::open(repo);
+        assert_eq!(index.issue_id(message.id()), Some(issue.id()));
+        let retrieved_issue = index
+            .issue_with_message(repo, &reply)
+            .expect("Could not retrieve issue");
+        assert_eq!(retrieved_issue.id(), issue.id());
+    }
+
+    #[test]
+    fn lookup() {
+        let mut testing_repo = TestingRepo::new("message_index_lookup");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let mut index = MessageIndex::updated(repo);
+
+        // an issue created after the index was refreshed
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        assert!(index.issue_with_message(repo, &initial_message).is_err());
+
+        let retrieved_issue = index
+            .lookup(repo, &initial_message)
+            .expect("Could not retrieve issue");
+        assert_eq!(retrieved_issue.id(), issue.id());
+        assert_eq!(index.issue_id(initial_message.id()), Some(issue.id()));
+    }
+}
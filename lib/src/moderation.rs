@@ -0,0 +1,311 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Moderation of messages
+//!
+//! Messages are immutable once released to the public. In order to deal with
+//! spam or abuse without rewriting history, messages may be hidden instead.
+//! A message is considered hidden if it is listed explicitly or if its author
+//! is blocked.
+//!
+//! Moderation data is stored in a chain of commits referred to by
+//! `refs/dit/meta/moderation`. The tree of each of those commits contains the
+//! files `blocked-authors` and `hidden-messages`, listing the email addresses
+//! of blocked authors and the ids of hidden messages respectively, one per
+//! line. Since the reference resides under `refs/dit/`, it is distributed
+//! along with the issues.
+//!
+
+use git2::{self, Commit, Oid, Repository, Tree};
+use std::collections::BTreeSet;
+use std::collections::btree_set;
+use std::marker::PhantomData;
+use std::str;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Name of the reference holding moderation data
+///
+pub const MODERATION_REF: &'static str = "refs/dit/meta/moderation";
+
+/// Name of the file listing blocked authors
+///
+const BLOCKED_AUTHORS_FILE: &'static str = "blocked-authors";
+
+/// Name of the file listing hidden messages
+///
+const HIDDEN_MESSAGES_FILE: &'static str = "hidden-messages";
+
+
+/// Moderation data
+///
+/// Instances of this type represent a set of blocked authors and hidden
+/// messages. Authors are identified by their email address.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Moderation {
+    blocked_authors: BTreeSet<String>,
+    hidden_messages: BTreeSet<Oid>,
+}
+
+impl Moderation {
+    /// Create a new set of moderation data, hiding nothing
+    ///
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Read moderation data from a tree
+    ///
+    /// Missing files are treated like empty ones.
+    ///
+    pub fn from_tree(repo: &Repository, tree: &Tree) -> Result<Self> {
+        let mut retval = Self::new();
+
+        for line in read_lines(repo, tree, BLOCKED_AUTHORS_FILE)? {
+            retval.blocked_authors.insert(line);
+        }
+        for line in read_lines(repo, tree, HIDDEN_MESSAGES_FILE)? {
+            let id = Oid::from_str(&line)
+                .chain_err(|| EK::OidFormatError(line.clone()))?;
+            retval.hidden_messages.insert(id);
+        }
+
+        Ok(retval)
+    }
+
+    /// Write the moderation data to a new tree
+    ///
+    pub fn write_tree(&self, repo: &Repository) -> Result<Oid> {
+        let authors = self.blocked_authors
+            .iter()
+            .fold(String::new(), |acc, author| acc + author + "\n");
+        let messages = self.hidden_messages
+            .iter()
+            .fold(String::new(), |acc, id| acc + &id.to_string() + "\n");
+
+        let mut builder = repo.treebuilder(None)?;
+        builder.insert(BLOCKED_AUTHORS_FILE, repo.blob(authors.as_bytes())?, 0o100644)?;
+        builder.insert(HIDDEN_MESSAGES_FILE, repo.blob(messages.as_bytes())?, 0o100644)?;
+        builder.write().chain_err(|| EK::CannotBuildTree)
+    }
+
+    /// Block an author
+    ///
+    /// Returns `false` if the author was already blocked.
+    ///
+    pub fn block_author<S>(&mut self, email: S) -> bool
+        where S: Into<String>
+    {
+        self.blocked_authors.insert(email.into())
+    }
+
+    /// Unblock an author
+    ///
+    /// Returns `false` if the author was not blocked.
+    ///
+    pub fn unblock_author(&mut self, email: &str) -> bool {
+        self.blocked_authors.remove(email)
+    }
+
+    /// Hide a message
+    ///
+    /// Returns `false` if the message was already hidden.
+    ///
+    pub fn hide_message(&mut self, id: Oid) -> bool {
+        self.hidden_messages.insert(id)
+    }
+
+    /// Unhide a message
+    ///
+    /// Returns `false` if the message was not hidden explicitly.
+    ///
+    pub fn unhide_message(&mut self, id: Oid) -> bool {
+        self.hidden_messages.remove(&id)
+    }
+
+    /// Get the blocked authors
+    ///
+    pub fn blocked_authors(&self) -> btree_set::Iter<String> {
+        self.blocked_authors.iter()
+    }
+
+    /// Get the explicitly hidden messages
+    ///
+    pub fn hidden_messages(&self) -> btree_set::Iter<Oid> {
+        self.hidden_messages.iter()
+    }
+
+    /// Check whether an author is blocked
+    ///
+    pub fn is_blocked(&self, email: &str) -> bool {
+        self.blocked_authors.contains(email)
+    }
+
+    /// Check whether a message is hidden
+    ///
+    /// A message is hidden if it is hidden explicitly or if its author is
+    /// blocked.
+    ///
+    pub fn is_hidden(&self, message: &Commit) -> bool {
+        self.hidden_messages.contains(&message.id()) ||
+            message.author().email().map(|e| self.is_blocked(e)).unwrap_or(false)
+    }
+
+    /// Filter hidden messages from an iterator over messages
+    ///
+    pub fn filter<'a, 'r, I>(&'a self, messages: I) -> Moderated<'a, 'r, I>
+        where I: Iterator<Item = Result<Commit<'r>>>
+    {
+        Moderated { moderation: self, inner: messages, phantom: PhantomData }
+    }
+}
+
+
+/// Iterator filtering hidden messages
+///
+/// This iterator wraps an iterator over messages, e.g. `Messages`, and skips
+/// all messages hidden by the moderation data it was created from. Errors are
+/// passed through.
+///
+pub struct Moderated<'a, 'r, I>
+    where I: Iterator<Item = Result<Commit<'r>>>
+{
+    moderation: &'a Moderation,
+    inner: I,
+    phantom: PhantomData<Commit<'r>>,
+}
+
+impl<'a, 'r, I> Iterator for Moderated<'a, 'r, I>
+    where I: Iterator<Item = Result<Commit<'r>>>
+{
+    type Item = Result<Commit<'r>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.inner.next() {
+            match item {
+                Ok(ref message) if self.moderation.is_hidden(message) => continue,
+                item => return Some(item),
+            }
+        }
+        None
+    }
+}
+
+
+/// Read the lines of a file in a tree
+///
+/// Empty lines are skipped. If the file does not exist, no lines are returned.
+///
+fn read_lines(repo: &Repository, tree: &Tree, name: &str) -> Result<Vec<String>> {
+    let entry = match tree.get_name(name) {
+        Some(entry) => entry,
+        None => return Ok(Vec::new()),
+    };
+    let blob = entry
+        .to_object(repo)
+        .and_then(|obj| obj.peel(git2::ObjectType::Blob))?
+        .into_blob()
+        .map_err(|_| Error::from_kind(EK::MalformedModerationData(name.to_owned())))?;
+
+    let lines = str::from_utf8(blob.content())
+        .chain_err(|| EK::MalformedModerationData(name.to_owned()))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    Ok(lines)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repository::RepositoryExt;
+    use test_utils::TestingRepo;
+
+    // Moderation tests
+
+    #[test]
+    fn moderation_roundtrip() {
+        let mut testing_repo = TestingRepo::new("moderation_roundtrip");
+        let repo = testing_repo.repo();
+
+        let mut moderation = Moderation::new();
+        assert!(moderation.block_author("spam@example.com"));
+        assert!(!moderation.block_author("spam@example.com"));
+        assert!(moderation.hide_message(Oid::from_str("65b56706fdc3501749d008750c61a1f24b888f72").unwrap()));
+
+        let tree_id = moderation
+            .write_tree(repo)
+            .expect("Could not write tree");
+        let tree = repo
+            .find_tree(tree_id)
+            .expect("Could not find tree");
+        let read = Moderation::from_tree(repo, &tree)
+            .expect("Could not read moderation data");
+        assert_eq!(read, moderation);
+
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        assert_eq!(Moderation::from_tree(repo, &empty_tree).expect("Could not read moderation data"),
+                   Moderation::new());
+    }
+
+    #[test]
+    fn moderated_messages() {
+        let mut testing_repo = TestingRepo::new("moderated_messages");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let spammer = git2::Signature::now("Spam", "spam@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue
+            .add_message(&spammer, &spammer, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&message1])
+            .expect("Could not add message");
+        let message3 = issue
+            .add_message(&sig, &sig, "Test message 4", &empty_tree, vec![&message2])
+            .expect("Could not add message");
+
+        let mut moderation = Moderation::new();
+        moderation.block_author("spam@example.com");
+        moderation.hide_message(message2.id());
+        assert!(moderation.is_hidden(&message1));
+        assert!(!moderation.is_hidden(&initial_message));
+
+        let mut ids: Vec<_> = moderation
+            .filter(repo.first_parent_messages(message3.id()).expect("Could not create iterator"))
+            .map(|message| message.expect("Could not retrieve message").id())
+            .collect();
+        ids.sort();
+        let mut expected = vec![issue.id(), message3.id()];
+        expected.sort();
+        assert_eq!(ids, expected);
+    }
+}
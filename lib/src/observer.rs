@@ -0,0 +1,249 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Observation of operations
+//!
+//! Applications embedding the library may want to react to changes, e.g. by
+//! updating a view, without polling the references of a repository. For this
+//! purpose, `Observer`s may be registered via `register()`. Registered
+//! observers are notified of all the `Operation`s performed through the
+//! library, in any repository, after the operation succeeded.
+//!
+//! Observers are called synchronously by the thread performing the operation.
+//! Hence, they should return quickly and must not perform operations through
+//! the library themselves.
+//!
+
+use git2::{Oid, Repository};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use issue::IssueRefType;
+
+
+/// Operation performed through the library
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// A new issue was created
+    IssueCreated(Oid),
+    /// A message was added to an issue
+    MessageAdded {
+        issue: Oid,
+        message: Oid,
+    },
+    /// A head reference of an issue was created or updated
+    HeadUpdated {
+        issue: Oid,
+        reference: String,
+        target: Oid,
+    },
+    /// A reference was deleted
+    ReferenceDeleted(String),
+}
+
+impl Operation {
+    /// Create an operation for an update of a reference
+    ///
+    /// Updates of a leaf reference are reported as the addition of the message
+    /// referred to. Returns `None` if the reference is neither a head nor a
+    /// leaf reference of an issue.
+    ///
+    pub fn for_update(reference: &str, target: Oid) -> Option<Self> {
+        match IssueRefType::of_ref(reference) {
+            Some((issue, IssueRefType::Head)) => Some(Operation::HeadUpdated {
+                issue: issue,
+                reference: reference.to_owned(),
+                target: target,
+            }),
+            Some((issue, IssueRefType::Leaf)) => Some(Operation::MessageAdded {
+                issue: issue,
+                message: target,
+            }),
+            _ => None,
+        }
+    }
+}
+
+
+/// Observer of operations
+///
+pub trait Observer: Send + Sync {
+    /// Handle an operation performed in a repository
+    ///
+    fn notify(&self, repo: &Repository, operation: &Operation);
+}
+
+
+/// Handle of a registered observer
+///
+/// The handle is required for unregistering the observer.
+///
+#[derive(Debug, PartialEq, Eq)]
+pub struct ObserverHandle(usize);
+
+
+lazy_static! {
+    // registered observers along with the ids of their handles
+    static ref OBSERVERS: RwLock<Vec<(usize, Arc<Observer>)>> = RwLock::new(Vec::new());
+}
+
+// id of the next observer registered
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Register an observer
+///
+pub fn register(observer: Arc<Observer>) -> ObserverHandle {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut observers) = OBSERVERS.write() {
+        observers.push((id, observer));
+    }
+    ObserverHandle(id)
+}
+
+
+/// Unregister an observer
+///
+/// Returns `false` if the observer was not registered.
+///
+pub fn unregister(handle: ObserverHandle) -> bool {
+    match OBSERVERS.write() {
+        Ok(mut observers) => {
+            let count = observers.len();
+            observers.retain(|&(id, _)| id != handle.0);
+            observers.len() != count
+        },
+        Err(_) => false,
+    }
+}
+
+
+/// Notify all registered observers of an operation
+///
+pub(crate) fn notify(repo: &Repository, operation: &Operation) {
+    // The observers are cloned in order not to hold the lock while notifying,
+    // which would prevent observers from (un)registering observers.
+    let observers: Vec<Arc<Observer>> = match OBSERVERS.read() {
+        Ok(observers) => observers.iter().map(|&(_, ref observer)| observer.clone()).collect(),
+        Err(_) => return,
+    };
+    for observer in observers {
+        observer.notify(repo, operation);
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use repository::RepositoryExt;
+    use transaction::RefTransaction;
+    use test_utils::TestingRepo;
+
+    /// Observer recording the operations performed in one repository
+    ///
+    struct Recorder {
+        path: PathBuf,
+        operations: Mutex<Vec<Operation>>,
+    }
+
+    impl Observer for Recorder {
+        fn notify(&self, repo: &Repository, operation: &Operation) {
+            if repo.path() == self.path {
+                self.operations.lock().unwrap().push(operation.clone());
+            }
+        }
+    }
+
+    // Observer tests
+
+    #[test]
+    fn observed_operations() {
+        let mut testing_repo = TestingRepo::new("observed_operations");
+        let repo = testing_repo.repo();
+
+        let recorder = Arc::new(Recorder { path: repo.path().to_owned(), operations: Mutex::new(Vec::new()) });
+        let handle = register(recorder.clone());
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let leaf = format!("refs/dit/{}/leaves/{}", issue.id(), message.id());
+        {
+            let mut transaction = RefTransaction::new(repo).expect("Could not start transaction");
+            transaction.remove(&leaf).expect("Could not remove reference");
+            transaction.commit().expect("Could not commit transaction");
+        }
+
+        assert!(unregister(handle));
+        issue.update_head(message.id(), true).expect("Could not update head");
+
+        let head = format!("refs/dit/{}/head", issue.id());
+        assert_eq!(*recorder.operations.lock().unwrap(), vec![
+            Operation::HeadUpdated { issue: issue.id(), reference: head, target: issue.id() },
+            Operation::IssueCreated(issue.id()),
+            Operation::MessageAdded { issue: issue.id(), message: message.id() },
+            Operation::ReferenceDeleted(leaf),
+        ]);
+    }
+
+    #[test]
+    fn observed_staged_reply() {
+        let mut testing_repo = TestingRepo::new("observed_staged_reply");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = repo
+            .commit(None, &sig, &sig, "Test message 2", &empty_tree, &[&initial_message])
+            .expect("Could not create message");
+
+        let recorder = Arc::new(Recorder { path: repo.path().to_owned(), operations: Mutex::new(Vec::new()) });
+        let handle = register(recorder.clone());
+        {
+            let mut transaction = RefTransaction::new(repo).expect("Could not start transaction");
+            issue.stage_leaf(&mut transaction, message).expect("Could not stage leaf");
+            issue.stage_head(&mut transaction, message).expect("Could not stage head");
+            transaction.commit().expect("Could not commit transaction");
+        }
+        assert!(unregister(handle));
+
+        let head = format!("refs/dit/{}/head", issue.id());
+        assert_eq!(*recorder.operations.lock().unwrap(), vec![
+            Operation::MessageAdded { issue: issue.id(), message: message },
+            Operation::HeadUpdated { issue: issue.id(), reference: head, target: message },
+        ]);
+    }
+}
@@ -0,0 +1,522 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Spam filtering and quarantine
+//!
+//! Messages entering a repository, e.g. via a fetch or an import, may be
+//! screened by a `MessageFilter`. A filter may accept a message, flag it for
+//! inspection by a human or demand its quarantine.
+//!
+//! Quarantining a message is done by moving the references through which it
+//! entered the repository out of the `refs/dit/` namespace into
+//! `refs/dit-quarantine/`. Quarantined messages are thus neither listed nor
+//! pushed, but they may be released later on.
+//!
+
+use git2::{Commit, Oid, Reference, References, Repository};
+use regex::Regex;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use issue::{Issue, IssueRefType};
+use message::Message;
+use moderation::Moderation;
+use utils::reference_target;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Prefix of the namespace holding quarantined references
+///
+pub const QUARANTINE_PREFIX: &'static str = "refs/dit-quarantine";
+
+
+/// Verdict of a message filter
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The message is acceptable
+    Accept,
+    /// The message should be inspected, for the reason given
+    Flag(String),
+    /// The message should be quarantined, for the reason given
+    Quarantine(String),
+}
+
+impl Verdict {
+    /// Get the severity of the verdict
+    ///
+    fn severity(&self) -> u8 {
+        match self {
+            &Verdict::Accept        => 0,
+            &Verdict::Flag(_)       => 1,
+            &Verdict::Quarantine(_) => 2,
+        }
+    }
+
+    /// Combine two verdicts, retaining the more severe one
+    ///
+    /// If both verdicts are equally severe, the first one is retained.
+    ///
+    pub fn max(self, other: Verdict) -> Verdict {
+        if other.severity() > self.severity() { other } else { self }
+    }
+}
+
+
+/// Filter for screening messages
+///
+pub trait MessageFilter {
+    /// Check a single message
+    ///
+    fn check(&self, message: &Commit) -> Result<Verdict>;
+}
+
+impl MessageFilter for Moderation {
+    fn check(&self, message: &Commit) -> Result<Verdict> {
+        Ok(if self.is_hidden(message) {
+            Verdict::Quarantine("message is hidden by moderation".to_owned())
+        } else {
+            Verdict::Accept
+        })
+    }
+}
+
+
+/// Filter based on the content of messages
+///
+/// Messages matching any of the patterns registered are quarantined. Messages
+/// containing more links than allowed are flagged.
+///
+#[derive(Default)]
+pub struct ContentFilter {
+    patterns: Vec<Regex>,
+    max_links: Option<usize>,
+}
+
+impl ContentFilter {
+    /// Create a new content filter accepting all messages
+    ///
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Quarantine messages matching the pattern given
+    ///
+    pub fn with_pattern(mut self, pattern: Regex) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Flag messages containing more than the given number of links
+    ///
+    pub fn with_max_links(mut self, max_links: usize) -> Self {
+        self.max_links = Some(max_links);
+        self
+    }
+}
+
+impl MessageFilter for ContentFilter {
+    fn check(&self, message: &Commit) -> Result<Verdict> {
+        let text: Vec<String> = message.message_lines().collect();
+
+        for pattern in self.patterns.iter() {
+            if text.iter().any(|line| pattern.is_match(line)) {
+                return Ok(Verdict::Quarantine(format!("message matches '{}'", pattern)));
+            }
+        }
+
+        if let Some(max) = self.max_links {
+            let links = text
+                .iter()
+                .flat_map(|line| line.split_whitespace())
+                .filter(|word| word.contains("://"))
+                .count();
+            if links > max {
+                return Ok(Verdict::Flag(format!("message contains {} links", links)));
+            }
+        }
+
+        Ok(Verdict::Accept)
+    }
+}
+
+
+/// Filter delegating to an external command
+///
+/// The command is invoked with the id of the message as its only argument.
+/// The message itself is passed via standard input and the author's name and
+/// email address via the environment variables `DIT_AUTHOR_NAME` and
+/// `DIT_AUTHOR_EMAIL`. An exit status of `0` accepts the message, an exit
+/// status of `1` flags it and any other status demands a quarantine. The first
+/// line of the command's output is used as the reason.
+///
+pub struct CommandFilter {
+    program: String,
+}
+
+impl CommandFilter {
+    /// Create a new filter invoking the program given
+    ///
+    pub fn new<S>(program: S) -> Self
+        where S: Into<String>
+    {
+        CommandFilter { program: program.into() }
+    }
+}
+
+impl MessageFilter for CommandFilter {
+    fn check(&self, message: &Commit) -> Result<Verdict> {
+        let author = message.author();
+        let mut child = Command::new(&self.program)
+            .arg(message.id().to_string())
+            .env("DIT_AUTHOR_NAME", author.name().unwrap_or(""))
+            .env("DIT_AUTHOR_EMAIL", author.email().unwrap_or(""))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .chain_err(|| EK::FilterProgramError(self.program.clone()))?;
+
+        // The message is written from a separate thread since the program may
+        // not consume all of it before we read its output. A program may also
+        // decide without reading the message at all.
+        let mut stdin = child.stdin
+            .take()
+            .ok_or_else(|| Error::from_kind(EK::FilterProgramError(self.program.clone())))?;
+        let input = message.message_bytes().to_vec();
+        let writer = thread::spawn(move || match stdin.write_all(&input) {
+            Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        });
+
+        let output = child
+            .wait_with_output()
+            .chain_err(|| EK::FilterProgramError(self.program.clone()))?;
+        let written = writer.join().map(|result| result.is_ok()).unwrap_or(false);
+        if !written {
+            return Err(Error::from_kind(EK::FilterProgramError(self.program.clone())));
+        }
+        let reason = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map(String::from)
+            .unwrap_or_else(|| format!("rejected by '{}'", self.program));
+
+        match output.status.code() {
+            Some(0) => Ok(Verdict::Accept),
+            Some(1) => Ok(Verdict::Flag(reason)),
+            Some(_) => Ok(Verdict::Quarantine(reason)),
+            None => Err(Error::from_kind(EK::FilterProgramError(self.program.clone()))),
+        }
+    }
+}
+
+
+/// Chain of message filters
+///
+/// The verdict of a chain is the most severe verdict of its filters.
+///
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<MessageFilter>>,
+}
+
+impl FilterChain {
+    /// Create an empty chain, accepting all messages
+    ///
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Append a filter to the chain
+    ///
+    pub fn push<F>(&mut self, filter: F)
+        where F: MessageFilter + 'static
+    {
+        self.filters.push(Box::new(filter));
+    }
+
+    /// Check whether the chain contains any filters
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+}
+
+impl MessageFilter for FilterChain {
+    fn check(&self, message: &Commit) -> Result<Verdict> {
+        let mut retval = Verdict::Accept;
+        for filter in self.filters.iter() {
+            retval = retval.max(filter.check(message)?);
+        }
+        Ok(retval)
+    }
+}
+
+
+/// Screen the messages made reachable by an issue reference
+///
+/// All messages reachable from the reference but not from any of the `known`
+/// messages are checked using the filter given. The messages are not followed
+/// beyond the issue's initial message. The most severe verdict is returned.
+///
+pub fn screen_ref<F, I>(repo: &Repository, filter: &F, reference: &Reference, known: I) -> Result<Verdict>
+    where F: MessageFilter + ?Sized,
+          I: IntoIterator<Item = Oid>
+{
+    let issue = issue_of_ref(repo, reference)?;
+    let mut messages = issue.terminated_messages()?;
+    messages.revwalk.push(reference_target(reference)?)?;
+    for id in known {
+        // Known messages might not be part of the issue or might not even be
+        // present in the repository.
+        messages.revwalk.hide(id).ok();
+    }
+
+    let mut retval = Verdict::Accept;
+    for message in messages {
+        retval = retval.max(filter.check(&message?)?);
+    }
+    Ok(retval)
+}
+
+
+/// Move an issue reference into quarantine
+///
+/// The reference is replaced by a reference in the quarantine namespace, which
+/// is returned. References of remote issues are quarantined as if they were
+/// local references.
+///
+pub fn quarantine_ref<'r>(repo: &'r Repository, reference: &mut Reference<'r>) -> Result<Reference<'r>> {
+    let name = quarantine_name(reference)?;
+    let target = reference_target(reference)?;
+    let reflogmsg = format!("git-dit: quarantine {}", name);
+
+    let retval = repo
+        .reference(&name, target, true, &reflogmsg)
+        .chain_err(|| EK::CannotSetReference(name.clone()))?;
+    let refname = reference.name().unwrap_or("").to_owned();
+    reference.delete().chain_err(|| EK::CannotDeleteReference(refname))?;
+    Ok(retval)
+}
+
+
+/// Release a reference from quarantine
+///
+/// The quarantined reference is replaced by a local issue reference, which is
+/// returned. Existing references will not be overwritten.
+///
+pub fn release_ref<'r>(repo: &'r Repository, reference: &mut Reference<'r>) -> Result<Reference<'r>> {
+    let refname = reference
+        .name()
+        .ok_or_else(|| Error::from_kind(EK::ReferenceNameError(String::from_utf8_lossy(reference.name_bytes()).into_owned())))?
+        .to_owned();
+    let name = refname
+        .splitn(2, &format!("{}/", QUARANTINE_PREFIX))
+        .nth(1)
+        .map(|part| format!("refs/dit/{}", part))
+        .ok_or_else(|| Error::from_kind(EK::MalFormedHeadReference(refname.clone())))?;
+    let target = reference_target(reference)?;
+    let reflogmsg = format!("git-dit: release {} from quarantine", name);
+
+    let retval = repo
+        .reference(&name, target, false, &reflogmsg)
+        .chain_err(|| EK::CannotSetReference(name.clone()))?;
+    reference.delete().chain_err(|| EK::CannotDeleteReference(refname))?;
+    Ok(retval)
+}
+
+
+/// Get all quarantined references
+///
+pub fn quarantined_refs(repo: &Repository) -> Result<References> {
+    let glob = format!("{}/**", QUARANTINE_PREFIX);
+    repo.references_glob(&glob)
+        .chain_err(|| EK::CannotGetReferences(glob))
+}
+
+
+/// Get the issue associated with an issue reference
+///
+fn issue_of_ref<'r>(repo: &'r Repository, reference: &Reference) -> Result<Issue<'r>> {
+    let name = reference
+        .name()
+        .ok_or_else(|| Error::from_kind(EK::ReferenceNameError(String::from_utf8_lossy(reference.name_bytes()).into_owned())))?;
+    let (id, _) = IssueRefType::of_ref(name)
+        .ok_or_else(|| Error::from_kind(EK::MalFormedHeadReference(name.to_owned())))?;
+    let component = IssueRefType::component_of_ref(name, id);
+    Issue::new_in_component(repo, id, component)
+}
+
+
+/// Get the name of the quarantined version of an issue reference
+///
+fn quarantine_name(reference: &Reference) -> Result<String> {
+    let name = reference
+        .name()
+        .ok_or_else(|| Error::from_kind(EK::ReferenceNameError(String::from_utf8_lossy(reference.name_bytes()).into_owned())))?;
+    let (id, _) = IssueRefType::of_ref(name)
+        .ok_or_else(|| Error::from_kind(EK::MalFormedHeadReference(name.to_owned())))?;
+
+    // The part of the name starting with the issue's id, e.g. "<id>/head"
+    let tail = name
+        .rfind(&format!("/{}/", id))
+        .map(|pos| &name[pos + 1..])
+        .ok_or_else(|| Error::from_kind(EK::MalFormedHeadReference(name.to_owned())))?;
+
+    Ok(match IssueRefType::component_of_ref(name, id) {
+        Some(component) => format!("{}/{}/{}", QUARANTINE_PREFIX, component, tail),
+        None => format!("{}/{}", QUARANTINE_PREFIX, tail),
+    })
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repository::RepositoryExt;
+    use test_utils::TestingRepo;
+
+    // Verdict tests
+
+    #[test]
+    fn verdict_max() {
+        let flag = Verdict::Flag("foo".to_owned());
+        let quarantine = Verdict::Quarantine("bar".to_owned());
+        assert_eq!(Verdict::Accept.max(flag.clone()), flag);
+        assert_eq!(flag.clone().max(quarantine.clone()), quarantine);
+        assert_eq!(quarantine.clone().max(Verdict::Accept), quarantine);
+    }
+
+    // Filter tests
+
+    #[test]
+    fn content_filter() {
+        let mut testing_repo = TestingRepo::new("content_filter");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Cheap pills\n\nhttp://a http://b", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+
+        let filter = ContentFilter::new();
+        assert_eq!(filter.check(&message).expect("Could not check message"), Verdict::Accept);
+
+        let filter = ContentFilter::new().with_max_links(1);
+        assert_eq!(filter.check(&message).expect("Could not check message").severity(), 1);
+
+        let mut chain = FilterChain::new();
+        chain.push(ContentFilter::new().with_max_links(1));
+        chain.push(ContentFilter::new().with_pattern(Regex::new("(?i)pills").unwrap()));
+        assert_eq!(chain.check(&message).expect("Could not check message").severity(), 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn command_filter() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut testing_repo = TestingRepo::new("command_filter");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        // a message exceeding the capacity of a pipe, echoed by the program
+        let text = format!("Long message\n\n{}", "Lorem ipsum dolor sit amet\n".repeat(10000));
+        let issue = repo
+            .create_issue(&sig, &sig, text, &empty_tree, vec![])
+            .expect("Could not create issue");
+        let message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+
+        let program = repo.path().join("echo-filter");
+        fs::write(&program, "#!/bin/sh\nexec cat\n")
+            .and_then(|_| fs::set_permissions(&program, fs::Permissions::from_mode(0o755)))
+            .expect("Could not write filter program");
+        let filter = CommandFilter::new(program.to_string_lossy());
+        assert_eq!(filter.check(&message).expect("Could not check message"), Verdict::Accept);
+
+        let filter = CommandFilter::new("false");
+        assert_eq!(filter.check(&message).expect("Could not check message").severity(), 1);
+    }
+
+    // Quarantine tests
+
+    #[test]
+    fn quarantine_and_release() {
+        let mut testing_repo = TestingRepo::new("quarantine_and_release");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let spammer = git2::Signature::now("Spam", "spam@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&spammer, &spammer, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let mut moderation = Moderation::new();
+        moderation.block_author("spam@example.com");
+
+        let mut leaf = issue
+            .local_refs(IssueRefType::Leaf)
+            .expect("Could not get leaves")
+            .next()
+            .expect("No leaf found")
+            .expect("Could not get leaf");
+        let verdict = screen_ref(repo, &moderation, &leaf, vec![])
+            .expect("Could not screen reference");
+        assert_eq!(verdict.severity(), 2);
+
+        let head = issue.local_head().expect("Could not get head");
+        let verdict = screen_ref(repo, &moderation, &head, vec![])
+            .expect("Could not screen reference");
+        assert_eq!(verdict, Verdict::Accept);
+
+        let mut quarantined = quarantine_ref(repo, &mut leaf)
+            .expect("Could not quarantine reference");
+        assert_eq!(quarantined.name(), Some(format!("refs/dit-quarantine/{}/leaves/{}", issue.id(), message.id()).as_str()));
+        assert_eq!(issue.local_refs(IssueRefType::Leaf).expect("Could not get leaves").count(), 0);
+        assert_eq!(quarantined_refs(repo).expect("Could not get quarantined refs").count(), 1);
+        assert!(repo.issues().expect("Could not get issues").contains(&issue));
+
+        release_ref(repo, &mut quarantined).expect("Could not release reference");
+        assert_eq!(issue.local_refs(IssueRefType::Leaf).expect("Could not get leaves").count(), 1);
+        assert_eq!(quarantined_refs(repo).expect("Could not get quarantined refs").count(), 0);
+    }
+}
@@ -9,10 +9,17 @@
 
 //! Module providing extension trait for remotes
 //!
+//! Apart from the extension trait, this module provides facilities for
+//! configuring remotes for git-dit and for summarizing changes to the
+//! references of a remote, e.g. after fetching or pushing issues.
+//!
 
-use git2::Remote;
+use git2::{Oid, Remote, Repository};
+use std::collections::{BTreeMap, HashMap};
 
-use issue::Issue;
+use error::*;
+use error::ErrorKind as EK;
+use issue::{Issue, IssueRefType};
 
 
 /// Extension trait for remotes
@@ -29,6 +36,10 @@ pub trait RemoteExt {
     /// A refspec will only be returned if the remote has a (valid) name.
     ///
     fn all_issues_refspec(&self) -> Option<String>;
+
+    /// Check whether the remote is configured to fetch all issues
+    ///
+    fn fetches_issues(&self) -> bool;
 }
 
 impl<'r> RemoteExt for Remote<'r> {
@@ -41,5 +52,222 @@ impl<'r> RemoteExt for Remote<'r> {
         self.name()
             .map(|name| format!("+refs/dit/*:refs/remotes/{0}/dit/*", name))
     }
+
+    fn fetches_issues(&self) -> bool {
+        let refspec = match self.all_issues_refspec() {
+            Some(refspec) => refspec,
+            None => return false,
+        };
+        self.fetch_refspecs()
+            .map(|refspecs| refspecs.iter().any(|r| r == Some(refspec.as_str())))
+            .unwrap_or(false)
+    }
+}
+
+
+/// Configure a remote for fetching issues
+///
+/// Adds the refspec for fetching all issues to the remote's configuration,
+/// unless it is already present. Hence, issues will also be fetched by a plain
+/// `git fetch`. Returns whether the configuration was changed.
+///
+/// Push refspecs are not configured, since they would replace the default
+/// behaviour of `git push`.
+///
+pub fn configure_remote(repo: &Repository, name: &str) -> Result<bool> {
+    let remote = repo.find_remote(name)?;
+    if remote.fetches_issues() {
+        return Ok(false);
+    }
+
+    let refspec = remote
+        .all_issues_refspec()
+        .ok_or_else(|| Error::from_kind(EK::UnknownRepository(name.to_owned())))?;
+    repo.remote_add_fetch(name, &refspec)?;
+    Ok(true)
+}
+
+
+/// Snapshot of a remote's issue references
+///
+/// A snapshot records the targets of all references of a remote associated
+/// with issues, e.g. those under `refs/remotes/<remote>/dit/`. Two snapshots
+/// may be compared in order to find out which issues changed in between.
+///
+pub struct RefSnapshot(HashMap<String, Oid>);
+
+impl RefSnapshot {
+    /// Take a snapshot of a remote's issue references
+    ///
+    pub fn of_remote(repo: &Repository, remote: &str) -> Result<Self> {
+        let glob = format!("refs/remotes/{}/dit/**", remote);
+        let mut targets = HashMap::new();
+        for reference in repo.references_glob(&glob).chain_err(|| EK::CannotGetReferences(glob.clone()))? {
+            let reference = reference?;
+            if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+                targets.insert(name.to_owned(), target);
+            }
+        }
+        Ok(RefSnapshot(targets))
+    }
+
+    /// Get the target of a reference at the time of the snapshot
+    ///
+    pub fn target(&self, name: &str) -> Option<Oid> {
+        self.0.get(name).cloned()
+    }
+
+    /// Compute the changes to issues between this and a later snapshot
+    ///
+    /// The changes are returned ordered by issue id. Issues without any changes
+    /// are omitted.
+    ///
+    pub fn changes(&self, later: &RefSnapshot) -> Vec<IssueChanges> {
+        let created_or_updated = later.0
+            .iter()
+            .filter_map(|(name, target)| match self.0.get(name) {
+                None => Some((name, RefChange::Created)),
+                Some(previous) if previous != target => Some((name, RefChange::Updated)),
+                _ => None,
+            });
+        let deleted = self.0
+            .keys()
+            .filter(|name| !later.0.contains_key(*name))
+            .map(|name| (name, RefChange::Deleted));
+
+        let mut changes: BTreeMap<Oid, IssueChanges> = BTreeMap::new();
+        for (name, change) in created_or_updated.chain(deleted) {
+            let id = match IssueRefType::of_ref(name) {
+                Some((id, _)) => id,
+                None => continue,
+            };
+            let entry = changes.entry(id).or_insert_with(|| IssueChanges::new(id));
+            match change {
+                RefChange::Created => entry.created += 1,
+                RefChange::Updated => entry.updated += 1,
+                RefChange::Deleted => entry.deleted += 1,
+            }
+        }
+
+        // find out which issues were not known before
+        let known: Vec<Oid> = self.0
+            .keys()
+            .filter_map(|name| IssueRefType::of_ref(name).map(|(id, _)| id))
+            .collect();
+        changes
+            .into_iter()
+            .map(|(id, mut change)| {
+                change.new_issue = !known.contains(&id);
+                change
+            })
+            .collect()
+    }
+}
+
+
+/// Kind of change to a single reference
+///
+enum RefChange {
+    Created,
+    Updated,
+    Deleted,
+}
+
+
+/// Summary of changes to the references of a single issue
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct IssueChanges {
+    /// Id of the issue
+    pub issue: Oid,
+    /// Whether the issue was not known before
+    pub new_issue: bool,
+    /// Number of references created
+    pub created: usize,
+    /// Number of references updated
+    pub updated: usize,
+    /// Number of references deleted
+    pub deleted: usize,
+}
+
+impl IssueChanges {
+    fn new(issue: Oid) -> Self {
+        IssueChanges { issue: issue, new_issue: false, created: 0, updated: 0, deleted: 0 }
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repository::RepositoryExt;
+    use test_utils::TestingRepo;
+
+    use git2;
+
+    // configure_remote tests
+
+    #[test]
+    fn configure_remote_refspecs() {
+        let mut testing_repo = TestingRepo::new("configure_remote_refspecs");
+        let repo = testing_repo.repo();
+
+        repo.remote("origin", "https://example.com/foo.git")
+            .expect("Could not create remote");
+        assert!(configure_remote(repo, "origin").expect("Could not configure remote"));
+        assert!(!configure_remote(repo, "origin").expect("Could not configure remote"));
+        assert!(repo
+            .find_remote("origin")
+            .expect("Could not find remote")
+            .fetches_issues());
+    }
+
+    // RefSnapshot tests
+
+    #[test]
+    fn snapshot_changes() {
+        let mut testing_repo = TestingRepo::new("snapshot_changes");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue2 = repo
+            .create_issue(&sig, &sig, "Test message 2", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let message = issue1
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&issue1.initial_message().unwrap()])
+            .expect("Could not add message");
+
+        let head1 = format!("refs/remotes/origin/dit/{}/head", issue1.id());
+        repo.reference(&head1, issue1.id(), false, "test")
+            .expect("Could not create reference");
+        let before = RefSnapshot::of_remote(repo, "origin")
+            .expect("Could not take snapshot");
+        assert_eq!(before.target(&head1), Some(issue1.id()));
+
+        repo.reference(&head1, message.id(), true, "test")
+            .expect("Could not update reference");
+        repo.reference(&format!("refs/remotes/origin/dit/{}/head", issue2.id()), issue2.id(), false, "test")
+            .expect("Could not create reference");
+        let after = RefSnapshot::of_remote(repo, "origin")
+            .expect("Could not take snapshot");
+
+        let mut expected = vec![
+            IssueChanges { issue: issue1.id(), new_issue: false, created: 0, updated: 1, deleted: 0 },
+            IssueChanges { issue: issue2.id(), new_issue: true, created: 1, updated: 0, deleted: 0 },
+        ];
+        expected.sort_by_key(|change| change.issue);
+        assert_eq!(before.changes(&after), expected);
+        assert_eq!(after.changes(&after), vec![]);
+    }
 }
 
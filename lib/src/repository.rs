@@ -16,9 +16,17 @@
 use git2::{self, Commit, Oid, Tree};
 use std::collections::HashSet;
 
+use config::DitConfig;
 use gc;
-use issue::Issue;
+use issue::{check_component, Issue, IssueRefType};
 use iter;
+use message::LineIteratorExt;
+use message_index::MessageIndex;
+use moderation::{Moderation, MODERATION_REF};
+use observer::{self, Operation};
+use search;
+#[cfg(feature = "search-index")]
+use search_index;
 use utils::ResultIterExt;
 
 use error::*;
@@ -30,6 +38,14 @@ use error::ErrorKind as EK;
 pub type UniqueIssues<'a> = HashSet<Issue<'a>>;
 
 
+/// Name of the symbolic reference recording the current issue
+///
+/// The reference refers to the local head reference of the current issue. It
+/// resides outside of `refs/` in order to prevent it from being pushed.
+///
+pub const CURRENT_ISSUE_REF: &'static str = "DIT_CURRENT";
+
+
 /// Extension trait for Repositories
 ///
 /// This trait is intended as an extension for repositories. It introduces
@@ -51,7 +67,10 @@ pub trait RepositoryExt {
 
     /// Find the issue with a given message in it
     ///
-    /// Returns the issue containing the message provided
+    /// Returns the issue containing the message provided. The issue is looked
+    /// up via the `MessageIndex`, which is updated and written as needed.
+    /// When looking up many messages, open the index once via
+    /// `MessageIndex::updated()` and use its `issue_with_message()` instead.
     ///
     fn issue_with_message<'a>(&'a self, message: &Commit<'a>) -> Result<Issue>;
 
@@ -63,14 +82,34 @@ pub trait RepositoryExt {
     ///
     fn issues_with_prefix(&self, prefix: &str) -> Result<UniqueIssues>;
 
+    /// Get issue hashes for a prefix and component
+    ///
+    /// This function returns all issues known to the DIT repo under the prefix
+    /// provided which reside in the given component (or one of its
+    /// sub-components), e.g. all issues for which refs exist under
+    /// `<prefix>/dit/<component>/`.
+    ///
+    fn issues_in_component(&self, prefix: &str, component: &str) -> Result<UniqueIssues>;
+
     /// Get all issue hashes
     ///
     /// This function returns all known issues known to the DIT repo.
     ///
     fn issues(&self) -> Result<UniqueIssues>;
 
+    /// Get all issue hashes, reporting failures for individual issues
+    ///
+    /// Like `issues()`, but issues which cannot be retrieved, e.g. because of
+    /// a malformed head reference, are reported as failures alongside the
+    /// remaining issues.
+    ///
+    fn issues_partial(&self) -> Result<BulkResult<UniqueIssues>>;
+
     /// Create a new issue with an initial message
     ///
+    /// Like `create_issue_in_component()`, the initial message must contain
+    /// the trailers required via `dit.new.required`.
+    ///
     fn create_issue<'a, A, I, J>(&self,
              author: &git2::Signature,
              committer: &git2::Signature,
@@ -82,6 +121,44 @@ pub trait RepositoryExt {
               I: IntoIterator<Item = &'a Commit<'a>, IntoIter = J>,
               J: Iterator<Item = &'a Commit<'a>>;
 
+    /// Create a new issue with an initial message in a component
+    ///
+    /// If a component is supplied, the references of the issue will be placed
+    /// in the component's namespace, e.g. under `refs/dit/<component>/`.
+    ///
+    /// The initial message must contain the trailers required via
+    /// `dit.new.required`. Otherwise, no issue is created.
+    ///
+    fn create_issue_in_component<'a, A, I, J>(&self,
+             component: Option<&str>,
+             author: &git2::Signature,
+             committer: &git2::Signature,
+             message: A,
+             tree: &Tree,
+             parents: I
+    ) -> Result<Issue>
+        where A: AsRef<str>,
+              I: IntoIterator<Item = &'a Commit<'a>, IntoIter = J>,
+              J: Iterator<Item = &'a Commit<'a>>;
+
+    /// Import an issue with an initial message in a component
+    ///
+    /// Like `create_issue_in_component()`, but the trailers required via
+    /// `dit.new.required` are not enforced. This is meant for importing issues
+    /// which were not created interactively, e.g. from other issue trackers.
+    ///
+    fn import_issue_in_component<'a, A, I, J>(&self,
+             component: Option<&str>,
+             author: &git2::Signature,
+             committer: &git2::Signature,
+             message: A,
+             tree: &Tree,
+             parents: I
+    ) -> Result<Issue>
+        where A: AsRef<str>,
+              I: IntoIterator<Item = &'a Commit<'a>, IntoIter = J>,
+              J: Iterator<Item = &'a Commit<'a>>;
+
     /// Get an revwalk configured as a first parent iterator
     ///
     /// This is a convenience function. It returns an iterator over messages in
@@ -105,19 +182,81 @@ pub trait RepositoryExt {
     /// This function returns an empty tree.
     ///
     fn empty_tree(&self) -> Result<Tree>;
+
+    /// Get the moderation data of the repository
+    ///
+    /// The data is read from the local moderation reference. If there is no
+    /// such reference, an empty set of moderation data is returned.
+    ///
+    fn moderation(&self) -> Result<Moderation>;
+
+    /// Update the moderation data of the repository
+    ///
+    /// A new commit holding the moderation data is created on top of the
+    /// current moderation reference, which is then updated.
+    ///
+    fn update_moderation(&self,
+                         moderation: &Moderation,
+                         author: &git2::Signature,
+                         committer: &git2::Signature,
+                         message: &str,
+    ) -> Result<Oid>;
+
+    /// Get the current issue
+    ///
+    /// Returns the issue recorded via `CURRENT_ISSUE_REF` or `None` if no
+    /// issue is recorded.
+    ///
+    fn current_issue(&self) -> Result<Option<Issue>>;
+
+    /// Set the current issue
+    ///
+    /// Records the issue supplied as the current issue or clears the record if
+    /// `None` is supplied.
+    ///
+    fn set_current_issue(&self, issue: Option<&Issue>) -> Result<()>;
+
+    /// Get the author of a message
+    ///
+    /// The author is resolved via the repository's mailmap, i.e. the canonical
+    /// name and email address of the author are returned.
+    ///
+    fn author_of(&self, message: &Commit) -> Result<git2::Signature<'static>>;
+
+    /// Search the messages of all issues
+    ///
+    /// Returns a hit for each issue containing a match, ordered by the ids of
+    /// the issues. See `search::search_issue()` for searching individual
+    /// issues or only some of their messages.
+    ///
+    /// If the library is built with the "search-index" feature and the
+    /// repository has a search index, only the messages which are candidates
+    /// according to the index are searched. See `search_index` for details.
+    ///
+    fn search(&self, query: &search::Query) -> Result<Vec<search::IssueHit>>;
 }
 
 impl RepositoryExt for git2::Repository {
     fn find_issue(&self, id: Oid) -> Result<Issue> {
-        let retval = Issue::new(self, id)?;
+        // the common case of a local issue outside any component doesn't
+        // require enumerating references
+        if self.find_reference(&format!("refs/dit/{}/head", id)).is_ok() {
+            return Issue::new(self, id);
+        }
 
         // make sure the id refers to an issue by checking whether an associated
-        // head reference exists
-        if retval.heads()?.next().is_some() {
-            Ok(retval)
-        } else {
-            Err(Error::from_kind(EK::CannotFindIssueHead(id)))
+        // head reference exists, possibly in some component's namespace
+        let pattern = format!("**{}/head", id);
+        for reference in iter::DitRefs::new(self, &pattern).chain_err(|| EK::CannotFindIssueHead(id))? {
+            let reference = reference.chain_err(|| EK::CannotFindIssueHead(id))?;
+            if let Some(name) = reference.name() {
+                if is_head_of(name, id) {
+                    return self.issue_by_head_ref(&reference);
+                }
+            }
         }
+
+        Err(Error::from_kind(EK::CannotFindIssueHead(id)))
     }
 
     fn issue_by_head_ref(&self, head_ref: &git2::Reference) -> Result<Issue> {
@@ -136,36 +275,54 @@ impl RepositoryExt for git2::Repository {
                Oid::from_str(hash)
                    .chain_err(|| EK::OidFormatError(hash.to_string()))
             })
-            .and_then(|id| Issue::new(self, id))
+            .and_then(|id| {
+                let component = name.and_then(|name| IssueRefType::component_of_ref(name, id));
+                Issue::new_in_component(self, id, component)
+            })
     }
 
     fn issue_with_message<'a>(&'a self, message: &Commit<'a>) -> Result<Issue> {
-        // follow the chain of first parents towards an initial message for
-        // which a head exists
-        for id in self.first_parent_messages(message.id())?.revwalk {
-            let issue = self.find_issue(id?);
-            if issue.is_ok() {
-                return issue
-            }
-        }
-
-        Err(Error::from_kind(EK::NoTreeInitFound(message.id())))
+        MessageIndex::updated(self).issue_with_message(self, message)
     }
 
     fn issues_with_prefix(&self, prefix: &str) -> Result<UniqueIssues> {
+        trace_span!("issues_with_prefix", prefix);
         let glob = format!("{}/dit/**/head", prefix);
-        self.references_glob(&glob)
+        let issues: Result<UniqueIssues> = self.references_glob(&glob)
             .chain_err(|| EK::CannotGetReferences(glob))
             .map(|refs| iter::HeadRefsToIssuesIter::new(self, refs))?
-            .collect_result()
+            .collect_result();
+        trace_event!(issues = issues.as_ref().map(HashSet::len).unwrap_or(0), "found issues");
+        issues
+    }
+
+    fn issues_in_component(&self, prefix: &str, component: &str) -> Result<UniqueIssues> {
+        trace_span!("issues_in_component", prefix, component);
+        check_component(component)?;
+        let glob = format!("{}/dit/{}/**/head", prefix, component);
+        let issues: Result<UniqueIssues> = self.references_glob(&glob)
+            .chain_err(|| EK::CannotGetReferences(glob))
+            .map(|refs| iter::HeadRefsToIssuesIter::new(self, refs))?
+            .collect_result();
+        trace_event!(issues = issues.as_ref().map(HashSet::len).unwrap_or(0), "found issues");
+        issues
     }
 
     fn issues(&self) -> Result<UniqueIssues> {
-        let glob = "**/dit/**/head";
-        self.references_glob(glob)
-            .chain_err(|| EK::CannotGetReferences(glob.to_owned()))
+        trace_span!("issues");
+        let issues: Result<UniqueIssues> = iter::DitRefs::new(self, "**/head")
             .map(|refs| iter::HeadRefsToIssuesIter::new(self, refs))?
-            .collect_result()
+            .collect_result();
+        trace_event!(issues = issues.as_ref().map(HashSet::len).unwrap_or(0), "found issues");
+        issues
+    }
+
+    fn issues_partial(&self) -> Result<BulkResult<UniqueIssues>> {
+        trace_span!("issues_partial");
+        let found: BulkResult<UniqueIssues> = iter::DitRefs::new(self, "**/head")
+            .map(|refs| iter::HeadRefsToIssuesIter::new(self, refs).collect())?;
+        trace_event!(issues = found.value.len(), failures = found.failures.len(), "found issues");
+        Ok(found)
     }
 
     fn create_issue<'a, A, I, J>(&self,
@@ -179,13 +336,52 @@ impl RepositoryExt for git2::Repository {
               I: IntoIterator<Item = &'a Commit<'a>, IntoIter = J>,
               J: Iterator<Item = &'a Commit<'a>>
     {
+        self.create_issue_in_component(None, author, committer, message, tree, parents)
+    }
+
+    fn create_issue_in_component<'a, A, I, J>(&self,
+             component: Option<&str>,
+             author: &git2::Signature,
+             committer: &git2::Signature,
+             message: A,
+             tree: &Tree,
+             parents: I
+    ) -> Result<Issue>
+        where A: AsRef<str>,
+              I: IntoIterator<Item = &'a Commit<'a>, IntoIter = J>,
+              J: Iterator<Item = &'a Commit<'a>>
+    {
+        message
+            .as_ref()
+            .lines()
+            .check_required_trailers(&DitConfig::open(self)?.required_trailers()?)?;
+        self.import_issue_in_component(component, author, committer, message, tree, parents)
+    }
+
+    fn import_issue_in_component<'a, A, I, J>(&self,
+             component: Option<&str>,
+             author: &git2::Signature,
+             committer: &git2::Signature,
+             message: A,
+             tree: &Tree,
+             parents: I
+    ) -> Result<Issue>
+        where A: AsRef<str>,
+              I: IntoIterator<Item = &'a Commit<'a>, IntoIter = J>,
+              J: Iterator<Item = &'a Commit<'a>>
+    {
+        if let Some(c) = component {
+            check_component(c)?;
+        }
+
         let parent_vec : Vec<&Commit> = parents.into_iter().collect();
 
         self.commit(None, author, committer, message.as_ref(), tree, &parent_vec)
             .chain_err(|| EK::CannotCreateMessage)
-            .and_then(|id| Issue::new(self, id))
+            .and_then(|id| Issue::new_in_component(self, id, component))
             .and_then(|issue| {
                 issue.update_head(issue.id(), true)?;
+                observer::notify(self, &Operation::IssueCreated(issue.id()));
                 Ok(issue)
             })
     }
@@ -194,11 +390,11 @@ impl RepositoryExt for git2::Repository {
         iter::Messages::empty(self)
             .and_then(|mut messages| {
                 messages.revwalk.push(id)?;
-                messages.revwalk.simplify_first_parent();
-                messages.revwalk.set_sorting(git2::Sort::TOPOLOGICAL);
+                messages.revwalk.simplify_first_parent()?;
+                messages.revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
                 Ok(messages)
             })
-            .chain_err(|| EK::CannotGetCommitForRev(id.to_string()))
+            .chain_err(|| EK::CannotGetCommit(id))
     }
 
     fn collectable_refs<'a>(&'a self) -> gc::CollectableRefs<'a> {
@@ -215,6 +411,128 @@ impl RepositoryExt for git2::Repository {
             .and_then(|oid| self.find_tree(oid))
             .chain_err(|| EK::CannotBuildTree)
     }
+
+    fn moderation(&self) -> Result<Moderation> {
+        match self.find_reference(MODERATION_REF) {
+            Ok(reference) => reference
+                .peel(git2::ObjectType::Tree)
+                .chain_err(|| EK::CannotGetReference(MODERATION_REF.to_owned()))
+                .and_then(|tree| {
+                    let tree = tree
+                        .into_tree()
+                        .map_err(|_| Error::from_kind(EK::CannotGetReference(MODERATION_REF.to_owned())))?;
+                    Moderation::from_tree(self, &tree)
+                }),
+            Err(ref err) if err.code() == git2::ErrorCode::NotFound => Ok(Moderation::new()),
+            Err(err) => Err(err).chain_err(|| EK::CannotGetReference(MODERATION_REF.to_owned())),
+        }
+    }
+
+    fn update_moderation(&self,
+                         moderation: &Moderation,
+                         author: &git2::Signature,
+                         committer: &git2::Signature,
+                         message: &str,
+    ) -> Result<Oid> {
+        let tree = self.find_tree(moderation.write_tree(self)?)?;
+        let parent = match self.find_reference(MODERATION_REF) {
+            Ok(reference) => Some(reference
+                .peel(git2::ObjectType::Commit)
+                .chain_err(|| EK::CannotGetReference(MODERATION_REF.to_owned()))?
+                .into_commit()
+                .map_err(|_| Error::from_kind(EK::CannotGetReference(MODERATION_REF.to_owned())))?),
+            Err(_) => None,
+        };
+
+        self.commit(Some(MODERATION_REF), author, committer, message, &tree, &parent.iter().collect::<Vec<_>>())
+            .chain_err(|| EK::CannotSetReference(MODERATION_REF.to_owned()))
+    }
+
+    fn current_issue(&self) -> Result<Option<Issue>> {
+        let reference = match self.find_reference(CURRENT_ISSUE_REF) {
+            Ok(reference) => reference,
+            Err(ref err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err).chain_err(|| EK::CannotGetReference(CURRENT_ISSUE_REF.to_owned())),
+        };
+
+        // The reference may refer to a head which does not exist (yet), e.g.
+        // for issues only known from a remote. Hence we don't resolve it.
+        let target = reference.symbolic_target().unwrap_or("");
+        match IssueRefType::of_ref(target) {
+            Some((id, IssueRefType::Head)) => {
+                let component = IssueRefType::component_of_ref(target, id);
+                Issue::new_in_component(self, id, component).map(Some)
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn set_current_issue(&self, issue: Option<&Issue>) -> Result<()> {
+        match issue {
+            Some(issue) => {
+                let target = format!("refs/dit/{}/head", issue.ref_part());
+                let reflogmsg = format!("git-dit: set current issue to {}", issue);
+                self.reference_symbolic(CURRENT_ISSUE_REF, &target, true, &reflogmsg)
+                    .chain_err(|| EK::CannotSetReference(CURRENT_ISSUE_REF.to_owned()))
+                    .map(|_| ())
+            },
+            None => match self.find_reference(CURRENT_ISSUE_REF) {
+                Ok(mut reference) => reference
+                    .delete()
+                    .chain_err(|| EK::CannotDeleteReference(CURRENT_ISSUE_REF.to_owned())),
+                Err(ref err) if err.code() == git2::ErrorCode::NotFound => Ok(()),
+                Err(err) => Err(err).chain_err(|| EK::CannotGetReference(CURRENT_ISSUE_REF.to_owned())),
+            },
+        }
+    }
+
+    fn author_of(&self, message: &Commit) -> Result<git2::Signature<'static>> {
+        self.mailmap()
+            .and_then(|mailmap| mailmap.resolve_signature(&message.author()))
+            .map_err(Error::from)
+    }
+
+    fn search(&self, query: &search::Query) -> Result<Vec<search::IssueHit>> {
+        let mut issues: Vec<Issue> = self.issues()?.into_iter().collect();
+        issues.sort_by_key(Issue::id);
+
+        let candidates = search_candidates(self, query)?;
+        let mut retval = Vec::new();
+        for issue in issues {
+            let hit = match candidates {
+                Some(ref candidates) => match candidates.get(&issue.id()) {
+                    Some(messages) => search::search_issue(issue, query, |message| messages.contains(&message.id()))?,
+                    None => continue,
+                },
+                None => search::search_issue(issue, query, |_| true)?,
+            };
+            retval.extend(hit);
+        }
+        Ok(retval)
+    }
+}
+
+
+/// Get the candidates for matches of a query from the search index, if any
+///
+#[cfg(feature = "search-index")]
+fn search_candidates(repo: &git2::Repository, query: &search::Query) -> Result<Option<search::Candidates>> {
+    search_index::SearchIndex::lookup(repo, query)
+}
+
+#[cfg(not(feature = "search-index"))]
+fn search_candidates(_: &git2::Repository, _: &search::Query) -> Result<Option<search::Candidates>> {
+    Ok(None)
+}
+
+
+/// Check whether a reference is a head reference of the issue given
+///
+fn is_head_of(refname: &str, id: Oid) -> bool {
+    match IssueRefType::of_ref(refname) {
+        Some((ref_id, IssueRefType::Head)) => ref_id == id,
+        _ => false,
+    }
 }
 
 
@@ -294,6 +612,51 @@ mod tests {
         assert_eq!(retrieved_issue.id(), issue.id());
     }
 
+    #[test]
+    fn required_trailers() {
+        let mut testing_repo = TestingRepo::new("required_trailers");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        repo.config()
+            .and_then(|mut config| config.set_multivar("dit.new.required", "^$", "Severity"))
+            .expect("Could not configure repository");
+
+        match repo.create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![]) {
+            Err(err) => assert_eq!(err.kind(), Some(&EK::MissingTrailers(vec!["Severity".to_owned()]))),
+            Ok(_) => panic!("Created issue without required trailer"),
+        }
+        assert!(repo.issues().expect("Could not retrieve issues").is_empty());
+
+        repo.create_issue(&sig, &sig, "Test message 1\n\nSeverity: low", &empty_tree, vec![])
+            .expect("Could not create issue");
+    }
+
+    #[test]
+    fn import_without_required_trailers() {
+        let mut testing_repo = TestingRepo::new("import_without_required_trailers");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        repo.config()
+            .and_then(|mut config| config.set_multivar("dit.new.required", "^$", "Severity"))
+            .expect("Could not configure repository");
+
+        let issue = repo
+            .import_issue_in_component(Some("backend"), &sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not import issue");
+        assert_eq!(issue.component(), Some("backend"));
+        assert!(repo.issues().expect("Could not retrieve issues").contains(&issue));
+    }
+
     #[test]
     fn issues() {
         let mut testing_repo = TestingRepo::new("issues");
@@ -317,6 +680,52 @@ mod tests {
             .expect("Could not retrieve issue");
         assert_eq!(retrieved_issue.id(), issue.id());
         assert!(issues.next().is_none());
+
+        // a head reference of an issue which doesn't exist
+        let bogus = "refs/dit/0123456789abcdef0123456789abcdef01234567/head";
+        repo.reference(bogus, issue.id(), false, "test").expect("Could not create reference");
+        assert!(repo.issues().is_err());
+        let issues = repo.issues_partial().expect("Could not retrieve issues");
+        assert_eq!(issues.value.len(), 1);
+        assert!(issues.value.contains(&issue));
+        assert_eq!(issues.failures.len(), 1);
+    }
+
+    #[test]
+    fn issues_in_component() {
+        let mut testing_repo = TestingRepo::new("issues_in_component");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        repo.create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        repo.create_issue_in_component(Some("frontend"), &sig, &sig, "Test message 2", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue = repo
+            .create_issue_in_component(Some("backend"), &sig, &sig, "Test message 3", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let mut issues = repo
+            .issues_in_component("refs", "backend")
+            .expect("Could not retrieve issues")
+            .into_iter();
+        let retrieved_issue = issues
+            .next()
+            .expect("Could not retrieve issue");
+        assert_eq!(retrieved_issue.id(), issue.id());
+        assert_eq!(retrieved_issue.component(), Some("backend"));
+        assert!(issues.next().is_none());
+
+        let found_issue = repo
+            .find_issue(issue.id())
+            .expect("Could not retrieve issue by id");
+        assert_eq!(found_issue.component(), Some("backend"));
+
+        assert_eq!(repo.issues().expect("Could not retrieve issues").len(), 3);
     }
 
     #[test]
@@ -347,6 +756,61 @@ mod tests {
         assert!(iter.next().is_none());
     }
 
+    #[test]
+    fn moderation() {
+        let mut testing_repo = TestingRepo::new("repo_moderation");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+
+        assert_eq!(repo.moderation().expect("Could not get moderation data"), Moderation::new());
+
+        let mut moderation = Moderation::new();
+        moderation.block_author("spam@example.com");
+        repo.update_moderation(&moderation, &sig, &sig, "Block spammer")
+            .expect("Could not update moderation data");
+        moderation.hide_message(Oid::from_str("65b56706fdc3501749d008750c61a1f24b888f72").unwrap());
+        let id = repo
+            .update_moderation(&moderation, &sig, &sig, "Hide message")
+            .expect("Could not update moderation data");
+
+        assert_eq!(repo.moderation().expect("Could not get moderation data"), moderation);
+        assert_eq!(repo.find_commit(id).expect("Could not find commit").parent_count(), 1);
+        assert!(repo.issues().expect("Could not retrieve issues").is_empty());
+    }
+
+    #[test]
+    fn current_issue() {
+        let mut testing_repo = TestingRepo::new("current_issue");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let issue = repo
+            .create_issue_in_component(Some("frontend"), &sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        assert!(repo.current_issue().expect("Could not get current issue").is_none());
+
+        repo.set_current_issue(Some(&issue))
+            .expect("Could not set current issue");
+        let current = repo
+            .current_issue()
+            .expect("Could not get current issue")
+            .expect("No current issue");
+        assert_eq!(current.id(), issue.id());
+        assert_eq!(current.component(), Some("frontend"));
+        assert_eq!(repo.issues().expect("Could not retrieve issues").len(), 1);
+
+        repo.set_current_issue(None)
+            .expect("Could not clear current issue");
+        assert!(repo.current_issue().expect("Could not get current issue").is_none());
+    }
+
     #[test]
     fn issue_messages_iter() {
         let mut testing_repo = TestingRepo::new("issue_messages_iter");
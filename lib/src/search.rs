@@ -0,0 +1,528 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Full-text search
+//!
+//! This module provides the means for searching the subjects and bodies of
+//! messages. A `Query` describes what to search for, either a substring or a
+//! regular expression, and which parts of the messages to search. Searching an
+//! issue via
+//! `search_issue()` yields an `IssueHit` holding a `MessageHit` for each
+//! message containing a match, which in turn holds a `Snippet` for each of the
+//! matching lines.
+//!
+//! `RepositoryExt::search()` searches all issues of a repository.
+//!
+
+use git2::{Commit, Oid};
+use std::collections::{HashMap, HashSet};
+use regex::{Regex, RegexBuilder};
+
+use issue::Issue;
+use message::Message;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Maximum length of a snippet, in characters
+///
+/// Lines exceeding this length are shortened around the match.
+///
+pub const MAX_SNIPPET_LENGTH: usize = 80;
+
+/// Marker for text omitted from a snippet
+///
+const ELLIPSIS: &'static str = "...";
+
+
+/// Parts of a message searched
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Both the subject and the body
+    All,
+    /// Only the subject
+    Subject,
+    /// Only the body
+    Body,
+}
+
+impl Scope {
+    /// Check whether a line of a message is searched
+    ///
+    /// The subject is line 0, the first line of the body is line 1.
+    ///
+    fn includes(self, line: usize) -> bool {
+        match self {
+            Scope::All      => true,
+            Scope::Subject  => line == 0,
+            Scope::Body     => line > 0,
+        }
+    }
+}
+
+
+/// Granularity of search results
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Granularity {
+    /// Report all the messages of an issue containing a match
+    Message,
+    /// Report only whether an issue contains a match
+    ///
+    /// The search of an issue stops at the first message containing a match,
+    /// which is the only message reported. The newest messages are searched
+    /// first.
+    Issue,
+}
+
+
+/// Pattern of a query
+///
+#[derive(Clone, Debug)]
+enum Pattern {
+    Substring(Vec<char>),
+    Regex(Regex),
+}
+
+
+/// Search query
+///
+/// By default, a query matches case-insensitively unless its pattern contains
+/// an upper case character ("smart case"). For regular expressions, characters
+/// following a backslash are not considered, since escapes like `\S` do not
+/// denote upper case characters.
+///
+/// All of a message is searched and all the messages containing a match are
+/// reported unless specified otherwise via `scope()` and `granularity()`.
+///
+#[derive(Clone, Debug)]
+pub struct Query {
+    pattern: Pattern,
+    case_sensitive: bool,
+    scope: Scope,
+    granularity: Granularity,
+}
+
+impl Query {
+    /// Create a query for a substring
+    ///
+    pub fn new<S>(pattern: S) -> Self
+        where S: AsRef<str>
+    {
+        let pattern: Vec<char> = pattern.as_ref().chars().collect();
+        let case_sensitive = pattern.iter().any(|c| c.is_uppercase());
+        Self::with_pattern(Pattern::Substring(pattern), case_sensitive)
+    }
+
+    /// Create a query for a regular expression
+    ///
+    /// The syntax is the one of the `regex` crate. Matches never span multiple
+    /// lines.
+    ///
+    pub fn regex<S>(pattern: S) -> Result<Self>
+        where S: AsRef<str>
+    {
+        let pattern = pattern.as_ref();
+        let mut escaped = false;
+        let case_sensitive = pattern.chars().any(|c| {
+            let upper = !escaped && c.is_uppercase();
+            escaped = !escaped && c == '\\';
+            upper
+        });
+        let regex = compile(pattern, case_sensitive)?;
+        Ok(Self::with_pattern(Pattern::Regex(regex), case_sensitive))
+    }
+
+    /// Select whether the query matches case-sensitively
+    ///
+    /// This overrides the smart case behaviour.
+    ///
+    pub fn case_sensitive(mut self, option: bool) -> Self {
+        if option != self.case_sensitive {
+            if let Pattern::Regex(ref mut regex) = self.pattern {
+                // The pattern compiled before, so compiling it again will not
+                // fail for reasons other than exceeding the size limit.
+                match compile(regex.as_str(), option) {
+                    Ok(compiled) => *regex = compiled,
+                    Err(_) => return self,
+                }
+            }
+        }
+        self.case_sensitive = option;
+        self
+    }
+
+    /// Select the parts of messages searched
+    ///
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Select the granularity of the results
+    ///
+    pub fn granularity(mut self, granularity: Granularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    /// Find the first match in a text
+    ///
+    /// Returns the byte range of the match. An empty pattern matches at the
+    /// start of any text.
+    ///
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        let pattern = match self.pattern {
+            Pattern::Substring(ref pattern) => pattern,
+            Pattern::Regex(ref regex) => return regex.find(text).map(|m| (m.start(), m.end())),
+        };
+
+        for (start, _) in text.char_indices().chain(Some((text.len(), ' '))) {
+            let mut chars = text[start..].char_indices();
+            let mut end = start;
+            let matched = pattern.iter().all(|p| match chars.next() {
+                Some((offset, c)) if self.char_matches(*p, c) => {
+                    end = start + offset + c.len_utf8();
+                    true
+                },
+                _ => false,
+            });
+            if matched {
+                return Some((start, end));
+            }
+        }
+        None
+    }
+
+    /// Search a message
+    ///
+    /// Returns a hit holding snippets of all the matching lines of the parts
+    /// of the message within the query's scope, if any.
+    ///
+    pub fn search_message(&self, message: &Commit) -> Option<MessageHit> {
+        let lines = message
+            .summary()
+            .map(String::from)
+            .into_iter()
+            .chain(message.body_lines());
+        let snippets: Vec<Snippet> = (0..)
+            .zip(lines)
+            .filter(|&(number, _)| self.scope.includes(number))
+            .filter_map(|(number, line)| self.find(&line).map(|range| Snippet::new(number, &line, range)))
+            .collect();
+
+        if snippets.is_empty() {
+            None
+        } else {
+            Some(MessageHit { message: message.id(), snippets: snippets })
+        }
+    }
+
+    /// Get the substring searched for
+    ///
+    /// Returns `None` if the query is a regular expression.
+    ///
+    #[cfg(feature = "search-index")]
+    pub(crate) fn substring(&self) -> Option<&[char]> {
+        match self.pattern {
+            Pattern::Substring(ref pattern) => Some(pattern),
+            Pattern::Regex(_) => None,
+        }
+    }
+
+    /// Get the parts of messages searched
+    ///
+    #[cfg(feature = "search-index")]
+    pub(crate) fn searched_scope(&self) -> Scope {
+        self.scope
+    }
+
+    /// Create a query with default options
+    ///
+    fn with_pattern(pattern: Pattern, case_sensitive: bool) -> Self {
+        Query {
+            pattern: pattern,
+            case_sensitive: case_sensitive,
+            scope: Scope::All,
+            granularity: Granularity::Message,
+        }
+    }
+
+    /// Check whether a character matches a character of the pattern
+    ///
+    fn char_matches(&self, pattern: char, c: char) -> bool {
+        if self.case_sensitive {
+            pattern == c
+        } else {
+            pattern.to_lowercase().eq(c.to_lowercase())
+        }
+    }
+}
+
+
+/// Compile a regular expression
+///
+fn compile(pattern: &str, case_sensitive: bool) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .chain_err(|| EK::MalformedPattern(pattern.to_owned()))
+}
+
+
+/// Excerpt of a matching line
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snippet {
+    /// Number of the line within the message
+    ///
+    /// The subject is line 0, the first line of the body is line 1.
+    pub line: usize,
+    /// Text of the line, trimmed and shortened around the match if necessary
+    pub text: String,
+    /// Byte range of the match within `text`
+    pub range: (usize, usize),
+}
+
+impl Snippet {
+    /// Create a snippet for a line containing a match at the range given
+    ///
+    fn new(number: usize, line: &str, range: (usize, usize)) -> Self {
+        // Trimming only removes whitespace, which is never part of a match
+        // starting or ending the line unless the pattern contains it.
+        let start = line.len() - line.trim_start().len();
+        let end = start + line[start..].trim_end().len();
+        let (start, end) = (start.min(range.0), end.max(range.1));
+
+        let length = line[start..end].chars().count();
+        if length <= MAX_SNIPPET_LENGTH {
+            return Snippet {
+                line: number,
+                text: line[start..end].to_owned(),
+                range: (range.0 - start, range.1 - start),
+            };
+        }
+
+        // Center the window on the match, keeping its start visible.
+        let matched = line[range.0..range.1].chars().count();
+        let context = MAX_SNIPPET_LENGTH.saturating_sub(matched) / 2;
+        let window_start = if context == 0 {
+            range.0
+        } else {
+            line[start..range.0]
+                .char_indices()
+                .rev()
+                .nth(context - 1)
+                .map(|(offset, _)| start + offset)
+                .unwrap_or(start)
+        };
+        let window_end = line[window_start..end]
+            .char_indices()
+            .nth(MAX_SNIPPET_LENGTH)
+            .map(|(offset, _)| window_start + offset)
+            .unwrap_or(end)
+            .max(range.1);
+
+        let mut text = String::new();
+        if window_start > start {
+            text.push_str(ELLIPSIS);
+        }
+        let offset = text.len();
+        text.push_str(&line[window_start..window_end]);
+        if window_end < end {
+            text.push_str(ELLIPSIS);
+        }
+
+        Snippet {
+            line: number,
+            text: text,
+            range: (offset + range.0 - window_start, offset + range.1 - window_start),
+        }
+    }
+}
+
+
+/// Matches within a message
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageHit {
+    /// Id of the message
+    pub message: Oid,
+    /// Snippets of the matching lines, in order
+    pub snippets: Vec<Snippet>,
+}
+
+
+/// Matches within an issue
+///
+pub struct IssueHit<'r> {
+    /// Issue containing the matches
+    pub issue: Issue<'r>,
+    /// Messages containing matches, oldest first
+    pub messages: Vec<MessageHit>,
+}
+
+
+/// Candidates for matches of a query
+///
+/// The candidates map the ids of issues to the ids of those of their messages
+/// which may contain a match, e.g. according to a search index.
+///
+pub type Candidates = HashMap<Oid, HashSet<Oid>>;
+
+
+/// Search the messages of an issue
+///
+/// Only the messages for which the filter supplied returns `true` are
+/// searched. Returns `None` if none of them contains a match. Otherwise, the
+/// issue is returned as part of the hit. If the query's granularity is
+/// `Granularity::Issue`, the search stops at the first match.
+///
+pub fn search_issue<'r, F>(issue: Issue<'r>, query: &Query, mut filter: F) -> Result<Option<IssueHit<'r>>>
+    where F: FnMut(&Commit) -> bool
+{
+    let mut messages = Vec::new();
+    for message in issue.messages()? {
+        let message = message?;
+        if filter(&message) {
+            messages.extend(query.search_message(&message));
+        }
+        if query.granularity == Granularity::Issue && !messages.is_empty() {
+            break;
+        }
+    }
+    messages.reverse();
+
+    if messages.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(IssueHit { issue: issue, messages: messages }))
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2;
+    use repository::RepositoryExt;
+    use test_utils::TestingRepo;
+
+    // Query tests
+
+    #[test]
+    fn smart_case() {
+        let query = Query::new("panic");
+        assert_eq!(query.find("Thread PANICKED here"), Some((7, 12)));
+        assert_eq!(query.find("no match"), None);
+
+        let query = Query::new("Panic");
+        assert_eq!(query.find("a panic"), None);
+        assert_eq!(query.find("a Panic"), Some((2, 7)));
+        assert_eq!(query.case_sensitive(false).find("a panic"), Some((2, 7)));
+
+        assert_eq!(Query::new("straße").find("Die STRASSE, die Straße"), Some((17, 24)));
+        assert_eq!(Query::new("").find("foo"), Some((0, 0)));
+        assert_eq!(Query::new("").find(""), Some((0, 0)));
+    }
+
+    #[test]
+    fn regex_queries() {
+        let query = Query::regex(r"pani[ck]+(ed)?\b").expect("Could not compile regex");
+        assert_eq!(query.find("Thread PANICKED here"), Some((7, 15)));
+        assert_eq!(query.find("panicking"), None);
+
+        let query = Query::regex(r"\SPanic").expect("Could not compile regex");
+        assert_eq!(query.find("a panic"), None);
+        assert_eq!(query.find("aPanic"), Some((0, 6)));
+        assert_eq!(query.case_sensitive(false).find("apanic"), Some((0, 6)));
+        assert!(!Query::regex(r"\S\\").expect("Could not compile regex").case_sensitive);
+        assert!(Query::regex(r"\\S").expect("Could not compile regex").case_sensitive);
+
+        assert!(Query::regex("(unclosed").is_err());
+    }
+
+    #[test]
+    fn scopes() {
+        assert!(Scope::All.includes(0) && Scope::All.includes(3));
+        assert!(Scope::Subject.includes(0) && !Scope::Subject.includes(1));
+        assert!(!Scope::Body.includes(0) && Scope::Body.includes(1));
+    }
+
+    #[test]
+    fn snippets() {
+        let snippet = Snippet::new(1, "  some text with a panic  ", (19, 24));
+        assert_eq!(snippet, Snippet { line: 1, text: "some text with a panic".to_owned(), range: (17, 22) });
+
+        let line = format!("{}panic{}", "a".repeat(100), "b".repeat(100));
+        let snippet = Snippet::new(0, &line, (100, 105));
+        assert!(snippet.text.starts_with("...a"));
+        assert!(snippet.text.ends_with("b..."));
+        assert_eq!(snippet.text.chars().count(), MAX_SNIPPET_LENGTH + 2 * ELLIPSIS.len());
+        assert_eq!(&snippet.text[snippet.range.0..snippet.range.1], "panic");
+    }
+
+    // Search tests
+
+    #[test]
+    fn search_repository() {
+        let mut testing_repo = TestingRepo::new("search_repository");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Crash on startup\n\nIt panics.", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let reply = issue
+            .add_message(&sig, &sig, "Re: Crash on startup\n\nNo crash here.", &empty_tree, vec![&initial])
+            .expect("Could not add message");
+        repo.create_issue(&sig, &sig, "Unrelated", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let hits = repo.search(&Query::new("crash")).expect("Could not search");
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].issue == issue);
+        let messages: Vec<(Oid, Vec<usize>)> = hits[0]
+            .messages
+            .iter()
+            .map(|hit| (hit.message, hit.snippets.iter().map(|snippet| snippet.line).collect()))
+            .collect();
+        assert_eq!(messages, vec![(initial.id(), vec![0]), (reply.id(), vec![0, 1])]);
+
+        let hits = search_issue(issue, &Query::new("crash"), |message| message.id() != reply.id())
+            .expect("Could not search")
+            .expect("No hit");
+        assert_eq!(hits.messages.len(), 1);
+
+        let query = Query::new("crash").scope(Scope::Body);
+        let hits = repo.search(&query).expect("Could not search");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].messages.len(), 1);
+        assert_eq!(hits[0].messages[0].message, reply.id());
+
+        let query = Query::new("crash").granularity(Granularity::Issue);
+        let hits = repo.search(&query).expect("Could not search");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].messages.iter().map(|hit| hit.message).collect::<Vec<_>>(), vec![reply.id()]);
+
+        assert!(repo.search(&Query::new("Crash here")).expect("Could not search").is_empty());
+    }
+}
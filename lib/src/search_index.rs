@@ -0,0 +1,467 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Full-text search index
+//!
+//! For large trackers, scanning all messages for each search is slow. This
+//! module provides the `SearchIndex`, an index of the subjects and bodies of
+//! messages built on `tantivy`. It is only available if the library is built
+//! with the "search-index" feature.
+//!
+//! The index is stored in the directory `dit/search` inside the repository's
+//! git directory. It does not exist unless created via `SearchIndex::create()`.
+//! Once it exists, `RepositoryExt::search()` updates and consults it. Updates
+//! are incremental: only the messages not reachable from the references
+//! indexed previously are added. Since it is a mere cache, the index may be
+//! deleted at any time, in which case searches scan all messages again.
+//!
+//! Messages are indexed by the trigrams of their lower case text. Hence, the
+//! index yields candidates for queries for substrings of at least three
+//! characters, which are then searched like any other message. Other queries,
+//! e.g. for regular expressions, are not served by the index.
+//!
+//! Encrypted parts of messages are indexed as they are stored, i.e. without
+//! being decrypted, so that the index never holds their plaintext. Hence,
+//! searches served by the index do not find text in encrypted bodies, whether
+//! or not a decryption cipher is registered.
+//!
+
+use git2::{Oid, Repository};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tantivy::{Index, IndexWriter, TantivyDocument, Term};
+use tantivy::collector::DocSetCollector;
+use tantivy::query::{BooleanQuery, Occur, Query as IndexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED, STRING};
+use tantivy::tokenizer::NgramTokenizer;
+
+use backend;
+use issue::IssueRefType;
+use message::Message;
+use message_index::MessageIndex;
+use search::{Candidates, Query, Scope};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Name under which the trigram tokenizer is registered
+///
+const TOKENIZER: &'static str = "dit-trigram";
+
+/// Length of the n-grams indexed
+///
+const GRAM_LENGTH: usize = 3;
+
+/// Memory budget of the index writer, in bytes
+///
+const WRITER_BUDGET: usize = 16_000_000;
+
+
+/// Fields of an indexed message
+///
+struct Fields {
+    message: Field,
+    issue: Field,
+    subject: Field,
+    body: Field,
+}
+
+impl Fields {
+    /// Create the schema of the index
+    ///
+    fn schema() -> Schema {
+        let indexing = TextFieldIndexing::default()
+            .set_tokenizer(TOKENIZER)
+            .set_index_option(IndexRecordOption::Basic);
+        let text = TextOptions::default().set_indexing_options(indexing);
+
+        let mut builder = Schema::builder();
+        builder.add_text_field("message", STRING | STORED);
+        builder.add_text_field("issue", STRING | STORED);
+        builder.add_text_field("subject", text.clone());
+        builder.add_text_field("body", text);
+        builder.build()
+    }
+
+    /// Look up the fields in a schema
+    ///
+    fn new(schema: &Schema) -> ::tantivy::Result<Self> {
+        Ok(Fields {
+            message: schema.get_field("message")?,
+            issue: schema.get_field("issue")?,
+            subject: schema.get_field("subject")?,
+            body: schema.get_field("body")?,
+        })
+    }
+}
+
+
+/// Full-text index of messages
+///
+pub struct SearchIndex {
+    path: PathBuf,
+    index: Index,
+    fields: Fields,
+}
+
+impl SearchIndex {
+    /// Open the search index of a repository
+    ///
+    /// Returns `None` if the repository has no search index.
+    ///
+    pub fn open(repo: &Repository) -> Result<Option<Self>> {
+        let path = index_path(repo);
+        if !path.join("meta.json").exists() {
+            return Ok(None);
+        }
+
+        let index = Index::open_in_dir(&path).chain_err(|| EK::CannotAccessSearchIndex(display(&path)))?;
+        Self::new(path, index).map(Some)
+    }
+
+    /// Create the search index of a repository
+    ///
+    /// If the index already exists, it is opened instead. A new index is empty
+    /// until updated.
+    ///
+    pub fn create(repo: &Repository) -> Result<Self> {
+        if let Some(index) = Self::open(repo)? {
+            return Ok(index);
+        }
+
+        let path = index_path(repo);
+        let error = || EK::CannotAccessSearchIndex(display(&path));
+        fs::create_dir_all(&path).chain_err(error)?;
+        let index = Index::create_in_dir(&path, Fields::schema()).chain_err(error)?;
+        Self::new(path, index)
+    }
+
+    /// Index the messages added since the last update
+    ///
+    /// The messages reachable from the issue references of the repository,
+    /// but not from the references indexed previously, are added. Messages of
+    /// issues which no longer have a head are dropped from the index.
+    ///
+    pub fn update(&self, repo: &Repository) -> Result<()> {
+        trace_span!("update_search_index");
+        let indexed = self.indexed_refs()?;
+        let current: HashMap<String, Oid> = backend::dit_references(repo, "**")?
+            .into_iter()
+            .filter(|(name, _)| IssueRefType::of_ref(name).is_some())
+            .collect();
+        if indexed == current {
+            return Ok(());
+        }
+
+        let mut messages = MessageIndex::open(repo);
+        messages.update(repo)?;
+        messages.save()?;
+
+        let mut revwalk = repo.revwalk()?;
+        for target in current.values() {
+            revwalk.push(*target)?;
+        }
+        for target in indexed.values() {
+            // targets may have been collected in the meantime
+            if repo.find_commit(*target).is_ok() {
+                revwalk.hide(*target)?;
+            }
+        }
+
+        let error = || EK::CannotAccessSearchIndex(display(&self.path));
+        let mut writer: IndexWriter = self.index.writer(WRITER_BUDGET).chain_err(error)?;
+        for issue in heads(&indexed).difference(&heads(&current)) {
+            writer.delete_term(Term::from_field_text(self.fields.issue, &issue.to_string()));
+        }
+
+        for id in revwalk {
+            let id = id?;
+            let issue = match messages.issue_id(id) {
+                Some(issue) => issue,
+                None => continue,
+            };
+            trace_event!(message = %id, "indexing message");
+            let message = repo.find_commit(id)?;
+            let body: Vec<String> = message.raw_body_lines().collect();
+
+            let mut document = TantivyDocument::new();
+            document.add_text(self.fields.message, id.to_string());
+            document.add_text(self.fields.issue, issue.to_string());
+            document.add_text(self.fields.subject, lowercase(message.summary().unwrap_or("").chars()));
+            document.add_text(self.fields.body, lowercase(body.join("\n").chars()));
+            writer.add_document(document).chain_err(error)?;
+        }
+
+        let payload: Vec<String> = current
+            .iter()
+            .map(|(name, target)| format!("{} {}", name, target))
+            .collect();
+        let mut commit = writer.prepare_commit().chain_err(error)?;
+        commit.set_payload(&payload.join("\n"));
+        commit.commit().chain_err(error)?;
+        Ok(())
+    }
+
+    /// Get the candidates for matches of a query
+    ///
+    /// Returns `None` if the index cannot serve the query, e.g. because it is
+    /// a regular expression or its substring is shorter than three characters.
+    /// The index is expected to be up to date.
+    ///
+    pub fn candidates(&self, query: &Query) -> Result<Option<Candidates>> {
+        let grams: HashSet<String> = match query.substring() {
+            Some(pattern) => trigrams(&lowercase(pattern.iter().cloned())),
+            None => return Ok(None),
+        };
+        if grams.is_empty() {
+            return Ok(None);
+        }
+
+        let field_query = |field: Field| -> Box<IndexQuery> {
+            let terms = grams
+                .iter()
+                .map(|gram| {
+                    let term = TermQuery::new(Term::from_field_text(field, gram), IndexRecordOption::Basic);
+                    (Occur::Must, Box::new(term) as Box<IndexQuery>)
+                })
+                .collect();
+            Box::new(BooleanQuery::new(terms))
+        };
+        let index_query: Box<IndexQuery> = match query.searched_scope() {
+            Scope::All => Box::new(BooleanQuery::new(vec![
+                (Occur::Should, field_query(self.fields.subject)),
+                (Occur::Should, field_query(self.fields.body)),
+            ])),
+            Scope::Subject => field_query(self.fields.subject),
+            Scope::Body => field_query(self.fields.body),
+        };
+
+        let error = || EK::CannotAccessSearchIndex(display(&self.path));
+        let searcher = self.index.reader().chain_err(error)?.searcher();
+        let mut retval = Candidates::new();
+        for address in searcher.search(&*index_query, &DocSetCollector).chain_err(error)? {
+            let document: TantivyDocument = searcher.doc(address).chain_err(error)?;
+            let id = |field| document
+                .get_first(field)
+                .and_then(|value| value.as_str())
+                .and_then(|id| Oid::from_str(id).ok());
+            if let (Some(message), Some(issue)) = (id(self.fields.message), id(self.fields.issue)) {
+                retval.entry(issue).or_default().insert(message);
+            }
+        }
+        Ok(Some(retval))
+    }
+
+    /// Get the candidates for matches of a query from a repository's index
+    ///
+    /// The index is updated before it is consulted. Returns `None` if the
+    /// repository has no search index or the index cannot serve the query.
+    ///
+    pub fn lookup(repo: &Repository, query: &Query) -> Result<Option<Candidates>> {
+        match Self::open(repo)? {
+            Some(index) => {
+                index.update(repo)?;
+                index.candidates(query)
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Create a search index for an opened tantivy index
+    ///
+    fn new(path: PathBuf, index: Index) -> Result<Self> {
+        let error = || EK::CannotAccessSearchIndex(display(&path));
+        let fields = Fields::new(&index.schema()).chain_err(error)?;
+        let tokenizer = NgramTokenizer::all_ngrams(GRAM_LENGTH, GRAM_LENGTH).chain_err(error)?;
+        index.tokenizers().register(TOKENIZER, tokenizer);
+        Ok(SearchIndex { path: path, index: index, fields: fields })
+    }
+
+    /// Get the references indexed during the last update along with their targets
+    ///
+    fn indexed_refs(&self) -> Result<HashMap<String, Oid>> {
+        let metas = self
+            .index
+            .load_metas()
+            .chain_err(|| EK::CannotAccessSearchIndex(display(&self.path)))?;
+        Ok(metas
+            .payload
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.rsplitn(2, ' ');
+                match (parts.next().map(Oid::from_str), parts.next()) {
+                    (Some(Ok(target)), Some(name)) => Some((name.to_owned(), target)),
+                    _ => None,
+                }
+            })
+            .collect())
+    }
+}
+
+
+/// Get the path of the search index of a repository
+///
+fn index_path(repo: &Repository) -> PathBuf {
+    repo.path().join("dit").join("search")
+}
+
+
+/// Get the printable form of a path
+///
+fn display(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+
+/// Get the ids of the issues referred to by the head references among some
+///
+fn heads(refs: &HashMap<String, Oid>) -> HashSet<Oid> {
+    refs.keys()
+        .filter_map(|name| match IssueRefType::of_ref(name) {
+            Some((issue, IssueRefType::Head)) => Some(issue),
+            _ => None,
+        })
+        .collect()
+}
+
+
+/// Convert text to lower case
+///
+/// Unlike `str::to_lowercase()`, each character is converted on its own, like
+/// when matching a query case-insensitively.
+///
+fn lowercase<I>(text: I) -> String
+    where I: IntoIterator<Item = char>
+{
+    text.into_iter().flat_map(char::to_lowercase).collect()
+}
+
+
+/// Get the trigrams of a text
+///
+fn trigrams(text: &str) -> HashSet<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.windows(GRAM_LENGTH).map(|gram| gram.iter().collect()).collect()
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2;
+    use repository::RepositoryExt;
+    use test_utils::TestingRepo;
+
+    // Search index tests
+
+    #[test]
+    fn trigram_extraction() {
+        assert_eq!(trigrams("ab"), HashSet::new());
+        assert_eq!(trigrams("abcb"), vec!["abc", "bcb"].into_iter().map(String::from).collect());
+        assert_eq!(lowercase("STRAßE".chars()), "straße");
+    }
+
+    #[test]
+    fn search_index() {
+        let mut testing_repo = TestingRepo::new("search_index");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Crash on startup\n\nIt panics.", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+
+        assert!(SearchIndex::lookup(repo, &Query::new("crash")).expect("Could not look up").is_none());
+        SearchIndex::create(repo).expect("Could not create index");
+        let candidates = SearchIndex::lookup(repo, &Query::new("CRASH"))
+            .expect("Could not look up")
+            .expect("No candidates");
+        assert_eq!(candidates.get(&issue.id()), Some(&vec![initial.id()].into_iter().collect()));
+
+        // only the new message is indexed during the next update
+        let reply = issue
+            .add_message(&sig, &sig, "Re: Crash on startup\n\nThe panic is gone.", &empty_tree, vec![&initial])
+            .expect("Could not add message");
+        let candidates = SearchIndex::lookup(repo, &Query::new("panic").scope(Scope::Body))
+            .expect("Could not look up")
+            .expect("No candidates");
+        assert_eq!(candidates.get(&issue.id()).map(HashSet::len), Some(2));
+        let candidates = SearchIndex::lookup(repo, &Query::new("gone"))
+            .expect("Could not look up")
+            .expect("No candidates");
+        assert_eq!(candidates.get(&issue.id()), Some(&vec![reply.id()].into_iter().collect()));
+
+        assert!(SearchIndex::lookup(repo, &Query::new("it")).expect("Could not look up").is_none());
+        assert!(SearchIndex::lookup(repo, &Query::regex("cra.h").expect("Could not compile regex"))
+            .expect("Could not look up")
+            .is_none());
+
+        let hits = repo.search(&Query::new("gone")).expect("Could not search");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].messages.iter().map(|hit| hit.message).collect::<Vec<_>>(), vec![reply.id()]);
+    }
+
+    #[test]
+    fn encrypted_messages() {
+        use message::crypt::Cipher;
+        use test_utils::with_decryption_cipher;
+
+        /// Dummy cipher decrypting any ciphertext to the same text
+        ///
+        struct RevealingCipher;
+
+        impl Cipher for RevealingCipher {
+            fn encrypt(&self, _: &str, _: &[String]) -> Result<String> {
+                unimplemented!()
+            }
+
+            fn decrypt(&self, _: &str) -> Result<String> {
+                Ok("Secret text".to_owned())
+            }
+        }
+
+        let mut testing_repo = TestingRepo::new("search_index_encrypted_messages");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let text = "Secret issue\n\n-----BEGIN PGP MESSAGE-----\nciphertext\n-----END PGP MESSAGE-----";
+        let issue = repo
+            .create_issue(&sig, &sig, text, &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        SearchIndex::create(repo).expect("Could not create index");
+        let candidates = with_decryption_cipher(Box::new(RevealingCipher), || {
+                SearchIndex::lookup(repo, &Query::new("secret text"))
+            })
+            .expect("Could not look up")
+            .expect("No candidates");
+        assert!(candidates.get(&issue.id()).is_none());
+        let candidates = SearchIndex::lookup(repo, &Query::new("ciphertext"))
+            .expect("Could not look up")
+            .expect("No candidates");
+        assert_eq!(candidates.get(&issue.id()).map(HashSet::len), Some(1));
+    }
+}
@@ -0,0 +1,387 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Issue templates
+//!
+//! A repository may ship templates for new issues, e.g. for bug reports or
+//! feature requests. Templates are stored in a chain of commits referred to by
+//! `refs/dit/meta/templates`. The tree of each of those commits contains one
+//! file per template, named after the template. Since the reference resides
+//! under `refs/dit/`, the templates are distributed along with the issues.
+//!
+//! Clones which did not yet create templates on their own use the templates
+//! fetched from a remote.
+//!
+//! Templates may contain placeholders of the form `{{name}}`, which are
+//! replaced via `expand()` before a template is presented to the user. See
+//! `Placeholder` for the kinds of placeholders. Since the contents of the
+//! environment or the configuration may be secret, placeholders referring to
+//! them should only be expanded in templates created locally, as reported by
+//! `has_local_templates()`. Otherwise, a remote could ship a template which
+//! ends up publishing a secret in a new issue.
+//!
+
+use git2::{self, Oid, Repository, Tree};
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::str;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Name of the reference holding the templates
+///
+pub const TEMPLATES_REF: &'static str = "refs/dit/meta/templates";
+
+/// Glob matching the remote-tracking copies of `TEMPLATES_REF`
+///
+const REMOTE_TEMPLATES_GLOB: &'static str = "refs/remotes/*/dit/meta/templates";
+
+
+/// Set of issue templates
+///
+/// Templates are identified by their name. A name may not be empty, start
+/// with a dot or contain a slash.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Templates {
+    templates: BTreeMap<String, String>,
+}
+
+impl Templates {
+    /// Create a new, empty set of templates
+    ///
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Read templates from a tree
+    ///
+    /// Entries which are not blobs are ignored.
+    ///
+    pub fn from_tree(repo: &Repository, tree: &Tree) -> Result<Self> {
+        let mut retval = Self::new();
+
+        for entry in tree.iter() {
+            if entry.kind() != Some(git2::ObjectType::Blob) {
+                continue;
+            }
+            let name = match entry.name() {
+                Some(name) => name.to_owned(),
+                None => continue,
+            };
+            let blob = repo.find_blob(entry.id())?;
+            let text = str::from_utf8(blob.content())
+                .chain_err(|| EK::MalformedTemplate(name.clone()))?
+                .to_owned();
+            retval.templates.insert(name, text);
+        }
+
+        Ok(retval)
+    }
+
+    /// Write the templates to a new tree
+    ///
+    pub fn write_tree(&self, repo: &Repository) -> Result<Oid> {
+        let mut builder = repo.treebuilder(None)?;
+        for (name, text) in &self.templates {
+            builder.insert(name, repo.blob(text.as_bytes())?, 0o100644)?;
+        }
+        builder.write().chain_err(|| EK::CannotBuildTree)
+    }
+
+    /// Add or replace a template
+    ///
+    /// Returns the template replaced, if any.
+    ///
+    pub fn insert<N, T>(&mut self, name: N, text: T) -> Result<Option<String>>
+        where N: Into<String>,
+              T: Into<String>
+    {
+        let name = name.into();
+        check_name(&name)?;
+        Ok(self.templates.insert(name, text.into()))
+    }
+
+    /// Remove a template
+    ///
+    /// Returns the template removed, if any.
+    ///
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.templates.remove(name)
+    }
+
+    /// Get a template by name
+    ///
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(String::as_str)
+    }
+
+    /// Get the names of all templates in alphabetical order
+    ///
+    pub fn names(&self) -> btree_map::Keys<String, String> {
+        self.templates.keys()
+    }
+
+    /// Check whether the set contains no templates
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+
+/// Placeholder in a template
+///
+/// A placeholder `{{env:NAME}}` refers to an environment variable and
+/// `{{config:KEY}}` to a configuration option. A placeholder
+/// `{{prompt:QUESTION}}` asks the user for a value. Any other placeholder
+/// refers to a variable of the given name.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Placeholder<'a> {
+    Env(&'a str),
+    Config(&'a str),
+    Prompt(&'a str),
+    Variable(&'a str),
+}
+
+impl<'a> Placeholder<'a> {
+    /// Parse the text between the braces of a placeholder
+    ///
+    /// Surrounding whitespace is ignored.
+    ///
+    pub fn parse(spec: &'a str) -> Self {
+        let spec = spec.trim();
+        if let Some(name) = spec.strip_prefix("env:").map(str::trim) {
+            Placeholder::Env(name)
+        } else if let Some(key) = spec.strip_prefix("config:").map(str::trim) {
+            Placeholder::Config(key)
+        } else if let Some(question) = spec.strip_prefix("prompt:").map(str::trim) {
+            Placeholder::Prompt(question)
+        } else {
+            Placeholder::Variable(spec)
+        }
+    }
+}
+
+
+/// Replace the placeholders in a template
+///
+/// Each placeholder is replaced by the value returned by the function supplied
+/// for it. Unterminated placeholders are left untouched.
+///
+pub fn expand<F>(text: &str, mut resolve: F) -> Result<String>
+    where F: FnMut(Placeholder) -> Result<String>
+{
+    let mut retval = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start + 2..].find("}}") {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+        retval.push_str(&rest[..start]);
+        retval.push_str(&resolve(Placeholder::parse(&rest[start + 2..end]))?);
+        rest = &rest[end + 2..];
+    }
+
+    retval.push_str(rest);
+    Ok(retval)
+}
+
+
+/// Check whether a string is a valid template name
+///
+pub fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        Err(Error::from_kind(EK::InvalidTemplateName(name.to_owned())))
+    } else {
+        Ok(())
+    }
+}
+
+
+/// Get the templates of a repository
+///
+/// The templates are read from the commit referred to by `TEMPLATES_REF`. If
+/// the reference does not exist, the templates are read from the first of the
+/// remote-tracking copies of the reference, if any.
+///
+pub fn templates(repo: &Repository) -> Result<Templates> {
+    match templates_reference(repo)? {
+        Some((name, reference)) => read_reference(repo, reference, &name),
+        None => Ok(Templates::new()),
+    }
+}
+
+/// Check whether the templates of a repository were created locally
+///
+/// Returns `false` if `TEMPLATES_REF` does not exist, i.e. if `templates()`
+/// falls back to the templates fetched from a remote.
+///
+pub fn has_local_templates(repo: &Repository) -> Result<bool> {
+    match repo.find_reference(TEMPLATES_REF) {
+        Ok(_) => Ok(true),
+        Err(ref err) if err.code() == git2::ErrorCode::NotFound => Ok(false),
+        Err(err) => Err(err).chain_err(|| EK::CannotGetReference(TEMPLATES_REF.to_owned())),
+    }
+}
+
+/// Update the templates of a repository
+///
+/// A new commit holding the templates supplied is created on top of the one
+/// the templates are currently read from, i.e. the one referred to by
+/// `TEMPLATES_REF` or its remote-tracking copy. `TEMPLATES_REF` is then
+/// updated.
+///
+pub fn update_templates(repo: &Repository,
+                        templates: &Templates,
+                        author: &git2::Signature,
+                        committer: &git2::Signature,
+                        message: &str,
+) -> Result<Oid> {
+    let tree = repo.find_tree(templates.write_tree(repo)?)?;
+    let parent = match templates_reference(repo)? {
+        Some((name, reference)) => Some(reference.peel_to_commit().chain_err(|| EK::CannotGetReference(name))?),
+        None => None,
+    };
+
+    repo.commit(Some(TEMPLATES_REF), author, committer, message, &tree, &parent.iter().collect::<Vec<_>>())
+        .chain_err(|| EK::CannotSetReference(TEMPLATES_REF.to_owned()))
+}
+
+
+/// Find the reference the templates of a repository are read from
+///
+/// Returns `TEMPLATES_REF` or, if it does not exist, the first of its
+/// remote-tracking copies along with its name.
+///
+fn templates_reference(repo: &Repository) -> Result<Option<(String, git2::Reference)>> {
+    match repo.find_reference(TEMPLATES_REF) {
+        Ok(reference) => return Ok(Some((TEMPLATES_REF.to_owned(), reference))),
+        Err(ref err) if err.code() == git2::ErrorCode::NotFound => (),
+        Err(err) => return Err(err).chain_err(|| EK::CannotGetReference(TEMPLATES_REF.to_owned())),
+    }
+
+    let mut remote_refs = Vec::new();
+    for reference in repo.references_glob(REMOTE_TEMPLATES_GLOB)? {
+        let reference = reference?;
+        let name = String::from_utf8_lossy(reference.name_bytes()).into_owned();
+        remote_refs.push((name, reference));
+    }
+    remote_refs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(remote_refs.into_iter().next())
+}
+
+/// Read the templates from the tree a reference refers to
+///
+fn read_reference(repo: &Repository, reference: git2::Reference, name: &str) -> Result<Templates> {
+    let tree = reference
+        .peel_to_tree()
+        .chain_err(|| EK::CannotGetReference(name.to_owned()))?;
+    Templates::from_tree(repo, &tree)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // Template tests
+
+    #[test]
+    fn template_names() {
+        assert!(check_name("bug").is_ok());
+        assert!(check_name("feature-request").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name(".hidden").is_err());
+        assert!(check_name("foo/bar").is_err());
+
+        let mut templates = Templates::new();
+        assert!(templates.insert("foo/bar", "").is_err());
+        assert!(templates.is_empty());
+    }
+
+    #[test]
+    fn placeholder_parsing() {
+        assert_eq!(Placeholder::parse("env:HOME"), Placeholder::Env("HOME"));
+        assert_eq!(Placeholder::parse(" config: user.name "), Placeholder::Config("user.name"));
+        assert_eq!(Placeholder::parse("prompt:Version?"), Placeholder::Prompt("Version?"));
+        assert_eq!(Placeholder::parse(" os "), Placeholder::Variable("os"));
+    }
+
+    #[test]
+    fn template_expansion() {
+        let expanded = expand("OS: {{os}}\nUser: {{ config:user.name }}\n{{unterminated", |placeholder| {
+            match placeholder {
+                Placeholder::Variable("os") => Ok("linux".to_owned()),
+                Placeholder::Config("user.name") => Ok("Foo Bar".to_owned()),
+                _ => panic!("Unexpected placeholder"),
+            }
+        }).expect("Could not expand template");
+        assert_eq!(expanded, "OS: linux\nUser: Foo Bar\n{{unterminated");
+
+        assert_eq!(expand("No placeholders", |_| panic!("Unexpected placeholder")).expect("Could not expand template"),
+                   "No placeholders");
+        assert!(expand("{{env:FOO}}", |_| Err(Error::from_kind(EK::CannotBuildTree))).is_err());
+    }
+
+    #[test]
+    fn templates_roundtrip() {
+        let mut testing_repo = TestingRepo::new("templates_roundtrip");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+
+        assert!(templates(repo).expect("Could not read templates").is_empty());
+
+        let mut shipped = Templates::new();
+        shipped.insert("bug", "Bug: \n\nSteps to reproduce:\n").expect("Could not add template");
+        shipped.insert("feature", "Feature: \n").expect("Could not add template");
+        let first = update_templates(repo, &shipped, &sig, &sig, "Add templates")
+            .expect("Could not update templates");
+
+        let read = templates(repo).expect("Could not read templates");
+        assert_eq!(read, shipped);
+        assert!(has_local_templates(repo).expect("Could not check templates"));
+        assert_eq!(read.names().collect::<Vec<_>>(), vec!["bug", "feature"]);
+        assert_eq!(read.get("feature"), Some("Feature: \n"));
+        assert_eq!(read.get("question"), None);
+
+        shipped.remove("feature");
+        let second = update_templates(repo, &shipped, &sig, &sig, "Remove template")
+            .expect("Could not update templates");
+        let commit = repo.find_commit(second).expect("Could not find commit");
+        assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![first]);
+
+        // Templates fetched from a remote are used in the absence of local ones
+        repo.reference("refs/remotes/origin/dit/meta/templates", second, false, "fetch")
+            .expect("Could not create reference");
+        repo.find_reference(TEMPLATES_REF)
+            .and_then(|mut reference| reference.delete())
+            .expect("Could not delete reference");
+        assert_eq!(templates(repo).expect("Could not read templates"), shipped);
+        assert!(!has_local_templates(repo).expect("Could not check templates"));
+
+        // Local templates are based on the ones fetched
+        let third = update_templates(repo, &Templates::new(), &sig, &sig, "Remove all templates")
+            .expect("Could not update templates");
+        let commit = repo.find_commit(third).expect("Could not find commit");
+        assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![second]);
+        assert!(has_local_templates(repo).expect("Could not check templates"));
+        assert!(templates(repo).expect("Could not read templates").is_empty());
+    }
+}
@@ -13,9 +13,13 @@
 //! purposes.
 //!
 
-use git2::{self, Repository};
+use git2::{self, Oid, Repository};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::fs;
+use std::sync::Mutex;
+
+use message::crypt::{self, Cipher};
 
 
 /// Testing repository
@@ -54,3 +58,110 @@ impl TestingRepo {
     }
 }
 
+
+/// Assemble a commit-graph file for the given commits
+///
+/// The parents of all commits supplied have to be supplied as well.
+///
+pub fn commit_graph(repo: &Repository, ids: &[Oid]) -> Vec<u8> {
+    let mut ids = ids.to_vec();
+    ids.sort();
+    let pos: HashMap<_, _> = ids.iter().enumerate().map(|(pos, id)| (*id, pos as u32)).collect();
+    let parents: Vec<Vec<u32>> = ids
+        .iter()
+        .map(|id| repo
+            .find_commit(*id)
+            .expect("Could not find commit")
+            .parent_ids()
+            .map(|parent| pos[&parent])
+            .collect())
+        .collect();
+
+    // generation numbers, computed until they don't change any more
+    let mut generations = vec![1u32; ids.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (commit, parents) in parents.iter().enumerate() {
+            let generation = parents.iter().map(|p| generations[*p as usize] + 1).max().unwrap_or(1);
+            if generation != generations[commit] {
+                generations[commit] = generation;
+                changed = true;
+            }
+        }
+    }
+
+    let mut fanout = Vec::new();
+    for byte in 0..256 {
+        let count = ids.iter().filter(|id| (id.as_bytes()[0] as usize) <= byte).count() as u32;
+        fanout.extend_from_slice(&count.to_be_bytes());
+    }
+    let mut oids = Vec::new();
+    let mut data = Vec::new();
+    let mut edges = Vec::new();
+    for (commit, id) in ids.iter().enumerate() {
+        oids.extend_from_slice(id.as_bytes());
+        data.extend_from_slice(&[0; 20]);
+        let parents = &parents[commit];
+        let first = parents.first().cloned().unwrap_or(0x7000_0000);
+        let second = match parents.len() {
+            0 | 1 => 0x7000_0000,
+            2 => parents[1],
+            _ => {
+                let start = (edges.len() / 4) as u32 | 0x8000_0000;
+                for (n, parent) in parents[1..].iter().enumerate() {
+                    let last = if n == parents.len() - 2 { 0x8000_0000 } else { 0 };
+                    edges.extend_from_slice(&(parent | last).to_be_bytes());
+                }
+                start
+            },
+        };
+        data.extend_from_slice(&first.to_be_bytes());
+        data.extend_from_slice(&second.to_be_bytes());
+        data.extend_from_slice(&((generations[commit] as u64) << 34).to_be_bytes());
+    }
+
+    let chunks: Vec<(&[u8], Vec<u8>)> = vec![
+        (b"OIDF", fanout),
+        (b"OIDL", oids),
+        (b"CDAT", data),
+        (b"EDGE", edges),
+    ];
+    let mut retval = b"CGPH\x01\x01".to_vec();
+    retval.push(chunks.len() as u8);
+    retval.push(0);
+    let mut offset = (8 + (chunks.len() + 1) * 12) as u64;
+    for &(id, ref content) in &chunks {
+        retval.extend_from_slice(id);
+        retval.extend_from_slice(&offset.to_be_bytes());
+        offset += content.len() as u64;
+    }
+    retval.extend_from_slice(&[0; 4]);
+    retval.extend_from_slice(&offset.to_be_bytes());
+    for (_, content) in chunks {
+        retval.extend(content);
+    }
+    retval
+}
+
+
+lazy_static! {
+    // lock serializing tests which register a decryption cipher
+    static ref CIPHER_LOCK: Mutex<()> = Mutex::new(());
+}
+
+
+/// Run a function with a decryption cipher registered
+///
+/// Since the cipher is registered globally, tests registering a cipher are
+/// serialized. The cipher is unregistered afterwards.
+///
+pub fn with_decryption_cipher<F, R>(cipher: Box<Cipher>, f: F) -> R
+    where F: FnOnce() -> R
+{
+    let _lock = CIPHER_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    crypt::set_decryption_cipher(Some(cipher));
+    let retval = f();
+    crypt::set_decryption_cipher(None);
+    retval
+}
@@ -0,0 +1,36 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Instrumentation
+//!
+//! With the "trace" feature enabled, the library emits spans and events via
+//! the `tracing` crate, e.g. for each issue processed by the garbage
+//! collector. Spans are entered via `trace_span!` and events emitted via
+//! `trace_event!`, both of which accept the arguments of `tracing::debug!`.
+//! Without the feature, the macros expand to nothing and their arguments are
+//! not evaluated.
+//!
+
+/// Enter a span for the remainder of the current scope
+///
+macro_rules! trace_span {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "trace")]
+        let _span = ::tracing::debug_span!($($arg)+).entered();
+    };
+}
+
+/// Emit an event
+///
+macro_rules! trace_event {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "trace")]
+        ::tracing::debug!($($arg)+);
+    };
+}
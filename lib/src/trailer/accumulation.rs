@@ -24,10 +24,16 @@ use trailer::{Trailer, TrailerValue};
 /// These enum values represent accumulation policies for trailers, e.g. how
 /// trailer values are accumulated.
 ///
+/// With the `Set` policy, values prefixed with a `-` remove a value from the
+/// set while other values, optionally prefixed with a `+`, add a value. Like
+/// with the `Latest` policy, trailers are expected to be processed from the
+/// newest to the oldest one.
+///
 #[derive(Clone)]
 pub enum AccumulationPolicy {
     Latest,
     List,
+    Set,
 }
 
 
@@ -40,6 +46,7 @@ pub enum AccumulationPolicy {
 pub enum ValueAccumulator {
     Latest(Option<TrailerValue>),
     List(Vec<TrailerValue>),
+    Set(Vec<TrailerValue>, collections::HashSet<String>),
 }
 
 impl ValueAccumulator {
@@ -51,15 +58,41 @@ impl ValueAccumulator {
                 *value = Some(new_value);
             },
             &mut ValueAccumulator::List(ref mut values)  => values.push(new_value),
+            &mut ValueAccumulator::Set(ref mut values, ref mut decided) => {
+                let (add, value) = parse_set_change(&new_value.to_string());
+                // Only the newest change of a value counts
+                if decided.insert(value.clone()) && add {
+                    values.push(TrailerValue::from_slice(&value));
+                }
+            },
         }
     }
 }
 
+
+/// Parse a change to a set of values
+///
+/// Returns whether the value is to be added, e.g. not removed, and the value
+/// without its prefix.
+///
+fn parse_set_change(value: &str) -> (bool, String) {
+    let value = value.trim();
+    if value.starts_with('-') {
+        (false, value[1..].trim_start().to_owned())
+    } else if value.starts_with('+') {
+        (true, value[1..].trim_start().to_owned())
+    } else {
+        (true, value.to_owned())
+    }
+}
+
+
 impl From<AccumulationPolicy> for ValueAccumulator {
     fn from(policy: AccumulationPolicy) -> Self {
         match policy {
             AccumulationPolicy::Latest  => ValueAccumulator::Latest(None),
             AccumulationPolicy::List    => ValueAccumulator::List(Vec::new()),
+            AccumulationPolicy::Set     => ValueAccumulator::Set(Vec::new(), Default::default()),
         }
     }
 }
@@ -72,6 +105,7 @@ impl IntoIterator for ValueAccumulator {
         match self {
             ValueAccumulator::Latest(value) => Box::new(value.into_iter()),
             ValueAccumulator::List(values)  => Box::new(values.into_iter()),
+            ValueAccumulator::Set(values, _) => Box::new(values.into_iter()),
         }
     }
 }
@@ -271,6 +305,22 @@ mod tests {
         assert_eq!(values.next(), None);
     }
 
+    #[test]
+    fn accumulate_set() {
+        let mut acc = ValueAccumulator::from(AccumulationPolicy::Set);
+        acc.process(TrailerValue::from_slice("-question"));
+        acc.process(TrailerValue::from_slice("+bug"));
+        acc.process(TrailerValue::from_slice("question"));
+        acc.process(TrailerValue::from_slice("-bug"));
+        acc.process(TrailerValue::from_slice("foo-bar"));
+        acc.process(TrailerValue::from_slice("bug"));
+
+        let mut values = acc.into_iter();
+        assert_eq!(values.next().expect("Could not retrieve value").to_string(), "bug");
+        assert_eq!(values.next().expect("Could not retrieve value").to_string(), "foo-bar");
+        assert_eq!(values.next(), None);
+    }
+
     // Accumulator tests
 
     #[test]
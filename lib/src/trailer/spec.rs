@@ -19,6 +19,11 @@ use std::iter::FromIterator;
 use trailer::accumulation::{AccumulationPolicy, SingleAccumulator, ValueAccumulator};
 
 
+/// Prefix of the keys of git-dit specific trailers
+///
+pub const DIT_KEY_PREFIX: &'static str = "Dit-";
+
+
 /// Metadata specification
 ///
 /// Use instances of this type for specifying the names and accumulation rules
@@ -53,6 +58,112 @@ pub const ISSUE_STATUS_SPEC: TrailerSpec = TrailerSpec {
     accumulation: AccumulationPolicy::Latest,
 };
 
+/// Metadata specification for the reason of an issue's status
+///
+/// The reason is recorded along with status changes, e.g. when closing an
+/// issue as a duplicate.
+///
+pub const ISSUE_STATUS_REASON_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-status-reason",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for an issue's tags
+///
+pub const ISSUE_TAG_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-tag",
+    accumulation: AccumulationPolicy::Set,
+};
+
+/// Metadata specification for an issue's assignees
+///
+pub const ISSUE_ASSIGNEE_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-assignee",
+    accumulation: AccumulationPolicy::Set,
+};
+
+/// Metadata specification for an issue's milestone
+///
+pub const ISSUE_MILESTONE_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-milestone",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for an issue's priority
+///
+/// The value is expected to be a number, optionally prefixed with "P". Lower
+/// numbers denote higher priorities.
+///
+pub const ISSUE_PRIORITY_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-priority",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for an issue's due date
+///
+/// The value is expected to be of the form "YYYY-MM-DD".
+///
+pub const ISSUE_DUE_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-due",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for references to related issues
+///
+/// The values are expected to be of the form `<repo-url>#<issue-id>`.
+///
+pub const SEE_ALSO_SPEC: TrailerSpec = TrailerSpec {
+    key: "See-also",
+    accumulation: AccumulationPolicy::List,
+};
+
+/// Metadata specification for issues blocked by an issue
+///
+/// The values are either ids of issues in the same repository or references
+/// of the form `<repo-url>#<issue-id>`.
+///
+pub const BLOCKS_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-blocks",
+    accumulation: AccumulationPolicy::Set,
+};
+
+/// Metadata specification for issues an issue depends on
+///
+/// The values are of the same form as those of `BLOCKS_SPEC`.
+///
+pub const DEPENDS_ON_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-depends-on",
+    accumulation: AccumulationPolicy::Set,
+};
+
+/// Metadata specification for the issue an issue duplicates
+///
+/// The value is of the same form as those of `BLOCKS_SPEC`.
+///
+pub const DUPLICATE_OF_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-duplicate-of",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for the origin of imported messages
+///
+/// The values identify the item of another tracker a message was imported
+/// from, prefixed with the kind of tracker, e.g. `github:<owner>/<repo>#1`.
+///
+pub const ORIGIN_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-origin",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for anonymous reporters
+///
+/// The values are keyed hashes of the real authors' email addresses.
+///
+pub const REPORTER_HMAC_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-reporter-hmac",
+    accumulation: AccumulationPolicy::List,
+};
+
 
 /// Construct an accumulation map from a set of MetadataSpecifications
 ///
@@ -0,0 +1,165 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Batched reference updates
+//!
+//! This module provides the `RefTransaction`, which collects updates and
+//! deletions of several references and applies them together. Each reference
+//! is locked as soon as an update is recorded and all of them are written only
+//! when the transaction is committed. Hence, an operation which is interrupted
+//! or which fails to lock one of its references leaves all of them untouched.
+//!
+//! Note that the final writes are not atomic: if writing one of the references
+//! fails during the commit, e.g. because the disk is full, updates which were
+//! already written are not rolled back.
+//!
+
+use git2::{self, Oid};
+use std::result::Result as RResult;
+
+use observer::{self, Operation};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Transaction updating several references at once
+///
+pub struct RefTransaction<'r> {
+    repo: &'r git2::Repository,
+    transaction: git2::Transaction<'r>,
+    refnames: Vec<String>,
+    operations: Vec<Operation>,
+}
+
+impl<'r> RefTransaction<'r> {
+    /// Start a new transaction
+    ///
+    pub fn new(repo: &'r git2::Repository) -> Result<Self> {
+        let transaction = repo
+            .transaction()
+            .chain_err(|| EK::CannotUpdateReferences(Vec::new()))?;
+        Ok(RefTransaction {
+            repo: repo,
+            transaction: transaction,
+            refnames: Vec::new(),
+            operations: Vec::new(),
+        })
+    }
+
+    /// Set a reference to a target, creating it if necessary
+    ///
+    /// The update is recorded in the reference's reflog with the message
+    /// supplied.
+    ///
+    pub fn set_target(&mut self, refname: &str, target: Oid, reflogmsg: &str) -> Result<()> {
+        self.lock(refname)
+            .and_then(|_| self.repo.reference_ensure_log(refname))
+            .and_then(|_| self.transaction.set_target(refname, target, None, reflogmsg))
+            .chain_err(|| EK::CannotSetReference(refname.to_owned()))?;
+        self.operations.extend(Operation::for_update(refname, target));
+        Ok(())
+    }
+
+    /// Delete a reference
+    ///
+    pub fn remove(&mut self, refname: &str) -> Result<()> {
+        self.lock(refname)
+            .and_then(|_| self.transaction.remove(refname))
+            .chain_err(|| EK::CannotDeleteReference(refname.to_owned()))?;
+        self.operations.push(Operation::ReferenceDeleted(refname.to_owned()));
+        Ok(())
+    }
+
+    /// Get the names of the references affected by the transaction
+    ///
+    pub fn refnames(&self) -> &[String] {
+        &self.refnames
+    }
+
+    /// Write all the updates recorded
+    ///
+    /// If the transaction is dropped without being committed, none of the
+    /// references is changed. Observers are notified of the updates once all
+    /// of them are written.
+    ///
+    pub fn commit(self) -> Result<()> {
+        let refnames = self.refnames;
+        self.transaction
+            .commit()
+            .chain_err(|| EK::CannotUpdateReferences(refnames))?;
+        for operation in &self.operations {
+            observer::notify(self.repo, operation);
+        }
+        Ok(())
+    }
+
+    /// Lock a reference, unless it is already locked
+    ///
+    fn lock(&mut self, refname: &str) -> RResult<(), git2::Error> {
+        if !self.refnames.iter().any(|name| name == refname) {
+            self.transaction.lock_ref(refname)?;
+            self.refnames.push(refname.to_owned());
+        }
+        Ok(())
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use repository::RepositoryExt;
+
+    #[test]
+    fn ref_transaction() {
+        let mut testing_repo = TestingRepo::new("ref_transaction");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let message = repo
+            .commit(None, &sig, &sig, "Test message 1", &empty_tree, &[])
+            .expect("Could not create commit");
+
+        repo.reference("refs/dit/foo", message, false, "test")
+            .expect("Could not create reference");
+
+        {
+            let mut transaction = RefTransaction::new(repo).expect("Could not start transaction");
+            transaction
+                .set_target("refs/dit/bar", message, "test")
+                .expect("Could not set reference");
+            transaction
+                .remove("refs/dit/foo")
+                .expect("Could not remove reference");
+        }
+        assert!(repo.find_reference("refs/dit/foo").is_ok());
+        assert!(repo.find_reference("refs/dit/bar").is_err());
+
+        let mut transaction = RefTransaction::new(repo).expect("Could not start transaction");
+        transaction
+            .set_target("refs/dit/bar", message, "test")
+            .expect("Could not set reference");
+        transaction
+            .remove("refs/dit/foo")
+            .expect("Could not remove reference");
+        assert_eq!(transaction.refnames().len(), 2);
+        transaction.commit().expect("Could not commit transaction");
+        assert!(repo.find_reference("refs/dit/foo").is_err());
+        assert_eq!(repo.find_reference("refs/dit/bar").unwrap().target(), Some(message));
+    }
+}
@@ -12,8 +12,12 @@
 //! This module provides utilities private to this library.
 //!
 
+use git2::{self, Oid, Reference};
 use std::result::Result as RResult;
 
+use error::*;
+use error::ErrorKind as EK;
+
 
 /// Trait for pre-accumulating results
 pub trait ResultIterExt<I, E> : Sized {
@@ -42,3 +46,12 @@ impl<I, E, J> ResultIterExt<I, E> for J
     }
 }
 
+
+/// Get the id of the message a reference refers to
+///
+pub(crate) fn reference_target(reference: &Reference) -> Result<Oid> {
+    reference
+        .peel(git2::ObjectType::Commit)
+        .map(|obj| obj.id())
+        .chain_err(|| EK::CannotGetReference(String::from_utf8_lossy(reference.name_bytes()).into_owned()))
+}
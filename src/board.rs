@@ -0,0 +1,204 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Kanban boards
+//!
+//! This module groups issues into the columns of a board according to their
+//! status, e.g. for a visual triage view. The columns may be configured via
+//! the `dit.board.<column>.status` configuration option, which may be given
+//! multiple times for assigning multiple statuses to a column. Issues whose
+//! status is not assigned to a column are placed in a column named after the
+//! status. Closed issues are omitted unless "closed" is assigned to a column.
+//!
+
+use git2::{self, Commit, Repository};
+use libgitdit::Issue;
+use libgitdit::trailer::spec;
+use serde_json::Value;
+
+use error::*;
+use html;
+use json;
+use util::RepositoryUtil;
+
+
+/// Assignment of statuses to columns
+///
+pub struct Columns {
+    columns: Vec<(String, Vec<String>)>,
+}
+
+impl Columns {
+    /// Create a new assignment without any configured columns
+    ///
+    pub fn new() -> Self {
+        Columns { columns: Vec::new() }
+    }
+
+    /// Create an assignment from the columns configured in a git config
+    ///
+    /// The columns are ordered by their first occurrence in the config.
+    ///
+    pub fn from_config(config: &git2::Config) -> Result<Self> {
+        let mut retval = Self::new();
+
+        let entries = config.entries(Some(r"^dit\.board\..*\.status$"))?;
+        for entry in &entries {
+            let entry = entry?;
+            if let (Some(name), Some(status)) = (entry.name(), entry.value()) {
+                // The name of the column may itself contain dots.
+                let column = &name["dit.board.".len()..name.len() - ".status".len()];
+                retval.add_status(column, status);
+            }
+        }
+        Ok(retval)
+    }
+
+    /// Assign a status to a column
+    ///
+    /// The column is appended if it is not known yet.
+    ///
+    pub fn add_status(&mut self, column: &str, status: &str) {
+        match self.columns.iter().position(|&(ref name, _)| name == column) {
+            Some(index) => self.columns[index].1.push(status.to_owned()),
+            None => self.columns.push((column.to_owned(), vec![status.to_owned()])),
+        }
+    }
+
+    /// Get the name of the column configured for a status, if any
+    ///
+    fn column_of(&self, status: &str) -> Option<&str> {
+        self.columns
+            .iter()
+            .find(|&&(_, ref statuses)| statuses.iter().any(|s| s == status))
+            .map(|&(ref name, _)| name.as_str())
+    }
+}
+
+
+/// Column of a board
+///
+pub struct Column<'r> {
+    pub name: String,
+    pub statuses: Vec<String>,
+    pub issues: Vec<(Issue<'r>, Commit<'r>)>,
+}
+
+
+/// Arrange the issues supplied on a board
+///
+/// The configured columns come first, even if they are empty, followed by the
+/// columns for statuses not assigned to any column, in the order in which they
+/// are encountered. Each issue is accompanied by its initial message. Issues
+/// with hidden initial messages are omitted.
+///
+pub fn arrange<'r, I>(repo: &'r Repository,
+                      issues: I,
+                      hidden: &Fn(&Commit) -> bool,
+                      columns: &Columns
+) -> Result<Vec<Column<'r>>>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    let mut retval: Vec<Column> = columns
+        .columns
+        .iter()
+        .map(|&(ref name, ref statuses)| Column {
+            name: name.clone(),
+            statuses: statuses.clone(),
+            issues: Vec::new(),
+        })
+        .collect();
+
+    for issue in issues {
+        let initial = issue.initial_message()?;
+        if hidden(&initial) {
+            continue;
+        }
+
+        let status = html::issue_status(repo, &issue);
+        let name = match columns.column_of(&status) {
+            Some(name) => name.to_owned(),
+            None if status == "closed" => continue,
+            None => status.clone(),
+        };
+        match retval.iter().position(|column| column.name == name) {
+            Some(index) => retval[index].issues.push((issue, initial)),
+            None => retval.push(Column {
+                name: name,
+                statuses: vec![status],
+                issues: vec![(issue, initial)],
+            }),
+        }
+    }
+    Ok(retval)
+}
+
+
+/// Represent a board
+///
+/// Issues are represented like by `json::issue()`.
+///
+pub fn value<'r>(repo: &'r Repository, columns: &[Column<'r>]) -> Value {
+    let columns: Vec<_> = columns
+        .iter()
+        .map(|column| json!({
+            "name": column.name,
+            "statuses": column.statuses,
+            "issues": column
+                .issues
+                .iter()
+                .map(|&(ref issue, ref initial)| json::issue(repo, issue, initial))
+                .collect::<Vec<_>>(),
+        }))
+        .collect();
+    json!({ "columns": columns })
+}
+
+
+/// Style of the board's columns
+///
+const COLUMN_STYLE: &str = "flex: 0 0 16em; background: #eee; padding: 0.5em; margin-right: 1em;";
+
+/// Style of the board's cards
+///
+const CARD_STYLE: &str = "background: #fff; border: 1px solid #ccc; padding: 0.5em; margin: 0.5em 0;";
+
+
+/// Render a board as a standalone HTML page
+///
+/// Each issue is rendered as a card holding its abbreviated id, its subject,
+/// and its assignees and tags, if any.
+///
+pub fn page<'r>(repo: &'r Repository, columns: &[Column<'r>], id_len: usize) -> String {
+    let mut content = String::from("<div style=\"display: flex; align-items: flex-start;\">");
+    for column in columns {
+        content.push_str(&format!("<div style=\"{}\"><h2>{} ({})</h2>",
+                                  COLUMN_STYLE,
+                                  html::escape(&column.name),
+                                  column.issues.len()));
+        for &(ref issue, ref initial) in &column.issues {
+            content.push_str(&format!("<div style=\"{}\"><small>{:.*}</small><br>{}",
+                                      CARD_STYLE,
+                                      id_len,
+                                      issue.id(),
+                                      html::escape(initial.summary().unwrap_or(""))));
+            let head = repo.issue_head(issue);
+            for &(label, spec) in &[("Assignees", &spec::ISSUE_ASSIGNEE_SPEC), ("Tags", &spec::ISSUE_TAG_SPEC)] {
+                let values = repo.metadata_values(issue, &head, spec);
+                if !values.is_empty() {
+                    content.push_str(&format!("<br><small>{}: {}</small>", label, html::escape(&values.join(", "))));
+                }
+            }
+            content.push_str("</div>");
+        }
+        content.push_str("</div>");
+    }
+    content.push_str("</div>");
+    html::page("Board", "", &content)
+}
@@ -0,0 +1,259 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Conversion of Bugzilla exports
+//!
+//! This module converts the XML documents produced by Bugzilla's "XML" bug
+//! view to the representation of issues accepted by the `import` module. Each
+//! bug becomes an issue whose comments form a chain of replies. Keywords, the
+//! assignee and the target milestone are converted to trailers. Attachments
+//! are attached to the comments created along with them, or represented by
+//! messages of their own. Textual attachments, e.g. patches, are included in
+//! the messages if the export contains the attachments' data.
+//!
+//! The export does not contain the history of changes made to a bug. A bug
+//! which is resolved is closed by a message at the time of its last change,
+//! with the resolution as the reason.
+//!
+
+use chrono::{DateTime, FixedOffset};
+use libgitdit::trailer::spec;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use error::*;
+use error::ErrorKind as EK;
+use import;
+use mail;
+use xml::{self, Element};
+
+
+/// Prefix of the origins of messages imported from Bugzilla
+///
+const ORIGIN_PREFIX: &str = "bugzilla:";
+
+
+/// Convert a Bugzilla XML document to issues
+///
+/// Bugs which could not be exported, e.g. due to missing permissions, are
+/// skipped. The ids of the messages are derived from the installation's URL
+/// and the bugs' numbers.
+///
+pub fn issue_values(document: &str) -> Result<Vec<Value>> {
+    let root = xml::parse(document)?;
+    if root.name != "bugzilla" {
+        return Err(Error::from_kind(EK::MalformedImport(format!("unexpected element '{}'", root.name))));
+    }
+
+    let installation = root
+        .attribute("urlbase")
+        .map(|url| url.splitn(2, "://").last().unwrap_or(url).trim_end_matches('/'))
+        .unwrap_or("bugzilla");
+    let maintainer = root.attribute("maintainer").unwrap_or("");
+    root.elements("bug")
+        .filter(|bug| bug.attribute("error").is_none())
+        .map(|bug| issue_value(installation, maintainer, bug))
+        .collect()
+}
+
+
+/// Convert a single bug to an issue
+///
+/// Private comments and attachments are not converted. The message closing a
+/// resolved bug is attributed to the installation's maintainer.
+///
+fn issue_value(installation: &str, maintainer: &str, bug: &Element) -> Result<Value> {
+    let id = format!("{}#{}", installation, bug.child_text("bug_id"));
+    let title = bug.child_text("short_desc");
+    let reply_subject = format!("Re: {}", title);
+
+    let mut attachments: HashMap<String, &Element> = bug
+        .elements("attachment")
+        .filter(|attachment| !private(attachment))
+        .map(|attachment| (attachment.child_text("attachid"), attachment))
+        .collect();
+    let mut comments = bug.elements("long_desc").filter(|comment| !private(comment));
+
+    let mut trailers = Vec::new();
+    for keyword in bug.child_text("keywords").split(',').map(str::trim).filter(|keyword| !keyword.is_empty()) {
+        trailers.push((spec::ISSUE_TAG_SPEC.key, keyword.to_owned()));
+    }
+    if let Some(assignee) = bug.child("assigned_to") {
+        trailers.push((spec::ISSUE_ASSIGNEE_SPEC.key, user(assignee)));
+    }
+    let milestone = bug.child_text("target_milestone");
+    if !milestone.is_empty() && milestone != "---" {
+        trailers.push((spec::ISSUE_MILESTONE_SPEC.key, milestone));
+    }
+    // The first comment is the bug's description, which may carry an
+    // attachment submitted along with the bug.
+    let description = comments
+        .next()
+        .map(|comment| comment_text(comment, &mut attachments))
+        .unwrap_or_default();
+    let date = parse_date(&bug.child_text("creation_ts"))?;
+    let reporter = bug.child("reporter").map(identity).unwrap_or_else(|| identity_of("", ""));
+    let mut messages = vec![(date, message(id.clone(), reporter, date, title, &description, trailers))];
+
+    let mut items = Vec::new();
+    for (number, comment) in comments.enumerate() {
+        let comment_id = match comment.child_text("commentid") {
+            ref commentid if commentid.is_empty() => format!("{}/comment/{}", id, number + 1),
+            commentid => format!("{}/comment/{}", id, commentid),
+        };
+        let author = comment.child("who").map(identity).unwrap_or_else(|| identity_of("", ""));
+        let date = parse_date(&comment.child_text("bug_when"))?;
+        let text = comment_text(comment, &mut attachments);
+        items.push((date, message(comment_id, author, date, reply_subject.clone(), &text, Vec::new())));
+    }
+    for attachment in bug.elements("attachment") {
+        let attachid = attachment.child_text("attachid");
+        if attachments.remove(&attachid).is_none() {
+            continue;
+        }
+        let attachment_id = format!("{}/attachment/{}", id, attachid);
+        let author = attachment.child("attacher").map(identity).unwrap_or_else(|| identity_of("", ""));
+        let date = parse_date(&attachment.child_text("date"))?;
+        let text = format!("{}\n\n{}", attachment.child_text("desc"), attachment_text(attachment));
+        items.push((date, message(attachment_id, author, date, reply_subject.clone(), &text, Vec::new())));
+    }
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    messages.extend(items);
+
+    let resolution = bug.child_text("resolution");
+    if !resolution.is_empty() {
+        let last = messages.last().map(|message| message.0).unwrap_or(date);
+        let date = parse_date(&bug.child_text("delta_ts")).ok().filter(|date| *date > last).unwrap_or(last);
+        let text = match bug.child_text("dup_id") {
+            ref duplicate if duplicate.is_empty() => format!("Resolved as {}.", resolution),
+            duplicate => format!("Marked as a duplicate of bug {}.", duplicate),
+        };
+        let trailers = vec![
+            (spec::ISSUE_STATUS_SPEC.key, "closed".to_owned()),
+            (spec::ISSUE_STATUS_REASON_SPEC.key, resolution.to_lowercase()),
+        ];
+        let closed = message(format!("{}/resolution", id), identity_of("Bugzilla", maintainer), date, reply_subject, &text, trailers);
+        messages.push((date, closed));
+    }
+
+    Ok(import::chain(id, messages.into_iter().map(|message| message.1).collect()))
+}
+
+
+/// Determine whether a comment or attachment is private
+///
+fn private(element: &Element) -> bool {
+    element.attribute("isprivate") == Some("1")
+}
+
+
+/// Assemble the text of a comment, including the attachment created with it
+///
+/// The attachment is removed from the attachments given.
+///
+fn comment_text(comment: &Element, attachments: &mut HashMap<String, &Element>) -> String {
+    let text = comment.child_text("thetext");
+    match attachments.remove(&comment.child_text("attachid")) {
+        Some(attachment) => format!("{}\n\n{}", text, attachment_text(attachment)),
+        None => text,
+    }
+}
+
+
+/// Describe an attachment
+///
+/// The description names the attachment's file and type. If the attachment
+/// is a patch or text and its data is part of the export, the data follows.
+///
+fn attachment_text(attachment: &Element) -> String {
+    let mut details = vec![attachment.child_text("type")];
+    let size = attachment.child_text("size");
+    if !size.is_empty() {
+        details.push(format!("{} bytes", size));
+    }
+    if attachment.attribute("isobsolete") == Some("1") {
+        details.push("obsolete".to_owned());
+    }
+    let mut retval = format!("Attachment {}: {} ({})",
+                             attachment.child_text("attachid"),
+                             attachment.child_text("filename"),
+                             details.join(", "));
+
+    let textual = attachment.attribute("ispatch") == Some("1") ||
+        attachment.child_text("type").starts_with("text/");
+    let data = attachment.child("data").filter(|_| textual).and_then(|data| match data.attribute("encoding") {
+        Some("base64") => String::from_utf8(mail::decode_base64(&data.text())).ok(),
+        _ => Some(data.text()),
+    });
+    if let Some(data) = data {
+        retval.push_str("\n\n");
+        retval.push_str(data.trim_end());
+    }
+    retval
+}
+
+
+/// Represent a message imported from Bugzilla
+///
+fn message(id: String,
+           author: (String, String),
+           date: DateTime<FixedOffset>,
+           subject: String,
+           text: &str,
+           trailers: Vec<(&str, String)>
+) -> Value {
+    let origin = format!("{}{}", ORIGIN_PREFIX, id);
+    import::message(id, origin, author, date.to_rfc3339(), subject, text, trailers)
+}
+
+
+/// Get the name and email address of a user element
+///
+/// User elements contain the user's login, usually an email address, and may
+/// carry the user's real name as an attribute.
+///
+fn identity(user: &Element) -> (String, String) {
+    identity_of(user.attribute("name").unwrap_or(""), user.text().trim())
+}
+
+
+/// Get the name and email address of a user from a name and login
+///
+/// Missing names are replaced by the login's local part and missing logins by
+/// "nobody".
+///
+fn identity_of(name: &str, login: &str) -> (String, String) {
+    let login = if login.is_empty() { "nobody" } else { login };
+    let name = if name.is_empty() { login.split('@').next().unwrap_or(login) } else { name };
+    (name.to_owned(), login.to_owned())
+}
+
+
+/// Format a user as used in trailers
+///
+fn user(user: &Element) -> String {
+    let (name, email) = identity(user);
+    format!("{} <{}>", name, email)
+}
+
+
+/// Parse a date as used by Bugzilla
+///
+/// Dates are of the form "2001-02-03 04:05:06 +0000". The seconds and the
+/// time zone may be missing, in which case UTC is assumed.
+///
+fn parse_date(date: &str) -> Result<DateTime<FixedOffset>> {
+    ["%Y-%m-%d %H:%M:%S %z", "%Y-%m-%d %H:%M %z"]
+        .iter()
+        .filter_map(|format| DateTime::parse_from_str(date, format)
+            .or_else(|_| DateTime::parse_from_str(&format!("{} +0000", date), format))
+            .ok())
+        .next()
+        .ok_or_else(|| Error::from_kind(EK::MalformedDate(date.to_owned())))
+}
@@ -0,0 +1,89 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Colorized output
+//!
+//! Output is colorized using ANSI escape sequences. The colors are chosen to
+//! resemble those used by git itself, e.g. hashes are displayed in yellow.
+//!
+
+
+/// Palette for colorizing output
+///
+/// A palette provides the escape sequences for the various elements of the
+/// output. If colors are disabled, all sequences are empty.
+///
+#[derive(Clone, Copy)]
+pub struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    /// Create a new palette
+    ///
+    pub fn new(enabled: bool) -> Self {
+        Palette { enabled: enabled }
+    }
+
+    /// Create a palette without colors
+    ///
+    pub fn plain() -> Self {
+        Self::new(false)
+    }
+
+    fn color(&self, sequence: &'static str) -> &'static str {
+        if self.enabled { sequence } else { "" }
+    }
+
+    /// Sequence for hashes
+    ///
+    pub fn id(&self) -> &'static str {
+        self.color("\x1b[33m")
+    }
+
+    /// Sequence for authors
+    ///
+    pub fn author(&self) -> &'static str {
+        self.color("\x1b[34m")
+    }
+
+    /// Sequence for tags and similar metadata
+    ///
+    pub fn tag(&self) -> &'static str {
+        self.color("\x1b[36m")
+    }
+
+    /// Sequence for an issue status
+    ///
+    /// Open issues are displayed in green, closed ones in red.
+    ///
+    pub fn status(&self, status: &str) -> &'static str {
+        match status {
+            "open"   => self.color("\x1b[32m"),
+            "closed" => self.color("\x1b[31m"),
+            _        => "",
+        }
+    }
+
+    /// Sequence resetting all colors
+    ///
+    pub fn reset(&self) -> &'static str {
+        self.color("\x1b[m")
+    }
+
+    /// Colorize a piece of text
+    ///
+    pub fn paint(&self, sequence: &str, text: &str) -> String {
+        if sequence.is_empty() {
+            text.to_owned()
+        } else {
+            format!("{}{}{}", sequence, text, self.reset())
+        }
+    }
+}
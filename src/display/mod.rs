@@ -9,10 +9,14 @@
 
 #[macro_use] mod formatter;
 
+mod color;
 mod message;
 mod msgtree;
+mod thread;
 
+pub use self::color::*;
 pub use self::formatter::*;
 pub use self::message::*;
 pub use self::msgtree::*;
+pub use self::thread::*;
 
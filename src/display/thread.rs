@@ -0,0 +1,201 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Helpers for displaying issues as threads
+//!
+//! In a threaded representation, each message is displayed below the message
+//! it replies to, indented according to its depth in the reply tree. Replies
+//! to the same message are displayed in chronological order.
+//!
+
+use chrono::format::strftime::StrftimeItems;
+use git2::{Commit, Oid};
+use libgitdit::Issue;
+use libgitdit::iter::MessagesExt;
+use libgitdit::message::Message;
+use libgitdit::trailer::spec::{ISSUE_ASSIGNEE_SPEC, ISSUE_STATUS_SPEC, ISSUE_TAG_SPEC};
+use std::collections::HashMap;
+
+use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter, Palette};
+use error::*;
+
+
+/// Render an issue as a thread
+///
+/// The lines returned start with a summary of the metadata accumulated from
+/// the head given, followed by the issue's messages in thread order, each
+/// indented according to its depth. Messages for which `hidden` returns true
+/// are replaced by a placeholder and quotes are folded after `max_quoted`
+/// lines. Hashes, authors and metadata are colorized using the palette given.
+///
+pub fn thread_lines<'r>(issue: &Issue<'r>,
+                        head: Oid,
+                        id_len: usize,
+                        hidden: &Fn(&Commit) -> bool,
+                        max_quoted: usize,
+                        palette: &Palette
+) -> Result<Vec<String>> {
+    // Summarize the metadata accumulated from the head
+    let specs = vec![
+        ("Status", ISSUE_STATUS_SPEC),
+        ("Tags", ISSUE_TAG_SPEC),
+        ("Assignees", ISSUE_ASSIGNEE_SPEC),
+    ];
+    let mut metadata = issue
+        .messages_from(head)?
+        .collect::<::std::result::Result<Vec<_>, _>>()?
+        .into_iter()
+        .accumulate_trailers(specs.iter().map(|spec| &spec.1));
+    let mut lines: Vec<String> = specs
+        .iter()
+        .map(|&(name, ref spec)| {
+            let mut values: Vec<String> = Vec::new();
+            for value in metadata.remove(spec.key).into_iter().flat_map(|acc| acc) {
+                let value = value.to_string();
+                let color = match name {
+                    "Status" => palette.status(&value),
+                    "Tags"   => palette.tag(),
+                    _        => palette.author(),
+                };
+                let value = palette.paint(color, &value);
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            if values.is_empty() {
+                values.push("-".to_owned());
+            }
+            format!("{}: {}", name, values.join(", "))
+        })
+        .collect();
+    lines.push(String::new());
+
+    let header : Vec<FT<_,_>> = tokenvec![
+        palette.id(), MFT::Id(id_len), MFT::IfId(head, tokenvec![" (head)"]), palette.reset(), " ",
+        palette.author(), MFT::Author, palette.reset(), FT::LineEnd,
+        "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd,
+        FT::LineEnd,
+        MFT::Subject, FT::LineEnd,
+        FT::LineEnd];
+    let hidden_header : Vec<FT<_,_>> = tokenvec![
+        palette.id(), MFT::Id(id_len), palette.reset(), " [hidden]", FT::LineEnd];
+
+    let messages = issue
+        .messages()?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+    // Render each message, indented according to its depth in the tree
+    for (depth, message) in thread_order(messages, issue.id()) {
+        let mut message_lines: Vec<String> = if hidden(&message) {
+            hidden_header.iter().formatted_lines(message.clone()).collect::<Result<_>>()?
+        } else {
+            let mut message_lines: Vec<String> = header
+                .iter()
+                .formatted_lines(message.clone())
+                .collect::<Result<_>>()?;
+            message_lines.extend(fold_quotes(message.body_lines(), max_quoted));
+            message_lines
+        };
+        message_lines.push(String::new());
+
+        let indent = "    ".repeat(depth);
+        lines.extend(message_lines.into_iter().map(|line| if line.is_empty() {
+            line
+        } else {
+            indent.clone() + &line
+        }));
+    }
+
+    Ok(lines)
+}
+
+
+/// Order messages as a reply tree
+///
+/// The messages supplied are ordered depth-first, starting with the message
+/// identified by `root`. Each message is returned along with its depth, i.e.
+/// the number of replies between it and the root. Only messages which are
+/// connected to the root via their first parents are returned.
+///
+pub fn thread_order<'r, I>(messages: I, root: Oid) -> Vec<(usize, Commit<'r>)>
+    where I: IntoIterator<Item = Commit<'r>>
+{
+    let mut root_message = None;
+    let mut replies: HashMap<Oid, Vec<Commit<'r>>> = HashMap::new();
+
+    for message in messages {
+        if message.id() == root {
+            root_message = Some(message);
+        } else if let Some(parent) = message.parent_ids().next() {
+            replies.entry(parent).or_insert_with(Vec::new).push(message);
+        }
+    }
+
+    // Replies are visited in reverse order since we use a stack
+    for siblings in replies.values_mut() {
+        siblings.sort_by_key(|message| message.time());
+        siblings.reverse();
+    }
+
+    let mut retval = Vec::new();
+    let mut stack: Vec<_> = root_message.into_iter().map(|m| (0, m)).collect();
+    while let Some((depth, message)) = stack.pop() {
+        if let Some(siblings) = replies.remove(&message.id()) {
+            stack.extend(siblings.into_iter().map(|m| (depth + 1, m)));
+        }
+        retval.push((depth, message));
+    }
+    retval
+}
+
+
+/// Fold quoted text
+///
+/// Runs of quoted lines, e.g. lines starting with a `>`, which exceed the
+/// number of lines given are cut short. Only the first lines of such a run are
+/// retained, followed by a line stating how many lines were folded.
+///
+pub fn fold_quotes<I, S>(lines: I, max_lines: usize) -> Vec<String>
+    where I: IntoIterator<Item = S>,
+          S: Into<String>
+{
+    let mut retval = Vec::new();
+    let mut folded = 0;
+
+    for line in lines {
+        let line = line.into();
+        if !line.starts_with('>') {
+            push_fold_marker(&mut retval, folded);
+            folded = 0;
+            retval.push(line);
+        } else if quoted_tail(&retval) < max_lines {
+            retval.push(line);
+        } else {
+            folded += 1;
+        }
+    }
+    push_fold_marker(&mut retval, folded);
+
+    retval
+}
+
+/// Get the number of quoted lines at the end of a sequence of lines
+///
+fn quoted_tail(lines: &[String]) -> usize {
+    lines.iter().rev().take_while(|line| line.starts_with('>')).count()
+}
+
+/// Push a marker for folded lines, if any lines were folded
+///
+fn push_fold_marker(lines: &mut Vec<String>, folded: usize) {
+    if folded > 0 {
+        lines.push(format!("> [... {} more quoted lines]", folded));
+    }
+}
+
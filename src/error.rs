@@ -7,7 +7,10 @@
 // published by the Free Software Foundation.
 //
 
+use git2::{ErrorClass, ErrorCode, Oid};
+use libgitdit::error::{DitError, ErrorKind as LibErrorKind};
 use std::error::Error as EError;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 
 error_chain! {
@@ -19,59 +22,396 @@ error_chain! {
     errors {
         MalformedFilterSpec(spec: String) {
             description("Malformed filter spec")
-            display("Malformed filter spec: {}", spec)
+            display("{}", tr!("Malformed filter spec: {}", spec))
         }
 
         MalformedAuthor(author: String) {
             description("Malformed author (expected author formatted like 'Foo Bar <foo@bar.net>')")
-            display("Malformed author: {}", author)
+            display("{}", tr!("Malformed author: {}", author))
         }
 
         MalformedDate(date: String) {
             description("Malformed date (expected rfc3339/iso8601)")
-            display("Malformed date: {}", date)
+            display("{}", tr!("Malformed date: {}", date))
         }
 
         UnknownMetadataKey(key: String) {
             description("Unknown metadata key")
-            display("Unknown metadata key: {}", key)
+            display("{}", tr!("Unknown metadata key: {}", key))
+        }
+
+        UnknownIdentity(identity: String) {
+            description("Unknown identity (expected an email address or a configured alias)")
+            display("{}", tr!("Unknown identity: {}", identity))
         }
 
         WrappedIOError {
             description("IO Error")
-            display("IO Error")
+            display("{}", tr!("IO Error"))
+        }
+
+        DatabaseError {
+            description("Database error")
+            display("{}", tr!("Database error"))
         }
 
         ProgramError(program_name: String) {
             description("Could not find some configuration or ENV variable specifying a program")
-            display("Could not find {} configuration or ENV variable", program_name)
+            display("{}", tr!("Could not find {} configuration or ENV variable", program_name))
         }
 
         ChildError {
             description("A child program was unsuccessful")
-            display("A child program was unsuccessful")
+            display("{}", tr!("A child program was unsuccessful"))
+        }
+
+        UnknownEncryptionBackend(backend: String) {
+            description("Unknown encryption backend (expected 'gpg' or 'age')")
+            display("{}", tr!("Unknown encryption backend: {}", backend))
+        }
+
+        ServerError(address: String, reason: String) {
+            description("Could not start the web server")
+            display("{}", tr!("Could not serve on {}: {}", address, reason))
+        }
+
+        EncryptionNotConfigured {
+            description("Encryption is not configured")
+            display("{}", tr!("Encryption requires 'dit.encryption.backend' and 'dit.encryption.recipients' to be set"))
+        }
+
+        NoCurrentIssue {
+            description("No issue was specified and there is no current issue")
+            display("{}", tr!("No issue specified and no current issue set (see 'git dit checkout')"))
+        }
+
+        UnknownProfile(profile: String) {
+            description("Unknown identity profile")
+            display("{}", tr!("Unknown identity profile '{}' (see dit.identity.<name>.*)", profile))
+        }
+
+        NoRemote {
+            description("No remote was specified and there is no default remote")
+            display("{}", tr!("No remote specified and 'dit.remote' is not set"))
+        }
+
+        HookFailed(name: String) {
+            description("Hook failed")
+            display("{}", tr!("The hook '{}' failed", name))
+        }
+
+        RejectedByLint {
+            description("Message rejected by lint")
+            display("{}", tr!("The message was rejected because of lint errors (see dit.lint.*)"))
+        }
+
+        UnknownTemplate(name: String) {
+            description("Unknown issue template")
+            display("{}", tr!("The repository does not ship a template named '{}'", name))
+        }
+
+        NoIssueForBranch(branch: String) {
+            description("No issue is linked to the branch")
+            display("{}", tr!("Could not determine the issue for branch '{}'", branch))
+        }
+
+        NothingToUndo(issue: git2::Oid) {
+            description("No locally created message to undo")
+            display("{}", tr!("No locally created message found in issue {}", issue))
+        }
+
+        MessagePublished(message: git2::Oid) {
+            description("The message was already published")
+            display("{}", tr!("Message {} is known to a remote and cannot be undone", message))
+        }
+
+        CannotRewindHead(message: git2::Oid) {
+            description("Cannot determine the previous target of the head")
+            display("{}", tr!("The reflog does not record the previous head before message {}", message))
+        }
+
+        CannotWriteFile(path: String) {
+            description("Cannot write a file")
+            display("{}", tr!("Cannot write '{}'", path))
+        }
+
+        MalformedImport(reason: String) {
+            description("The data to import is malformed")
+            display("{}", tr!("Cannot import data: {}", reason))
+        }
+
+        MalformedXml(reason: String) {
+            description("Malformed XML document")
+            display("{}", tr!("Malformed XML document: {}", reason))
+        }
+
+        CannotSendMail(message: git2::Oid) {
+            description("Cannot send a message as mail")
+            display("{}", tr!("Could not send message {} as mail", message))
+        }
+
+        ImapError(reason: String) {
+            description("IMAP error")
+            display("{}", tr!("IMAP error: {}", reason))
+        }
+
+        HttpError(method: String, url: String) {
+            description("An HTTP request failed")
+            display("{}", tr!("HTTP request {} {} failed", method, url))
+        }
+    }
+}
+
+
+/// Category of a failure
+///
+/// Each category corresponds to a stable exit code, which allows wrappers to
+/// distinguish failures without parsing error messages.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    /// A failure not covered by any other category
+    Failure,
+    /// Malformed arguments or input supplied by the user
+    Usage,
+    /// An issue, message or reference does not exist
+    NotFound,
+    /// Malformed data in the repository
+    Malformed,
+    /// Communication with a remote or server failed
+    Network,
+    /// An update was prevented by a lock or a concurrent modification
+    Conflict,
+    /// An external program failed
+    External,
+}
+
+impl Category {
+    /// Get the exit code associated with the category
+    ///
+    pub fn code(self) -> i32 {
+        match self {
+            Category::Failure   => 1,
+            Category::Usage     => 2,
+            Category::NotFound  => 3,
+            Category::Malformed => 4,
+            Category::Network   => 5,
+            Category::Conflict  => 6,
+            Category::External  => 7,
+        }
+    }
+
+    /// Get the name of the category used in porcelain output
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Failure   => "failure",
+            Category::Usage     => "usage",
+            Category::NotFound  => "not-found",
+            Category::Malformed => "malformed",
+            Category::Network   => "network",
+            Category::Conflict  => "conflict",
+            Category::External  => "external",
+        }
+    }
+}
+
+
+/// Determine the category of a chain of errors
+///
+/// The outermost error with a specific category determines the category of
+/// the whole chain.
+///
+fn chain_category(err: &(EError + 'static)) -> Category {
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if let Some(category) = error_category(err) {
+            return category;
+        }
+        current = err.source();
+    }
+    Category::Failure
+}
+
+/// Determine the category of a single error, if it has a specific one
+///
+fn error_category(err: &(EError + 'static)) -> Option<Category> {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return match *err.kind() {
+            ErrorKind::GitError(ref err) => git_category(err),
+            ErrorKind::GitDitError(ref err) => Some(chain_category(err)),
+            ErrorKind::MalformedFilterSpec(_) |
+            ErrorKind::MalformedAuthor(_) |
+            ErrorKind::MalformedDate(_) |
+            ErrorKind::UnknownMetadataKey(_) |
+            ErrorKind::UnknownIdentity(_) |
+            ErrorKind::UnknownEncryptionBackend(_) |
+            ErrorKind::EncryptionNotConfigured |
+            ErrorKind::NoCurrentIssue |
+            ErrorKind::NoRemote |
+            ErrorKind::UnknownProfile(_) => Some(Category::Usage),
+            ErrorKind::NoIssueForBranch(_) |
+            ErrorKind::NothingToUndo(_) |
+            ErrorKind::UnknownTemplate(_) => Some(Category::NotFound),
+            ErrorKind::RejectedByLint |
+            ErrorKind::MalformedImport(_) |
+            ErrorKind::MalformedXml(_) => Some(Category::Malformed),
+            ErrorKind::ServerError(..) |
+            ErrorKind::CannotSendMail(_) |
+            ErrorKind::ImapError(_) |
+            ErrorKind::HttpError(..) => Some(Category::Network),
+            ErrorKind::MessagePublished(_) => Some(Category::Conflict),
+            ErrorKind::HookFailed(_) |
+            ErrorKind::ProgramError(_) |
+            ErrorKind::ChildError => Some(Category::External),
+            _ => None,
+        };
+    }
+
+    if let Some(kind) = err.downcast_ref::<DitError>().and_then(DitError::kind) {
+        return match *kind {
+            LibErrorKind::OidFormatError(_) |
+            LibErrorKind::MalformedIssueReference(_) |
+            LibErrorKind::MalformedComponent(_) |
+            LibErrorKind::MalformedAge(_) |
+            LibErrorKind::MalformedPattern(_) |
+            LibErrorKind::UnknownSortKey(_) |
+            LibErrorKind::UnknownSortOrder(_) |
+            LibErrorKind::UnknownGroupKey(_) |
+            LibErrorKind::UnknownInvolvement(_) |
+            LibErrorKind::UnknownHeadPolicy(_) |
+            LibErrorKind::InvalidConfigValue(..) |
+            LibErrorKind::IncompleteProfile(_) |
+            LibErrorKind::InvalidTemplateName(_) |
+            LibErrorKind::MissingAnonymityKey |
+            LibErrorKind::NoRecipients |
+            LibErrorKind::AttachmentLimitExceeded(..) => Some(Category::Usage),
+            LibErrorKind::CannotFindIssueHead(_) |
+            LibErrorKind::NoTreeInitFound(_) |
+            LibErrorKind::UnknownRepository(_) => Some(Category::NotFound),
+            LibErrorKind::ReferenceNameError(_) |
+            LibErrorKind::MalFormedHeadReference(_) |
+            LibErrorKind::TrailerFormatError(_) |
+            LibErrorKind::EmptyMessage |
+            LibErrorKind::EmptySubject |
+            LibErrorKind::MalformedMessage |
+            LibErrorKind::NonUtf8Message(_) |
+            LibErrorKind::MisplacedTrailer(_) |
+            LibErrorKind::MalformedModerationData(_) |
+            LibErrorKind::MalformedTemplate(_) |
+            LibErrorKind::MissingTrailers(_) => Some(Category::Malformed),
+            LibErrorKind::FilterProgramError(_) |
+            LibErrorKind::CipherProgramError(_) => Some(Category::External),
+            _ => None,
+        };
+    }
+
+    err.downcast_ref::<::git2::Error>().and_then(git_category)
+}
+
+/// Determine the category of a `git2::Error`, if it has a specific one
+///
+fn git_category(err: &::git2::Error) -> Option<Category> {
+    match err.code() {
+        ErrorCode::NotFound => return Some(Category::NotFound),
+        ErrorCode::InvalidSpec | ErrorCode::Ambiguous => return Some(Category::Usage),
+        ErrorCode::Exists |
+        ErrorCode::Locked |
+        ErrorCode::Modified |
+        ErrorCode::Conflict |
+        ErrorCode::NotFastForward => return Some(Category::Conflict),
+        ErrorCode::Auth | ErrorCode::Certificate => return Some(Category::Network),
+        _ => {},
+    }
+    match err.class() {
+        ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssh | ErrorClass::Ssl => Some(Category::Network),
+        ErrorClass::Invalid => Some(Category::Usage),
+        _ => None,
+    }
+}
+
+/// Determine the id of the object a chain of errors refers to, if any
+///
+fn chain_object_id(err: &(EError + 'static)) -> Option<Oid> {
+    let mut current = Some(err);
+    while let Some(err) = current {
+        let id = if let Some(err) = err.downcast_ref::<Error>() {
+            match *err.kind() {
+                ErrorKind::GitDitError(ref err) => err.object_id(),
+                ErrorKind::NothingToUndo(id) |
+                ErrorKind::MessagePublished(id) |
+                ErrorKind::CannotRewindHead(id) |
+                ErrorKind::CannotSendMail(id) => Some(id),
+                _ => None,
+            }
+        } else {
+            err.downcast_ref::<DitError>().and_then(DitError::object_id)
+        };
+        if id.is_some() {
+            return id;
         }
+        current = err.source();
     }
+    None
+}
+
+
+// whether failures are reported in the porcelain format
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+
+/// Select whether failures are reported in the porcelain format
+///
+/// In the porcelain format, each failure is reported as a single line holding
+/// the exit code, the category, the id of the object concerned (or "-") and
+/// the message, separated by tabs.
+///
+pub fn set_porcelain(porcelain: bool) {
+    PORCELAIN.store(porcelain, Ordering::Relaxed);
 }
 
 
 /// Convenience trait for logging error types
 ///
-/// Logs all layers of an error using the `error!` macro.
+/// Logs all layers of an error using the `error!` macro, or a single line if
+/// the porcelain format was selected.
 ///
 pub trait LoggableError {
     fn log(&self);
+
+    /// Get the category of the error
+    ///
+    fn category(&self) -> Category;
+
+    /// Get the id of the object the error refers to, if any
+    ///
+    fn object_id(&self) -> Option<Oid>;
 }
 
 impl<E> LoggableError for E
-    where E: EError
+    where E: EError + 'static
 {
     fn log(&self) {
+        if PORCELAIN.load(Ordering::Relaxed) {
+            let category = self.category();
+            let id = self.object_id().map(|id| id.to_string()).unwrap_or_else(|| "-".to_owned());
+            let message = self.to_string().replace(&['\t', '\n'][..], " ");
+            error!("{}\t{}\t{}\t{}", category.code(), category.name(), id, message);
+            return;
+        }
+
         let mut current = Some(self as &EError);
         while let Some(err) = current {
             error!("{}", err);
             current = err.source();
         }
     }
+
+    fn category(&self) -> Category {
+        chain_category(self)
+    }
+
+    fn object_id(&self) -> Option<Oid> {
+        chain_object_id(self)
+    }
 }
 
@@ -0,0 +1,578 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Export of issues
+//!
+//! This module provides exporters writing issues in formats suitable for
+//! migrating them into other trackers or for processing them with other tools.
+//! The JSON formats are documented in `doc/json.md`. In addition, summaries of
+//! issues may be exported as CSV, e.g. for spreadsheets, messages may be
+//! exported as mbox for mail clients, the relations between issues may be
+//! exported as a Graphviz DOT graph, open issues may be arranged on a Kanban
+//! board and all issues may be rendered as a static HTML site.
+//!
+
+use chrono::{TimeZone, Utc};
+use git2::{self, Commit, Oid, Repository};
+use libgitdit::{Issue, RepositoryExt};
+use libgitdit::crossref::IssueReference;
+use libgitdit::trailer::spec::{self, TrailerSpec};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use board::{self, Columns};
+use error::*;
+use error::ErrorKind as EK;
+use html;
+use json;
+use mail;
+use util::RepositoryUtil;
+
+
+/// Version of the JSON export format
+///
+/// The version is only incremented for changes which are not backward
+/// compatible.
+///
+pub const JSON_VERSION: u64 = 1;
+
+
+/// Export format
+///
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// A single JSON document
+    Json,
+    /// JSON Lines, one message per line
+    JsonLines,
+    /// CSV, one summary per issue
+    Csv,
+    /// mbox, one mail per message
+    Mbox,
+    /// Graphviz DOT, one node per issue
+    Dot,
+    /// A Kanban board as JSON document
+    Board,
+    /// A Kanban board as standalone HTML page
+    BoardHtml,
+}
+
+impl Format {
+    /// Get the format selected via the "format" option
+    ///
+    pub fn from_matches(matches: &::clap::ArgMatches) -> Self {
+        match matches.value_of("format") {
+            Some("jsonl")      => Format::JsonLines,
+            Some("csv")        => Format::Csv,
+            Some("mbox")       => Format::Mbox,
+            Some("dot")        => Format::Dot,
+            Some("board")      => Format::Board,
+            Some("board-html") => Format::BoardHtml,
+            _ => Format::Json,
+        }
+    }
+
+    /// Get the file name extension for the format
+    ///
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Json      => "json",
+            Format::JsonLines => "jsonl",
+            Format::Csv       => "csv",
+            Format::Mbox      => "mbox",
+            Format::Dot       => "dot",
+            Format::Board     => "json",
+            Format::BoardHtml => "html",
+        }
+    }
+}
+
+
+/// Export the issues supplied to a stream
+///
+/// Messages for which `hidden` returns true are only represented by their id
+/// and parents. Ids in DOT graphs and HTML boards are abbreviated to `id_len`
+/// characters.
+///
+pub fn write<'r, I>(repo: &'r Repository,
+                    issues: I,
+                    hidden: &Fn(&Commit) -> bool,
+                    format: Format,
+                    id_len: usize,
+                    stream: &mut Write
+) -> Result<()>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    match format {
+        Format::Json      => json_document(repo, issues, hidden, stream),
+        Format::JsonLines => json_lines(issues, hidden, stream),
+        Format::Csv       => csv(repo, issues, hidden, stream),
+        Format::Mbox      => mbox(issues, hidden, stream),
+        Format::Dot       => dot(repo, issues, hidden, id_len, stream),
+        Format::Board     => board(repo, issues, hidden, None, stream),
+        Format::BoardHtml => board(repo, issues, hidden, Some(id_len), stream),
+    }
+}
+
+
+/// Export each issue supplied to a file in a directory
+///
+/// The files are named after the issues' ids. The directory is created if it
+/// does not exist. The paths of the files written are returned.
+///
+pub fn files<'r, I>(repo: &'r Repository,
+                    issues: I,
+                    hidden: &Fn(&Commit) -> bool,
+                    format: Format,
+                    id_len: usize,
+                    directory: &Path
+) -> Result<Vec<PathBuf>>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    fs::create_dir_all(directory)
+        .chain_err(|| EK::CannotWriteFile(directory.to_string_lossy().into_owned()))?;
+
+    let mut retval = Vec::new();
+    for issue in issues {
+        let path = directory.join(format!("{}.{}", issue.id(), format.extension()));
+        let name = path.to_string_lossy().into_owned();
+        let mut file = File::create(&path)
+            .map(BufWriter::new)
+            .chain_err(|| EK::CannotWriteFile(name.clone()))?;
+
+        match format {
+            Format::Json => {
+                // a single issue rather than a document
+                let mut value = issue_value(repo, &issue, hidden)?;
+                value["version"] = json!(JSON_VERSION);
+                json::write(&mut file, &value)
+            },
+            Format::JsonLines => json_lines(Some(issue), hidden, &mut file),
+            Format::Csv       => csv(repo, Some(issue), hidden, &mut file),
+            Format::Mbox      => mbox(Some(issue), hidden, &mut file),
+            Format::Dot       => dot(repo, Some(issue), hidden, id_len, &mut file),
+            Format::Board     => board(repo, Some(issue), hidden, None, &mut file),
+            Format::BoardHtml => board(repo, Some(issue), hidden, Some(id_len), &mut file),
+        }.chain_err(|| EK::CannotWriteFile(name))?;
+        retval.push(path);
+    }
+    Ok(retval)
+}
+
+
+/// Render the issues supplied as a static HTML site
+///
+/// An index page listing the issues, most recent first, is written to the
+/// directory given along with one page per issue holding its thread. Pages
+/// link to each other via relative URLs, so the site may be published at any
+/// location. Issues with hidden initial messages are omitted. The directory is
+/// created if it does not exist. The paths of the pages written are returned.
+///
+pub fn html_site<'r, I>(repo: &'r Repository,
+                        issues: I,
+                        hidden: &Fn(&Commit) -> bool,
+                        id_len: usize,
+                        directory: &Path
+) -> Result<Vec<PathBuf>>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    let header = "<p><a href=\"index.html\">Issues</a></p>";
+    let write_page = |name: &str, title: &str, content: &str| -> Result<PathBuf> {
+        let path = directory.join(name);
+        let mut file = File::create(&path)
+            .chain_err(|| EK::CannotWriteFile(path.to_string_lossy().into_owned()))?;
+        file.write_all(html::page(title, header, content).as_bytes())
+            .chain_err(|| EK::CannotWriteFile(path.to_string_lossy().into_owned()))?;
+        Ok(path)
+    };
+
+    fs::create_dir_all(directory)
+        .chain_err(|| EK::CannotWriteFile(directory.to_string_lossy().into_owned()))?;
+
+    let mut retval = Vec::new();
+    let mut rows = Vec::new();
+    for issue in issues {
+        let initial = issue.initial_message()?;
+        if hidden(&initial) {
+            continue;
+        }
+
+        let name = format!("{}.html", issue.id());
+        let thread = html::issue_thread(repo, &issue, hidden, id_len)?;
+        retval.push(write_page(&name, initial.summary().unwrap_or(""), &thread)?);
+        rows.push((initial.time(), html::issue_row(repo, &issue, &initial, &name, id_len)));
+    }
+
+    rows.sort_by(|a, b| b.0.cmp(&a.0));
+    let rows: Vec<_> = rows.into_iter().map(|(_, row)| row).collect();
+    retval.push(write_page("index.html", "Issues", &format!("<table>{}</table>", rows.join("")))?);
+    Ok(retval)
+}
+
+
+/// Write a JSON document containing all the issues supplied
+///
+fn json_document<'r, I>(repo: &'r Repository,
+                        issues: I,
+                        hidden: &Fn(&Commit) -> bool,
+                        stream: &mut Write
+) -> Result<()>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    let mut values = Vec::new();
+    for issue in issues {
+        values.push(issue_value(repo, &issue, hidden)?);
+    }
+
+    json::write(stream, &json!({
+        "version": JSON_VERSION,
+        "issues": values,
+    }))
+}
+
+
+/// Write the messages of the issues supplied as JSON Lines
+///
+/// Each message is written as soon as it is retrieved, with an additional
+/// "issue" field holding the id of its issue. The messages of an issue are
+/// ordered such that each message succeeds its parents.
+///
+fn json_lines<'r, I>(issues: I,
+                     hidden: &Fn(&Commit) -> bool,
+                     stream: &mut Write
+) -> Result<()>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    for issue in issues {
+        let mut messages = issue.messages()?;
+        messages.revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        for message in messages {
+            let message = message?;
+            let mut value = json::message(&message, hidden(&message));
+            value["issue"] = json!(issue.id().to_string());
+
+            ::serde_json::to_writer(&mut *stream, &value)
+                .map_err(|err| Error::with_chain(err, EK::WrappedIOError))?;
+            writeln!(stream).chain_err(|| EK::WrappedIOError)?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Write the messages of the issues supplied as mbox
+///
+/// Each message is written as a mail as soon as it is retrieved, in the same
+/// order as for JSON Lines. Hidden messages are omitted. Lines of the mails'
+/// bodies starting with "From " are quoted as specified for the "mboxrd"
+/// variant.
+///
+fn mbox<'r, I>(issues: I,
+               hidden: &Fn(&Commit) -> bool,
+               stream: &mut Write
+) -> Result<()>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    for issue in issues {
+        let mut messages = issue.messages()?;
+        messages.revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        for message in messages {
+            let message = message?;
+            if hidden(&message) {
+                continue;
+            }
+
+            let date = Utc.timestamp(message.author().when().seconds(), 0);
+            writeln!(stream, "From {} {}", mail::message_id(message.id()), date.format("%a %b %e %H:%M:%S %Y"))
+                .chain_err(|| EK::WrappedIOError)?;
+            for line in mail::mail(issue.id(), &message).lines() {
+                let quote = line.trim_start_matches('>').starts_with("From ");
+                writeln!(stream, "{}{}", if quote { ">" } else { "" }, line)
+                    .chain_err(|| EK::WrappedIOError)?;
+            }
+            writeln!(stream).chain_err(|| EK::WrappedIOError)?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Write the relations between the issues supplied as a Graphviz DOT graph
+///
+/// Each issue is represented by a node labelled with its abbreviated id and
+/// title, closed issues being drawn in grey. Edges point from blocking issues
+/// to the issues they block, regardless of whether the relation was recorded
+/// via `Dit-blocks` or `Dit-depends-on` trailers. Duplicates are linked to the
+/// issues they duplicate via dashed edges. Issues referred to but not supplied,
+/// including issues in other repositories, are represented by dotted nodes.
+///
+fn dot<'r, I>(repo: &'r Repository,
+              issues: I,
+              hidden: &Fn(&Commit) -> bool,
+              id_len: usize,
+              stream: &mut Write
+) -> Result<()>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    let blocks = "label=\"blocks\"";
+    let duplicate = "label=\"duplicate of\", style=dashed";
+
+    let mut nodes = Vec::new();
+    let mut selected = BTreeSet::new();
+    let mut edges: Vec<(String, String, &str)> = Vec::new();
+    for issue in issues {
+        let id = issue.id().to_string();
+        let initial = issue.initial_message()?;
+        let head = repo.issue_head(&issue);
+        let values = |spec: &TrailerSpec| repo.metadata_values(&issue, &head, spec);
+
+        let title = if hidden(&initial) { "" } else { initial.summary().unwrap_or("") };
+        let closed = values(&spec::ISSUE_STATUS_SPEC).pop().map_or(false, |status| status == "closed");
+        nodes.push(format!("{} [label={}{}]",
+                           dot_quote(&id),
+                           dot_quote(&issue_label(&id, title, id_len)),
+                           if closed { ", color=grey, fontcolor=grey" } else { "" }));
+
+        for blocked in values(&spec::BLOCKS_SPEC) {
+            edges.push((id.clone(), relation_target(&blocked), blocks));
+        }
+        for dependency in values(&spec::DEPENDS_ON_SPEC) {
+            edges.push((relation_target(&dependency), id.clone(), blocks));
+        }
+        for original in values(&spec::DUPLICATE_OF_SPEC) {
+            edges.push((id.clone(), relation_target(&original), duplicate));
+        }
+        selected.insert(id);
+    }
+
+    // the same relation may have been recorded for both issues involved
+    let mut seen = BTreeSet::new();
+    edges.retain(|edge| seen.insert(edge.clone()));
+    let referred: BTreeSet<&String> = edges
+        .iter()
+        .flat_map(|edge| vec![&edge.0, &edge.1])
+        .filter(|node| !selected.contains(*node))
+        .collect();
+    for node in referred {
+        let local = Oid::from_str(node)
+            .ok()
+            .filter(|_| node.len() == 40)
+            .and_then(|id| repo.find_issue(id).ok());
+        let label = match local {
+            Some(issue) => {
+                let title = issue
+                    .initial_message()
+                    .ok()
+                    .filter(|initial| !hidden(initial))
+                    .and_then(|initial| initial.summary().map(str::to_owned))
+                    .unwrap_or_default();
+                issue_label(node, &title, id_len)
+            },
+            None => match node.parse::<IssueReference>() {
+                Ok(reference) => format!("{}#{}", reference.repo, abbreviate(&reference.issue.to_string(), id_len)),
+                Err(_) => node.clone(),
+            },
+        };
+        nodes.push(format!("{} [label={}, style=dotted]", dot_quote(node), dot_quote(&label)));
+    }
+
+    writeln!(stream, "digraph issues {{").chain_err(|| EK::WrappedIOError)?;
+    writeln!(stream, "    node [shape=box];").chain_err(|| EK::WrappedIOError)?;
+    for node in nodes {
+        writeln!(stream, "    {};", node).chain_err(|| EK::WrappedIOError)?;
+    }
+    for (from, to, attributes) in edges {
+        writeln!(stream, "    {} -> {} [{}];", dot_quote(&from), dot_quote(&to), attributes)
+            .chain_err(|| EK::WrappedIOError)?;
+    }
+    writeln!(stream, "}}").chain_err(|| EK::WrappedIOError)
+}
+
+
+/// Write a Kanban board of the issues supplied
+///
+/// The columns are taken from the repository's configuration. The board is
+/// written as a standalone HTML page if an id length is given, or as a JSON
+/// document otherwise.
+///
+fn board<'r, I>(repo: &'r Repository,
+                issues: I,
+                hidden: &Fn(&Commit) -> bool,
+                html_id_len: Option<usize>,
+                stream: &mut Write
+) -> Result<()>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    let columns = Columns::from_config(&repo.config()?)?;
+    let columns = board::arrange(repo, issues, hidden, &columns)?;
+    match html_id_len {
+        Some(id_len) => stream
+            .write_all(board::page(repo, &columns, id_len).as_bytes())
+            .chain_err(|| EK::WrappedIOError),
+        None => {
+            let mut value = board::value(repo, &columns);
+            value["version"] = json!(JSON_VERSION);
+            json::write(stream, &value)
+        },
+    }
+}
+
+
+/// Normalize the value of a relation trailer for use as a node's name
+///
+/// Ids of local issues are lowercased and references to issues in other
+/// repositories are brought into their canonical form. Other values are used
+/// as they are.
+///
+fn relation_target(value: &str) -> String {
+    let value = value.trim();
+    match Oid::from_str(value) {
+        Ok(id) if value.len() == 40 => id.to_string(),
+        _ => value
+            .parse::<IssueReference>()
+            .map(|reference| reference.to_string())
+            .unwrap_or_else(|_| value.to_owned()),
+    }
+}
+
+
+/// Quote a string for use as an id in a DOT graph
+///
+/// Line breaks are converted to the corresponding escape sequence.
+///
+fn dot_quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+
+/// Label an issue's node with its abbreviated id and, if known, its title
+///
+fn issue_label(id: &str, title: &str, id_len: usize) -> String {
+    if title.is_empty() {
+        abbreviate(id, id_len).to_owned()
+    } else {
+        format!("{}\n{}", abbreviate(id, id_len), title)
+    }
+}
+
+
+/// Abbreviate an id to the given length
+///
+fn abbreviate(id: &str, len: usize) -> &str {
+    id.get(..len).unwrap_or(id)
+}
+
+
+/// Columns of the CSV export
+///
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "title",
+    "status",
+    "tags",
+    "assignees",
+    "created",
+    "last_activity",
+    "messages",
+];
+
+
+/// Write summaries of the issues supplied as CSV
+///
+/// A header is written, followed by one record per issue. Multiple tags and
+/// assignees are separated by semicolons. Hidden messages are neither counted nor
+/// considered for the last activity, and the title of an issue is empty if its
+/// initial message is hidden.
+///
+fn csv<'r, I>(repo: &'r Repository,
+              issues: I,
+              hidden: &Fn(&Commit) -> bool,
+              stream: &mut Write
+) -> Result<()>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    write_csv_record(stream, CSV_COLUMNS.iter().cloned())?;
+
+    for issue in issues {
+        let initial = issue.initial_message()?;
+        let head = repo.issue_head(&issue);
+        let values = |spec: &TrailerSpec| repo.metadata_values(&issue, &head, spec);
+
+        let mut count = 0;
+        let mut last_activity = None;
+        for message in issue.messages()? {
+            let message = message?;
+            if hidden(&message) {
+                continue;
+            }
+            count += 1;
+            if last_activity.map(|last| message.time() > last).unwrap_or(true) {
+                last_activity = Some(message.time());
+            }
+        }
+
+        let title = if hidden(&initial) {
+            String::new()
+        } else {
+            initial.summary().unwrap_or("").to_owned()
+        };
+        let status = values(&spec::ISSUE_STATUS_SPEC)
+            .pop()
+            .unwrap_or_else(|| "open".to_owned());
+
+        write_csv_record(stream, vec![
+            issue.id().to_string().as_str(),
+            title.as_str(),
+            status.as_str(),
+            values(&spec::ISSUE_TAG_SPEC).join("; ").as_str(),
+            values(&spec::ISSUE_ASSIGNEE_SPEC).join("; ").as_str(),
+            json::date(initial.time()).as_str(),
+            last_activity.map(json::date).unwrap_or_default().as_str(),
+            count.to_string().as_str(),
+        ])?;
+    }
+    Ok(())
+}
+
+
+/// Write a single CSV record
+///
+/// Fields are quoted if necessary, as specified in RFC 4180.
+///
+fn write_csv_record<'a, I>(stream: &mut Write, fields: I) -> Result<()>
+    where I: IntoIterator<Item = &'a str>
+{
+    let record: Vec<String> = fields
+        .into_iter()
+        .map(|field| if field.contains(|c| c == ',' || c == '"' || c == '\r' || c == '\n') {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_owned()
+        })
+        .collect();
+    write!(stream, "{}\r\n", record.join(",")).chain_err(|| EK::WrappedIOError)
+}
+
+
+/// Represent an issue along with all its messages
+///
+fn issue_value<'r>(repo: &'r Repository, issue: &Issue<'r>, hidden: &Fn(&Commit) -> bool) -> Result<Value> {
+    let initial = issue.initial_message()?;
+    let messages = issue
+        .messages()?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    Ok(json::issue_with_messages(repo, issue, &initial, messages, hidden))
+}
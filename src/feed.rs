@@ -0,0 +1,103 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Feed generation
+//!
+//! This module provides the rendering of issue events as an Atom feed
+//! (RFC 4287), allowing followers to subscribe to a tracker's activity.
+//!
+
+use git2::Repository;
+use libgitdit::Message;
+use libgitdit::event::{Event, EventKind};
+use std::io::Write;
+
+use error::*;
+use error::ErrorKind as EK;
+use html::escape;
+use json;
+
+
+/// Write an Atom feed of the events supplied
+///
+/// The events are expected in reverse chronological order, as returned by
+/// `libgitdit::event::events()`. Each event is rendered as an entry with the
+/// event's message as content. If a base URL is given, entries link to the
+/// issues' pages of a static site published at that URL, e.g. as written by
+/// `export::html_site()`, and the URL serves as the feed's id.
+///
+pub fn atom<'r>(repo: &'r Repository,
+                events: &[Event<'r>],
+                title: &str,
+                base_url: Option<&str>,
+                stream: &mut Write
+) -> Result<()> {
+    let base_url = base_url.map(|url| url.trim_end_matches('/'));
+    let updated = events
+        .first()
+        .map(|event| json::date(event.time()))
+        .unwrap_or_else(|| "1970-01-01T00:00:00+00:00".to_owned());
+
+    let mut lines = vec![
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>".to_owned(),
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\">".to_owned(),
+        format!("<title>{}</title>", escape(title)),
+        format!("<id>{}</id>", escape(&base_url.map(|url| format!("{}/", url)).unwrap_or_else(|| "urn:git-dit:feed".to_owned()))),
+        format!("<updated>{}</updated>", updated),
+    ];
+    if let Some(url) = base_url {
+        lines.push(format!("<link href=\"{}/index.html\"/>", escape(url)));
+    }
+
+    for event in events {
+        let message = event.message();
+        let author = message.author();
+        let subject = repo
+            .find_commit(event.issue())
+            .ok()
+            .and_then(|initial| initial.summary().map(String::from))
+            .unwrap_or_default();
+        let action = match event.kind() {
+            &EventKind::NewIssue => "opened".to_owned(),
+            &EventKind::Reply => "replied to".to_owned(),
+            &EventKind::StatusChange(ref status) if status == "closed" => "closed".to_owned(),
+            &EventKind::StatusChange(ref status) if status == "open" => "reopened".to_owned(),
+            &EventKind::StatusChange(ref status) => format!("set status \"{}\" of", status),
+        };
+        let mut content = vec![message.summary().unwrap_or("").to_owned()];
+        let body: Vec<String> = message.body_lines().collect();
+        if !body.is_empty() {
+            content.push(String::new());
+            content.extend(body);
+        }
+
+        lines.push("<entry>".to_owned());
+        lines.push(format!("<title>{} {} {}</title>",
+                           escape(author.name().unwrap_or("")),
+                           action,
+                           escape(&subject)));
+        lines.push(format!("<id>urn:git-dit:{}</id>", message.id()));
+        lines.push(format!("<updated>{}</updated>", json::date(event.time())));
+        lines.push(format!("<author><name>{}</name><email>{}</email></author>",
+                           escape(author.name().unwrap_or("")),
+                           escape(author.email().unwrap_or(""))));
+        if let Some(url) = base_url {
+            lines.push(format!("<link href=\"{}/{}.html\"/>", escape(url), event.issue()));
+        }
+        lines.push(format!("<content type=\"text\">{}</content>", escape(&content.join("\n"))));
+        lines.push("</entry>".to_owned());
+    }
+    lines.push("</feed>".to_owned());
+
+    for line in lines {
+        writeln!(stream, "{}", line).chain_err(|| EK::WrappedIOError)?;
+    }
+    Ok(())
+}
+
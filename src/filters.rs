@@ -7,53 +7,109 @@
 //   published by the Free Software Foundation.
 //
 
-use libgitdit::Issue;
+use chrono::{DateTime, Duration, Local, Months, NaiveDate, TimeZone};
+use git2::Repository;
+use libgitdit::{Issue, RepositoryExt};
+use libgitdit::blame::blame;
+use libgitdit::gc;
+use libgitdit::listing;
+use libgitdit::search::Query;
+use libgitdit::trailer::accumulation::ValueAccumulator;
 use libgitdit::trailer::filter::{TrailerFilter, ValueMatcher};
 use libgitdit::trailer::{TrailerValue, spec};
 use regex::{Regex, Match};
+use std::cmp;
 use std::str::FromStr;
 
 use error::*;
 use error::ErrorKind as EK;
-use gitext::{RemotePriorization, ReferrencesExt};
-use system::{Abortable, IteratorExt};
+use gitext::RemotePriorization;
+use index::Metadata;
+use system::Abortable;
 
 
 /// Representation of non-trailer metadata
 ///
 enum NonTrailer {
+    Author,
     ReporterName,
     ReporterEMail,
 }
 
 impl NonTrailer {
-    /// Retrieve the value for a given issue
+    /// Retrieve the values for a given issue
     ///
-    pub fn for_issue(&self, issue: &Issue) -> Result<TrailerValue> {
+    /// The author is resolved via the repository's mailmap.
+    ///
+    pub fn for_issue(&self, repo: &Repository, issue: &Issue) -> Result<Vec<TrailerValue>> {
+        let initial = issue.initial_message()?;
+        let author = repo.author_of(&initial)?;
+        let name = author.name().map(TrailerValue::from_slice).unwrap_or_default();
+        let email = author.email().map(TrailerValue::from_slice).unwrap_or_default();
+
+        Ok(match self {
+            &NonTrailer::Author        => vec![name, email],
+            &NonTrailer::ReporterName  => vec![name],
+            &NonTrailer::ReporterEMail => vec![email],
+        })
+    }
+}
+
+
+/// Date of an issue
+///
+enum DateField {
+    /// Date at which the issue was created
+    Created,
+    /// Date of the latest message of the issue
+    Updated,
+    /// Date at which the issue was closed
+    Closed,
+}
+
+impl DateField {
+    /// Retrieve the date for a given issue, in seconds since the epoch
+    ///
+    /// Issues which are not closed have no closing date. The closing date of
+    /// a closed issue is the date of the message which set its status.
+    ///
+    pub fn for_issue(&self, issue: &Issue, prios: &RemotePriorization) -> Option<i64> {
         match self {
-            &NonTrailer::ReporterName => {
-                let initial = issue.initial_message()?;
-                let value = initial
-                    .author()
-                    .name()
-                    .map(TrailerValue::from_slice)
-                    .unwrap_or_default();
-                Ok(value)
-            },
-            &NonTrailer::ReporterEMail => {
-                let initial = issue.initial_message()?;
-                let value = initial
-                    .author()
-                    .email()
-                    .map(TrailerValue::from_slice)
-                    .unwrap_or_default();
-                Ok(value)
+            &DateField::Created => Some(issue.initial_message().unwrap_or_abort().time().seconds()),
+            &DateField::Updated => listing::last_activity(issue).unwrap_or_abort(),
+            &DateField::Closed => {
+                let head = prios.select_target(&issue.head_targets().unwrap_or_abort())?;
+                let messages = issue.messages_from(head).unwrap_or_abort();
+                blame(messages, &[spec::ISSUE_STATUS_SPEC])
+                    .unwrap_or_abort()
+                    .first()
+                    .filter(|attribution| attribution.value().to_string() == "closed")
+                    .map(|attribution| attribution.message().time().seconds())
             },
         }
     }
 }
 
 
+/// Matcher for a piece of metadata
+///
+enum Matcher {
+    /// Match the value of the metadata
+    Value(ValueMatcher),
+    /// Match dates older than the number of seconds given
+    OlderThan(i64),
+    /// Match dates younger than the number of seconds given
+    YoungerThan(i64),
+    /// Match dates within a range of timestamps
+    ///
+    /// The start of the range is inclusive, the end exclusive. Either may be
+    /// omitted.
+    Range(Option<i64>, Option<i64>),
+    /// Match issues containing the text in the subject or body of a message
+    Text(Query),
+}
+
+
 /// Filter specification
 ///
 /// This type represents a filter rule for a single piece of metadata.
@@ -62,18 +118,56 @@ pub struct FilterSpec {
     /// Metadata to filter
     key: String,
     /// Matcher for the value
-    matcher: ValueMatcher,
+    matcher: Matcher,
     /// Indicator whether the filter shall be negated or not
     negated: bool,
 }
 
 impl FilterSpec {
-    fn into_trailer<'a>(self, spec: spec::TrailerSpec<'a>) -> (TrailerFilter<'a>, bool) {
-        (TrailerFilter::new(spec, self.matcher), self.negated)
+    /// Create a filter specification restricting the creation date
+    ///
+    /// The start of the range is inclusive, the end exclusive. Either may be
+    /// omitted.
+    ///
+    pub fn created_within(start: Option<i64>, end: Option<i64>) -> Self {
+        FilterSpec { key: "created".to_owned(), matcher: Matcher::Range(start, end), negated: false }
+    }
+
+    /// Get the key of the metadata to filter
+    ///
+    pub fn key(&self) -> &str {
+        self.key.as_ref()
+    }
+
+    fn value_matcher(self) -> Result<(ValueMatcher, bool)> {
+        match self.matcher {
+            Matcher::Value(matcher) => Ok((matcher, self.negated)),
+            _ => Err(Error::from_kind(EK::MalformedFilterSpec(self.key))),
+        }
     }
 
-    fn into_nontrailer<'a>(self, spec: NonTrailer) -> (NonTrailer, ValueMatcher, bool) {
-        (spec, self.matcher, self.negated)
+    fn into_trailer<'a>(self, spec: spec::TrailerSpec<'a>) -> Result<(TrailerFilter<'a>, bool)> {
+        self.value_matcher()
+            .map(|(matcher, negated)| (TrailerFilter::new(spec, matcher), negated))
+    }
+
+    fn into_nontrailer(self, spec: NonTrailer) -> Result<(NonTrailer, ValueMatcher, bool)> {
+        self.value_matcher()
+            .map(|(matcher, negated)| (spec, matcher, negated))
+    }
+
+    fn into_date(self, field: DateField) -> Result<(DateField, Matcher, bool)> {
+        match self.matcher {
+            Matcher::Value(_) | Matcher::Text(_) => Err(Error::from_kind(EK::MalformedFilterSpec(self.key))),
+            matcher => Ok((field, matcher, self.negated)),
+        }
+    }
+
+    fn into_text(self) -> Result<(Query, bool)> {
+        match self.matcher {
+            Matcher::Text(query) => Ok((query, self.negated)),
+            _ => Err(Error::from_kind(EK::MalformedFilterSpec(self.key))),
+        }
     }
 }
 
@@ -83,7 +177,7 @@ impl FromStr for FilterSpec {
     fn from_str(s: &str) -> Result<Self> {
         lazy_static! {
             // regex for parsing a trailer spec
-            static ref RE: Regex = Regex::new(r"^(!)?([[:alnum:]-]+)((=|~)(.*))?$").unwrap();
+            static ref RE: Regex = Regex::new(r"^(!)?([[:alnum:]-]+)((=|~|<|>)(.*))?$").unwrap();
         }
 
         let parts = RE
@@ -110,12 +204,16 @@ impl FromStr for FilterSpec {
                 .ok_or_else(|| Error::from_kind(EK::MalformedFilterSpec(s.to_owned())))?;
 
             match op {
-                "=" => ValueMatcher::Equals(TrailerValue::from_slice(value)),
-                "~" => ValueMatcher::Contains(value.to_string()),
+                "=" => Matcher::Value(ValueMatcher::Equals(TrailerValue::from_slice(value))),
+                "~" => Matcher::Value(ValueMatcher::Contains(value.to_string())),
+                "<" => Matcher::YoungerThan(parse_age(value)
+                    .chain_err(|| EK::MalformedFilterSpec(s.to_owned()))?),
+                ">" => Matcher::OlderThan(parse_age(value)
+                    .chain_err(|| EK::MalformedFilterSpec(s.to_owned()))?),
                 _   => return Err(Error::from_kind(EK::MalformedFilterSpec(s.to_owned()))),
             }
         } else {
-            ValueMatcher::Any
+            Matcher::Value(ValueMatcher::Any)
         };
 
         Ok(FilterSpec {
@@ -127,61 +225,316 @@ impl FromStr for FilterSpec {
 }
 
 
-/// Metadata filter
+/// Parse an age, e.g. "3d", into a number of seconds
 ///
-pub struct MetadataFilter<'a> {
-    prios: &'a RemotePriorization,
+/// Supported units are "h" (hours), "d" (days), "w" (weeks), "m" (months of 30
+/// days) and "y" (years of 365 days). A number without a unit denotes days.
+///
+pub fn parse_age(s: &str) -> Result<i64> {
+    gc::parse_age(s)
+        .map(|age| cmp::min(age.as_secs(), i64::MAX as u64) as i64)
+        .map_err(Error::from)
+}
+
+
+/// Parse a query into groups of filter specifications
+///
+/// A query consists of terms separated by whitespace, e.g.
+/// `status:open tag:bug author:alice since:2024-01-01 "panic"`. A term of the
+/// form `<key>:<value>` matches metadata: "author", "reporter-name" and
+/// "reporter-email" match if the value is contained in the name or address,
+/// the other keys accepted by `FilterSpec` match the exact value. The keys
+/// "since" and "until" restrict the date at which an issue was created. Any
+/// other term, or a term enclosed in double quotes, matches issues containing
+/// the text in one of their messages, see `Query`.
+///
+/// The keys "created", "updated" and "closed" restrict the respective date of
+/// an issue to a range of the form `<start>..<end>`, `><start>` or `<<end>`,
+/// e.g. `updated:>2.weeks.ago`. Either end of a range may be omitted. A single
+/// date denotes the start of a range. See `parse_date()` for the formats of
+/// dates.
+///
+/// Terms prefixed with "-" are negated. Terms are combined using AND, groups
+/// of terms may be separated by "or".
+///
+pub fn parse_query(query: &str) -> Result<Vec<Vec<FilterSpec>>> {
+    lazy_static! {
+        // regex for parsing a metadata term
+        static ref RE: Regex = Regex::new(r"^([[:alnum:]-]+):(.+)$").unwrap();
+    }
+
+    let mut groups = vec![Vec::new()];
+    for (term, quoted) in query_terms(query)? {
+        if !quoted && term.eq_ignore_ascii_case("or") {
+            groups.push(Vec::new());
+            continue;
+        }
+
+        let (term, negated) = match term.strip_prefix('-') {
+            Some(rest) if !quoted && !rest.is_empty() => (rest, true),
+            _ => (term.as_str(), false),
+        };
+
+        let metadata = if quoted || term.starts_with('"') {
+            None
+        } else {
+            RE.captures(term)
+        };
+        let spec = match metadata {
+            Some(parts) => {
+                let key = &parts[1];
+                let value = parts[2].trim_matches('"');
+                let matcher = match key {
+                    "status" | "type" | "tag" | "assignee" | "milestone" =>
+                        Matcher::Value(ValueMatcher::Equals(TrailerValue::from_slice(value))),
+                    "author" | "reporter-name" | "reporter-email" =>
+                        Matcher::Value(ValueMatcher::Contains(value.to_owned())),
+                    "since" => Matcher::Range(Some(parse_date(value)?), None),
+                    "until" => Matcher::Range(None, Some(parse_date(value)?)),
+                    "created" | "updated" | "closed" => {
+                        let (start, end) = parse_range(value)?;
+                        Matcher::Range(start, end)
+                    },
+                    _ => return Err(Error::from_kind(EK::UnknownMetadataKey(key.to_owned()))),
+                };
+                let key = match key {
+                    "since" | "until" => "created",
+                    key => key,
+                };
+                FilterSpec { key: key.to_owned(), matcher: matcher, negated: negated }
+            },
+            None => FilterSpec {
+                key: "text".to_owned(),
+                matcher: Matcher::Text(Query::new(term.trim_matches('"'))),
+                negated: negated,
+            },
+        };
+        groups.last_mut().unwrap().push(spec);
+    }
+
+    Ok(groups)
+}
+
+
+/// Split a query into terms
+///
+/// Terms are separated by whitespace, which may be part of a term if enclosed
+/// in double quotes. The terms are returned along with the information whether
+/// they were enclosed in quotes entirely, in which case the quotes are removed.
+///
+fn query_terms(query: &str) -> Result<Vec<(String, bool)>> {
+    let mut terms = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    loop {
+        while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            return Ok(terms);
+        }
+
+        let quoted = chars.peek() == Some(&'"');
+        let mut term = String::new();
+        let mut in_quotes = false;
+        for c in chars.by_ref() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                c if c.is_whitespace() && !in_quotes => break,
+                _ => (),
+            }
+            term.push(c);
+        }
+        if in_quotes {
+            return Err(Error::from_kind(EK::MalformedFilterSpec(query.to_owned())));
+        }
+
+        let whole = quoted && term.len() > 1 && term.ends_with('"') && !term[1..term.len() - 1].contains('"');
+        if whole {
+            terms.push((term[1..term.len() - 1].to_owned(), true));
+        } else {
+            terms.push((term, false));
+        }
+    }
+}
+
+
+/// Parse a range of dates, e.g. "2024-01-01..2024-02-01", into timestamps
+///
+/// Ranges are of the form `<start>..<end>`, `><start>` or `<<end>`, where
+/// either end may be omitted. A single date denotes the start of a range.
+///
+fn parse_range(s: &str) -> Result<(Option<i64>, Option<i64>)> {
+    let optional = |date: &str| if date.trim().is_empty() {
+        Ok(None)
+    } else {
+        parse_date(date).map(Some)
+    };
+
+    if let Some(start) = s.strip_prefix('>') {
+        Ok((Some(parse_date(start)?), None))
+    } else if let Some(end) = s.strip_prefix('<') {
+        Ok((None, Some(parse_date(end)?)))
+    } else if let Some(separator) = s.find("..") {
+        Ok((optional(&s[..separator])?, optional(&s[separator + 2..])?))
+    } else {
+        Ok((Some(parse_date(s)?), None))
+    }
+}
+
+
+/// Parse a date, e.g. "2024-01-01" or "2 weeks ago", into a timestamp
+///
+/// Dates without a time refer to the start of the day in the local time zone.
+/// Dates with a time must be formatted according to RFC 3339. Like git's
+/// "approxidate", relative dates such as "now", "today", "yesterday",
+/// "3 days ago", "last week" or "2.months.ago" are accepted as well as ages
+/// accepted by `parse_age()`, e.g. "2w".
+///
+pub fn parse_date(s: &str) -> Result<i64> {
+    let s = s.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.timestamp());
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+        .or_else(|| parse_relative_date(s, Local::now()))
+        .map(|date| date.timestamp())
+        .or_else(|| parse_age(s).ok().map(|age| Local::now().timestamp().saturating_sub(age)))
+        .ok_or_else(|| Error::from_kind(EK::MalformedDate(s.to_owned())))
+}
+
+
+/// Parse a date relative to the given point in time
+///
+/// Words may be separated by whitespace or dots. Months and years are
+/// calendar months and years. `None` is returned for dates out of range.
+///
+fn parse_relative_date(s: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let words: Vec<String> = s
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    let (count, unit) = match words.as_slice() {
+        ["now"] => return Some(now),
+        ["today"] => return now
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .and_then(|date| Local.from_local_datetime(&date).earliest()),
+        ["yesterday"] => return now.checked_sub_signed(Duration::days(1)),
+        ["last", unit] => (1, *unit),
+        [count, unit, "ago"] => match *count {
+            "a" | "an" | "one" => (1, *unit),
+            count => (count.parse::<u32>().ok()?, *unit),
+        },
+        _ => return None,
+    };
+
+    let seconds = match unit.trim_end_matches('s') {
+        "second"    => 1,
+        "minute"    => 60,
+        "hour"      => 60 * 60,
+        "day"       => 24 * 60 * 60,
+        "week"      => 7 * 24 * 60 * 60,
+        "month"     => return now.checked_sub_months(Months::new(count)),
+        "year"      => return now.checked_sub_months(Months::new(count.checked_mul(12)?)),
+        _ => return None,
+    };
+    i64::from(count)
+        .checked_mul(seconds)
+        .and_then(Duration::try_seconds)
+        .and_then(|duration| now.checked_sub_signed(duration))
+}
+
+
+/// Group of filter rules, all of which have to match
+///
+#[derive(Default)]
+struct FilterGroup<'a> {
     nontrailers: Vec<(NonTrailer, ValueMatcher, bool)>,
     trailers: Vec<(TrailerFilter<'a>, bool)>,
+    dates: Vec<(DateField, Matcher, bool)>,
+    texts: Vec<(Query, bool)>,
 }
 
-impl<'a> MetadataFilter<'a> {
-    /// Create a new metadata filter
+impl<'a> FilterGroup<'a> {
+    /// Create a new filter group
     ///
-    pub fn new<I>(prios: &'a RemotePriorization, spec: I) -> Result<Self>
+    fn new<I>(spec: I) -> Result<Self>
         where I: IntoIterator<Item = FilterSpec>
     {
-        let mut nontrailers = Vec::new();
-        let mut trailers = Vec::new();
+        let mut retval = Self::default();
 
         for s in spec.into_iter() {
             match s.key.as_ref() {
-                "status"            => trailers.push(s.into_trailer(spec::ISSUE_STATUS_SPEC.clone())),
-                "type"              => trailers.push(s.into_trailer(spec::ISSUE_TYPE_SPEC.clone())),
-                "reporter-name"     => nontrailers.push(s.into_nontrailer(NonTrailer::ReporterName)),
-                "reporter-email"    => nontrailers.push(s.into_nontrailer(NonTrailer::ReporterEMail)),
+                "status"            => retval.trailers.push(s.into_trailer(spec::ISSUE_STATUS_SPEC.clone())?),
+                "type"              => retval.trailers.push(s.into_trailer(spec::ISSUE_TYPE_SPEC.clone())?),
+                "tag"               => retval.trailers.push(s.into_trailer(spec::ISSUE_TAG_SPEC.clone())?),
+                "assignee"          => retval.trailers.push(s.into_trailer(spec::ISSUE_ASSIGNEE_SPEC.clone())?),
+                "milestone"         => retval.trailers.push(s.into_trailer(spec::ISSUE_MILESTONE_SPEC.clone())?),
+                "author"            => retval.nontrailers.push(s.into_nontrailer(NonTrailer::Author)?),
+                "reporter-name"     => retval.nontrailers.push(s.into_nontrailer(NonTrailer::ReporterName)?),
+                "reporter-email"    => retval.nontrailers.push(s.into_nontrailer(NonTrailer::ReporterEMail)?),
+                "age" | "created"   => retval.dates.push(s.into_date(DateField::Created)?),
+                "updated"           => retval.dates.push(s.into_date(DateField::Updated)?),
+                "closed"            => retval.dates.push(s.into_date(DateField::Closed)?),
+                "text"              => retval.texts.push(s.into_text()?),
                 _                   => return Err(Error::from_kind(EK::UnknownMetadataKey(s.key.to_string()))),
             }
         }
 
-        Ok(MetadataFilter { prios: prios, nontrailers: nontrailers, trailers: trailers })
+        Ok(retval)
     }
 
-    /// Create an empty metadata filter
+    /// Check whether the trailers filtered are held by the cached metadata
     ///
-    /// The filter will not filter out any issues.
-    ///
-    pub fn empty(prios: &'a RemotePriorization) -> Self {
-        MetadataFilter {
-            prios: prios,
-            nontrailers: Vec::new(),
-            trailers: Vec::new(),
-        }
+    fn uses_cached_metadata(&self) -> bool {
+        !self.trailers.is_empty() && self.trailers.iter().all(|&(ref filter, _)| {
+            let key = filter.spec().key;
+            key == spec::ISSUE_TAG_SPEC.key || key == spec::ISSUE_ASSIGNEE_SPEC.key
+        })
     }
 
     /// Filter an issue
     ///
-    pub fn filter(&self, issue: &Issue) -> bool {
-        // NOTE: if we ever add the filters crate as a dependency, this method
-        //       may be transferred to an implementatio nof the Filter trait
-        use git2::ObjectType;
-        use libgitdit::iter::MessagesExt;
-        use std::collections::HashMap;
+    /// If the trailers filtered are held by the metadata supplied, which was
+    /// accumulated from the issue's head, the metadata is not accumulated
+    /// anew.
+    ///
+    fn filter(&self,
+              repo: &Repository,
+              issue: &Issue,
+              prios: &RemotePriorization,
+              metadata: Option<&Metadata>
+    ) -> bool {
+        use libgitdit::trailer::spec::ToMap;
 
-        // Check non-trailer metadata first
+        // Check the dates of the issue
+        let now = Local::now().timestamp();
+        for &(ref field, ref matcher, negated) in self.dates.iter() {
+            // issues lacking a date, e.g. a closing date, match no range
+            let matches = field.for_issue(issue, prios).map(|date| match matcher {
+                &Matcher::OlderThan(limit)          => now - date > limit,
+                &Matcher::YoungerThan(limit)        => now - date < limit,
+                &Matcher::Range(start, end)         => start.map(|s| date >= s).unwrap_or(true) &&
+                                                       end.map(|e| date < e).unwrap_or(true),
+                &Matcher::Value(_)                  => true,
+                &Matcher::Text(_)                   => true,
+            });
+            if !matches.unwrap_or(false) ^ negated {
+                return false;
+            }
+        }
+
+        // Check non-trailer metadata
         for metadata in self.nontrailers.iter() {
-            let value = metadata.0.for_issue(issue).unwrap_or_abort();
-            if !metadata.1.matches(&value) ^ metadata.2 {
+            let values = metadata.0.for_issue(repo, issue).unwrap_or_abort();
+            if !metadata.1.matches_any(values) ^ metadata.2 {
                 return false;
             }
         }
@@ -189,26 +542,112 @@ impl<'a> MetadataFilter<'a> {
         // Filtering may be expensive, so it makes sense to return early if the
         // filter is empty.
         if self.trailers.is_empty() {
-            return true;
+            return self.filter_texts(issue);
+        }
+
+        // Use the cached metadata if possible
+        if let Some(metadata) = metadata.filter(|_| self.uses_cached_metadata()) {
+            let values = |values: &[String]| ValueAccumulator::List(values
+                .iter()
+                .map(|value| TrailerValue::from_slice(value))
+                .collect());
+            let mut acc = ::std::collections::HashMap::new();
+            acc.insert(spec::ISSUE_TAG_SPEC.key.to_owned(), values(&metadata.tags));
+            acc.insert(spec::ISSUE_ASSIGNEE_SPEC.key.to_owned(), values(&metadata.assignees));
+            return self.trailers
+                .iter()
+                .all(|spec| spec.0.matches(&acc) ^ spec.1) && self.filter_texts(issue);
         }
 
-        // Get the head reference
-        let head = issue
-            .heads()
-            .abort_on_err()
-            .select_ref(self.prios)
-            .map(|head| head.peel(ObjectType::Commit).unwrap_or_abort().id());
+        // Get the head
+        let head = prios.select_target(&issue.head_targets().unwrap_or_abort());
 
         // Accumulate all the metadata we care about
-        let acc: HashMap<_, _> = head
-            .into_iter()
-            .flat_map(|head| issue.messages_from(head).abort_on_err())
-            .accumulate_trailers(self.trailers.iter().map(|i| i.0.spec()));
+        let specs = self.trailers.iter().map(|i| i.0.spec());
+        let acc = match head {
+            Some(head) => issue
+                .messages_from(head)
+                .unwrap_or_abort()
+                .parsed()
+                .accumulate_trailers(specs)
+                .unwrap_or_abort(),
+            None => specs.into_map(),
+        };
 
         // Compute whether all constraints are met
         self.trailers
             .iter()
-            .all(|spec| spec.0.matches(&acc) ^ spec.1)
+            .all(|spec| spec.0.matches(&acc) ^ spec.1) && self.filter_texts(issue)
+    }
+
+    /// Check whether an issue contains the texts searched for
+    ///
+    fn filter_texts(&self, issue: &Issue) -> bool {
+        if self.texts.is_empty() {
+            return true;
+        }
+
+        let mut found = vec![false; self.texts.len()];
+        for message in issue.messages().unwrap_or_abort() {
+            let message = message.unwrap_or_abort();
+            for (query, found) in self.texts.iter().map(|text| &text.0).zip(found.iter_mut()) {
+                *found = *found || query.search_message(&message).is_some();
+            }
+        }
+
+        self.texts
+            .iter()
+            .zip(found)
+            .all(|(&(_, negated), found)| found ^ negated)
     }
 }
 
+
+/// Metadata filter
+///
+/// A metadata filter consists of groups of filter rules. An issue passes the
+/// filter if it matches all the rules of at least one group.
+///
+pub struct MetadataFilter<'a> {
+    prios: &'a RemotePriorization,
+    groups: Vec<FilterGroup<'a>>,
+}
+
+impl<'a> MetadataFilter<'a> {
+    /// Create a new metadata filter from alternative groups of rules
+    ///
+    /// An issue will pass the filter if it matches all the rules of any group.
+    /// If no groups are supplied, the filter will not filter out any issues.
+    ///
+    pub fn from_groups<I, J>(prios: &'a RemotePriorization, groups: I) -> Result<Self>
+        where I: IntoIterator<Item = J>,
+              J: IntoIterator<Item = FilterSpec>
+    {
+        let groups = groups
+            .into_iter()
+            .map(FilterGroup::new)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(MetadataFilter { prios: prios, groups: groups })
+    }
+
+    /// Check whether the filter benefits from cached metadata
+    ///
+    /// Returns `true` if any group filters only trailers held by the
+    /// `Metadata` cached in the issue index, i.e. tags and assignees.
+    ///
+    pub fn uses_cached_metadata(&self) -> bool {
+        self.groups.iter().any(FilterGroup::uses_cached_metadata)
+    }
+
+    /// Filter an issue residing in the given repository
+    ///
+    /// The metadata supplied, if any, must be accumulated from the head of the
+    /// issue selected according to the remote priorization of the filter.
+    ///
+    pub fn filter(&self, repo: &Repository, issue: &Issue, metadata: Option<&Metadata>) -> bool {
+        // NOTE: if we ever add the filters crate as a dependency, this method
+        //       may be transferred to an implementatio nof the Filter trait
+        self.groups.is_empty() ||
+            self.groups.iter().any(|group| group.filter(repo, issue, self.prios, metadata))
+    }
+}
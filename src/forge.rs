@@ -0,0 +1,700 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Interoperability with software forges
+//!
+//! This module provides access to the issues of repositories hosted on GitHub
+//! or on Gitea and Forgejo instances, which provide a similar REST API, and
+//! their conversion into the JSON representation accepted by the `import`
+//! module. Labels, assignees and milestones are converted to trailers. Users
+//! are represented by their "noreply" addresses.
+//!
+//! Issues and messages may also be mirrored to a forge. Mirrored items carry a
+//! marker referring to the original message, which prevents them from being
+//! imported again.
+//!
+
+use clap::ArgMatches;
+use git2::{Commit, Config, Oid, Repository};
+use libgitdit::{Issue, Message, RepositoryExt};
+use libgitdit::error::{BulkResult, Result as LibResult};
+use libgitdit::issue::HeadPolicy;
+use libgitdit::trailer::spec::{self, TrailerSpec};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use error::*;
+use error::ErrorKind as EK;
+use import;
+use json::{array, string};
+use system::programs;
+
+
+/// URL of the public GitHub API
+///
+pub const GITHUB_API: &str = "https://api.github.com";
+
+
+/// Number of items requested per page
+///
+/// Servers may return fewer items per page, e.g. Gitea returns at most 50 by
+/// default.
+///
+const PER_PAGE: usize = 100;
+
+
+/// Prefix of the marker identifying items mirrored from messages
+///
+/// The marker is an HTML comment, which is not displayed by forges.
+///
+const MARKER_PREFIX: &str = "<!-- git-dit:";
+
+
+/// Kind of forge
+///
+pub enum Forge {
+    GitHub,
+    /// Gitea or Forgejo instance, identified by its host
+    Gitea(String),
+}
+
+impl Forge {
+    /// Get the forge selected via the "github" or "gitea" option
+    ///
+    /// The forge is returned along with the URL of its API, taken from the
+    /// "api" option, and the repository given as "<owner>/<name>".
+    ///
+    pub fn from_matches<'a>(matches: &'a ArgMatches) -> Option<(Self, &'a str, &'a str)> {
+        if let Some(repository) = matches.value_of("github") {
+            return Some((Forge::GitHub, matches.value_of("api").unwrap_or(GITHUB_API), repository));
+        }
+        matches.value_of("gitea").map(|repository| {
+            let api = matches.value_of("api").unwrap_or("");
+            let host = api
+                .splitn(2, "://")
+                .last()
+                .and_then(|rest| rest.split('/').next())
+                .unwrap_or("");
+            (Forge::Gitea(host.to_owned()), api, repository)
+        })
+    }
+
+    /// Get the token for accessing the API
+    ///
+    /// The token is taken from `dit.github.token` or `dit.gitea.token` or, if
+    /// not configured, from the `GITHUB_TOKEN` or `GITEA_TOKEN` environment
+    /// variable.
+    ///
+    pub fn token(&self, config: &Config) -> Option<String> {
+        let (key, variable) = match *self {
+            Forge::GitHub   => ("dit.github.token", "GITHUB_TOKEN"),
+            Forge::Gitea(_) => ("dit.gitea.token", "GITEA_TOKEN"),
+        };
+        config
+            .get_string(key)
+            .ok()
+            .or_else(|| ::std::env::var(variable).ok())
+    }
+
+    /// Get the prefix of the origin recorded for messages imported
+    ///
+    fn origin(&self) -> String {
+        match *self {
+            Forge::GitHub          => "github:".to_owned(),
+            Forge::Gitea(ref host) => format!("gitea:{}/", host),
+        }
+    }
+
+    /// Get the name and email address of a user
+    ///
+    /// GitHub does not expose the email addresses of users, which are hence
+    /// represented by their "noreply" addresses. Gitea exposes either the
+    /// actual address or a "noreply" address, depending on the user's
+    /// settings.
+    ///
+    fn identity(&self, user: &Value) -> (String, String) {
+        let login = string(user, "login");
+        let email = match *self {
+            Forge::GitHub => match user.get("id").and_then(Value::as_u64) {
+                Some(id) => format!("{}+{}@users.noreply.github.com", id, login),
+                None     => format!("{}@users.noreply.github.com", login),
+            },
+            Forge::Gitea(ref host) => Some(string(user, "email"))
+                .filter(|email| !email.is_empty())
+                .unwrap_or_else(|| format!("{}@noreply.{}", login, host)),
+        };
+        (login, email)
+    }
+
+    /// Format a user as used in trailers
+    ///
+    fn user(&self, user: &Value) -> String {
+        let (name, email) = self.identity(user);
+        format!("{} <{}>", name, email)
+    }
+}
+
+
+/// Item transferred during a synchronization
+///
+pub enum Synced {
+    /// The item given as "<owner>/<name>#<number>[/...]" was imported as a message
+    Imported(String, Oid),
+    /// The message was mirrored as the item given
+    Exported(Oid, String),
+}
+
+
+/// Client for the REST API of a forge
+///
+pub struct Client<'c> {
+    config: &'c Config,
+    forge: Forge,
+    api: String,
+    token: Option<String>,
+}
+
+impl<'c> Client<'c> {
+    /// Create a new client for the API of a forge
+    ///
+    /// The token configured for the forge, if any, is used for authenticating
+    /// all requests.
+    ///
+    pub fn new(config: &'c Config, forge: Forge, api: &str) -> Self {
+        Client {
+            config: config,
+            token: forge.token(config),
+            forge: forge,
+            api: api.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    /// Get all issues of a repository, excluding pull requests
+    ///
+    /// The repository is given as "<owner>/<name>". Issues are returned in
+    /// the order in which they were created.
+    ///
+    pub fn issues(&self, repository: &str) -> Result<Vec<Value>> {
+        let query = match self.forge {
+            Forge::GitHub   => "state=all&direction=asc",
+            Forge::Gitea(_) => "state=all&type=issues",
+        };
+        let mut issues: Vec<Value> = self
+            .get_all(&format!("/repos/{}/issues?{}", repository, query))?
+            .into_iter()
+            .filter(|issue| issue.get("pull_request").filter(|pull| !pull.is_null()).is_none())
+            .collect();
+        issues.sort_by_key(|issue| issue.get("number").and_then(Value::as_u64));
+        Ok(issues)
+    }
+
+    /// Get all issues of a repository in the representation used for imports
+    ///
+    /// Each issue is returned along with its comments, as converted by
+    /// `issue_value()`.
+    ///
+    pub fn issue_values(&self, repo: &Repository, repository: &str) -> Result<Vec<Value>> {
+        self.issues(repository)?
+            .into_iter()
+            .map(|issue| {
+                let number = issue.get("number").and_then(Value::as_u64).unwrap_or(0);
+                self.comments(repository, number)
+                    .map(|comments| issue_value(repo, &self.forge, repository, &issue, &comments))
+            })
+            .collect()
+    }
+
+    /// Synchronize the issues of a repository with those of a remote repository
+    ///
+    /// Issues and comments not mirrored from messages are imported first, as by
+    /// `issue_values()`, with the issues' heads advanced according to the policy
+    /// given. Afterwards, issues not present on the forge are created and
+    /// messages not present on the forge are mirrored as comments, in the order
+    /// in which they were created. Messages setting an issue's status to "open"
+    /// or "closed" also reopen or close the issue on the forge. Messages
+    /// imported from the remote repository, e.g. for items removed since, and
+    /// messages for which `hidden` returns true are not mirrored.
+    ///
+    /// Mirrored items are attributed to the account of the token used, with
+    /// the message's author named in the text.
+    ///
+    /// Issues which cannot be synchronized, local ones as well as those on the
+    /// forge, are reported as failures. The remaining issues are synchronized
+    /// regardless.
+    ///
+    pub fn sync(&self,
+                repo: &Repository,
+                repository: &str,
+                hidden: &Fn(&Commit) -> bool,
+                policy: HeadPolicy
+    ) -> Result<BulkResult<Vec<Synced>, Error>> {
+        trace_span!("sync", repository);
+        let mut retval = BulkResult::new(Vec::new());
+
+        let issues = repo.issues_partial()?;
+        retval.failures.extend(issues.failures.into_iter().map(Error::from));
+        let mut known = HashSet::new();
+        for issue in issues.value {
+            let messages = issue.messages().and_then(|messages| {
+                messages.map(|message| message.map(|message| message.id())).collect::<LibResult<Vec<_>>>()
+            });
+            match messages {
+                Ok(messages) => known.extend(messages),
+                Err(err) => retval.failures.push(err.into()),
+            }
+        }
+
+        // messages present on the forge and the numbers of the issues
+        let mut present = HashSet::new();
+        let mut numbers = HashMap::new();
+
+        for issue in self.issues(repository)? {
+            trace_span!("import_issue", number = issue.get("number").and_then(::serde_json::Value::as_u64));
+            match self.import_issue(repo, repository, &issue, policy, &mut present, &mut numbers) {
+                Ok(mapping) => {
+                    for (foreign, id) in mapping.value {
+                        if !known.contains(&id) {
+                            retval.value.push(Synced::Imported(foreign, id));
+                        }
+                    }
+                    retval.failures.extend(mapping.failures);
+                },
+                Err(err) => retval.failures.push(err),
+            }
+        }
+
+        // Issues which cannot be retrieved were already reported as failures.
+        for issue in repo.issues_partial()?.value {
+            trace_span!("export_issue", issue = %issue.id());
+            let exported = self.export_issue(repository, &issue, hidden, &mut present, &numbers, &mut retval.value);
+            if let Err(err) = exported {
+                retval.failures.push(err);
+            }
+        }
+
+        trace_event!(synced = retval.value.len(), failures = retval.failures.len(), "synced issues");
+        Ok(retval)
+    }
+
+    /// Import an issue of the forge along with its comments
+    ///
+    /// The messages imported and those mirrored to the forge are recorded as
+    /// present, and the number of the issue is recorded for its initial
+    /// message.
+    ///
+    fn import_issue(&self,
+                    repo: &Repository,
+                    repository: &str,
+                    issue: &Value,
+                    policy: HeadPolicy,
+                    present: &mut HashSet<Oid>,
+                    numbers: &mut HashMap<Oid, u64>
+    ) -> Result<BulkResult<import::Mapping, Error>> {
+        let number = issue.get("number").and_then(Value::as_u64).unwrap_or(0);
+        let comments = self.comments(repository, number)?;
+        present.extend(comments.iter().filter_map(|comment| marker(repo, comment)));
+
+        // The head is advanced according to the policy rather than set.
+        let mut value = issue_value(repo, &self.forge, repository, issue, &comments);
+        let head = value
+            .as_object_mut()
+            .and_then(|value| value.remove("head"))
+            .and_then(|head| head.as_str().map(String::from));
+        let mapping = import::json(repo, &value)?;
+        let mapped = |id: &str| mapping.value.iter().find(|entry| entry.0 == id).map(|entry| entry.1);
+
+        let initial = marker(repo, issue).or_else(|| mapped(&format!("{}#{}", repository, number)));
+        if let Some(initial) = initial {
+            present.insert(initial);
+            numbers.insert(initial, number);
+
+            if let Some(head) = head.and_then(|head| mapped(&head)) {
+                repo.find_issue(initial)?.advance_head(&repo.find_commit(head)?, policy)?;
+            }
+        }
+        present.extend(mapping.value.iter().map(|&(_, id)| id));
+        Ok(mapping)
+    }
+
+    /// Mirror a local issue and its messages not present on the forge
+    ///
+    /// The items created on the forge are recorded in `synced`.
+    ///
+    fn export_issue(&self,
+                    repository: &str,
+                    issue: &Issue,
+                    hidden: &Fn(&Commit) -> bool,
+                    present: &mut HashSet<Oid>,
+                    numbers: &HashMap<Oid, u64>,
+                    synced: &mut Vec<Synced>
+    ) -> Result<()> {
+        let origin = format!("{}{}#", self.forge.origin(), repository);
+        let imported = |message: &Commit| latest(message, &spec::ORIGIN_SPEC)
+            .map(|value| value.starts_with(&origin))
+            .unwrap_or(false);
+
+        let initial = issue.initial_message()?;
+        if hidden(&initial) {
+            return Ok(());
+        }
+
+        let number = match numbers.get(&issue.id()) {
+            Some(number) => *number,
+            None if imported(&initial) => return Ok(()),
+            None => {
+                let created = self.request("POST", &format!("/repos/{}/issues", repository), &json!({
+                    "title": initial.summary().unwrap_or(""),
+                    "body": mirror_text(&initial, false),
+                }))?;
+                let number = created.get("number").and_then(Value::as_u64).unwrap_or(0);
+                present.insert(initial.id());
+                synced.push(Synced::Exported(initial.id(), format!("{}#{}", repository, number)));
+                number
+            },
+        };
+
+        let mut messages = Vec::new();
+        for message in issue.messages()? {
+            let message = message?;
+            if !present.contains(&message.id()) && !hidden(&message) && !imported(&message) {
+                messages.push(message);
+            }
+        }
+        messages.sort_by_key(|message| message.time());
+
+        for message in messages {
+            let path = format!("/repos/{}/issues/{}", repository, number);
+            let created = self.request("POST", &format!("{}/comments", path), &json!({
+                "body": mirror_text(&message, true),
+            }))?;
+            let comment = created.get("id").and_then(Value::as_u64).unwrap_or(0);
+            synced.push(Synced::Exported(message.id(), format!("{}#{}/comment/{}", repository, number, comment)));
+
+            if let Some(state) = latest(&message, &spec::ISSUE_STATUS_SPEC).filter(|state| state == "open" || state == "closed") {
+                self.request("PATCH", &path, &json!({"state": state}))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get all comments on an issue
+    ///
+    pub fn comments(&self, repository: &str, number: u64) -> Result<Vec<Value>> {
+        self.get_all(&format!("/repos/{}/issues/{}/comments", repository, number))
+    }
+
+    /// Get all items of a paginated list
+    ///
+    /// Pages are requested until one without any new items is returned. Some
+    /// endpoints, e.g. Gitea's comments of an issue, ignore the pagination and
+    /// return all items for each page. Items already returned on a previous
+    /// page are skipped.
+    ///
+    fn get_all(&self, path: &str) -> Result<Vec<Value>> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let size = match self.forge {
+            Forge::GitHub   => "per_page",
+            Forge::Gitea(_) => "limit",
+        };
+        let mut retval = Vec::new();
+        let mut seen = HashSet::new();
+        for page in 1.. {
+            let value = self.get(&format!("{}{}{}={}&page={}", path, separator, size, PER_PAGE, page))?;
+            let items = value
+                .as_array()
+                .cloned()
+                .ok_or_else(|| Error::from_kind(EK::MalformedImport(format!("unexpected response for {}", path))))?;
+            let count = retval.len();
+            retval.extend(items.into_iter().filter(|item| {
+                seen.insert(item.get("id").unwrap_or(item).to_string())
+            }));
+            if retval.len() == count {
+                break;
+            }
+        }
+        Ok(retval)
+    }
+
+    /// Perform a GET request
+    ///
+    fn get(&self, path: &str) -> Result<Value> {
+        self.perform("GET", path, None)
+    }
+
+    /// Perform a request sending a JSON document
+    ///
+    fn request(&self, method: &str, path: &str, body: &Value) -> Result<Value> {
+        self.perform(method, path, Some(&body.to_string()))
+    }
+
+    /// Perform a request and parse the response
+    ///
+    fn perform(&self, method: &str, path: &str, body: Option<&str>) -> Result<Value> {
+        let accept = match self.forge {
+            Forge::GitHub   => "application/vnd.github+json",
+            Forge::Gitea(_) => "application/json",
+        };
+        let mut headers = vec![
+            format!("Accept: {}", accept),
+            "Content-Type: application/json".to_owned(),
+            "User-Agent: git-dit".to_owned(),
+        ];
+        headers.extend(self.token.iter().map(|token| format!("Authorization: token {}", token)));
+
+        let url = format!("{}{}", self.api, path);
+        let data = programs::http_request(self.config, method, &url, &headers, body)
+            .chain_err(|| EK::HttpError(method.to_owned(), url.clone()))?;
+        ::serde_json::from_slice(&data)
+            .chain_err(|| EK::MalformedImport(format!("cannot parse response for {}", url)))
+    }
+}
+
+
+/// Convert an issue and its comments to the representation used for imports
+///
+/// The issue and its comments are expected as returned by the forge's API.
+/// The initial message carries the issue's labels, assignees and milestone as
+/// trailers. Comments are represented as a chain of replies. If the issue is
+/// closed, a message closing it is attached to the last reply preceding the
+/// closing and serves as the issue's head. The ids of the messages are derived
+/// from the repository given as "<owner>/<name>" and the issue's number.
+///
+/// Items mirrored from messages present in the repository are not converted
+/// but referred to by the original messages' ids. In particular, the id of an
+/// issue mirrored from an issue of the repository is the original issue's id.
+/// The head is only included if it refers to a message converted.
+///
+pub fn issue_value(repo: &Repository,
+                   forge: &Forge,
+                   repository: &str,
+                   issue: &Value,
+                   comments: &[Value]
+) -> Value {
+    let number = issue.get("number").and_then(Value::as_u64).unwrap_or(0);
+    let title = string(issue, "title");
+    let reply_subject = format!("Re: {}", title);
+
+    // messages along with the times used for ordering them and their ids
+    let mut messages = Vec::new();
+    let id = match marker(repo, issue) {
+        Some(original) => {
+            messages.push((String::new(), original.to_string(), None));
+            original.to_string()
+        },
+        None => {
+            let id = format!("{}#{}", repository, number);
+            let mut trailers = Vec::new();
+            for label in array(issue, "labels") {
+                trailers.push((spec::ISSUE_TAG_SPEC.key, string(label, "name")));
+            }
+            for assignee in array(issue, "assignees") {
+                trailers.push((spec::ISSUE_ASSIGNEE_SPEC.key, forge.user(assignee)));
+            }
+            if let Some(milestone) = issue.get("milestone").filter(|milestone| !milestone.is_null()) {
+                trailers.push((spec::ISSUE_MILESTONE_SPEC.key, string(milestone, "title")));
+            }
+
+            let date = string(issue, "created_at");
+            let message = message(forge, id.clone(), issue.get("user"), date.clone(), title.clone(), &string(issue, "body"), trailers);
+            messages.push((date, id.clone(), Some(message)));
+            id
+        },
+    };
+
+    let foreign_id = format!("{}#{}", repository, number);
+    for comment in comments {
+        let date = string(comment, "created_at");
+        match marker(repo, comment) {
+            Some(original) => messages.push((date, original.to_string(), None)),
+            None => {
+                let comment_id = format!("{}/comment/{}", foreign_id, comment.get("id").and_then(Value::as_u64).unwrap_or(0));
+                let message = message(forge, comment_id.clone(), comment.get("user"), date.clone(), reply_subject.clone(), &string(comment, "body"), Vec::new());
+                messages.push((date, comment_id, Some(message)));
+            },
+        }
+    }
+
+    // dates are given in UTC, so they may be compared as strings
+    messages[1..].sort_by(|a, b| a.0.cmp(&b.0));
+
+    // A closed issue receives a message closing it, unless it was closed via a
+    // mirrored message. Since that message depends on the issue's current
+    // state, it is attached to the chain of replies rather than being part of
+    // it, keeping the ids of later replies stable.
+    let mirrored_status = messages
+        .iter()
+        .filter(|message| message.2.is_none())
+        .filter_map(|message| Oid::from_str(&message.1).ok())
+        .filter_map(|id| repo.find_commit(id).ok())
+        .filter_map(|message| latest(&message, &spec::ISSUE_STATUS_SPEC))
+        .last();
+    let mut closed = None;
+    if string(issue, "state") == "closed" && mirrored_status.as_ref().map(String::as_str) != Some("closed") {
+        let date = string(issue, "closed_at");
+        let closer = issue.get("closed_by").filter(|user| !user.is_null()).or_else(|| issue.get("user"));
+        let parent = messages
+            .iter()
+            .take_while(|message| message.0 <= date)
+            .last()
+            .map(|message| message.1.clone());
+        let status = vec![(spec::ISSUE_STATUS_SPEC.key, "closed".to_owned())];
+        let mut message = message(forge, format!("{}/closed", foreign_id), closer, date, reply_subject, "", status);
+        message["parents"] = json!(parent.iter().collect::<Vec<_>>());
+        closed = Some(message);
+    }
+
+    // The head is only given if it is a message converted.
+    let mut parent: Option<String> = None;
+    let mut head = None;
+    let mut messages: Vec<Value> = messages
+        .into_iter()
+        .filter_map(|(_, id, message)| {
+            let retval = message.map(|mut message| {
+                message["parents"] = json!(parent.iter().collect::<Vec<_>>());
+                message
+            });
+            head = retval.as_ref().map(|_| id.clone());
+            parent = Some(id);
+            retval
+        })
+        .collect();
+    if let Some(closed) = closed {
+        head = closed.get("id").and_then(Value::as_str).map(String::from);
+        messages.push(closed);
+    }
+
+    json!({
+        "id": id,
+        "head": head,
+        "messages": messages,
+    })
+}
+
+
+/// Get the last value of a trailer in a message, if any
+///
+fn latest(message: &Commit, spec: &TrailerSpec) -> Option<String> {
+    message
+        .trailers()
+        .filter(|trailer| trailer.key.as_ref() == spec.key)
+        .map(|trailer| trailer.value.to_string())
+        .last()
+}
+
+
+/// Get the message an item was mirrored from
+///
+/// The message is only returned if it is present in the repository.
+///
+fn marker(repo: &Repository, item: &Value) -> Option<Oid> {
+    let body = string(item, "body");
+    let start = body.find(MARKER_PREFIX)? + MARKER_PREFIX.len();
+    let end = body[start..].find("-->")? + start;
+    Oid::from_str(body[start..end].trim())
+        .ok()
+        .filter(|id| repo.find_commit(*id).is_ok())
+}
+
+
+/// Render the text of an item mirroring a message
+///
+/// The text names the message's author and ends with the marker referring to
+/// the message. The subject is only included if requested, and omitted for
+/// replies with the default subject. Encrypted parts of the message are
+/// mirrored as they are, i.e. without being decrypted.
+///
+fn mirror_text(message: &Commit, subject: bool) -> String {
+    let author = message.author();
+    let mut lines = vec![format!("**{}** wrote:", author.name().unwrap_or("")), String::new()];
+    let summary = message.summary().unwrap_or("");
+    if subject && !summary.starts_with("Re: ") {
+        lines.push(summary.to_owned());
+        lines.push(String::new());
+    }
+    lines.extend(message.raw_body_lines());
+    while lines.last().map(|line| line.trim().is_empty()).unwrap_or(false) {
+        lines.pop();
+    }
+    lines.push(String::new());
+    lines.push(format!("{}{} -->", MARKER_PREFIX, message.id()));
+    lines.join("\n")
+}
+
+
+/// Represent a message
+///
+/// The message's text is followed by the trailers given and a trailer naming
+/// the item the message is derived from.
+///
+fn message(forge: &Forge,
+           id: String,
+           user: Option<&Value>,
+           date: String,
+           subject: String,
+           text: &str,
+           trailers: Vec<(&str, String)>
+) -> Value {
+    let author = forge.identity(user.unwrap_or(&json!({"login": "ghost"})));
+    let origin = format!("{}{}", forge.origin(), id);
+    import::message(id, origin, author, date, subject, text, trailers)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use libgitdit::message::crypt::{self, Cipher};
+    use std::env;
+    use std::fs;
+
+    /// Dummy cipher decrypting any ciphertext to the same text
+    ///
+    struct RevealingCipher;
+
+    impl Cipher for RevealingCipher {
+        fn encrypt(&self, _: &str, _: &[String]) -> LibResult<String> {
+            unimplemented!()
+        }
+
+        fn decrypt(&self, _: &str) -> LibResult<String> {
+            Ok("Secret text".to_owned())
+        }
+    }
+
+    // Mirroring tests
+
+    #[test]
+    fn mirror_encrypted_message() {
+        let path = env::temp_dir().join("git-dit-test-mirror-encrypted-message");
+        fs::remove_dir_all(&path).ok();
+        let repo = Repository::init_bare(&path).expect("Could not init repository");
+
+        let sig = Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let text = "Secret issue\n\n-----BEGIN PGP MESSAGE-----\nciphertext\n-----END PGP MESSAGE-----\n";
+        let id = repo
+            .commit(None, &sig, &sig, text, &empty_tree, &[])
+            .expect("Could not create message");
+        let message = repo.find_commit(id).expect("Could not find message");
+
+        crypt::set_decryption_cipher(Some(Box::new(RevealingCipher)));
+        let mirrored = mirror_text(&message, true);
+        crypt::set_decryption_cipher(None);
+        fs::remove_dir_all(&path).ok();
+
+        assert!(mirrored.contains("-----BEGIN PGP MESSAGE-----\nciphertext\n-----END PGP MESSAGE-----"));
+        assert!(!mirrored.contains("Secret text"));
+    }
+}
@@ -7,7 +7,7 @@
 //   published by the Free Software Foundation.
 //
 
-use git2::Reference;
+use git2::{Oid, Reference};
 use std::borrow::Borrow;
 
 
@@ -25,23 +25,29 @@ pub trait ReferrenceExt {
 
 impl<'r> ReferrenceExt for Reference<'r> {
     fn remote(&self) -> Option<&str> {
-        if let Some(name) = self.name() {
-            let mut name_parts = name.split('/');
-
-            if !is_match!(name_parts.next(), Some("refs")) {
-                return None
-            }
-            if !is_match!(name_parts.next(), Some("remotes")) {
-                return None
-            }
-            name_parts.next()
-        } else {
-            None
-        }
+        self.name().and_then(remote_of)
     }
 }
 
 
+/// Get the name of the remote associated with a reference name
+///
+/// If the name is the name of a remote tracking ref, the name of the remote
+/// will be returned. Otherwise, the function will return `None`.
+///
+pub fn remote_of(name: &str) -> Option<&str> {
+    let mut name_parts = name.split('/');
+
+    if !is_match!(name_parts.next(), Some("refs")) {
+        return None
+    }
+    if !is_match!(name_parts.next(), Some("remotes")) {
+        return None
+    }
+    name_parts.next()
+}
+
+
 /// Expression of priorization of remotes
 ///
 /// Use this type for querying the priority of a remote, represented as a
@@ -75,6 +81,24 @@ impl RemotePriorization {
             None => Some(0),
         }
     }
+
+    /// Select the target of the reference with the highest priority
+    ///
+    /// This function selects among references given by their names and
+    /// targets, e.g. as returned by `Issue::head_targets()`.
+    ///
+    pub fn select_target<'a, I>(&self, references: I) -> Option<Oid>
+        where I: IntoIterator<Item = &'a (String, Oid)>
+    {
+        references
+            .into_iter()
+            .filter_map(|&(ref name, target)| match remote_of(name) {
+                Some(remote) => self.priority_for_remote(remote),
+                None => Some(0),
+            }.map(|prio| (target, prio)))
+            .min_by_key(|item| item.1)
+            .map(|item| item.0)
+    }
 }
 
 impl<'a> From<&'a str> for RemotePriorization {
@@ -0,0 +1,96 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! HTML rendering
+//!
+//! This module provides the rendering of pages shared by the web interface and
+//! the static site export.
+//!
+
+use git2::{Commit, Repository};
+use libgitdit::Issue;
+use libgitdit::trailer::spec::ISSUE_STATUS_SPEC;
+
+use display::{Palette, thread_lines};
+use error::*;
+use util::RepositoryUtil;
+
+
+/// Render a row of a table of issues
+///
+/// The row links the issue's id to the URL given and contains the issue's
+/// status, author and subject.
+///
+pub fn issue_row(repo: &Repository, issue: &Issue, initial: &Commit, url: &str, id_len: usize) -> String {
+    format!("<tr><td><a href=\"{}\">{:.*}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(url),
+            id_len,
+            issue.id(),
+            escape(&issue_status(repo, issue)),
+            escape(&initial.author().to_string()),
+            escape(initial.summary().unwrap_or("")))
+}
+
+
+/// Render an issue as a thread
+///
+/// The issue is rendered from its selected head. Messages for which `hidden`
+/// returns true are replaced by a placeholder.
+///
+pub fn issue_thread(repo: &Repository, issue: &Issue, hidden: &Fn(&Commit) -> bool, id_len: usize) -> Result<String> {
+    let head = repo.issue_head(issue);
+    let lines = thread_lines(issue, head.id(), id_len, hidden, 3, &Palette::plain())?;
+    Ok(format!("<pre>{}</pre>", escape(&lines.join("\n"))))
+}
+
+
+/// Get the status of an issue
+///
+/// Issues without a status are considered open.
+///
+pub fn issue_status(repo: &Repository, issue: &Issue) -> String {
+    let head = repo.issue_head(issue);
+    repo.metadata_values(issue, &head, &ISSUE_STATUS_SPEC)
+        .pop()
+        .unwrap_or_else(|| "open".to_owned())
+}
+
+
+/// Wrap content in an HTML page
+///
+/// The header is placed above the page's title and is expected to be HTML.
+///
+pub fn page(title: &str, header: &str, content: &str) -> String {
+    format!(concat!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0} - git-dit</title>",
+            "<style>body {{ font-family: sans-serif; margin: 2em; }} td {{ padding: 0 1em 0 0; }}</style>",
+            "</head><body>{1}<h1>{0}</h1>{2}</body></html>\n"),
+        escape(title),
+        header,
+        content)
+}
+
+
+/// Escape text for inclusion in HTML
+///
+pub fn escape(text: &str) -> String {
+    let mut retval = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&'  => retval.push_str("&amp;"),
+            '<'  => retval.push_str("&lt;"),
+            '>'  => retval.push_str("&gt;"),
+            '"'  => retval.push_str("&quot;"),
+            '\'' => retval.push_str("&#39;"),
+            c    => retval.push(c),
+        }
+    }
+    retval
+}
+
@@ -0,0 +1,250 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Localization of user-facing messages
+//!
+//! Messages are written in English and passed through the `tr!` macro, which
+//! looks up a translation in the catalog of the selected `Locale`. Messages
+//! without a translation are presented in English. The English text itself
+//! serves as the key, so a missing catalog entry never hides information.
+//!
+//! Arguments are filled in after the lookup. A translation may refer to them
+//! via `{}`, in order, or via `{0}`, `{1}` etc. if the word order differs.
+//!
+
+use std::env;
+use std::fmt::{self, Write};
+use std::sync::RwLock;
+
+
+/// Environment variables consulted for the locale, in order of precedence
+///
+const LOCALE_VARS: &'static [&'static str] = &["LC_ALL", "LC_MESSAGES", "LANG"];
+
+
+/// Look up the translation of a message
+///
+/// The message may contain placeholders for arguments, which are filled in
+/// after the lookup.
+///
+macro_rules! tr {
+    ($msg:expr) => {
+        $crate::i18n::translate($msg)
+    };
+    ($msg:expr, $($arg:expr),+ $(,)*) => {
+        $crate::i18n::format($crate::i18n::translate($msg), &[$(&$arg as &::std::fmt::Display),+])
+    };
+}
+
+
+/// Locale for user-facing messages
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Locale {
+    English,
+    German,
+}
+
+impl Locale {
+    /// Get the locale for a language tag
+    ///
+    /// Tags like "`de`", "`de_DE.UTF-8`" or "`de-AT`" are accepted. The
+    /// portable locales "`C`" and "`POSIX`" map to English. Returns `None` for
+    /// languages without a catalog.
+    ///
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag
+            .split(&['_', '-', '.', '@'][..])
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        match language.as_str() {
+            "c" | "posix" | "en" => Some(Locale::English),
+            "de" => Some(Locale::German),
+            _ => None,
+        }
+    }
+
+    /// Determine the locale from the environment
+    ///
+    /// The first of `LC_ALL`, `LC_MESSAGES` and `LANG` which is set and not
+    /// empty determines the locale. English is used as a fallback.
+    ///
+    pub fn from_env() -> Self {
+        LOCALE_VARS
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::from_tag(&value))
+            .unwrap_or(Locale::English)
+    }
+
+    /// Get the catalog of the locale
+    ///
+    /// The catalog maps English messages to their translation.
+    ///
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::English => &[],
+            Locale::German  => GERMAN,
+        }
+    }
+}
+
+
+lazy_static! {
+    // locale selected for the current process
+    static ref LOCALE: RwLock<Locale> = RwLock::new(Locale::English);
+}
+
+
+/// Select the locale used for messages
+///
+pub fn set_locale(locale: Locale) {
+    if let Ok(mut current) = LOCALE.write() {
+        *current = locale;
+    }
+}
+
+
+/// Get the translation of a message for the selected locale
+///
+/// The message is returned unaltered if there is no translation.
+///
+pub fn translate(message: &'static str) -> &'static str {
+    let locale = LOCALE.read().map(|locale| *locale).unwrap_or(Locale::English);
+    locale
+        .catalog()
+        .iter()
+        .find(|&&(english, _)| english == message)
+        .map(|&(_, translation)| translation)
+        .unwrap_or(message)
+}
+
+
+/// Fill in the placeholders of a message
+///
+/// A placeholder `{}` refers to the argument following the one referred to by
+/// the previous placeholder, `{N}` to the N-th argument. Placeholders referring
+/// to arguments not supplied are removed.
+///
+pub fn format(message: &str, args: &[&fmt::Display]) -> String {
+    let mut retval = String::with_capacity(message.len());
+    let mut next = 0;
+    let mut rest = message;
+
+    while let Some(start) = rest.find('{') {
+        retval.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let index = match &rest[1..end] {
+            "" => next,
+            spec => match spec.parse::<usize>() {
+                Ok(index) => index,
+                Err(_) => {
+                    // not a placeholder
+                    retval.push('{');
+                    rest = &rest[1..];
+                    continue;
+                },
+            },
+        };
+        if let Some(arg) = args.get(index) {
+            write!(retval, "{}", arg).ok();
+        }
+        next = index + 1;
+        rest = &rest[end + 1..];
+    }
+
+    retval.push_str(rest);
+    retval
+}
+
+
+/// German catalog
+///
+const GERMAN: &'static [(&'static str, &'static str)] = &[
+    // errors
+    ("Malformed filter spec: {}", "Fehlerhafte Filterangabe: {}"),
+    ("Malformed author: {}", "Fehlerhafte Autorenangabe: {}"),
+    ("Malformed date: {}", "Fehlerhaftes Datum: {}"),
+    ("Unknown metadata key: {}", "Unbekannter Metadatenschlüssel: {}"),
+    ("Unknown identity: {}", "Unbekannte Identität: {}"),
+    ("IO Error", "Ein-/Ausgabefehler"),
+    ("Database error", "Datenbankfehler"),
+    ("Could not find {} configuration or ENV variable",
+     "Weder Konfiguration noch Umgebungsvariable für {} gefunden"),
+    ("A child program was unsuccessful", "Ein aufgerufenes Programm ist fehlgeschlagen"),
+    ("Unknown encryption backend: {}", "Unbekanntes Verschlüsselungs-Backend: {}"),
+    ("Could not serve on {}: {}", "Kann nicht auf {} bereitstellen: {}"),
+    ("Encryption requires 'dit.encryption.backend' and 'dit.encryption.recipients' to be set",
+     "Verschlüsselung erfordert, dass 'dit.encryption.backend' und 'dit.encryption.recipients' gesetzt sind"),
+    ("No issue specified and no current issue set (see 'git dit checkout')",
+     "Kein Issue angegeben und kein aktuelles Issue gesetzt (siehe 'git dit checkout')"),
+    ("Unknown identity profile '{}' (see dit.identity.<name>.*)",
+     "Unbekanntes Identitätsprofil '{}' (siehe dit.identity.<name>.*)"),
+    ("No remote specified and 'dit.remote' is not set",
+     "Kein Remote angegeben und 'dit.remote' ist nicht gesetzt"),
+    ("The hook '{}' failed", "Der Hook '{}' ist fehlgeschlagen"),
+    ("The message was rejected because of lint errors (see dit.lint.*)",
+     "Die Nachricht wurde wegen Lint-Fehlern abgelehnt (siehe dit.lint.*)"),
+    ("The repository does not ship a template named '{}'",
+     "Das Repository enthält keine Vorlage namens '{}'"),
+    ("Could not determine the issue for branch '{}'",
+     "Das Issue zum Branch '{}' konnte nicht ermittelt werden"),
+    ("No locally created message found in issue {}",
+     "Keine lokal erstellte Nachricht in Issue {} gefunden"),
+    ("Message {} is known to a remote and cannot be undone",
+     "Nachricht {} ist einem Remote bekannt und kann nicht rückgängig gemacht werden"),
+    ("The reflog does not record the previous head before message {}",
+     "Das Reflog verzeichnet keinen vorherigen Head vor Nachricht {}"),
+    ("Cannot write '{}'", "Kann '{}' nicht schreiben"),
+    ("Cannot import data: {}", "Kann Daten nicht importieren: {}"),
+    ("Malformed XML document: {}", "Fehlerhaftes XML-Dokument: {}"),
+    ("Could not send message {} as mail", "Nachricht {} konnte nicht als Mail versandt werden"),
+    ("IMAP error: {}", "IMAP-Fehler: {}"),
+    ("HTTP request {} {} failed", "HTTP-Anfrage {} {} fehlgeschlagen"),
+    // warnings and other diagnostics
+    ("git-dit was built without support for SQLite databases",
+     "git-dit wurde ohne Unterstützung für SQLite-Datenbanken gebaut"),
+    ("git-dit was built without support for the interactive interface",
+     "git-dit wurde ohne Unterstützung für die interaktive Oberfläche gebaut"),
+    ("git-dit was built without support for the web interface",
+     "git-dit wurde ohne Unterstützung für die Weboberfläche gebaut"),
+    ("Could not initialize logger: {}", "Logger konnte nicht initialisiert werden: {}"),
+    ("Unknown locale '{}'", "Unbekannte Sprachumgebung '{}'"),
+    ("Unknown trace level in {}", "Unbekannte Trace-Stufe in {}"),
+    ("Flagged {}: {}", "Markiert {}: {}"),
+    ("Quarantined {}: {}", "In Quarantäne verschoben {}: {}"),
+    ("Could not update the issue index: {}", "Der Issue-Index konnte nicht aktualisiert werden: {}"),
+    ("Could not use the search index: {}", "Der Suchindex konnte nicht verwendet werden: {}"),
+    ("Not expanding '{}' in a template fetched from a remote",
+     "'{}' wird in einer von einem Remote geholten Vorlage nicht ersetzt"),
+    ("Ignoring mail {}, which is not a reply to any known message",
+     "Ignoriere Mail {}, die keine Antwort auf eine bekannte Nachricht ist"),
+    ("Rejected mail {}: {}", "Mail {} abgelehnt: {}"),
+    ("No commit was created because the moderation data did not change.",
+     "Es wurde kein Commit erstellt, da sich die Moderationsdaten nicht geändert haben."),
+    ("No commit was created because the protected refs did not change.",
+     "Es wurde kein Commit erstellt, da sich die geschützten Referenzen nicht geändert haben."),
+    ("Cannot repair {}", "Kann {} nicht reparieren"),
+    ("Message will only be quoted if an editor is used.",
+     "Die Nachricht wird nur zitiert, wenn ein Editor verwendet wird."),
+    ("Issue {} is already {}.", "Issue {} ist bereits {}."),
+    ("No commit was created because the assignees would not change.",
+     "Es wurde kein Commit erstellt, da sich die Zuständigen nicht ändern würden."),
+    ("No commit was created because no reference or tags were supplied.",
+     "Es wurde kein Commit erstellt, da weder Referenzen noch Tags angegeben wurden."),
+    ("No commit was created because the template did not change.",
+     "Es wurde kein Commit erstellt, da sich die Vorlage nicht geändert hat."),
+];
+
@@ -0,0 +1,197 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Minimal IMAP client
+//!
+//! This module provides an IMAP (RFC 3501) client supporting only the commands
+//! required for retrieving unseen mails from a mailbox. The client operates on
+//! arbitrary streams, e.g. a TCP connection or the stdin and stdout of a tunnel
+//! program taking care of encryption and authentication.
+//!
+
+use std::io::{BufRead, Write};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Untagged response
+///
+/// The response consists of its text, without the leading "* ", and the
+/// contents of all literals it contains.
+///
+struct Response {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+
+/// IMAP session
+///
+pub struct Session<R: BufRead, W: Write> {
+    reader: R,
+    writer: W,
+    tag: u32,
+}
+
+impl<R: BufRead, W: Write> Session<R, W> {
+    /// Start a session on a connection
+    ///
+    /// The server's greeting is consumed.
+    ///
+    pub fn new(reader: R, writer: W) -> Result<Self> {
+        let mut retval = Session { reader: reader, writer: writer, tag: 0 };
+        let (greeting, _) = retval.read_line()?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(imap_error(&greeting));
+        }
+        Ok(retval)
+    }
+
+    /// Log in with a user name and password
+    ///
+    pub fn login(&mut self, user: &str, password: &str) -> Result<()> {
+        self.command(&format!("LOGIN {} {}", quote(user), quote(password))).map(|_| ())
+    }
+
+    /// Select a mailbox
+    ///
+    pub fn select(&mut self, mailbox: &str) -> Result<()> {
+        self.command(&format!("SELECT {}", quote(mailbox))).map(|_| ())
+    }
+
+    /// Get the UIDs of all unseen mails in the selected mailbox
+    ///
+    pub fn unseen(&mut self) -> Result<Vec<u32>> {
+        Ok(self.command("UID SEARCH UNSEEN")?
+            .into_iter()
+            .filter(|response| response.text.starts_with("SEARCH"))
+            .flat_map(|response| response
+                .text
+                .split_whitespace()
+                .skip(1)
+                .filter_map(|uid| uid.parse().ok())
+                .collect::<Vec<_>>())
+            .collect())
+    }
+
+    /// Retrieve a mail without marking it as seen
+    ///
+    pub fn fetch(&mut self, uid: u32) -> Result<Vec<u8>> {
+        self.command(&format!("UID FETCH {} BODY.PEEK[]", uid))?
+            .into_iter()
+            .filter(|response| response.text.contains("FETCH"))
+            .flat_map(|response| response.literals)
+            .next()
+            .ok_or_else(|| imap_error(&format!("no mail with UID {}", uid)))
+    }
+
+    /// Mark a mail as seen
+    ///
+    pub fn mark_seen(&mut self, uid: u32) -> Result<()> {
+        self.command(&format!("UID STORE {} +FLAGS (\\Seen)", uid)).map(|_| ())
+    }
+
+    /// Process all unseen mails in the selected mailbox
+    ///
+    /// Mails for which `process` returns true are marked as seen, others are
+    /// left unseen.
+    ///
+    pub fn process_unseen(&mut self, process: &mut FnMut(&[u8]) -> bool) -> Result<()> {
+        for uid in self.unseen()? {
+            let mail = self.fetch(uid)?;
+            if process(&mail) {
+                self.mark_seen(uid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// End the session
+    ///
+    pub fn logout(&mut self) -> Result<()> {
+        self.command("LOGOUT").map(|_| ())
+    }
+
+    /// Issue a command and collect the untagged responses
+    ///
+    /// An error is returned if the command does not complete successfully.
+    ///
+    fn command(&mut self, command: &str) -> Result<Vec<Response>> {
+        self.tag += 1;
+        let tag = format!("A{}", self.tag);
+        write!(self.writer, "{} {}\r\n", tag, command).chain_err(|| EK::WrappedIOError)?;
+        self.writer.flush().chain_err(|| EK::WrappedIOError)?;
+
+        let mut responses = Vec::new();
+        loop {
+            let (line, literals) = self.read_line()?;
+            if line.starts_with("* ") {
+                responses.push(Response { text: line[2..].to_owned(), literals: literals });
+                continue;
+            }
+
+            let mut parts = line.splitn(3, ' ');
+            if parts.next() != Some(tag.as_str()) {
+                continue;
+            }
+            return match parts.next() {
+                Some("OK") => Ok(responses),
+                _ => Err(imap_error(&line)),
+            };
+        }
+    }
+
+    /// Read a line of a response, including any literals
+    ///
+    /// Literals are replaced by their length in the line returned.
+    ///
+    fn read_line(&mut self) -> Result<(String, Vec<Vec<u8>>)> {
+        let mut line = String::new();
+        let mut literals = Vec::new();
+        loop {
+            let mut part = Vec::new();
+            if self.reader.read_until(b'\n', &mut part).chain_err(|| EK::WrappedIOError)? == 0 {
+                return Err(imap_error("connection closed"));
+            }
+            let part = String::from_utf8_lossy(&part);
+            let part = part.trim_end_matches(|c| c == '\r' || c == '\n');
+
+            // a literal is announced as "{<length>}" at the end of a line
+            let length = part
+                .rfind('{')
+                .filter(|_| part.ends_with('}'))
+                .and_then(|pos| part[pos + 1..part.len() - 1].parse::<usize>().ok());
+            line.push_str(part);
+            match length {
+                Some(length) => {
+                    let mut literal = vec![0; length];
+                    self.reader.read_exact(&mut literal).chain_err(|| EK::WrappedIOError)?;
+                    literals.push(literal);
+                },
+                None => return Ok((line, literals)),
+            }
+        }
+    }
+}
+
+
+/// Quote a string for use as argument
+///
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+
+/// Create an error for a failed command or unexpected response
+///
+fn imap_error(reason: &str) -> Error {
+    Error::from_kind(EK::ImapError(reason.to_owned()))
+}
+
@@ -0,0 +1,487 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Import of issues
+//!
+//! This module provides importers creating issues and messages from data
+//! produced by other tools or trackers. The JSON format accepted is the one
+//! produced by the "export" subcommand, documented in `doc/json.md`. Replies
+//! received as mails are imported as messages of the issues they refer to.
+//!
+
+use chrono::DateTime;
+use git2::{Commit, Oid, Repository, Signature, Time};
+use libgitdit::RepositoryExt;
+use libgitdit::error::BulkResult;
+use libgitdit::issue::{HeadPolicy, IssueRefType};
+use libgitdit::message::LineIteratorExt;
+use libgitdit::message_index::MessageIndex;
+use libgitdit::quarantine::{MessageFilter, Verdict};
+use libgitdit::trailer::spec;
+use libgitdit::transaction::RefTransaction;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use error::*;
+use error::ErrorKind as EK;
+use export::JSON_VERSION;
+use mail::{self, Mail};
+
+
+/// Mapping of foreign message ids to the ids of the imported messages
+///
+/// The entries are ordered in the order in which the messages were imported.
+///
+pub type Mapping = Vec<(String, Oid)>;
+
+
+/// Import issues from a JSON value
+///
+/// The value may either be a document as produced by the "export" subcommand
+/// or a single issue with messages. Messages are created with the authorship,
+/// dates and contents recorded. Messages hidden in the export, which only
+/// consist of an id, are skipped and replies to them are attached to their
+/// parent instead. Parents not part of an issue are retained if they exist in
+/// the repository. If the id of an issue refers to an issue in the repository,
+/// the messages are added to that issue instead of creating a new one.
+///
+/// Since the ids of messages depend only on their contents, importing the
+/// same data twice yields the same messages.
+///
+/// Issues which cannot be imported are reported as failures. The remaining
+/// issues are imported regardless.
+///
+pub fn json(repo: &Repository, value: &Value) -> Result<BulkResult<Mapping, Error>> {
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(JSON_VERSION);
+    if version > JSON_VERSION {
+        return Err(Error::from_kind(EK::MalformedImport(format!("unsupported version {}", version))));
+    }
+
+    let mut mapping = HashMap::new();
+    let mut retval = BulkResult::new(Vec::new());
+    let issues = match value.get("issues") {
+        Some(issues) => array(issues, "issues")?,
+        None => ::std::slice::from_ref(value),
+    };
+    for issue in issues {
+        match json_issue(repo, issue, &mut mapping) {
+            Ok(imported) => retval.value.extend(imported),
+            Err(err) => retval.failures.push(err),
+        }
+    }
+    Ok(retval)
+}
+
+
+/// Import a single issue from a JSON value
+///
+/// The ids of all messages imported, including hidden ones, are recorded in
+/// `mapping`, which is used for resolving the parents of further messages.
+///
+fn json_issue(repo: &Repository, value: &Value, mapping: &mut HashMap<String, Oid>) -> Result<Mapping> {
+    let id = string(value, "id")?;
+    let component = value.get("component").and_then(Value::as_str);
+    let messages = array(value.get("messages").unwrap_or(&Value::Null), "messages")?;
+    let ids: Vec<&str> = messages.iter().filter_map(|m| m.get("id").and_then(Value::as_str)).collect();
+    let tree = repo.empty_tree()?;
+
+    let mut issue = None;
+    let mut leaves = HashSet::new();
+    let mut retval = Vec::new();
+
+    // messages may be added to an existing issue, referred to by its id
+    if let Some(existing) = Oid::from_str(id).ok().and_then(|id| repo.find_issue(id).ok()) {
+        for leaf in existing.local_refs(IssueRefType::Leaf)? {
+            leaves.extend(leaf?.target());
+        }
+        mapping.insert(id.to_owned(), existing.id());
+        issue = Some(existing);
+    }
+
+    // Messages are imported once all their parents are, regardless of the
+    // order in which they are supplied.
+    let mut pending: Vec<&Value> = messages.iter().collect();
+    while !pending.is_empty() {
+        let count = pending.len();
+        let mut deferred = Vec::new();
+
+        for message in pending {
+            let message_id = string(message, "id")?;
+            let foreign_parents = parents(message)?;
+            if foreign_parents.iter().any(|p| ids.contains(p) && !mapping.contains_key(*p)) {
+                deferred.push(message);
+                continue;
+            }
+
+            let mut parent_ids = Vec::new();
+            for parent in foreign_parents {
+                match mapping.get(parent) {
+                    Some(parent) => parent_ids.push(*parent),
+                    None => if let Some(parent) = Oid::from_str(parent).ok().filter(|p| repo.find_commit(*p).is_ok()) {
+                        parent_ids.push(parent);
+                    },
+                }
+            }
+
+            if message.get("hidden").and_then(Value::as_bool).unwrap_or(false) {
+                // replies to hidden messages are attached to their parent
+                match parent_ids.first() {
+                    Some(parent) => mapping.insert(message_id.to_owned(), *parent),
+                    None => return Err(Error::from_kind(EK::MalformedImport(
+                        format!("hidden message {} has no parent", message_id)
+                    ))),
+                };
+                continue;
+            }
+
+            let author = signature(message.get("author").ok_or_else(|| malformed("author"))?)?;
+            let committer = match message.get("committer") {
+                Some(committer) => signature(committer)?,
+                None => author.clone(),
+            };
+            let text = text(message)?;
+            let parent_messages = parent_ids
+                .iter()
+                .map(|id| repo.find_commit(*id))
+                .collect::<::std::result::Result<Vec<Commit>, _>>()?;
+
+            let parent_refs: Vec<&Commit> = parent_messages.iter().collect();
+            let new_id = if message_id == id {
+                // Issues imported previously are retained, along with their heads.
+                let existing = repo
+                    .commit(None, &author, &committer, &text, &tree, &parent_refs)
+                    .ok()
+                    .and_then(|id| repo.find_issue(id).ok());
+                let created = match existing {
+                    Some(existing) => existing,
+                    None => repo.import_issue_in_component(component,
+                                                           &author,
+                                                           &committer,
+                                                           text,
+                                                           &tree,
+                                                           parent_messages.iter())?,
+                };
+                let new_id = created.id();
+
+                // Messages imported previously already have leaves.
+                for leaf in created.local_refs(IssueRefType::Leaf)? {
+                    leaves.extend(leaf?.target());
+                }
+                issue = Some(created);
+                new_id
+            } else {
+                let issue = match issue {
+                    Some(ref issue) => issue,
+                    None => {
+                        // the initial message has to be imported first
+                        deferred.push(message);
+                        continue;
+                    },
+                };
+                let new_id = repo.commit(None, &author, &committer, &text, &tree, &parent_refs)?;
+                if leaves.insert(new_id) {
+                    issue.add_leaf(new_id)?;
+                }
+                new_id
+            };
+            mapping.insert(message_id.to_owned(), new_id);
+            retval.push((message_id.to_owned(), new_id));
+        }
+
+        if deferred.len() == count {
+            return Err(Error::from_kind(EK::MalformedImport(
+                format!("cannot resolve the parents of the messages of issue {}", id)
+            )));
+        }
+        pending = deferred;
+    }
+
+    let issue = issue.ok_or_else(|| Error::from_kind(EK::MalformedImport(
+        format!("initial message of issue {} is missing", id)
+    )))?;
+
+    // The head is set to the imported version of the head exported, if any.
+    let head = value
+        .get("head")
+        .and_then(Value::as_str)
+        .and_then(|head| mapping.get(head))
+        .cloned();
+    if let Some(head) = head {
+        issue.update_head(head, true)?;
+    }
+
+    Ok(retval)
+}
+
+
+/// Outcome of importing a mail
+///
+pub enum MailImport {
+    /// The mail was imported as the message given
+    Imported(Oid),
+    /// The mail was imported, but flagged by the filter for the reason given
+    Flagged(Oid, String),
+    /// The mail is a message sent via mail, which is known already
+    Known(Oid),
+    /// The mail does not refer to any known message
+    Unrelated,
+    /// The mail was rejected by the filter, for the reason given
+    Rejected(String),
+}
+
+
+/// Import a mail as reply to the message it refers to
+///
+/// The parent is determined from the "In-Reply-To" header or, if it does not
+/// refer to a known message, the "References" header. The message is created
+/// with the mail's sender and date as author and the signature given as
+/// committer. The text of the message consists of the mail's subject and its
+/// plain text body. The message is screened by the filter before it is added
+/// to its issue, whose head is advanced according to the policy given. The
+/// issue is looked up in the message index given, which is refreshed if the
+/// parent is not found.
+///
+/// Since the ids of messages depend only on their contents, importing the
+/// same mail twice yields the same message.
+///
+pub fn mail<F>(repo: &Repository,
+               index: &mut MessageIndex,
+               mail: &Mail,
+               committer: &Signature,
+               filter: &F,
+               policy: HeadPolicy
+) -> Result<MailImport>
+    where F: MessageFilter
+{
+    // mails sent for messages may be received again, e.g. via a mailing list
+    let own_id = mail
+        .header("Message-ID")
+        .and_then(mail::parse_message_id)
+        .filter(|id| repo.find_commit(*id).is_ok());
+    if let Some(id) = own_id {
+        return Ok(MailImport::Known(id));
+    }
+
+    let parent = mail
+        .message_ids("In-Reply-To")
+        .into_iter()
+        .chain(mail.message_ids("References").into_iter().rev())
+        .filter_map(|id| mail::parse_message_id(&id))
+        .filter_map(|id| repo.find_commit(id).ok())
+        .next();
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return Ok(MailImport::Unrelated),
+    };
+    let issue = match index.lookup(repo, &parent) {
+        Ok(issue) => issue,
+        Err(_) => return Ok(MailImport::Unrelated),
+    };
+
+    let author = mail.author()?;
+    let tree = repo.empty_tree()?;
+    let id = repo.commit(None, &author, committer, &mail_text(mail), &tree, &[&parent])?;
+    let message = repo.find_commit(id)?;
+    let verdict = filter.check(&message)?;
+    if let Verdict::Quarantine(reason) = verdict {
+        return Ok(MailImport::Rejected(reason));
+    }
+
+    let mut known = false;
+    for leaf in issue.local_refs(IssueRefType::Leaf)? {
+        known |= leaf?.target() == Some(id);
+    }
+    let mut transaction = RefTransaction::new(repo)?;
+    if !known {
+        issue.stage_leaf(&mut transaction, id)?;
+    }
+    if issue.head_advances(&message, policy)? {
+        issue.stage_head(&mut transaction, id)?;
+    }
+    transaction.commit()?;
+    Ok(match verdict {
+        Verdict::Flag(reason) => MailImport::Flagged(id, reason),
+        _ => MailImport::Imported(id),
+    })
+}
+
+
+/// Import a mail as a new issue
+///
+/// The initial message is created like a reply by `mail()` and screened by the
+/// filter before the issue is created.
+///
+pub fn mail_as_issue<F>(repo: &Repository, mail: &Mail, committer: &Signature, filter: &F) -> Result<MailImport>
+    where F: MessageFilter
+{
+    let author = mail.author()?;
+    let tree = repo.empty_tree()?;
+    let text = mail_text(mail);
+
+    // The message is created before the issue for screening. Since the ids of
+    // messages depend only on their contents, the issue's initial message will
+    // be the same message.
+    let id = repo.commit(None, &author, committer, &text, &tree, &[])?;
+    let verdict = filter.check(&repo.find_commit(id)?)?;
+    if let Verdict::Quarantine(reason) = verdict {
+        return Ok(MailImport::Rejected(reason));
+    }
+
+    let issue = repo.import_issue_in_component(None, &author, committer, text, &tree, Vec::new())?;
+    Ok(match verdict {
+        Verdict::Flag(reason) => MailImport::Flagged(issue.id(), reason),
+        _ => MailImport::Imported(issue.id()),
+    })
+}
+
+
+/// Represent a message for importing
+///
+/// The message's text is followed by the trailers given and a trailer naming
+/// the origin of the message. The author is given as name and email address,
+/// the date in RFC 3339 format.
+///
+pub fn message(id: String,
+               origin: String,
+               author: (String, String),
+               date: String,
+               subject: String,
+               text: &str,
+               mut trailers: Vec<(&str, String)>
+) -> Value {
+    trailers.push((spec::ORIGIN_SPEC.key, origin));
+    json!({
+        "id": id,
+        "author": {
+            "name": author.0,
+            "email": author.1,
+            "date": date,
+        },
+        "subject": subject,
+        "body": body(text, &trailers),
+    })
+}
+
+
+/// Represent an issue whose messages form a chain of replies
+///
+/// Each message is a reply to the preceding one and the last message is the
+/// issue's head.
+///
+pub fn chain(id: String, mut messages: Vec<Value>) -> Value {
+    for index in 1..messages.len() {
+        let parent = messages[index - 1]["id"].clone();
+        messages[index]["parents"] = json!([parent]);
+    }
+    let head = messages.last().map(|message| message["id"].clone());
+    json!({
+        "id": id,
+        "head": head,
+        "messages": messages,
+    })
+}
+
+
+/// Assemble the body of a message from a text and trailers
+///
+/// The trailers are appended to the text as a separate paragraph.
+///
+pub fn body(text: &str, trailers: &[(&str, String)]) -> String {
+    let mut retval = text.replace("\r\n", "\n").trim().to_owned();
+    if !retval.is_empty() {
+        retval.push_str("\n\n");
+    }
+    let trailers: Vec<String> = trailers
+        .iter()
+        .map(|&(key, ref value)| format!("{}: {}", key, value))
+        .collect();
+    retval.push_str(&trailers.join("\n"));
+    retval
+}
+
+
+/// Assemble the text of a message from a mail's subject and body
+///
+fn mail_text(mail: &Mail) -> String {
+    let mut lines = vec![mail.subject()];
+    let body = mail.text().trim();
+    if !body.is_empty() {
+        lines.push(String::new());
+        lines.extend(body.lines().map(|line| line.trim_end().to_owned()));
+    }
+    lines.into_iter().collect_string()
+}
+
+
+/// Get the ids of the parents of a message
+///
+/// Representations of messages lacking a "parents" field are accepted, in
+/// which case the "parent" field is used.
+///
+fn parents(message: &Value) -> Result<Vec<&str>> {
+    match message.get("parents") {
+        Some(parents) => array(parents, "parents")?
+            .iter()
+            .map(|parent| parent.as_str().ok_or_else(|| malformed("parents")))
+            .collect(),
+        None => Ok(message.get("parent").and_then(Value::as_str).into_iter().collect()),
+    }
+}
+
+
+/// Assemble the text of a message from its subject and body
+///
+fn text(message: &Value) -> Result<String> {
+    let subject = string(message, "subject")?;
+    let body = message.get("body").and_then(Value::as_str).unwrap_or("");
+
+    let mut lines = vec![subject];
+    if !body.is_empty() {
+        lines.push("");
+        lines.extend(body.lines());
+    }
+    Ok(lines.into_iter().collect_string())
+}
+
+
+/// Create a signature from its JSON representation
+///
+fn signature(value: &Value) -> Result<Signature<'static>> {
+    let name = string(value, "name")?;
+    let email = string(value, "email")?;
+    let date = string(value, "date")?;
+    let date = DateTime::parse_from_rfc3339(date)
+        .chain_err(|| EK::MalformedDate(date.to_owned()))?;
+    let time = Time::new(date.timestamp(), date.offset().local_minus_utc() / 60);
+    Signature::new(name, email, &time).map_err(Error::from)
+}
+
+
+/// Get a string field of an object
+///
+fn string<'v>(value: &'v Value, field: &str) -> Result<&'v str> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| malformed(field))
+}
+
+
+/// Get an array
+///
+fn array<'v>(value: &'v Value, field: &str) -> Result<&'v Vec<Value>> {
+    value.as_array().ok_or_else(|| malformed(field))
+}
+
+
+/// Create an error for a missing or malformed field
+///
+fn malformed(field: &str) -> Error {
+    Error::from_kind(EK::MalformedImport(format!("missing or malformed field '{}'", field)))
+}
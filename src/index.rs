@@ -0,0 +1,465 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Issue index
+//!
+//! This module maintains an on-disk index holding the title and status of each
+//! issue, so that listing many issues does not require walking each issue's
+//! messages. The index is stored as JSON in the file `dit/index` inside the
+//! repository's git directory. It is written to a temporary file first, which
+//! then replaces the index, so readers never see a partially written index.
+//!
+//! Entries are invalidated by changes to the references of their issues: each
+//! entry records a fingerprint of the names and targets of the references it
+//! was computed from. Since the status depends on the head selected for an
+//! issue, the whole index is discarded if the priorization of remotes changes.
+//!
+//! In addition, the index caches the status, tags and assignees accumulated
+//! from a head. Since these are determined by the head commit alone, they are
+//! keyed by the head's id and only need to be computed for heads which moved.
+//! Metadata of commits which are no longer the target of any head reference
+//! is dropped.
+//!
+//! Finally, the time of each issue's last activity is cached on demand, e.g.
+//! for sorting issues by activity. Like entries, these times are invalidated by
+//! changes to the references of their issues.
+//!
+
+use git2::{Commit, ObjectType, Oid, Repository};
+use libgitdit::Issue;
+use libgitdit::config::DitConfig;
+use libgitdit::issue::IssueRefType;
+use libgitdit::iter::DitRefs;
+use libgitdit::listing;
+use libgitdit::trailer::spec;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+
+use error::*;
+use error::ErrorKind as EK;
+use parallel;
+use util::RepositoryUtil;
+
+
+/// Version of the index format
+///
+/// Indexes of other versions are discarded.
+///
+const INDEX_VERSION: u64 = 1;
+
+
+/// Information about an issue held by the index
+///
+#[derive(Clone)]
+pub struct Entry {
+    pub title: String,
+    pub status: String,
+}
+
+
+/// Metadata accumulated from an issue's head
+///
+#[derive(Clone)]
+pub struct Metadata {
+    pub status: String,
+    pub tags: Vec<String>,
+    pub assignees: Vec<String>,
+}
+
+
+/// On-disk index of issues
+///
+pub struct Index<'r> {
+    repo: &'r Repository,
+    path: PathBuf,
+    remote_prios: String,
+    entries: HashMap<Oid, (String, Entry)>,
+    metadata: HashMap<Oid, Metadata>,
+    activity: HashMap<Oid, (String, i64)>,
+    fingerprints: HashMap<Oid, String>,
+    heads: HashSet<Oid>,
+    changed: bool,
+}
+
+impl<'r> Index<'r> {
+    /// Open the index of a repository
+    ///
+    /// The fingerprints of all issues' references are computed in a single
+    /// pass. A missing or unreadable index is treated as an empty one.
+    ///
+    pub fn open(repo: &'r Repository) -> Result<Self> {
+        let path = repo.path().join("dit").join("index");
+        let remote_prios = DitConfig::open(repo)?
+            .remote_prios()?
+            .unwrap_or_else(|| "*".to_owned());
+
+        let mut refs: HashMap<Oid, Vec<String>> = HashMap::new();
+        let mut heads = HashSet::new();
+        for reference in DitRefs::new(repo, "**")? {
+            let reference = reference?;
+            let name = match reference.name() {
+                Some(name) => name,
+                None => continue,
+            };
+            if let (Some((id, ref_type)), Some(target)) = (IssueRefType::of_ref(name), reference.target()) {
+                refs.entry(id).or_insert_with(Vec::new).push(format!("{} {}", target, name));
+                if ref_type == IssueRefType::Head {
+                    heads.insert(target);
+                }
+            }
+        }
+        let fingerprints = refs
+            .into_iter()
+            .map(|(id, mut lines)| {
+                lines.sort();
+                let fingerprint = Oid::hash_object(ObjectType::Blob, lines.join("\n").as_bytes())
+                    .map(|oid| oid.to_string())
+                    .unwrap_or_default();
+                (id, fingerprint)
+            })
+            .collect();
+
+        let value = fs::read(&path)
+            .ok()
+            .and_then(|data| ::serde_json::from_slice::<Value>(&data).ok())
+            .filter(|value| value["version"] == json!(INDEX_VERSION));
+        let entries = value
+            .as_ref()
+            .filter(|value| value["remote-prios"] == json!(remote_prios))
+            .map(parse_entries)
+            .unwrap_or_default();
+        let metadata = value
+            .as_ref()
+            .map(parse_metadata)
+            .unwrap_or_default();
+        let activity = value
+            .as_ref()
+            .map(parse_activity)
+            .unwrap_or_default();
+
+        Ok(Index {
+            repo: repo,
+            path: path,
+            remote_prios: remote_prios,
+            entries: entries,
+            metadata: metadata,
+            activity: activity,
+            fingerprints: fingerprints,
+            heads: heads,
+            changed: false,
+        })
+    }
+
+    /// Compute the entries of the issues supplied which are not current
+    ///
+    /// The entries are computed via the `parallel` module.
+    ///
+    pub fn update(&mut self, issues: &[Issue<'r>]) -> Result<()> {
+        let computed = {
+            let entries = &self.entries;
+            let fingerprints = &self.fingerprints;
+            let metadata = &self.metadata;
+            parallel::map(self.repo, issues, |repo, issue| {
+                let id = issue.id();
+                let current = match (entries.get(&id), fingerprints.get(&id)) {
+                    (Some(&(ref known, _)), Some(fingerprint)) => known == fingerprint,
+                    _ => false,
+                };
+                if current { None } else { Some(compute_entry(repo, issue, metadata)) }
+            })
+        };
+
+        for (issue, computed) in issues.iter().zip(computed) {
+            if let Some(computed) = computed {
+                let (entry, head, metadata) = computed?;
+                let id = issue.id();
+                let fingerprint = self.fingerprints.get(&id).cloned().unwrap_or_default();
+                self.entries.insert(id, (fingerprint, entry));
+                self.metadata.insert(head, metadata);
+                self.changed = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the metadata of the issues supplied
+    ///
+    /// Returns the id of the head selected for each issue along with the
+    /// metadata accumulated from that head. Metadata not held by the index is
+    /// computed via the `parallel` module.
+    ///
+    pub fn metadata(&mut self, issues: &[Issue<'r>]) -> Result<Vec<(Oid, Metadata)>> {
+        let computed = {
+            let known = &self.metadata;
+            parallel::map(self.repo, issues, |repo, issue| {
+                let head = repo.issue_head(issue);
+                match known.get(&head.id()) {
+                    Some(metadata) => Ok((head.id(), metadata.clone(), false)),
+                    None => compute_metadata(issue, &head).map(|metadata| (head.id(), metadata, true)),
+                }
+            })
+        };
+
+        let mut retval = Vec::new();
+        for computed in computed {
+            let (head, metadata, new) = computed?;
+            if new {
+                self.metadata.insert(head, metadata.clone());
+                self.changed = true;
+            }
+            retval.push((head, metadata));
+        }
+        Ok(retval)
+    }
+
+    /// Get the time of the last activity of the issues supplied
+    ///
+    /// The times are given in seconds since the epoch. Times not held by the
+    /// index or computed before the issue's references changed are computed via
+    /// the `parallel` module.
+    ///
+    pub fn last_activities(&mut self, issues: &[Issue<'r>]) -> Result<Vec<Option<i64>>> {
+        let computed = {
+            let activity = &self.activity;
+            let fingerprints = &self.fingerprints;
+            parallel::map(self.repo, issues, |_, issue| {
+                let id = issue.id();
+                match (activity.get(&id), fingerprints.get(&id)) {
+                    (Some(&(ref known, time)), Some(fingerprint)) if known == fingerprint => Ok((Some(time), false)),
+                    _ => listing::last_activity(issue).map(|time| (time, true)),
+                }
+            })
+        };
+
+        let mut retval = Vec::new();
+        for (issue, computed) in issues.iter().zip(computed) {
+            let (time, new) = computed?;
+            if let (true, Some(time)) = (new, time) {
+                let id = issue.id();
+                let fingerprint = self.fingerprints.get(&id).cloned().unwrap_or_default();
+                self.activity.insert(id, (fingerprint, time));
+                self.changed = true;
+            }
+            retval.push(time);
+        }
+        Ok(retval)
+    }
+
+    /// Get the entry for an issue
+    ///
+    /// The entry is computed if the index holds no entry for the issue or if
+    /// the issue's references changed since it was computed.
+    ///
+    pub fn entry(&mut self, issue: &Issue<'r>) -> Result<Entry> {
+        let id = issue.id();
+        let fingerprint = self.fingerprints.get(&id).cloned().unwrap_or_default();
+        if let Some(&(ref known, ref entry)) = self.entries.get(&id) {
+            if *known == fingerprint {
+                return Ok(entry.clone());
+            }
+        }
+
+        let (entry, head, metadata) = compute_entry(self.repo, issue, &self.metadata)?;
+        self.entries.insert(id, (fingerprint, entry.clone()));
+        self.metadata.insert(head, metadata);
+        self.changed = true;
+        Ok(entry)
+    }
+
+    /// Write the index if any entries or metadata were computed
+    ///
+    /// Entries and activity of issues which no longer exist and metadata of
+    /// commits which are no longer heads are dropped.
+    ///
+    pub fn save(&mut self) -> Result<()> {
+        let fingerprints = &self.fingerprints;
+        let heads = &self.heads;
+        let count = self.entries.len() + self.metadata.len() + self.activity.len();
+        self.entries.retain(|id, _| fingerprints.contains_key(id));
+        self.metadata.retain(|head, _| heads.contains(head));
+        self.activity.retain(|id, _| fingerprints.contains_key(id));
+        if !self.changed && self.entries.len() + self.metadata.len() + self.activity.len() == count {
+            return Ok(());
+        }
+
+        let name = self.path.to_string_lossy().into_owned();
+        let issues: ::serde_json::Map<String, Value> = self.entries
+            .iter()
+            .map(|(id, &(ref fingerprint, ref entry))| (id.to_string(), json!({
+                "refs": fingerprint,
+                "title": entry.title,
+                "status": entry.status,
+            })))
+            .collect();
+        let metadata: ::serde_json::Map<String, Value> = self.metadata
+            .iter()
+            .map(|(head, metadata)| (head.to_string(), json!({
+                "status": metadata.status,
+                "tags": metadata.tags,
+                "assignees": metadata.assignees,
+            })))
+            .collect();
+        let activity: ::serde_json::Map<String, Value> = self.activity
+            .iter()
+            .map(|(id, &(ref fingerprint, time))| (id.to_string(), json!({
+                "refs": fingerprint,
+                "last": time,
+            })))
+            .collect();
+        let value = json!({
+            "version": INDEX_VERSION,
+            "remote-prios": self.remote_prios,
+            "issues": issues,
+            "metadata": metadata,
+            "activity": activity,
+        });
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory).chain_err(|| EK::CannotWriteFile(name.clone()))?;
+        }
+        let temporary = self.path.with_extension(format!("{}.tmp", process::id()));
+        let write = || -> Result<()> {
+            let mut file = File::create(&temporary)
+                .map(BufWriter::new)
+                .chain_err(|| EK::CannotWriteFile(name.clone()))?;
+            ::serde_json::to_writer(&mut file, &value).chain_err(|| EK::CannotWriteFile(name.clone()))?;
+            file.flush().chain_err(|| EK::CannotWriteFile(name.clone()))?;
+            fs::rename(&temporary, &self.path).chain_err(|| EK::CannotWriteFile(name.clone()))
+        };
+        if let Err(err) = write() {
+            fs::remove_file(&temporary).ok();
+            return Err(err);
+        }
+        self.changed = false;
+        Ok(())
+    }
+}
+
+
+/// Compute the entry for an issue
+///
+/// The entry is returned along with the id of the issue's head and the
+/// metadata accumulated from it, which is only computed if it is not known.
+///
+fn compute_entry<'r>(repo: &'r Repository,
+                     issue: &Issue<'r>,
+                     known: &HashMap<Oid, Metadata>
+) -> Result<(Entry, Oid, Metadata)> {
+    let head = repo.issue_head(issue);
+    let metadata = match known.get(&head.id()) {
+        Some(metadata) => metadata.clone(),
+        None => compute_metadata(issue, &head)?,
+    };
+
+    let entry = Entry {
+        title: issue.initial_message()?.summary().unwrap_or("").to_owned(),
+        status: metadata.status.clone(),
+    };
+    Ok((entry, head.id(), metadata))
+}
+
+
+/// Accumulate the metadata of an issue from a head
+///
+fn compute_metadata<'r>(issue: &Issue<'r>, head: &Commit<'r>) -> Result<Metadata> {
+    let specs = [spec::ISSUE_STATUS_SPEC, spec::ISSUE_TAG_SPEC, spec::ISSUE_ASSIGNEE_SPEC];
+    let mut values = issue.messages_from(head.id())?.parsed().accumulate_trailers(specs.iter())?;
+    let mut take = |key: &str| -> Vec<String> {
+        values
+            .remove(key)
+            .into_iter()
+            .flat_map(|values| values)
+            .map(|value| value.to_string())
+            .collect()
+    };
+
+    Ok(Metadata {
+        status: take(spec::ISSUE_STATUS_SPEC.key).pop().unwrap_or_else(|| "open".to_owned()),
+        tags: take(spec::ISSUE_TAG_SPEC.key),
+        assignees: take(spec::ISSUE_ASSIGNEE_SPEC.key),
+    })
+}
+
+
+/// Extract the entries from the JSON representation of an index
+///
+/// Malformed entries are skipped.
+///
+fn parse_entries(value: &Value) -> HashMap<Oid, (String, Entry)> {
+    let issues = match value["issues"].as_object() {
+        Some(issues) => issues,
+        None => return HashMap::new(),
+    };
+
+    issues
+        .iter()
+        .filter_map(|(id, entry)| {
+            let id = Oid::from_str(id).ok()?;
+            let fingerprint = entry["refs"].as_str()?.to_owned();
+            Some((id, (fingerprint, Entry {
+                title: entry["title"].as_str()?.to_owned(),
+                status: entry["status"].as_str()?.to_owned(),
+            })))
+        })
+        .collect()
+}
+
+
+/// Extract the cached metadata from the JSON representation of an index
+///
+/// Malformed metadata is skipped.
+///
+fn parse_metadata(value: &Value) -> HashMap<Oid, Metadata> {
+    let metadata = match value["metadata"].as_object() {
+        Some(metadata) => metadata,
+        None => return HashMap::new(),
+    };
+    let strings = |value: &Value| -> Option<Vec<String>> {
+        value
+            .as_array()?
+            .iter()
+            .map(|value| value.as_str().map(String::from))
+            .collect()
+    };
+
+    metadata
+        .iter()
+        .filter_map(|(head, metadata)| Some((Oid::from_str(head).ok()?, Metadata {
+            status: metadata["status"].as_str()?.to_owned(),
+            tags: strings(&metadata["tags"])?,
+            assignees: strings(&metadata["assignees"])?,
+        })))
+        .collect()
+}
+
+
+/// Extract the cached times of last activity from the JSON representation of
+/// an index
+///
+/// Malformed times are skipped.
+///
+fn parse_activity(value: &Value) -> HashMap<Oid, (String, i64)> {
+    let activity = match value["activity"].as_object() {
+        Some(activity) => activity,
+        None => return HashMap::new(),
+    };
+
+    activity
+        .iter()
+        .filter_map(|(id, activity)| {
+            let id = Oid::from_str(id).ok()?;
+            let fingerprint = activity["refs"].as_str()?.to_owned();
+            Some((id, (fingerprint, activity["last"].as_i64()?)))
+        })
+        .collect()
+}
@@ -0,0 +1,337 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Conversion of Jira exports
+//!
+//! This module converts issues as returned by Jira's REST API, e.g. by its
+//! "search" resource, to the representation of issues accepted by the `import`
+//! module. Each Jira issue becomes an issue whose comments and status changes
+//! form a chain of replies. Both plain text and Atlassian Document Format
+//! descriptions and comments are supported.
+//!
+//! The issue type, labels, the assignee and the last fix version are converted
+//! to git-dit trailers. The epic, the parent, the priority, the components and
+//! custom fields are converted to trailers prefixed with "Jira-". Custom fields
+//! are named after their display names if the export contains them, i.e. if it
+//! was requested with "expand=names".
+//!
+//! If the export contains the issues' changelogs, i.e. if it was requested with
+//! "expand=changelog", status changes are converted to messages. Otherwise, a
+//! resolved issue is closed by a message at the time of its resolution.
+//!
+
+use chrono::{DateTime, FixedOffset};
+use libgitdit::trailer::spec;
+use serde_json::{Map, Value};
+
+use error::*;
+use error::ErrorKind as EK;
+use import;
+use json::{array, string, strings};
+
+
+/// Prefix of the origins of messages imported from Jira
+///
+const ORIGIN_PREFIX: &str = "jira:";
+
+/// Prefix of the keys of trailers holding Jira specific fields
+///
+const TRAILER_PREFIX: &str = "Jira-";
+
+
+/// Convert a Jira export to issues
+///
+/// The export may be a search result, a list of issues or a single issue. The
+/// ids of the messages are derived from the instance's URL and the issues'
+/// keys.
+///
+pub fn issue_values(value: &Value) -> Result<Vec<Value>> {
+    let empty = Map::new();
+    let names = value.get("names").and_then(Value::as_object).unwrap_or(&empty);
+    let issues = match value.get("issues").unwrap_or(value) {
+        &Value::Array(ref issues) => issues.iter().collect(),
+        issue if issue.get("fields").is_some() => vec![issue],
+        _ => return Err(Error::from_kind(EK::MalformedImport("no Jira issues found".to_owned()))),
+    };
+    issues.into_iter().map(|issue| issue_value(issue, names)).collect()
+}
+
+
+/// Convert a single Jira issue to an issue
+///
+fn issue_value(issue: &Value, names: &Map<String, Value>) -> Result<Value> {
+    let instance = issue
+        .get("self")
+        .and_then(Value::as_str)
+        .map(|url| url.splitn(2, "://").last().unwrap_or(url))
+        .map(|url| url.split("/rest/").next().unwrap_or(url))
+        .unwrap_or("jira");
+    let fields = issue.get("fields").ok_or_else(|| Error::from_kind(EK::MalformedImport("missing field 'fields'".to_owned())))?;
+    let id = format!("{}/{}", instance, string(issue, "key"));
+    let title = string(fields, "summary");
+    let reply_subject = format!("Re: {}", title);
+
+    let mut trailers = Vec::new();
+    if let Some(kind) = fields.get("issuetype") {
+        trailers.push((spec::ISSUE_TYPE_SPEC.key.to_owned(), string(kind, "name").to_lowercase()));
+    }
+    for label in strings(fields, "labels") {
+        trailers.push((spec::ISSUE_TAG_SPEC.key.to_owned(), label.to_owned()));
+    }
+    if let Some(assignee) = fields.get("assignee").filter(|user| !user.is_null()) {
+        let (name, email) = identity(instance, assignee);
+        trailers.push((spec::ISSUE_ASSIGNEE_SPEC.key.to_owned(), format!("{} <{}>", name, email)));
+    }
+    if let Some(version) = array(fields, "fixVersions").last() {
+        trailers.push((spec::ISSUE_MILESTONE_SPEC.key.to_owned(), string(version, "name")));
+    }
+    if let Some(parent) = fields.get("parent").filter(|parent| !parent.is_null()) {
+        let kind = parent.pointer("/fields/issuetype/name").and_then(Value::as_str);
+        let key = if kind == Some("Epic") { "epic" } else { "parent" };
+        trailers.push((trailer_key(key), string(parent, "key")));
+    }
+    for priority in values(fields.get("priority").unwrap_or(&Value::Null)) {
+        trailers.push((trailer_key("priority"), priority));
+    }
+    for component in values(fields.get("components").unwrap_or(&Value::Null)) {
+        trailers.push((trailer_key("component"), component));
+    }
+
+    // Custom fields are ordered by their ids, which reflect the order in which
+    // they were created. The epic link of older instances is a custom field.
+    let mut custom: Vec<(&String, &Value)> = fields
+        .as_object()
+        .into_iter()
+        .flat_map(|fields| fields.iter())
+        .filter(|&(field, _)| field.starts_with("customfield_"))
+        .collect();
+    custom.sort_by_key(|&(field, _)| field[12..].parse::<u64>().unwrap_or(0));
+    for (field, value) in custom {
+        let name = match names.get(field).and_then(Value::as_str) {
+            Some("Epic Link") => "epic",
+            Some(name) => name,
+            None => field,
+        };
+        for value in values(value) {
+            trailers.push((trailer_key(name), value));
+        }
+    }
+
+    let reporter = fields.get("reporter").or_else(|| fields.get("creator")).unwrap_or(&Value::Null);
+    let trailers = trailers.iter().map(|&(ref key, ref value)| (key.as_str(), value.clone())).collect();
+    let date = parse_date(&string(fields, "created"))?;
+    let mut messages = vec![
+        (date, message(id.clone(), identity(instance, reporter), date, title, &text(fields.get("description")), trailers))
+    ];
+
+    let mut items = Vec::new();
+    for comment in array(fields.get("comment").unwrap_or(&Value::Null), "comments") {
+        let comment_id = format!("{}/comment/{}", id, string(comment, "id"));
+        let author = identity(instance, comment.get("author").unwrap_or(&Value::Null));
+        let date = parse_date(&string(comment, "created"))?;
+        let text = text(comment.get("body"));
+        items.push((date, message(comment_id, author, date, reply_subject.clone(), &text, Vec::new())));
+    }
+
+    match issue.get("changelog") {
+        Some(changelog) => for history in array(changelog, "histories") {
+            let mut lines = Vec::new();
+            let mut trailers = Vec::new();
+            for item in array(history, "items") {
+                let from = string(item, "fromString");
+                let to = string(item, "toString");
+                match string(item, "field").as_str() {
+                    "status" => trailers.push((trailer_key("status"), to.clone())),
+                    "resolution" if to.is_empty() => trailers.push((spec::ISSUE_STATUS_SPEC.key.to_owned(), "open".to_owned())),
+                    "resolution" => {
+                        trailers.push((spec::ISSUE_STATUS_SPEC.key.to_owned(), "closed".to_owned()));
+                        trailers.push((spec::ISSUE_STATUS_REASON_SPEC.key.to_owned(), to.to_lowercase()));
+                    },
+                    _ => continue,
+                }
+                lines.push(match (from.is_empty(), to.is_empty()) {
+                    (true, _)      => format!("Set {} to {}.", string(item, "field"), to),
+                    (false, true)  => format!("Cleared {} {}.", string(item, "field"), from),
+                    (false, false) => format!("Changed {} from {} to {}.", string(item, "field"), from, to),
+                });
+            }
+            if lines.is_empty() {
+                continue;
+            }
+
+            let history_id = format!("{}/history/{}", id, string(history, "id"));
+            let author = identity(instance, history.get("author").unwrap_or(&Value::Null));
+            let date = parse_date(&string(history, "created"))?;
+            let trailers = trailers.iter().map(|&(ref key, ref value)| (key.as_str(), value.clone())).collect();
+            items.push((date, message(history_id, author, date, reply_subject.clone(), &lines.join("\n"), trailers)));
+        },
+        None => if let Some(resolution) = fields.get("resolution").filter(|resolution| !resolution.is_null()) {
+            let resolution = string(resolution, "name");
+            let date = parse_date(&string(fields, "resolutiondate")).unwrap_or(date);
+            let trailers = vec![
+                (spec::ISSUE_STATUS_SPEC.key, "closed".to_owned()),
+                (spec::ISSUE_STATUS_REASON_SPEC.key, resolution.to_lowercase()),
+            ];
+            let text = format!("Resolved as {}.", resolution);
+            let author = ("Jira".to_owned(), "nobody".to_owned());
+            items.push((date, message(format!("{}/resolution", id), author, date, reply_subject.clone(), &text, trailers)));
+        },
+    }
+
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    messages.extend(items);
+    Ok(import::chain(id, messages.into_iter().map(|message| message.1).collect()))
+}
+
+
+/// Represent a message imported from Jira
+///
+fn message(id: String,
+           author: (String, String),
+           date: DateTime<FixedOffset>,
+           subject: String,
+           text: &str,
+           trailers: Vec<(&str, String)>
+) -> Value {
+    let origin = format!("{}{}", ORIGIN_PREFIX, id);
+    import::message(id, origin, author, date.to_rfc3339(), subject, text, trailers)
+}
+
+
+/// Get the name and email address of a user
+///
+/// Users whose email address is hidden are represented by an address derived
+/// from their user name or account id and the instance's host.
+///
+fn identity(instance: &str, user: &Value) -> (String, String) {
+    let login = ["name", "accountId", "key"]
+        .iter()
+        .map(|field| string(user, field))
+        .find(|login| !login.is_empty())
+        .unwrap_or_else(|| "nobody".to_owned());
+    let email = match string(user, "emailAddress") {
+        ref email if email.is_empty() => {
+            let host = instance.split('/').next().unwrap_or(instance);
+            format!("{}@noreply.{}", login, host)
+        },
+        email => email,
+    };
+    let name = match string(user, "displayName") {
+        ref name if name.is_empty() => login,
+        name => name,
+    };
+    (name, email)
+}
+
+
+/// Derive the key of a trailer holding a Jira specific field
+///
+/// Characters not allowed in keys are replaced by dashes.
+///
+fn trailer_key(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let words: Vec<&str> = name.split('-').filter(|word| !word.is_empty()).collect();
+    format!("{}{}", TRAILER_PREFIX, words.join("-"))
+}
+
+
+/// Get the values of a field suitable for trailers
+///
+/// Options, versions, users and other objects are represented by their names
+/// or values. Lists yield multiple values. Empty values and values spanning
+/// multiple lines are omitted.
+///
+fn values(value: &Value) -> Vec<String> {
+    let retval = match *value {
+        Value::String(ref value) => vec![value.clone()],
+        Value::Number(ref number) => vec![number.to_string()],
+        Value::Bool(flag) => vec![flag.to_string()],
+        Value::Array(ref list) => return list.iter().flat_map(values).collect(),
+        Value::Object(_) => ["value", "name", "displayName", "key"]
+            .iter()
+            .filter_map(|field| value.get(field).and_then(Value::as_str))
+            .map(str::to_owned)
+            .take(1)
+            .collect(),
+        Value::Null => Vec::new(),
+    };
+    retval
+        .into_iter()
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty() && !value.contains('\n'))
+        .collect()
+}
+
+
+/// Get the text of a description or comment
+///
+/// The text may be given as plain text or in the Atlassian Document Format.
+///
+fn text(value: Option<&Value>) -> String {
+    match value {
+        Some(&Value::String(ref text)) => text.clone(),
+        Some(document) if document.is_object() => {
+            let mut retval = String::new();
+            document_text(document, &mut retval);
+            retval
+        },
+        _ => String::new(),
+    }
+}
+
+
+/// Append the text of a node of a document in the Atlassian Document Format
+///
+/// Block nodes are separated by blank lines and list items are rendered as
+/// bullet points.
+///
+fn document_text(node: &Value, text: &mut String) {
+    let kind = string(node, "type");
+    match kind.as_str() {
+        "text"      => text.push_str(&string(node, "text")),
+        "hardBreak" => text.push('\n'),
+        "mention"   => text.push_str(&string(node.get("attrs").unwrap_or(&Value::Null), "text")),
+        "emoji"     => text.push_str(&string(node.get("attrs").unwrap_or(&Value::Null), "shortName")),
+        "inlineCard" | "blockCard" => text.push_str(&string(node.get("attrs").unwrap_or(&Value::Null), "url")),
+        "listItem"  => {
+            text.push_str("- ");
+            let mut item = String::new();
+            for child in array(node, "content") {
+                document_text(child, &mut item);
+            }
+            text.push_str(item.trim_end());
+            text.push('\n');
+        },
+        _ => {
+            for child in array(node, "content") {
+                document_text(child, text);
+            }
+            if ["paragraph", "heading", "codeBlock", "blockquote", "bulletList", "orderedList", "rule", "table"].contains(&kind.as_str()) {
+                while text.ends_with('\n') {
+                    text.pop();
+                }
+                text.push_str("\n\n");
+            }
+        },
+    }
+}
+
+
+/// Parse a date as used by Jira
+///
+/// Dates are of the form "2001-02-03T04:05:06.000+0000".
+///
+fn parse_date(date: &str) -> Result<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f%z")
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .chain_err(|| EK::MalformedDate(date.to_owned()))
+}
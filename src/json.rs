@@ -0,0 +1,219 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! JSON representations
+//!
+//! This module provides the JSON representations of issues and messages used
+//! by the "--format json" output mode of various subcommands. The schema is
+//! documented in `doc/json.md` and shall only be extended in a backward
+//! compatible way.
+//!
+//! In addition, it provides accessors for fields of JSON documents read, e.g.
+//! from other issue trackers, which tolerate missing and null fields.
+//!
+
+use chrono::{FixedOffset, TimeZone};
+use git2::{Commit, Repository, Signature, Time};
+use libgitdit::{Issue, Message};
+use libgitdit::trailer::spec::{self, TrailerSpec};
+use serde_json::Value;
+use std::io::{self, Write};
+
+use error::*;
+use error::ErrorKind as EK;
+use util::RepositoryUtil;
+
+
+/// Output format selected on the command line
+///
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    /// Get the format selected via the "format" option
+    ///
+    pub fn from_matches(matches: &::clap::ArgMatches) -> Self {
+        matches
+            .value_of("format")
+            .and_then(Self::from_name)
+            .unwrap_or(Format::Text)
+    }
+
+    /// Get the format with the given name, if known
+    ///
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+
+/// Print a JSON value to stdout
+///
+pub fn print(value: &Value) -> Result<()> {
+    write(&mut io::stdout(), value)
+}
+
+
+/// Write a JSON value to a stream
+///
+pub fn write(stream: &mut Write, value: &Value) -> Result<()> {
+    // Serializing a `Value` does not fail
+    let text = ::serde_json::to_string_pretty(value).expect("Could not serialize value");
+    writeln!(stream, "{}", text).chain_err(|| EK::WrappedIOError)
+}
+
+
+/// Format a time as RFC 3339 date
+///
+pub fn date(time: Time) -> String {
+    FixedOffset::east(time.offset_minutes() * 60)
+        .timestamp(time.seconds(), 0)
+        .to_rfc3339()
+}
+
+
+/// Represent a signature
+///
+pub fn signature(signature: &Signature) -> Value {
+    json!({
+        "name": signature.name(),
+        "email": signature.email(),
+        "date": date(signature.when()),
+    })
+}
+
+
+/// Represent a message
+///
+/// Only the id and the parents of hidden messages are represented.
+///
+pub fn message(message: &Commit, hidden: bool) -> Value {
+    let parent = message.parent_ids().next().map(|id| id.to_string());
+    let parents: Vec<String> = message.parent_ids().map(|id| id.to_string()).collect();
+    if hidden {
+        return json!({
+            "id": message.id().to_string(),
+            "parent": parent,
+            "parents": parents,
+            "hidden": true,
+        });
+    }
+
+    let body: Vec<String> = message.body_lines().collect();
+    let trailers: Vec<Value> = message
+        .trailers()
+        .map(|trailer| json!({
+            "key": trailer.key.as_ref(),
+            "value": trailer.value.to_string(),
+        }))
+        .collect();
+
+    json!({
+        "id": message.id().to_string(),
+        "parent": parent,
+        "parents": parents,
+        "hidden": false,
+        "author": signature(&message.author()),
+        "committer": signature(&message.committer()),
+        "subject": message.summary(),
+        "body": body.join("\n"),
+        "trailers": trailers,
+    })
+}
+
+
+/// Represent an issue
+///
+/// The representation contains the initial message's subject and author as
+/// well as the metadata accumulated from the issue's selected head.
+///
+pub fn issue<'r>(repo: &'r Repository, issue: &Issue<'r>, initial: &Commit) -> Value {
+    let head = repo.issue_head(issue);
+    let values = |spec: &TrailerSpec| repo.metadata_values(issue, &head, spec);
+    let latest = |spec: &TrailerSpec| values(spec).pop();
+
+    json!({
+        "id": issue.id().to_string(),
+        "component": issue.component(),
+        "subject": initial.summary(),
+        "author": signature(&initial.author()),
+        "head": head.id().to_string(),
+        "status": latest(&spec::ISSUE_STATUS_SPEC).unwrap_or_else(|| "open".to_owned()),
+        "type": latest(&spec::ISSUE_TYPE_SPEC),
+        "milestone": latest(&spec::ISSUE_MILESTONE_SPEC),
+        "tags": values(&spec::ISSUE_TAG_SPEC),
+        "assignees": values(&spec::ISSUE_ASSIGNEE_SPEC),
+    })
+}
+
+
+/// Represent an issue along with messages
+///
+/// The issue is represented like by `issue()`, with an additional "messages"
+/// field holding the messages supplied in chronological order.
+///
+pub fn issue_with_messages<'r>(repo: &'r Repository,
+                               issue: &Issue<'r>,
+                               initial: &Commit,
+                               mut messages: Vec<Commit<'r>>,
+                               hidden: &Fn(&Commit) -> bool
+) -> Value {
+    messages.sort_by_key(|message| message.time());
+    let messages: Vec<_> = messages
+        .iter()
+        .map(|message| self::message(message, hidden(message)))
+        .collect();
+
+    let mut value = self::issue(repo, issue, initial);
+    value["messages"] = json!(messages);
+    value
+}
+
+
+/// Get a string field, which may be missing or null
+///
+pub fn optional_str<'v>(value: &'v Value, field: &str) -> Option<&'v str> {
+    value.get(field).and_then(Value::as_str)
+}
+
+
+/// Get a string field, which may be missing or null
+///
+/// Missing and null fields are represented by an empty string.
+///
+pub fn string(value: &Value, field: &str) -> String {
+    optional_str(value, field).unwrap_or("").to_owned()
+}
+
+
+/// Get an array field, which may be missing or null
+///
+pub fn array<'v>(value: &'v Value, field: &str) -> &'v [Value] {
+    value
+        .get(field)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or(&[])
+}
+
+
+/// Get the strings of an array field, which may be missing or null
+///
+/// Elements which are not strings are skipped.
+///
+pub fn strings<'v>(value: &'v Value, field: &str) -> impl Iterator<Item = &'v str> + 'v {
+    array(value, field).iter().filter_map(Value::as_str)
+}
@@ -0,0 +1,478 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Mail representation of messages
+//!
+//! This module provides the conversion of messages to RFC 5322 mails and the
+//! parsing of mails. The threading headers of the mails are derived from the
+//! messages' ids, so the same message always yields the same "Message-ID" and
+//! mails may be related to messages without any additional state.
+//!
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+use git2::{Commit, Oid, Signature, Time};
+use libgitdit::Message;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Domain used for message ids
+///
+const MESSAGE_ID_DOMAIN: &str = "git-dit";
+
+
+/// Get the mail "Message-ID" of a message
+///
+/// The id is returned without the enclosing angle brackets.
+///
+pub fn message_id(id: Oid) -> String {
+    format!("{}@{}", id, MESSAGE_ID_DOMAIN)
+}
+
+
+/// Get the id of the message a mail "Message-ID" refers to
+///
+/// Enclosing angle brackets and whitespace are ignored. `None` is returned if
+/// the id was not derived from a message.
+///
+pub fn parse_message_id(message_id: &str) -> Option<Oid> {
+    let message_id = message_id.trim().trim_start_matches('<').trim_end_matches('>');
+    let mut parts = message_id.splitn(2, '@');
+    match (parts.next(), parts.next()) {
+        (Some(id), Some(MESSAGE_ID_DOMAIN)) => Oid::from_str(id).ok(),
+        _ => None,
+    }
+}
+
+
+/// Represent a message as mail
+///
+/// The mail's "In-Reply-To" header refers to the message's first parent and
+/// the "References" header to the issue's initial message and all parents.
+/// The id of the issue is recorded in an "X-Git-Dit-Issue" header. Lines are
+/// terminated by a single line feed. Encrypted parts of the message are not
+/// decrypted, since mails leave the repository.
+///
+pub fn mail(issue: Oid, message: &Commit) -> String {
+    let author = message.author();
+    let mut lines = vec![
+        format!("From: {}", mailbox(author.name().unwrap_or(""), author.email().unwrap_or(""))),
+        format!("Date: {}", date(author.when())),
+        format!("Subject: {}", encode_header(message.summary().unwrap_or(""))),
+        format!("Message-ID: <{}>", message_id(message.id())),
+    ];
+
+    let parents: Vec<Oid> = message.parent_ids().collect();
+    if let Some(parent) = parents.first() {
+        lines.push(format!("In-Reply-To: <{}>", message_id(*parent)));
+
+        let mut references = vec![issue];
+        references.extend(parents.iter().filter(|id| **id != issue));
+        let references: Vec<String> = references
+            .into_iter()
+            .map(|id| format!("<{}>", message_id(id)))
+            .collect();
+        lines.push(format!("References: {}", references.join(" ")));
+    }
+
+    lines.push(format!("X-Git-Dit-Issue: {}", issue));
+    lines.push("MIME-Version: 1.0".to_owned());
+    lines.push("Content-Type: text/plain; charset=utf-8".to_owned());
+    lines.push("Content-Transfer-Encoding: 8bit".to_owned());
+    lines.push(String::new());
+    lines.extend(message.raw_body_lines());
+
+    let mut retval = lines.join("\n");
+    retval.push('\n');
+    retval
+}
+
+
+/// Format a mailbox from a name and an address
+///
+fn mailbox(name: &str, email: &str) -> String {
+    if name.is_empty() {
+        format!("<{}>", email)
+    } else if name.is_ascii() && name.chars().all(|c| c.is_alphanumeric() || c == ' ') {
+        format!("{} <{}>", name, email)
+    } else if name.is_ascii() {
+        format!("\"{}\" <{}>", name.replace('\\', "\\\\").replace('"', "\\\""), email)
+    } else {
+        format!("{} <{}>", encode_header(name), email)
+    }
+}
+
+
+/// Format a time as RFC 5322 date
+///
+fn date(time: Time) -> String {
+    FixedOffset::east(time.offset_minutes() * 60)
+        .timestamp(time.seconds(), 0)
+        .to_rfc2822()
+}
+
+
+/// Encode text for use in a header
+///
+/// Text containing non-ASCII characters is encoded as RFC 2047 encoded word
+/// using the "Q" encoding. Other text is returned verbatim.
+///
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_owned();
+    }
+
+    let mut retval = "=?utf-8?q?".to_owned();
+    for byte in text.bytes() {
+        match byte {
+            b' ' => retval.push('_'),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => retval.push(byte as char),
+            byte => retval.push_str(&format!("={:02X}", byte)),
+        }
+    }
+    retval.push_str("?=");
+    retval
+}
+
+
+
+/// Split an mbox into mails
+///
+/// Mails are separated by "From " lines. Quoting of lines starting with
+/// "From " is reverted as specified for the "mboxrd" variant.
+///
+pub fn split_mbox(text: &str) -> Vec<String> {
+    let mut retval = Vec::new();
+    let mut current: Option<String> = None;
+    for line in text.lines() {
+        if line.starts_with("From ") {
+            retval.extend(current.take());
+            current = Some(String::new());
+            continue;
+        }
+
+        let mail = current.get_or_insert_with(String::new);
+        if line.trim_start_matches('>').starts_with("From ") {
+            mail.push_str(&line[1..]);
+        } else {
+            mail.push_str(line);
+        }
+        mail.push('\n');
+    }
+    retval.extend(current);
+    retval
+}
+
+
+/// Parsed mail
+///
+/// Only the parts of a mail relevant for creating messages are retained, i.e.
+/// the headers and the plain text of its body.
+///
+pub struct Mail {
+    headers: Vec<(String, String)>,
+    text: String,
+}
+
+impl Mail {
+    /// Parse a mail
+    ///
+    /// Line endings may either be CRLF or LF. For multipart mails, the text is
+    /// taken from the first "text/plain" part. Quoted-printable and base64
+    /// transfer encodings are decoded.
+    ///
+    pub fn parse(data: &[u8]) -> Self {
+        let data = String::from_utf8_lossy(data).replace("\r\n", "\n");
+        let (head, body) = match data.find("\n\n") {
+            Some(pos) => (&data[..pos], &data[pos + 2..]),
+            None      => (data.as_str(), ""),
+        };
+
+        // unfold the headers
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in head.lines() {
+            if line.starts_with(' ') || line.starts_with('\t') {
+                if let Some(&mut (_, ref mut value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            if let Some(pos) = line.find(':') {
+                headers.push((line[..pos].trim().to_owned(), line[pos + 1..].trim().to_owned()));
+            }
+        }
+
+        let mut retval = Mail { headers: headers, text: String::new() };
+        retval.text = retval.body_text(body).unwrap_or_default();
+        retval
+    }
+
+    /// Get the raw value of a header
+    ///
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_str())
+    }
+
+    /// Get the message ids listed in a header, e.g. "References"
+    ///
+    pub fn message_ids(&self, name: &str) -> Vec<String> {
+        self.header(name)
+            .unwrap_or("")
+            .split('<')
+            .skip(1)
+            .filter_map(|part| part.find('>').map(|pos| part[..pos].trim().to_owned()))
+            .collect()
+    }
+
+    /// Get the decoded subject
+    ///
+    pub fn subject(&self) -> String {
+        decode_header(self.header("Subject").unwrap_or(""))
+    }
+
+    /// Get the plain text of the body
+    ///
+    pub fn text(&self) -> &str {
+        self.text.as_ref()
+    }
+
+    /// Create a signature from the sender and date of the mail
+    ///
+    /// If the mail has no date, the current time is used.
+    ///
+    pub fn author(&self) -> Result<Signature<'static>> {
+        let from = self.header("From").ok_or_else(|| malformed("missing sender"))?;
+        let (name, email) = match (from.rfind('<'), from.rfind('>')) {
+            (Some(start), Some(end)) if start < end => (
+                decode_header(from[..start].trim()).trim_matches('"').to_owned(),
+                from[start + 1..end].trim().to_owned(),
+            ),
+            _ => (String::new(), from.trim().to_owned()),
+        };
+        let name = if name.is_empty() { email.clone() } else { name };
+
+        match self.header("Date") {
+            Some(date) => {
+                // comments, e.g. "(UTC)", are not accepted by the parser
+                let date = date.split('(').next().unwrap_or("").trim();
+                let date = DateTime::parse_from_rfc2822(date)
+                    .chain_err(|| EK::MalformedDate(date.to_owned()))?;
+                let time = Time::new(date.timestamp(), date.offset().local_minus_utc() / 60);
+                Signature::new(&name, &email, &time)
+            },
+            None => Signature::now(&name, &email),
+        }.map_err(Error::from)
+    }
+
+    /// Extract the plain text from a body
+    ///
+    fn body_text(&self, body: &str) -> Option<String> {
+        let content_type = self.header("Content-Type").unwrap_or("text/plain").to_owned();
+        let mime_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+
+        if mime_type.starts_with("multipart/") {
+            let boundary = format!("--{}", parameter(&content_type, "boundary")?);
+            return body
+                .split(boundary.as_str())
+                .skip(1)
+                .take_while(|part| !part.starts_with("--"))
+                .map(|part| Mail::parse(part.trim_start_matches('\n').as_bytes()))
+                .filter(|part| !part.text.is_empty())
+                .map(|part| part.text)
+                .next();
+        }
+        if mime_type != "text/plain" {
+            return None;
+        }
+
+        let encoding = self.header("Content-Transfer-Encoding").unwrap_or("").trim().to_lowercase();
+        let bytes = match encoding.as_ref() {
+            "quoted-printable" => decode_quoted_printable(body, false),
+            "base64"           => decode_base64(body),
+            _                  => body.as_bytes().to_vec(),
+        };
+        Some(decode_charset(&bytes, &parameter(&content_type, "charset").unwrap_or_default()))
+    }
+}
+
+
+/// Get a parameter of a header value, e.g. the boundary of a content type
+///
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value
+        .split(';')
+        .skip(1)
+        .filter_map(|param| {
+            let mut parts = param.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case(name) => {
+                    Some(value.trim().trim_matches('"').to_owned())
+                },
+                _ => None,
+            }
+        })
+        .next()
+}
+
+
+/// Decode RFC 2047 encoded words in a header value
+///
+/// Encoded words with unknown encodings are retained verbatim.
+///
+fn decode_header(value: &str) -> String {
+    let mut retval = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        // The encoded text may start with "=", e.g. in "=?utf-8?q?=C3=84?=".
+        // Hence, the end of the word is only searched after the encoding.
+        let mut parts = rest[start + 2..].splitn(3, '?');
+        let decoded = match (parts.next(), parts.next(), parts.next()) {
+            (Some(charset), Some(encoding), Some(tail)) => tail
+                .find("?=")
+                .map(|end| &tail[..end])
+                .filter(|text| ![charset, encoding, text].iter().any(|part| part.contains(char::is_whitespace)))
+                .and_then(|text| {
+                    let bytes = match encoding {
+                        "Q" | "q" => decode_quoted_printable(text, true),
+                        "B" | "b" => decode_base64(text),
+                        _         => return None,
+                    };
+                    Some((decode_charset(&bytes, charset), charset.len() + encoding.len() + text.len() + 6))
+                }),
+            _ => None,
+        };
+
+        match decoded {
+            Some((text, len)) => {
+                // whitespace between adjacent encoded words is ignored
+                let between = &rest[..start];
+                if !(after_word && between.trim().is_empty()) {
+                    retval.push_str(between);
+                }
+                retval.push_str(&text);
+                rest = &rest[start + len..];
+                after_word = true;
+            },
+            None => {
+                retval.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            },
+        }
+    }
+    retval.push_str(rest);
+    retval
+}
+
+
+/// Decode text in a charset
+///
+/// Only UTF-8 and ISO-8859-1 are supported. Text in other charsets is decoded
+/// as UTF-8, replacing invalid sequences.
+///
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    match charset.to_lowercase().as_ref() {
+        "iso-8859-1" | "latin1" => bytes.iter().map(|byte| *byte as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+
+/// Decode quoted-printable data
+///
+/// In headers, underscores represent spaces.
+///
+fn decode_quoted_printable(data: &str, header: bool) -> Vec<u8> {
+    let bytes = data.as_bytes();
+    let mut retval = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'=' if bytes.get(pos + 1) == Some(&b'\n') => pos += 1,
+            b'=' => match data.get(pos + 1..pos + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                Some(byte) => {
+                    retval.push(byte);
+                    pos += 2;
+                },
+                None => retval.push(b'='),
+            },
+            b'_' if header => retval.push(b' '),
+            byte => retval.push(byte),
+        }
+        pos += 1;
+    }
+    retval
+}
+
+
+/// Decode base64 data
+///
+/// Characters not part of the base64 alphabet, e.g. line breaks, are ignored.
+///
+pub fn decode_base64(data: &str) -> Vec<u8> {
+    let mut retval = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+'        => 62,
+            b'/'        => 63,
+            _           => continue,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            retval.push((buffer >> bits) as u8);
+        }
+    }
+    retval
+}
+
+
+/// Create an error for a malformed mail
+///
+fn malformed(reason: &str) -> Error {
+    Error::from_kind(EK::MalformedImport(reason.to_owned()))
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Header encoding tests
+
+    #[test]
+    fn header_roundtrip() {
+        for text in &["Ärger", "Grüße aus Köln", "naïve = wrong?", "日本語", "plain ASCII"] {
+            assert_eq!(decode_header(&encode_header(text)), *text);
+        }
+    }
+
+    #[test]
+    fn header_decoding() {
+        assert_eq!(decode_header("=?utf-8?q?=C3=84rger?="), "Ärger");
+        assert_eq!(decode_header("=?UTF-8?B?w4RyZ2Vy?= und =?iso-8859-1?q?=C4rger?="), "Ärger und Ärger");
+        assert_eq!(decode_header("=?utf-8?q?a?= =?utf-8?q?b?="), "ab");
+        assert_eq!(decode_header("x =? y ?= z"), "x =? y ?= z");
+        assert_eq!(decode_header("=?utf-8?x?foo?="), "=?utf-8?x?foo?=");
+    }
+}
@@ -6,6 +6,7 @@
 //   it under the terms of the GNU General Public License version 2 as
 //   published by the Free Software Foundation.
 //
+#![recursion_limit = "1024"]
 
 #[macro_use] extern crate clap;
 #[macro_use] extern crate error_chain;
@@ -17,16 +18,46 @@ extern crate chrono;
 extern crate git2;
 extern crate libgitdit;
 extern crate regex;
-
+#[macro_use] extern crate serde_json;
+#[cfg(feature = "tui")] extern crate crossterm;
+#[cfg(feature = "tui")] extern crate ratatui;
+#[cfg(feature = "parallel")] extern crate rayon;
+#[cfg(feature = "sqlite")] extern crate rusqlite;
+#[cfg(feature = "web")] extern crate tiny_http;
+#[cfg(feature = "trace")] extern crate tracing;
+#[cfg(feature = "trace")] extern crate tracing_subscriber;
+
+#[macro_use] mod i18n;
+#[macro_use] mod trace;
 #[macro_use] mod display;
 
+mod board;
+mod bugzilla;
 mod error;
+mod export;
+mod feed;
 mod filters;
+mod forge;
 mod gitext;
+mod html;
+mod imap;
+mod import;
+mod index;
+mod jira;
+mod json;
+mod mail;
+mod nntp;
+mod parallel;
+mod radicle;
+#[cfg(feature = "sqlite")] mod sqlite;
 mod system;
+#[cfg(feature = "tui")] mod tui;
 mod util;
+mod xml;
+#[cfg(feature = "web")] mod web;
 
 use clap::App;
+use error::Category;
 use git2::Commit;
 use libgitdit::issue::IssueRefType;
 use libgitdit::message::LineIteratorExt;
@@ -36,7 +67,7 @@ use std::fs::File;
 use std::io::{self, Read, Write};
 
 use util::{RepositoryUtil};
-use system::{Abortable, IteratorExt, LinesExt};
+use system::{abort, abort_on_failures, Abortable, IteratorExt, LinesExt};
 
 
 // Plumbing subcommand implementations
@@ -67,16 +98,18 @@ fn check_refname(matches: &clap::ArgMatches) {
     if let Some((id, reftype)) = refdata {
         // The reference is a valid dit reference. We may now answer questions
         // about it.
+        let mut records = Vec::new();
         if matches.is_present("issue-id") {
-            println!("{}", id);
+            records.push(id.to_string());
         }
         if matches.is_present("reftype") {
-            println!("{}", match reftype {
+            records.push(match reftype {
                 IssueRefType::Head => "head",
                 IssueRefType::Leaf => "leaf",
                 _ => "unknown",
-            });
+            }.to_owned());
         }
+        records.print_records(record_terminator(matches)).unwrap_or_abort();
     } else {
         use std::process::exit;
         exit(1);
@@ -90,7 +123,7 @@ fn create_message(matches: &clap::ArgMatches) {
     let repo = util::open_dit_repo();
     let issue = repo.cli_issue(matches);
     let author = repo.cli_author(matches);
-    let committer = repo.signature().unwrap_or_abort();
+    let committer = repo.dit_signature();
 
     // Note: The list of parents must live long enough to back the references we
     //       supply to `libgitdit::repository::RepositoryExt::create_message()`.
@@ -112,7 +145,12 @@ fn create_message(matches: &clap::ArgMatches) {
         Some(i) => i.add_message(&author, &committer, message, &tree, parent_refs)
                     .unwrap_or_abort()
                     .id(),
-        None => repo.create_issue(&author, &committer, message, &tree, parent_refs)
+        None => repo.create_issue_in_component(repo.cli_component(matches).as_ref().map(String::as_str),
+                                               &author,
+                                               &committer,
+                                               message,
+                                               &tree,
+                                               parent_refs)
                     .unwrap_or_abort()
                     .id(),
     };
@@ -124,11 +162,20 @@ fn create_message(matches: &clap::ArgMatches) {
 /// find-tree-init-hash subcommand implementation
 ///
 fn find_tree_init_hash(matches: &clap::ArgMatches) {
+    use libgitdit::message_index::MessageIndex;
+
     let repo = util::open_dit_repo();
+    let index = MessageIndex::updated(&repo);
 
     // note: commit is always present since it is a required parameter
-    let commit = repo.value_to_commit(matches.value_of("commit").unwrap());
-    println!("{}", repo.issue_with_message(&commit).unwrap_or_abort());
+    matches
+        .values_of("commit")
+        .unwrap()
+        .map(|commit| repo.value_to_commit(commit))
+        .map(|commit| index.issue_with_message(&repo, &commit))
+        .abort_on_err()
+        .print_records(record_terminator(matches))
+        .unwrap_or_abort();
 }
 
 
@@ -139,6 +186,7 @@ fn get_issue_metadata(matches: &clap::ArgMatches) {
     use libgitdit::trailer::iter::PairsToTrailers;
 
     let repo = util::open_dit_repo();
+    let terminator = record_terminator(matches);
 
     // note: "head" is always present since it is a required parameter
     let head = repo.value_to_commit(matches.value_of("head").unwrap());
@@ -158,328 +206,1432 @@ fn get_issue_metadata(matches: &clap::ArgMatches) {
         let mut acc = accumulation::SingleAccumulator::new(key.to_owned(), policy);
         acc.process_all(trailers);
         if matches.is_present("values-only") {
-            acc.into_values().print_lines().unwrap_or_abort();
+            acc.into_values().print_records(terminator).unwrap_or_abort();
         } else {
-            PairsToTrailers::from(acc).print_lines().unwrap_or_abort();
+            PairsToTrailers::from(acc).print_records(terminator).unwrap_or_abort();
         }
     } else {
-        trailers.print_lines().unwrap_or_abort();
+        trailers.print_records(terminator).unwrap_or_abort();
     }
 }
 
 
-/// find-tree-init-hash subcommand implementation
+/// get-issue-tree-init-hashes subcommand implementation
 ///
-fn get_issue_tree_init_hashes(_: &clap::ArgMatches) {
+fn get_issue_tree_init_hashes(matches: &clap::ArgMatches) {
     let repo = util::open_dit_repo();
 
-    repo.issues().unwrap_or_abort().print_lines().unwrap_or_abort();
+    // sort the ids in order to provide a stable output
+    let mut ids: Vec<_> = repo
+        .issues()
+        .unwrap_or_abort()
+        .into_iter()
+        .map(|issue| issue.id())
+        .collect();
+    ids.sort();
+    ids.print_records(record_terminator(matches)).unwrap_or_abort();
+}
+
+
+/// Get the terminator for records printed by plumbing subcommands
+///
+/// Records are terminated by NUL characters if the "null" flag is present and
+/// by newlines otherwise.
+///
+fn record_terminator(matches: &clap::ArgMatches) -> char {
+    if matches.is_present("null") {
+        '\0'
+    } else {
+        '\n'
+    }
 }
 
 
 // Porcelain subcommand implementations
 
-/// fetch subcommand implementation
+/// assign subcommand implementation
 ///
-fn fetch_impl(matches: &clap::ArgMatches) {
-    use libgitdit::RemoteExt;
-
-    let repo = util::open_dit_repo();
+fn assign_impl(matches: &clap::ArgMatches) {
+    set_assignees(matches, true)
+}
 
-    // note: "remote" is always present since it is a required parameter
-    let mut remote = repo
-        .find_remote(matches.value_of("remote").unwrap())
-        .unwrap_or_abort();
 
-    // accumulate the refspecs to fetch
-    let refspecs : Vec<String> = if let Some(mut issues) = repo.cli_issues(matches) {
-        // fetch a specific list of issues
-        if matches.is_present("known") {
-            issues.extend(repo.issues().unwrap_or_abort());
-        }
-        issues
-            .into_iter()
-            .filter_map(|issue| remote.issue_refspec(issue))
-            .collect()
-    } else {
-        vec![remote.all_issues_refspec().unwrap()]
-    };
+/// blame subcommand implementation
+///
+fn blame_impl(matches: &clap::ArgMatches) {
+    use chrono::format::strftime::StrftimeItems;
+    use libgitdit::blame::blame;
+    use libgitdit::trailer::spec;
 
-    // set the options for the fetch
-    let mut fetch_options = git2::FetchOptions::new();
-    fetch_options.prune(if matches.is_present("prune") {
-        git2::FetchPrune::On
-    } else {
-        git2::FetchPrune::Unspecified
-    });
-    fetch_options.remote_callbacks(gitext::callbacks());
+    use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
 
-    let refspec_refs : Vec<&str> = refspecs.iter().map(String::as_str).collect();
-    remote.fetch(refspec_refs.as_ref(), Some(&mut fetch_options), None)
-          .unwrap_or_abort();
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+    let palette = repo.palette();
+    let issue = repo.cli_issue_or_current(matches);
+    let head = repo.issue_head(&issue);
+
+    let specs = [
+        spec::ISSUE_STATUS_SPEC,
+        spec::ISSUE_TYPE_SPEC,
+        spec::ISSUE_MILESTONE_SPEC,
+        spec::ISSUE_TAG_SPEC,
+        spec::ISSUE_ASSIGNEE_SPEC,
+    ];
+    let messages = issue.messages_from(head.id()).unwrap_or_abort();
+
+    let result = blame(messages, &specs)
+        .unwrap_or_abort()
+        .into_iter()
+        .flat_map(|attribution| {
+            let formatter : Vec<FT<_,_>> = tokenvec![
+                palette.id(), MFT::Id(id_len), palette.reset(),
+                " (", palette.author(), MFT::AuthorName, palette.reset(),
+                " ", MFT::Date(StrftimeItems::new("%F %T %z")), ") ",
+                format!("{}: {}", attribution.key(), attribution.value())];
+            formatter.iter().formatted_lines(attribution.message().clone()).collect::<Vec<_>>()
+        })
+        .abort_on_err()
+        .pipe_lines(repo.pager())
+        .unwrap_or_abort();
+    std::process::exit(result);
 }
 
 
-/// gc subcommand implementation
+/// checkout subcommand implementation
 ///
-fn gc_impl(matches: &clap::ArgMatches) {
-    use libgitdit::gc::ReferenceCollectionSpec;
-    use libgitdit::iter::ReferenceDeletingIter;
-
+fn checkout_impl(matches: &clap::ArgMatches) {
     let repo = util::open_dit_repo();
 
-    let collect = {
-        let collect_heads = if matches.is_present("collect-heads") {
-            ReferenceCollectionSpec::BackedByRemoteHead
-        } else {
-            ReferenceCollectionSpec::Never
-        };
-        repo.collectable_refs()
-            .consider_remote_refs(matches.is_present("consider-remote"))
-            .collect_heads(collect_heads)
-    };
+    if matches.is_present("clear") {
+        repo.set_current_issue(None).unwrap_or_abort();
+        return;
+    }
 
-    let refs = repo
-        .cli_issues(matches)
-        .unwrap_or_else(|| repo.issues().unwrap_or_abort())
-        .into_iter()
-        .map(|issue| collect.for_issue(&issue))
-        .abort_on_err()
-        .flat_map(|collector| collector)
-        .abort_on_err();
+    let issue = repo.cli_issue(matches);
+    if let Some(issue) = issue {
+        repo.set_current_issue(Some(&issue)).unwrap_or_abort();
+        let initial = issue.initial_message().unwrap_or_abort();
+        println!("Current issue is now {} {}", issue.id(), initial.summary().unwrap_or(""));
+        return;
+    }
 
-    if matches.is_present("dry-run") {
-        refs.into_iter()
-            .map(|r| r.name().unwrap_or("Unknown ref").to_owned())
-            .print_lines()
-            .unwrap_or_abort();
-    } else {
-        ReferenceDeletingIter::from(refs).print_lines().unwrap_or_abort();
+    let current = repo.current_issue().unwrap_or_abort();
+    if let Some(issue) = current {
+        println!("{}", issue.id());
     }
 }
 
 
-/// list subcommand implementation
+/// close subcommand implementation
 ///
-fn list_impl(matches: &clap::ArgMatches) {
-    use chrono::format::strftime::StrftimeItems;
-    use libgitdit::Issue;
-
-    use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
-    use filters::MetadataFilter;
+fn close_impl(matches: &clap::ArgMatches) {
+    set_status(matches, "closed")
+}
 
-    let repo = util::open_dit_repo();
-    let remote_prios = repo.remote_priorization();
 
-    // construct filter
-    let filter = match matches.values_of("filter") {
-        Some(values) => {
-            let specs = values.map(str::parse).abort_on_err();
-            MetadataFilter::new(&remote_prios, specs).unwrap_or_abort()
-        },
-        None         => MetadataFilter::empty(&remote_prios),
-    };
+/// export subcommand implementation
+///
+fn export_impl(matches: &clap::ArgMatches) {
+    use std::path::Path;
 
-    let id_len = repo.abbreviation_length(matches);
+    use error::ErrorKind as EK;
+    use error::ResultExt;
 
-    let formatter = if matches.is_present("long") {
-        tokenvec![
-            MFT::Id(id_len), FT::LineEnd,
-            "Author: ", MFT::Author, FT::LineEnd,
-            "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd,
-            FT::LineEnd,
-            MFT::Subject, FT::LineEnd,
-            FT::LineEnd,
-            MFT::BodyText,
-            FT::LineEnd]
-    } else {
-        tokenvec![MFT::Id(id_len), " (", MFT::Date(StrftimeItems::new("%c")), ") ", MFT::Subject]
-    };
+    let repo = util::open_dit_repo();
+    let moderation = repo.moderation().unwrap_or_abort();
+    let show_hidden = matches.is_present("show-hidden");
+    let hidden = |message: &Commit| !show_hidden && moderation.is_hidden(message);
 
-    // get initial commits
-    let mut issues : Vec<Issue> = repo
-        .issues()
-        .unwrap_or_abort()
+    // issues are exported in the order in which they were created
+    let mut issues: Vec<_> = filtered_issues(&repo, matches, true)
         .into_iter()
-        .filter(|issue| filter.filter(issue))
+        .map(|issue| (issue.initial_message().unwrap_or_abort().time(), issue))
         .collect();
+    issues.sort_by_key(|&(time, _)| time);
+    let issues = issues.into_iter().map(|(_, issue)| issue);
 
-    // descending order
-    let mut sort_key : Box<FnMut(&Issue) -> git2::Time> = Box::new(|ref issue| issue
-        .initial_message()
-        .unwrap_or_abort()
-        .time());
-    issues.sort_by(|a, b| sort_key(b).cmp(&sort_key(a)));
-
-    // optionally limit to some number specified by the user
-    if let Some(number) = matches.value_of("n") {
-        // TODO: better error reporting?
-        issues.truncate(str::parse(number).unwrap_or_abort());
+    let format = export::Format::from_matches(matches);
+    let id_len = repo.abbreviation_length(matches);
+    if let Some(path) = matches.value_of("sqlite") {
+        export_sqlite(&repo, issues, &hidden, path)
+    } else if let Some(directory) = matches.value_of("html") {
+        export::html_site(&repo, issues, &hidden, id_len, Path::new(directory))
+            .unwrap_or_abort()
+            .into_iter()
+            .map(|path| path.display().to_string())
+            .print_lines()
+            .unwrap_or_abort();
+    } else if let Some(directory) = matches.value_of("directory") {
+        export::files(&repo, issues, &hidden, format, id_len, Path::new(directory))
+            .unwrap_or_abort()
+            .into_iter()
+            .map(|path| path.display().to_string())
+            .print_lines()
+            .unwrap_or_abort();
+    } else if let Some(path) = matches.value_of("output") {
+        let mut file = File::create(path)
+            .map(io::BufWriter::new)
+            .chain_err(|| EK::CannotWriteFile(path.to_owned()))
+            .unwrap_or_abort();
+        export::write(&repo, issues, &hidden, format, id_len, &mut file).unwrap_or_abort();
+    } else {
+        let stdout = io::stdout();
+        let mut stream = io::BufWriter::new(stdout.lock());
+        export::write(&repo, issues, &hidden, format, id_len, &mut stream).unwrap_or_abort();
     }
+}
 
-    // present the list to the user
-    let result = issues
-        .into_iter()
-        .map(|issue| issue.initial_message())
-        .abort_on_err()
-        .flat_map(|initial| formatter.iter().formatted_lines(initial))
-        .abort_on_err()
-        .pipe_lines(repo.pager())
+
+/// Mirror issues into an SQLite database, printing the ids of the issues written
+///
+#[cfg(feature = "sqlite")]
+fn export_sqlite<'r, I>(repo: &'r git2::Repository, issues: I, hidden: &Fn(&Commit) -> bool, path: &str)
+    where I: IntoIterator<Item = libgitdit::Issue<'r>>
+{
+    use error::ErrorKind as EK;
+    use error::ResultExt;
+
+    sqlite::mirror(repo, issues, hidden, std::path::Path::new(path))
+        .chain_err(|| EK::CannotWriteFile(path.to_owned()))
+        .unwrap_or_abort()
+        .print_lines()
         .unwrap_or_abort();
-    std::process::exit(result);
+}
+
+/// Mirror issues into an SQLite database, printing the ids of the issues written
+///
+#[cfg(not(feature = "sqlite"))]
+fn export_sqlite<'r, I>(_: &'r git2::Repository, _: I, _: &Fn(&Commit) -> bool, _: &str)
+    where I: IntoIterator<Item = libgitdit::Issue<'r>>
+{
+    error!("{}", tr!("git-dit was built without support for SQLite databases"));
+    std::process::exit(1);
 }
 
 
-/// new subcommand implementation
+/// fetch subcommand implementation
 ///
-fn mirror_impl(matches: &clap::ArgMatches) {
-    use std::collections::HashSet;
-    use gitext::{RemotePriorization, ReferrenceExt, ReferrencesExt};
+fn fetch_impl(matches: &clap::ArgMatches) {
+    use libgitdit::RemoteExt;
+    use libgitdit::quarantine::{quarantine_ref, screen_ref, FilterChain, Verdict};
+    use libgitdit::remote::RefSnapshot;
 
     let repo = util::open_dit_repo();
 
-    // retrieve the options and flags
-    let remote = matches.value_of("remote");
-    let clone_head = matches.is_present("clone-head");
-    let update_head = matches.is_present("update-head");
-    let create_leaves = matches.is_present("clone-leaves");
+    let filter = if matches.is_present("no-filter") {
+        FilterChain::new()
+    } else {
+        repo.message_filter()
+    };
 
-    let prios = remote
-        .map(RemotePriorization::from)
-        .unwrap_or_else(|| repo.remote_priorization());
-    let issues = repo
-        .cli_issues(matches)
-        .unwrap_or_else(|| repo.issues().unwrap_or_abort());
+    for name in repo.cli_remotes(matches) {
+        repo.setup_remote(&name);
+        let mut remote = repo.find_remote(&name).unwrap_or_abort();
 
-    for issue in issues {
-        if clone_head || update_head {
-            // take care about the head reference
-            if let Some(r) = issue.heads().abort_on_err().select_ref(&prios) {
-                let id = r
-                    .peel(git2::ObjectType::Commit)
-                    .unwrap_or_abort()
-                    .id();
-                // TODO: Failure to update a head ref should probably result in
-                //       a warning instead of a hard error.
-                issue.update_head(id, update_head).unwrap_or_abort();
+        // accumulate the refspecs to fetch
+        let refspecs : Vec<String> = if let Some(mut issues) = repo.cli_issues(matches) {
+            // fetch a specific list of issues
+            if matches.is_present("known") {
+                issues.extend(repo.issues().unwrap_or_abort());
             }
-        }
+            issues
+                .into_iter()
+                .filter_map(|issue| remote.issue_refspec(issue))
+                .collect()
+        } else {
+            vec![remote.all_issues_refspec().unwrap()]
+        };
 
-        if create_leaves {
-            // construct a hash set with all the relevant leaves
-            let mut leaves: HashSet<_> = issue
-                .remote_refs(IssueRefType::Leaf)
+        // set the options for the fetch
+        let mut fetch_options = git2::FetchOptions::new();
+        fetch_options.prune(if matches.is_present("prune") {
+            git2::FetchPrune::On
+        } else {
+            git2::FetchPrune::Unspecified
+        });
+        fetch_options.remote_callbacks(gitext::callbacks());
+
+        // remember the remote references' targets prior to the fetch
+        let before = RefSnapshot::of_remote(&repo, &name).unwrap_or_abort();
+
+        let refspec_refs : Vec<&str> = refspecs.iter().map(String::as_str).collect();
+        remote.fetch(refspec_refs.as_ref(), Some(&mut fetch_options), None)
+              .unwrap_or_abort();
+
+        if !filter.is_empty() {
+            // screen all messages which were not known prior to the fetch
+            let local_targets : Vec<git2::Oid> = repo
+                .references_glob("refs/dit/**")
+                .unwrap_or_abort()
                 .abort_on_err()
-                .filter(|reference| match remote {
-                    Some(r) => reference
-                        .remote()
-                        .map(|name| name == r)
-                        .unwrap_or(false),
-                    None => true,
-                })
-                .map(|reference| reference
-                    .peel(git2::ObjectType::Commit)
-                    .unwrap_or_abort()
-                    .id()
-                )
+                .filter_map(|r| r.target())
                 .collect();
-
-            // Prepare revwalk for iterating over all messages which already
-            // hang by local refs.
-            let mut existing_refs = issue
-                .terminated_messages()
+            let fetched = repo
+                .references_glob(&format!("refs/remotes/{}/dit/**", name))
                 .unwrap_or_abort()
-                .revwalk;
-            for reference in issue.local_refs(IssueRefType::Any).abort_on_err() {
-                existing_refs
-                    .push(reference
-                        .peel(git2::ObjectType::Commit)
-                        .unwrap_or_abort()
-                        .id()
-                    )
-                    .unwrap_or_abort();
-            }
-            {
-                // This also includes future refs. We therefore add parents of
-                // the supposed leaves as starting points of the revwalk. If any
-                // of the ids cloned from the remote do not refer to leaves,
-                // they will be filtered out early.
-                let leaf_messages = leaves
-                    .iter()
-                    .cloned()
-                    .map(|id| repo.find_commit(id))
-                    .abort_on_err();
-                for message in leaf_messages {
-                    for parent in message.parent_ids() {
-                        existing_refs.push(parent).unwrap_or_abort();
-                    }
+                .abort_on_err()
+                .filter(|r| r.name().and_then(IssueRefType::of_ref).is_some());
+            for mut reference in fetched {
+                let refname = reference.name().unwrap_or_default().to_owned();
+                let previous = before.target(&refname);
+                if previous.is_some() && previous == reference.target() {
+                    continue;
                 }
-            }
-
-            // filter out any leaf which is not required
-            for id in existing_refs.abort_on_err() {
-                leaves.remove(&id);
-            }
 
-            // create refs for remaining leaves
-            for leaf in leaves {
-                issue.add_leaf(leaf).unwrap_or_abort();
+                let known = local_targets.iter().cloned().chain(previous);
+                match screen_ref(&repo, &filter, &reference, known).unwrap_or_abort() {
+                    Verdict::Accept => {},
+                    Verdict::Flag(reason) => warn!("{}", tr!("Flagged {}: {}", refname, reason)),
+                    Verdict::Quarantine(reason) => {
+                        warn!("{}", tr!("Quarantined {}: {}", refname, reason));
+                        quarantine_ref(&repo, &mut reference).unwrap_or_abort();
+                    },
+                }
             }
         }
+
+        // summarize what moved
+        let after = RefSnapshot::of_remote(&repo, &name).unwrap_or_abort();
+        util::change_summary(&name, before.changes(&after))
+            .into_iter()
+            .print_lines()
+            .unwrap_or_abort();
     }
 }
 
 
-/// new subcommand implementation
+/// Get the issues selected by the filter specification given
 ///
-fn new_impl(matches: &clap::ArgMatches) {
-    use util::message_from_args;
-
-    let repo = util::open_dit_repo();
-    let author = repo.cli_author(matches);
-    let committer = repo.signature().unwrap_or_abort();
-
-    // get the message, either from the command line argument or an editor
-    let message = if let Some(m) = message_from_args(matches) {
-        // the message was supplied via the command line
-        m.into_iter()
-         .chain(repo.prepare_trailers(matches)
-                    .into_iter()
-                    .map(|t| t.to_string()))
-         .collect()
-    } else {
-        // we need an editor
+/// Issues are restricted to a component and filtered by metadata according
+/// to the options and positional filter specifications shared by the "list"
+/// and "search" subcommands. Issues hidden via moderation are omitted unless
+/// requested otherwise. Closed issues are omitted unless `include_closed` is
+/// set or the status is filtered explicitly.
+///
+fn filtered_issues<'r>(repo: &'r git2::Repository,
+                       matches: &clap::ArgMatches,
+                       include_closed: bool
+) -> Vec<libgitdit::Issue<'r>> {
+    use std::collections::HashMap;
+    use libgitdit::Issue;
 
-        // get the path where we want to edit the message
-        let path = repo.commitmsg_edit_path(matches);
+    use filters::{self, FilterSpec, MetadataFilter};
+    use index::Index;
 
-        { // write
-            let mut file = File::create(path.as_path()).unwrap_or_abort();
-            repo.prepare_trailers(matches)
-                .write_lines(&mut file)
-                .unwrap_or_abort();
-            file.flush().unwrap_or_abort();
+    let remote_prios = repo.remote_priorization();
+    let moderation = repo.moderation().unwrap_or_abort();
+    let show_hidden = matches.is_present("show-hidden");
+
+    // construct filter from groups of specs separated by "or"
+    let mut groups : Vec<Vec<FilterSpec>> = vec![Vec::new()];
+    for value in matches.values_of("filter").into_iter().flat_map(|values| values) {
+        if value == "or" {
+            groups.push(Vec::new());
+        } else {
+            groups.last_mut().unwrap().push(value.parse().unwrap_or_abort());
         }
+    }
 
-        repo.get_commit_msg(path)
-    }.into_iter().collect_string();
-
-    // commit the message
-    let tree = repo.empty_tree().unwrap_or_abort();
-    let id = repo
-        .create_issue(&author, &committer, message.trim(), &tree, Vec::new())
+    // the query is applied in addition to the filter
+    let query = matches
+        .value_of("query")
+        .map(filters::parse_query)
+        .unwrap_or_else(|| Ok(Vec::new()))
         .unwrap_or_abort();
-    println!("[dit][new] {}", id);
-}
 
+    // so is the range of creation dates
+    let since = matches.value_of("since").map(|date| filters::parse_date(date).unwrap_or_abort());
+    let until = matches.value_of("until").map(|date| filters::parse_date(date).unwrap_or_abort());
+    let created = if since.is_some() || until.is_some() {
+        Some(vec![FilterSpec::created_within(since, until)])
+    } else {
+        None
+    };
+
+    // unless requested otherwise, only list open issues, whose status is
+    // looked up in the index
+    let status_filtered = groups
+        .iter()
+        .chain(query.iter())
+        .flat_map(|group| group.iter())
+        .any(|spec| spec.key() == "status" || spec.key() == "closed");
+    let filter = MetadataFilter::from_groups(&remote_prios, groups).unwrap_or_abort();
+    let query = MetadataFilter::from_groups(&remote_prios, query).unwrap_or_abort();
+    let created = MetadataFilter::from_groups(&remote_prios, created).unwrap_or_abort();
+
+    // tags and assignees filtered are also looked up in the index
+    let cached_metadata = filter.uses_cached_metadata() || query.uses_cached_metadata();
+    let mut index = if (!include_closed && !status_filtered) || cached_metadata {
+        Some(Index::open(repo).unwrap_or_abort())
+    } else {
+        None
+    };
+
+    let mut issues: Vec<_> = match matches.value_of("component") {
+            Some(component) => repo.issues_in_component("**", component),
+            None            => repo.issues(),
+        }
+        .unwrap_or_abort()
+        .into_iter()
+        .collect();
+    let mut metadata = HashMap::new();
+    if let Some(ref mut index) = index {
+        if !include_closed && !status_filtered {
+            index.update(&issues).unwrap_or_abort();
+            issues.retain(|issue| index.entry(issue).unwrap_or_abort().status != "closed");
+        }
+        if cached_metadata {
+            let known = index.metadata(&issues).unwrap_or_abort();
+            metadata = issues.iter().map(Issue::id).zip(known.into_iter().map(|(_, m)| m)).collect();
+        }
+    }
+
+    let selected = parallel::map(repo, &issues, |repo, issue| {
+        let metadata = metadata.get(&issue.id());
+        (show_hidden || !moderation.is_hidden(&issue.initial_message().unwrap_or_abort())) &&
+            filter.filter(repo, issue, metadata) &&
+            query.filter(repo, issue, metadata) &&
+            created.filter(repo, issue, metadata)
+    });
+    let issues = issues
+        .into_iter()
+        .zip(selected)
+        .filter(|&(_, selected)| selected)
+        .map(|(issue, _)| issue)
+        .collect();
+
+    if let Some(Err(err)) = index.as_mut().map(Index::save) {
+        warn!("{}", tr!("Could not update the issue index: {}", err));
+    }
+    issues
+}
+
+
+/// Sort issues according to the "sort" and "order" options
+///
+/// Issues are sorted by creation date, most recent first, by default. If
+/// only a key is given, it is sorted in the key's default order.
+///
+fn sorted_issues<'r>(repo: &'r git2::Repository,
+                     matches: &clap::ArgMatches,
+                     issues: Vec<libgitdit::Issue<'r>>
+) -> Vec<libgitdit::Issue<'r>> {
+    use std::collections::HashMap;
+    use libgitdit::listing::{self, SortKey};
+
+    use index::Index;
+
+    let key = matches
+        .value_of("sort")
+        .map(str::parse)
+        .unwrap_or(Ok(SortKey::Created))
+        .unwrap_or_abort();
+    let order = matches
+        .value_of("order")
+        .map(str::parse)
+        .unwrap_or_else(|| Ok(key.default_order()))
+        .unwrap_or_abort();
+
+    if key != SortKey::LastActivity {
+        return listing::sort_issues(issues, key, order, |issue| Ok(repo.issue_head(issue).id())).unwrap_or_abort();
+    }
+
+    // the time of the last activity is cached in the index
+    let mut index = Index::open(repo).unwrap_or_abort();
+    let activity: HashMap<_, _> = issues
+        .iter()
+        .map(libgitdit::Issue::id)
+        .zip(index.last_activities(&issues).unwrap_or_abort())
+        .collect();
+    if let Err(err) = index.save() {
+        warn!("{}", tr!("Could not update the issue index: {}", err));
+    }
+    listing::sort_issues_by(issues, order, |issue| Ok(activity.get(&issue.id()).and_then(|&time| time)))
+        .unwrap_or_abort()
+}
+
+
+/// fsck subcommand implementation
+///
+fn fsck_impl(matches: &clap::ArgMatches) {
+    use libgitdit::fsck::{check, Severity};
+
+    let repo = util::open_dit_repo();
+    let problems = check(&repo).unwrap_or_abort();
+
+    let min_severity = if matches.is_present("no-warnings") {
+        Severity::Error
+    } else {
+        Severity::Warning
+    };
+    problems
+        .iter()
+        .filter(|problem| problem.severity() >= min_severity)
+        .map(|problem| format!("{}: {}", problem.severity(), problem))
+        .print_lines()
+        .unwrap_or_abort();
+
+    if problems.iter().any(|problem| problem.severity() == Severity::Error) {
+        std::process::exit(1);
+    }
+}
+
+
+/// gc subcommand implementation
+///
+fn gc_impl(matches: &clap::ArgMatches) {
+    use libgitdit::error::{Error as LibError, ErrorKind as LibEK};
+    use libgitdit::gc::{parse_age, CollectableRefs, ReferenceCollectionSpec as RCS};
+    use libgitdit::issue::IssueRefType;
+
+    use json::Format;
+
+    let repo = util::open_dit_repo();
+
+    // The defaults are read from the configuration and overridden by the
+    // options given on the command line.
+    let mut collect = CollectableRefs::with_config(&repo).unwrap_or_abort();
+    if matches.is_present("consider-remote") {
+        collect = collect.consider_remote_refs(true);
+    }
+    match matches.value_of("collect-heads") {
+        Some("never") => collect = collect.collect_heads(RCS::Never),
+        // `--collect-heads` without a value is equivalent to
+        // `--collect-heads=backed-by-remote`
+        _ if matches.is_present("collect-heads") => collect = collect.collect_heads(RCS::BackedByRemoteHead),
+        _ => {},
+    }
+    match matches.value_of("collect-leaves") {
+        Some("always") => collect = collect.collect_leaves(RCS::Always),
+        Some("backed-by-remote") => collect = collect.collect_leaves(RCS::BackedByRemote),
+        _ => {},
+    }
+    if let Some(age) = matches.value_of("older-than") {
+        collect = collect.older_than(Some(parse_age(age).unwrap_or_abort()));
+    }
+
+    let dry_run = matches.is_present("dry-run");
+    let issues = repo.cli_issues(matches);
+    let format = Format::from_matches(matches);
+
+    if !dry_run {
+        let result = collect
+            .collect_orphaned(matches.is_present("orphaned"))
+            .collect(|issue| issues.as_ref().map(|i| i.contains(issue)).unwrap_or(true))
+            .unwrap_or_abort();
+        let report = result.value;
+
+        if matches.is_present("summary") {
+            if format == Format::Json {
+                json::print(&json!({
+                    "examined": report.examined,
+                    "deleted": {
+                        "heads": report.heads,
+                        "leaves": report.leaves,
+                        "orphaned": report.orphaned,
+                    },
+                    "issues": report.issues,
+                    "duration": report.duration.as_secs_f64(),
+                })).unwrap_or_abort();
+            } else {
+                println!(
+                    "examined {} refs, deleted {} heads, {} leaves and {} orphaned refs of {} issues in {:.2}s",
+                    report.examined,
+                    report.heads,
+                    report.leaves,
+                    report.orphaned,
+                    report.issues,
+                    report.duration.as_secs_f64(),
+                );
+            }
+        } else if format == Format::Json {
+            let refs: Vec<_> = report
+                .refs
+                .into_iter()
+                .map(|(name, target)| json!({"ref": name, "target": target.to_string()}))
+                .collect();
+            json::print(&json!(refs)).unwrap_or_abort();
+        } else {
+            report
+                .refs
+                .into_iter()
+                .map(|(name, _)| name)
+                .print_lines()
+                .unwrap_or_abort();
+        }
+        abort_on_failures(&result.failures);
+        return;
+    }
+
+    // For a dry run, we also determine why the references are collectable.
+    // Like the collection itself, issues which cannot be examined are reported
+    // after the remaining ones.
+    let ids: Option<Vec<_>> = issues
+        .as_ref()
+        .map(|issues| issues.iter().map(|issue| issue.id()).collect());
+    let mut failures = Vec::new();
+    let issues = issues.unwrap_or_else(|| {
+        let found = repo.issues_partial().unwrap_or_abort();
+        failures.extend(found.failures);
+        found.value
+    });
+    let mut collected = Vec::new();
+    for issue in issues {
+        match collect.preview(&issue) {
+            Ok(refs) => collected.extend(refs),
+            Err(err) => failures.push(LibError::with_chain(err, LibEK::CannotCollectReferences(issue.id()))),
+        }
+    }
+
+    // references of removed remotes, restricted to the issues specified
+    if matches.is_present("orphaned") {
+        collected.extend(collect
+            .orphaned_refs()
+            .unwrap_or_abort()
+            .into_iter()
+            .filter(|r| match (ids.as_ref(), r.reference.name().and_then(IssueRefType::of_ref)) {
+                (Some(ids), Some((id, _))) => ids.contains(&id),
+                (Some(_), None) => false,
+                (None, _) => true,
+            }));
+    }
+
+    if format == Format::Json {
+        let refs: Vec<_> = collected
+            .into_iter()
+            .map(|r| json!({
+                "ref": r.reference.name(),
+                "target": r.reference.target().map(|id| id.to_string()),
+                "reason": r.reason.to_string(),
+            }))
+            .collect();
+        json::print(&json!(refs)).unwrap_or_abort();
+    } else {
+        collected
+            .into_iter()
+            .filter_map(|r| r.reference.name().map(|name| format!("{} ({})", name, r.reason)))
+            .print_lines()
+            .unwrap_or_abort();
+    }
+    abort_on_failures(&failures);
+}
+
+
+/// import subcommand implementation
+///
+fn import_impl(matches: &clap::ArgMatches) {
+    use error::ErrorKind as EK;
+    use error::ResultExt;
+    use forge::Forge;
+
+    let repo = util::open_dit_repo();
+
+    let values: Vec<serde_json::Value> = if let Some((forge, api, repository)) = Forge::from_matches(matches) {
+        let config = repo.config().and_then(|mut config| config.snapshot()).unwrap_or_abort();
+        forge::Client::new(&config, forge, api)
+            .issue_values(&repo, repository)
+            .unwrap_or_abort()
+    } else if let Some(path) = matches.value_of("radicle") {
+        let storage = git2::Repository::open(path).unwrap_or_abort();
+        radicle::issue_values(&storage).unwrap_or_abort()
+    } else {
+        let documents: Vec<(String, String)> = match matches.values_of("file") {
+            Some(files) => files
+                .map(|path| {
+                    let mut document = String::new();
+                    File::open(path)
+                        .and_then(|mut file| file.read_to_string(&mut document))
+                        .chain_err(|| EK::WrappedIOError)
+                        .unwrap_or_abort();
+                    (format!("'{}'", path), document)
+                })
+                .collect(),
+            None => {
+                let mut document = String::new();
+                io::stdin().read_to_string(&mut document).chain_err(|| EK::WrappedIOError).unwrap_or_abort();
+                vec![("input".to_owned(), document)]
+            },
+        };
+
+        let mut values = Vec::new();
+        for (name, document) in documents {
+            let parse_error = || EK::MalformedImport(format!("cannot parse {}", name));
+            match matches.value_of("format") {
+                Some("bugzilla") => values.extend(bugzilla::issue_values(&document)
+                    .chain_err(parse_error)
+                    .unwrap_or_abort()),
+                Some("jira") => values.extend(serde_json::from_str(&document)
+                    .chain_err(&parse_error)
+                    .and_then(|value| jira::issue_values(&value).chain_err(&parse_error))
+                    .unwrap_or_abort()),
+                _ => values.push(serde_json::from_str(&document)
+                    .chain_err(parse_error)
+                    .unwrap_or_abort()),
+            }
+        }
+        values
+    };
+
+    let mut failures = Vec::new();
+    for value in values {
+        let result = import::json(&repo, &value).unwrap_or_abort();
+        result
+            .value
+            .into_iter()
+            .map(|(foreign, id)| format!("{} {}", foreign, id))
+            .print_lines()
+            .unwrap_or_abort();
+        failures.extend(result.failures);
+    }
+    abort_on_failures(&failures);
+}
+
+
+/// ingest subcommand implementation
+///
+fn ingest_impl(matches: &clap::ArgMatches) {
+    use libgitdit::message_index::MessageIndex;
+    use libgitdit::quarantine::FilterChain;
+    use std::io::BufReader;
+    use std::net::TcpStream;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use error::ErrorKind as EK;
+    use error::{LoggableError, ResultExt};
+    use imap::Session;
+    use import::MailImport;
+    use mail::Mail;
+    use system::programs;
+
+    let repo = util::open_dit_repo();
+    let committer = repo.dit_signature();
+    let policy = repo.head_policy(matches);
+    let filter = if matches.is_present("no-filter") {
+        FilterChain::new()
+    } else {
+        repo.message_filter()
+    };
+    let mut index = MessageIndex::updated(&repo);
+
+    // Import a single mail, returning whether it was processed. Mails which do
+    // not refer to a known message or cannot be imported are not processed.
+    let mut ingest = |data: &[u8]| {
+        let mail = Mail::parse(data);
+        let name = mail.header("Message-ID").unwrap_or("<unknown>").to_owned();
+        match import::mail(&repo, &mut index, &mail, &committer, &filter, policy) {
+            Ok(MailImport::Imported(id)) => println!("{} {}", name, id),
+            Ok(MailImport::Flagged(id, reason)) => {
+                warn!("{}", tr!("Flagged {}: {}", id, reason));
+                println!("{} {}", name, id);
+            },
+            Ok(MailImport::Known(id)) => info!("Mail {} is message {}", name, id),
+            Ok(MailImport::Unrelated) => {
+                warn!("{}", tr!("Ignoring mail {}, which is not a reply to any known message", name));
+                return false;
+            },
+            Ok(MailImport::Rejected(reason)) => warn!("{}", tr!("Rejected mail {}: {}", name, reason)),
+            Err(err) => {
+                err.chain_err(|| EK::MalformedImport(format!("cannot import mail {}", name))).log();
+                return false;
+            },
+        }
+        true
+    };
+
+    if !matches.is_present("imap") {
+        let mut data = String::new();
+        match matches.values_of("mbox") {
+            Some(files) => for path in files {
+                File::open(path)
+                    .and_then(|mut file| file.read_to_string(&mut data))
+                    .chain_err(|| EK::WrappedIOError)
+                    .unwrap_or_abort();
+            },
+            None => {
+                io::stdin().read_to_string(&mut data).unwrap_or_abort();
+            },
+        }
+        for mail in mail::split_mbox(&data) {
+            ingest(mail.as_bytes());
+        }
+        return;
+    }
+
+    let interval = matches
+        .value_of("interval")
+        .map(|interval| Duration::from_secs(str::parse(interval).unwrap_or_abort()));
+    loop {
+        // the configuration is read anew for each poll
+        let config = repo.config().and_then(|mut config| config.snapshot()).unwrap_or_abort();
+        let mailbox = config
+            .get_string("dit.mail.imap.mailbox")
+            .unwrap_or_else(|_| "INBOX".to_owned());
+        let login = config.get_string("dit.mail.imap.user").ok().map(|user| {
+            (user, config.get_string("dit.mail.imap.password").unwrap_or_default())
+        });
+        // The connection is either provided by a tunnel or plain TCP. Since
+        // the latter is unencrypted, credentials are only sent via plain TCP
+        // to the local host, e.g. to a TLS proxy.
+        let mut tunnel = None;
+        let connection: error::Result<(Box<io::BufRead>, Box<Write>, bool)> = if config.get_string("dit.mail.imap.tunnel").is_ok() {
+            programs::imap_tunnel(config).map(|mut child| {
+                let reader = BufReader::new(child.stdout.take().expect("Could not access stdout"));
+                let writer = child.stdin.take().expect("Could not access stdin");
+                tunnel = Some(child);
+                (Box::new(reader) as Box<io::BufRead>, Box::new(writer) as Box<Write>, true)
+            })
+        } else {
+            config
+                .get_string("dit.mail.imap.server")
+                .chain_err(|| EK::ImapError("no server or tunnel configured".to_owned()))
+                .and_then(|server| TcpStream::connect(&server).chain_err(|| EK::ImapError(server)))
+                .and_then(|stream| {
+                    let local = stream.peer_addr().map(|addr| addr.ip().is_loopback()).unwrap_or(false);
+                    let reader = BufReader::new(stream.try_clone().chain_err(|| EK::WrappedIOError)?);
+                    Ok((Box::new(reader) as Box<io::BufRead>, Box::new(stream) as Box<Write>, local))
+                })
+        };
+
+        let result = connection
+            .and_then(|(reader, writer, secure)| Session::new(reader, writer).map(|session| (session, secure)))
+            .and_then(|(mut session, secure)| {
+                if let Some((ref user, ref password)) = login {
+                    if !secure {
+                        let reason = "refusing to log in via an unencrypted connection, use dit.mail.imap.tunnel";
+                        return Err(error::Error::from_kind(EK::ImapError(reason.to_owned())));
+                    }
+                    session.login(user, password)?;
+                }
+                session.select(&mailbox)?;
+                session.process_unseen(&mut ingest)?;
+                session.logout()
+            });
+        if let Some(mut child) = tunnel {
+            child.wait().unwrap_or_abort();
+        }
+
+        match interval {
+            Some(interval) => {
+                if let Err(err) = result {
+                    err.log();
+                }
+                sleep(interval);
+            },
+            None => return result.unwrap_or_abort(),
+        }
+    }
+}
+
+
+/// lint subcommand implementation
+///
+fn lint_impl(matches: &clap::ArgMatches) {
+    use error::Error;
+    use error::ErrorKind as EK;
+    use libgitdit::message::line_processor::WithoutCommentsIter;
+    use libgitdit::message::lint::{self, Linter};
+
+    let reader: Box<Read> = match matches.value_of("filename") {
+        Some(filename)  => Box::from(File::open(filename).unwrap_or_abort()),
+        None            => Box::from(io::stdin()),
+    };
+    use io::BufRead;
+    let lines = WithoutCommentsIter::from(io::BufReader::new(reader).lines().abort_on_err());
+
+    let repo = util::open_dit_repo();
+    let lints = Linter::with_config(&repo.dit_config())
+        .unwrap_or_abort()
+        .lint(lines);
+    lints.iter().print_lines().unwrap_or_abort();
+
+    if lint::has_errors(&lints) {
+        abort(&Error::from_kind(EK::RejectedByLint));
+    }
+}
+
+
+/// list subcommand implementation
+///
+fn list_impl(matches: &clap::ArgMatches) {
+    use chrono::format::strftime::StrftimeItems;
+    use libgitdit::Issue;
+    use libgitdit::error::{Error as LibError, ErrorKind as LibEK};
+    use libgitdit::listing::{self, GroupKey};
+
+    use std::collections::HashMap;
+
+    use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
+    use index::Index;
+    use json::Format;
+
+    let repo = util::open_dit_repo();
+
+    let id_len = repo.abbreviation_length(matches);
+    let palette = repo.palette();
+    let long = matches.is_present("long");
+
+    let issues = filtered_issues(&repo, matches, matches.is_present("all"));
+    let group_key = matches.value_of("group-by").map(|key| key.parse::<GroupKey>().unwrap_or_abort());
+
+    // the format may be configured via `dit.list.format`
+    let format = match matches.value_of("format") {
+        Some(_) => Format::from_matches(matches),
+        None => match repo.dit_config().list_format().unwrap_or_abort() {
+            Some(name) => Format::from_name(&name)
+                .ok_or_else(|| LibError::from_kind(LibEK::InvalidConfigValue("dit.list.format".to_owned(), name)))
+                .unwrap_or_abort(),
+            None => Format::Text,
+        },
+    };
+
+    // the issues need not be sorted if only counted
+    if matches.is_present("count") {
+        let counts = listing::count_issues(issues, group_key, |issue| Ok(repo.issue_head(issue).id())).unwrap_or_abort();
+        if format == Format::Json {
+            let groups: Vec<_> = counts
+                .groups
+                .iter()
+                .map(|&(ref value, count)| json!({"value": value, "count": count}))
+                .collect();
+            json::print(&json!({"total": counts.total, "groups": groups})).unwrap_or_abort();
+        } else if group_key.is_some() {
+            counts
+                .groups
+                .iter()
+                .map(|&(ref value, count)| format!("{:>7} {}", count, value.as_deref().unwrap_or("(none)")))
+                .print_lines()
+                .unwrap_or_abort();
+        } else {
+            println!("{}", counts.total);
+        }
+        return;
+    }
+
+    let mut issues = sorted_issues(&repo, matches, issues);
+
+    // optionally limit to some number specified by the user
+    if let Some(number) = matches.value_of("n") {
+        // TODO: better error reporting?
+        issues.truncate(str::parse(number).unwrap_or_abort());
+    }
+
+    // optionally group the issues
+    let groups = group_key.map(|key| {
+        let groups = listing::group_issues(&issues, key, |issue| Ok(repo.issue_head(issue).id())).unwrap_or_abort();
+        (key, groups)
+    });
+    let json_list = |issues: &[&Issue]| -> Vec<_> {
+        issues
+            .iter()
+            .map(|issue| json::issue(&repo, issue, &issue.initial_message().unwrap_or_abort()))
+            .collect()
+    };
+
+    if format == Format::Json {
+        let list = match groups {
+            Some((key, groups)) => groups
+                .iter()
+                .map(|group| json!({
+                    "key": key.name(),
+                    "value": group.value,
+                    "count": group.len(),
+                    "issues": json_list(&group.issues),
+                }))
+                .collect(),
+            None => json_list(&issues.iter().collect::<Vec<_>>()),
+        };
+        json::print(&json!(list)).unwrap_or_abort();
+        return;
+    }
+
+    // the titles listed are looked up in the index
+    let mut titles = HashMap::new();
+    if !long {
+        let mut index = Index::open(&repo).unwrap_or_abort();
+        index.update(&issues).unwrap_or_abort();
+        for issue in &issues {
+            titles.insert(issue.id(), index.entry(issue).unwrap_or_abort().title);
+        }
+        if let Err(err) = index.save() {
+            warn!("{}", tr!("Could not update the issue index: {}", err));
+        }
+    }
+
+    let lines_of = |issue: &Issue| {
+        let formatter = if long {
+            tokenvec![
+                palette.id(), MFT::Id(id_len), palette.reset(), FT::LineEnd,
+                "Author: ", palette.author(), MFT::Author, palette.reset(), FT::LineEnd,
+                "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd,
+                FT::LineEnd,
+                MFT::Subject, FT::LineEnd,
+                FT::LineEnd,
+                MFT::BodyText,
+                FT::LineEnd]
+        } else {
+            let title = titles.get(&issue.id()).cloned().unwrap_or_default();
+            tokenvec![
+                palette.id(), MFT::Id(id_len), palette.reset(),
+                " (", MFT::Date(StrftimeItems::new("%c")), ") ", title]
+        };
+        formatter
+            .iter()
+            .formatted_lines(issue.initial_message().unwrap_or_abort())
+            .abort_on_err()
+            .collect::<Vec<_>>()
+    };
+
+    // present the list to the user, each group under a header
+    let lines: Vec<String> = match groups {
+        Some((key, groups)) => {
+            let mut lines = Vec::new();
+            for group in groups {
+                if !lines.is_empty() {
+                    lines.push(String::new());
+                }
+                let value = group.value.as_deref().unwrap_or("(none)");
+                let color = match key {
+                    GroupKey::Status => palette.status(value),
+                    _ => palette.tag(),
+                };
+                lines.push(format!("{} ({})", palette.paint(color, value), group.len()));
+                lines.extend(group.issues.iter().flat_map(|issue| lines_of(issue)));
+            }
+            lines
+        },
+        None => issues.iter().flat_map(lines_of).collect(),
+    };
+    let result = lines
+        .into_iter()
+        .pipe_lines(repo.pager())
+        .unwrap_or_abort();
+    std::process::exit(result);
+}
+
+
+/// log subcommand implementation
+///
+fn log_impl(matches: &clap::ArgMatches) {
+    use chrono::format::strftime::StrftimeItems;
+    use libgitdit::event::{self, EventKind};
+
+    use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
+    use filters::parse_date;
+
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+    let palette = repo.palette();
+    let moderation = repo.moderation().unwrap_or_abort();
+    let show_hidden = matches.is_present("show-hidden");
+
+    let issues: Vec<_> = match repo.cli_issues(matches) {
+        Some(issues) => issues.into_iter().collect(),
+        None => match matches.value_of("component") {
+                Some(component) => repo.issues_in_component("**", component),
+                None            => repo.issues(),
+            }
+            .unwrap_or_abort()
+            .into_iter()
+            .collect(),
+    };
+
+    // only events younger than the cutoff are shown
+    let cutoff = matches
+        .value_of("since")
+        .map(|date| parse_date(date).unwrap_or_abort());
+
+    let mut events: Vec<_> = event::events(issues)
+        .unwrap_or_abort()
+        .into_iter()
+        .filter(|event| show_hidden || !moderation.is_hidden(event.message()))
+        .take_while(|event| cutoff.map(|c| event.time().seconds() >= c).unwrap_or(true))
+        .collect();
+
+    // optionally limit to some number specified by the user
+    if let Some(number) = matches.value_of("n") {
+        events.truncate(str::parse(number).unwrap_or_abort());
+    }
+
+    if matches.value_of("format") == Some("atom") {
+        // NOTE: the title has a default value
+        let title = matches.value_of("title").unwrap();
+        let stdout = io::stdout();
+        let mut stream = io::BufWriter::new(stdout.lock());
+        feed::atom(&repo, &events, title, matches.value_of("base-url"), &mut stream).unwrap_or_abort();
+        return;
+    }
+
+    let result = events
+        .into_iter()
+        .flat_map(|event| {
+            let action = match event.kind() {
+                &EventKind::NewIssue => "opened".to_owned(),
+                &EventKind::Reply => "replied to".to_owned(),
+                &EventKind::StatusChange(ref status) if status == "closed" => {
+                    palette.paint(palette.status(status), "closed")
+                },
+                &EventKind::StatusChange(ref status) if status == "open" => {
+                    palette.paint(palette.status(status), "reopened")
+                },
+                &EventKind::StatusChange(ref status) => format!("set status \"{}\" of", status),
+            };
+            let subject = repo
+                .find_commit(event.issue())
+                .ok()
+                .and_then(|initial| initial.summary().map(String::from))
+                .unwrap_or_default();
+            let formatter : Vec<FT<_,_>> = tokenvec![
+                palette.id(), MFT::Id(id_len), palette.reset(),
+                " (", MFT::Date(StrftimeItems::new("%c")), ") ",
+                palette.author(), MFT::AuthorName, palette.reset(), " ",
+                format!("{} {}{:.*}{}: {}", action, palette.id(), id_len, event.issue(), palette.reset(), subject)];
+            formatter.iter().formatted_lines(event.message().clone()).collect::<Vec<_>>()
+        })
+        .abort_on_err()
+        .pipe_lines(repo.pager())
+        .unwrap_or_abort();
+    std::process::exit(result);
+}
+
+
+/// mine subcommand implementation
+///
+fn mine_impl(matches: &clap::ArgMatches) {
+    use chrono::format::strftime::StrftimeItems;
+    use libgitdit::listing::{self, Involvement};
+
+    use display::{MessageFmtToken as MFT, LineFormatter};
+    use json::Format;
+
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+    let palette = repo.palette();
+    let person = repo.dit_signature();
+
+    // all buckets are listed unless some are selected
+    let buckets: Vec<Involvement> = match matches.values_of("bucket") {
+        Some(values) => values.map(|value| value.parse().unwrap_or_abort()).collect(),
+        None => Involvement::all().to_vec(),
+    };
+
+    let issues = filtered_issues(&repo, matches, matches.is_present("all"));
+    let issues = sorted_issues(&repo, matches, issues);
+    let involvements: Vec<_> = issues
+        .iter()
+        .map(|issue| listing::involvements(issue, repo.issue_head(issue).id(), &person).unwrap_or_abort())
+        .collect();
+    let bucket = |involvement: &Involvement| issues
+        .iter()
+        .zip(involvements.iter())
+        .filter(|&(_, involved)| involved.contains(involvement))
+        .map(|(issue, _)| issue)
+        .collect::<Vec<_>>();
+
+    if Format::from_matches(matches) == Format::Json {
+        let list: Vec<_> = buckets
+            .iter()
+            .map(|involvement| {
+                let issues: Vec<_> = bucket(involvement)
+                    .into_iter()
+                    .map(|issue| json::issue(&repo, issue, &issue.initial_message().unwrap_or_abort()))
+                    .collect();
+                json!({"bucket": involvement.name(), "count": issues.len(), "issues": issues})
+            })
+            .collect();
+        json::print(&json!(list)).unwrap_or_abort();
+        return;
+    }
+
+    let formatter = tokenvec![
+        palette.id(), MFT::Id(id_len), palette.reset(),
+        " (", MFT::Date(StrftimeItems::new("%c")), ") ", MFT::Subject];
+
+    // present each bucket under a header
+    let mut lines = Vec::new();
+    for involvement in &buckets {
+        let issues = bucket(involvement);
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(format!("{} ({})", palette.paint(palette.tag(), involvement.name()), issues.len()));
+        for issue in issues {
+            lines.extend(formatter.iter().formatted_lines(issue.initial_message().unwrap_or_abort()).abort_on_err());
+        }
+    }
+    let result = lines
+        .into_iter()
+        .pipe_lines(repo.pager())
+        .unwrap_or_abort();
+    std::process::exit(result);
+}
+
+
+/// new subcommand implementation
+///
+fn mirror_impl(matches: &clap::ArgMatches) {
+    use std::collections::HashSet;
+    use gitext::{RemotePriorization, ReferrenceExt, ReferrencesExt};
+
+    let repo = util::open_dit_repo();
+
+    // retrieve the options and flags
+    let remote = matches.value_of("remote");
+    let clone_head = matches.is_present("clone-head");
+    let update_head = matches.is_present("update-head");
+    let create_leaves = matches.is_present("clone-leaves");
+
+    let prios = remote
+        .map(RemotePriorization::from)
+        .unwrap_or_else(|| repo.remote_priorization());
+    let issues = repo
+        .cli_issues(matches)
+        .unwrap_or_else(|| repo.issues().unwrap_or_abort());
+
+    for issue in issues {
+        if clone_head || update_head {
+            // take care about the head reference
+            if let Some(r) = issue.heads().abort_on_err().select_ref(&prios) {
+                let id = r
+                    .peel(git2::ObjectType::Commit)
+                    .unwrap_or_abort()
+                    .id();
+                // TODO: Failure to update a head ref should probably result in
+                //       a warning instead of a hard error.
+                issue.update_head(id, update_head).unwrap_or_abort();
+            }
+        }
+
+        if create_leaves {
+            // construct a hash set with all the relevant leaves
+            let mut leaves: HashSet<_> = issue
+                .remote_refs(IssueRefType::Leaf)
+                .abort_on_err()
+                .filter(|reference| match remote {
+                    Some(r) => reference
+                        .remote()
+                        .map(|name| name == r)
+                        .unwrap_or(false),
+                    None => true,
+                })
+                .map(|reference| reference
+                    .peel(git2::ObjectType::Commit)
+                    .unwrap_or_abort()
+                    .id()
+                )
+                .collect();
+
+            // Prepare revwalk for iterating over all messages which already
+            // hang by local refs.
+            let mut existing_refs = issue
+                .terminated_messages()
+                .unwrap_or_abort()
+                .revwalk;
+            for reference in issue.local_refs(IssueRefType::Any).abort_on_err() {
+                existing_refs
+                    .push(reference
+                        .peel(git2::ObjectType::Commit)
+                        .unwrap_or_abort()
+                        .id()
+                    )
+                    .unwrap_or_abort();
+            }
+            {
+                // This also includes future refs. We therefore add parents of
+                // the supposed leaves as starting points of the revwalk. If any
+                // of the ids cloned from the remote do not refer to leaves,
+                // they will be filtered out early.
+                let leaf_messages = leaves
+                    .iter()
+                    .cloned()
+                    .map(|id| repo.find_commit(id))
+                    .abort_on_err();
+                for message in leaf_messages {
+                    for parent in message.parent_ids() {
+                        existing_refs.push(parent).unwrap_or_abort();
+                    }
+                }
+            }
+
+            // filter out any leaf which is not required
+            for id in existing_refs.abort_on_err() {
+                leaves.remove(&id);
+            }
+
+            // create refs for remaining leaves
+            for leaf in leaves {
+                issue.add_leaf(leaf).unwrap_or_abort();
+            }
+        }
+    }
+}
+
+
+/// moderate subcommand implementation
+///
+fn moderate_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let mut moderation = repo.moderation().unwrap_or_abort();
+
+    if matches.is_present("list") {
+        // we only list the moderation data
+        moderation
+            .blocked_authors()
+            .map(|author| format!("blocked {}", author))
+            .chain(moderation.hidden_messages().map(|id| format!("hidden {}", id)))
+            .print_lines()
+            .unwrap_or_abort();
+        return;
+    }
+
+    let mut changed = false;
+    for message in matches.values_of("hide").into_iter().flat_map(|values| values) {
+        changed |= moderation.hide_message(repo.value_to_commit(message).id());
+    }
+    for message in matches.values_of("unhide").into_iter().flat_map(|values| values) {
+        changed |= moderation.unhide_message(repo.value_to_commit(message).id());
+    }
+    for author in matches.values_of("block").into_iter().flat_map(|values| values) {
+        changed |= moderation.block_author(author);
+    }
+    for author in matches.values_of("unblock").into_iter().flat_map(|values| values) {
+        changed |= moderation.unblock_author(author);
+    }
+
+    if !changed {
+        warn!("{}", tr!("No commit was created because the moderation data did not change."));
+        return;
+    }
+
+    let sig = repo.dit_signature();
+    repo.update_moderation(&moderation, &sig, &sig, "Update moderation data")
+        .unwrap_or_abort();
+}
+
+
+/// new subcommand implementation
+///
+fn new_impl(matches: &clap::ArgMatches) {
+    use util::message_from_args;
+
+    let repo = util::open_dit_repo();
+    let (author, committer) = repo.cli_signatures(matches);
+
+    // get the message, either from the command line argument or an editor
+    let message = if let Some(m) = message_from_args(matches) {
+        // the message was supplied via the command line
+        m.into_iter()
+         .chain(repo.prepare_trailers(matches)
+                    .into_iter()
+                    .map(|t| t.to_string()))
+         .collect()
+    } else {
+        // we need an editor
+
+        // get the path where we want to edit the message
+        let path = repo.commitmsg_edit_path(matches);
+
+        { // write the template, followed by the trailers to add
+            let mut file = File::create(path.as_path()).unwrap_or_abort();
+            repo.issue_template(matches)
+                .into_iter()
+                .chain(Some(String::new()))
+                .chain(repo.prepare_trailers(matches).into_iter().map(|t| t.to_string()))
+                .write_lines(&mut file)
+                .unwrap_or_abort();
+            file.flush().unwrap_or_abort();
+        }
+
+        repo.get_commit_msg(path)
+    }.into_iter().collect_string();
+    let message = repo.complete_trailers(message);
+    let message = repo.pre_message_hook(matches, message, None);
+    repo.lint_message(&message);
+
+    let message = if matches.is_present("encrypt") {
+        repo.encrypt_message(message.trim())
+    } else {
+        message
+    };
+
+    // commit the message
+    let tree = repo.empty_tree().unwrap_or_abort();
+    let id = repo
+        .create_issue_in_component(repo.cli_component(matches).as_ref().map(String::as_str),
+                                   &author,
+                                   &committer,
+                                   message.trim(),
+                                   &tree,
+                                   Vec::new())
+        .unwrap_or_abort();
+    repo.mail_message(id.id(), &id.initial_message().unwrap_or_abort());
+    println!("[dit][new] {}", id);
+    repo.post_issue_hook(id.id());
+}
+
+
+/// nntp subcommand implementation
+///
+fn nntp_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let posting = if matches.is_present("no-posting") {
+        None
+    } else {
+        Some(nntp::Posting {
+            committer: repo.dit_signature(),
+            filter: repo.message_filter(),
+            policy: repo.head_policy(matches),
+        })
+    };
+
+    // NOTE: the address and group have default values
+    let address = matches.value_of("address").unwrap();
+    let group = matches.value_of("group").unwrap();
+    println!("Serving issues as {} on nntp://{}/", group, address);
+    nntp::serve(&repo, address, group, posting.as_ref()).unwrap_or_abort();
+}
+
+
+/// protect subcommand implementation
+///
+fn protect_impl(matches: &clap::ArgMatches) {
+    use libgitdit::gc::{protected_patterns, update_protected_patterns};
 
-/// push subcommand implementation
-///
-fn push_impl(matches: &clap::ArgMatches) {
     let repo = util::open_dit_repo();
+    let mut patterns = protected_patterns(&repo).unwrap_or_abort();
+
+    if matches.is_present("list") {
+        patterns.into_iter().print_lines().unwrap_or_abort();
+        return;
+    }
+
+    let previous = patterns.clone();
+    for pattern in matches.values_of("pattern").into_iter().flatten() {
+        if matches.is_present("remove") {
+            patterns.retain(|p| p != pattern);
+        } else if !patterns.iter().any(|p| p == pattern) {
+            patterns.push(pattern.to_owned());
+        }
+    }
+
+    if patterns == previous {
+        warn!("{}", tr!("No commit was created because the protected refs did not change."));
+        return;
+    }
+
+    let sig = repo.dit_signature();
+    update_protected_patterns(&repo, patterns, &sig, &sig, "Update protected refs")
+        .unwrap_or_abort();
+}
+
+
+/// push subcommand implementation
+///
+fn push_impl(matches: &clap::ArgMatches) {
+    use libgitdit::remote::RefSnapshot;
 
-    // note: "remote" is always present since it is a required parameter
-    let mut remote = repo.find_remote(matches.value_of("remote").unwrap()).unwrap_or_abort();
+    let repo = util::open_dit_repo();
 
     // accumulate the refspecs to push
     let refspecs : Vec<String> = repo
@@ -497,38 +1649,136 @@ fn push_impl(matches: &clap::ArgMatches) {
             names
         })
         .collect();
+    let refspec_refs : Vec<&str> = refspecs.iter().map(String::as_str).collect();
 
-    // set the options for the push
-    let mut fetch_options = git2::PushOptions::new();
-    fetch_options.remote_callbacks(gitext::callbacks());
+    for name in repo.cli_remotes(matches) {
+        // The remote-tracking references are updated according to the fetch
+        // refspecs, which allows us to summarize what was pushed.
+        repo.setup_remote(&name);
+        let mut remote = repo.find_remote(&name).unwrap_or_abort();
 
-    let refspec_refs : Vec<&str> = refspecs.iter().map(String::as_str).collect();
-    remote.push(refspec_refs.as_ref(), Some(&mut fetch_options))
-          .unwrap_or_abort();
+        // set the options for the push
+        let mut push_options = git2::PushOptions::new();
+        push_options.remote_callbacks(gitext::callbacks());
+
+        let before = RefSnapshot::of_remote(&repo, &name).unwrap_or_abort();
+        remote.push(refspec_refs.as_ref(), Some(&mut push_options))
+              .unwrap_or_abort();
+        let after = RefSnapshot::of_remote(&repo, &name).unwrap_or_abort();
+
+        util::change_summary(&name, before.changes(&after))
+            .into_iter()
+            .print_lines()
+            .unwrap_or_abort();
+    }
+}
+
+
+/// quarantine subcommand implementation
+///
+fn quarantine_impl(matches: &clap::ArgMatches) {
+    use libgitdit::quarantine::{quarantined_refs, release_ref};
+
+    let repo = util::open_dit_repo();
+
+    let release : Vec<&str> = matches.values_of("release").into_iter().flat_map(|v| v).collect();
+    let drop : Vec<&str> = matches.values_of("drop").into_iter().flat_map(|v| v).collect();
+    if release.is_empty() && drop.is_empty() {
+        // we only list the quarantined references
+        quarantined_refs(&repo)
+            .unwrap_or_abort()
+            .abort_on_err()
+            .filter_map(|r| r.name().map(String::from))
+            .print_lines()
+            .unwrap_or_abort();
+        return;
+    }
+
+    for name in release {
+        release_ref(&repo, &mut repo.find_reference(name).unwrap_or_abort())
+            .unwrap_or_abort();
+    }
+    for name in drop {
+        repo.find_reference(name)
+            .and_then(|mut r| r.delete())
+            .unwrap_or_abort();
+    }
+}
+
+
+/// reopen subcommand implementation
+///
+fn reopen_impl(matches: &clap::ArgMatches) {
+    set_status(matches, "open")
+}
+
+
+/// repair subcommand implementation
+///
+fn repair_impl(matches: &clap::ArgMatches) {
+    use libgitdit::fsck::{check, Severity};
+
+    use error::LoggableError;
+
+    let repo = util::open_dit_repo();
+    let dry_run = matches.is_present("dry-run");
+
+    let mut repaired = Vec::new();
+    for problem in check(&repo).unwrap_or_abort() {
+        let repair = match problem.repair(&repo).unwrap_or_abort() {
+            Some(repair) => repair,
+            None => {
+                if problem.severity() == Severity::Error {
+                    warn!("{}", tr!("Cannot repair {}", problem));
+                }
+                continue;
+            },
+        };
+        if !dry_run {
+            if let Err(err) = repair.apply(&repo) {
+                error!("Could not {}", repair);
+                err.log();
+                continue;
+            }
+        }
+        repaired.push(repair.to_string());
+    }
+
+    repaired
+        .into_iter()
+        .print_lines()
+        .unwrap_or_abort();
 }
 
 
 /// reply subcommand implementation
 ///
 fn reply_impl(matches: &clap::ArgMatches) {
+    use chrono::{FixedOffset, TimeZone};
+    use libgitdit::message::block::Block;
+    use libgitdit::message::crypt;
+    use libgitdit::transaction::RefTransaction;
     use util::message_from_args;
 
     let repo = util::open_dit_repo();
-    let author = repo.cli_author(matches);
-    let committer = repo.signature().unwrap_or_abort();
+    let (author, committer) = repo.cli_signatures(matches);
 
     // NOTE: We want to do a lot of stuff early, because we want to report
     //       errors before a user spent time writing a commit message in her
     //       editor. This means that we have a lot of bindings which may not
     //       be neccessary otherwise, resulting in data lying around.
 
-    // the unwrap is safe since `parent` is a required value
-    // and get all the info from it that we might need
-    let mut parent = repo.value_to_commit(matches.value_of("parent").unwrap());
+    // get all the info from the parent that we might need, replying to the
+    // current issue's head if no parent was given
+    let mut parent = match matches.value_of("parent") {
+        Some(parent) => repo.value_to_commit(parent),
+        None => repo.issue_head(&repo.cli_issue_or_current(matches)),
+    };
 
     // extract the subject and tree from the parent
     let subject = parent.reply_subject();
     let tree = parent.tree().unwrap_or_abort();
+    let encrypted_parent = crypt::is_encrypted(parent.message().unwrap_or("").lines());
 
     // figure out to what issue we reply
     let issue = repo.issue_with_message(&parent).unwrap_or_abort();
@@ -536,11 +1786,13 @@ fn reply_impl(matches: &clap::ArgMatches) {
     // get the references specified on the command line
     let references = repo.cli_references(matches);
 
+    let head_policy = repo.head_policy(matches);
+
     // get the message, either from the command line argument or an editor
     let message = if let Some(m) = message_from_args(matches) {
         // the message was supplied via the command line
         if matches.is_present("quote") {
-            warn!("Message will only quoted if an editor is used.");
+            warn!("{}", tr!("Message will only be quoted if an editor is used."));
         }
 
         m.into_iter()
@@ -549,43 +1801,288 @@ fn reply_impl(matches: &clap::ArgMatches) {
                     .map(|t| t.to_string()))
          .collect()
     } else {
-        // we need an editor
+        // we need an editor
+
+        // get the path where we want to edit the message
+        let path = repo.commitmsg_edit_path(matches);
+
+        { // write
+            let mut file = File::create(path.as_path()).unwrap_or_abort();
+            if let Some(s) = subject {
+                write!(&mut file, "{}\n\n", s).unwrap_or_abort();
+            }
+
+            // only quote the text, since quoted trailers are of no use
+            let text: Vec<String> = parent
+                .body_blocks()
+                .filter_map(|block| match block {
+                    Block::Text(lines) => Some(lines),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(&String::new());
+
+            if repo.quote_parent(matches) && !text.is_empty() {
+                // attribute the quote to the parent's author
+                let time = parent.time();
+                let date = FixedOffset::east(time.offset_minutes() * 60)
+                    .timestamp(time.seconds(), 0)
+                    .format("%a, %d %b %Y %H:%M:%S %z");
+                write!(&mut file, "On {}, {} wrote:\n", date, parent.author()).unwrap_or_abort();
+
+                text.into_iter()
+                    .quoted()
+                    .write_lines(&mut file)
+                    .unwrap_or_abort();
+                write!(&mut file, "\n").unwrap_or_abort();
+            }
+
+            repo.prepare_trailers(matches)
+                .write_lines(&mut file)
+                .unwrap_or_abort();
+            file.flush().unwrap_or_abort();
+        }
+
+        repo.get_commit_msg(path)
+    }.into_iter().collect_string();
+    let message = repo.pre_message_hook(matches, message, Some(issue.id()));
+    repo.lint_message(&message);
+
+    // replies to encrypted messages should not leak their content
+    let message = if matches.is_present("encrypt") || encrypted_parent {
+        repo.encrypt_message(message.trim())
+    } else {
+        message
+    };
+
+    // construct a vector holding all parents
+    let parent_refs : Vec<&Commit> = Some(&parent).into_iter().chain(references.iter()).collect();
+
+    // finally, create the message
+    let message = repo
+        .commit(None, &author, &committer, message.trim(), &tree, &parent_refs)
+        .and_then(|id| repo.find_commit(id))
+        .unwrap_or_abort();
+
+    // the leaf and the head are updated together
+    let advance = issue.head_advances(&message, head_policy).unwrap_or_abort();
+    let mut transaction = RefTransaction::new(&repo).unwrap_or_abort();
+    issue.stage_leaf(&mut transaction, message.id()).unwrap_or_abort();
+    if advance {
+        issue.stage_head(&mut transaction, message.id()).unwrap_or_abort();
+    }
+    transaction.commit().unwrap_or_abort();
+    repo.mail_message(issue.id(), &message);
+
+    if advance {
+        info!("Advanced head of issue {} to {}", issue.id(), message.id());
+    }
+}
+
+/// search subcommand implementation
+///
+fn search_impl(matches: &clap::ArgMatches) {
+    use libgitdit::search::{search_issue, Granularity, Query, Scope};
+
+    use json::Format;
+
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+    let palette = repo.palette();
+    let moderation = repo.moderation().unwrap_or_abort();
+    let show_hidden = matches.is_present("show-hidden");
+    let issues_only = matches.is_present("issues-only");
+
+    // NOTE: the pattern is a required parameter
+    let pattern = matches.value_of("pattern").unwrap();
+    let mut query = if matches.is_present("regex") {
+        Query::regex(pattern).unwrap_or_abort()
+    } else {
+        Query::new(pattern)
+    };
+    if matches.is_present("ignore-case") {
+        query = query.case_sensitive(false);
+    }
+    if matches.is_present("subject-only") {
+        query = query.scope(Scope::Subject);
+    } else if matches.is_present("body-only") {
+        query = query.scope(Scope::Body);
+    }
+    if issues_only {
+        query = query.granularity(Granularity::Issue);
+    }
+
+    let abbreviated = |id: git2::Oid| {
+        let mut id = id.to_string();
+        id.truncate(id_len);
+        palette.paint(palette.id(), &id)
+    };
+
+    let candidates = search_candidates(&repo, &query);
+    let json = Format::from_matches(matches) == Format::Json;
+    let mut lines = Vec::new();
+    let mut results = Vec::new();
+    for issue in filtered_issues(&repo, matches, matches.is_present("all")) {
+        let messages = match candidates {
+            Some(ref candidates) => match candidates.get(&issue.id()) {
+                Some(messages) => Some(messages),
+                None => continue,
+            },
+            None => None,
+        };
+        let searched = |message: &git2::Commit| {
+            (show_hidden || !moderation.is_hidden(message)) &&
+                messages.map(|messages| messages.contains(&message.id())).unwrap_or(true)
+        };
+        let hit = search_issue(issue, &query, searched).unwrap_or_abort();
+        let hit = match hit {
+            Some(hit) => hit,
+            None => continue,
+        };
+        let initial = hit.issue.initial_message().unwrap_or_abort();
+
+        // the matching lines of all messages, oldest first
+        let snippets = hit
+            .messages
+            .into_iter()
+            .flat_map(|message| {
+                let id = message.message;
+                message.snippets.into_iter().map(move |snippet| (id, snippet.text))
+            });
+
+        if json {
+            let snippets: Vec<_> = snippets
+                .map(|(id, line)| json!({"message": id.to_string(), "line": line}))
+                .collect();
+            results.push(json!({"issue": json::issue(&repo, &hit.issue, &initial), "matches": snippets}));
+        } else {
+            lines.push(format!("{} {}", abbreviated(hit.issue.id()), initial.summary().unwrap_or("")));
+            if !issues_only {
+                lines.extend(snippets.map(|(id, line)| format!("    {}: {}", abbreviated(id), line)));
+            }
+        }
+    }
+
+    if json {
+        json::print(&json!(results)).unwrap_or_abort();
+        return;
+    }
+
+    let result = lines
+        .into_iter()
+        .pipe_lines(repo.pager())
+        .unwrap_or_abort();
+    std::process::exit(result);
+}
+
+
+/// Get the candidates for matches of a query from the search index
+///
+/// The index is created if `dit.search.index` is set. Returns `None` if there
+/// is no index or it cannot serve the query, in which case all messages are to
+/// be searched.
+///
+#[cfg(feature = "search-index")]
+fn search_candidates(repo: &git2::Repository, query: &libgitdit::search::Query) -> Option<libgitdit::search::Candidates> {
+    use libgitdit::search_index::SearchIndex;
+
+    if repo.dit_config().search_index().unwrap_or_abort().unwrap_or(false) {
+        if let Err(err) = SearchIndex::create(repo) {
+            warn!("{}", tr!("Could not use the search index: {}", err));
+            return None;
+        }
+    }
+    SearchIndex::lookup(repo, query)
+        .map_err(|err| warn!("{}", tr!("Could not use the search index: {}", err)))
+        .unwrap_or(None)
+}
+
+#[cfg(not(feature = "search-index"))]
+fn search_candidates(_: &git2::Repository, _: &libgitdit::search::Query) -> Option<libgitdit::search::Candidates> {
+    None
+}
+
+
+/// Create a message setting the status of an issue
+///
+/// The message replies to the issue's selected head and is made the new local
+/// head of the issue.
+///
+fn set_status(matches: &clap::ArgMatches, status: &str) {
+    use libgitdit::trailer::Trailer;
+    use libgitdit::trailer::spec::{ISSUE_STATUS_REASON_SPEC, ISSUE_STATUS_SPEC};
+    use util::message_from_args;
+
+    let repo = util::open_dit_repo();
+
+    let issue = if matches.is_present("from-branch") {
+        repo.branch_issue(matches.value_of("from-branch"))
+    } else {
+        repo.cli_issue_or_current(matches)
+    };
+    let head = repo.issue_head(&issue);
+
+    // Issues without a status are considered open
+    let current = repo
+        .metadata_values(&issue, &head, &ISSUE_STATUS_SPEC)
+        .pop()
+        .unwrap_or_else(|| "open".to_owned());
+    if current == status && !matches.is_present("force") {
+        warn!("{}", tr!("Issue {} is already {}.", issue.id(), status));
+        return;
+    }
 
-        // get the path where we want to edit the message
-        let path = repo.commitmsg_edit_path(matches);
+    let mut trailers = vec![Trailer::new(ISSUE_STATUS_SPEC.key, status)];
+    if let Some(reason) = matches.value_of("reason") {
+        trailers.push(Trailer::new(ISSUE_STATUS_REASON_SPEC.key, reason));
+    }
 
-        { // write
-            let mut file = File::create(path.as_path()).unwrap_or_abort();
-            if let Some(s) = subject {
-                write!(&mut file, "{}\n\n", s).unwrap_or_abort();
-            }
+    let comment = message_from_args(matches).unwrap_or_default();
+    let new = repo.add_metadata_message(matches, &issue, head, comment, trailers);
+    println!("[dit][{}] {}", status, new);
+}
 
-            if matches.is_present("quote") {
-                parent
-                    .body_lines()
-                    .quoted()
-                    .write_lines(&mut file)
-                    .unwrap_or_abort();
-                write!(&mut file, "\n").unwrap_or_abort();
-            }
 
-            repo.prepare_trailers(matches)
-                .write_lines(&mut file)
-                .unwrap_or_abort();
-            file.flush().unwrap_or_abort();
-        }
+/// Create a message changing the assignees of an issue
+///
+/// The assignees are resolved from the command line and either added or
+/// removed. The resulting set of assignees is printed.
+///
+fn set_assignees(matches: &clap::ArgMatches, add: bool) {
+    use libgitdit::trailer::spec::ISSUE_ASSIGNEE_SPEC;
+    use util::message_from_args;
 
-        repo.get_commit_msg(path)
-    }.into_iter().collect_string();
+    let repo = util::open_dit_repo();
 
-    // construct a vector holding all parents
-    let parent_refs = Some(&parent).into_iter().chain(references.iter());
+    // NOTE: the issue is a required parameter
+    let issue = repo.cli_issue(matches).unwrap();
+    let head = repo.issue_head(&issue);
 
-    // finally, create the message
-    issue.add_message(&author, &committer, message.trim(), &tree, parent_refs)
-         .unwrap_or_abort();
+    let prefix = if add { "+" } else { "-" };
+    let changes: Vec<String> = matches
+        .values_of("assignee")
+        .into_iter()
+        .flat_map(|values| values)
+        .map(|assignee| format!("{}{}", prefix, repo.resolve_identity(assignee)))
+        .collect();
+
+    let mut assignees = repo.metadata_values(&issue, &head, &ISSUE_ASSIGNEE_SPEC);
+    let trailers = util::set_change_trailers(
+        ISSUE_ASSIGNEE_SPEC.key,
+        &mut assignees,
+        changes.iter().map(String::as_str)
+    );
+
+    if trailers.is_empty() {
+        warn!("{}", tr!("No commit was created because the assignees would not change."));
+    } else {
+        let comment = message_from_args(matches).unwrap_or_default();
+        repo.add_metadata_message(matches, &issue, head, comment, trailers);
+    }
+    println!("Assignees: {}", assignees.join(", "));
 }
 
+
 /// show subcommand implementation
 ///
 fn show_impl(matches: &clap::ArgMatches) {
@@ -594,19 +2091,66 @@ fn show_impl(matches: &clap::ArgMatches) {
     use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
     use display::{IntoTreeGraph, TreeGraphElem, TreeGraphElemLine};
     use gitext::ReferrencesExt;
+    use json::Format;
 
     let repo = util::open_dit_repo();
     let id_len = repo.abbreviation_length(matches);
+    let palette = repo.palette();
     let prios = repo.remote_priorization();
 
-    // NOTE: the issue is a required parameter
-    let issue = repo.cli_issue(matches).unwrap();
+    let issue = repo.cli_issue_or_current(matches);
+
+    let moderation = repo.moderation().unwrap_or_abort();
+    let show_hidden = matches.is_present("show-hidden");
+
+    if Format::from_matches(matches) == Format::Json {
+        let initial = issue.initial_message().unwrap_or_abort();
+
+        let messages: Vec<_> = if matches.is_present("initial") {
+            vec![initial.clone()]
+        } else {
+            issue.messages().abort_on_err().collect()
+        };
+        let hidden = |message: &Commit| !show_hidden && moderation.is_hidden(message);
+        let value = json::issue_with_messages(&repo, &issue, &initial, messages, &hidden);
+        json::print(&value).unwrap_or_abort();
+        return;
+    }
+
+    if matches.is_present("threaded") {
+        use display::thread_lines;
+
+        let head = issue
+            .heads()
+            .abort_on_err()
+            .select_ref(&prios)
+            .unwrap() // TODO: abort gracefully
+            .target()
+            .unwrap(); // TODO: abort gracefully
+        let max_quoted = if matches.is_present("no-fold-quotes") {
+            std::usize::MAX
+        } else {
+            3
+        };
+
+        let hidden = |message: &Commit| !show_hidden && moderation.is_hidden(message);
+        let lines = thread_lines(&issue, head, id_len, &hidden, max_quoted, &palette)
+            .unwrap_or_abort();
+
+        let result = lines
+            .into_iter()
+            .pipe_lines(repo.pager())
+            .unwrap_or_abort();
+        std::process::exit(result);
+    }
 
     // translate commit to lines representing the commit
     let formatter : Vec<FT<_,_>> = if matches.is_present("msgtree") {
         // With the "tree" option, we only display subjects in a short
         // format
-        tokenvec![MFT::Id(id_len), " ", MFT::Author, " ", MFT::Subject]
+        tokenvec![
+            palette.id(), MFT::Id(id_len), palette.reset(), " ",
+            palette.author(), MFT::Author, palette.reset(), " ", MFT::Subject]
     } else {
         let head = issue
             .heads()
@@ -617,8 +2161,8 @@ fn show_impl(matches: &clap::ArgMatches) {
             .unwrap(); // TODO: abort gracefully
 
         tokenvec![
-            MFT::Id(id_len), MFT::IfId(head, tokenvec![" (head)"]), FT::LineEnd,
-            "Author: ", MFT::Author, FT::LineEnd,
+            palette.id(), MFT::Id(id_len), MFT::IfId(head, tokenvec![" (head)"]), palette.reset(), FT::LineEnd,
+            "Author: ", palette.author(), MFT::Author, palette.reset(), FT::LineEnd,
             "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd,
             FT::LineEnd,
             MFT::Subject, FT::LineEnd,
@@ -628,6 +2172,14 @@ fn show_impl(matches: &clap::ArgMatches) {
             FT::LineEnd]
     };
 
+    // Hidden messages are replaced by a placeholder in order to preserve the
+    // structure of the tree.
+    let hidden_formatter : Vec<FT<_,_>> = if matches.is_present("msgtree") {
+        tokenvec![palette.id(), MFT::Id(id_len), palette.reset(), " [hidden]"]
+    } else {
+        tokenvec![palette.id(), MFT::Id(id_len), palette.reset(), " [hidden]", FT::LineEnd, FT::LineEnd]
+    };
+
     // first, get us an iterator over all the commits
     let mut commits : Vec<(TreeGraphElemLine, Commit)> =
         if matches.is_present("initial") {
@@ -665,10 +2217,14 @@ fn show_impl(matches: &clap::ArgMatches) {
             (elems.commit_iterator(), commit.1)
         })
         // expand the message to a series of lines
-        .flat_map(|commit| commit
-            .0
-            .zip(formatter.iter().formatted_lines(commit.1).abort_on_err())
-        )
+        .flat_map(|commit| {
+            let formatter = if !show_hidden && moderation.is_hidden(&commit.1) {
+                &hidden_formatter
+            } else {
+                &formatter
+            };
+            commit.0.zip(formatter.iter().formatted_lines(commit.1).abort_on_err())
+        })
         // combine each line of graph elements and message
         .map(|line| format!("{} {}", line.0, line.1))
         .pipe_lines(repo.pager())
@@ -677,17 +2233,348 @@ fn show_impl(matches: &clap::ArgMatches) {
     std::process::exit(result);
 }
 
+/// start subcommand implementation
+///
+fn start_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let issue = repo.cli_issue_or_current(matches);
+    let initial = issue.initial_message().unwrap_or_abort();
+
+    // derive a name from the issue's id and subject, e.g.
+    // "issue/1a2b3c4-crash-on-startup"
+    let name = matches.value_of("name").map(String::from).unwrap_or_else(|| {
+        let words: Vec<_> = initial
+            .summary()
+            .unwrap_or("")
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .take(6)
+            .map(str::to_lowercase)
+            .collect();
+        let mut name = format!("issue/{:.7}", issue.id());
+        if !words.is_empty() {
+            name.push('-');
+            name.push_str(&words.join("-"));
+        }
+        name
+    });
+
+    let target = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .unwrap_or_abort();
+    let branch = repo.branch(&name, &target, false).unwrap_or_abort();
+
+    // the link is used by "close --from-branch"
+    repo.config()
+        .and_then(|mut config| config.set_str(&format!("branch.{}.dit-issue", name), &issue.id().to_string()))
+        .unwrap_or_abort();
+
+    if matches.is_present("no-checkout") {
+        println!("Created branch '{}' for issue {}", name, issue.id());
+    } else {
+        // The branch refers to the commit checked out, so we only need to
+        // update HEAD.
+        repo.set_head(branch.get().name().unwrap_or_default()).unwrap_or_abort();
+        println!("Switched to a new branch '{}' for issue {}", name, issue.id());
+    }
+}
+
+
+/// stale subcommand implementation
+///
+fn stale_impl(matches: &clap::ArgMatches) {
+    use chrono::{Local, TimeZone};
+    use std::collections::HashMap;
+    use libgitdit::gc::parse_age;
+    use libgitdit::listing::Staleness;
+
+    use display::{MessageFmtToken as MFT, LineFormatter};
+    use index::Index;
+    use json::Format;
+
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+    let palette = repo.palette();
+
+    let mut staleness = Staleness::with_config(&repo.dit_config()).unwrap_or_abort();
+    if let Some(age) = matches.value_of("older-than") {
+        staleness = staleness.age(parse_age(age).unwrap_or_abort());
+    }
+    for tag in matches.values_of("exclude-tag").into_iter().flat_map(|values| values) {
+        staleness = staleness.exclude_tag(tag);
+    }
+
+    let issues = filtered_issues(&repo, matches, matches.is_present("all"));
+
+    // the time of the last activity is cached in the index
+    let mut index = Index::open(&repo).unwrap_or_abort();
+    let activity: HashMap<_, _> = issues
+        .iter()
+        .map(libgitdit::Issue::id)
+        .zip(index.last_activities(&issues).unwrap_or_abort())
+        .collect();
+    if let Err(err) = index.save() {
+        warn!("{}", tr!("Could not update the issue index: {}", err));
+    }
+
+    let stale = staleness
+        .stale_issues(issues,
+                      Local::now().timestamp(),
+                      |issue| Ok(repo.issue_head(issue).id()),
+                      |issue| Ok(activity.get(&issue.id()).and_then(|&time| time)))
+        .unwrap_or_abort();
+
+    if Format::from_matches(matches) == Format::Json {
+        let list: Vec<_> = stale
+            .iter()
+            .map(|stale| {
+                let mut value = json::issue(&repo, &stale.issue, &stale.issue.initial_message().unwrap_or_abort());
+                value["last_activity"] = json!(json::date(git2::Time::new(stale.last_activity, 0)));
+                value
+            })
+            .collect();
+        json::print(&json!(list)).unwrap_or_abort();
+        return;
+    }
+
+    // the date presented is the one of the last activity, not of the message
+    let mut lines = Vec::new();
+    for stale in &stale {
+        let date = Local.timestamp(stale.last_activity, 0).format("%c").to_string();
+        let formatter = tokenvec![
+            palette.id(), MFT::Id(id_len), palette.reset(), " (", date, ") ", MFT::Subject];
+        lines.extend(formatter.iter().formatted_lines(stale.issue.initial_message().unwrap_or_abort()).abort_on_err());
+    }
+    let result = lines
+        .into_iter()
+        .pipe_lines(repo.pager())
+        .unwrap_or_abort();
+    std::process::exit(result);
+}
+
+
+/// stats subcommand implementation
+///
+fn stats_impl(matches: &clap::ArgMatches) {
+    use chrono::{Local, TimeZone};
+    use libgitdit::blame::blame;
+    use libgitdit::event::{self, EventKind};
+    use libgitdit::trailer::spec::ISSUE_STATUS_SPEC;
+    use std::collections::{BTreeMap, HashMap};
+
+    use index::Index;
+    use json::Format;
+
+    const WEEK: i64 = 7 * 24 * 60 * 60;
+
+    let repo = util::open_dit_repo();
+    let weeks: i64 = matches
+        .value_of("weeks")
+        .map(|weeks| str::parse(weeks).unwrap_or_abort())
+        .unwrap_or(4);
+    let issues = filtered_issues(&repo, matches, true);
+
+    // counts of issues in total and of open issues
+    let mut total = (0, 0);
+    let mut tags: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let mut assignees: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let mut participants: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let mut durations = Vec::new();
+    // metadata is looked up in the index, durations are computed for closed
+    // issues only
+    let mut index = Index::open(&repo).unwrap_or_abort();
+    let metadata = index.metadata(&issues).unwrap_or_abort();
+    if let Err(err) = index.save() {
+        warn!("{}", tr!("Could not update the issue index: {}", err));
+    }
+    let closing_heads: HashMap<_, _> = issues
+        .iter()
+        .zip(metadata.iter())
+        .filter(|&(_, &(_, ref metadata))| metadata.status == "closed")
+        .map(|(issue, &(head, _))| (issue.id(), head))
+        .collect();
+    let durations_of = parallel::map(&repo, &issues, |_, issue| {
+        // the time to close is determined by the message closing the issue
+        closing_heads.get(&issue.id()).and_then(|head| {
+            let messages = issue.messages_from(*head).unwrap_or_abort();
+            let opened = issue.initial_message().unwrap_or_abort().time().seconds();
+            blame(messages, &[ISSUE_STATUS_SPEC])
+                .unwrap_or_abort()
+                .first()
+                .map(|closing| closing.message().time().seconds() - opened)
+        })
+    });
+    // participants are resolved via the mailmap
+    let participants_of = parallel::map(&repo, &issues, |_, issue| issue
+        .participants()
+        .unwrap_or_abort()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+    );
+    let per_issue = metadata.into_iter().zip(durations_of).zip(participants_of);
+    for (((_, metadata), duration), issue_participants) in per_issue {
+        let open = if metadata.status != "closed" { 1 } else { 0 };
+
+        total.0 += 1;
+        total.1 += open;
+        for tag in metadata.tags {
+            let entry = tags.entry(tag).or_insert((0, 0));
+            entry.0 += 1;
+            entry.1 += open;
+        }
+        for assignee in metadata.assignees {
+            let entry = assignees.entry(assignee).or_insert((0, 0));
+            entry.0 += 1;
+            entry.1 += open;
+        }
+        for participant in issue_participants {
+            let entry = participants.entry(participant).or_insert((0, 0));
+            entry.0 += 1;
+            entry.1 += open;
+        }
+        durations.extend(duration);
+    }
+
+    durations.sort();
+    let median = match durations.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((durations[n / 2 - 1] + durations[n / 2]) / 2),
+        n => Some(durations[n / 2]),
+    };
+
+    // activity in the last few weeks, most recent week first
+    let now = Local::now().timestamp();
+    let mut activity = vec![(0, 0, 0); weeks.max(0) as usize];
+    for event in event::events(issues).unwrap_or_abort() {
+        let week = (now - event.time().seconds()) / WEEK;
+        if week < 0 || week >= weeks {
+            continue;
+        }
+        let counts = &mut activity[week as usize];
+        match event.kind() {
+            &EventKind::NewIssue => counts.0 += 1,
+            &EventKind::Reply => counts.1 += 1,
+            &EventKind::StatusChange(_) => counts.2 += 1,
+        }
+    }
+    let week_start = |week: usize| Local
+        .timestamp(now - (week as i64 + 1) * WEEK, 0)
+        .format("%F")
+        .to_string();
+
+    if Format::from_matches(matches) == Format::Json {
+        let breakdown = |map: BTreeMap<String, (usize, usize)>| map
+            .into_iter()
+            .map(|(key, (total, open))| (key, json!({"total": total, "open": open})))
+            .collect::<serde_json::Map<_, _>>();
+        let activity: Vec<_> = activity
+            .into_iter()
+            .enumerate()
+            .map(|(week, (new, replies, changes))| json!({
+                "week": week_start(week),
+                "new": new,
+                "replies": replies,
+                "status_changes": changes,
+            }))
+            .collect();
+        json::print(&json!({
+            "issues": {
+                "total": total.0,
+                "open": total.1,
+                "closed": total.0 - total.1,
+            },
+            "median_time_to_close": median,
+            "tags": breakdown(tags),
+            "assignees": breakdown(assignees),
+            "participants": breakdown(participants),
+            "activity": activity,
+        })).unwrap_or_abort();
+        return;
+    }
+
+    let duration = |seconds: i64| {
+        let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+        match (days, hours) {
+            (0, 0) => format!("{} minutes", minutes),
+            (0, _) => format!("{} hours {} minutes", hours, minutes),
+            _      => format!("{} days {} hours", days, hours),
+        }
+    };
+
+    let mut lines = vec![
+        format!("Issues: {} ({} open, {} closed)", total.0, total.1, total.0 - total.1),
+        format!("Median time to close: {}", median.map(&duration).unwrap_or_else(|| "-".to_owned())),
+    ];
+    for (title, map) in vec![("Tags", tags), ("Assignees", assignees), ("Participants", participants)] {
+        if map.is_empty() {
+            continue;
+        }
+        lines.push(String::new());
+        lines.push(format!("{}:", title));
+        lines.extend(map
+            .into_iter()
+            .map(|(key, (total, open))| format!("    {:<30} {:>5} ({} open)", key, total, open)));
+    }
+    if !activity.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("Activity in the last {} weeks:", weeks));
+        lines.extend(activity
+            .into_iter()
+            .enumerate()
+            .map(|(week, (new, replies, changes))| format!(
+                "    {}  {} new, {} replies, {} status changes",
+                week_start(week), new, replies, changes
+            )));
+    }
+
+    let result = lines
+        .into_iter()
+        .pipe_lines(repo.pager())
+        .unwrap_or_abort();
+    std::process::exit(result);
+}
+
+
+/// sync subcommand implementation
+///
+fn sync_impl(matches: &clap::ArgMatches) {
+    use forge::{Forge, Synced};
+
+    let repo = util::open_dit_repo();
+    let moderation = repo.moderation().unwrap_or_abort();
+    let config = repo.config().and_then(|mut config| config.snapshot()).unwrap_or_abort();
+    let (forge, api, repository) = Forge::from_matches(matches).unwrap();
+
+    let result = forge::Client::new(&config, forge, api)
+        .sync(&repo, repository, &|message| moderation.is_hidden(message), repo.head_policy(matches))
+        .unwrap_or_abort();
+    result
+        .value
+        .into_iter()
+        .map(|synced| match synced {
+            Synced::Imported(foreign, id) => format!("imported {} {}", foreign, id),
+            Synced::Exported(id, foreign) => format!("exported {} {}", id, foreign),
+        })
+        .print_lines()
+        .unwrap_or_abort();
+    abort_on_failures(&result.failures);
+}
+
+
 /// tag subcommand implementation
 ///
 fn tag_impl(matches: &clap::ArgMatches) {
     use libgitdit::trailer::Trailer;
+    use libgitdit::trailer::spec::ISSUE_TAG_SPEC;
     use std::str::FromStr;
 
     use gitext::ReferrencesExt;
 
     let repo = util::open_dit_repo();
     let author = repo.cli_author(matches);
-    let committer = repo.signature().unwrap_or_abort();
+    let committer = repo.dit_signature();
     let prios = repo.remote_priorization();
 
     // get the head for the issue to tag
@@ -719,14 +2606,25 @@ fn tag_impl(matches: &clap::ArgMatches) {
 
     // get references and trailers for the new commit
     let references = repo.cli_references(matches);
-    let trailers : Vec<Trailer> = matches.values_of("set-status")
-                                         .into_iter()
-                                         .flat_map(|values| values)
-                                         .map(Trailer::from_str)
-                                         .abort_on_err()
-                                         .collect();
+    let mut trailers : Vec<Trailer> = matches.values_of("set-status")
+                                             .into_iter()
+                                             .flat_map(|values| values)
+                                             .map(Trailer::from_str)
+                                             .abort_on_err()
+                                             .collect();
+
+    // translate tag changes into trailers, omitting those without effect
+    let mut tags = repo.metadata_values(&issue, &head_commit, &ISSUE_TAG_SPEC);
+    let tag_changes = util::set_change_trailers(
+        ISSUE_TAG_SPEC.key,
+        &mut tags,
+        matches.values_of("tags").into_iter().flat_map(|values| values)
+    );
+    let tags_changed = !tag_changes.is_empty();
+    trailers.extend(tag_changes);
+
     if references.is_empty() && trailers.is_empty() {
-        warn!("No commit was created because no reference or tags were supplied.");
+        warn!("{}", tr!("No commit was created because no reference or tags were supplied."));
         return;
     }
 
@@ -744,11 +2642,207 @@ fn tag_impl(matches: &clap::ArgMatches) {
 
     // update the head reference
     issue.update_head(new, true).unwrap_or_abort();
+    repo.mail_message(issue.id(), &repo.find_commit(new).unwrap_or_abort());
+
+    if tags_changed {
+        println!("Tags: {}", tags.join(", "));
+    }
+}
+
+
+/// templates subcommand implementation
+///
+fn templates_impl(matches: &clap::ArgMatches) {
+    use error::Error;
+    use error::ErrorKind as EK;
+    use error::ResultExt;
+    use libgitdit::template::{templates, update_templates};
+
+    let repo = util::open_dit_repo();
+    let mut shipped = templates(&repo).unwrap_or_abort();
+
+    let name = match matches.value_of("name") {
+        Some(name) => name,
+        None => {
+            // we only list the templates
+            shipped.names().print_lines().unwrap_or_abort();
+            return;
+        },
+    };
+
+    let message = if matches.is_present("remove") {
+        if shipped.remove(name).is_none() {
+            abort(&Error::from_kind(EK::UnknownTemplate(name.to_owned())));
+        }
+        format!("Remove template '{}'", name)
+    } else if let Some(path) = matches.value_of("file") {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .chain_err(|| EK::WrappedIOError)
+            .unwrap_or_abort();
+        if shipped.get(name) == Some(text.as_str()) {
+            warn!("{}", tr!("No commit was created because the template did not change."));
+            return;
+        }
+        shipped.insert(name, text).unwrap_or_abort();
+        format!("Update template '{}'", name)
+    } else {
+        // we only show the template
+        match shipped.get(name) {
+            Some(text) => print!("{}", text),
+            None => abort(&Error::from_kind(EK::UnknownTemplate(name.to_owned()))),
+        }
+        return;
+    };
+
+    let sig = repo.dit_signature();
+    update_templates(&repo, &shipped, &sig, &sig, &message).unwrap_or_abort();
+}
+
+
+/// tui subcommand implementation
+///
+#[cfg(feature = "tui")]
+fn tui_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+
+    let load = || sorted_issues(&repo, matches, filtered_issues(&repo, matches, matches.is_present("all")));
+    tui::run(&repo, load, id_len).unwrap_or_abort();
+}
+
+/// tui subcommand implementation
+///
+#[cfg(not(feature = "tui"))]
+fn tui_impl(_: &clap::ArgMatches) {
+    error!("{}", tr!("git-dit was built without support for the interactive interface"));
+    std::process::exit(1);
+}
+
+
+/// undo subcommand implementation
+///
+fn undo_impl(matches: &clap::ArgMatches) {
+    use libgitdit::issue::IssueRefType;
+    use libgitdit::message_index::MessageIndex;
+
+    use error::Error;
+    use error::ErrorKind as EK;
+
+    let repo = util::open_dit_repo();
+    let issue = repo.cli_issue_or_current(matches);
+    let dry_run = matches.is_present("dry-run");
+
+    let message = issue
+        .last_created_message()
+        .unwrap_or_abort()
+        .ok_or_else(|| Error::from_kind(EK::NothingToUndo(issue.id())))
+        .unwrap_or_abort();
+    let id = message.id();
+
+    // Messages reachable from a remote reference may already be known to
+    // others, so we must not remove them.
+    let reachable_from = |refs: git2::References| refs
+        .abort_on_err()
+        .filter_map(|reference| reference.target())
+        .any(|target| target == id || repo.graph_descendant_of(target, id).unwrap_or_abort());
+    if reachable_from(issue.remote_refs(IssueRefType::Any).unwrap_or_abort()) {
+        abort(&Error::from_kind(EK::MessagePublished(id)));
+    }
+
+    // The head is only rewound if the message was made the head by git-dit,
+    // according to the reflog. Entries of previous undos are skipped since
+    // they don't tell us anything about the message. The same holds for
+    // entries which don't record an update, e.g. deletions of leaves.
+    let head = issue.local_head().ok().filter(|head| head.target() == Some(id));
+    let previous = if head.is_some() {
+        let previous = issue
+            .head_reflog()
+            .unwrap_or_abort()
+            .iter()
+            .filter(|entry| entry.id_old() != entry.id_new())
+            .filter(|entry| !entry.message().map(|m| m.starts_with("git-dit: undo")).unwrap_or(false))
+            .find(|entry| entry.id_new() == id)
+            .filter(|entry| entry.message().map(|m| m.starts_with("git-dit:")).unwrap_or(false))
+            .map(|entry| entry.id_old())
+            .ok_or_else(|| Error::from_kind(EK::CannotRewindHead(id)))
+            .unwrap_or_abort();
+        Some(previous)
+    } else {
+        None
+    };
+
+    if dry_run {
+        println!("Would remove message {} from issue {}", id, issue.id());
+        if let Some(previous) = previous {
+            println!("Would reset head of issue {} to {}", issue.id(), previous);
+        }
+        return;
+    }
+
+    if let (Some(mut head), Some(previous)) = (head, previous) {
+        if previous.is_zero() {
+            head.delete().unwrap_or_abort();
+        } else {
+            let message = format!("git-dit: undo {}", id);
+            head.set_target(previous, &message).unwrap_or_abort();
+        }
+    }
+
+    let leaf = format!("refs/dit/{}/leaves/{}", issue.ref_part(), id);
+    repo.find_reference(&leaf)
+        .and_then(|mut reference| reference.delete())
+        .unwrap_or_abort();
+
+    // Make sure the messages replied to remain reachable
+    let index = MessageIndex::updated(&repo);
+    let issue_refs = || issue.all_refs(IssueRefType::Any).unwrap_or_abort();
+    for parent in message.parent_ids() {
+        let reachable = issue_refs()
+            .abort_on_err()
+            .filter_map(|reference| reference.target())
+            .any(|target| target == parent || repo.graph_descendant_of(target, parent).unwrap_or_abort());
+        if !reachable && index.issue_with_message(&repo, &repo.find_commit(parent).unwrap_or_abort()).is_ok() {
+            issue.restore_leaf(parent).unwrap_or_abort();
+        }
+    }
+
+    println!("Removed message {} from issue {}", id, issue.id());
+}
+
+
+/// unassign subcommand implementation
+///
+fn unassign_impl(matches: &clap::ArgMatches) {
+    set_assignees(matches, false)
 }
 
 
 // Unknown subcommand handler
 
+/// web subcommand implementation
+///
+#[cfg(feature = "web")]
+fn web_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+
+    // NOTE: the address has a default value
+    let address = matches.value_of("address").unwrap();
+    println!("Serving issues on http://{}/", address);
+    web::serve(&repo, address, id_len).unwrap_or_abort();
+}
+
+/// web subcommand implementation
+///
+#[cfg(not(feature = "web"))]
+fn web_impl(_: &clap::ArgMatches) {
+    error!("{}", tr!("git-dit was built without support for the web interface"));
+    std::process::exit(1);
+}
+
+
 /// Handle unknown subcommands
 ///
 /// Try to invoke an executable matching the name of the subcommand.
@@ -775,10 +2869,38 @@ fn handle_unknown_subcommand(name: &str, matches: &clap::ArgMatches) {
 
 fn main() {
     let yaml    = load_yaml!("cli.yaml");
-    let matches = App::from_yaml(yaml).get_matches();
+    let matches = App::from_yaml(yaml).get_matches_safe().unwrap_or_else(|err| {
+        if !err.use_stderr() {
+            // help and version information
+            err.exit();
+        }
+        writeln!(io::stderr(), "{}", err.message).ok();
+        std::process::exit(Category::Usage.code())
+    });
+
+    i18n::set_locale(i18n::Locale::from_env());
 
     if let Err(err) = system::Logger::init(Level::Warn) {
-        writeln!(io::stderr(), "Could not initialize logger: {}", err).ok();
+        writeln!(io::stderr(), "{}", tr!("Could not initialize logger: {}", err)).ok();
+    }
+
+    #[cfg(feature = "trace")]
+    {
+        if !trace::init() {
+            warn!("{}", tr!("Unknown trace level in {}", trace::TRACE_VAR));
+        }
+    }
+
+    error::set_porcelain(matches.is_present("porcelain"));
+
+    // The pager is selected like git does, so we may disable it the same way
+    if matches.is_present("no-pager") {
+        std::env::set_var("GIT_PAGER", "cat");
+    }
+
+    // The profile is passed on like the pager, so it applies to the whole run
+    if let Some(profile) = matches.value_of("profile") {
+        std::env::set_var(util::PROFILE_VAR, profile);
     }
 
     match matches.subcommand() {
@@ -790,19 +2912,46 @@ fn main() {
         ("get-issue-metadata",          Some(sub_matches)) => get_issue_metadata(sub_matches),
         ("get-issue-tree-init-hashes",  Some(sub_matches)) => get_issue_tree_init_hashes(sub_matches),
         // Porcelain subcommands
+        ("assign",  Some(sub_matches)) => assign_impl(sub_matches),
+        ("blame",   Some(sub_matches)) => blame_impl(sub_matches),
+        ("checkout",Some(sub_matches)) => checkout_impl(sub_matches),
+        ("close",   Some(sub_matches)) => close_impl(sub_matches),
+        ("export",  Some(sub_matches)) => export_impl(sub_matches),
         ("fetch",   Some(sub_matches)) => fetch_impl(sub_matches),
+        ("fsck",    Some(sub_matches)) => fsck_impl(sub_matches),
         ("gc",      Some(sub_matches)) => gc_impl(sub_matches),
+        ("import",  Some(sub_matches)) => import_impl(sub_matches),
+        ("ingest",  Some(sub_matches)) => ingest_impl(sub_matches),
+        ("lint",    Some(sub_matches)) => lint_impl(sub_matches),
         ("list",    Some(sub_matches)) => list_impl(sub_matches),
+        ("log",     Some(sub_matches)) => log_impl(sub_matches),
+        ("mine",    Some(sub_matches)) => mine_impl(sub_matches),
         ("mirror",  Some(sub_matches)) => mirror_impl(sub_matches),
+        ("moderate",Some(sub_matches)) => moderate_impl(sub_matches),
         ("new",     Some(sub_matches)) => new_impl(sub_matches),
+        ("nntp",    Some(sub_matches)) => nntp_impl(sub_matches),
+        ("protect", Some(sub_matches)) => protect_impl(sub_matches),
         ("push",    Some(sub_matches)) => push_impl(sub_matches),
+        ("quarantine",Some(sub_matches)) => quarantine_impl(sub_matches),
+        ("reopen",  Some(sub_matches)) => reopen_impl(sub_matches),
+        ("repair",  Some(sub_matches)) => repair_impl(sub_matches),
         ("reply",   Some(sub_matches)) => reply_impl(sub_matches),
+        ("search",  Some(sub_matches)) => search_impl(sub_matches),
         ("show",    Some(sub_matches)) => show_impl(sub_matches),
+        ("stale",   Some(sub_matches)) => stale_impl(sub_matches),
+        ("start",   Some(sub_matches)) => start_impl(sub_matches),
+        ("stats",   Some(sub_matches)) => stats_impl(sub_matches),
+        ("sync",    Some(sub_matches)) => sync_impl(sub_matches),
         ("tag",     Some(sub_matches)) => tag_impl(sub_matches),
+        ("templates",Some(sub_matches)) => templates_impl(sub_matches),
+        ("tui",     Some(sub_matches)) => tui_impl(sub_matches),
+        ("undo",    Some(sub_matches)) => undo_impl(sub_matches),
+        ("unassign",Some(sub_matches)) => unassign_impl(sub_matches),
+        ("web",     Some(sub_matches)) => web_impl(sub_matches),
         // Unknown subcommands
         ("", _) => {
             writeln!(io::stderr(), "{}", matches.usage()).ok();
-            std::process::exit(1);
+            std::process::exit(Category::Usage.code());
         },
         (name, sub_matches) => {
             let default = clap::ArgMatches::default();
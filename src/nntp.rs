@@ -0,0 +1,461 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! NNTP server
+//!
+//! This module provides a small NNTP (RFC 3977) server exposing all issues of
+//! a repository as a single newsgroup. Each message is an article, numbered in
+//! chronological order and represented like the mails sent for messages.
+//! Articles posted are imported like mails received, i.e. as replies to the
+//! messages they refer to or, if they do not refer to any message, as new
+//! issues.
+//!
+
+use chrono::Utc;
+use git2::{Commit, Oid, Repository, Signature};
+use libgitdit::RepositoryExt;
+use libgitdit::issue::HeadPolicy;
+use libgitdit::message_index::MessageIndex;
+use libgitdit::quarantine::FilterChain;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use error::*;
+use error::ErrorKind as EK;
+use error::LoggableError;
+use import::{self, MailImport};
+use mail::{self, Mail};
+
+
+/// Time after which idle clients are disconnected
+///
+/// Since connections are handled one after another, a client which neither
+/// sends commands nor disconnects would otherwise block all other clients.
+///
+const IDLE_TIMEOUT: Duration = Duration::from_secs(180);
+
+
+/// Settings for importing articles posted
+///
+pub struct Posting<'a> {
+    pub committer: Signature<'a>,
+    pub filter: FilterChain,
+    pub policy: HeadPolicy,
+}
+
+
+/// Serve the issues of a repository as newsgroup on the address given
+///
+/// Connections are handled one after another until the process is terminated.
+/// Clients are disconnected after being idle for `IDLE_TIMEOUT`. If no posting
+/// settings are supplied, posting is not permitted.
+///
+pub fn serve(repo: &Repository, address: &str, group: &str, posting: Option<&Posting>) -> Result<()> {
+    let listener = TcpListener::bind(address)
+        .map_err(|err| Error::from_kind(EK::ServerError(address.to_owned(), err.to_string())))?;
+
+    for stream in listener.incoming() {
+        let result = stream
+            .chain_err(|| EK::WrappedIOError)
+            .and_then(|stream| Connection::new(repo, group, posting, stream))
+            .and_then(|mut connection| connection.handle());
+        if let Err(err) = result {
+            err.log();
+        }
+    }
+    Ok(())
+}
+
+
+/// Client connection
+///
+struct Connection<'r, 'p> {
+    repo: &'r Repository,
+    group: &'p str,
+    posting: Option<&'p Posting<'p>>,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Index used for looking up the issues of articles by message id
+    index: MessageIndex,
+    /// Articles of the group, if selected, along with the issues they belong to
+    articles: Option<Vec<(Oid, Commit<'r>)>>,
+    /// Number of the current article
+    current: Option<usize>,
+}
+
+impl<'r, 'p> Connection<'r, 'p> {
+    fn new(repo: &'r Repository, group: &'p str, posting: Option<&'p Posting<'p>>, stream: TcpStream) -> Result<Self> {
+        stream
+            .set_read_timeout(Some(IDLE_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(IDLE_TIMEOUT)))
+            .chain_err(|| EK::WrappedIOError)?;
+        Ok(Connection {
+            repo: repo,
+            group: group,
+            posting: posting,
+            reader: BufReader::new(stream.try_clone().chain_err(|| EK::WrappedIOError)?),
+            writer: stream,
+            index: MessageIndex::updated(repo),
+            articles: None,
+            current: None,
+        })
+    }
+
+    /// Handle commands until the client quits or disconnects
+    ///
+    fn handle(&mut self) -> Result<()> {
+        let greeting = self.posting_status("git-dit NNTP service ready");
+        self.respond(&greeting)?;
+
+        loop {
+            let line = match self.read_line()? {
+                Some(line) => line,
+                None => return Ok(()),
+            };
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("").to_uppercase();
+            let args: Vec<&str> = words.collect();
+
+            match command.as_ref() {
+                "QUIT" => return self.respond("205 closing connection"),
+                "CAPABILITIES" => {
+                    let mut capabilities = vec!["VERSION 2", "READER", "OVER", "LIST ACTIVE NEWSGROUPS"];
+                    if self.posting.is_some() {
+                        capabilities.push("POST");
+                    }
+                    self.respond_lines("101 capability list follows", capabilities.into_iter().map(String::from))?;
+                },
+                "MODE" if args.first().map(|arg| arg.eq_ignore_ascii_case("READER")).unwrap_or(false) => {
+                    let status = self.posting_status("reader mode");
+                    self.respond(&status)?;
+                },
+                "DATE" => self.respond(&format!("111 {}", Utc::now().format("%Y%m%d%H%M%S")))?,
+                "HELP" => self.respond_lines("100 help text follows", vec![
+                    "ARTICLE BODY CAPABILITIES DATE GROUP HEAD HELP LAST LIST LISTGROUP".to_owned(),
+                    "MODE NEWGROUPS NEWNEWS NEXT OVER POST QUIT STAT XOVER".to_owned(),
+                ])?,
+                "LIST" => self.list(args.first().map(|arg| arg.to_uppercase()))?,
+                "NEWGROUPS" => self.respond_lines("231 list of new newsgroups follows", Vec::new())?,
+                "NEWNEWS" => self.respond_lines("230 list of new articles follows", Vec::new())?,
+                "GROUP" => self.group(args.first().cloned(), false)?,
+                "LISTGROUP" => self.group(args.first().cloned(), true)?,
+                "ARTICLE" | "HEAD" | "BODY" | "STAT" => self.article(&command, args.first().cloned())?,
+                "NEXT" => self.step(true)?,
+                "LAST" => self.step(false)?,
+                "OVER" | "XOVER" => self.over(args.first().cloned())?,
+                "POST" => self.post()?,
+                _ => self.respond("500 unknown command")?,
+            }
+        }
+    }
+
+    /// Handle the LIST command
+    ///
+    fn list(&mut self, keyword: Option<String>) -> Result<()> {
+        let line = match keyword.as_ref().map(String::as_str) {
+            None | Some("ACTIVE") => {
+                let count = self.load_articles()?.len();
+                let posting = if self.posting.is_some() { "y" } else { "n" };
+                format!("{} {} {} {}", self.group, count, 1, posting)
+            },
+            Some("NEWSGROUPS") => format!("{}\tgit-dit issues", self.group),
+            _ => return self.respond("501 unsupported keyword"),
+        };
+        self.respond_lines("215 list of newsgroups follows", vec![line])
+    }
+
+    /// Handle the GROUP and LISTGROUP commands
+    ///
+    fn group(&mut self, name: Option<&str>, list: bool) -> Result<()> {
+        match name {
+            Some(name) if name != self.group => return self.respond("411 no such newsgroup"),
+            None if self.articles.is_none() => return self.respond("412 no newsgroup selected"),
+            _ => {},
+        }
+
+        // articles are reloaded, so new messages become visible
+        self.articles = None;
+        let count = self.load_articles()?.len();
+        self.current = if count > 0 { Some(1) } else { None };
+
+        let status = format!("211 {} {} {} {}", count, 1, count, self.group);
+        if list {
+            self.respond_lines(&status, (1..count + 1).map(|number| number.to_string()))
+        } else {
+            self.respond(&status)
+        }
+    }
+
+    /// Handle the ARTICLE, HEAD, BODY and STAT commands
+    ///
+    fn article(&mut self, command: &str, selector: Option<&str>) -> Result<()> {
+        let (number, issue, message) = match selector {
+            Some(id) if id.starts_with('<') => {
+                let repo = self.repo;
+                let index = &mut self.index;
+                let found = mail::parse_message_id(id)
+                    .and_then(|id| repo.find_commit(id).ok())
+                    .and_then(|message| index
+                        .lookup(repo, &message)
+                        .ok()
+                        .map(|issue| (0, issue.id(), message)));
+                match found {
+                    Some(found) => found,
+                    None => return self.respond("430 no such article"),
+                }
+            },
+            selector => {
+                if self.articles.is_none() {
+                    return self.respond("412 no newsgroup selected");
+                }
+                let number = match selector.map(str::parse::<usize>) {
+                    Some(Ok(number)) => number,
+                    Some(Err(_)) => return self.respond("501 malformed article number"),
+                    None => match self.current {
+                        Some(number) => number,
+                        None => return self.respond("420 no current article"),
+                    },
+                };
+                let found = self.load_articles()?
+                    .get(number.wrapping_sub(1))
+                    .map(|&(issue, ref message)| (number, issue, message.clone()));
+                match found {
+                    Some(found) => {
+                        self.current = Some(number);
+                        found
+                    },
+                    None => return self.respond("423 no such article number"),
+                }
+            },
+        };
+
+        let article = self.render(issue, &message);
+        let (head, body) = match article.find("\n\n") {
+            Some(pos) => (&article[..pos], &article[pos + 2..]),
+            None => (article.as_str(), ""),
+        };
+        let message_id = format!("<{}>", mail::message_id(message.id()));
+        let lines: Vec<String> = match command {
+            "ARTICLE" => article.lines().map(String::from).collect(),
+            "HEAD"    => head.lines().map(String::from).collect(),
+            "BODY"    => body.lines().map(String::from).collect(),
+            _         => return self.respond(&format!("223 {} {}", number, message_id)),
+        };
+        let code = match command {
+            "ARTICLE" => 220,
+            "HEAD"    => 221,
+            _         => 222,
+        };
+        self.respond_lines(&format!("{} {} {}", code, number, message_id), lines)
+    }
+
+    /// Handle the NEXT and LAST commands
+    ///
+    fn step(&mut self, forward: bool) -> Result<()> {
+        if self.articles.is_none() {
+            return self.respond("412 no newsgroup selected");
+        }
+        let current = match self.current {
+            Some(current) => current,
+            None => return self.respond("420 no current article"),
+        };
+        let count = self.load_articles()?.len();
+        let number = match forward {
+            true if current < count => current + 1,
+            true => return self.respond("421 no next article"),
+            false if current > 1 => current - 1,
+            false => return self.respond("422 no previous article"),
+        };
+
+        self.current = Some(number);
+        let id = self.load_articles()?[number - 1].1.id();
+        self.respond(&format!("223 {} <{}>", number, mail::message_id(id)))
+    }
+
+    /// Handle the OVER and XOVER commands
+    ///
+    fn over(&mut self, range: Option<&str>) -> Result<()> {
+        if self.articles.is_none() {
+            return self.respond("412 no newsgroup selected");
+        }
+        let count = self.load_articles()?.len();
+        let (first, last) = match range {
+            None => match self.current {
+                Some(current) => (current, current),
+                None => return self.respond("420 no current article"),
+            },
+            Some(range) => {
+                let mut bounds = range.splitn(2, '-');
+                let first = bounds.next().and_then(|bound| bound.parse().ok()).unwrap_or(1);
+                let last = match bounds.next() {
+                    Some("") => count,
+                    Some(bound) => bound.parse().unwrap_or(count),
+                    None => first,
+                };
+                (first, last)
+            },
+        };
+
+        let articles: Vec<(usize, Oid, Commit)> = self.load_articles()?
+            .iter()
+            .enumerate()
+            .map(|(index, &(issue, ref message))| (index + 1, issue, message.clone()))
+            .filter(|&(number, _, _)| number >= first && number <= last)
+            .collect();
+        if articles.is_empty() {
+            return self.respond("423 no articles in that range");
+        }
+
+        let lines: Vec<String> = articles
+            .into_iter()
+            .map(|(number, issue, message)| {
+                let article = self.render(issue, &message);
+                let mail = Mail::parse(article.as_bytes());
+                let header = |name| mail.header(name).unwrap_or("").replace('\t', " ");
+                format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                        number,
+                        header("Subject"),
+                        header("From"),
+                        header("Date"),
+                        header("Message-ID"),
+                        header("References"),
+                        article.len(),
+                        article.lines().count())
+            })
+            .collect();
+        self.respond_lines("224 overview information follows", lines)
+    }
+
+    /// Handle the POST command
+    ///
+    fn post(&mut self) -> Result<()> {
+        let posting = match self.posting {
+            Some(posting) => posting,
+            None => return self.respond("440 posting not permitted"),
+        };
+        self.respond("340 send article to be posted")?;
+
+        let mut article = String::new();
+        loop {
+            let line = match self.read_line()? {
+                Some(line) => line,
+                None => return Ok(()),
+            };
+            if line == "." {
+                break;
+            }
+            article.push_str(if line.starts_with("..") { &line[1..] } else { &line });
+            article.push('\n');
+        }
+
+        let mail = Mail::parse(article.as_bytes());
+        let replies = mail.header("In-Reply-To").is_some() || mail.header("References").is_some();
+        let result = if replies {
+            import::mail(self.repo, &mut self.index, &mail, &posting.committer, &posting.filter, posting.policy)
+        } else {
+            import::mail_as_issue(self.repo, &mail, &posting.committer, &posting.filter)
+        };
+
+        match result {
+            Ok(MailImport::Imported(_)) | Ok(MailImport::Known(_)) => self.respond("240 article received"),
+            Ok(MailImport::Flagged(id, reason)) => {
+                warn!("{}", tr!("Flagged {}: {}", id, reason));
+                self.respond("240 article received")
+            },
+            Ok(MailImport::Unrelated) => self.respond("441 article does not refer to any known article"),
+            Ok(MailImport::Rejected(reason)) => self.respond(&format!("441 article rejected: {}", reason)),
+            Err(err) => {
+                err.log();
+                self.respond("441 posting failed")
+            },
+        }
+    }
+
+    /// Get the articles of the group, loading them if necessary
+    ///
+    /// Articles are ordered by the time they were authored. Messages hidden
+    /// via moderation are omitted.
+    ///
+    fn load_articles(&mut self) -> Result<&Vec<(Oid, Commit<'r>)>> {
+        if self.articles.is_none() {
+            let moderation = self.repo.moderation()?;
+            let mut articles = Vec::new();
+            for issue in self.repo.issues()? {
+                for message in issue.messages()? {
+                    let message = message?;
+                    if !moderation.is_hidden(&message) {
+                        articles.push((issue.id(), message));
+                    }
+                }
+            }
+            articles.sort_by_key(|&(_, ref message)| (message.author().when().seconds(), message.id()));
+            self.articles = Some(articles);
+        }
+        Ok(self.articles.as_ref().unwrap())
+    }
+
+    /// Render a message as article
+    ///
+    fn render(&self, issue: Oid, message: &Commit) -> String {
+        format!("Newsgroups: {}\nPath: git-dit\n{}", self.group, mail::mail(issue, message))
+    }
+
+    /// Get a status line indicating whether posting is permitted
+    ///
+    fn posting_status(&self, text: &str) -> String {
+        match self.posting {
+            Some(_) => format!("200 {}, posting allowed", text),
+            None    => format!("201 {}, posting prohibited", text),
+        }
+    }
+
+    /// Read a line sent by the client
+    ///
+    /// `None` is returned if the client disconnected or exceeded the idle
+    /// timeout.
+    ///
+    fn read_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => return Ok(None),
+            Ok(_) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut =>
+                return Ok(None),
+            Err(err) => return Err(err).chain_err(|| EK::WrappedIOError),
+        }
+        Ok(Some(line.trim_end_matches(|c| c == '\r' || c == '\n').to_owned()))
+    }
+
+    /// Send a single line response
+    ///
+    fn respond(&mut self, status: &str) -> Result<()> {
+        write!(self.writer, "{}\r\n", status).chain_err(|| EK::WrappedIOError)
+    }
+
+    /// Send a multi-line response
+    ///
+    /// Lines starting with a dot are dot-stuffed.
+    ///
+    fn respond_lines<I>(&mut self, status: &str, lines: I) -> Result<()>
+        where I: IntoIterator<Item = String>
+    {
+        let mut response = format!("{}\r\n", status);
+        for line in lines {
+            if line.starts_with('.') {
+                response.push('.');
+            }
+            response.push_str(&line);
+            response.push_str("\r\n");
+        }
+        response.push_str(".\r\n");
+        self.writer.write_all(response.as_bytes()).chain_err(|| EK::WrappedIOError)
+    }
+}
+
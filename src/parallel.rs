@@ -0,0 +1,74 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Parallel processing of issues
+//!
+//! This module provides the processing of issues in worker threads, which is
+//! available if git-dit is built with the "parallel" feature. Since repository
+//! handles may not be shared between threads, each worker opens a handle of
+//! its own. The number of workers may be configured via `dit.threads`, which
+//! defaults to the number of CPUs. Without the feature, or if a single worker
+//! is configured, issues are processed in the calling thread.
+//!
+
+use git2::Repository;
+use libgitdit::Issue;
+
+
+/// Apply a function to each of the issues supplied
+///
+/// The results are returned in the order of the issues. The function is
+/// passed the repository handle of the thread it is called in along with a
+/// handle of the issue for that repository.
+///
+#[cfg(feature = "parallel")]
+pub fn map<'r, T, F>(repo: &'r Repository, issues: &[Issue<'r>], f: F) -> Vec<T>
+    where T: Send,
+          F: for<'a> Fn(&'a Repository, &Issue<'a>) -> T + Sync
+{
+    use rayon::ThreadPoolBuilder;
+    use rayon::prelude::*;
+
+    use system::Abortable;
+
+    let threads = repo
+        .config()
+        .ok()
+        .and_then(|config| config.get_i64("dit.threads").ok())
+        .unwrap_or(0);
+    let pool = ThreadPoolBuilder::new().num_threads(threads.max(0) as usize).build();
+    let pool = match pool {
+        Ok(ref pool) if threads != 1 && issues.len() > 1 => pool,
+        _ => return issues.iter().map(|issue| f(repo, issue)).collect(),
+    };
+
+    let path = repo.path().to_owned();
+    let issues: Vec<_> = issues.iter().map(Issue::handle).collect();
+    pool.install(|| issues
+        .par_iter()
+        .map_init(|| Repository::open(&path).unwrap_or_abort(), |repo, handle| {
+            let issue = handle.bind(repo).unwrap_or_abort();
+            f(repo, &issue)
+        })
+        .collect())
+}
+
+/// Apply a function to each of the issues supplied
+///
+/// The results are returned in the order of the issues. The function is
+/// passed the repository handle of the thread it is called in along with a
+/// handle of the issue for that repository.
+///
+#[cfg(not(feature = "parallel"))]
+pub fn map<'r, T, F>(repo: &'r Repository, issues: &[Issue<'r>], f: F) -> Vec<T>
+    where T: Send,
+          F: for<'a> Fn(&'a Repository, &Issue<'a>) -> T + Sync
+{
+    issues.iter().map(|issue| f(repo, issue)).collect()
+}
@@ -0,0 +1,209 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Conversion of Radicle issues
+//!
+//! This module reads issues stored as Radicle collaborative objects (COBs) and
+//! converts them to the representation of issues accepted by the `import`
+//! module. A COB is a graph of change commits referred to by references named
+//! after the object's type and id, optionally inside the namespaces of the
+//! peers which contributed to it. Each change carries a list of actions encoded
+//! as JSON in its tree.
+//!
+//! Each change becomes a message and the messages of an issue form a chain of
+//! replies in the order of the changes. Comments become the messages' texts,
+//! while labels, assignees and changes of the issue's state become trailers.
+//! Reactions and redactions are not converted.
+//!
+
+use git2::{Commit, ObjectType, Oid, Repository, Sort, Tree};
+use libgitdit::trailer::spec;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+use error::*;
+use error::ErrorKind as EK;
+use import;
+use json::{self, string};
+
+
+/// Type name of Radicle issues
+///
+const ISSUE_TYPE: &str = "xyz.radicle.issue";
+
+/// Prefix of the origins of messages imported from Radicle
+///
+const ORIGIN_PREFIX: &str = "radicle:";
+
+
+/// Convert the Radicle issues stored in a repository to issues
+///
+/// Issues are looked up both outside of namespaces and inside the namespaces
+/// of all peers, e.g. in a repository of a Radicle node's storage. The ids of
+/// the messages are derived from the ids of the issues and changes.
+///
+pub fn issue_values(repo: &Repository) -> Result<Vec<Value>> {
+    let mut tips: BTreeMap<String, Vec<Oid>> = BTreeMap::new();
+    let globs = [
+        format!("refs/cobs/{}/*", ISSUE_TYPE),
+        format!("refs/namespaces/*/refs/cobs/{}/*", ISSUE_TYPE),
+    ];
+    for glob in &globs {
+        for reference in repo.references_glob(glob)? {
+            let reference = reference?;
+            let object = reference.name().and_then(|name| name.rsplit('/').next()).map(str::to_owned);
+            if let (Some(object), Some(tip)) = (object, reference.target()) {
+                tips.entry(object).or_insert_with(Vec::new).push(tip);
+            }
+        }
+    }
+
+    tips.iter().map(|(object, tips)| issue_value(repo, object, tips)).collect()
+}
+
+
+/// Convert a single Radicle issue to an issue
+///
+/// The changes reachable from any of the tips given are converted in
+/// topological order. Changes consisting only of actions not converted are
+/// skipped.
+///
+fn issue_value(repo: &Repository, object: &str, tips: &[Oid]) -> Result<Value> {
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME | Sort::REVERSE)?;
+    for tip in tips {
+        walk.push(*tip)?;
+    }
+
+    let id = format!("{}/{}", ISSUE_TYPE, object);
+    let mut title = String::new();
+    let mut labels = BTreeSet::new();
+    let mut assignees = BTreeSet::new();
+    let mut messages = Vec::new();
+    for change in walk {
+        let change = repo.find_commit(change?)?;
+        let initial = messages.is_empty();
+
+        let mut paragraphs = Vec::new();
+        let mut trailers = Vec::new();
+        for action in actions(repo, &change)? {
+            match string(&action, "type").as_str() {
+                "edit" => {
+                    title = string(&action, "title");
+                    if !initial {
+                        paragraphs.push(format!("Changed the title to \"{}\".", title));
+                    }
+                },
+                "comment" => paragraphs.push(string(&action, "body")),
+                "comment.edit" => {
+                    paragraphs.push(if string(&action, "id") == object {
+                        "Edited the description:".to_owned()
+                    } else {
+                        format!("Edited comment {}:", string(&action, "id"))
+                    });
+                    paragraphs.push(string(&action, "body"));
+                },
+                "lifecycle" => {
+                    let state = action.get("state").unwrap_or(&Value::Null);
+                    let status = string(state, "status");
+                    if status == "open" || status == "closed" {
+                        trailers.push((spec::ISSUE_STATUS_SPEC.key, status));
+                    }
+                    let reason = string(state, "reason");
+                    if !reason.is_empty() {
+                        trailers.push((spec::ISSUE_STATUS_REASON_SPEC.key, reason));
+                    }
+                },
+                "label" => set_changes(&mut labels, &action, "labels", spec::ISSUE_TAG_SPEC.key, &mut trailers),
+                "assign" => set_changes(&mut assignees, &action, "assignees", spec::ISSUE_ASSIGNEE_SPEC.key, &mut trailers),
+                _ => (),
+            }
+        }
+        if !initial && paragraphs.is_empty() && trailers.is_empty() {
+            continue;
+        }
+
+        let (message_id, subject) = if initial {
+            (id.clone(), title.clone())
+        } else {
+            (format!("{}/{}", id, change.id()), format!("Re: {}", title))
+        };
+        let author = change.author();
+        let origin = format!("{}{}", ORIGIN_PREFIX, message_id);
+        messages.push(import::message(message_id,
+                                      origin,
+                                      (author.name().unwrap_or("").to_owned(), author.email().unwrap_or("").to_owned()),
+                                      json::date(author.when()),
+                                      subject,
+                                      &paragraphs.join("\n\n"),
+                                      trailers));
+    }
+
+    Ok(import::chain(id, messages))
+}
+
+
+/// Get the actions of a change
+///
+/// The actions are encoded as JSON and stored in the change's tree, either in
+/// a blob named "change" or in blobs inside a tree of that name, which are
+/// ordered by their names.
+///
+fn actions(repo: &Repository, change: &Commit) -> Result<Vec<Value>> {
+    let tree = change.tree()?;
+    let mut blobs = Vec::new();
+    match tree.get_name("change") {
+        Some(entry) => match entry.kind() {
+            Some(ObjectType::Tree) => blobs.extend(tree_blobs(&repo.find_tree(entry.id())?)),
+            _ => blobs.push((String::new(), entry.id())),
+        },
+        None => blobs.extend(tree_blobs(&tree).into_iter().filter(|blob| blob.0 != "manifest")),
+    }
+    blobs.sort_by_key(|blob| blob.0.parse::<u64>().unwrap_or(0));
+
+    let mut retval = Vec::new();
+    for (_, blob) in blobs {
+        let blob = repo.find_blob(blob)?;
+        let value: Value = ::serde_json::from_slice(blob.content())
+            .chain_err(|| EK::MalformedImport(format!("cannot parse change {}", change.id())))?;
+        match value {
+            Value::Array(actions) => retval.extend(actions),
+            action => retval.push(action),
+        }
+    }
+    Ok(retval)
+}
+
+
+/// Get the names and ids of the blobs in a tree
+///
+fn tree_blobs(tree: &Tree) -> Vec<(String, Oid)> {
+    tree.iter()
+        .filter(|entry| entry.kind() == Some(ObjectType::Blob))
+        .map(|entry| (entry.name().unwrap_or("").to_owned(), entry.id()))
+        .collect()
+}
+
+
+/// Convert the replacement of a set of values to trailers
+///
+/// Values added to the set are represented by trailers with the values, values
+/// removed by trailers with the values prefixed with "-". The set is updated.
+///
+fn set_changes<'k>(set: &mut BTreeSet<String>,
+                   action: &Value,
+                   field: &str,
+                   key: &'k str,
+                   trailers: &mut Vec<(&'k str, String)>
+) {
+    let new: BTreeSet<String> = json::strings(action, field).map(str::to_owned).collect();
+    trailers.extend(set.difference(&new).map(|value| (key, format!("-{}", value))));
+    trailers.extend(new.difference(set).map(|value| (key, value.clone())));
+    *set = new;
+}
@@ -0,0 +1,241 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! SQLite mirror of issues
+//!
+//! This module maintains an SQLite database mirroring issues, their messages
+//! and their metadata, e.g. for querying them with external reporting tools.
+//! The database is updated incrementally: an issue is only written if its
+//! references or the set of its hidden messages changed since it was last
+//! mirrored.
+//!
+
+use git2::{Commit, Repository};
+use libgitdit::Issue;
+use libgitdit::issue::IssueRefType;
+use rusqlite::{Connection, Transaction};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use error::*;
+use error::ErrorKind as EK;
+use json::{self, array, optional_str, strings};
+
+
+/// Schema of the database
+///
+/// The "refs" column of the issues table records the state of the issue
+/// mirrored. Dates are stored in RFC 3339 format.
+///
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS issues (
+    id TEXT PRIMARY KEY,
+    component TEXT,
+    subject TEXT,
+    author_name TEXT,
+    author_email TEXT,
+    created TEXT,
+    head TEXT,
+    status TEXT,
+    type TEXT,
+    milestone TEXT,
+    refs TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tags (
+    issue TEXT NOT NULL,
+    tag TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS assignees (
+    issue TEXT NOT NULL,
+    assignee TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS messages (
+    id TEXT PRIMARY KEY,
+    issue TEXT NOT NULL,
+    hidden INTEGER NOT NULL,
+    author_name TEXT,
+    author_email TEXT,
+    author_date TEXT,
+    committer_name TEXT,
+    committer_email TEXT,
+    committer_date TEXT,
+    subject TEXT,
+    body TEXT
+);
+CREATE TABLE IF NOT EXISTS parents (
+    message TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    parent TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS trailers (
+    message TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tags_issue ON tags (issue);
+CREATE INDEX IF NOT EXISTS assignees_issue ON assignees (issue);
+CREATE INDEX IF NOT EXISTS messages_issue ON messages (issue);
+CREATE INDEX IF NOT EXISTS parents_message ON parents (message);
+CREATE INDEX IF NOT EXISTS trailers_message ON trailers (message);
+";
+
+
+/// Mirror the issues supplied into an SQLite database
+///
+/// The database is created if it does not exist. Issues which are part of the
+/// database but not supplied are removed from it. Messages for which `hidden`
+/// returns true are only represented by their id and parents. The ids of the
+/// issues written are returned.
+///
+pub fn mirror<'r, I>(repo: &'r Repository,
+                     issues: I,
+                     hidden: &Fn(&Commit) -> bool,
+                     path: &Path
+) -> Result<Vec<String>>
+    where I: IntoIterator<Item = Issue<'r>>
+{
+    let mut connection = Connection::open(path).chain_err(|| EK::DatabaseError)?;
+    connection.execute_batch(SCHEMA).chain_err(|| EK::DatabaseError)?;
+    let transaction = connection.transaction().chain_err(|| EK::DatabaseError)?;
+
+    let mut known: HashMap<String, String> = {
+        let mut statement = transaction
+            .prepare("SELECT id, refs FROM issues")
+            .chain_err(|| EK::DatabaseError)?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .chain_err(|| EK::DatabaseError)?;
+        rows.collect::<::rusqlite::Result<_>>().chain_err(|| EK::DatabaseError)?
+    };
+
+    let mut retval = Vec::new();
+    for issue in issues {
+        let id = issue.id().to_string();
+        let messages = issue
+            .messages()?
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+        let refs = refs_state(&issue, &messages, hidden)?;
+        if known.remove(&id).as_ref() == Some(&refs) {
+            continue;
+        }
+
+        remove_issue(&transaction, &id).chain_err(|| EK::DatabaseError)?;
+        let initial = issue.initial_message()?;
+        let value = json::issue_with_messages(repo, &issue, &initial, messages, hidden);
+        insert_issue(&transaction, &value, &refs).chain_err(|| EK::DatabaseError)?;
+        retval.push(id);
+    }
+
+    // issues left are no longer mirrored
+    for id in known.keys() {
+        remove_issue(&transaction, id).chain_err(|| EK::DatabaseError)?;
+    }
+
+    transaction.commit().chain_err(|| EK::DatabaseError)?;
+    Ok(retval)
+}
+
+
+/// Describe the state of an issue as relevant for the mirror
+///
+/// The state consists of the names and targets of the issue's references and
+/// the ids of the hidden messages.
+///
+fn refs_state<'r>(issue: &Issue<'r>, messages: &[Commit<'r>], hidden: &Fn(&Commit) -> bool) -> Result<String> {
+    let mut lines = Vec::new();
+    for reference in issue.all_refs(IssueRefType::Any)? {
+        let reference = reference?;
+        let target = reference.target().map(|id| id.to_string()).unwrap_or_default();
+        lines.push(format!("{} {}", target, reference.name().unwrap_or("")));
+    }
+    lines.sort();
+    lines.extend(messages
+        .iter()
+        .filter(|message| hidden(message))
+        .map(|message| format!("{} hidden", message.id())));
+    Ok(lines.join("\n"))
+}
+
+
+/// Remove an issue and all its messages from the database
+///
+fn remove_issue(transaction: &Transaction, id: &str) -> ::rusqlite::Result<()> {
+    for statement in &[
+        "DELETE FROM trailers WHERE message IN (SELECT id FROM messages WHERE issue = ?1)",
+        "DELETE FROM parents WHERE message IN (SELECT id FROM messages WHERE issue = ?1)",
+        "DELETE FROM messages WHERE issue = ?1",
+        "DELETE FROM assignees WHERE issue = ?1",
+        "DELETE FROM tags WHERE issue = ?1",
+        "DELETE FROM issues WHERE id = ?1",
+    ] {
+        transaction.execute(statement, [id])?;
+    }
+    Ok(())
+}
+
+
+/// Insert an issue represented as by `json::issue_with_messages()`
+///
+fn insert_issue(transaction: &Transaction, issue: &Value, refs: &str) -> ::rusqlite::Result<()> {
+    let id = optional_str(issue, "id");
+    transaction.execute(
+        "INSERT INTO issues VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        (
+            id,
+            optional_str(issue, "component"),
+            optional_str(issue, "subject"),
+            issue.pointer("/author/name").and_then(Value::as_str),
+            issue.pointer("/author/email").and_then(Value::as_str),
+            issue.pointer("/author/date").and_then(Value::as_str),
+            optional_str(issue, "head"),
+            optional_str(issue, "status"),
+            optional_str(issue, "type"),
+            optional_str(issue, "milestone"),
+            refs,
+        ),
+    )?;
+    for tag in strings(issue, "tags") {
+        transaction.execute("INSERT INTO tags VALUES (?1, ?2)", (id, tag))?;
+    }
+    for assignee in strings(issue, "assignees") {
+        transaction.execute("INSERT INTO assignees VALUES (?1, ?2)", (id, assignee))?;
+    }
+
+    for message in array(issue, "messages") {
+        let message_id = optional_str(message, "id");
+        transaction.execute(
+            "INSERT INTO messages VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            (
+                message_id,
+                id,
+                message.get("hidden").and_then(Value::as_bool).unwrap_or(false),
+                message.pointer("/author/name").and_then(Value::as_str),
+                message.pointer("/author/email").and_then(Value::as_str),
+                message.pointer("/author/date").and_then(Value::as_str),
+                message.pointer("/committer/name").and_then(Value::as_str),
+                message.pointer("/committer/email").and_then(Value::as_str),
+                message.pointer("/committer/date").and_then(Value::as_str),
+                optional_str(message, "subject"),
+                optional_str(message, "body"),
+            ),
+        )?;
+        for (position, parent) in strings(message, "parents").enumerate() {
+            transaction.execute("INSERT INTO parents VALUES (?1, ?2, ?3)", (message_id, position, parent))?;
+        }
+        for (position, trailer) in array(message, "trailers").iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO trailers VALUES (?1, ?2, ?3, ?4)",
+                (message_id, position, optional_str(trailer, "key"), optional_str(trailer, "value")),
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -9,11 +9,11 @@
 
 use std::process::exit;
 
-use error::LoggableError;
+use error::{Category, LoggableError};
 
 /// Aborting iterator
 ///
-/// Unwraps items and aborts (calls `exit(1)) if an error value was encountered.
+/// Unwraps items and aborts (calls `abort()`) if an error value was encountered.
 /// It yields the unwrapped values.
 ///
 /// This iterator is intended for uses where it is resonable to abort the
@@ -85,10 +85,47 @@ impl<V, E> Abortable<V> for Result<V, E>
     where E: LoggableError
 {
     fn unwrap_or_abort(self) -> V {
-        self.unwrap_or_else(|e| {
-            e.log();
-            exit(1)
-        })
+        self.unwrap_or_else(|e| abort(&e))
+    }
+}
+
+
+/// Log an error and abort the program
+///
+/// The program exits with the code associated with the error's category.
+///
+pub fn abort<E>(err: &E) -> !
+    where E: LoggableError
+{
+    err.log();
+    exit(err.category().code())
+}
+
+
+/// Log the failures of a bulk operation and abort if there were any
+///
+/// Bulk operations report failures for individual items alongside the items
+/// processed successfully. This function is intended to be called after the
+/// latter were reported, so a single failure doesn't hide all the others.
+///
+/// If all failures share a category, the program exits with the associated
+/// code. Otherwise, the code of the generic `Category::Failure` is used.
+///
+pub fn abort_on_failures<E>(failures: &[E])
+    where E: LoggableError
+{
+    for failure in failures {
+        failure.log();
+    }
+
+    let mut categories = failures.iter().map(LoggableError::category);
+    if let Some(first) = categories.next() {
+        let category = if categories.all(|category| category == first) {
+            first
+        } else {
+            Category::Failure
+        };
+        exit(category.code())
     }
 }
 
@@ -8,10 +8,10 @@
 //
 
 use std::env::var as env_var;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 
-use git2::Config;
+use git2::{Config, Repository};
 
 use error::*;
 use error::ErrorKind as EK;
@@ -85,23 +85,178 @@ pub fn run_editor(config: Config, path: &PathBuf) -> Result<Child> {
 }
 
 
+/// Run a sendmail compatible program for sending a mail to a recipient
+///
+/// The program is taken from `dit.mail.sendmail` and defaults to `sendmail`.
+/// The mail, including its headers, is expected to be written to the piped
+/// stdin of the handle returned.
+///
+pub fn sendmail(config: Config, recipient: &str) -> Result<Child> {
+    let prefs = [
+        Var::GitConf("dit.mail.sendmail"),
+        Var::Default("sendmail")
+    ];
+    command("sendmail", &prefs, &config)?
+        .arg("-i")
+        .arg("--")
+        .arg(recipient)
+        .stdin(Stdio::piped())
+        .spawn().chain_err(|| EK::WrappedIOError)
+}
+
+
+/// Run the tunnel program configured for IMAP connections
+///
+/// The program is taken from `dit.mail.imap.tunnel`. It is expected to speak
+/// IMAP via its stdin and stdout, both of which are piped.
+///
+pub fn imap_tunnel(config: Config) -> Result<Child> {
+    let prefs = [
+        Var::GitConf("dit.mail.imap.tunnel"),
+    ];
+    command("IMAP tunnel", &prefs, &config)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn().chain_err(|| EK::WrappedIOError)
+}
+
+
+/// Perform an HTTP request
+///
+/// The request is performed using the curl compatible program configured via
+/// `dit.curl`, defaulting to `curl`. Headers and the request body, if any, are
+/// passed via a configuration read from stdin rather than the command line, so
+/// credentials are not exposed to other processes. The response body is
+/// returned.
+///
+pub fn http_request(config: &Config,
+                    method: &str,
+                    url: &str,
+                    headers: &[String],
+                    body: Option<&str>
+) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let prefs = [
+        Var::GitConf("dit.curl"),
+        Var::Default("curl")
+    ];
+    let mut child = command("curl", &prefs, config)?
+        .args(&["--silent", "--show-error", "--fail", "--location", "--config", "-", "--"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .chain_err(|| EK::WrappedIOError)?;
+
+    {
+        // values are quoted as defined for curl's configuration files
+        let quote = |value: &str| value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\t', "\\t")
+            .replace('\r', "\\r")
+            .replace('\n', "\\n");
+
+        let stdin = child.stdin.as_mut().expect("Could not access stdin");
+        writeln!(stdin, "request = \"{}\"", quote(method)).chain_err(|| EK::WrappedIOError)?;
+        for header in headers {
+            writeln!(stdin, "header = \"{}\"", quote(header)).chain_err(|| EK::WrappedIOError)?;
+        }
+        if let Some(body) = body {
+            writeln!(stdin, "data-binary = \"{}\"", quote(body)).chain_err(|| EK::WrappedIOError)?;
+        }
+    }
+    let output = child.wait_with_output().chain_err(|| EK::WrappedIOError)?;
+    if !output.status.success() {
+        return Err(Error::from_kind(EK::ChildError));
+    }
+    Ok(output.stdout)
+}
+
+
+/// Assemble the command for running a hook
+///
+/// Like git, hooks are looked up in the directory configured via
+/// `core.hooksPath`, defaulting to the `hooks` directory in the repository.
+/// If no executable hook of the given name exists, `None` is returned. The
+/// command is run in the root of the working tree, or the repository if it is
+/// bare, with `GIT_DIR` set.
+///
+pub fn hook(repo: &Repository, name: &str) -> Result<Option<Command>> {
+    let base = repo.workdir().unwrap_or_else(|| repo.path());
+    let dir = match repo.config()?.get_path("core.hooksPath") {
+        Ok(dir) => base.join(dir),
+        Err(_) => repo.path().join("hooks"),
+    };
+    let path = dir.join(name);
+    if !is_executable(&path) {
+        return Ok(None);
+    }
+
+    let mut command = Command::new(path);
+    command.current_dir(base).env("GIT_DIR", repo.path());
+    Ok(Some(command))
+}
+
+
+/// Check whether a file is executable
+///
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Check whether a file is executable
+///
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+
 /// Assemble and execute a pager command
 ///
 /// Returns the handle to a pager, with a piped stdin, to which the caller may
-/// write in order to generate paged output.
+/// write in order to generate paged output. Like git, no pager is used if the
+/// pager configured is empty or "cat", in which case `None` is returned.
 ///
-pub fn pager(config: Config) -> Result<Child> {
-    // preference order as specified by the `git var` man page
+pub fn pager(config: Config) -> Result<Option<Child>> {
+    // preference order as specified by the `git var` man page, with the
+    // command specific `pager.dit` taking precedence over `core.pager`
     let prefs = [
         Var::Environ("GIT_PAGER"),
+        Var::GitConf("pager.dit"),
         Var::GitConf("core.pager"),
         Var::Environ("PAGER"),
         Var::Default("less") // TODO: make settable at compile time
     ];
+    let disabled = prefs
+        .iter()
+        .filter_map(|var| var.value(&config))
+        .next()
+        .map(|pager| pager.trim().is_empty() || pager.trim() == "cat")
+        .unwrap_or(false);
+    if disabled {
+        return Ok(None);
+    }
+
     command("pager", &prefs, &config)
         .and_then(|mut command| {
+            // Like git, we make `less` quit for short output and pass through
+            // colors unless the user chose otherwise.
+            if env_var("LESS").is_err() {
+                command.env("LESS", "FRX");
+            }
+            if env_var("LV").is_err() {
+                command.env("LV", "-c");
+            }
             command.stdin(Stdio::piped());
-            command.spawn().chain_err(|| EK::WrappedIOError)
+            command.spawn().chain_err(|| EK::WrappedIOError).map(Some)
         })
 }
 
@@ -11,8 +11,6 @@ use std::fmt::Display;
 use std::io::{self, Result as RResult, Write};
 use std::process::Child;
 
-use atty;
-
 use error::*;
 use error::ErrorKind as EK;
 
@@ -20,21 +18,35 @@ use error::ErrorKind as EK;
 /// Extension trait for convenient writing of lines
 ///
 pub trait LinesExt: Sized {
+    /// Write the items returned as records to a given stream
+    ///
+    /// Each item is followed by the terminator given.
+    ///
+    fn write_records(self, stream: &mut Write, terminator: char) -> RResult<()>;
+
     /// Write the items returned as lines to a given stream
     ///
-    fn write_lines(self, stream: &mut Write) -> RResult<()>;
+    fn write_lines(self, stream: &mut Write) -> RResult<()> {
+        self.write_records(stream, '\n')
+    }
+
+    /// Write the items returned as records to stdout
+    ///
+    fn print_records(self, terminator: char) -> RResult<()> {
+        let mut stream = io::stdout();
+        self.write_records(&mut stream, terminator)
+    }
 
     /// Write the items returned as lines to stdout
     ///
     fn print_lines(self) -> RResult<()> {
-        let mut stream = io::stdout();
-        self.write_lines(&mut stream)
+        self.print_records('\n')
     }
 
     /// Pipe lines to a child process
     ///
-    /// If stdout is a TTY, pipe the lines to the child process and wait until
-    /// it is closed. Otherwise, just print them to stdout.
+    /// If a child process is supplied, pipe the lines to the child process and
+    /// wait until it is closed. Otherwise, just print them to stdout.
     ///
     /// Returns `0` on success and a non-null return code if an error occured
     /// in the child.
@@ -45,8 +57,8 @@ pub trait LinesExt: Sized {
     /// e.g. it must accept data via standart input. Otherwise, this function
     /// panics.
     ///
-    fn pipe_lines(self, mut child: Child) -> Result<i32> {
-        if atty::is(atty::Stream::Stdout) {
+    fn pipe_lines(self, child: Option<Child>) -> Result<i32> {
+        if let Some(mut child) = child {
             // NOTE: this unwrap is ok via the requirements on `child`.
             self.write_lines(child.stdin.as_mut().unwrap())
                 .chain_err(|| Error::from(EK::WrappedIOError))?;
@@ -67,10 +79,10 @@ impl<I, L> LinesExt for I
     where I: IntoIterator<Item = L>,
           L: Display
 {
-    fn write_lines(self, stream: &mut Write) -> RResult<()>
+    fn write_records(self, stream: &mut Write, terminator: char) -> RResult<()>
     {
         for line in self {
-            write!(stream, "{}\n", line)?;
+            write!(stream, "{}{}", line, terminator)?;
         }
         Ok(())
     }
@@ -0,0 +1,73 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Instrumentation
+//!
+//! With the "trace" feature enabled, spans and events are emitted via the
+//! `tracing` crate, both by the library and by some subcommands. They are
+//! printed to stderr if a level is selected via `DIT_TRACE`, along with the
+//! time spent in each span.
+//!
+//! Like in the library, spans are entered via `trace_span!` and events emitted
+//! via `trace_event!`. Without the feature, the macros expand to nothing.
+//!
+
+/// Environment variable selecting the level of spans and events printed
+///
+#[cfg(feature = "trace")]
+pub const TRACE_VAR: &'static str = "DIT_TRACE";
+
+
+/// Enter a span for the remainder of the current scope
+///
+macro_rules! trace_span {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "trace")]
+        let _span = ::tracing::debug_span!($($arg)+).entered();
+    };
+}
+
+/// Emit an event
+///
+macro_rules! trace_event {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "trace")]
+        ::tracing::debug!($($arg)+);
+    };
+}
+
+
+/// Print spans and events to stderr
+///
+/// Spans and events are only printed if a level, e.g. "`debug`", is selected
+/// via `TRACE_VAR`. Returns `false` if the level is not recognized.
+///
+#[cfg(feature = "trace")]
+pub fn init() -> bool {
+    use std::env;
+    use std::io;
+    use tracing::Level;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    let level = match env::var(TRACE_VAR) {
+        Ok(ref level) if !level.is_empty() => level.parse::<Level>(),
+        _ => return true,
+    };
+    match level {
+        Ok(level) => {
+            tracing_subscriber::fmt()
+                .with_max_level(level)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_writer(io::stderr)
+                .init();
+            true
+        },
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,327 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Interactive terminal interface
+//!
+//! The interface consists of a pane listing issues and a pane displaying the
+//! selected issue as a thread. Actions modifying issues are performed by
+//! invoking git-dit itself, which keeps the behaviour consistent with the
+//! respective subcommands.
+//!
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use git2::{Commit, Repository};
+use libgitdit::{Issue, RepositoryExt};
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use std::io::{self, Stdout};
+use std::process::Command;
+
+use display::{Palette, thread_lines};
+use error::*;
+use error::ErrorKind as EK;
+use gitext::ReferrencesExt;
+use util::RepositoryUtil;
+
+
+type Backend = CrosstermBackend<Stdout>;
+
+
+/// Help text displayed in the status line
+///
+const KEY_HELP: &'static str =
+    "j/k: select  J/K: scroll  r: reply  c: close  o: reopen  t: tag  g: reload  q: quit";
+
+
+/// Run the interactive interface
+///
+/// The issues listed are retrieved using `load`, which is called again after
+/// each action modifying an issue. They are listed in the order returned.
+///
+pub fn run<'r, F>(repo: &'r Repository, load: F, id_len: usize) -> Result<()>
+    where F: Fn() -> Vec<Issue<'r>>
+{
+    let mut state = State::new(repo, load, id_len)?;
+
+    let mut terminal = enter()?;
+    let result = state.event_loop(&mut terminal);
+    leave(&mut terminal)?;
+    result
+}
+
+
+/// Input mode of the interface
+///
+enum Mode {
+    /// Keys select issues and trigger actions
+    Normal,
+    /// Keys are appended to a line of tags
+    Tags(String),
+}
+
+
+/// State of the interactive interface
+///
+struct State<'r, F>
+    where F: Fn() -> Vec<Issue<'r>>
+{
+    repo: &'r Repository,
+    load: F,
+    id_len: usize,
+    issues: Vec<(Issue<'r>, String)>,
+    selection: ListState,
+    thread: Vec<String>,
+    scroll: u16,
+    mode: Mode,
+    status: String,
+}
+
+impl<'r, F> State<'r, F>
+    where F: Fn() -> Vec<Issue<'r>>
+{
+    fn new(repo: &'r Repository, load: F, id_len: usize) -> Result<Self> {
+        let mut state = State {
+            repo: repo,
+            load: load,
+            id_len: id_len,
+            issues: Vec::new(),
+            selection: ListState::default(),
+            thread: Vec::new(),
+            scroll: 0,
+            mode: Mode::Normal,
+            status: KEY_HELP.to_owned(),
+        };
+        state.reload()?;
+        Ok(state)
+    }
+
+    /// Reload the list of issues, retaining the selected issue if possible
+    ///
+    fn reload(&mut self) -> Result<()> {
+        let selected = self.selected_issue().map(Issue::id);
+
+        let mut issues = Vec::new();
+        for issue in (self.load)() {
+            let line = format!("{:.*} {}", self.id_len, issue.id(), issue.initial_message()?.summary().unwrap_or(""));
+            issues.push((issue, line));
+        }
+        self.issues = issues;
+
+        let index = selected
+            .and_then(|id| self.issues.iter().position(|&(ref issue, _)| issue.id() == id))
+            .or_else(|| if self.issues.is_empty() { None } else { Some(0) });
+        self.select(index)
+    }
+
+    fn selected_issue(&self) -> Option<&Issue<'r>> {
+        self.selection
+            .selected()
+            .and_then(|index| self.issues.get(index))
+            .map(|&(ref issue, _)| issue)
+    }
+
+    /// Select an issue and render its thread
+    ///
+    fn select(&mut self, index: Option<usize>) -> Result<()> {
+        self.selection.select(index);
+        self.scroll = 0;
+        self.thread = match self.selected_issue() {
+            Some(issue) => {
+                let head = issue
+                    .heads()?
+                    .collect::<::std::result::Result<Vec<_>, _>>()?
+                    .select_ref(&self.repo.remote_priorization())
+                    .and_then(|head| head.target())
+                    .unwrap_or_else(|| issue.id());
+                let moderation = self.repo.moderation()?;
+                let hidden = |message: &Commit| moderation.is_hidden(message);
+                thread_lines(issue, head, self.id_len, &hidden, 3, &Palette::plain())?
+            },
+            None => Vec::new(),
+        };
+        Ok(())
+    }
+
+    /// Move the selection by the offset given
+    ///
+    fn move_selection(&mut self, offset: isize) -> Result<()> {
+        if self.issues.is_empty() {
+            return Ok(());
+        }
+        let last = self.issues.len() as isize - 1;
+        let current = self.selection.selected().unwrap_or(0) as isize;
+        let index = (current + offset).max(0).min(last) as usize;
+        self.select(Some(index))
+    }
+
+    fn event_loop(&mut self, terminal: &mut Terminal<Backend>) -> Result<()> {
+        loop {
+            self.draw(terminal)?;
+
+            let key = match event::read().chain_err(|| EK::WrappedIOError)? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key.code,
+                _ => continue,
+            };
+
+            let selected = self.selected_issue().map(Issue::id);
+            let mode = ::std::mem::replace(&mut self.mode, Mode::Normal);
+            match (mode, key) {
+                (Mode::Tags(tags), KeyCode::Enter) => if let Some(id) = selected {
+                    let mut args = vec!["tag".to_owned(), id.to_string()];
+                    args.extend(tags.split_whitespace().map(String::from));
+                    self.run_captured(args)?;
+                },
+                (Mode::Tags(_), KeyCode::Esc) => self.status = KEY_HELP.to_owned(),
+                (Mode::Tags(mut tags), KeyCode::Backspace) => {
+                    tags.pop();
+                    self.mode = Mode::Tags(tags);
+                },
+                (Mode::Tags(mut tags), KeyCode::Char(c)) => {
+                    tags.push(c);
+                    self.mode = Mode::Tags(tags);
+                },
+                (mode @ Mode::Tags(_), _) => self.mode = mode,
+                (Mode::Normal, KeyCode::Char('q')) | (Mode::Normal, KeyCode::Esc) => return Ok(()),
+                (Mode::Normal, KeyCode::Char('j')) | (Mode::Normal, KeyCode::Down) => self.move_selection(1)?,
+                (Mode::Normal, KeyCode::Char('k')) | (Mode::Normal, KeyCode::Up) => self.move_selection(-1)?,
+                (Mode::Normal, KeyCode::Char('J')) | (Mode::Normal, KeyCode::PageDown) => {
+                    self.scroll = self.scroll.saturating_add(10);
+                },
+                (Mode::Normal, KeyCode::Char('K')) | (Mode::Normal, KeyCode::PageUp) => {
+                    self.scroll = self.scroll.saturating_sub(10);
+                },
+                (Mode::Normal, KeyCode::Char('g')) => self.reload()?,
+                (Mode::Normal, KeyCode::Char('r')) => if let Some(id) = selected {
+                    self.run_interactive(terminal, vec!["reply".to_owned(), id.to_string()])?;
+                },
+                (Mode::Normal, KeyCode::Char('c')) => if let Some(id) = selected {
+                    self.run_captured(vec!["close".to_owned(), id.to_string()])?;
+                },
+                (Mode::Normal, KeyCode::Char('o')) => if let Some(id) = selected {
+                    self.run_captured(vec!["reopen".to_owned(), id.to_string()])?;
+                },
+                (Mode::Normal, KeyCode::Char('t')) => if selected.is_some() {
+                    self.mode = Mode::Tags(String::new());
+                },
+                (Mode::Normal, _) => {},
+            }
+        }
+    }
+
+    fn draw(&mut self, terminal: &mut Terminal<Backend>) -> Result<()> {
+        let status = match self.mode {
+            Mode::Tags(ref tags) => format!("Tags (e.g. +bug -question): {}", tags),
+            Mode::Normal => self.status.clone(),
+        };
+        let items: Vec<ListItem> = self
+            .issues
+            .iter()
+            .map(|&(_, ref line)| ListItem::new(line.as_str()))
+            .collect();
+        let thread: Vec<Line> = self.thread.iter().map(|line| Line::from(line.as_str())).collect();
+        let scroll = self.scroll;
+        let selection = &mut self.selection;
+
+        terminal
+            .draw(|frame| {
+                let rows = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(1)])
+                    .split(frame.size());
+                let panes = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+                    .split(rows[0]);
+
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title("Issues"))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                frame.render_stateful_widget(list, panes[0], selection);
+
+                let thread = Paragraph::new(thread)
+                    .block(Block::default().borders(Borders::ALL).title("Thread"))
+                    .scroll((scroll, 0));
+                frame.render_widget(thread, panes[1]);
+
+                frame.render_widget(Paragraph::new(status), rows[1]);
+            })
+            .map(|_| ())
+            .chain_err(|| EK::WrappedIOError)
+    }
+
+    /// Run git-dit with the arguments given, capturing its output
+    ///
+    /// The last line of the output is displayed in the status line.
+    ///
+    fn run_captured(&mut self, args: Vec<String>) -> Result<()> {
+        let output = dit_command(args)?
+            .output()
+            .chain_err(|| EK::WrappedIOError)?;
+
+        self.status = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .chain(String::from_utf8_lossy(&output.stderr).lines())
+            .filter(|line| !line.trim().is_empty())
+            .last()
+            .map(String::from)
+            .unwrap_or_else(|| KEY_HELP.to_owned());
+        self.reload()
+    }
+
+    /// Run git-dit with the arguments given, handing over the terminal
+    ///
+    fn run_interactive(&mut self, terminal: &mut Terminal<Backend>, args: Vec<String>) -> Result<()> {
+        leave(terminal)?;
+        let status = dit_command(args)?
+            .status()
+            .chain_err(|| EK::WrappedIOError);
+        *terminal = enter()?;
+
+        self.status = if status?.success() {
+            KEY_HELP.to_owned()
+        } else {
+            Error::from_kind(EK::ChildError).to_string()
+        };
+        self.reload()
+    }
+}
+
+
+/// Prepare a command invoking git-dit itself
+///
+fn dit_command(args: Vec<String>) -> Result<Command> {
+    let mut command = Command::new(::std::env::current_exe().chain_err(|| EK::WrappedIOError)?);
+    command.args(args);
+    Ok(command)
+}
+
+
+/// Set up the terminal for the interactive interface
+///
+fn enter() -> Result<Terminal<Backend>> {
+    terminal::enable_raw_mode().chain_err(|| EK::WrappedIOError)?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen).chain_err(|| EK::WrappedIOError)?;
+    Terminal::new(CrosstermBackend::new(stdout)).chain_err(|| EK::WrappedIOError)
+}
+
+
+/// Restore the terminal
+///
+fn leave(terminal: &mut Terminal<Backend>) -> Result<()> {
+    terminal::disable_raw_mode().chain_err(|| EK::WrappedIOError)?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen).chain_err(|| EK::WrappedIOError)?;
+    terminal.show_cursor().chain_err(|| EK::WrappedIOError)
+}
@@ -7,39 +7,88 @@
 //   published by the Free Software Foundation.
 //
 
+use atty;
 use clap::{ArgMatches, Values};
 use git2::{self, Commit, Repository};
 use regex::{Regex, Match};
+use std::env;
 use std::fs::File;
 use std::io;
 use std::path::PathBuf;
 use std::process::Child;
 use std::str::FromStr;
 
+use libgitdit::anonymous::AnonymousIdentity;
+use libgitdit::config::DitConfig;
+use libgitdit::issue::HeadPolicy;
+use libgitdit::Message;
 use libgitdit::message::LineIteratorExt;
+use libgitdit::message::crypt::{self, Backend, ProgramCipher};
+use libgitdit::message::lint::{self, Linter};
+use libgitdit::quarantine::{CommandFilter, ContentFilter, FilterChain};
+use libgitdit::remote::{self, IssueChanges};
 use libgitdit::repository::UniqueIssues;
+use libgitdit::template;
 use libgitdit::trailer::Trailer;
+use libgitdit::trailer::spec::{DIT_KEY_PREFIX, TrailerSpec};
 use libgitdit::{Issue, RepositoryExt};
 
+use display::Palette;
 use error::*;
 use error::ErrorKind as EK;
 use gitext::RemotePriorization;
-use system::{Abortable, IteratorExt, programs};
+use i18n;
+use mail;
+use system::{abort, Abortable, IteratorExt, programs};
+
+
+/// Template used for new issues if none is configured
+///
+const DEFAULT_ISSUE_TEMPLATE: &'static str = "
+# Please enter the subject of the new issue in the first line, followed by an
+# empty line and a description of the issue. Trailers left empty will be
+# removed. Lines starting with '#' will be ignored.
+
+Dit-type:
+Dit-tag:
+";
+
+/// Environment variable selecting the identity profile
+///
+pub const PROFILE_VAR: &'static str = "DIT_PROFILE";
 
 /// Open the DIT repo
 ///
 /// Opens the DIT repo corresponding to the current one honouring the user
 /// configuration.
 ///
+/// If encryption is configured, the cipher is registered for transparent
+/// decryption of messages. A locale configured via `dit.locale` takes
+/// precedence over the one selected by the environment.
+///
 pub fn open_dit_repo() -> Repository {
     // TODO: access the config and maybe return another repo instead
-    Repository::open_from_env().unwrap_or_abort()
+    let repo = Repository::open_from_env().unwrap_or_abort();
+    if let Some(tag) = repo.dit_config().locale().unwrap_or_abort() {
+        match i18n::Locale::from_tag(&tag) {
+            Some(locale) => i18n::set_locale(locale),
+            None => warn!("{}", tr!("Unknown locale '{}'", tag)),
+        }
+    }
+    if let Some(cipher) = repo.cipher() {
+        crypt::set_decryption_cipher(Some(Box::new(cipher)));
+    }
+    repo
 }
 
 
 /// Utility trait for some repository-specific functionality
 ///
 pub trait RepositoryUtil<'r> {
+    /// Get the git-dit configuration of the repository
+    ///
+    fn dit_config(&self) -> DitConfig;
+
     /// Get a commit from a rev
     ///
     /// This function returns a commit for a rev-string.
@@ -58,6 +107,30 @@ pub trait RepositoryUtil<'r> {
     ///
     fn cli_issue(&'r self, matches: &ArgMatches) -> Option<Issue<'r>>;
 
+    /// Get the issue specified on the command line or the current issue
+    ///
+    /// If no issue is specified via the `"issue"` field, the current issue is
+    /// returned. If there is no current issue either, the program aborts.
+    ///
+    fn cli_issue_or_current(&'r self, matches: &ArgMatches) -> Issue<'r>;
+
+    /// Get the component specified on the command line
+    ///
+    /// If no component is specified via the `"component"` field, the component
+    /// configured via `dit.component` is returned, if any.
+    ///
+    fn cli_component(&self, matches: &ArgMatches) -> Option<String>;
+
+    /// Get the issue linked to a branch
+    ///
+    /// The issue is looked up in the `branch.<name>.dit-issue` configuration
+    /// written by the "start" subcommand. If it is not configured, the issue
+    /// is derived from the branch name, which is expected to contain an
+    /// abbreviated issue id after the last slash, e.g. `issue/1a2b3c4-subject`.
+    /// If no branch is given, the branch checked out is used.
+    ///
+    fn branch_issue(&'r self, branch: Option<&str>) -> Issue<'r>;
+
     /// Get the issues specified on the command line
     ///
     /// This function parses the issues specified via the `"issue"` field.
@@ -83,6 +156,46 @@ pub trait RepositoryUtil<'r> {
     ///
     fn get_commit_msg(&self, path: PathBuf) -> Vec<String>;
 
+    /// Get the template for new issues
+    ///
+    /// The `"template"` field may name one of the templates shipped with the
+    /// repository or specify a file to read the template from. Otherwise, the
+    /// template is read from the file configured via `dit.template.new`. If
+    /// neither is present, a default template is returned.
+    ///
+    fn issue_template(&self, matches: &ArgMatches) -> Vec<String>;
+
+    /// Ask for the values of required trailers missing from a new issue
+    ///
+    /// The trailers required are configured via `dit.new.required`. Values
+    /// entered are appended to the message's trailers. If stdin is not a
+    /// terminal, the message is returned unaltered.
+    ///
+    fn complete_trailers(&self, message: String) -> String;
+
+    /// Run the `dit-pre-message` hook for a message about to be committed
+    ///
+    /// The message is written to the file used for editing messages, which is
+    /// passed to the hook along with the id of the issue, if the message is
+    /// not the initial message of a new issue. The hook may alter the message,
+    /// which is read back and returned. If the hook fails, the process is
+    /// aborted.
+    ///
+    fn pre_message_hook(&self, matches: &ArgMatches, message: String, issue: Option<git2::Oid>) -> String;
+
+    /// Run the `dit-post-issue` hook for a newly created issue
+    ///
+    /// Failures of the hook are reported as warnings.
+    ///
+    fn post_issue_hook(&self, issue: git2::Oid);
+
+    /// Lint a message about to be committed
+    ///
+    /// Lints are reported as warnings. If any lint is an error according to
+    /// the `dit.lint.*` configuration, the process is aborted.
+    ///
+    fn lint_message(&self, message: &str);
+
     /// Retrieve metadata from command line arguments
     ///
     fn prepare_trailers(&self, matches: &ArgMatches) -> Vec<Trailer>;
@@ -91,21 +204,145 @@ pub trait RepositoryUtil<'r> {
     ///
     fn cli_author(&self, matches: &ArgMatches) -> git2::Signature;
 
+    /// Get the signature of the user
+    ///
+    /// If an identity profile is selected via the `DIT_PROFILE` environment
+    /// variable or `dit.profile`, the signature is created from the profile.
+    /// Otherwise, the user's default signature is returned.
+    ///
+    fn dit_signature(&self) -> git2::Signature<'static>;
+
+    /// Retrieve the author and committer to use for new messages
+    ///
+    /// If the message is to be authored anonymously, the signature of the
+    /// anonymous identity is used for both.
+    ///
+    fn cli_signatures(&self, matches: &ArgMatches) -> (git2::Signature, git2::Signature);
+
+    /// Get the anonymous identity configured for the repository
+    ///
+    fn anonymous_identity(&self) -> AnonymousIdentity;
+
     /// Get the abbreviation length for oids
     ///
     fn abbreviation_length(&self, matches: &ArgMatches) -> usize;
 
+    /// Get the message referred to by an issue's selected head
+    ///
+    /// The head is selected according to the remote priorization.
+    ///
+    fn issue_head(&'r self, issue: &Issue<'r>) -> Commit<'r>;
+
+    /// Get the values of a piece of metadata of an issue
+    ///
+    /// The metadata is accumulated from the message supplied, usually the
+    /// issue's head, according to the specification.
+    ///
+    fn metadata_values(&'r self, issue: &Issue<'r>, head: &Commit, spec: &TrailerSpec) -> Vec<String>;
+
+    /// Create a message changing the metadata of an issue
+    ///
+    /// The message replies to the head supplied and consists of a reply
+    /// subject, the comment and the trailers. Signatures and references are
+    /// taken from the command line. Comments on encrypted issues are encrypted.
+    /// The issue's local head is set to the new message, which is returned.
+    ///
+    fn add_metadata_message(&'r self,
+                            matches: &ArgMatches,
+                            issue: &Issue<'r>,
+                            head: Commit<'r>,
+                            comment: Vec<String>,
+                            trailers: Vec<Trailer>
+    ) -> git2::Oid;
+
+    /// Send a new message to the mailing list configured
+    ///
+    /// If `dit.mail.to` is set, the message is sent as mail to that address
+    /// with threading headers derived from the message's and issue's ids.
+    /// Failures are reported but do not abort, since the message was created
+    /// regardless.
+    ///
+    fn mail_message(&self, issue: git2::Oid, message: &Commit);
+
+    /// Resolve an identity
+    ///
+    /// Identities may be given literally, e.g. as `Foo Bar <foo@example.com>`
+    /// or as an email address, via an alias configured as
+    /// `dit.identity.<alias>`, via the name of an identity profile or as `me`,
+    /// referring to the configured user.
+    ///
+    fn resolve_identity(&self, identity: &str) -> String;
+
+    /// Get the names of the remotes specified on the command line
+    ///
+    /// If the `"all"` flag is present, the names of all remotes are returned.
+    /// Otherwise, the remote specified via the `"remote"` field or, if none is
+    /// specified, the remote configured via `dit.remote` is returned. If there
+    /// is no such remote either, the program aborts.
+    ///
+    fn cli_remotes(&self, matches: &ArgMatches) -> Vec<String>;
+
+    /// Configure a remote for git-dit, if neccessary
+    ///
+    /// The refspec for fetching issues is added to the remote's configuration
+    /// unless `dit.configure-remotes` is set to `false`.
+    ///
+    fn setup_remote(&self, name: &str);
+
     /// Get remote priorization from the config
     fn remote_priorization(&self) -> RemotePriorization;
 
     /// Get a pager
     ///
-    /// Get a pager suitable for paging output
+    /// Get a pager suitable for paging output. If stdout is not a TTY or the
+    /// pager is disabled, `None` is returned.
     ///
-    fn pager(&self) -> Child;
+    fn pager(&self) -> Option<Child>;
+
+    /// Get the palette for colorizing output
+    ///
+    /// Colors are used according to `color.dit` or, if not set, `color.ui`.
+    /// With the default "auto", colors are only used if stdout is a TTY.
+    ///
+    fn palette(&self) -> Palette;
+
+    /// Get the cipher configured for encrypting messages
+    ///
+    /// If no encryption backend is configured, `None` will be returned.
+    ///
+    fn cipher(&self) -> Option<ProgramCipher>;
+
+    /// Encrypt a message to the configured recipients
+    ///
+    fn encrypt_message(&self, message: &str) -> String;
+
+    /// Get the message filter for screening incoming messages
+    ///
+    /// The filter is assembled from the moderation data and the `dit.filter.*`
+    /// configuration options.
+    ///
+    fn message_filter(&self) -> FilterChain;
+
+    /// Get the policy for advancing head references
+    ///
+    /// The policy is taken from the `"head-policy"` field or the
+    /// `dit.head-policy` configuration and defaults to fast-forwarding.
+    ///
+    fn head_policy(&self, matches: &ArgMatches) -> HeadPolicy;
+
+    /// Check whether the parent should be quoted in replies
+    ///
+    /// Quoting is controlled via the `"quote"` and `"no-quote"` flags and the
+    /// `dit.reply.quote` configuration, which defaults to `true`.
+    ///
+    fn quote_parent(&self, matches: &ArgMatches) -> bool;
 }
 
 impl<'r> RepositoryUtil<'r> for Repository {
+    fn dit_config(&self) -> DitConfig {
+        DitConfig::open(self).unwrap_or_abort()
+    }
+
     fn value_to_commit(&'r self, rev: &str) -> Commit<'r> {
         self.revparse_single(rev)
             .and_then(|oid| self.find_commit(oid.id()))
@@ -127,6 +364,54 @@ impl<'r> RepositoryUtil<'r> for Repository {
                .map(|value| value_to_issue(self, value))
     }
 
+    fn cli_issue_or_current(&'r self, matches: &ArgMatches) -> Issue<'r> {
+        self.cli_issue(matches).unwrap_or_else(|| self
+            .current_issue()
+            .unwrap_or_abort()
+            .ok_or_else(|| Error::from_kind(EK::NoCurrentIssue))
+            .unwrap_or_abort()
+        )
+    }
+
+    fn cli_component(&self, matches: &ArgMatches) -> Option<String> {
+        matches
+            .value_of("component")
+            .map(String::from)
+            .or_else(|| self.dit_config().component().unwrap_or_abort())
+    }
+
+    fn branch_issue(&'r self, branch: Option<&str>) -> Issue<'r> {
+        let branch = match branch {
+            Some(branch) => branch.to_owned(),
+            None => self
+                .head()
+                .ok()
+                .and_then(|head| head.shorthand().map(String::from))
+                .unwrap_or_else(|| "HEAD".to_owned()),
+        };
+
+        let configured = self
+            .config()
+            .unwrap_or_abort()
+            .get_string(&format!("branch.{}.dit-issue", branch))
+            .ok();
+        if let Some(id) = configured {
+            return value_to_issue(self, &id);
+        }
+
+        // Only consider names containing something which looks like a hash,
+        // since we would resolve arbitrary revisions otherwise.
+        branch
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.split('-').next())
+            .filter(|id| id.len() >= 4 && id.chars().all(|c| c.is_digit(16)))
+            .and_then(|id| self.revparse_single(id).ok())
+            .and_then(|object| self.find_issue(object.id()).ok())
+            .ok_or_else(|| Error::from_kind(EK::NoIssueForBranch(branch.clone())))
+            .unwrap_or_abort()
+    }
+
     fn cli_issues(&'r self, matches: &ArgMatches) -> Option<UniqueIssues<'r>> {
         matches
             .values_of("issue")
@@ -153,8 +438,7 @@ impl<'r> RepositoryUtil<'r> for Repository {
             .unwrap_or_abort()
             .success()
         {
-            Error::from_kind(EK::ChildError).log();
-            ::std::process::exit(1);
+            abort(&Error::from_kind(EK::ChildError));
         }
 
         // read the message back, check for validity
@@ -162,6 +446,7 @@ impl<'r> RepositoryUtil<'r> for Repository {
         let lines : Vec<String> = io::BufReader::new(File::open(path).unwrap_or_abort())
             .lines()
             .abort_on_err()
+            .filter(|line| !is_empty_dit_trailer(line))
             .stripped()
             .collect();
 
@@ -169,18 +454,186 @@ impl<'r> RepositoryUtil<'r> for Repository {
             .iter()
             .check_message_format()
             .unwrap_or_abort();
+        lines
+            .iter()
+            .check_trailers()
+            .unwrap_or_abort();
 
         lines
     }
 
+    fn issue_template(&self, matches: &ArgMatches) -> Vec<String> {
+        use io::Read;
+        use libgitdit::config::config_value;
+        use libgitdit::template::Placeholder;
+
+        let shipped = matches
+            .value_of("template")
+            .filter(|name| template::check_name(name).is_ok())
+            .and_then(|name| template::templates(self).unwrap_or_abort().get(name).map(String::from));
+
+        let from_repo = shipped.is_some();
+        let text = match shipped {
+            Some(text) => text,
+            None => {
+                let path = matches
+                    .value_of("template")
+                    .map(PathBuf::from)
+                    .or_else(|| self
+                        .config()
+                        .and_then(|config| config.get_path("dit.template.new"))
+                        .ok()
+                    );
+
+                match path {
+                    Some(path) => {
+                        let mut text = String::new();
+                        File::open(path)
+                            .and_then(|mut file| file.read_to_string(&mut text))
+                            .unwrap_or_abort();
+                        text
+                    },
+                    None => DEFAULT_ISSUE_TEMPLATE.to_owned(),
+                }
+            },
+        };
+
+        // The environment and configuration may hold secrets, which must not
+        // end up in issues via templates shipped by a remote.
+        let trusted = !from_repo || template::has_local_templates(self).unwrap_or_abort();
+        let untrusted = |placeholder: &str| {
+            warn!("{}", tr!("Not expanding '{}' in a template fetched from a remote", placeholder));
+            String::new()
+        };
+
+        // fill in the placeholders
+        let dit_config = self.dit_config();
+        let config = self.config().unwrap_or_abort();
+        template::expand(&text, |placeholder| Ok(match placeholder {
+            Placeholder::Env(name) if !trusted => untrusted(&format!("env:{}", name)),
+            Placeholder::Config(key) if !trusted => untrusted(&format!("config:{}", key)),
+            Placeholder::Env(name) => env::var(name).unwrap_or_default(),
+            Placeholder::Config(key) => config_value(config.get_string(key))
+                .unwrap_or_abort()
+                .unwrap_or_default(),
+            Placeholder::Prompt(question) => prompt(question),
+            Placeholder::Variable(name) => dit_config
+                .template_variable(name)
+                .unwrap_or_abort()
+                .or_else(|| builtin_template_variable(name))
+                .unwrap_or_else(|| prompt(&format!("{}:", name))),
+        }))
+        .unwrap_or_abort()
+        .lines()
+        .map(String::from)
+        .collect()
+    }
+
+    fn complete_trailers(&self, message: String) -> String {
+        use libgitdit::message::block::Block;
+
+        let required = self.dit_config().required_trailers().unwrap_or_abort();
+        let missing = message.lines().missing_trailers(&required);
+        if missing.is_empty() || !atty::is(atty::Stream::Stdin) {
+            return message;
+        }
+
+        let trailers: Vec<Trailer> = missing
+            .iter()
+            .map(|key| (key, prompt(&format!("{}:", key))))
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| Trailer::new(key, &value))
+            .collect();
+        if trailers.is_empty() {
+            return message;
+        }
+
+        // trailers are only recognized as part of a block of trailers
+        let last_block = message.lines().skip(1).line_blocks().last();
+        let in_trailer_block = matches!(last_block, Some(Block::Trailer(_)));
+        let mut retval = message.trim_end().to_owned();
+        retval.push('\n');
+        if !in_trailer_block {
+            retval.push('\n');
+        }
+        retval.push_str(&trailers.into_iter().map(|t| t.to_string()).collect_string());
+        retval
+    }
+
+    fn pre_message_hook(&self, matches: &ArgMatches, message: String, issue: Option<git2::Oid>) -> String {
+        use io::{Read, Write};
+
+        let mut command = match programs::hook(self, "dit-pre-message").unwrap_or_abort() {
+            Some(command) => command,
+            None => return message,
+        };
+
+        let path = self.commitmsg_edit_path(matches);
+        File::create(path.as_path())
+            .and_then(|mut file| file.write_all(message.as_bytes()))
+            .unwrap_or_abort();
+
+        command.arg(path.as_os_str());
+        if let Some(id) = issue {
+            command.arg(id.to_string());
+        }
+        if !command.status().unwrap_or_abort().success() {
+            abort(&Error::from_kind(EK::HookFailed("dit-pre-message".to_owned())));
+        }
+
+        // the hook may have altered the message
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .unwrap_or_abort();
+        text.lines().check_message_format().unwrap_or_abort();
+        text.lines().check_trailers().unwrap_or_abort();
+        text
+    }
+
+    fn post_issue_hook(&self, issue: git2::Oid) {
+        let command = programs::hook(self, "dit-post-issue")
+            .and_then(|command| match command {
+                Some(mut command) => command
+                    .arg(issue.to_string())
+                    .status()
+                    .chain_err(|| EK::WrappedIOError)
+                    .map(Some),
+                None => Ok(None),
+            });
+        match command {
+            Ok(Some(ref status)) if !status.success() =>
+                Error::from_kind(EK::HookFailed("dit-post-issue".to_owned())).log(),
+            Ok(_) => (),
+            Err(err) => err.log(),
+        }
+    }
+
+    fn lint_message(&self, message: &str) {
+        let lints = Linter::with_config(&self.dit_config())
+            .unwrap_or_abort()
+            .lint(message.lines());
+        for lint in &lints {
+            warn!("{}", lint);
+        }
+        if lint::has_errors(&lints) {
+            abort(&Error::from_kind(EK::RejectedByLint));
+        }
+    }
+
     fn prepare_trailers(&self, matches: &ArgMatches) -> Vec<Trailer> {
         let mut trailers = Vec::new();
 
         if matches.is_present("signoff") {
-            let sig = self.signature().unwrap_or_abort().to_string();
+            let sig = self.dit_signature().to_string();
             trailers.push(Trailer::new("Signed-off-by", sig.as_str()));
         }
 
+        if matches.is_present("anonymous") {
+            let author = self.cli_author(matches);
+            trailers.push(self.anonymous_identity().reporter_trailer(&author));
+        }
+
         // append misc metadata
         if let Some(metadata) = matches.values_of("metadata") {
             for trailer in metadata.map(Trailer::from_str) {
@@ -218,10 +671,9 @@ impl<'r> RepositoryUtil<'r> for Repository {
                     .ok_or_else(|| Error::from_kind(EK::MalformedAuthor(a.to_owned())))
                     .unwrap_or_abort();
 
-                git2::Signature::now(name, email)
+                git2::Signature::now(name, email).unwrap_or_abort()
             })
-            .unwrap_or_else(|| self.signature())
-            .unwrap_or_abort();
+            .unwrap_or_else(|| self.dit_signature());
 
         // If an explicit date and time was specified, change the signature
         if let Some(d) = matches.value_of("date") {
@@ -242,6 +694,48 @@ impl<'r> RepositoryUtil<'r> for Repository {
         }
     }
 
+    fn dit_signature(&self) -> git2::Signature<'static> {
+        let config = self.dit_config();
+        let profile = match env::var(PROFILE_VAR) {
+            Ok(profile) => Some(profile),
+            Err(_) => config.profile().unwrap_or_abort(),
+        };
+        match profile {
+            Some(profile) => config
+                .identity_profile(&profile)
+                .unwrap_or_abort()
+                .ok_or_else(|| Error::from_kind(EK::UnknownProfile(profile)))
+                .unwrap_or_abort()
+                .signature()
+                .unwrap_or_abort(),
+            None => self.signature().unwrap_or_abort(),
+        }
+    }
+
+    fn cli_signatures(&self, matches: &ArgMatches) -> (git2::Signature, git2::Signature) {
+        if matches.is_present("anonymous") {
+            let signature = self.anonymous_identity().signature().unwrap_or_abort();
+            (signature.clone(), signature)
+        } else {
+            (self.cli_author(matches), self.dit_signature())
+        }
+    }
+
+    fn anonymous_identity(&self) -> AnonymousIdentity {
+        let config = self.config().unwrap_or_abort();
+        let name = config
+            .get_string("dit.anonymous.name")
+            .unwrap_or_else(|_| "Anonymous".to_owned());
+        let email = config
+            .get_string("dit.anonymous.email")
+            .unwrap_or_else(|_| "anonymous@localhost".to_owned());
+        let key = config
+            .get_string("dit.anonymous.key")
+            .unwrap_or_default();
+
+        AnonymousIdentity::new(name, email, key).unwrap_or_abort()
+    }
+
     fn abbreviation_length(&self, matches: &ArgMatches) -> usize {
         if !matches.is_present("abbrev") {
             // If the abbreviation option was not used, we can just use the
@@ -267,17 +761,272 @@ impl<'r> RepositoryUtil<'r> for Repository {
         7
     }
 
+    fn issue_head(&'r self, issue: &Issue<'r>) -> Commit<'r> {
+        // the heads are resolved only once per issue handle
+        let heads = issue.head_targets().unwrap_or_abort();
+        self.remote_priorization()
+            .select_target(&heads)
+            .ok_or_else(|| ::libgitdit::error::Error::from_kind(
+                ::libgitdit::error::ErrorKind::CannotFindIssueHead(issue.id())
+            ))
+            .and_then(|head| self.find_commit(head).map_err(From::from))
+            .unwrap_or_abort()
+    }
+
+    fn metadata_values(&'r self, issue: &Issue<'r>, head: &Commit, spec: &TrailerSpec) -> Vec<String> {
+        use libgitdit::iter::MessagesExt;
+
+        issue
+            .messages_from(head.id())
+            .abort_on_err()
+            .accumulate_trailers(Some(spec))
+            .remove(spec.key)
+            .into_iter()
+            .flat_map(|values| values)
+            .map(|value| value.to_string())
+            .collect()
+    }
+
+    fn add_metadata_message(&'r self,
+                            matches: &ArgMatches,
+                            issue: &Issue<'r>,
+                            mut head: Commit<'r>,
+                            comment: Vec<String>,
+                            trailers: Vec<Trailer>
+    ) -> git2::Oid {
+        let (author, committer) = self.cli_signatures(matches);
+
+        let message = vec![head.reply_subject().unwrap_or_default(), String::new()]
+            .into_iter()
+            .chain(comment.iter().cloned())
+            .chain(trailers.into_iter().chain(self.prepare_trailers(matches)).map(|t| t.to_string()))
+            .collect_string();
+
+        // comments on encrypted issues should not leak their content
+        let encrypted = crypt::is_encrypted(head.message().unwrap_or("").lines());
+        let message = if encrypted && !comment.is_empty() {
+            self.encrypt_message(message.trim())
+        } else {
+            message
+        };
+
+        let references = self.cli_references(matches);
+        let tree = self.empty_tree().unwrap_or_abort();
+        let parent_refs : Vec<&Commit> = Some(&head).into_iter().chain(references.iter()).collect();
+        let new = self
+            .commit(None, &author, &committer, message.trim(), &tree, &parent_refs)
+            .unwrap_or_abort();
+
+        issue.update_head(new, true).unwrap_or_abort();
+        self.mail_message(issue.id(), &self.find_commit(new).unwrap_or_abort());
+        new
+    }
+
+    fn mail_message(&self, issue: git2::Oid, message: &Commit) {
+        use std::io::Write;
+
+        // `programs` retrieves strings, which requires a snapshot
+        let config = self.config().and_then(|mut config| config.snapshot()).unwrap_or_abort();
+        let recipient = match config.get_string("dit.mail.to") {
+            Ok(recipient) => recipient,
+            Err(_) => return,
+        };
+
+        let result = programs::sendmail(config, &recipient).and_then(|mut child| {
+            {
+                let stdin = child.stdin.as_mut().expect("Could not access stdin");
+                write!(stdin, "To: {}\n{}", recipient, mail::mail(issue, message))
+                    .chain_err(|| EK::WrappedIOError)?;
+            }
+            if child.wait().chain_err(|| EK::WrappedIOError)?.success() {
+                Ok(())
+            } else {
+                Err(Error::from_kind(EK::ChildError))
+            }
+        });
+        if let Err(err) = result.chain_err(|| EK::CannotSendMail(message.id())) {
+            err.log();
+        }
+    }
+
+    fn resolve_identity(&self, identity: &str) -> String {
+        if identity == "me" {
+            // the canonical identity is used, as for participants
+            let signature = self.dit_signature();
+            return self
+                .mailmap()
+                .and_then(|mailmap| mailmap.resolve_signature(&signature))
+                .unwrap_or_abort()
+                .to_string();
+        }
+        if identity.contains('@') {
+            return identity.to_owned();
+        }
+        let config = self.dit_config();
+        if let Some(alias) = config.identity(identity).unwrap_or_abort() {
+            return alias;
+        }
+        config
+            .identity_profile(identity)
+            .unwrap_or_abort()
+            .map(|profile| format!("{} <{}>", profile.name, profile.email))
+            .ok_or_else(|| Error::from_kind(EK::UnknownIdentity(identity.to_owned())))
+            .unwrap_or_abort()
+    }
+
+    fn cli_remotes(&self, matches: &ArgMatches) -> Vec<String> {
+        if matches.is_present("all") {
+            self.remotes()
+                .unwrap_or_abort()
+                .iter()
+                .filter_map(|name| name.map(String::from))
+                .collect()
+        } else {
+            let remote = matches
+                .value_of("remote")
+                .map(String::from)
+                .or_else(|| self.dit_config().remote().unwrap_or_abort())
+                .ok_or_else(|| Error::from_kind(EK::NoRemote))
+                .unwrap_or_abort();
+            vec![remote]
+        }
+    }
+
+    fn setup_remote(&self, name: &str) {
+        let enabled = self
+            .config()
+            .unwrap_or_abort()
+            .get_bool("dit.configure-remotes")
+            .unwrap_or(true);
+        if enabled && remote::configure_remote(self, name).unwrap_or_abort() {
+            println!("[dit] Configured remote '{}' for fetching issues", name);
+        }
+    }
+
     fn remote_priorization(&self) -> RemotePriorization {
-        self.config()
+        self.dit_config()
+            .remote_prios()
             .unwrap_or_abort()
-            .get_str("dit.remote-prios")
+            .as_ref()
+            .map(String::as_str)
             .unwrap_or("*")
             .into()
     }
 
-    fn pager(&self) -> Child {
+    fn pager(&self) -> Option<Child> {
+        if !atty::is(atty::Stream::Stdout) {
+            return None;
+        }
         programs::pager(self.config().unwrap_or_abort()).unwrap_or_abort()
     }
+
+    fn palette(&self) -> Palette {
+        let config = self.config().unwrap_or_abort();
+        let setting = config
+            .get_string("color.dit")
+            .or_else(|_| config.get_string("color.ui"))
+            .unwrap_or_else(|_| "auto".to_owned());
+
+        let enabled = match setting.as_ref() {
+            "always" => true,
+            "never" | "false" => false,
+            _ => atty::is(atty::Stream::Stdout),
+        };
+        Palette::new(enabled)
+    }
+
+    fn cipher(&self) -> Option<ProgramCipher> {
+        let config = self.config().unwrap_or_abort();
+
+        let cipher = match config.get_string("dit.encryption.backend").ok() {
+            Some(ref b) if b == "gpg" => ProgramCipher::new(Backend::Gpg),
+            Some(ref b) if b == "age" => ProgramCipher::new(Backend::Age),
+            Some(b) => abort(&Error::from_kind(EK::UnknownEncryptionBackend(b))),
+            None => return None,
+        };
+
+        let cipher = match config.get_string("dit.encryption.program") {
+            Ok(program) => cipher.with_program(program),
+            Err(_) => cipher,
+        };
+        Some(match config.get_path("dit.encryption.identity") {
+            Ok(identity) => cipher.with_identity(identity.to_string_lossy()),
+            Err(_) => cipher,
+        })
+    }
+
+    fn encrypt_message(&self, message: &str) -> String {
+        let recipients: Vec<String> = self
+            .config()
+            .unwrap_or_abort()
+            .get_string("dit.encryption.recipients")
+            .unwrap_or_default()
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|r| !r.is_empty())
+            .map(String::from)
+            .collect();
+
+        let cipher = self.cipher();
+        match cipher {
+            Some(ref cipher) if !recipients.is_empty() => {
+                crypt::encrypt_message(cipher, message, &recipients).unwrap_or_abort()
+            },
+            _ => abort(&Error::from_kind(EK::EncryptionNotConfigured)),
+        }
+    }
+
+    fn message_filter(&self) -> FilterChain {
+        let config = self.config().unwrap_or_abort();
+        let mut filter = FilterChain::new();
+
+        let moderation = self.moderation().unwrap_or_abort();
+        if moderation != Default::default() {
+            filter.push(moderation);
+        }
+
+        let mut content = ContentFilter::new();
+        let mut use_content = false;
+        for entry in &config.entries(Some("dit.filter.pattern")).unwrap_or_abort() {
+            if let Some(pattern) = entry.unwrap_or_abort().value() {
+                content = content.with_pattern(Regex::new(pattern).unwrap_or_abort());
+                use_content = true;
+            }
+        }
+        if let Some(max_links) = self.dit_config().filter_max_links().unwrap_or_abort() {
+            content = content.with_max_links(max_links);
+            use_content = true;
+        }
+        if use_content {
+            filter.push(content);
+        }
+
+        if let Ok(command) = config.get_string("dit.filter.command") {
+            filter.push(CommandFilter::new(command));
+        }
+
+        filter
+    }
+
+    fn head_policy(&self, matches: &ArgMatches) -> HeadPolicy {
+        matches
+            .value_of("head-policy")
+            .map(|policy| HeadPolicy::from_str(policy).unwrap_or_abort())
+            .or_else(|| self.dit_config().head_policy().unwrap_or_abort())
+            .unwrap_or(HeadPolicy::FastForward)
+    }
+
+    fn quote_parent(&self, matches: &ArgMatches) -> bool {
+        if matches.is_present("quote") {
+            return true;
+        }
+        if matches.is_present("no-quote") {
+            return false;
+        }
+        self.config()
+            .unwrap_or_abort()
+            .get_bool("dit.reply.quote")
+            .unwrap_or(true)
+    }
 }
 
 
@@ -294,6 +1043,120 @@ pub fn message_from_args(matches: &ArgMatches) -> Option<Vec<String>> {
 }
 
 
+/// Summarize changes to the references of a remote
+///
+/// One line is returned for each issue changed. If no issue was changed, a
+/// single line stating so is returned.
+///
+pub fn change_summary(remote: &str, changes: Vec<IssueChanges>) -> Vec<String> {
+    if changes.is_empty() {
+        return vec![format!("{}: up to date", remote)];
+    }
+
+    changes
+        .into_iter()
+        .map(|change| {
+            let mut parts = Vec::new();
+            if change.new_issue {
+                parts.push("new issue".to_owned());
+            }
+            for &(count, kind) in [(change.created, "new"),
+                                   (change.updated, "updated"),
+                                   (change.deleted, "deleted")].iter() {
+                if count > 0 {
+                    parts.push(format!("{} {}", count, kind));
+                }
+            }
+            format!("{}: {} {} reference(s)", remote, change.issue, parts.join(", "))
+        })
+        .collect()
+}
+
+
+/// Compute trailers changing a set of values
+///
+/// Changes are given as values prefixed with a `+` for adding or a `-` for
+/// removing a value. Values without a prefix are added. Changes which would
+/// not alter the set of current values are omitted. The current values are
+/// updated accordingly.
+///
+pub fn set_change_trailers<'a, I>(key: &str, current: &mut Vec<String>, changes: I) -> Vec<Trailer>
+    where I: IntoIterator<Item = &'a str>
+{
+    let mut trailers = Vec::new();
+    for change in changes {
+        let (add, value) = if change.starts_with('-') {
+            (false, &change[1..])
+        } else if change.starts_with('+') {
+            (true, &change[1..])
+        } else {
+            (true, change)
+        };
+
+        let position = current.iter().position(|v| v == value);
+        match (add, position) {
+            (true, None) => {
+                current.push(value.to_owned());
+                trailers.push(Trailer::new(key, value));
+            },
+            (false, Some(index)) => {
+                current.remove(index);
+                trailers.push(Trailer::new(key, &format!("-{}", value)));
+            },
+            _ => {},
+        }
+    }
+    trailers
+}
+
+
+/// Check whether a line is a git-dit trailer without a value
+///
+/// Such lines are part of the trailer skeleton of a template. They are removed
+/// from messages if they were not filled in.
+///
+fn is_empty_dit_trailer(line: &str) -> bool {
+    line.starts_with(DIT_KEY_PREFIX) && Trailer::from_str(line)
+        .map(|trailer| trailer.value.to_string().is_empty())
+        .unwrap_or(false)
+}
+
+
+/// Get the value of a variable predefined for templates
+///
+/// The variables "os" and "arch" hold the operating system and architecture
+/// and "dit-version" the version of git-dit.
+///
+fn builtin_template_variable(name: &str) -> Option<String> {
+    match name {
+        "os" => Some(env::consts::OS.to_owned()),
+        "arch" => Some(env::consts::ARCH.to_owned()),
+        "dit-version" => Some(env!("CARGO_PKG_VERSION").to_owned()),
+        _ => None,
+    }
+}
+
+
+/// Ask the user for a value
+///
+/// If stdin is not a terminal, no question is asked and an empty value is
+/// returned.
+///
+fn prompt(question: &str) -> String {
+    use io::{BufRead, Write};
+
+    if !atty::is(atty::Stream::Stdin) {
+        return String::new();
+    }
+
+    write!(io::stderr(), "{} ", question).unwrap_or_abort();
+    io::stderr().flush().unwrap_or_abort();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).unwrap_or_abort();
+    answer.trim().to_owned()
+}
+
+
 /// Get an issue from a string representation
 ///
 /// This function returns an issue from a string representation.
@@ -0,0 +1,247 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Local web interface
+//!
+//! This module provides a read-only web interface rendering issues directly
+//! from the repository. It offers a list of issues, a threaded view of single
+//! issues and a search. Pages are rendered on each request, so changes to the
+//! repository are visible immediately. The list may be sorted via the `sort`
+//! and `order` parameters, which take the values of the "list" subcommand's
+//! options.
+//!
+
+use git2::{Commit, Oid, Repository};
+use libgitdit::{Issue, RepositoryExt};
+use libgitdit::listing::{self, Order, SortKey};
+use libgitdit::search::{search_issue, Query};
+use tiny_http::{Header, Response, Server};
+
+use error::*;
+use error::ErrorKind as EK;
+use error::LoggableError;
+use html::{self, escape as escape_html};
+use util::RepositoryUtil;
+
+
+/// Serve the web interface on the address given
+///
+/// Requests are handled one after another until the process is terminated.
+///
+pub fn serve(repo: &Repository, address: &str, id_len: usize) -> Result<()> {
+    let server = Server::http(address)
+        .map_err(|err| Error::from_kind(EK::ServerError(address.to_owned(), err.to_string())))?;
+
+    for request in server.incoming_requests() {
+        let (status, body) = match route(repo, request.url(), id_len) {
+            Ok(Some(body)) => (200, body),
+            Ok(None) => (404, page("Not found", "<p>No such page.</p>".to_owned())),
+            Err(err) => {
+                err.log();
+                (500, page("Error", format!("<p>{}</p>", escape_html(&err.to_string()))))
+            },
+        };
+
+        let content_type = Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..])
+            .expect("Invalid header");
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(content_type);
+        if let Err(err) = request.respond(response) {
+            err.log();
+        }
+    }
+    Ok(())
+}
+
+
+/// Render the page for a request URL
+///
+/// `None` is returned if no page exists for the URL.
+///
+fn route(repo: &Repository, url: &str, id_len: usize) -> Result<Option<String>> {
+    let (path, query) = match url.find('?') {
+        Some(pos) => (&url[..pos], &url[pos + 1..]),
+        None      => (url, ""),
+    };
+    let params: Vec<(String, String)> = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| match param.find('=') {
+            Some(pos) => (decode(&param[..pos]), decode(&param[pos + 1..])),
+            None      => (decode(param), String::new()),
+        })
+        .collect();
+    let param = |name: &str| params
+        .iter()
+        .find(|&&(ref key, _)| key == name)
+        .map(|&(_, ref value)| value.as_str());
+
+    if path == "/" {
+        let all = param("all").is_some();
+        let key = match param("sort") {
+            Some(key) => key.parse()?,
+            None => SortKey::Created,
+        };
+        let order = match param("order") {
+            Some(order) => order.parse()?,
+            None => key.default_order(),
+        };
+        return match param("q") {
+            Some(pattern) if !pattern.is_empty() => search_page(repo, pattern, all, id_len),
+            _ => list_page(repo, all, key, order, id_len),
+        }.map(Some);
+    }
+
+    if path.starts_with("/issue/") {
+        let issue = match Oid::from_str(&path["/issue/".len()..]).ok().and_then(|id| repo.find_issue(id).ok()) {
+            Some(issue) => issue,
+            None => return Ok(None),
+        };
+        return issue_page(repo, &issue, id_len).map(Some);
+    }
+
+    Ok(None)
+}
+
+
+/// Render the list of issues
+///
+/// Closed issues are omitted unless `all` is set.
+///
+fn list_page(repo: &Repository, all: bool, key: SortKey, order: Order, id_len: usize) -> Result<String> {
+    let mut rows = Vec::new();
+    for (issue, initial) in sorted_issues(repo, key, order)? {
+        if !all && html::issue_status(repo, &issue) == "closed" {
+            continue;
+        }
+        rows.push(html::issue_row(repo, &issue, &initial, &format!("/issue/{}", issue.id()), id_len));
+    }
+
+    let toggle = if all {
+        "<p><a href=\"/\">Show open issues only</a></p>"
+    } else {
+        "<p><a href=\"/?all\">Show all issues</a></p>"
+    };
+    Ok(page("Issues", format!("{}<table>{}</table>", toggle, rows.join(""))))
+}
+
+
+/// Render the results of a search
+///
+/// The subjects and bodies of all messages are searched for the pattern,
+/// ignoring case. Closed issues are omitted unless `all` is set.
+///
+fn search_page(repo: &Repository, pattern: &str, all: bool, id_len: usize) -> Result<String> {
+    let query = Query::new(pattern).case_sensitive(false);
+    let moderation = repo.moderation()?;
+
+    let mut results = Vec::new();
+    for (issue, initial) in sorted_issues(repo, SortKey::Created, Order::Descending)? {
+        if !all && html::issue_status(repo, &issue) == "closed" {
+            continue;
+        }
+
+        let hit = match search_issue(issue, &query, |message| !moderation.is_hidden(message))? {
+            Some(hit) => hit,
+            None => continue,
+        };
+        let snippets: Vec<_> = hit
+            .messages
+            .into_iter()
+            .flat_map(|message| message.snippets)
+            .map(|snippet| format!("<li>{}</li>", escape_html(&snippet.text)))
+            .collect();
+        results.push(format!("<h2><a href=\"/issue/{}\">{:.*}</a> {}</h2><ul>{}</ul>",
+                             hit.issue.id(),
+                             id_len,
+                             hit.issue.id(),
+                             escape_html(initial.summary().unwrap_or("")),
+                             snippets.join("")));
+    }
+
+    if results.is_empty() {
+        results.push("<p>No matches.</p>".to_owned());
+    }
+    Ok(page(&format!("Search: {}", pattern), results.join("")))
+}
+
+
+/// Render an issue as a thread
+///
+fn issue_page(repo: &Repository, issue: &Issue, id_len: usize) -> Result<String> {
+    let moderation = repo.moderation()?;
+    let hidden = |message: &Commit| moderation.is_hidden(message);
+    let thread = html::issue_thread(repo, issue, &hidden, id_len)?;
+
+    let subject = issue
+        .initial_message()?
+        .summary()
+        .map(String::from)
+        .unwrap_or_default();
+    Ok(page(&subject, thread))
+}
+
+
+/// Get all issues along with their initial messages, sorted by the key given
+///
+fn sorted_issues<'r>(repo: &'r Repository, key: SortKey, order: Order) -> Result<Vec<(Issue<'r>, Commit<'r>)>> {
+    let moderation = repo.moderation()?;
+
+    let mut issues = Vec::new();
+    for issue in repo.issues()? {
+        if !moderation.is_hidden(&issue.initial_message()?) {
+            issues.push(issue);
+        }
+    }
+
+    let mut retval = Vec::new();
+    for issue in listing::sort_issues(issues, key, order, |issue| Ok(repo.issue_head(issue).id()))? {
+        let initial = issue.initial_message()?;
+        retval.push((issue, initial));
+    }
+    Ok(retval)
+}
+
+
+/// Wrap content in an HTML page
+///
+/// The page's header links to the list of issues and contains a search form.
+///
+fn page(title: &str, content: String) -> String {
+    html::page(title, concat!(
+            "<p><a href=\"/\">Issues</a></p>",
+            "<form action=\"/\"><input name=\"q\" placeholder=\"Search\"> ",
+            "<label><input type=\"checkbox\" name=\"all\"> including closed issues</label></form>"),
+        &content)
+}
+
+
+/// Decode a URL-encoded query component
+///
+/// Malformed escape sequences are retained verbatim.
+///
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut retval = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let hex = component.get(pos + 1..pos + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[pos], hex) {
+            (b'+', _) => retval.push(b' '),
+            (b'%', Some(byte)) => {
+                retval.push(byte);
+                pos += 2;
+            },
+            (byte, _) => retval.push(byte),
+        }
+        pos += 1;
+    }
+    String::from_utf8_lossy(&retval).into_owned()
+}
@@ -0,0 +1,314 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2016, 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2016, 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Minimal XML parser
+//!
+//! This module provides a parser for XML documents as exported by other
+//! trackers. Only the subset of XML required for reading such documents is
+//! supported: elements, attributes, text, CDATA sections and character
+//! references. Declarations, processing instructions and comments are skipped
+//! and entities other than the predefined ones are retained verbatim.
+//!
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// XML element
+///
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// Get the value of an attribute
+    ///
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.0 == name)
+            .map(|attribute| attribute.1.as_str())
+    }
+
+    /// Get the first child element with a given name
+    ///
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().filter_map(|node| match *node {
+            Node::Element(ref element) if element.name == name => Some(element),
+            _ => None,
+        }).next()
+    }
+
+    /// Get all child elements with a given name
+    ///
+    pub fn elements<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter_map(move |node| match *node {
+            Node::Element(ref element) if element.name == name => Some(element),
+            _ => None,
+        })
+    }
+
+    /// Get the text of the element, including the text of all descendants
+    ///
+    pub fn text(&self) -> String {
+        let mut retval = String::new();
+        for node in &self.children {
+            match *node {
+                Node::Element(ref element) => retval.push_str(&element.text()),
+                Node::Text(ref text) => retval.push_str(text),
+            }
+        }
+        retval
+    }
+
+    /// Get the trimmed text of the first child element with a given name
+    ///
+    /// If there is no such element, an empty string is returned.
+    ///
+    pub fn child_text(&self, name: &str) -> String {
+        self.child(name).map(|child| child.text().trim().to_owned()).unwrap_or_default()
+    }
+}
+
+
+/// Node of an element's content
+///
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+
+/// Parse an XML document, returning its root element
+///
+pub fn parse(document: &str) -> Result<Element> {
+    let mut parser = Parser { input: document, pos: 0 };
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    parser.skip_misc()?;
+    if parser.pos < parser.input.len() {
+        return Err(parser.error("content after the root element"));
+    }
+    Ok(root)
+}
+
+
+/// Parser state
+///
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Get the remaining input
+    ///
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    /// Skip whitespace, declarations, processing instructions and comments
+    ///
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if trimmed.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if trimmed.starts_with("<!") {
+                self.skip_declaration()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skip past the next occurrence of a delimiter
+    ///
+    fn skip_past(&mut self, delimiter: &str) -> Result<()> {
+        match self.rest().find(delimiter) {
+            Some(end) => {
+                self.pos += end + delimiter.len();
+                Ok(())
+            },
+            None => Err(self.error(&format!("missing '{}'", delimiter))),
+        }
+    }
+
+    /// Skip a declaration, e.g. a document type declaration
+    ///
+    /// Declarations may contain an internal subset enclosed in brackets.
+    ///
+    fn skip_declaration(&mut self) -> Result<()> {
+        let mut depth = 0;
+        for (index, character) in self.rest().char_indices() {
+            match character {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.pos += index + 1;
+                    return Ok(());
+                },
+                _ => (),
+            }
+        }
+        Err(self.error("unterminated declaration"))
+    }
+
+    /// Parse an element, starting at its start tag
+    ///
+    fn element(&mut self) -> Result<Element> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+        self.pos += 1;
+        let name = self.name()?;
+
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(Element { name: name, attributes: attributes, children: Vec::new() });
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error(&format!("missing value of attribute '{}'", key)));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => quote,
+                _ => return Err(self.error(&format!("unquoted value of attribute '{}'", key))),
+            };
+            self.pos += 1;
+            let end = self.rest().find(quote).ok_or_else(|| self.error("unterminated attribute value"))?;
+            let value = unescape(&self.rest()[..end]);
+            self.pos += end + 1;
+            attributes.push((key, value));
+        }
+
+        let mut children = Vec::new();
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let end = self.name()?;
+                if end != name {
+                    return Err(self.error(&format!("expected end of '{}', found end of '{}'", name, end)));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(self.error("malformed end tag"));
+                }
+                self.pos += 1;
+                return Ok(Element { name: name, attributes: attributes, children: children });
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += 9;
+                let end = self.rest().find("]]>").ok_or_else(|| self.error("unterminated CDATA section"))?;
+                children.push(Node::Text(self.rest()[..end].to_owned()));
+                self.pos += end + 3;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                children.push(Node::Element(self.element()?));
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("unterminated element '{}'", name)));
+            } else {
+                let end = rest.find('<').unwrap_or_else(|| rest.len());
+                children.push(Node::Text(unescape(&rest[..end])));
+                self.pos += end;
+            }
+        }
+    }
+
+    /// Parse a name of an element or attribute
+    ///
+    fn name(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || "/>=".contains(c))
+            .unwrap_or_else(|| rest.len());
+        if end == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += end;
+        Ok(rest[..end].to_owned())
+    }
+
+    /// Skip whitespace
+    ///
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Create an error for the current position
+    ///
+    fn error(&self, reason: &str) -> Error {
+        let line = self.input[..self.pos].matches('\n').count() + 1;
+        Error::from_kind(EK::MalformedXml(format!("line {}: {}", line, reason)))
+    }
+}
+
+
+/// Replace the predefined entities and character references in a text
+///
+fn unescape(text: &str) -> String {
+    let mut retval = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        retval.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let replacement = rest.find(';').and_then(|end| {
+            let character = match &rest[1..end] {
+                "lt"   => Some('<'),
+                "gt"   => Some('>'),
+                "amp"  => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                reference if reference.starts_with("#x") => u32::from_str_radix(&reference[2..], 16)
+                    .ok()
+                    .and_then(::std::char::from_u32),
+                reference if reference.starts_with('#') => reference[1..]
+                    .parse()
+                    .ok()
+                    .and_then(::std::char::from_u32),
+                _ => None,
+            };
+            character.map(|character| (character, end))
+        });
+        match replacement {
+            Some((character, end)) => {
+                retval.push(character);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                retval.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    retval.push_str(rest);
+    retval
+}